@@ -3,6 +3,8 @@ use criterion::criterion_main;
 mod benchmarks;
 
 criterion_main! {
+    benchmarks::conflict::conflict,
     benchmarks::mev_boost::serialization,
+    benchmarks::scratch::scratch,
     benchmarks::txpool_fetcher::txpool,
 }
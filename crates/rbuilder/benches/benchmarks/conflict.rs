@@ -0,0 +1,154 @@
+use alloy_primitives::{Address, B256, U256};
+use criterion::{black_box, criterion_group, Criterion};
+use rbuilder::{
+    building::{
+        access_set_conflicts,
+        evm_inspector::{SlotKey, UsedStateTrace},
+        get_conflict_sets,
+        order_index::OrderIndex,
+        Conflict, IndexedAccessSets, OrderAccessSet,
+    },
+    primitives::OrderId,
+};
+use std::collections::HashMap;
+
+/// Orders on a busy slot.
+const ORDERS: u64 = 1000;
+
+fn order_ids() -> Vec<OrderId> {
+    (0..ORDERS)
+        .map(|i| OrderId::Tx(B256::from(U256::from(i))))
+        .collect()
+}
+
+/// Every pair like find_conflict_fast gives, orders conflict in chains of 10.
+fn slot_conflicts(ids: &[OrderId]) -> HashMap<(OrderId, OrderId), Conflict> {
+    let mut conflicts = HashMap::with_capacity(ids.len() * ids.len());
+    for (i, id1) in ids.iter().enumerate() {
+        for (j, id2) in ids.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let conflict = if i / 10 == j / 10 && j == i + 1 {
+                Conflict::AccessOverlap
+            } else {
+                Conflict::NoConflict
+            };
+            conflicts.insert((*id1, *id2), conflict);
+        }
+    }
+    conflicts
+}
+
+fn bench_conflict_sets(c: &mut Criterion) {
+    let ids = order_ids();
+    let conflicts = slot_conflicts(&ids);
+    let mut group = c.benchmark_group("Conflict sets 1k orders");
+    group.sample_size(10);
+    group.bench_function("get_conflict_sets", |b| {
+        b.iter(|| black_box(get_conflict_sets(&conflicts)))
+    });
+    group.finish();
+}
+
+/// Orders touching a few of 100 contracts, each reads 4 slots and writes 2.
+fn access_sets(ids: &[OrderId]) -> Vec<(OrderId, OrderAccessSet)> {
+    let slot = |i: u64, j: u64| SlotKey {
+        address: Address::with_last_byte(((i * 7 + j) % 100) as u8),
+        key: B256::from(U256::from((i + j) % 50)),
+    };
+    ids.iter()
+        .zip(0u64..)
+        .map(|(id, i)| {
+            let mut trace = UsedStateTrace::default();
+            for j in 0..4 {
+                trace.read_slot_values.insert(slot(i, j), B256::ZERO);
+            }
+            for j in 4..6 {
+                trace.written_slot_values.insert(slot(i, j), B256::ZERO);
+            }
+            (*id, OrderAccessSet::new(vec![], trace, 0, 21_000))
+        })
+        .collect()
+}
+
+/// Access overlap of every pair, hashed sets (baseline) vs interned sorted indexes (interning included).
+fn bench_access_overlap(c: &mut Criterion) {
+    let ids = order_ids();
+    let sets = access_sets(&ids);
+    let mut group = c.benchmark_group("Access overlap 1k orders");
+    group.sample_size(10);
+    group.bench_function("hashed", |b| {
+        b.iter(|| {
+            let mut overlaps = 0;
+            for (_, set1) in &sets {
+                for (_, set2) in &sets {
+                    overlaps += set1.affects(set2) as usize;
+                }
+            }
+            black_box(overlaps)
+        })
+    });
+    group.bench_function("indexed", |b| {
+        b.iter(|| {
+            let indexed = IndexedAccessSets::new(sets.iter().map(|(_, set)| set));
+            let mut overlaps = 0;
+            for index1 in 0..sets.len() {
+                for index2 in 0..sets.len() {
+                    overlaps += indexed.affects(index1, index2) as usize;
+                }
+            }
+            black_box(overlaps)
+        })
+    });
+    group.bench_function("access_set_conflicts", |b| {
+        b.iter(|| black_box(access_set_conflicts(&sets, 30_000_000, 786_432)))
+    });
+    group.finish();
+}
+
+/// Profit alone of the second order of every pair (what the pair analysis looks up).
+fn bench_profit_lookups(c: &mut Criterion) {
+    let ids = order_ids();
+    let by_id: HashMap<OrderId, U256> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, U256::from(i)))
+        .collect();
+    let by_index: Vec<U256> = (0..ids.len()).map(U256::from).collect();
+
+    let mut group = c.benchmark_group("Profit alone lookups 1k orders");
+    group.bench_function("hashmap", |b| {
+        b.iter(|| {
+            let mut total = U256::ZERO;
+            for _ in &ids {
+                for id2 in &ids {
+                    total += by_id[id2];
+                }
+            }
+            black_box(total)
+        })
+    });
+    group.bench_function("order_index", |b| {
+        // the index is built once per slot so it's part of the measurement
+        b.iter(|| {
+            let index = OrderIndex::from_ids(ids.iter().copied());
+            let indexes: Vec<u32> = ids.iter().filter_map(|id| index.get(id)).collect();
+            let mut total = U256::ZERO;
+            for _ in &indexes {
+                for index2 in &indexes {
+                    total += by_index[*index2 as usize];
+                }
+            }
+            black_box(total)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    conflict,
+    bench_conflict_sets,
+    bench_access_overlap,
+    bench_profit_lookups
+);
@@ -83,6 +83,7 @@ fn bench_mevboost_sign(c: &mut Criterion) {
             let _ = sign_block_for_relay(
                 &signer,
                 &sealed_block,
+                &[],
                 &blobs,
                 &Vec::new(),
                 &chain_spec,
@@ -108,6 +109,7 @@ fn bench_mevboost_sign(c: &mut Criterion) {
             let _ = sign_block_for_relay(
                 &signer,
                 &sealed_block_deneb,
+                &[],
                 &blobs,
                 &Vec::new(),
                 &chain_spec,
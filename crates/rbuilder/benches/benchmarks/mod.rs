@@ -1,2 +1,4 @@
+pub mod conflict;
 pub mod mev_boost;
+pub mod scratch;
 pub mod txpool_fetcher;
@@ -0,0 +1,54 @@
+use alloy_primitives::{Address, B256, U256};
+use criterion::{black_box, criterion_group, Criterion};
+use rbuilder::building::{
+    evm_inspector::{SlotKey, UsedStateTrace},
+    scratch::{recycle_used_state_trace, take_used_state_trace},
+};
+
+/// Roughly what a swap leaves on the trace.
+fn fill_trace(trace: &mut UsedStateTrace) {
+    for i in 0..32u8 {
+        let key = SlotKey {
+            address: Address::with_last_byte(i % 4),
+            key: B256::with_last_byte(i),
+        };
+        trace.read_slot_values.insert(key.clone(), B256::ZERO);
+        if i % 2 == 0 {
+            trace
+                .written_slot_values
+                .insert(key, B256::with_last_byte(1));
+        }
+    }
+    for i in 0..4u8 {
+        trace
+            .read_balances
+            .insert(Address::with_last_byte(i), U256::from(i));
+        trace
+            .sent_amount
+            .insert(Address::with_last_byte(i), U256::from(i));
+        trace
+            .received_amount
+            .insert(Address::with_last_byte(i + 1), U256::from(i));
+    }
+}
+
+fn bench_used_state_trace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Used state trace allocation");
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            let mut trace = UsedStateTrace::default();
+            fill_trace(&mut trace);
+            black_box(trace);
+        })
+    });
+    group.bench_function("scratch", |b| {
+        b.iter(|| {
+            let mut trace = take_used_state_trace();
+            fill_trace(&mut trace);
+            recycle_used_state_trace(black_box(trace));
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(scratch, bench_used_state_trace);
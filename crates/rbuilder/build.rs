@@ -1,3 +1,6 @@
 fn main() {
     built::write_built_file().expect("Failed to acquire build-time information");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/orderflow.proto")
+        .expect("Failed to compile orderflow.proto");
 }
@@ -0,0 +1,113 @@
+//! Backtest app to debug conflicts: simulates the orders of a block (same as backtest-build-block), runs
+//! find_conflict_slow on the ones that work at top of block and exports the result as a [ConflictGraph].
+//! Sample calls:
+//! - backtest-conflict-graph --config /home/happy_programmer/config.toml 19380913 | dot -Tsvg > conflicts.svg
+//! - backtest-conflict-graph --config /home/happy_programmer/config.toml --format json --output conflicts.json 19380913
+
+use crate::{
+    backtest::{
+        execute::{backtest_prepare_ctx_for_block, BacktestBlockInput},
+        HistoricalDataStorage,
+    },
+    building::{conflict_graph::ConflictGraph, ParallelConflictFinder},
+    live_builder::{base_config::load_config_toml_and_env, cli::LiveBuilderConfig},
+    primitives::Order,
+};
+use clap::{Parser, ValueEnum};
+use reth_provider::StateProviderFactory;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[clap(long, help = "Config file path", env = "RBUILDER_CONFIG")]
+    config: PathBuf,
+    #[clap(
+        long,
+        help = "build block lag (ms)",
+        default_value = "0",
+        allow_hyphen_values = true
+    )]
+    block_building_time_ms: i64,
+    #[clap(long, help = "use only this orders")]
+    only_order_ids: Vec<String>,
+    #[clap(long, help = "Output format", value_enum, default_value = "dot")]
+    format: GraphFormat,
+    #[clap(long, help = "Output file (stdout if not set)")]
+    output: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Threads used to execute the pairs (0 = one per core), overrides backtest_conflict_detection_threads"
+    )]
+    threads: Option<usize>,
+    #[clap(help = "Block Number")]
+    block: u64,
+}
+
+pub async fn run_backtest_conflict_graph<ConfigType>() -> eyre::Result<()>
+where
+    ConfigType: LiveBuilderConfig,
+{
+    let cli = Cli::parse();
+
+    let config: ConfigType = load_config_toml_and_env(cli.config)?;
+    config.base_config().setup_tracing_subscriber()?;
+
+    let mut historical_data_storage =
+        HistoricalDataStorage::new_from_path(&config.base_config().backtest_fetch_output_file)
+            .await?;
+    let mut block_data = historical_data_storage.read_block_data(cli.block).await?;
+    if !cli.only_order_ids.is_empty() {
+        block_data.filter_orders_by_ids(&cli.only_order_ids);
+    }
+    if cli.block_building_time_ms != 0 {
+        block_data.filter_late_orders(cli.block_building_time_ms);
+    }
+
+    let provider_factory = config.base_config().create_provider_factory()?;
+    let BacktestBlockInput {
+        ctx, sim_orders, ..
+    } = backtest_prepare_ctx_for_block(
+        block_data,
+        provider_factory.clone(),
+        config.base_config().chain_spec()?,
+        cli.block_building_time_ms,
+        config.base_config().blocklist()?,
+        config.base_config().coinbase_signer()?,
+    )?;
+    let orders: Vec<Order> = sim_orders.iter().map(|o| o.order.clone()).collect();
+    info!(
+        block = cli.block,
+        orders = orders.len(),
+        "Finding conflicts"
+    );
+
+    let state_provider = provider_factory.history_by_block_hash(ctx.attributes.parent)?;
+    let conflict_finder = ParallelConflictFinder::new(
+        cli.threads
+            .unwrap_or(config.base_config().backtest_conflict_detection_threads),
+    )?;
+    let conflicts = conflict_finder.find_conflicts(state_provider, &ctx, &orders)?;
+    let graph = ConflictGraph::new(&conflicts);
+    info!(
+        nodes = graph.nodes.len(),
+        edges = graph.edges.len(),
+        "Conflict graph built"
+    );
+
+    let output = match cli.format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Json => graph.to_json()?,
+    };
+    match cli.output {
+        Some(path) => std::fs::write(path, output)?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
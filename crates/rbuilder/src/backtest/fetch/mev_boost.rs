@@ -42,7 +42,10 @@ impl Default for PayloadDeliveredFetcher {
                     use_ssz_for_submit: false, //Don't use submit so don't care
                     use_gzip_for_submit: false, //Don't use submit so don't care
                     optimistic: false,
+                    requires_payment_proof: false,
                     submission_rate_limiter: None,
+                    min_bid_value: None,
+                    extra_data: None,
                 }
             })
             .collect::<Vec<_>>();
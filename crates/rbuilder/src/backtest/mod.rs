@@ -1,15 +1,19 @@
 mod backtest_build_block;
 mod backtest_build_range;
+mod backtest_conflict_graph;
 pub mod execute;
 pub mod fetch;
 
 pub mod redistribute;
 pub mod restore_landed_orders;
 mod results_store;
+mod scenario_runner;
 mod store;
 
 pub use backtest_build_block::run_backtest_build_block;
 pub use backtest_build_range::run_backtest_build_range;
+pub use backtest_conflict_graph::run_backtest_conflict_graph;
+pub use scenario_runner::run_scenario_runner;
 use std::collections::HashSet;
 
 use crate::{
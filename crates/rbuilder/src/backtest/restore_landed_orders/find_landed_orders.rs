@@ -716,9 +716,7 @@ mod tests {
 
     #[test]
     fn test_simplified_order_conversion_mempool_tx() {
-        let order = Order::Tx(MempoolTx {
-            tx_with_blobs: tx(0x01),
-        });
+        let order = Order::Tx(MempoolTx::new(tx(0x01)));
         let expected = SimplifiedOrder::new(
             OrderId::Tx(hash(0x01)),
             vec![OrderChunk::new(
@@ -833,6 +831,7 @@ mod tests {
             signer: None,
             replacement_data: None,
             original_orders: vec![],
+            privacy_hints: Vec::new(),
             metadata: Default::default(),
         });
         let expected = SimplifiedOrder::new(
@@ -0,0 +1,67 @@
+//! App to run builder scenarios (see [`crate::building::testing::scenario`]) with the builders of a config file.
+//! Exits with an error if any scenario fails.
+//! Sample call:
+//! scenario-runner --config /home/happy_programmer/config.toml --builders mgp-ordering --builders mp-ordering scenarios/*.toml
+
+use crate::{
+    building::testing::scenario::{run_scenario, Scenario},
+    live_builder::{base_config::load_config_toml_and_env, cli::LiveBuilderConfig},
+};
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[clap(long, help = "Config file path", env = "RBUILDER_CONFIG")]
+    config: PathBuf,
+    #[clap(
+        long,
+        help = "builders to run the scenarios with (see config builders)",
+        default_value = "mp-ordering"
+    )]
+    builders: Vec<String>,
+    #[clap(long, help = "Print outcomes as json")]
+    json: bool,
+    #[clap(help = "Scenario files (toml or json)", required = true)]
+    scenarios: Vec<PathBuf>,
+}
+
+pub async fn run_scenario_runner<ConfigType>() -> eyre::Result<()>
+where
+    ConfigType: LiveBuilderConfig,
+{
+    let cli = Cli::parse();
+    let config: ConfigType = load_config_toml_and_env(cli.config)?;
+
+    let mut failed = 0;
+    for path in &cli.scenarios {
+        let scenario = Scenario::load(path)
+            .map_err(|err| eyre::eyre!("Failed to load scenario {:?}: {}", path, err))?;
+        for builder_name in &cli.builders {
+            let outcome = run_scenario(&scenario, &config, builder_name)?;
+            if !outcome.passed() {
+                failed += 1;
+            }
+            if cli.json {
+                println!("{}", serde_json::to_string(&outcome)?);
+                continue;
+            }
+            let status = if outcome.passed() { "PASS" } else { "FAIL" };
+            println!(
+                "{} {} [{}] included: {:?} block value: {}",
+                status,
+                outcome.scenario,
+                outcome.builder_name,
+                outcome.included,
+                outcome.block_value
+            );
+            for failure in &outcome.failures {
+                println!("    {}", failure);
+            }
+        }
+    }
+    if failed > 0 {
+        eyre::bail!("{} scenario runs failed", failed);
+    }
+    Ok(())
+}
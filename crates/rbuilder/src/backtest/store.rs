@@ -497,6 +497,7 @@ fn order_type(order: &RawOrder) -> &'static str {
     match order {
         RawOrder::Bundle(_) => "bundle",
         RawOrder::Tx(_) => "tx",
+        RawOrder::PrivateTx(_) => "private_tx",
         RawOrder::ShareBundle(_) => "sbundle",
     }
 }
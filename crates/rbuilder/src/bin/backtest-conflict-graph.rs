@@ -0,0 +1,8 @@
+//! Instantiation of run_backtest_conflict_graph on our sample configuration.
+
+use rbuilder::{backtest::run_backtest_conflict_graph, live_builder::config::Config};
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    run_backtest_conflict_graph::<Config>().await
+}
@@ -14,6 +14,7 @@ use rbuilder::{
             BlockBuildingAlgorithm, BlockBuildingAlgorithmInput, OrderConsumer,
             UnfinishedBlockBuildingSink, UnfinishedBlockBuildingSinkFactory,
         },
+        extra_data::DEFAULT_BUILDER_NAME,
         BlockBuildingContext, SimulatedOrderStore,
     },
     live_builder::{
@@ -35,7 +36,10 @@ use rbuilder::{
         SimulatedOrder,
     },
     roothash::RootHashConfig,
-    utils::{ProviderFactoryReopener, Signer},
+    utils::{
+        clock::{system_clock, ClockRef},
+        ProviderFactoryReopener, Signer,
+    },
 };
 use reth_chainspec::MAINNET;
 use reth_db::{database::Database, DatabaseEnv};
@@ -66,7 +70,7 @@ async fn main() -> eyre::Result<()> {
         with_url("https://0xac6e77dfe25ecd6110b8e780608cce0dab71fdd5ebea22a16c0205200f2f8e2e3ad3b71d3499c54ad14d6c21b41a37ae@boost-relay.flashbots.net").
         with_name("flashbots");
 
-    let relay = MevBoostRelay::from_config(&relay_config)?;
+    let relay = MevBoostRelay::from_config(&relay_config, DEFAULT_BUILDER_NAME)?;
 
     let payload_event = MevBoostSlotDataGenerator::new(
         vec![Client::default()],
@@ -94,7 +98,24 @@ async fn main() -> eyre::Result<()> {
     > {
         watchdog_timeout: Some(Duration::from_secs(10000)),
         error_storage_path: None,
+        slot_resource_report_db_path: None,
+        signer_reputation_db_path: None,
+        algorithm_params_path: None,
+        admin_rpc_server_address: None,
+        leader_election: None,
+        node_health: None,
+        warm_standby: false,
+        slot_outcome_predictor: None,
+        state_access_heatmap: false,
+        bytecode_cache_size: 0,
+        gas_price_oracle: Default::default(),
+        refund_settlement: None,
+        victim_protection: None,
+        clock: system_clock(),
         simulation_threads: 1,
+        shared_worker_threads: 0,
+        tenant_cgroup_root: None,
+        sim_queue_weights: Default::default(),
         blocks_source: payload_event,
         order_input_config,
         chain_chain_spec: chain_spec.clone(),
@@ -105,13 +126,17 @@ async fn main() -> eyre::Result<()> {
             chain_spec.clone(),
         )?,
         coinbase_signer: Signer::random(),
-        extra_data: Vec::new(),
+        extra_data: Default::default(),
         blocklist: Default::default(),
         global_cancellation: cancel.clone(),
         extra_rpc: RpcModule::new(()),
         sink_factory: Box::new(TraceBlockSinkFactory {}),
+        block_inclusion_observers: Default::default(),
         builders: vec![Arc::new(DummyBuildingAlgorithm::new(10))],
         run_sparse_trie_prefetcher: false,
+        late_order_fast_path: None,
+        sim_bundle: None,
+        orderpool_delta_interval: None,
         orderpool_sender,
         orderpool_receiver,
     };
@@ -137,6 +162,7 @@ impl UnfinishedBlockBuildingSinkFactory for TraceBlockSinkFactory {
         &mut self,
         _slot_data: MevBoostSlotData,
         _cancel: CancellationToken,
+        _clock: ClockRef,
     ) -> Arc<dyn rbuilder::building::builders::UnfinishedBlockBuildingSink> {
         Arc::new(TracingBlockSink {})
     }
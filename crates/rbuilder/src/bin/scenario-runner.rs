@@ -0,0 +1,8 @@
+//! Instantiation of run_scenario_runner on our sample configuration.
+
+use rbuilder::{backtest::run_scenario_runner, live_builder::config::Config};
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    run_scenario_runner::<Config>().await
+}
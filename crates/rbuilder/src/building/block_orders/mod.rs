@@ -157,6 +157,13 @@ impl BlockOrders {
             .insert_order(order);
     }
 
+    /// See [`PrioritizedOrderStore::defer_order`]. Like readd_order it bypasses other stages.
+    pub fn defer_order(&mut self, order: SimulatedOrder, until: AccountNonce) {
+        self.prioritized_order_store
+            .borrow_mut()
+            .defer_order(order, until);
+    }
+
     pub fn remove_orders(
         &mut self,
         orders: impl IntoIterator<Item = OrderId>,
@@ -334,6 +341,31 @@ mod test {
         }
     }
 
+    #[test]
+    /// Order failing on a nonce gap of another account waits until the gap is filled in the block.
+    fn test_block_orders_deferred_order() {
+        let (nonce_1, nonce_2, mut context) = TestContext::new_2_accounts(0, 0);
+        let gapped_order = context.create_add_tx_order(&nonce_1, 5);
+        let gap_filler = context.create_add_tx_order(&nonce_2, 1);
+
+        context.assert_pop_order(&gapped_order);
+        // failed with NonceTooHigh: it needs nonce_2 at 1
+        context
+            .order_pool
+            .defer_order(gapped_order.clone(), nonce_2.clone().with_nonce(1));
+        context.assert_pop_order(&gap_filler);
+        context.assert_pop_none();
+        context.update_nonce(&nonce_2, 1);
+        context.assert_pop_order(&gapped_order);
+        context.assert_pop_none();
+
+        // deferring on a nonce already reached is a plain readd
+        context
+            .order_pool
+            .defer_order(gapped_order.clone(), nonce_2.clone().with_nonce(1));
+        context.assert_pop_order(&gapped_order);
+    }
+
     #[test]
     /// Tests 2 tx from different accounts, can execute both
     fn test_block_orders_simple() {
@@ -167,6 +167,29 @@ impl PrioritizedOrderStore {
         }
     }
 
+    /// Parks an order that failed because one of its txs needs the account at a nonce it's not at yet (nonce gap).
+    /// It goes back to the main queue as soon as update_onchain_nonces reaches until.
+    pub fn defer_order(&mut self, sim_order: SimulatedOrder, until: AccountNonce) {
+        if self.orders.contains_key(&sim_order.id()) {
+            return;
+        }
+        let onchain_nonce = self
+            .onchain_nonces
+            .get(&until.account)
+            .cloned()
+            .unwrap_or_default();
+        if onchain_nonce >= until.nonce {
+            // the gap is already filled (or skipped), nothing to wait for
+            self.insert_order(sim_order);
+            return;
+        }
+        let pending = self.pending_orders.entry(until).or_default();
+        if !pending.contains(&sim_order.id()) {
+            pending.push(sim_order.id());
+        }
+        self.orders.insert(sim_order.id(), sim_order);
+    }
+
     pub fn get_all_orders(&self) -> Vec<SimulatedOrder> {
         self.orders.values().cloned().collect()
     }
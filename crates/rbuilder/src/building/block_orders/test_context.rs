@@ -175,6 +175,7 @@ impl<TestedSinkType: SimulatedOrderSink> TestContext<TestedSinkType> {
             sim_value,
             prev_order: None,
             used_state_trace: None,
+            execution_cost: Default::default(),
         }
     }
 
@@ -202,6 +203,7 @@ impl<TestedSinkType: SimulatedOrderSink> TestContext<TestedSinkType> {
             signer,
             replacement_data: None,
             original_orders: Vec::new(),
+            privacy_hints: Vec::new(),
             metadata: Default::default(),
         };
         sbundle.hash_slow();
@@ -0,0 +1,516 @@
+//! Complete json export of a built block for systems that check or prove it without our node (stateless validators,
+//! proof generators).
+//!
+//! Format (version [`BLOCK_TEMPLATE_VERSION`], all fields camelCase, numbers as hex quantities):
+//! - header: the execution header of the block (same fields as eth_getBlockByHash) and blockHash.
+//! - transactions: EIP-2718 envelopes in block order (blob txs without sidecar), withdrawals and the Pectra
+//!   executionRequests.
+//! - stateDiff: for every account changed by the block its pre (parent state) and post state (null if it didn't /
+//!   doesn't exist) and the changed storage slots.
+//! - witness: merkle proofs against the parent state root of every account and storage slot read or written while
+//!   building (the read set can be a superset of what the block needs since the read cache is shared between the
+//!   block attempts of a slot) and the bytecodes read.
+//!
+//! Generating the witness needs a proof per touched account so finalize (if
+//! [`crate::roothash::RootHashConfig::generate_block_template`] is set) only keeps what's needed in a
+//! [`BlockTemplateSource`] and the template is generated off the building threads for the blocks we submit.
+//! Templates contain our whole block so admin_blockTemplate is only served on the admin rpc.
+
+use crate::roothash::payment_proof::AccountStateProof;
+use alloy_primitives::{Address, Bytes, B256, U256};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use lru::LruCache;
+use parking_lot::Mutex;
+use reth::{
+    primitives::{Header, SealedBlock, Withdrawals},
+    providers::ExecutionOutcome,
+    revm::cached::CachedReads,
+};
+use reth_errors::ProviderError;
+use reth_provider::{StateProofProvider, StateProviderFactory};
+use reth_trie::TrieInput;
+use revm::primitives::AccountInfo;
+use revm_primitives::KECCAK_EMPTY;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub const BLOCK_TEMPLATE_VERSION: u32 = 1;
+/// Submitted templates kept for admin_blockTemplate.
+pub const RECENT_TEMPLATES: usize = 64;
+
+lazy_static! {
+    static ref RECENT_BLOCK_TEMPLATES: Mutex<LruCache<B256, Arc<BlockTemplate>>> =
+        Mutex::new(LruCache::new(NonZeroUsize::new(RECENT_TEMPLATES).unwrap()));
+}
+
+/// Makes the template available on demand (by block hash) for a while.
+pub fn record_block_template(template: Arc<BlockTemplate>) {
+    RECENT_BLOCK_TEMPLATES
+        .lock()
+        .put(template.block_hash, template);
+}
+
+pub fn recent_block_template(block_hash: &B256) -> Option<Arc<BlockTemplate>> {
+    RECENT_BLOCK_TEMPLATES.lock().get(block_hash).cloned()
+}
+
+/// admin_blockTemplate: template of one of the last submitted blocks (by block hash), null if we don't have it.
+pub fn block_template_rpc_module() -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    module.register_method("admin_blockTemplate", |params, _| {
+        let block_hash: B256 = params.one()?;
+        Ok::<_, ErrorObject<'static>>(
+            recent_block_template(&block_hash).map(|template| (*template).clone()),
+        )
+    })?;
+    Ok(module)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountState {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code_hash: B256,
+}
+
+impl From<&AccountInfo> for AccountState {
+    fn from(info: &AccountInfo) -> Self {
+        Self {
+            balance: info.balance,
+            nonce: info.nonce,
+            code_hash: info.code_hash,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageDiff {
+    pub pre: U256,
+    pub post: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    /// None if the account didn't exist.
+    pub pre: Option<AccountState>,
+    /// None if the account doesn't exist after the block (eg: selfdestructed).
+    pub post: Option<AccountState>,
+    /// Changed slots.
+    pub storage: BTreeMap<B256, StorageDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageWitness {
+    pub key: B256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountWitness {
+    pub address: Address,
+    pub account: AccountStateProof,
+    pub storage: Vec<StorageWitness>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockWitness {
+    /// Against the parent state root, sorted by address.
+    pub accounts: Vec<AccountWitness>,
+    /// Bytecodes by code hash.
+    pub codes: BTreeMap<B256, Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTemplate {
+    pub version: u32,
+    pub block_hash: B256,
+    pub header: Header,
+    pub transactions: Vec<Bytes>,
+    pub withdrawals: Option<Withdrawals>,
+    pub execution_requests: Vec<Bytes>,
+    pub state_diff: BTreeMap<Address, AccountDiff>,
+    pub witness: BlockWitness,
+}
+
+impl BlockTemplate {
+    /// Template of the same block resealed with another extra data (see
+    /// [`crate::building::extra_data::with_extra_data`]).
+    pub fn with_extra_data(&self, extra_data: Bytes) -> Self {
+        let mut header = self.header.clone();
+        header.extra_data = extra_data;
+        Self {
+            block_hash: header.hash_slow(),
+            header,
+            ..self.clone()
+        }
+    }
+
+    /// Saves to dir/{block_number}-{block_hash}.template.json and returns the path.
+    pub fn save(&self, dir: &Path) -> eyre::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}-{:?}.template.json",
+            self.header.number, self.block_hash
+        ));
+        std::fs::write(&path, serde_json::to_vec(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+/// Accounts changed by the outcome with their changed slots.
+pub fn state_diff(outcome: &ExecutionOutcome) -> BTreeMap<Address, AccountDiff> {
+    outcome
+        .bundle
+        .state
+        .iter()
+        .filter(|(_, account)| {
+            account.is_info_changed() || account.storage.values().any(|slot| slot.is_changed())
+        })
+        .map(|(address, account)| {
+            let storage = account
+                .storage
+                .iter()
+                .filter(|(_, slot)| slot.is_changed())
+                .map(|(key, slot)| {
+                    (
+                        B256::from(*key),
+                        StorageDiff {
+                            pre: slot.previous_or_original_value,
+                            post: slot.present_value,
+                        },
+                    )
+                })
+                .collect();
+            (
+                *address,
+                AccountDiff {
+                    pre: account.original_info.as_ref().map(AccountState::from),
+                    post: account.info.as_ref().map(AccountState::from),
+                    storage,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Slots read (cached_reads) or written (outcome) by account.
+fn touched_state(
+    outcome: &ExecutionOutcome,
+    cached_reads: &CachedReads,
+) -> BTreeMap<Address, BTreeSet<B256>> {
+    let mut touched: BTreeMap<Address, BTreeSet<B256>> = BTreeMap::new();
+    for (address, account) in &cached_reads.accounts {
+        touched
+            .entry(*address)
+            .or_default()
+            .extend(account.storage.keys().map(|key| B256::from(*key)));
+    }
+    for (address, account) in &outcome.bundle.state {
+        touched
+            .entry(*address)
+            .or_default()
+            .extend(account.storage.keys().map(|key| B256::from(*key)));
+    }
+    touched
+}
+
+/// Proofs against the parent state. A trait so blocks don't carry the provider type.
+pub trait StateProofSource: std::fmt::Debug + Send + Sync {
+    /// Sorted by address.
+    fn account_witnesses(
+        &self,
+        parent_hash: B256,
+        touched: &BTreeMap<Address, BTreeSet<B256>>,
+    ) -> Result<Vec<AccountWitness>, ProviderError>;
+}
+
+struct ProviderProofSource<P>(P);
+
+impl<P> std::fmt::Debug for ProviderProofSource<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderProofSource")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> StateProofSource for ProviderProofSource<P>
+where
+    P: StateProviderFactory,
+{
+    fn account_witnesses(
+        &self,
+        parent_hash: B256,
+        touched: &BTreeMap<Address, BTreeSet<B256>>,
+    ) -> Result<Vec<AccountWitness>, ProviderError> {
+        let state_provider = self.0.history_by_block_hash(parent_hash)?;
+        let mut accounts = Vec::with_capacity(touched.len());
+        for (address, slots) in touched {
+            let slots: Vec<B256> = slots.iter().copied().collect();
+            let proof = state_provider.proof(TrieInput::default(), *address, &slots)?;
+            let storage = proof
+                .storage_proofs
+                .iter()
+                .map(|storage_proof| StorageWitness {
+                    key: storage_proof.key,
+                    value: storage_proof.value,
+                    proof: storage_proof.proof.clone(),
+                })
+                .collect();
+            accounts.push(AccountWitness {
+                address: *address,
+                account: proof.into(),
+                storage,
+            });
+        }
+        Ok(accounts)
+    }
+}
+
+/// Everything a finalized block needs to generate its template later, the proofs are left for
+/// [`BlockTemplateSource::generate`].
+#[derive(Debug, Clone)]
+pub struct BlockTemplateSource {
+    parent_hash: B256,
+    state_diff: BTreeMap<Address, AccountDiff>,
+    touched: BTreeMap<Address, BTreeSet<B256>>,
+    codes: BTreeMap<B256, Bytes>,
+    proofs: Arc<dyn StateProofSource>,
+}
+
+impl BlockTemplateSource {
+    pub fn new<P>(
+        provider: P,
+        parent_hash: B256,
+        outcome: &ExecutionOutcome,
+        cached_reads: &CachedReads,
+    ) -> Self
+    where
+        P: StateProviderFactory + 'static,
+    {
+        Self::with_proof_source(
+            Arc::new(ProviderProofSource(provider)),
+            parent_hash,
+            outcome,
+            cached_reads,
+        )
+    }
+
+    pub fn with_proof_source(
+        proofs: Arc<dyn StateProofSource>,
+        parent_hash: B256,
+        outcome: &ExecutionOutcome,
+        cached_reads: &CachedReads,
+    ) -> Self {
+        let codes = cached_reads
+            .contracts
+            .iter()
+            .filter(|(code_hash, _)| **code_hash != KECCAK_EMPTY)
+            .map(|(code_hash, code)| (*code_hash, code.original_bytes()))
+            .collect();
+        Self {
+            parent_hash,
+            state_diff: state_diff(outcome),
+            touched: touched_state(outcome, cached_reads),
+            codes,
+            proofs,
+        }
+    }
+
+    /// Slow (a proof per touched account), don't call on the building threads.
+    pub fn witness(&self) -> Result<BlockWitness, ProviderError> {
+        Ok(BlockWitness {
+            accounts: self
+                .proofs
+                .account_witnesses(self.parent_hash, &self.touched)?,
+            codes: self.codes.clone(),
+        })
+    }
+
+    /// Slow, see [`Self::witness`].
+    pub fn generate(
+        &self,
+        sealed_block: &SealedBlock,
+        encoded_txs: &[Bytes],
+        execution_requests: &[Bytes],
+    ) -> Result<BlockTemplate, ProviderError> {
+        Ok(BlockTemplate {
+            version: BLOCK_TEMPLATE_VERSION,
+            block_hash: sealed_block.hash(),
+            header: sealed_block.header.header().clone(),
+            transactions: encoded_txs.to_vec(),
+            withdrawals: sealed_block.body.withdrawals.clone(),
+            execution_requests: execution_requests.to_vec(),
+            state_diff: self.state_diff.clone(),
+            witness: self.witness()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState};
+    use reth::revm::db::BundleState;
+    use revm_primitives::{Bytecode, HashMap};
+
+    #[test]
+    fn test_witness() {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11)).unwrap();
+        let user = test_chain.named_address(NamedAddr::User(1)).unwrap();
+        let written = Address::with_last_byte(0x42);
+        let mut cached_reads = CachedReads::default();
+        cached_reads.insert_account(
+            user,
+            AccountInfo::default(),
+            [(U256::from(7), U256::from(1))].into_iter().collect(),
+        );
+        let code = Bytecode::new_raw(Bytes::from_static(&[0x60, 0x00]));
+        cached_reads
+            .contracts
+            .insert(code.hash_slow(), code.clone());
+        cached_reads
+            .contracts
+            .insert(KECCAK_EMPTY, Bytecode::default());
+        let bundle = BundleState::new(
+            [(
+                written,
+                None,
+                Some(AccountInfo {
+                    balance: U256::from(1),
+                    ..Default::default()
+                }),
+                [(U256::from(3), (U256::ZERO, U256::from(4)))]
+                    .into_iter()
+                    .collect::<HashMap<_, _>>(),
+            )],
+            Vec::<Vec<(Address, Option<Option<AccountInfo>>, Vec<(U256, U256)>)>>::new(),
+            Vec::new(),
+        );
+        let outcome = ExecutionOutcome::new(bundle, Default::default(), 11, Vec::new());
+        let source = BlockTemplateSource::new(
+            test_chain.provider_factory().clone(),
+            test_chain.block_building_context().attributes.parent,
+            &outcome,
+            &cached_reads,
+        );
+        assert_eq!(source.state_diff.len(), 1);
+
+        let witness = source.witness().unwrap();
+        let mut expected_accounts = vec![
+            (user, vec![B256::from(U256::from(7))]),
+            (written, vec![B256::from(U256::from(3))]),
+        ];
+        expected_accounts.sort();
+        assert_eq!(
+            witness
+                .accounts
+                .iter()
+                .map(|account| (
+                    account.address,
+                    account
+                        .storage
+                        .iter()
+                        .map(|slot| slot.key)
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            expected_accounts
+        );
+        // empty code is implicit
+        assert_eq!(
+            witness.codes,
+            BTreeMap::from([(code.hash_slow(), code.original_bytes())])
+        );
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let changed = Address::with_last_byte(1);
+        let untouched = Address::with_last_byte(2);
+        let info = |balance: u64, nonce: u64| AccountInfo {
+            balance: U256::from(balance),
+            nonce,
+            ..Default::default()
+        };
+        let bundle = BundleState::new(
+            [
+                (
+                    changed,
+                    Some(info(10, 0)),
+                    Some(info(7, 1)),
+                    [
+                        (U256::from(1), (U256::ZERO, U256::from(5))),
+                        (U256::from(2), (U256::from(3), U256::from(3))),
+                    ]
+                    .into_iter()
+                    .collect::<HashMap<_, _>>(),
+                ),
+                (
+                    untouched,
+                    Some(info(1, 1)),
+                    Some(info(1, 1)),
+                    HashMap::default(),
+                ),
+            ],
+            Vec::<Vec<(Address, Option<Option<AccountInfo>>, Vec<(U256, U256)>)>>::new(),
+            Vec::new(),
+        );
+        let outcome = ExecutionOutcome::new(bundle, Default::default(), 1, Vec::new());
+        let diff = state_diff(&outcome);
+        assert_eq!(diff.len(), 1);
+        let account = &diff[&changed];
+        assert_eq!(account.pre.as_ref().unwrap().balance, U256::from(10));
+        assert_eq!(account.post.as_ref().unwrap().nonce, 1);
+        assert_eq!(
+            account.storage,
+            BTreeMap::from([(
+                B256::from(U256::from(1)),
+                StorageDiff {
+                    pre: U256::ZERO,
+                    post: U256::from(5)
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn test_template_with_extra_data() {
+        let sealed_block = SealedBlock::default();
+        let template = BlockTemplate {
+            version: BLOCK_TEMPLATE_VERSION,
+            block_hash: sealed_block.hash(),
+            header: sealed_block.header.header().clone(),
+            transactions: Vec::new(),
+            withdrawals: None,
+            execution_requests: Vec::new(),
+            state_diff: BTreeMap::new(),
+            witness: BlockWitness::default(),
+        };
+        let extra_data = Bytes::from_static(b"relay");
+        let resealed = template.with_extra_data(extra_data.clone());
+        assert_eq!(
+            resealed.block_hash,
+            crate::building::extra_data::with_extra_data(&sealed_block, extra_data.clone()).hash()
+        );
+        assert_eq!(resealed.header.extra_data, extra_data);
+        assert_eq!(resealed.header.state_root, template.header.state_root);
+    }
+}
@@ -0,0 +1,462 @@
+//! Live tuning of the builder algorithm parameters.
+//! Each builder registers its config ([`register_builder`]) and applies the current overrides at the start of every
+//! slot ([`apply_overrides`]) so operators can change eg: the exhaustive search depth or the phase boundaries from the
+//! admin rpc without restarting.
+//! Every change is journaled (and logged). Overrides are lost on restart unless persisted
+//! (admin_persistAlgorithmParams) to the file given to [`init_algorithm_params`], which is loaded on startup.
+
+use super::{ordering_builder::OrderingBuilderConfig, parallel_builder::ParallelBuilderConfig};
+use ahash::HashMap;
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+const MAX_JOURNAL_LEN: usize = 1_000;
+
+type Overrides = BTreeMap<String, Value>;
+
+#[derive(Debug, Error)]
+pub enum AlgorithmParamError {
+    #[error("Unknown builder {0}")]
+    UnknownBuilder(String),
+    #[error("Param {param} can't be changed live, tunable params: {tunable:?}")]
+    NotTunable {
+        param: String,
+        tunable: &'static [&'static str],
+    },
+    #[error("Invalid value for {param}: {error}")]
+    InvalidValue { param: String, error: String },
+    #[error("No algorithm params file configured")]
+    NoPersistPath,
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Builder config with params that can be changed between slots.
+pub trait TunableConfig: Clone + Send + Sync + 'static {
+    const TUNABLE_PARAMS: &'static [&'static str];
+
+    fn set_param(&mut self, param: &str, value: &Value) -> Result<(), AlgorithmParamError>;
+}
+
+fn parse_param<T: DeserializeOwned>(param: &str, value: &Value) -> Result<T, AlgorithmParamError> {
+    T::deserialize(value).map_err(|err| AlgorithmParamError::InvalidValue {
+        param: param.to_string(),
+        error: err.to_string(),
+    })
+}
+
+fn not_tunable<C: TunableConfig>(param: &str) -> AlgorithmParamError {
+    AlgorithmParamError::NotTunable {
+        param: param.to_string(),
+        tunable: C::TUNABLE_PARAMS,
+    }
+}
+
+impl TunableConfig for OrderingBuilderConfig {
+    const TUNABLE_PARAMS: &'static [&'static str] = &[
+        "failed_order_retries",
+        "drop_failed_orders",
+        "build_duration_deadline_ms",
+        "zero_profit_txs",
+        "zero_profit_tx_max_gas",
+        "value_per_cost_window_ms",
+    ];
+
+    fn set_param(&mut self, param: &str, value: &Value) -> Result<(), AlgorithmParamError> {
+        match param {
+            "failed_order_retries" => self.failed_order_retries = parse_param(param, value)?,
+            "drop_failed_orders" => self.drop_failed_orders = parse_param(param, value)?,
+            "build_duration_deadline_ms" => {
+                self.build_duration_deadline_ms = parse_param(param, value)?
+            }
+            "zero_profit_txs" => self.zero_profit_txs = parse_param(param, value)?,
+            "zero_profit_tx_max_gas" => self.zero_profit_tx_max_gas = parse_param(param, value)?,
+            "value_per_cost_window_ms" => {
+                self.value_per_cost_window_ms = parse_param(param, value)?
+            }
+            _ => return Err(not_tunable::<Self>(param)),
+        }
+        Ok(())
+    }
+}
+
+impl TunableConfig for ParallelBuilderConfig {
+    const TUNABLE_PARAMS: &'static [&'static str] = &[
+        "max_group_len_for_exhaustive_search",
+        "min_time_left_for_exhaustive_search_ms",
+        "min_time_left_for_heuristic_search_ms",
+        "adaptive_exhaustive_search_min_gain_bps",
+        "prune_groups_below_marginal_gas_value",
+        "max_conflict_group_len",
+        "zero_profit_txs",
+        "zero_profit_tx_max_gas",
+        "value_per_cost_window_ms",
+    ];
+
+    fn set_param(&mut self, param: &str, value: &Value) -> Result<(), AlgorithmParamError> {
+        match param {
+            "max_group_len_for_exhaustive_search" => {
+                self.max_group_len_for_exhaustive_search = parse_param(param, value)?
+            }
+            "min_time_left_for_exhaustive_search_ms" => {
+                self.min_time_left_for_exhaustive_search_ms = parse_param(param, value)?
+            }
+            "min_time_left_for_heuristic_search_ms" => {
+                self.min_time_left_for_heuristic_search_ms = parse_param(param, value)?
+            }
+            "adaptive_exhaustive_search_min_gain_bps" => {
+                self.adaptive_exhaustive_search_min_gain_bps = parse_param(param, value)?
+            }
+            "prune_groups_below_marginal_gas_value" => {
+                self.prune_groups_below_marginal_gas_value = parse_param(param, value)?
+            }
+            "max_conflict_group_len" => self.max_conflict_group_len = parse_param(param, value)?,
+            "zero_profit_txs" => self.zero_profit_txs = parse_param(param, value)?,
+            "zero_profit_tx_max_gas" => self.zero_profit_tx_max_gas = parse_param(param, value)?,
+            "value_per_cost_window_ms" => {
+                self.value_per_cost_window_ms = parse_param(param, value)?
+            }
+            _ => return Err(not_tunable::<Self>(param)),
+        }
+        Ok(())
+    }
+}
+
+fn with_overrides<C: TunableConfig>(
+    config: &C,
+    overrides: &Overrides,
+) -> Result<C, AlgorithmParamError> {
+    let mut config = config.clone();
+    for (param, value) in overrides {
+        config.set_param(param, value)?;
+    }
+    Ok(config)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlgorithmParamChange {
+    pub timestamp_ms: u64,
+    pub builder: String,
+    pub param: String,
+    /// None -> default from the config.
+    pub previous: Option<Value>,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuilderParamsReport {
+    pub tunable_params: &'static [&'static str],
+    pub overrides: Overrides,
+}
+
+type ValidateFn = dyn Fn(&Overrides) -> Result<(), AlgorithmParamError> + Send + Sync;
+
+struct RegisteredBuilder {
+    tunable_params: &'static [&'static str],
+    /// Checks that the overrides apply to the builder config.
+    validate: Box<ValidateFn>,
+}
+
+#[derive(Default)]
+struct AlgorithmParams {
+    builders: HashMap<String, RegisteredBuilder>,
+    overrides: BTreeMap<String, Overrides>,
+    journal: VecDeque<AlgorithmParamChange>,
+    persist_path: Option<PathBuf>,
+}
+
+impl AlgorithmParams {
+    fn validate(&self, builder: &str, overrides: &Overrides) -> Result<(), AlgorithmParamError> {
+        let registered = self
+            .builders
+            .get(builder)
+            .ok_or_else(|| AlgorithmParamError::UnknownBuilder(builder.to_string()))?;
+        (registered.validate)(overrides)
+    }
+
+    fn journal_change(
+        &mut self,
+        builder: &str,
+        param: &str,
+        previous: Option<Value>,
+        value: Option<Value>,
+    ) {
+        info!(builder, param, ?previous, ?value, "Algorithm param changed");
+        if self.journal.len() >= MAX_JOURNAL_LEN {
+            self.journal.pop_front();
+        }
+        self.journal.push_back(AlgorithmParamChange {
+            timestamp_ms: (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64,
+            builder: builder.to_string(),
+            param: param.to_string(),
+            previous,
+            value,
+        });
+    }
+
+    /// Returns the previous override.
+    fn set_param(
+        &mut self,
+        builder: &str,
+        param: &str,
+        value: Value,
+    ) -> Result<Option<Value>, AlgorithmParamError> {
+        let mut overrides = self.overrides.get(builder).cloned().unwrap_or_default();
+        let previous = overrides.insert(param.to_string(), value.clone());
+        self.validate(builder, &overrides)?;
+        self.overrides.insert(builder.to_string(), overrides);
+        self.journal_change(builder, param, previous.clone(), Some(value));
+        Ok(previous)
+    }
+
+    /// param None -> all the overrides of the builder.
+    fn reset_params(&mut self, builder: &str, param: Option<&str>) {
+        let Some(overrides) = self.overrides.get_mut(builder) else {
+            return;
+        };
+        let removed: Vec<_> = match param {
+            Some(param) => overrides.remove_entry(param).into_iter().collect(),
+            None => std::mem::take(overrides).into_iter().collect(),
+        };
+        if overrides.is_empty() {
+            self.overrides.remove(builder);
+        }
+        for (param, previous) in removed {
+            self.journal_change(builder, &param, Some(previous), None);
+        }
+    }
+
+    fn report(&self) -> BTreeMap<String, BuilderParamsReport> {
+        self.builders
+            .iter()
+            .map(|(name, registered)| {
+                (
+                    name.clone(),
+                    BuilderParamsReport {
+                        tunable_params: registered.tunable_params,
+                        overrides: self.overrides.get(name).cloned().unwrap_or_default(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Loads the persisted overrides, the ones that don't apply anymore are dropped.
+    fn load(&mut self, persisted: BTreeMap<String, Overrides>) {
+        for (builder, overrides) in persisted {
+            if let Err(err) = self.validate(&builder, &overrides) {
+                warn!(builder, ?err, "Ignoring persisted algorithm params");
+                continue;
+            }
+            info!(builder, ?overrides, "Loaded persisted algorithm params");
+            self.overrides.insert(builder, overrides);
+        }
+    }
+
+    fn persist(&self) -> Result<(), AlgorithmParamError> {
+        let path = self
+            .persist_path
+            .as_ref()
+            .ok_or(AlgorithmParamError::NoPersistPath)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&self.overrides)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref ALGORITHM_PARAMS: Mutex<AlgorithmParams> = Mutex::new(AlgorithmParams::default());
+}
+
+/// Makes the builder tunable, config is the one from the config file.
+pub fn register_builder<C: TunableConfig>(name: &str, config: &C) {
+    let config = config.clone();
+    ALGORITHM_PARAMS.lock().builders.insert(
+        name.to_string(),
+        RegisteredBuilder {
+            tunable_params: C::TUNABLE_PARAMS,
+            validate: Box::new(move |overrides| with_overrides(&config, overrides).map(|_| ())),
+        },
+    );
+}
+
+/// Config to use for the next slot.
+pub fn apply_overrides<C: TunableConfig>(name: &str, config: &C) -> C {
+    let params = ALGORITHM_PARAMS.lock();
+    let Some(overrides) = params.overrides.get(name) else {
+        return config.clone();
+    };
+    // overrides are validated when set
+    with_overrides(config, overrides).unwrap_or_else(|err| {
+        warn!(builder = name, ?err, "Failed to apply algorithm params");
+        config.clone()
+    })
+}
+
+/// Call after registering the builders. If persist_path exists the overrides in it are loaded.
+pub fn init_algorithm_params(persist_path: Option<PathBuf>) -> eyre::Result<()> {
+    let mut params = ALGORITHM_PARAMS.lock();
+    if let Some(path) = &persist_path {
+        if path.exists() {
+            params.load(serde_json::from_slice(&std::fs::read(path)?)?);
+        }
+    }
+    params.persist_path = persist_path;
+    Ok(())
+}
+
+fn invalid_params_error(err: impl std::fmt::Display) -> ErrorObject<'static> {
+    ErrorObject::owned(-32602, err.to_string(), None::<()>)
+}
+
+/// - admin_algorithmParams(): tunable params and current overrides of every builder.
+/// - admin_setAlgorithmParam(builder, param, value): returns the previous override.
+/// - admin_resetAlgorithmParams(builder, param?): back to the config value (all params if param is not given).
+/// - admin_algorithmParamsJournal(): last changes, oldest first.
+/// - admin_persistAlgorithmParams(): saves the current overrides so they survive restarts.
+pub fn algorithm_params_rpc_module() -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    module.register_method("admin_algorithmParams", |_, _| {
+        Ok::<_, ErrorObject<'static>>(ALGORITHM_PARAMS.lock().report())
+    })?;
+    module.register_method("admin_setAlgorithmParam", |params, _| {
+        let (builder, param, value): (String, String, Value) =
+            params.parse().map_err(invalid_params_error)?;
+        ALGORITHM_PARAMS
+            .lock()
+            .set_param(&builder, &param, value)
+            .map_err(invalid_params_error)
+    })?;
+    module.register_method("admin_resetAlgorithmParams", |params, _| {
+        let mut seq = params.sequence();
+        let builder: String = seq.next().map_err(invalid_params_error)?;
+        let param: Option<String> = seq.optional_next().map_err(invalid_params_error)?;
+        ALGORITHM_PARAMS
+            .lock()
+            .reset_params(&builder, param.as_deref());
+        Ok::<_, ErrorObject<'static>>(())
+    })?;
+    module.register_method("admin_algorithmParamsJournal", |_, _| {
+        Ok::<_, ErrorObject<'static>>(ALGORITHM_PARAMS.lock().journal.clone())
+    })?;
+    module.register_method("admin_persistAlgorithmParams", |_, _| {
+        ALGORITHM_PARAMS
+            .lock()
+            .persist()
+            .map_err(|err| ErrorObject::owned(-32000, err.to_string(), None::<()>))
+    })?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::Sorting;
+    use serde_json::json;
+
+    fn ordering_config() -> OrderingBuilderConfig {
+        OrderingBuilderConfig {
+            discard_txs: true,
+            sorting: Sorting::MaxProfit,
+            failed_order_retries: 1,
+            drop_failed_orders: true,
+            coinbase_payment: false,
+            build_duration_deadline_ms: None,
+            zero_profit_txs: Default::default(),
+            zero_profit_tx_max_gas: None,
+            value_per_cost_window_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_algorithm_params() {
+        let config = ordering_config();
+        let mut params = AlgorithmParams::default();
+        params.builders.insert(
+            "ordering".to_string(),
+            RegisteredBuilder {
+                tunable_params: OrderingBuilderConfig::TUNABLE_PARAMS,
+                validate: Box::new(move |overrides| with_overrides(&config, overrides).map(|_| ())),
+            },
+        );
+
+        assert_eq!(
+            params
+                .set_param("ordering", "failed_order_retries", json!(5))
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            params.set_param("ordering", "failed_order_retries", json!("many")),
+            Err(AlgorithmParamError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            params.set_param("ordering", "discard_txs", json!(false)),
+            Err(AlgorithmParamError::NotTunable { .. })
+        ));
+        assert!(matches!(
+            params.set_param("parallel", "failed_order_retries", json!(5)),
+            Err(AlgorithmParamError::UnknownBuilder(_))
+        ));
+        params
+            .set_param("ordering", "build_duration_deadline_ms", json!(200))
+            .unwrap();
+
+        let tuned = with_overrides(&ordering_config(), &params.overrides["ordering"]).unwrap();
+        assert_eq!(tuned.failed_order_retries, 5);
+        assert_eq!(tuned.build_duration_deadline_ms, Some(200));
+        assert_eq!(tuned.discard_txs, ordering_config().discard_txs);
+
+        params.reset_params("ordering", Some("failed_order_retries"));
+        assert_eq!(
+            params.report()["ordering"].overrides,
+            BTreeMap::from([("build_duration_deadline_ms".to_string(), json!(200))])
+        );
+        params.reset_params("ordering", None);
+        assert!(params.overrides.is_empty());
+
+        // 2 sets + 2 resets, failed sets are not journaled
+        let journal: Vec<_> = params
+            .journal
+            .iter()
+            .map(|change| (change.param.as_str(), change.value.clone()))
+            .collect();
+        assert_eq!(
+            journal,
+            vec![
+                ("failed_order_retries", Some(json!(5))),
+                ("build_duration_deadline_ms", Some(json!(200))),
+                ("failed_order_retries", None),
+                ("build_duration_deadline_ms", None),
+            ]
+        );
+
+        // persisted params that don't apply anymore are dropped
+        params.load(BTreeMap::from([
+            (
+                "ordering".to_string(),
+                BTreeMap::from([("failed_order_retries".to_string(), json!(2))]),
+            ),
+            (
+                "removed_builder".to_string(),
+                BTreeMap::from([("failed_order_retries".to_string(), json!(2))]),
+            ),
+        ]));
+        assert_eq!(params.overrides.len(), 1);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     cmp::max,
     marker::PhantomData,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -21,7 +22,7 @@ use crate::{
     },
     primitives::SimulatedOrder,
     roothash::RootHashConfig,
-    telemetry,
+    telemetry::{self, record_order_drop, OrderDropStage},
 };
 
 use super::Block;
@@ -317,6 +318,8 @@ where
                 Err(err) => {
                     self.built_block_trace
                         .modify_payment_when_no_signer_error(&err);
+                    self.built_block_trace.add_excluded_order(order.id(), &err);
+                    record_order_drop(order.id(), OrderDropStage::Building, err.reason());
                     Ok(Err(err))
                 }
             },
@@ -381,6 +384,14 @@ where
                 return Err(BlockBuildingHelperError::FinalizeError(err));
             }
         };
+        self.built_block_trace.verify_block_txs(
+            finalized_block
+                .sealed_block
+                .body
+                .transactions
+                .iter()
+                .map(|tx| tx.hash()),
+        )?;
         self.built_block_trace.update_orders_sealed_at();
         self.built_block_trace.root_hash_time = finalized_block.root_hash_time;
 
@@ -398,8 +409,11 @@ where
             trace: self.built_block_trace,
             sealed_block: finalized_block.sealed_block,
             txs_blobs_sidecars: finalized_block.txs_blob_sidecars,
+            encoded_txs: finalized_block.encoded_txs,
             builder_name: self.builder_name.clone(),
             execution_requests: finalized_block.execution_requests,
+            payment_proof: finalized_block.payment_proof,
+            block_template_source: finalized_block.block_template_source.map(Arc::new),
         };
         Ok(FinalizeBlockResult {
             block,
@@ -80,8 +80,11 @@ impl BlockBuildingHelper for MockBlockBuildingHelper {
             trace: self.built_block_trace,
             sealed_block: SealedBlock::default(),
             txs_blobs_sidecars: Vec::new(),
+            encoded_txs: Vec::new(),
             builder_name: "BlockBuildingHelper".to_string(),
             execution_requests: Default::default(),
+            payment_proof: None,
+            block_template_source: None,
         };
 
         Ok(FinalizeBlockResult {
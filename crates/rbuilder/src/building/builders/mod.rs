@@ -1,15 +1,22 @@
 //! builders is a subprocess that builds a block
+pub mod algorithm_params;
 pub mod block_building_helper;
 pub mod mock_block_building_helper;
 pub mod ordering_builder;
 pub mod parallel_builder;
 
 use crate::{
-    building::{BlockBuildingContext, BlockOrders, BuiltBlockTrace, SimulatedOrderSink, Sorting},
+    building::{
+        block_template::BlockTemplateSource, BlockBuildingContext, BlockOrders, BuiltBlockTrace,
+        SimulatedOrderSink, Sorting,
+    },
     live_builder::{payload_events::MevBoostSlotData, simulation::SimulatedOrderCommand},
-    primitives::{AccountNonce, OrderId, SimulatedOrder},
-    roothash::RootHashConfig,
-    utils::{is_provider_factory_health_error, NonceCache},
+    primitives::{AccountNonce, Order, OrderId, SimulatedOrder},
+    roothash::{payment_proof::ProposerPaymentProof, RootHashConfig},
+    utils::{
+        clock::{Clock, ClockRef},
+        is_provider_factory_health_error, NonceCache,
+    },
 };
 use ahash::HashSet;
 use alloy_primitives::{Address, Bytes, B256};
@@ -21,6 +28,7 @@ use reth::{
 use reth_db::Database;
 use reth_errors::ProviderError;
 use reth_provider::{DatabaseProviderFactory, StateProviderFactory};
+use serde::Deserialize;
 use std::{fmt::Debug, marker::PhantomData, sync::Arc};
 use tokio::sync::{broadcast, broadcast::error::TryRecvError};
 use tokio_util::sync::CancellationToken;
@@ -33,8 +41,15 @@ pub struct Block {
     pub sealed_block: SealedBlock,
     /// Sidecars for the txs included in SealedBlock
     pub txs_blobs_sidecars: Vec<Arc<BlobTransactionSidecar>>,
+    /// Canonical envelopes of the txs included in SealedBlock (in the same order), used to build the payload
+    /// without re-encoding.
+    pub encoded_txs: Vec<Bytes>,
     /// The Pectra execution requests for this bid.
     pub execution_requests: Vec<Bytes>,
+    /// For relays that need to check the proposer payment (see [`crate::roothash::payment_proof`]).
+    pub payment_proof: Option<ProposerPaymentProof>,
+    /// Generates the [`crate::building::block_template::BlockTemplate`] if we submit the block.
+    pub block_template_source: Option<Arc<BlockTemplateSource>>,
     pub builder_name: String,
 }
 
@@ -234,10 +249,12 @@ where
 pub trait UnfinishedBlockBuildingSinkFactory: Debug + Send + Sync {
     /// Creates an UnfinishedBlockBuildingSink to receive block for slot_data.
     /// cancel: If this is signaled the sink should cancel. If any unrecoverable situation is found signal cancel.
+    /// clock: Time source of the slot (the [BlockBuildingContext::clock]).
     fn create_sink(
         &mut self,
         slot_data: MevBoostSlotData,
         cancel: CancellationToken,
+        clock: ClockRef,
     ) -> Arc<dyn UnfinishedBlockBuildingSink>;
 }
 
@@ -253,6 +270,50 @@ pub struct BacktestSimulateBlockInput<'a, P> {
 
 /// Handles error from block filling stage.
 /// Answers if block filling should continue.
+/// Zero profit txs don't change the bid, they only make the block fuller (some operators want this for propagation
+/// or statistics) at the cost of execution time that could go to paying orders.
+/// Builders only try them once every other order was, to backfill the block.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ZeroProfitTxPolicy {
+    /// Use them to backfill the block.
+    #[default]
+    Include,
+    /// Never execute them.
+    Exclude,
+}
+
+impl ZeroProfitTxPolicy {
+    /// false if sim_order is a zero profit tx we don't want.
+    /// With Include, zero profit txs using more gas than max_gas are not wanted either.
+    pub fn accepts(&self, sim_order: &SimulatedOrder, max_gas: Option<u64>) -> bool {
+        if !is_zero_profit_tx(sim_order) {
+            return true;
+        }
+        match self {
+            ZeroProfitTxPolicy::Include => {
+                max_gas.map_or(true, |max_gas| sim_order.sim_value.gas_used <= max_gas)
+            }
+            ZeroProfitTxPolicy::Exclude => false,
+        }
+    }
+}
+
+/// Mempool tx paying only the base fee (no tip, no direct payment) on its top of block simulation.
+pub fn is_zero_profit_tx(sim_order: &SimulatedOrder) -> bool {
+    matches!(sim_order.order, Order::Tx(_)) && sim_order.sim_value.coinbase_profit.is_zero()
+}
+
+/// If we are in the last window_ms before the slot timestamp. There CPU time, not gas, limits what can still be added
+/// to the blocks so builders prefer the orders with the best value per [`ExecutionCost`](crate::primitives::ExecutionCost).
+pub fn in_value_per_cost_window(ctx: &BlockBuildingContext, window_ms: Option<u64>) -> bool {
+    let Some(window_ms) = window_ms else {
+        return false;
+    };
+    let time_left = ctx.timestamp() - ctx.clock.now_utc();
+    time_left.is_positive() && time_left < time::Duration::milliseconds(window_ms as i64)
+}
+
 pub fn handle_building_error(err: eyre::Report) -> bool {
     // @Types
     let err_str = err.to_string();
@@ -266,3 +327,26 @@ pub fn handle_building_error(err: eyre::Report) -> bool {
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::SimulatedClock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_in_value_per_cost_window() {
+        let mut ctx = BlockBuildingContext::dummy_for_testing();
+        let clock = Arc::new(SimulatedClock::new(
+            ctx.timestamp() - Duration::from_millis(100),
+        ));
+        ctx.clock = clock.clone();
+        assert!(!in_value_per_cost_window(&ctx, Some(50)));
+        assert!(!in_value_per_cost_window(&ctx, None));
+        clock.advance(Duration::from_millis(60));
+        assert!(in_value_per_cost_window(&ctx, Some(50)));
+        // the slot started
+        clock.advance(Duration::from_millis(40));
+        assert!(!in_value_per_cost_window(&ctx, Some(50)));
+    }
+}
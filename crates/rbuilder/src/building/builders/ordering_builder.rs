@@ -4,17 +4,26 @@
 //! but it can be later reused.
 //! The described algorithm is ran continuously adding new SimulatedOrders (they arrive on real time!) on each iteration until we run out of time (slot ends).
 //! Sorting criteria are described on [`Sorting`].
+//! With build_duration_deadline_ms CPU time (not gas) can be what limits the block, near the deadline orders whose
+//! [`crate::primitives::ExecutionCost`] does not fit the time left are skipped so that time goes to cheaper orders.
+//! In the last value_per_cost_window_ms of the slot the next orders are taken by value per execution cost instead
+//! (see [`pop_best_value_per_cost`]).
+//! Mempool txs that only pay the base fee (see [`is_zero_profit_tx`]) are not committed by the main loop, once every
+//! other order was tried they backfill the block ([`ZeroProfitTxPolicy`] decides if we want them at all).
 //! For some more details see [`OrderingBuilderConfig`]
 use crate::{
     building::{
         block_orders_from_sim_orders,
         builders::{
-            block_building_helper::BlockBuildingHelper, LiveBuilderInput, OrderIntakeConsumer,
+            algorithm_params::apply_overrides, block_building_helper::BlockBuildingHelper,
+            in_value_per_cost_window, is_zero_profit_tx, LiveBuilderInput, OrderIntakeConsumer,
+            ZeroProfitTxPolicy,
         },
         BlockBuildingContext, BlockOrders, ExecutionError, Sorting,
     },
-    primitives::{AccountNonce, OrderId},
+    primitives::{AccountNonce, OrderId, SimulatedOrder},
     roothash::RootHashConfig,
+    telemetry::{record_order_drop, OrderDropStage, BUILDING_ZERO_PROFIT_TX},
 };
 use ahash::{HashMap, HashSet};
 use alloy_primitives::Address;
@@ -23,6 +32,7 @@ use reth_db::database::Database;
 use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
 use serde::Deserialize;
 use std::{
+    cmp::Reverse,
     marker::PhantomData,
     time::{Duration, Instant},
 };
@@ -54,12 +64,55 @@ pub struct OrderingBuilderConfig {
     /// Amount of time allocated for EVM execution while building block.
     #[serde(default)]
     pub build_duration_deadline_ms: Option<u64>,
+    /// What to do with mempool txs with no coinbase profit (no tip, no direct payment).
+    #[serde(default)]
+    pub zero_profit_txs: ZeroProfitTxPolicy,
+    /// With zero_profit_txs = "include", zero profit txs using more gas than this are skipped anyway.
+    #[serde(default)]
+    pub zero_profit_tx_max_gas: Option<u64>,
+    /// Last ms of the slot where orders are taken by value per execution cost (see [`in_value_per_cost_window`]).
+    #[serde(default)]
+    pub value_per_cost_window_ms: Option<u64>,
+}
+
+/// Orders compared by [`pop_best_value_per_cost`].
+const VALUE_PER_COST_CANDIDATES: usize = 8;
+
+/// Of the next VALUE_PER_COST_CANDIDATES orders (by the store priority) pops the one with the best coinbase profit
+/// per [`crate::primitives::ExecutionCost`], the other candidates go back to the store.
+fn pop_best_value_per_cost(block_orders: &mut BlockOrders) -> Option<SimulatedOrder> {
+    let mut candidates = Vec::with_capacity(VALUE_PER_COST_CANDIDATES);
+    while candidates.len() < VALUE_PER_COST_CANDIDATES {
+        match block_orders.pop_order() {
+            Some(sim_order) => candidates.push(sim_order),
+            None => break,
+        }
+    }
+    // on ties the first one (best priority) wins
+    let (best, _) = candidates.iter().enumerate().min_by_key(|(_, sim_order)| {
+        Reverse(
+            sim_order
+                .execution_cost
+                .value_per_cost(sim_order.sim_value.coinbase_profit),
+        )
+    })?;
+    let best = candidates.swap_remove(best);
+    for sim_order in candidates {
+        block_orders.readd_order(sim_order);
+    }
+    Some(best)
 }
 
 impl OrderingBuilderConfig {
     pub fn build_duration_deadline(&self) -> Option<Duration> {
         self.build_duration_deadline_ms.map(Duration::from_millis)
     }
+
+    /// See [`ZeroProfitTxPolicy::accepts`].
+    pub fn accepts_order(&self, sim_order: &SimulatedOrder) -> bool {
+        self.zero_profit_txs
+            .accepts(sim_order, self.zero_profit_tx_max_gas)
+    }
 }
 
 pub fn run_ordering_builder<P, DB>(input: LiveBuilderInput<P, DB>, config: &OrderingBuilderConfig)
@@ -136,6 +189,22 @@ pub fn backtest_simulate_block<P, DB>(
     ordering_config: OrderingBuilderConfig,
     input: BacktestSimulateBlockInput<'_, P>,
 ) -> eyre::Result<(Block, CachedReads)>
+where
+    DB: Database + Clone + 'static,
+    P: DatabaseProviderFactory<DB = DB, Provider: BlockReader>
+        + StateProviderFactory
+        + Clone
+        + 'static,
+{
+    build_block_from_sim_orders(ordering_config, input, RootHashConfig::skip_root_hash())
+}
+
+/// Runs a single block building iteration with the given simulated orders and finalizes the block.
+pub fn build_block_from_sim_orders<P, DB>(
+    ordering_config: OrderingBuilderConfig,
+    input: BacktestSimulateBlockInput<'_, P>,
+    root_hash_config: RootHashConfig,
+) -> eyre::Result<(Block, CachedReads)>
 where
     DB: Database + Clone + 'static,
     P: DatabaseProviderFactory<DB = DB, Provider: BlockReader>
@@ -158,7 +227,7 @@ where
         input.builder_name,
         input.ctx.clone(),
         ordering_config,
-        RootHashConfig::skip_root_hash(),
+        root_hash_config,
     )
     .with_cached_reads(input.cached_reads.unwrap_or_default());
     let block_builder = builder.build_block(
@@ -283,11 +352,51 @@ where
         build_start: Instant,
     ) -> eyre::Result<()> {
         let mut order_attempts: HashMap<OrderId, usize> = HashMap::default();
+        // an order failing again on the same nonce is dropped, its gap is not going to be filled
+        let mut deferred_orders: HashSet<(OrderId, AccountNonce)> = HashSet::default();
+        let mut backfill_txs = Vec::new();
         // @Perf when gas left is too low we should break.
-        while let Some(sim_order) = block_orders.pop_order() {
+        loop {
+            let sim_order =
+                if in_value_per_cost_window(&self.ctx, self.config.value_per_cost_window_ms) {
+                    pop_best_value_per_cost(&mut block_orders)
+                } else {
+                    block_orders.pop_order()
+                };
+            let Some(sim_order) = sim_order else {
+                break;
+            };
+            if is_zero_profit_tx(&sim_order) {
+                if self.config.accepts_order(&sim_order) {
+                    backfill_txs.push(sim_order);
+                } else {
+                    trace!(
+                        order_id = ?sim_order.id(),
+                        gas_used = sim_order.sim_value.gas_used,
+                        "Skipping zero profit tx"
+                    );
+                    record_order_drop(
+                        sim_order.id(),
+                        OrderDropStage::Building,
+                        BUILDING_ZERO_PROFIT_TX,
+                    );
+                }
+                continue;
+            }
             if let Some(deadline) = self.config.build_duration_deadline() {
-                if build_start.elapsed() > deadline {
-                    break;
+                let elapsed = build_start.elapsed();
+                if elapsed > deadline {
+                    // no time left for the backfill either
+                    return Ok(());
+                }
+                if sim_order.execution_cost.evm_time > deadline - elapsed {
+                    trace!(
+                        order_id = ?sim_order.id(),
+                        evm_time_mus = sim_order.execution_cost.evm_time.as_micros(),
+                        state_reads = sim_order.execution_cost.state_reads,
+                        "Skipping order, execution cost does not fit the build deadline"
+                    );
+                    continue;
                 }
             }
             let start_time = Instant::now();
@@ -296,6 +405,7 @@ where
             let mut gas_used = 0;
             let mut execution_error = None;
             let mut reinserted = false;
+            let mut deferred = false;
             let success = commit_result.is_ok();
             match commit_result {
                 Ok(res) => {
@@ -324,7 +434,19 @@ where
                         }
                     }
                     if !reinserted {
-                        self.failed_orders.insert(sim_order.id());
+                        // nonce gap: retry when some other order (or the chain) fills it instead of dropping it
+                        let gap = err
+                            .try_get_tx_too_high_error(&sim_order.order)
+                            .map(|(account, nonce)| AccountNonce { account, nonce })
+                            .filter(|until| {
+                                deferred_orders.insert((sim_order.id(), until.clone()))
+                            });
+                        if let Some(until) = gap {
+                            block_orders.defer_order(sim_order.clone(), until);
+                            deferred = true;
+                        } else {
+                            self.failed_orders.insert(sim_order.id());
+                        }
                     }
                     execution_error = Some(err);
                 }
@@ -336,9 +458,40 @@ where
                 gas_used,
                 ?execution_error,
                 reinserted,
+                deferred,
                 "Executed order"
             );
         }
+        self.backfill(block_building_helper, backfill_txs, build_start)
+    }
+
+    /// Commits the zero profit txs (in the order they were popped) into the gas left by the paying orders.
+    fn backfill(
+        &mut self,
+        block_building_helper: &mut dyn BlockBuildingHelper,
+        backfill_txs: Vec<SimulatedOrder>,
+        build_start: Instant,
+    ) -> eyre::Result<()> {
+        for sim_order in backfill_txs {
+            if self
+                .config
+                .build_duration_deadline()
+                .is_some_and(|deadline| build_start.elapsed() > deadline)
+            {
+                break;
+            }
+            let commit_result = block_building_helper.commit_order(&sim_order)?;
+            let success = commit_result.is_ok();
+            if !success {
+                self.failed_orders.insert(sim_order.id());
+            }
+            trace!(
+                order_id = ?sim_order.id(),
+                success,
+                execution_error = ?commit_result.err(),
+                "Backfilled zero profit tx"
+            );
+        }
         Ok(())
     }
 }
@@ -391,6 +544,175 @@ where
             sbundle_mergeabe_signers: self.sbundle_mergeabe_signers.clone(),
             phantom: Default::default(),
         };
-        run_ordering_builder(live_input, &self.config);
+        run_ordering_builder(live_input, &apply_overrides(&self.name, &self.config));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::{
+            testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+            Sorting,
+        },
+        primitives::{
+            Bundle, ExecutionCost, MempoolTx, Order, SimValue,
+            TransactionSignedEcRecoveredWithBlobs,
+        },
+        utils::test_utils::{tx, u256},
+    };
+
+    fn sim_tx(coinbase_profit: u64, gas_used: u64) -> SimulatedOrder {
+        SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(tx(1))),
+            sim_value: SimValue::new(u256(coinbase_profit), gas_used, 0, Vec::new()),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_zero_profit_tx_policy() {
+        let mut config = OrderingBuilderConfig {
+            discard_txs: true,
+            sorting: Sorting::MaxProfit,
+            failed_order_retries: 1,
+            drop_failed_orders: true,
+            coinbase_payment: false,
+            build_duration_deadline_ms: None,
+            zero_profit_txs: ZeroProfitTxPolicy::Include,
+            zero_profit_tx_max_gas: None,
+            value_per_cost_window_ms: None,
+        };
+        assert!(config.accepts_order(&sim_tx(0, 1_000_000)));
+        assert!(is_zero_profit_tx(&sim_tx(0, 21_000)));
+        assert!(!is_zero_profit_tx(&sim_tx(1, 21_000)));
+
+        config.zero_profit_tx_max_gas = Some(100_000);
+        assert!(config.accepts_order(&sim_tx(0, 21_000)));
+        assert!(!config.accepts_order(&sim_tx(0, 1_000_000)));
+        assert!(config.accepts_order(&sim_tx(1, 1_000_000)));
+
+        config.zero_profit_txs = ZeroProfitTxPolicy::Exclude;
+        assert!(!config.accepts_order(&sim_tx(0, 21_000)));
+        assert!(config.accepts_order(&sim_tx(1, 21_000)));
+    }
+
+    #[test]
+    fn test_pop_best_value_per_cost() {
+        let sim_order = |id, coinbase_profit, evm_time_mus| SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(tx(id))),
+            execution_cost: ExecutionCost {
+                evm_time: Duration::from_micros(evm_time_mus),
+                state_reads: 0,
+            },
+            ..sim_tx(coinbase_profit, 21_000)
+        };
+        // profit per µs: 1, 5, 10
+        let orders = [
+            sim_order(1, 100, 100),
+            sim_order(2, 50, 10),
+            sim_order(3, 10, 1),
+        ];
+        let block_orders = || {
+            let mut block_orders = BlockOrders::new(Sorting::MaxProfit, [], &[]);
+            for order in &orders {
+                block_orders.add_order(order.clone());
+            }
+            block_orders
+        };
+
+        assert_eq!(block_orders().pop_order(), Some(orders[0].clone()));
+        let mut block_orders = block_orders();
+        for expected in orders.iter().rev() {
+            assert_eq!(
+                pop_best_value_per_cost(&mut block_orders),
+                Some(expected.clone())
+            );
+        }
+        assert_eq!(pop_best_value_per_cost(&mut block_orders), None);
+    }
+
+    #[test]
+    fn test_nonce_gap_order_is_deferred() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let tx = |from, nonce| -> eyre::Result<_> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(from, nonce, 5))?;
+            Ok(TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap())
+        };
+        let sim_order = |order, coinbase_profit| SimulatedOrder {
+            order,
+            sim_value: SimValue::new(u256(coinbase_profit), 21_000, 0, Vec::new()),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        // the optional (so the store doesn't wait for it) nonce 1 of User(1) fails with discard_txs off
+        let gap_tx = tx(NamedAddr::User(1), 1)?;
+        let mut bundle = Bundle {
+            block: 11,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: vec![tx(NamedAddr::User(2), 0)?, gap_tx.clone()],
+            reverting_tx_hashes: vec![gap_tx.hash()],
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        let gapped = sim_order(Order::Bundle(bundle), 2);
+        let gap_filler = sim_order(Order::Tx(MempoolTx::new(tx(NamedAddr::User(1), 0)?)), 1);
+
+        let mut block_orders = BlockOrders::new(
+            Sorting::MaxProfit,
+            [NamedAddr::User(1), NamedAddr::User(2)]
+                .into_iter()
+                .map(|user| {
+                    Ok(AccountNonce {
+                        account: test_chain.named_address(user)?,
+                        nonce: 0,
+                    })
+                })
+                .collect::<eyre::Result<Vec<_>>>()?,
+            &[],
+        );
+        block_orders.add_order(gapped.clone());
+        block_orders.add_order(gap_filler.clone());
+
+        let config = OrderingBuilderConfig {
+            discard_txs: false,
+            sorting: Sorting::MaxProfit,
+            failed_order_retries: 1,
+            drop_failed_orders: true,
+            coinbase_payment: false,
+            build_duration_deadline_ms: None,
+            zero_profit_txs: ZeroProfitTxPolicy::Include,
+            zero_profit_tx_max_gas: None,
+            value_per_cost_window_ms: None,
+        };
+        let mut builder = OrderingBuilderContext::new(
+            test_chain.provider_factory().clone(),
+            "test".to_string(),
+            test_chain.block_building_context().clone(),
+            config,
+            RootHashConfig::skip_root_hash(),
+        );
+        let block = builder.build_block(block_orders, false, CancellationToken::new())?;
+        // popped first, failed, and committed again once the filler reached the nonce
+        assert_eq!(
+            block
+                .built_block_trace()
+                .included_orders
+                .iter()
+                .map(|res| res.order.id())
+                .collect::<Vec<_>>(),
+            vec![gap_filler.id(), gapped.id()]
+        );
+        assert!(builder.failed_orders.is_empty());
+        Ok(())
     }
 }
@@ -3,11 +3,11 @@ use super::{
     ResolutionResult,
 };
 use ahash::HashMap;
-use alloy_primitives::utils::format_ether;
+use alloy_primitives::{utils::format_ether, Address, U256};
 use reth::revm::cached::CachedReads;
 use reth_db::Database;
 use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
-use std::{marker::PhantomData, sync::Arc, time::Instant};
+use std::{borrow::Cow, cmp::Reverse, marker::PhantomData, sync::Arc, time::Instant};
 use time::OffsetDateTime;
 use tokio_util::sync::CancellationToken;
 use tracing::{info_span, trace};
@@ -16,13 +16,100 @@ use crate::{
     building::{
         builders::{
             block_building_helper::{BlockBuildingHelper, BlockBuildingHelperFromProvider},
-            handle_building_error, UnfinishedBlockBuildingSink,
+            handle_building_error, in_value_per_cost_window, is_zero_profit_tx,
+            UnfinishedBlockBuildingSink, ZeroProfitTxPolicy,
         },
         BlockBuildingContext,
     },
+    primitives::{ExecutionCost, SimulatedOrder},
     roothash::RootHashConfig,
 };
 
+/// Commits the zero profit txs after the groups, into the gas they left.
+fn backfill(
+    block_building_helper: &mut dyn BlockBuildingHelper,
+    backfill_txs: &[SimulatedOrder],
+    cancellation_token: &CancellationToken,
+) -> eyre::Result<()> {
+    for sim_order in backfill_txs {
+        if cancellation_token.is_cancelled() {
+            break;
+        }
+        let commit_result = block_building_helper.commit_order(sim_order)?;
+        trace!(
+            order_id = ?sim_order.id(),
+            success = commit_result.is_ok(),
+            execution_error = ?commit_result.err(),
+            "Backfilled zero profit tx"
+        );
+    }
+    Ok(())
+}
+
+/// Orders that failed on a nonce gap by the (account, nonce) they wait for.
+type DeferredOrders = HashMap<(Address, u64), Vec<SimulatedOrder>>;
+
+/// Commits sim_order. If it fails on a nonce gap (see
+/// [`crate::building::ExecutionError::try_get_tx_too_high_error`]), eg: the order
+/// filling it is in another group, it waits in deferred and is committed again right after an order reaching the nonce.
+fn commit_order(
+    block_building_helper: &mut dyn BlockBuildingHelper,
+    sim_order: &SimulatedOrder,
+    deferred: &mut DeferredOrders,
+) -> eyre::Result<()> {
+    let mut to_commit = vec![Cow::Borrowed(sim_order)];
+    while let Some(sim_order) = to_commit.pop() {
+        let start_time = Instant::now();
+        let commit_result = block_building_helper.commit_order(&sim_order)?;
+        let order_commit_time = start_time.elapsed();
+
+        let mut gas_used = 0;
+        let mut execution_error = None;
+        let mut deferred_order = false;
+        let success = commit_result.is_ok();
+        match commit_result {
+            Ok(res) => {
+                gas_used = res.gas_used;
+                for nonce in &res.nonces_updated {
+                    if let Some(orders) = deferred.remove(nonce) {
+                        to_commit.extend(orders.into_iter().map(Cow::Owned));
+                    }
+                }
+            }
+            Err(err) => {
+                if let Some(nonce) = err.try_get_tx_too_high_error(&sim_order.order) {
+                    deferred
+                        .entry(nonce)
+                        .or_default()
+                        .push(sim_order.clone().into_owned());
+                    deferred_order = true;
+                }
+                execution_error = Some(err);
+            }
+        }
+        trace!(
+            order_id = ?sim_order.id(),
+            success,
+            order_commit_time_mus = order_commit_time.as_micros(),
+            gas_used,
+            ?execution_error,
+            deferred = deferred_order,
+            "Executed order"
+        );
+    }
+    Ok(())
+}
+
+/// Total profit of the ordering per execution cost of its orders.
+fn value_per_cost(ordering: &ResolutionResult, group: &ConflictGroup) -> U256 {
+    ordering
+        .sequence_of_orders
+        .iter()
+        .map(|(order_idx, _)| group.orders[*order_idx].execution_cost)
+        .sum::<ExecutionCost>()
+        .value_per_cost(ordering.total_profit)
+}
+
 /// Assembles block building results from the best orderings of order groups.
 pub struct BlockBuildingResultAssembler<P, DB> {
     provider: P,
@@ -32,6 +119,9 @@ pub struct BlockBuildingResultAssembler<P, DB> {
     discard_txs: bool,
     coinbase_payment: bool,
     can_use_suggested_fee_recipient_as_coinbase: bool,
+    value_per_cost_window_ms: Option<u64>,
+    zero_profit_txs: ZeroProfitTxPolicy,
+    zero_profit_tx_max_gas: Option<u64>,
     root_hash_config: RootHashConfig,
     builder_name: String,
     sink: Option<Arc<dyn UnfinishedBlockBuildingSink>>,
@@ -77,6 +167,9 @@ where
             discard_txs: config.discard_txs,
             coinbase_payment: config.coinbase_payment,
             can_use_suggested_fee_recipient_as_coinbase,
+            value_per_cost_window_ms: config.value_per_cost_window_ms,
+            zero_profit_txs: config.zero_profit_txs,
+            zero_profit_tx_max_gas: config.zero_profit_tx_max_gas,
             root_hash_config,
             builder_name,
             sink,
@@ -103,7 +196,7 @@ where
                 break;
             }
             if self.best_results.get_number_of_orders() > 0 {
-                let orders_closed_at = OffsetDateTime::now_utc();
+                let orders_closed_at = self.ctx.clock.now_utc();
                 if !self.try_build_block(orders_closed_at) {
                     break;
                 }
@@ -215,11 +308,20 @@ where
         )?;
         block_building_helper.set_trace_orders_closed_at(orders_closed_at);
 
-        // Sort groups by total profit in descending order
-        best_orderings_per_group.sort_by(|(a_ordering, _), (b_ordering, _)| {
-            b_ordering.total_profit.cmp(&a_ordering.total_profit)
-        });
+        if in_value_per_cost_window(&self.ctx, self.value_per_cost_window_ms) {
+            // CPU bound, best profit per execution time first
+            best_orderings_per_group
+                .sort_by_cached_key(|(ordering, group)| Reverse(value_per_cost(ordering, group)));
+        } else {
+            // Sort groups by total profit in descending order
+            best_orderings_per_group.sort_by(|(a_ordering, _), (b_ordering, _)| {
+                b_ordering.total_profit.cmp(&a_ordering.total_profit)
+            });
+        }
+        trace_group_orderings(best_orderings_per_group);
 
+        let mut backfill_txs = Vec::new();
+        let mut deferred = DeferredOrders::default();
         loop {
             if self.cancellation_token.is_cancelled() {
                 break;
@@ -234,33 +336,24 @@ where
                 // Get the next order from this group
                 let (order_idx, _) = sequence_of_orders.sequence_of_orders.remove(0);
                 let sim_order = &order_group.orders[order_idx];
-
-                let start_time = Instant::now();
-                let commit_result = block_building_helper.commit_order(sim_order)?;
-                let order_commit_time = start_time.elapsed();
-
-                let mut gas_used = 0;
-                let mut execution_error = None;
-                let success = commit_result.is_ok();
-                match commit_result {
-                    Ok(res) => {
-                        gas_used = res.gas_used;
+                if is_zero_profit_tx(sim_order) {
+                    if self.accepts_order(sim_order) {
+                        backfill_txs.push(sim_order.clone());
                     }
-                    Err(err) => execution_error = Some(err),
+                    continue;
                 }
-                trace!(
-                    order_id = ?sim_order.id(),
-                    success,
-                    order_commit_time_mus = order_commit_time.as_micros(),
-                    gas_used,
-                    ?execution_error,
-                    "Executed order"
-                );
+
+                commit_order(&mut block_building_helper, sim_order, &mut deferred)?;
             } else {
                 // No more orders in any group
                 break;
             }
         }
+        backfill(
+            &mut block_building_helper,
+            &backfill_txs,
+            &self.cancellation_token,
+        )?;
         block_building_helper.set_trace_fill_time(build_start.elapsed());
         self.cached_reads = Some(block_building_helper.clone_cached_reads());
         Ok(Box::new(block_building_helper))
@@ -291,6 +384,7 @@ where
         best_orderings_per_group.sort_by(|(a_ordering, _), (b_ordering, _)| {
             b_ordering.total_profit.cmp(&a_ordering.total_profit)
         });
+        trace_group_orderings(&best_orderings_per_group);
 
         let use_suggested_fee_recipient_as_coinbase =
             self.coinbase_payment && !self.contains_refunds(&best_orderings_per_group);
@@ -303,38 +397,38 @@ where
 
         let build_start = Instant::now();
 
+        let mut backfill_txs = Vec::new();
+        let mut deferred = DeferredOrders::default();
         for (sequence_of_orders, order_group) in best_orderings_per_group.iter_mut() {
             for (order_idx, _) in sequence_of_orders.sequence_of_orders.iter() {
                 let sim_order = &order_group.orders[*order_idx];
-
-                let commit_result = block_building_helper.commit_order(sim_order)?;
-
-                match commit_result {
-                    Ok(res) => {
-                        tracing::trace!(
-                            order_id = ?sim_order.id(),
-                            success = true,
-                            gas_used = res.gas_used,
-                            "Executed order in backtest"
-                        );
-                    }
-                    Err(err) => {
-                        tracing::trace!(
-                            order_id = ?sim_order.id(),
-                            success = false,
-                            error = ?err,
-                            "Failed to execute order in backtest"
-                        );
+                if is_zero_profit_tx(sim_order) {
+                    if self.accepts_order(sim_order) {
+                        backfill_txs.push(sim_order.clone());
                     }
+                    continue;
                 }
+
+                commit_order(&mut block_building_helper, sim_order, &mut deferred)?;
             }
         }
+        backfill(
+            &mut block_building_helper,
+            &backfill_txs,
+            &CancellationToken::new(),
+        )?;
 
         block_building_helper.set_trace_fill_time(build_start.elapsed());
 
         Ok(Box::new(block_building_helper))
     }
 
+    /// See [`ZeroProfitTxPolicy::accepts`].
+    fn accepts_order(&self, sim_order: &SimulatedOrder) -> bool {
+        self.zero_profit_txs
+            .accepts(sim_order, self.zero_profit_tx_max_gas)
+    }
+
     /// Checks if any of the orders in the given orderings contain refunds.
     ///
     /// # Arguments
@@ -358,3 +452,122 @@ where
         })
     }
 }
+
+/// Records which algorithm (and strategy) produced the ordering used for every group so it can be evaluated offline.
+fn trace_group_orderings(orderings: &[(ResolutionResult, ConflictGroup)]) {
+    for (resolution_result, group) in orderings {
+        trace!(
+            group_id = group.id,
+            order_count = resolution_result.sequence_of_orders.len(),
+            profit = format_ether(resolution_result.total_profit),
+            algorithm = ?resolution_result.algorithm,
+            strategy = ?resolution_result
+                .algorithm
+                .map(|algorithm| algorithm.strategy()),
+            "Using group ordering"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::{Bundle, MempoolTx, Order, SimValue, TransactionSignedEcRecoveredWithBlobs},
+        utils::test_utils::tx,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_value_per_cost() {
+        let sim_order = |id, evm_time_mus| SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(tx(id))),
+            sim_value: SimValue::default(),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: ExecutionCost {
+                evm_time: Duration::from_micros(evm_time_mus),
+                state_reads: 0,
+            },
+        };
+        let group = ConflictGroup {
+            id: 0,
+            orders: Arc::new(vec![sim_order(1, 10), sim_order(2, 30)]),
+            conflicting_group_ids: Default::default(),
+        };
+        let ordering = |sequence: Vec<usize>, total_profit: u64| ResolutionResult {
+            total_profit: U256::from(total_profit),
+            sequence_of_orders: sequence.into_iter().map(|idx| (idx, U256::ZERO)).collect(),
+            algorithm: None,
+        };
+        assert_eq!(
+            value_per_cost(&ordering(vec![1, 0], 400), &group),
+            U256::from(10)
+        );
+        // only the orders in the sequence count
+        assert_eq!(
+            value_per_cost(&ordering(vec![0], 400), &group),
+            U256::from(40)
+        );
+    }
+
+    #[test]
+    fn test_commit_order_defers_nonce_gaps() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let tx = |from, nonce| -> eyre::Result<_> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(from, nonce, 5))?;
+            Ok(TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap())
+        };
+        let sim_order = |order| SimulatedOrder {
+            order,
+            sim_value: SimValue::default(),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        let mut bundle = Bundle {
+            block: 11,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: vec![tx(NamedAddr::User(2), 0)?, tx(NamedAddr::User(1), 1)?],
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        let gapped = sim_order(Order::Bundle(bundle));
+        let gap_filler = sim_order(Order::Tx(MempoolTx::new(tx(NamedAddr::User(1), 0)?)));
+        let unrelated = sim_order(Order::Tx(MempoolTx::new(tx(NamedAddr::User(3), 0)?)));
+
+        let mut block_building_helper = BlockBuildingHelperFromProvider::new(
+            test_chain.provider_factory().clone(),
+            RootHashConfig::skip_root_hash(),
+            test_chain.block_building_context().clone(),
+            None,
+            "test".to_string(),
+            false,
+            None,
+            CancellationToken::new(),
+        )?;
+        let mut deferred = DeferredOrders::default();
+        // eg: from the groups of a bad ordering
+        for sim_order in [&gapped, &unrelated, &gap_filler] {
+            commit_order(&mut block_building_helper, sim_order, &mut deferred)?;
+        }
+        assert_eq!(
+            block_building_helper
+                .built_block_trace()
+                .included_orders
+                .iter()
+                .map(|res| res.order.id())
+                .collect::<Vec<_>>(),
+            vec![unrelated.id(), gap_filler.id(), gapped.id()]
+        );
+        assert!(deferred.is_empty());
+        Ok(())
+    }
+}
@@ -15,7 +15,10 @@ use super::{
 };
 
 use crate::{
-    building::{BlockBuildingContext, BlockState, ExecutionError, ExecutionResult, PartialBlock},
+    building::{
+        order_index::OrderIndex, BlockBuildingContext, BlockState, ExecutionError, ExecutionResult,
+        PartialBlock,
+    },
     primitives::{OrderId, SimulatedOrder},
 };
 
@@ -83,6 +86,7 @@ where
         let mut best_resolution_result = ResolutionResult {
             total_profit: U256::ZERO,
             sequence_of_orders: vec![],
+            algorithm: Some(task.algorithm),
         };
 
         for sequence_of_orders in sequence_to_try {
@@ -141,9 +145,14 @@ where
         let full_sequence_of_orders = self.initialize_full_order_ids_vec(&sequence_of_orders, task);
 
         // Check for cached simulation state
-        let (cached_state_option, cached_up_to_index) = self
-            .simulation_cache
-            .get_cached_state(&full_sequence_of_orders);
+        // Cached states don't know about skipped orders so they are not used with a gas budget.
+        let use_simulation_cache = task.gas_budget.is_none();
+        let (cached_state_option, cached_up_to_index) = if use_simulation_cache {
+            self.simulation_cache
+                .get_cached_state(&full_sequence_of_orders)
+        } else {
+            (None, 0)
+        };
 
         // Initialize state and partial block
         let mut partial_block = PartialBlock::new(true, None);
@@ -167,6 +176,7 @@ where
         remaining_orders.reverse(); // Use as a stack: pop from the end
 
         let mut pending_orders: HashMap<(Address, u64), usize> = HashMap::default();
+        let mut gas_used = 0;
 
         // Processing loop
         while let Some(order_idx) = remaining_orders.pop() {
@@ -175,31 +185,42 @@ where
             }
 
             let sim_order = &task.group.orders[order_idx];
+            if let Some(gas_budget) = task.gas_budget {
+                if gas_used + sim_order.sim_value.gas_used > gas_budget {
+                    continue;
+                }
+            }
             match partial_block.commit_order(sim_order, &self.ctx, &mut state)? {
-                Ok(res) => self.handle_successful_commit(
-                    res,
-                    sim_order,
-                    order_idx,
-                    &mut pending_orders,
-                    &mut remaining_orders,
-                    &mut sequenced_order_result,
-                    &mut total_profit,
-                    &mut per_order_profits,
-                ),
+                Ok(res) => {
+                    gas_used += res.gas_used;
+                    self.handle_successful_commit(
+                        res,
+                        sim_order,
+                        order_idx,
+                        &mut pending_orders,
+                        &mut remaining_orders,
+                        &mut sequenced_order_result,
+                        &mut total_profit,
+                        &mut per_order_profits,
+                    )
+                }
                 Err(err) => self.handle_err(&err, sim_order, &mut pending_orders, order_idx),
             }
         }
 
-        self.store_simulation_state(
-            &full_sequence_of_orders,
-            &state,
-            total_profit,
-            &per_order_profits,
-        );
+        if use_simulation_cache {
+            self.store_simulation_state(
+                &full_sequence_of_orders,
+                &state,
+                total_profit,
+                &per_order_profits,
+            );
+        }
 
         let resolution_result = ResolutionResult {
             total_profit,
             sequence_of_orders: sequenced_order_result,
+            algorithm: Some(task.algorithm),
         };
         Ok((resolution_result, state))
     }
@@ -241,14 +262,14 @@ where
         };
     }
 
-    /// Initializes a HashMap of order id to index.
-    fn initialize_order_id_to_index_map(&self, task: &ConflictTask) -> HashMap<OrderId, usize> {
-        task.group
-            .orders
-            .iter()
-            .enumerate()
-            .map(|(idx, sim_order)| (sim_order.order.id(), idx))
-            .collect()
+    /// Initializes the index of the group orders (index is the position on task.group.orders).
+    fn initialize_order_id_to_index_map(&self, task: &ConflictTask) -> OrderIndex {
+        OrderIndex::from_ids(
+            task.group
+                .orders
+                .iter()
+                .map(|sim_order| sim_order.order.id()),
+        )
     }
 
     /// Initializes a vector of full order ids corresponding to the sequence of orders.
@@ -267,14 +288,16 @@ where
     fn initialize_result_order_sequence(
         &self,
         cached_state_option: &Option<Arc<CachedSimulationState>>,
-        order_id_to_index: &HashMap<OrderId, usize>,
+        order_id_to_index: &OrderIndex,
     ) -> Vec<(usize, U256)> {
         if let Some(cached_state) = &cached_state_option {
             cached_state
                 .per_order_profits
                 .iter()
                 .filter_map(|(order_id, profit)| {
-                    order_id_to_index.get(order_id).map(|&idx| (idx, *profit))
+                    order_id_to_index
+                        .get(order_id)
+                        .map(|idx| (idx as usize, *profit))
                 })
                 .collect::<Vec<_>>()
         } else {
@@ -332,14 +355,143 @@ where
 /// # Returns
 ///
 /// A vector of different sequences of order indices to try.
+/// With a gas budget the greedy algorithms also try [generate_gas_budget_sequence].
 fn generate_sequences_of_orders_to_try(task: &ConflictTask) -> Vec<Vec<usize>> {
-    match task.algorithm {
+    let mut sequences = match task.algorithm {
         Algorithm::Greedy => generate_greedy_sequence(task, false),
         Algorithm::ReverseGreedy => generate_greedy_sequence(task, true),
         Algorithm::Length => generate_length_based_sequence(task),
         Algorithm::AllPermutations => generate_all_permutations(task),
         Algorithm::Random { seed, count } => generate_random_permutations(task, seed, count),
+        Algorithm::NonceSort => generate_nonce_sorted_sequence(task),
+        Algorithm::Recurrent => vec![(0..task.group.orders.len()).collect()],
+    };
+    if let (Algorithm::Greedy, Some(gas_budget)) = (task.algorithm, task.gas_budget) {
+        sequences.push(generate_gas_budget_sequence(task, gas_budget));
     }
+    sequences
+}
+
+/// Groups up to this size get an exact search (every subset) in [generate_gas_budget_sequence].
+const MAX_GROUP_LEN_FOR_EXACT_GAS_BUDGET_SEARCH: usize = 16;
+
+/// Generates the subset of orders with max simulated profit whose simulated gas fits in gas_budget (best profit
+/// first). Exact for small groups, for bigger ones orders are taken by mev gas price while they fit.
+///
+/// # Arguments
+///
+/// * `task` - The current conflict task.
+/// * `gas_budget` - Max gas of the orders in the sequence.
+///
+/// # Returns
+///
+/// A sequence of order indices.
+fn generate_gas_budget_sequence(task: &ConflictTask, gas_budget: u64) -> Vec<usize> {
+    let orders = &task.group.orders;
+    let mut sequence: Vec<usize> = if orders.len() <= MAX_GROUP_LEN_FOR_EXACT_GAS_BUDGET_SEARCH {
+        let mut best_profit = U256::ZERO;
+        let mut best_subset = 0u32;
+        for subset in 1u32..(1 << orders.len()) {
+            let mut gas = 0u64;
+            let mut profit = U256::ZERO;
+            for (idx, order) in orders.iter().enumerate() {
+                if subset & (1 << idx) != 0 {
+                    gas = gas.saturating_add(order.sim_value.gas_used);
+                    profit += order.sim_value.coinbase_profit;
+                }
+            }
+            if gas <= gas_budget && profit > best_profit {
+                best_profit = profit;
+                best_subset = subset;
+            }
+        }
+        (0..orders.len())
+            .filter(|idx| best_subset & (1 << idx) != 0)
+            .collect()
+    } else {
+        let mut by_mev_gas_price: Vec<usize> = (0..orders.len()).collect();
+        by_mev_gas_price.sort_by(|a, b| {
+            orders[*b]
+                .sim_value
+                .mev_gas_price
+                .cmp(&orders[*a].sim_value.mev_gas_price)
+        });
+        let mut gas = 0u64;
+        by_mev_gas_price
+            .into_iter()
+            .filter(|idx| {
+                let order_gas = orders[*idx].sim_value.gas_used;
+                if gas.saturating_add(order_gas) <= gas_budget {
+                    gas += order_gas;
+                    true
+                } else {
+                    false
+                }
+            })
+            .collect()
+    };
+    sequence.sort_by(|a, b| {
+        orders[*b]
+            .sim_value
+            .coinbase_profit
+            .cmp(&orders[*a].sim_value.coinbase_profit)
+            .then_with(|| a.cmp(b))
+    });
+    sequence
+}
+
+/// Generates a single deterministic sequence of order indices.
+/// Orders are sorted by coinbase profit and then, for every signer, the positions taken by that signer
+/// are reassigned so its orders appear in ascending nonce order.
+///
+/// # Arguments
+///
+/// * `task` - The current conflict task.
+///
+/// # Returns
+///
+/// A vector containing the nonce sorted sequence of order indices.
+fn generate_nonce_sorted_sequence(task: &ConflictTask) -> Vec<Vec<usize>> {
+    let orders = &task.group.orders;
+
+    let mut sequence: Vec<usize> = (0..orders.len()).collect();
+    sequence.sort_by(|a, b| {
+        orders[*b]
+            .sim_value
+            .coinbase_profit
+            .cmp(&orders[*a].sim_value.coinbase_profit)
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut positions_by_signer: HashMap<Address, Vec<usize>> = HashMap::default();
+    for (position, order_idx) in sequence.iter().enumerate() {
+        if let Some(nonce) = orders[*order_idx].order.nonces().first() {
+            positions_by_signer
+                .entry(nonce.address)
+                .or_default()
+                .push(position);
+        }
+    }
+
+    for (signer, positions) in positions_by_signer {
+        let mut order_idxs: Vec<usize> = positions.iter().map(|pos| sequence[*pos]).collect();
+        order_idxs.sort_by_key(|order_idx| {
+            let min_nonce = orders[*order_idx]
+                .order
+                .nonces()
+                .iter()
+                .filter(|nonce| nonce.address == signer)
+                .map(|nonce| nonce.nonce)
+                .min()
+                .unwrap_or(u64::MAX);
+            (min_nonce, *order_idx)
+        });
+        for (position, order_idx) in positions.into_iter().zip(order_idxs) {
+            sequence[position] = order_idx;
+        }
+    }
+
+    vec![sequence]
 }
 
 /// Generates random permutations of sequences of order indices.
@@ -551,6 +703,7 @@ mod tests {
                 used_state_trace: None,
                 sim_value,
                 prev_order: None,
+                execution_cost: Default::default(),
             }
         }
     }
@@ -581,9 +734,73 @@ mod tests {
             algorithm,
             priority,
             created_at,
+            gas_budget: None,
         }
     }
 
+    #[test]
+    fn test_gas_budget_sequence() {
+        let mut data_generator = DataGenerator::new();
+        let mut order = |coinbase_profit: u64, gas_used: u64| {
+            let mut order = data_generator.create_order_with_length(
+                U256::from(coinbase_profit),
+                U256::from(coinbase_profit / gas_used),
+                1,
+            );
+            order.sim_value.gas_used = gas_used;
+            order
+        };
+        // the most profitable order doesn't leave room for the 2 others that together pay more
+        let group = create_mock_order_group(
+            1,
+            vec![order(1000, 100), order(700, 60), order(800, 60)],
+            HashSet::default(),
+        );
+        let mut task = create_mock_task(
+            0,
+            group,
+            Algorithm::Greedy,
+            TaskPriority::Low,
+            Instant::now(),
+        );
+        assert_eq!(generate_gas_budget_sequence(&task, 120), vec![2, 1]);
+        assert_eq!(generate_gas_budget_sequence(&task, 100), vec![0]);
+        assert_eq!(generate_gas_budget_sequence(&task, 50), Vec::<usize>::new());
+        assert_eq!(generate_gas_budget_sequence(&task, 220), vec![0, 2, 1]);
+
+        assert_eq!(generate_sequences_of_orders_to_try(&task).len(), 2);
+        task.gas_budget = Some(120);
+        let sequences = generate_sequences_of_orders_to_try(&task);
+        assert_eq!(sequences.len(), 3);
+        assert_eq!(sequences[2], vec![2, 1]);
+    }
+
+    #[test]
+    fn test_gas_budget_sequence_big_group() {
+        let mut data_generator = DataGenerator::new();
+        // mev gas price goes down with the index, every order uses 10 gas
+        let orders = (0..MAX_GROUP_LEN_FOR_EXACT_GAS_BUDGET_SEARCH + 4)
+            .map(|idx| {
+                let mut order = data_generator.create_order_with_length(
+                    U256::from(1000 - idx * 10),
+                    U256::from(100 - idx),
+                    1,
+                );
+                order.sim_value.gas_used = 10;
+                order
+            })
+            .collect();
+        let group = create_mock_order_group(1, orders, HashSet::default());
+        let task = create_mock_task(
+            0,
+            group,
+            Algorithm::Greedy,
+            TaskPriority::Low,
+            Instant::now(),
+        );
+        assert_eq!(generate_gas_budget_sequence(&task, 35), vec![0, 1, 2]);
+    }
+
     #[test]
     fn test_all_permutations() {
         let mut data_generator = DataGenerator::new();
@@ -702,4 +919,31 @@ mod tests {
         // MEV gas price is the second
         assert_eq!(sequences[1], vec![3, 1, 2, 0]);
     }
+
+    #[test]
+    fn test_nonce_sorted_sequence() {
+        let mut data_generator = DataGenerator::new();
+        // All orders share the same signer and nonces grow with the index
+        let group = create_mock_order_group(
+            1,
+            vec![
+                data_generator.create_order_with_length(U256::from(100), U256::from(100), 1), // index: 0, profit 100
+                data_generator.create_order_with_length(U256::from(300), U256::from(300), 1), // index: 1, profit 300
+                data_generator.create_order_with_length(U256::from(200), U256::from(200), 1), // index: 2, profit 200
+            ],
+            HashSet::default(),
+        );
+
+        let task = create_mock_task(
+            0,
+            group,
+            Algorithm::NonceSort,
+            TaskPriority::Low,
+            Instant::now(),
+        );
+
+        let sequences = generate_sequences_of_orders_to_try(&task);
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0], vec![0, 1, 2]);
+    }
 }
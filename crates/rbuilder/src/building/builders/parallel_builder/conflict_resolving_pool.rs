@@ -4,18 +4,24 @@ use eyre::Result;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use reth_provider::StateProviderFactory;
 use std::{
-    sync::{mpsc as std_mpsc, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc as std_mpsc, Arc,
+    },
     time::Instant,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{trace, warn};
 
 use super::{
-    conflict_resolvers::ResolverContext, conflict_task_generator::get_tasks_for_group,
-    simulation_cache::SharedSimulationCache, ConflictGroup, ConflictResolutionResultPerGroup,
+    conflict_resolvers::ResolverContext, simulation_cache::SharedSimulationCache,
+    strategy_selector::StrategySelector, ConflictGroup, ConflictResolutionResultPerGroup,
     ConflictTask, GroupId, ResolutionResult, TaskPriority,
 };
-use crate::building::BlockBuildingContext;
+use crate::{
+    building::BlockBuildingContext,
+    live_builder::simulation::shared_workers::{register_building_work, BuildingWork},
+};
 
 pub type TaskQueue = Arc<SegQueue<ConflictTask>>;
 
@@ -59,47 +65,21 @@ where
     }
 
     pub fn start(&self) {
-        let task_queue = self.task_queue.clone();
-        let cancellation_token = self.cancellation_token.clone();
-        let provider = self.provider.clone();
-        let group_result_sender = self.group_result_sender.clone();
-        let simulation_cache = self.simulation_cache.clone();
-        let ctx = self.ctx.clone();
+        let runner = Arc::new(ConflictTaskRunner {
+            task_queue: self.task_queue.clone(),
+            group_result_sender: self.group_result_sender.clone(),
+            cancellation_token: self.cancellation_token.clone(),
+            ctx: self.ctx.clone(),
+            provider: self.provider.clone(),
+            simulation_cache: self.simulation_cache.clone(),
+            result_receiver_dropped: AtomicBool::new(false),
+        });
+        // Shared workers (if any) help us when we have more tasks than threads.
+        register_building_work(runner.clone());
 
         self.thread_pool.spawn(move || {
-            while !cancellation_token.is_cancelled() {
-                if let Some(task) = task_queue.pop() {
-                    if cancellation_token.is_cancelled() {
-                        return;
-                    }
-                    let task_start = Instant::now();
-                    if let Ok((task_id, result)) = Self::process_task(
-                        task,
-                        &ctx,
-                        &provider,
-                        cancellation_token.clone(),
-                        Arc::clone(&simulation_cache),
-                    ) {
-                        match group_result_sender.send((task_id, result)) {
-                            Ok(_) => {
-                                trace!(
-                                    task_id = %task_id,
-                                    time_taken_ms = %task_start.elapsed().as_millis(),
-                                    "Conflict resolving: successfully sent group result"
-                                );
-                            }
-                            Err(err) => {
-                                warn!(
-                                    task_id = %task_id,
-                                    error = ?err,
-                                    time_taken_ms = %task_start.elapsed().as_millis(),
-                                    "Conflict resolving: failed to send group result"
-                                );
-                                return;
-                            }
-                        }
-                    }
-                }
+            while !runner.is_finished() {
+                runner.run_task();
             }
         });
     }
@@ -126,6 +106,7 @@ where
             Ok(sequence_of_orders) => {
                 trace!(
                     task_type = ?task_algo,
+                    strategy = task_algo.strategy().display(),
                     group_id = task_id,
                     profit = format_ether(sequence_of_orders.total_profit),
                     order_count = sequence_of_orders.sequence_of_orders.len(),
@@ -154,10 +135,11 @@ where
         ctx: &BlockBuildingContext,
         provider: &P,
         simulation_cache: Arc<SharedSimulationCache>,
+        strategy_selector: &StrategySelector,
     ) -> Vec<(GroupId, (ResolutionResult, ConflictGroup))> {
         let mut results = Vec::new();
         for new_group in new_groups {
-            let tasks = get_tasks_for_group(&new_group, TaskPriority::High);
+            let tasks = strategy_selector.tasks_for_group(&new_group, TaskPriority::High);
             for task in tasks {
                 let simulation_cache = Arc::clone(&simulation_cache);
                 let result = Self::process_task(
@@ -175,3 +157,66 @@ where
         results
     }
 }
+
+/// Pops and executes tasks from the [`TaskQueue`].
+struct ConflictTaskRunner<P> {
+    task_queue: TaskQueue,
+    group_result_sender: std_mpsc::Sender<ConflictResolutionResultPerGroup>,
+    cancellation_token: CancellationToken,
+    ctx: BlockBuildingContext,
+    provider: P,
+    simulation_cache: Arc<SharedSimulationCache>,
+    result_receiver_dropped: AtomicBool,
+}
+
+impl<P> BuildingWork for ConflictTaskRunner<P>
+where
+    P: StateProviderFactory + Clone + 'static,
+{
+    fn pending_tasks(&self) -> usize {
+        self.task_queue.len()
+    }
+
+    fn run_task(&self) -> bool {
+        let task = match self.task_queue.pop() {
+            Some(task) => task,
+            None => return false,
+        };
+        if self.cancellation_token.is_cancelled() {
+            return true;
+        }
+        let task_start = Instant::now();
+        if let Ok((task_id, result)) = ConflictResolvingPool::<P>::process_task(
+            task,
+            &self.ctx,
+            &self.provider,
+            self.cancellation_token.clone(),
+            Arc::clone(&self.simulation_cache),
+        ) {
+            match self.group_result_sender.send((task_id, result)) {
+                Ok(_) => {
+                    trace!(
+                        task_id = %task_id,
+                        time_taken_ms = %task_start.elapsed().as_millis(),
+                        "Conflict resolving: successfully sent group result"
+                    );
+                }
+                Err(err) => {
+                    warn!(
+                        task_id = %task_id,
+                        error = ?err,
+                        time_taken_ms = %task_start.elapsed().as_millis(),
+                        "Conflict resolving: failed to send group result"
+                    );
+                    self.result_receiver_dropped.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        true
+    }
+
+    fn is_finished(&self) -> bool {
+        self.cancellation_token.is_cancelled()
+            || self.result_receiver_dropped.load(Ordering::Relaxed)
+    }
+}
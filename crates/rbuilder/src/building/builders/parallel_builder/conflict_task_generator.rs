@@ -1,27 +1,30 @@
-use crate::primitives::SimulatedOrder;
+use crate::{
+    building::recurrent_orders::{group_fingerprint, order_fingerprint, SharedRecurrentOrders},
+    primitives::SimulatedOrder,
+};
 use ahash::{HashMap, HashSet};
 use alloy_primitives::{utils::format_ether, U256};
 use crossbeam_queue::SegQueue;
 use itertools::Itertools;
-use std::time::Instant;
 use tracing::trace;
 
 use super::{
-    task::ConflictTask, Algorithm, ConflictGroup, ConflictResolutionResultPerGroup, GroupId,
+    strategy_selector::StrategySelector, ConflictGroup, ConflictResolutionResultPerGroup, GroupId,
     ResolutionResult, TaskPriority, TaskQueue,
 };
 use std::sync::mpsc as std_mpsc;
 
 const THRESHOLD_FOR_SIGNIFICANT_CHANGE: u64 = 20;
 const NUMBER_OF_TOP_ORDERS_TO_CONSIDER_FOR_SIGNIFICANT_CHANGE: usize = 10;
-const MAX_LENGTH_FOR_ALL_PERMUTATIONS: usize = 3;
-const NUMBER_OF_RANDOM_TASKS: usize = 50;
 
 /// Manages conflicts and updates for conflict groups, coordinating with a worker pool to process tasks.
 pub struct ConflictTaskGenerator {
     existing_groups: HashMap<GroupId, ConflictGroup>,
     task_queue: TaskQueue,
     group_result_sender: std_mpsc::Sender<ConflictResolutionResultPerGroup>,
+    strategy_selector: StrategySelector,
+    /// (block we are building, orders seen on previous slots) to replay the orderings of recurrent groups.
+    recurrent_orders: Option<(u64, SharedRecurrentOrders)>,
 }
 
 impl ConflictTaskGenerator {
@@ -31,14 +34,30 @@ impl ConflictTaskGenerator {
     ///
     /// * `task_queue` - The queue to store the generated tasks.
     /// * `group_result_sender` - The sender to send the results of the conflict resolution.
+    /// * `strategy_selector` - Decides which algorithms are run for each group.
     pub fn new(
         task_queue: TaskQueue,
         group_result_sender: std_mpsc::Sender<ConflictResolutionResultPerGroup>,
+        strategy_selector: StrategySelector,
     ) -> Self {
         Self {
             existing_groups: HashMap::default(),
             task_queue,
             group_result_sender,
+            strategy_selector,
+            recurrent_orders: None,
+        }
+    }
+
+    /// Groups whose orders were all resolved together on a previous slot replay that ordering instead of searching again.
+    pub fn with_recurrent_orders(
+        self,
+        block: u64,
+        recurrent_orders: SharedRecurrentOrders,
+    ) -> Self {
+        Self {
+            recurrent_orders: Some((block, recurrent_orders)),
+            ..self
         }
     }
 
@@ -48,6 +67,15 @@ impl ConflictTaskGenerator {
     ///
     /// * `new_groups` - A vector of new [ConflictGroup]s to process.
     pub fn process_groups(&mut self, new_groups: Vec<ConflictGroup>) {
+        let new_group_ids: HashSet<GroupId> = new_groups.iter().map(|group| group.id).collect();
+        self.strategy_selector.update_marginal_gas_price(
+            new_groups.iter().chain(
+                self.existing_groups
+                    .values()
+                    .filter(|group| !new_group_ids.contains(&group.id)),
+            ),
+        );
+
         let mut sorted_groups = new_groups;
         sorted_groups.sort_by(|a, b| b.orders.len().cmp(&a.orders.len()));
 
@@ -182,6 +210,7 @@ impl ConflictTaskGenerator {
         let sequence_of_orders = ResolutionResult {
             total_profit: group.orders[0].sim_value.coinbase_profit,
             sequence_of_orders: vec![(0, group.orders[0].sim_value.coinbase_profit)],
+            algorithm: None,
         };
         // We ignore the error since it means "receiver disconnected" and we expect the caller will detect the cancellation and stop calling us.
         let _ = self
@@ -324,82 +353,57 @@ impl ConflictTaskGenerator {
     /// * `new_group` - The `ConflictGroup` to create tasks for.
     /// * `priority` - The priority to assign to the tasks.
     fn create_new_tasks(&mut self, new_group: &ConflictGroup, priority: TaskPriority) {
-        let tasks = get_tasks_for_group(new_group, priority);
+        let tasks = match self.previous_ordering(new_group) {
+            Some(ordering) => {
+                trace!(
+                    group = new_group.id,
+                    "Recurrent group, replaying previous ordering"
+                );
+                self.strategy_selector
+                    .tasks_for_previous_ordering(new_group, &ordering, priority)
+            }
+            None => self.strategy_selector.tasks_for_group(new_group, priority),
+        };
         for task in tasks {
             self.task_queue.push(task);
         }
     }
-}
 
-/// Generates a vector of conflict tasks for a given order group.
-///
-/// # Arguments
-///
-/// * `group` - The `ConflictGroup` to create tasks for.
-/// * `priority` - The priority to assign to the tasks.
-///
-/// # Returns
-///
-/// A vector of `ConflictTask`s for the given group.
-pub fn get_tasks_for_group(group: &ConflictGroup, priority: TaskPriority) -> Vec<ConflictTask> {
-    let mut tasks = vec![];
-
-    let created_at = Instant::now();
-    // We want to run Greedy first so we can get quick, decent results
-    tasks.push(ConflictTask {
-        group_idx: group.id,
-        algorithm: Algorithm::Greedy,
-        priority,
-        group: group.clone(),
-        created_at,
-    });
-
-    // Then, we can push lower priority tasks that have a low chance, but a chance, of finding a better result
-    if group.orders.len() <= MAX_LENGTH_FOR_ALL_PERMUTATIONS {
-        // AllPermutations
-        tasks.push(ConflictTask {
-            group_idx: group.id,
-            algorithm: Algorithm::AllPermutations,
-            priority,
-            group: group.clone(),
-            created_at,
-        });
-    } else {
-        // Random
-        tasks.push(ConflictTask {
-            group_idx: group.id,
-            algorithm: Algorithm::Random {
-                seed: group.id as u64,
-                count: NUMBER_OF_RANDOM_TASKS,
-            },
-            priority: TaskPriority::Low,
-            group: group.clone(),
-            created_at,
-        });
-        tasks.push(ConflictTask {
-            group_idx: group.id,
-            algorithm: Algorithm::Length,
-            priority: TaskPriority::Low,
-            group: group.clone(),
-            created_at,
-        });
-        tasks.push(ConflictTask {
-            group_idx: group.id,
-            algorithm: Algorithm::ReverseGreedy,
-            priority: TaskPriority::Low,
-            group: group.clone(),
-            created_at,
-        });
+    /// Indexes of the group orders in the best order found for the same orders on a previous slot.
+    fn previous_ordering(&self, group: &ConflictGroup) -> Option<Vec<usize>> {
+        let (block, recurrent_orders) = self.recurrent_orders.as_ref()?;
+        let recurrent_orders = recurrent_orders.lock();
+        if !recurrent_orders.all_recurrent(group.orders.iter().map(|o| &o.order)) {
+            return None;
+        }
+        let fingerprints: Vec<_> = group
+            .orders
+            .iter()
+            .map(|o| order_fingerprint(&o.order))
+            .collect();
+        let previous_ordering = recurrent_orders
+            .previous_resolution(*block, &group_fingerprint(fingerprints.iter().copied()))?;
+        let mut idx_by_fingerprint: HashMap<_, _> = HashMap::default();
+        for (idx, fingerprint) in fingerprints.iter().enumerate() {
+            idx_by_fingerprint.entry(*fingerprint).or_insert(idx);
+        }
+        Some(
+            previous_ordering
+                .iter()
+                .filter_map(|fingerprint| idx_by_fingerprint.remove(fingerprint))
+                .collect(),
+        )
     }
-
-    tasks
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::primitives::{
-        MempoolTx, Order, SimValue, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs,
+    use crate::{
+        building::builders::parallel_builder::{task::ConflictTask, Algorithm},
+        primitives::{
+            MempoolTx, Order, SimValue, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs,
+        },
     };
     use alloy_consensus::TxLegacy;
     use alloy_primitives::{Address, TxHash, B256, U256};
@@ -438,7 +442,11 @@ mod tests {
             TransactionSignedEcRecovered::from_signed_transaction(
                 TransactionSigned {
                     hash: self.create_hash(),
-                    transaction: Transaction::Legacy(TxLegacy::default()),
+                    // different value so each order has a different fingerprint
+                    transaction: Transaction::Legacy(TxLegacy {
+                        value: self.create_u256(),
+                        ..Default::default()
+                    }),
                     ..Default::default()
                 },
                 Address::default(),
@@ -469,13 +477,11 @@ mod tests {
             };
 
             SimulatedOrder {
-                order: Order::Tx(MempoolTx {
-                    tx_with_blobs: TransactionSignedEcRecoveredWithBlobs::new_no_blobs(
-                        self.create_tx(),
-                    )
-                    .unwrap(),
-                }),
+                order: Order::Tx(MempoolTx::new(
+                    TransactionSignedEcRecoveredWithBlobs::new_no_blobs(self.create_tx()).unwrap(),
+                )),
                 used_state_trace: Some(trace),
+                execution_cost: Default::default(),
                 sim_value,
                 prev_order: None,
             }
@@ -501,7 +507,63 @@ mod tests {
 
     fn create_task_generator() -> ConflictTaskGenerator {
         let (sender, _receiver) = mpsc::channel();
-        ConflictTaskGenerator::new(create_task_queue(), sender)
+        ConflictTaskGenerator::new(create_task_queue(), sender, StrategySelector::default())
+    }
+
+    #[test]
+    fn test_recurrent_group_replays_previous_ordering() {
+        let mut data_generator = DataGenerator::new();
+        let orders = vec![
+            data_generator.create_order(None, None, U256::from(100)),
+            data_generator.create_order(None, None, U256::from(200)),
+            data_generator.create_order(None, None, U256::from(300)),
+        ];
+        let fingerprints: Vec<_> = orders.iter().map(|o| order_fingerprint(&o.order)).collect();
+        let recurrent_orders = SharedRecurrentOrders::default();
+        {
+            let mut recurrent_orders = recurrent_orders.lock();
+            for order in &orders {
+                recurrent_orders.observe(10, &order.order);
+                recurrent_orders.observe(11, &order.order);
+            }
+            // best ordering found on block 10
+            recurrent_orders.record_resolution(
+                10,
+                group_fingerprint(fingerprints.iter().copied()),
+                vec![fingerprints[2], fingerprints[0], fingerprints[1]],
+            );
+        }
+
+        let (sender, _receiver) = mpsc::channel();
+        let task_queue = create_task_queue();
+        let mut conflict_manager =
+            ConflictTaskGenerator::new(task_queue.clone(), sender, StrategySelector::default())
+                .with_recurrent_orders(11, recurrent_orders.clone());
+        conflict_manager.process_groups(vec![create_conflict_group(
+            1,
+            orders.clone(),
+            HashSet::default(),
+        )]);
+        let tasks: Vec<_> = std::iter::from_fn(|| task_queue.pop()).collect();
+        let algorithms: Vec<_> = tasks.iter().map(|task| task.algorithm).collect();
+        assert_eq!(algorithms, vec![Algorithm::Recurrent, Algorithm::Greedy]);
+        let replayed: Vec<_> = tasks[0].group.orders.iter().map(|o| o.id()).collect();
+        assert_eq!(
+            replayed,
+            vec![orders[2].id(), orders[0].id(), orders[1].id()]
+        );
+
+        // a new order changes the group so it's searched again
+        let mut grown_orders = orders;
+        grown_orders.push(data_generator.create_order(None, None, U256::from(400)));
+        recurrent_orders.lock().observe(11, &grown_orders[3].order);
+        conflict_manager.process_groups(vec![create_conflict_group(
+            2,
+            grown_orders,
+            HashSet::default(),
+        )]);
+        assert!(std::iter::from_fn(|| task_queue.pop())
+            .all(|task| task.algorithm != Algorithm::Recurrent));
     }
 
     #[test]
@@ -0,0 +1,309 @@
+//! Keeps track, across slots, of how much value exhaustive search ([Algorithm::AllPermutations]) adds over
+//! [Algorithm::Greedy] for each conflict group size.
+//! Exhaustive search is n! so we want to use it only on the group sizes where it usually pays off.
+//! [StrategySelector](super::strategy_selector::StrategySelector) uses the learned max group len when
+//! `adaptive_max_group_len_for_exhaustive_search` is configured.
+//!
+//! For sizes above the learned max we don't get any samples so 1 of every [EXPLORATION_INTERVAL] groups
+//! of the next size is resolved exhaustively anyway.
+//!
+//! The process wide history is only locked once per slot on each side: the selector reads the learned max when
+//! it's created and the [ConflictValueComparator] of the slot records its samples when dropped.
+
+use super::{Algorithm, ConflictGroup, GroupId};
+use crate::telemetry::set_conflict_exhaustive_search_stats;
+use ahash::HashMap;
+use alloy_primitives::U256;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// Weight of a new sample on the moving average.
+const GAIN_EWMA_ALPHA: f64 = 0.05;
+/// Samples needed before we trust the average of a size.
+const MIN_SAMPLES: u64 = 20;
+pub const EXPLORATION_INTERVAL: usize = 16;
+/// Sizes bigger than this are never resolved exhaustively.
+pub const MAX_TRACKED_GROUP_LEN: usize = 8;
+
+lazy_static! {
+    static ref CONFLICT_VALUE_HISTORY: Mutex<ConflictValueHistory> =
+        Mutex::new(ConflictValueHistory::default());
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct GroupLenStats {
+    samples: u64,
+    /// Moving average of (exhaustive_profit - greedy_profit) / greedy_profit in basis points.
+    avg_gain_bps: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct ConflictValueHistory {
+    /// Indexed by group len.
+    stats: [GroupLenStats; MAX_TRACKED_GROUP_LEN + 1],
+}
+
+impl ConflictValueHistory {
+    pub fn record(&mut self, group_len: usize, greedy_profit: U256, exhaustive_profit: U256) {
+        let Some(stats) = self.stats.get_mut(group_len) else {
+            return;
+        };
+        let gain_bps = gain_bps(greedy_profit, exhaustive_profit);
+        stats.avg_gain_bps = if stats.samples == 0 {
+            gain_bps
+        } else {
+            stats.avg_gain_bps * (1.0 - GAIN_EWMA_ALPHA) + gain_bps * GAIN_EWMA_ALPHA
+        };
+        stats.samples += 1;
+    }
+
+    /// Max group len for exhaustive search.
+    /// Sizes up to default_max_len are assumed to be worth it until we have enough samples saying otherwise,
+    /// bigger sizes (up to limit) need enough samples with an average gain of at least min_gain_bps.
+    /// Stops at the first size not worth it.
+    pub fn learned_max_group_len(
+        &self,
+        default_max_len: usize,
+        limit: usize,
+        min_gain_bps: u64,
+    ) -> usize {
+        let limit = limit.min(MAX_TRACKED_GROUP_LEN);
+        let mut max_len = 1;
+        for len in 2..=limit {
+            let stats = &self.stats[len];
+            let worth_it = if stats.samples < MIN_SAMPLES {
+                len <= default_max_len
+            } else {
+                stats.avg_gain_bps >= min_gain_bps as f64
+            };
+            if !worth_it {
+                break;
+            }
+            max_len = len;
+        }
+        max_len
+    }
+
+    fn report_telemetry(&self, learned_max_len: usize) {
+        let gains: Vec<_> = self
+            .stats
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.samples > 0)
+            .map(|(len, stats)| (len, stats.avg_gain_bps))
+            .collect();
+        set_conflict_exhaustive_search_stats(&gains, learned_max_len);
+    }
+}
+
+fn gain_bps(greedy_profit: U256, exhaustive_profit: U256) -> f64 {
+    if exhaustive_profit <= greedy_profit {
+        return 0.0;
+    }
+    if greedy_profit.is_zero() {
+        // infinite gain, count it as 100%
+        return 10_000.0;
+    }
+    let gain = exhaustive_profit - greedy_profit;
+    let gain_bps = gain.saturating_mul(U256::from(10_000)) / greedy_profit;
+    u64::try_from(gain_bps).unwrap_or(u64::MAX).min(10_000) as f64
+}
+
+/// See [ConflictValueHistory::learned_max_group_len]. Uses the process wide history, call it once per slot.
+pub fn learned_max_group_len(default_max_len: usize, limit: usize, min_gain_bps: u64) -> usize {
+    let history = CONFLICT_VALUE_HISTORY.lock();
+    let max_len = history.learned_max_group_len(default_max_len, limit, min_gain_bps);
+    history.report_telemetry(max_len);
+    max_len
+}
+
+/// Whether a group above the learned max should be resolved exhaustively to get samples.
+pub fn should_explore(group_id: GroupId) -> bool {
+    group_id % EXPLORATION_INTERVAL == 0
+}
+
+#[derive(Debug)]
+struct PendingComparison {
+    /// Orders are only added to a group (under the same id) so its len identifies the version the results are for.
+    group_len: usize,
+    greedy_profit: Option<U256>,
+    exhaustive_profit: Option<U256>,
+}
+
+/// Pairs, per group version, the Greedy and AllPermutations results of a slot.
+/// Owned by the slot's results aggregator, the samples go to the process wide history when dropped.
+#[derive(Debug, Default)]
+pub struct ConflictValueComparator {
+    pending: HashMap<GroupId, PendingComparison>,
+    /// (group len, greedy profit, exhaustive profit)
+    samples: Vec<(usize, U256, U256)>,
+}
+
+impl ConflictValueComparator {
+    pub fn on_result(&mut self, group: &ConflictGroup, algorithm: Option<Algorithm>, profit: U256) {
+        // groups merged into this one won't get more results
+        for merged_id in group.conflicting_group_ids.iter() {
+            self.pending.remove(merged_id);
+        }
+        let group_len = group.orders.len();
+        if group_len < 2 || group_len > MAX_TRACKED_GROUP_LEN {
+            self.pending.remove(&group.id);
+            return;
+        }
+        let is_greedy = match algorithm {
+            Some(Algorithm::Greedy) => true,
+            Some(Algorithm::AllPermutations) => false,
+            _ => return,
+        };
+        let comparison = self
+            .pending
+            .entry(group.id)
+            .or_insert_with(|| PendingComparison {
+                group_len,
+                greedy_profit: None,
+                exhaustive_profit: None,
+            });
+        if comparison.group_len != group_len {
+            // results of an older version of the group are not comparable
+            *comparison = PendingComparison {
+                group_len,
+                greedy_profit: None,
+                exhaustive_profit: None,
+            };
+        }
+        if is_greedy {
+            comparison.greedy_profit = Some(profit);
+        } else {
+            comparison.exhaustive_profit = Some(profit);
+        }
+        if let (Some(greedy_profit), Some(exhaustive_profit)) =
+            (comparison.greedy_profit, comparison.exhaustive_profit)
+        {
+            self.pending.remove(&group.id);
+            self.samples
+                .push((group_len, greedy_profit, exhaustive_profit));
+        }
+    }
+}
+
+impl Drop for ConflictValueComparator {
+    fn drop(&mut self) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let mut history = CONFLICT_VALUE_HISTORY.lock();
+        for (group_len, greedy_profit, exhaustive_profit) in self.samples.drain(..) {
+            history.record(group_len, greedy_profit, exhaustive_profit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        primitives::{MempoolTx, Order, SimulatedOrder},
+        utils::test_utils::tx,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_gain_bps() {
+        assert_eq!(gain_bps(U256::from(100), U256::from(100)), 0.0);
+        assert_eq!(gain_bps(U256::from(100), U256::from(90)), 0.0);
+        assert_eq!(gain_bps(U256::from(100), U256::from(101)), 100.0);
+        assert_eq!(gain_bps(U256::ZERO, U256::from(1)), 10_000.0);
+        assert_eq!(gain_bps(U256::from(1), U256::from(1000)), 10_000.0);
+    }
+
+    #[test]
+    fn test_learned_max_group_len() {
+        let mut history = ConflictValueHistory::default();
+        // no data, default
+        assert_eq!(history.learned_max_group_len(3, 6, 10), 3);
+
+        // exhaustive search pays off on groups of 4
+        for _ in 0..MIN_SAMPLES {
+            history.record(4, U256::from(100), U256::from(110));
+        }
+        assert_eq!(history.learned_max_group_len(3, 6, 10), 4);
+        assert_eq!(history.learned_max_group_len(3, 3, 10), 3);
+
+        // but not on groups of 2, so we stop there
+        for _ in 0..MIN_SAMPLES {
+            history.record(2, U256::from(100), U256::from(100));
+        }
+        assert_eq!(history.learned_max_group_len(3, 6, 10), 1);
+    }
+
+    fn group(id: GroupId, len: usize, merged_ids: &[GroupId]) -> ConflictGroup {
+        let order = SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(tx(id as u64))),
+            sim_value: Default::default(),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        ConflictGroup {
+            id,
+            orders: Arc::new(vec![order; len]),
+            conflicting_group_ids: Arc::new(merged_ids.iter().copied().collect()),
+        }
+    }
+
+    #[test]
+    fn test_comparator_pairs_results() {
+        let mut comparator = ConflictValueComparator::default();
+        comparator.on_result(&group(1, 3, &[]), Some(Algorithm::Greedy), U256::from(10));
+        comparator.on_result(&group(1, 3, &[]), Some(Algorithm::Length), U256::from(12));
+        assert_eq!(comparator.pending.len(), 1);
+        comparator.on_result(
+            &group(1, 3, &[]),
+            Some(Algorithm::AllPermutations),
+            U256::from(11),
+        );
+        assert!(comparator.pending.is_empty());
+        assert_eq!(
+            comparator.samples,
+            vec![(3, U256::from(10), U256::from(11))]
+        );
+        // single order groups are not tracked
+        comparator.on_result(&group(2, 1, &[]), Some(Algorithm::Greedy), U256::from(10));
+        assert!(comparator.pending.is_empty());
+        // keep them off the process wide history
+        comparator.samples.clear();
+    }
+
+    #[test]
+    fn test_comparator_group_versions() {
+        let mut comparator = ConflictValueComparator::default();
+        comparator.on_result(&group(1, 3, &[]), Some(Algorithm::Greedy), U256::from(10));
+        // the group got an order, the greedy result of the old version is dropped
+        comparator.on_result(
+            &group(1, 4, &[]),
+            Some(Algorithm::AllPermutations),
+            U256::from(20),
+        );
+        assert!(comparator.samples.is_empty());
+        comparator.on_result(&group(1, 4, &[]), Some(Algorithm::Greedy), U256::from(15));
+        assert_eq!(
+            comparator.samples,
+            vec![(4, U256::from(15), U256::from(20))]
+        );
+
+        // merged groups are drained
+        comparator.on_result(&group(2, 2, &[]), Some(Algorithm::Greedy), U256::from(10));
+        comparator.on_result(&group(3, 2, &[]), Some(Algorithm::Greedy), U256::from(10));
+        assert_eq!(comparator.pending.len(), 2);
+        comparator.on_result(
+            &group(4, 5, &[2, 3]),
+            Some(Algorithm::Greedy),
+            U256::from(10),
+        );
+        assert_eq!(
+            comparator.pending.keys().copied().collect::<Vec<_>>(),
+            vec![4]
+        );
+        comparator.samples.clear();
+    }
+}
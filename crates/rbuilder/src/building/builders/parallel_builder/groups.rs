@@ -9,6 +9,8 @@ use revm_primitives::{Address, B256};
 
 use std::sync::Arc;
 
+use super::Algorithm;
+
 /// ResolutionResult describes order of certain groups of orders.
 #[derive(Debug, Default, Clone)]
 pub struct ResolutionResult {
@@ -16,6 +18,8 @@ pub struct ResolutionResult {
     pub total_profit: U256,
     /// Sequence of orders and their profit in that sequence
     pub sequence_of_orders: Vec<(usize, U256)>,
+    /// Algorithm that produced this ordering, None if no resolution was needed (eg: single order groups).
+    pub algorithm: Option<Algorithm>,
 }
 
 /// ConflictGroups describes set of conflicting orders.
@@ -475,13 +479,11 @@ mod tests {
             }
 
             SimulatedOrder {
-                order: Order::Tx(MempoolTx {
-                    tx_with_blobs: TransactionSignedEcRecoveredWithBlobs::new_no_blobs(
-                        self.create_tx(),
-                    )
-                    .unwrap(),
-                }),
+                order: Order::Tx(MempoolTx::new(
+                    TransactionSignedEcRecoveredWithBlobs::new_no_blobs(self.create_tx()).unwrap(),
+                )),
                 used_state_trace: Some(trace),
+                execution_cost: Default::default(),
                 sim_value: SimValue::default(),
                 prev_order: None,
             }
@@ -2,11 +2,14 @@ pub mod block_building_result_assembler;
 pub mod conflict_resolvers;
 pub mod conflict_resolving_pool;
 pub mod conflict_task_generator;
+pub mod conflict_value_history;
 pub mod groups;
 pub mod order_intake_store;
 pub mod results_aggregator;
 pub mod simulation_cache;
+pub mod strategy_selector;
 pub mod task;
+pub mod value_bound;
 pub use groups::*;
 
 use ahash::HashMap;
@@ -23,16 +26,21 @@ use std::{
     thread,
     time::Instant,
 };
+use strategy_selector::StrategySelector;
 use task::*;
-use time::OffsetDateTime;
 use tokio_util::sync::CancellationToken;
 use tracing::{error, trace};
 
 use crate::{
-    building::builders::{
-        BacktestSimulateBlockInput, Block, BlockBuildingAlgorithm, BlockBuildingAlgorithmInput,
-        LiveBuilderInput,
+    building::{
+        builders::{
+            BacktestSimulateBlockInput, Block, BlockBuildingAlgorithm, BlockBuildingAlgorithmInput,
+            LiveBuilderInput, ZeroProfitTxPolicy,
+        },
+        conflict_resolver::DEFAULT_MAX_SET_LEN,
+        recurrent_orders::SharedRecurrentOrders,
     },
+    primitives::SimulatedOrder,
     roothash::RootHashConfig,
 };
 use alloy_primitives::Address;
@@ -51,6 +59,16 @@ pub type ConflictResolutionResultPerGroup = (GroupId, (ResolutionResult, Conflic
 /// ParallelBuilderConfig configures parallel builder.
 /// * `num_threads` - number of threads to use for merging.
 /// * `merge_wait_time_ms` - time to wait for merging to finish before consuming new orders.
+/// * `max_group_len_for_exhaustive_search` - groups up to this size are resolved trying all permutations.
+/// * `min_time_left_for_exhaustive_search_ms` - time left until the slot needed to schedule exhaustive search.
+/// * `min_time_left_for_heuristic_search_ms` - below this time left until the slot only nonce sort is scheduled.
+/// * `adaptive_max_group_len_for_exhaustive_search` - if set, the max group len for exhaustive search is learned
+///   (up to this value) from how much value exhaustive search added over greedy in the past (see [conflict_value_history]).
+/// * `adaptive_exhaustive_search_min_gain_bps` - min avg gain over greedy for a group len to be resolved exhaustively.
+/// * `prune_groups_below_marginal_gas_value` - groups that can't beat the marginal gas price of the block only get
+///   greedy treatment (see [value_bound]).
+/// * `max_conflict_group_len` - bigger groups are only resolved on their max_conflict_group_len orders with the best
+///   profit alone (see [StrategySelector]), 0 disables the pruning.
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct ParallelBuilderConfig {
@@ -58,6 +76,50 @@ pub struct ParallelBuilderConfig {
     pub num_threads: usize,
     #[serde(default)]
     pub coinbase_payment: bool,
+    #[serde(default = "default_max_group_len_for_exhaustive_search")]
+    pub max_group_len_for_exhaustive_search: usize,
+    #[serde(default = "default_min_time_left_for_exhaustive_search_ms")]
+    pub min_time_left_for_exhaustive_search_ms: u64,
+    #[serde(default)]
+    pub min_time_left_for_heuristic_search_ms: u64,
+    #[serde(default)]
+    pub adaptive_max_group_len_for_exhaustive_search: Option<usize>,
+    #[serde(default = "default_adaptive_exhaustive_search_min_gain_bps")]
+    pub adaptive_exhaustive_search_min_gain_bps: u64,
+    #[serde(default = "default_prune_groups_below_marginal_gas_value")]
+    pub prune_groups_below_marginal_gas_value: bool,
+    #[serde(default = "default_max_conflict_group_len")]
+    pub max_conflict_group_len: usize,
+    /// What to do with mempool txs with no coinbase profit, see [`ZeroProfitTxPolicy`].
+    #[serde(default)]
+    pub zero_profit_txs: ZeroProfitTxPolicy,
+    /// With zero_profit_txs = "include", zero profit txs using more gas than this are skipped anyway.
+    #[serde(default)]
+    pub zero_profit_tx_max_gas: Option<u64>,
+    /// Last ms of the slot where groups are merged by value per execution cost instead of total profit (see
+    /// [`crate::building::builders::in_value_per_cost_window`]).
+    #[serde(default)]
+    pub value_per_cost_window_ms: Option<u64>,
+}
+
+fn default_max_group_len_for_exhaustive_search() -> usize {
+    3
+}
+
+fn default_min_time_left_for_exhaustive_search_ms() -> u64 {
+    500
+}
+
+fn default_adaptive_exhaustive_search_min_gain_bps() -> u64 {
+    10
+}
+
+fn default_prune_groups_below_marginal_gas_value() -> bool {
+    true
+}
+
+fn default_max_conflict_group_len() -> usize {
+    DEFAULT_MAX_SET_LEN
 }
 
 fn get_communication_channels() -> (
@@ -80,6 +142,8 @@ struct ParallelBuilder<P, DB> {
     conflict_resolving_pool: ConflictResolvingPool<P>,
     results_aggregator: ResultsAggregator,
     block_building_result_assembler: BlockBuildingResultAssembler<P, DB>,
+    block: u64,
+    recurrent_orders: SharedRecurrentOrders,
 }
 
 impl<P, DB> ParallelBuilder<P, DB>
@@ -92,7 +156,15 @@ where
 {
     /// Creates a ParallelBuilder.
     /// Sets up the various components and communication channels.
-    pub fn new(input: LiveBuilderInput<P, DB>, config: &ParallelBuilderConfig) -> Self {
+    /// recurrent_orders outlives the slot, it's used to replay the orderings found on previous slots.
+    pub fn new(
+        input: LiveBuilderInput<P, DB>,
+        config: &ParallelBuilderConfig,
+        recurrent_orders: SharedRecurrentOrders,
+    ) -> Self {
+        let block = input.ctx.block();
+        recurrent_orders.lock().prune(block);
+
         let (group_result_sender, group_result_receiver) = get_communication_channels();
         let group_result_sender_for_task_generator = group_result_sender.clone();
 
@@ -102,10 +174,21 @@ where
 
         let conflict_finder = ConflictFinder::new();
 
+        let mut strategy_selector =
+            StrategySelector::new(config, Some(input.ctx.timestamp()), input.ctx.clock.clone());
+        if config.prune_groups_below_marginal_gas_value {
+            strategy_selector =
+                strategy_selector.with_block_gas_limit(input.ctx.block_env.gas_limit.to());
+        }
+        if input.ctx.reduced_effort {
+            strategy_selector.disable_exhaustive_search();
+        }
         let conflict_task_generator = ConflictTaskGenerator::new(
             Arc::clone(&task_queue),
             group_result_sender_for_task_generator,
-        );
+            strategy_selector,
+        )
+        .with_recurrent_orders(block, Arc::clone(&recurrent_orders));
 
         let conflict_resolving_pool = ConflictResolvingPool::new(
             config.num_threads,
@@ -118,7 +201,8 @@ where
         );
 
         let results_aggregator =
-            ResultsAggregator::new(group_result_receiver, Arc::clone(&best_results));
+            ResultsAggregator::new(group_result_receiver, Arc::clone(&best_results))
+                .with_recurrent_orders(block, Arc::clone(&recurrent_orders));
 
         let block_building_result_assembler = BlockBuildingResultAssembler::new(
             config,
@@ -142,6 +226,8 @@ where
             conflict_resolving_pool,
             results_aggregator,
             block_building_result_assembler,
+            block,
+            recurrent_orders,
         }
     }
 
@@ -149,10 +235,22 @@ where
     fn initialize_orders(&mut self) {
         let initial_orders = self.order_intake_consumer.get_orders();
         trace!("Initializing with {} orders", initial_orders.len());
+        observe_recurrent_orders(&self.recurrent_orders, self.block, &initial_orders);
         self.conflict_finder.add_orders(initial_orders);
     }
 }
 
+fn observe_recurrent_orders(
+    recurrent_orders: &SharedRecurrentOrders,
+    block: u64,
+    orders: &[SimulatedOrder],
+) {
+    let mut recurrent_orders = recurrent_orders.lock();
+    for order in orders {
+        recurrent_orders.observe(block, &order.order);
+    }
+}
+
 /// Runs the parallel builder algorithm to construct blocks from incoming orders.
 ///
 /// This function implements a continuous block building process that:
@@ -180,11 +278,15 @@ where
 /// # Arguments
 /// * `input`: LiveBuilderInput containing necessary context and resources for block building.
 /// * `config`: Configuration parameters for the parallel builder.
+/// * `recurrent_orders`: Orders (and their best orderings) seen on previous slots.
 ///
 /// # Type Parameters
 /// * `DB`: The database type, which must implement Database, Clone, and have a static lifetime.
-pub fn run_parallel_builder<P, DB>(input: LiveBuilderInput<P, DB>, config: &ParallelBuilderConfig)
-where
+pub fn run_parallel_builder<P, DB>(
+    input: LiveBuilderInput<P, DB>,
+    config: &ParallelBuilderConfig,
+    recurrent_orders: SharedRecurrentOrders,
+) where
     DB: Database + Clone + 'static,
     P: DatabaseProviderFactory<DB = DB, Provider: BlockReader>
         + StateProviderFactory
@@ -195,7 +297,7 @@ where
     let cancel_for_block_building_result_assembler = input.cancel.clone();
     let cancel_for_process_orders_loop = input.cancel.clone();
 
-    let mut builder = ParallelBuilder::new(input, config);
+    let mut builder = ParallelBuilder::new(input, config, recurrent_orders);
     builder.initialize_orders();
 
     // Start task processing
@@ -224,6 +326,8 @@ where
         &mut builder.order_intake_consumer,
         &mut builder.conflict_finder,
         &mut builder.conflict_task_generator,
+        builder.block,
+        &builder.recurrent_orders,
     );
 }
 
@@ -232,6 +336,8 @@ fn run_order_intake(
     order_intake_consumer: &mut OrderIntakeStore,
     conflict_finder: &mut ConflictFinder,
     conflict_task_generator: &mut ConflictTaskGenerator,
+    block: u64,
+    recurrent_orders: &SharedRecurrentOrders,
 ) {
     'building: loop {
         if cancel_token.is_cancelled() {
@@ -257,6 +363,7 @@ fn run_order_intake(
             if !new_orders.is_empty() {
                 let time_start = Instant::now();
                 let len = new_orders.len();
+                observe_recurrent_orders(recurrent_orders, block, &new_orders);
                 conflict_finder.add_orders(new_orders);
                 trace!(
                     new_orders_count = len,
@@ -319,11 +426,19 @@ where
     // Group processing
     let processing_start = Instant::now();
     let groups = conflict_finder.get_order_groups();
+    // No time limit in backtests, the slot already happened.
+    let mut strategy_selector = StrategySelector::new(&config, None, input.ctx.clock.clone());
+    if config.prune_groups_below_marginal_gas_value {
+        strategy_selector =
+            strategy_selector.with_block_gas_limit(input.ctx.block_env.gas_limit.to());
+    }
+    strategy_selector.update_marginal_gas_price(&groups);
     let results = conflict_resolving_pool.process_groups_backtest(
         groups,
         &input.ctx,
         &input.provider,
         Arc::clone(&simulation_cache),
+        &strategy_selector,
     );
     let processing_duration = processing_start.elapsed();
 
@@ -356,7 +471,7 @@ where
     // Block building
     let building_start = Instant::now();
     let block_building_helper = block_building_result_assembler
-        .build_backtest_block(best_results, OffsetDateTime::now_utc())?;
+        .build_backtest_block(best_results, input.ctx.clock.now_utc())?;
 
     let payout_tx_value = if config.coinbase_payment {
         None
@@ -387,6 +502,8 @@ pub struct ParallelBuildingAlgorithm {
     sbundle_mergeabe_signers: Vec<Address>,
     config: ParallelBuilderConfig,
     name: String,
+    /// Shared by all the slots.
+    recurrent_orders: SharedRecurrentOrders,
 }
 
 impl ParallelBuildingAlgorithm {
@@ -401,6 +518,7 @@ impl ParallelBuildingAlgorithm {
             sbundle_mergeabe_signers,
             config,
             name,
+            recurrent_orders: Default::default(),
         }
     }
 }
@@ -429,6 +547,10 @@ where
             sbundle_mergeabe_signers: self.sbundle_mergeabe_signers.clone(),
             phantom: Default::default(),
         };
-        run_parallel_builder(live_input, &self.config);
+        run_parallel_builder(
+            live_input,
+            &apply_overrides(&self.name, &self.config),
+            Arc::clone(&self.recurrent_orders),
+        );
     }
 }
@@ -1,4 +1,9 @@
-use super::{ConflictGroup, GroupId, ResolutionResult};
+use super::{
+    conflict_value_history::ConflictValueComparator, ConflictGroup, GroupId, ResolutionResult,
+};
+use crate::building::recurrent_orders::{
+    group_fingerprint, order_fingerprint, SharedRecurrentOrders,
+};
 use alloy_primitives::{utils::format_ether, U256};
 use dashmap::DashMap;
 use std::{
@@ -65,6 +70,10 @@ impl Default for BestResults {
 pub struct ResultsAggregator {
     group_result_receiver: std_mpsc::Receiver<(GroupId, (ResolutionResult, ConflictGroup))>,
     best_results: Arc<BestResults>,
+    /// Feeds the greedy vs exhaustive search history.
+    value_comparator: ConflictValueComparator,
+    /// (block we are building, orders seen on previous slots) to remember the best orderings for the next slots.
+    recurrent_orders: Option<(u64, SharedRecurrentOrders)>,
 }
 
 impl ResultsAggregator {
@@ -82,6 +91,20 @@ impl ResultsAggregator {
         Self {
             group_result_receiver,
             best_results,
+            value_comparator: ConflictValueComparator::default(),
+            recurrent_orders: None,
+        }
+    }
+
+    /// Records the best ordering of each group so the next slots can replay it if the same orders come back.
+    pub fn with_recurrent_orders(
+        self,
+        block: u64,
+        recurrent_orders: SharedRecurrentOrders,
+    ) -> Self {
+        Self {
+            recurrent_orders: Some((block, recurrent_orders)),
+            ..self
         }
     }
 
@@ -183,18 +206,24 @@ impl ResultsAggregator {
     /// * `sequence_of_orders` - The new ordering information for the group.
     /// * `order_group` - The new order group.
     fn process_update(
-        &self,
+        &mut self,
         group_id: GroupId,
         sequence_of_orders: ResolutionResult,
         order_group: ConflictGroup,
     ) {
         let start = Instant::now();
         trace!("Received group result for group: {:?}", group_id);
+        self.value_comparator.on_result(
+            &order_group,
+            sequence_of_orders.algorithm,
+            sequence_of_orders.total_profit,
+        );
         let (best_result_updated, old_profit) =
-            self.update_best_result(group_id, sequence_of_orders.clone(), order_group);
+            self.update_best_result(group_id, sequence_of_orders.clone(), order_group.clone());
         let duration = start.elapsed();
 
         if best_result_updated {
+            self.record_recurrent_resolution(&sequence_of_orders, &order_group);
             self.log_updated_result(group_id, &sequence_of_orders, old_profit, duration);
         } else {
             trace!(
@@ -205,6 +234,33 @@ impl ResultsAggregator {
         }
     }
 
+    fn record_recurrent_resolution(
+        &self,
+        sequence_of_orders: &ResolutionResult,
+        order_group: &ConflictGroup,
+    ) {
+        let Some((block, recurrent_orders)) = &self.recurrent_orders else {
+            return;
+        };
+        if order_group.orders.len() < 2 {
+            return;
+        }
+        let group = group_fingerprint(
+            order_group
+                .orders
+                .iter()
+                .map(|o| order_fingerprint(&o.order)),
+        );
+        let ordering = sequence_of_orders
+            .sequence_of_orders
+            .iter()
+            .map(|(idx, _)| order_fingerprint(&order_group.orders[*idx].order))
+            .collect();
+        recurrent_orders
+            .lock()
+            .record_resolution(*block, group, ordering);
+    }
+
     /// Helper function to log the updated best result for a given group.
     fn log_updated_result(
         &self,
@@ -257,6 +313,7 @@ mod tests {
         ResolutionResult {
             total_profit: U256::from(profit),
             sequence_of_orders: vec![],
+            algorithm: None,
         }
     }
 
@@ -0,0 +1,571 @@
+use alloy_primitives::U256;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+use tracing::trace;
+
+use super::{
+    conflict_value_history::{learned_max_group_len, should_explore},
+    task::ConflictTask,
+    value_bound::{is_below_marginal_value, marginal_gas_price},
+    Algorithm, ConflictGroup, GroupId, ParallelBuilderConfig, ResolutionStrategy, TaskPriority,
+};
+use crate::{
+    telemetry::add_conflict_set_pruned,
+    utils::clock::{system_clock, ClockRef},
+};
+
+const NUMBER_OF_RANDOM_TASKS: usize = 50;
+
+/// Decides, per [ConflictGroup], which [ResolutionStrategy] to run based on the group size and the time left in the slot.
+///
+/// - Exhaustive search is only used on small groups while there is plenty of time left.
+/// - Heuristics are used on the rest of the groups.
+/// - When we are about to run out of time only the deterministic nonce sort is scheduled.
+///
+/// If adaptive_max_group_len is set the max group len for exhaustive search comes from
+/// [conflict_value_history](super::conflict_value_history).
+///
+/// If a block gas limit is set, groups whose profit upper bound is below the value of their gas at the marginal gas
+/// price (see [value_bound](super::value_bound)) only get greedy treatment.
+///
+/// Groups with more than max_group_len orders are pruned (as [ConflictResolver](crate::building::conflict_resolver::ConflictResolver)
+/// does with its sets): the tasks only get the max_group_len orders with the best profit alone.
+#[derive(Debug, Clone)]
+pub struct StrategySelector {
+    max_group_len_for_exhaustive_search: usize,
+    /// (learned max group len, limit) if adaptive, learned when the selector is created.
+    adaptive_max_group_len: Option<(usize, usize)>,
+    min_time_left_for_exhaustive_search: Duration,
+    min_time_left_for_heuristic_search: Duration,
+    /// None means no time limit (eg: backtesting).
+    slot_deadline: Option<OffsetDateTime>,
+    /// Time source for the time left until slot_deadline.
+    clock: ClockRef,
+    /// None disables the marginal value pruning.
+    block_gas_limit: Option<u64>,
+    marginal_gas_price: U256,
+    /// 0 disables the pruning.
+    max_group_len: usize,
+}
+
+impl Default for StrategySelector {
+    fn default() -> Self {
+        Self {
+            max_group_len_for_exhaustive_search: 3,
+            adaptive_max_group_len: None,
+            min_time_left_for_exhaustive_search: Duration::ZERO,
+            min_time_left_for_heuristic_search: Duration::ZERO,
+            slot_deadline: None,
+            clock: system_clock(),
+            block_gas_limit: None,
+            marginal_gas_price: U256::ZERO,
+            max_group_len: 0,
+        }
+    }
+}
+
+impl StrategySelector {
+    /// Creates a new [StrategySelector].
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the Parallel builder.
+    /// * `slot_deadline` - Time at which we stop caring about new results, None for no time limit.
+    /// * `clock` - Time source of the slot, usually [BlockBuildingContext::clock](crate::building::BlockBuildingContext::clock).
+    pub fn new(
+        config: &ParallelBuilderConfig,
+        slot_deadline: Option<OffsetDateTime>,
+        clock: ClockRef,
+    ) -> Self {
+        Self {
+            max_group_len_for_exhaustive_search: config.max_group_len_for_exhaustive_search,
+            adaptive_max_group_len: config.adaptive_max_group_len_for_exhaustive_search.map(
+                |limit| {
+                    let learned = learned_max_group_len(
+                        config.max_group_len_for_exhaustive_search,
+                        limit,
+                        config.adaptive_exhaustive_search_min_gain_bps,
+                    );
+                    (learned, limit)
+                },
+            ),
+            min_time_left_for_exhaustive_search: Duration::from_millis(
+                config.min_time_left_for_exhaustive_search_ms,
+            ),
+            min_time_left_for_heuristic_search: Duration::from_millis(
+                config.min_time_left_for_heuristic_search_ms,
+            ),
+            slot_deadline,
+            clock,
+            block_gas_limit: None,
+            marginal_gas_price: U256::ZERO,
+            max_group_len: config.max_conflict_group_len,
+        }
+    }
+
+    /// Enables the marginal value pruning.
+    pub fn with_block_gas_limit(self, block_gas_limit: u64) -> Self {
+        Self {
+            block_gas_limit: Some(block_gas_limit),
+            ..self
+        }
+    }
+
+    /// Recomputes the marginal gas price from all the groups competing for the block.
+    pub fn update_marginal_gas_price<'a>(
+        &mut self,
+        groups: impl IntoIterator<Item = &'a ConflictGroup>,
+    ) {
+        if let Some(block_gas_limit) = self.block_gas_limit {
+            self.marginal_gas_price = marginal_gas_price(groups, block_gas_limit);
+        }
+    }
+
+    /// Only heuristics from now on, for slots not worth the cpu.
+    pub fn disable_exhaustive_search(&mut self) {
+        self.max_group_len_for_exhaustive_search = 0;
+        self.adaptive_max_group_len = None;
+    }
+
+    /// Selects the strategy for a group using the current time of the clock.
+    pub fn select_strategy(&self, group_id: GroupId, group_len: usize) -> ResolutionStrategy {
+        self.select_strategy_at(
+            group_len,
+            self.max_group_len_for_exhaustive_search(group_id),
+            self.clock.now_utc(),
+        )
+    }
+
+    /// Static config value or, if adaptive, the learned one.
+    /// Some groups just above the learned value also get exhaustive search to keep learning.
+    fn max_group_len_for_exhaustive_search(&self, group_id: GroupId) -> usize {
+        let Some((learned, limit)) = self.adaptive_max_group_len else {
+            return self.max_group_len_for_exhaustive_search;
+        };
+        if learned < limit && should_explore(group_id) {
+            learned + 1
+        } else {
+            learned
+        }
+    }
+
+    fn select_strategy_at(
+        &self,
+        group_len: usize,
+        max_group_len_for_exhaustive_search: usize,
+        now: OffsetDateTime,
+    ) -> ResolutionStrategy {
+        let time_left = self
+            .slot_deadline
+            .map(|deadline| Duration::try_from(deadline - now).unwrap_or_default());
+
+        if let Some(time_left) = time_left {
+            if time_left < self.min_time_left_for_heuristic_search {
+                return ResolutionStrategy::NonceSort;
+            }
+        }
+
+        let enough_time_for_exhaustive = time_left
+            .map(|time_left| time_left >= self.min_time_left_for_exhaustive_search)
+            .unwrap_or(true);
+        if group_len <= max_group_len_for_exhaustive_search && enough_time_for_exhaustive {
+            ResolutionStrategy::Exhaustive
+        } else {
+            ResolutionStrategy::Heuristic
+        }
+    }
+
+    /// Generates a vector of conflict tasks for a given order group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The `ConflictGroup` to create tasks for.
+    /// * `priority` - The priority to assign to the tasks.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `ConflictTask`s for the given group.
+    pub fn tasks_for_group(
+        &self,
+        group: &ConflictGroup,
+        priority: TaskPriority,
+    ) -> Vec<ConflictTask> {
+        let pruned_group = prune_group(group, self.max_group_len);
+        let group = pruned_group.as_ref().unwrap_or(group);
+        let mut strategy = self.select_strategy(group.id, group.orders.len());
+        if strategy != ResolutionStrategy::NonceSort
+            && is_below_marginal_value(group, self.marginal_gas_price)
+        {
+            trace!(
+                group = group.id,
+                "Group below marginal gas value, greedy only"
+            );
+            strategy = ResolutionStrategy::GreedyOnly;
+        }
+        tasks_for_strategy(strategy, group, priority, self.gas_budget(group))
+    }
+
+    /// Generates the tasks for a group already resolved on a previous slot: the previous best ordering and greedy.
+    /// The ordering is re-executed so it's only used if it's still valid for this block.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The `ConflictGroup` to create tasks for.
+    /// * `ordering` - Indexes of the orders of the group in the previous best order (missing orders go last).
+    /// * `priority` - The priority to assign to the tasks.
+    pub fn tasks_for_previous_ordering(
+        &self,
+        group: &ConflictGroup,
+        ordering: &[usize],
+        priority: TaskPriority,
+    ) -> Vec<ConflictTask> {
+        let mut orders: Vec<_> = ordering
+            .iter()
+            .map(|idx| group.orders[*idx].clone())
+            .collect();
+        orders.extend(
+            (0..group.orders.len())
+                .filter(|idx| !ordering.contains(idx))
+                .map(|idx| group.orders[idx].clone()),
+        );
+        let ordered_group = ConflictGroup {
+            id: group.id,
+            orders: Arc::new(orders),
+            conflicting_group_ids: group.conflicting_group_ids.clone(),
+        };
+        tasks_for_strategy(
+            ResolutionStrategy::Recurrent,
+            &ordered_group,
+            priority,
+            self.gas_budget(group),
+        )
+    }
+
+    fn gas_budget(&self, group: &ConflictGroup) -> Option<u64> {
+        self.block_gas_limit.filter(|block_gas_limit| {
+            let group_gas: u64 = group.orders.iter().map(|o| o.sim_value.gas_used).sum();
+            group_gas > *block_gas_limit
+        })
+    }
+}
+
+/// Some(group with only the max_len orders with the best profit alone) if group is bigger than max_len (and max_len != 0).
+/// Kept orders stay in the group order (ties keep the first ones).
+fn prune_group(group: &ConflictGroup, max_len: usize) -> Option<ConflictGroup> {
+    if max_len == 0 || group.orders.len() <= max_len {
+        return None;
+    }
+    let mut by_profit: Vec<usize> = (0..group.orders.len()).collect();
+    by_profit.sort_by(|a, b| {
+        group.orders[*b]
+            .sim_value
+            .coinbase_profit
+            .cmp(&group.orders[*a].sim_value.coinbase_profit)
+    });
+    let pruned = by_profit.split_off(max_len);
+    let pruned_value = pruned
+        .iter()
+        .map(|idx| group.orders[*idx].sim_value.coinbase_profit)
+        .sum();
+    add_conflict_set_pruned(pruned.len(), pruned_value);
+    trace!(
+        group = group.id,
+        pruned_orders = pruned.len(),
+        "Pruned conflict group"
+    );
+    by_profit.sort_unstable();
+    Some(ConflictGroup {
+        id: group.id,
+        orders: Arc::new(
+            by_profit
+                .into_iter()
+                .map(|idx| group.orders[idx].clone())
+                .collect(),
+        ),
+        conflicting_group_ids: group.conflicting_group_ids.clone(),
+    })
+}
+
+fn tasks_for_strategy(
+    strategy: ResolutionStrategy,
+    group: &ConflictGroup,
+    priority: TaskPriority,
+    gas_budget: Option<u64>,
+) -> Vec<ConflictTask> {
+    let created_at = Instant::now();
+    let new_task = |algorithm: Algorithm, priority: TaskPriority| ConflictTask {
+        group_idx: group.id,
+        algorithm,
+        priority,
+        group: group.clone(),
+        created_at,
+        gas_budget,
+    };
+
+    match strategy {
+        ResolutionStrategy::NonceSort => vec![new_task(Algorithm::NonceSort, priority)],
+        ResolutionStrategy::GreedyOnly => vec![new_task(Algorithm::Greedy, priority)],
+        ResolutionStrategy::Recurrent => vec![
+            new_task(Algorithm::Recurrent, priority),
+            new_task(Algorithm::Greedy, priority),
+        ],
+        // We want to run Greedy first so we can get quick, decent results
+        ResolutionStrategy::Exhaustive => vec![
+            new_task(Algorithm::Greedy, priority),
+            new_task(Algorithm::AllPermutations, priority),
+        ],
+        // Then, we can push lower priority tasks that have a low chance, but a chance, of finding a better result
+        ResolutionStrategy::Heuristic => vec![
+            new_task(Algorithm::Greedy, priority),
+            new_task(
+                Algorithm::Random {
+                    seed: group.id as u64,
+                    count: NUMBER_OF_RANDOM_TASKS,
+                },
+                TaskPriority::Low,
+            ),
+            new_task(Algorithm::Length, TaskPriority::Low),
+            new_task(Algorithm::ReverseGreedy, TaskPriority::Low),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::builders::parallel_builder::conflict_value_history::EXPLORATION_INTERVAL;
+    use crate::{
+        primitives::{
+            MempoolTx, Order, SimValue, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs,
+        },
+        utils::clock::SimulatedClock,
+    };
+    use ahash::HashSet;
+    use alloy_consensus::TxLegacy;
+    use alloy_primitives::Address;
+    use reth::primitives::{Transaction, TransactionSigned, TransactionSignedEcRecovered};
+
+    fn order(profit: u64) -> SimulatedOrder {
+        let tx = TransactionSignedEcRecovered::from_signed_transaction(
+            TransactionSigned {
+                transaction: Transaction::Legacy(TxLegacy::default()),
+                ..Default::default()
+            },
+            Address::default(),
+        );
+        SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            )),
+            sim_value: SimValue::new(U256::from(profit), 21_000, 0, Vec::new()),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        }
+    }
+
+    fn create_selector(slot_deadline: Option<OffsetDateTime>) -> StrategySelector {
+        StrategySelector {
+            max_group_len_for_exhaustive_search: 3,
+            adaptive_max_group_len: None,
+            min_time_left_for_exhaustive_search: Duration::from_millis(1000),
+            min_time_left_for_heuristic_search: Duration::from_millis(100),
+            slot_deadline,
+            clock: system_clock(),
+            block_gas_limit: None,
+            marginal_gas_price: U256::ZERO,
+            max_group_len: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_strategy_without_deadline() {
+        let selector = create_selector(None);
+        let now = OffsetDateTime::now_utc();
+        assert_eq!(
+            selector.select_strategy_at(3, 3, now),
+            ResolutionStrategy::Exhaustive
+        );
+        assert_eq!(
+            selector.select_strategy_at(4, 3, now),
+            ResolutionStrategy::Heuristic
+        );
+    }
+
+    #[test]
+    fn test_disable_exhaustive_search() {
+        let mut selector = create_selector(None);
+        assert_eq!(
+            selector.select_strategy(0, 1),
+            ResolutionStrategy::Exhaustive
+        );
+        selector.disable_exhaustive_search();
+        assert_eq!(
+            selector.select_strategy(0, 1),
+            ResolutionStrategy::Heuristic
+        );
+    }
+
+    #[test]
+    fn test_adaptive_max_group_len() {
+        let mut selector = create_selector(None);
+        selector.adaptive_max_group_len = Some((4, 6));
+        assert_eq!(selector.max_group_len_for_exhaustive_search(1), 4);
+        // exploring the next len
+        assert_eq!(
+            selector.max_group_len_for_exhaustive_search(EXPLORATION_INTERVAL),
+            5
+        );
+        selector.adaptive_max_group_len = Some((6, 6));
+        assert_eq!(
+            selector.max_group_len_for_exhaustive_search(EXPLORATION_INTERVAL),
+            6
+        );
+    }
+
+    #[test]
+    fn test_select_strategy_with_deadline() {
+        let now = OffsetDateTime::now_utc();
+        let selector = create_selector(Some(now + time::Duration::seconds(2)));
+        assert_eq!(
+            selector.select_strategy_at(2, 3, now),
+            ResolutionStrategy::Exhaustive
+        );
+
+        // Not enough time for exhaustive search
+        let selector = create_selector(Some(now + time::Duration::milliseconds(500)));
+        assert_eq!(
+            selector.select_strategy_at(2, 3, now),
+            ResolutionStrategy::Heuristic
+        );
+
+        // Deadline passed, only nonce sort
+        let selector = create_selector(Some(now - time::Duration::seconds(1)));
+        assert_eq!(
+            selector.select_strategy_at(2, 3, now),
+            ResolutionStrategy::NonceSort
+        );
+        assert_eq!(
+            selector.select_strategy_at(10, 3, now),
+            ResolutionStrategy::NonceSort
+        );
+    }
+
+    #[test]
+    fn test_select_strategy_with_clock() {
+        let now = OffsetDateTime::now_utc();
+        let clock = Arc::new(SimulatedClock::new(now));
+        let selector = StrategySelector {
+            clock: clock.clone(),
+            ..create_selector(Some(now + time::Duration::seconds(2)))
+        };
+        assert_eq!(
+            selector.select_strategy(0, 2),
+            ResolutionStrategy::Exhaustive
+        );
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(
+            selector.select_strategy(0, 2),
+            ResolutionStrategy::Heuristic
+        );
+        clock.advance(Duration::from_millis(450));
+        assert_eq!(
+            selector.select_strategy(0, 2),
+            ResolutionStrategy::NonceSort
+        );
+    }
+
+    #[test]
+    fn test_tasks_for_strategy() {
+        let group = ConflictGroup {
+            id: 1,
+            orders: Arc::new(vec![]),
+            conflicting_group_ids: Arc::new(HashSet::default()),
+        };
+        for (strategy, expected_len) in [
+            (ResolutionStrategy::NonceSort, 1),
+            (ResolutionStrategy::GreedyOnly, 1),
+            (ResolutionStrategy::Exhaustive, 2),
+            (ResolutionStrategy::Heuristic, 4),
+            (ResolutionStrategy::Recurrent, 2),
+        ] {
+            let tasks = tasks_for_strategy(strategy, &group, TaskPriority::High, None);
+            assert_eq!(tasks.len(), expected_len);
+            // first task always keeps the requested priority
+            assert_eq!(tasks[0].priority, TaskPriority::High);
+            if strategy == ResolutionStrategy::Exhaustive {
+                assert!(tasks
+                    .iter()
+                    .any(|task| task.algorithm.strategy() == ResolutionStrategy::Exhaustive));
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_group() {
+        let group = ConflictGroup {
+            id: 1,
+            orders: Arc::new([3, 1, 4, 1, 5].into_iter().map(order).collect()),
+            conflicting_group_ids: Arc::new(HashSet::default()),
+        };
+        let profits = |group: &ConflictGroup| {
+            group
+                .orders
+                .iter()
+                .map(|o| o.sim_value.coinbase_profit.to::<u64>())
+                .collect::<Vec<_>>()
+        };
+        assert!(prune_group(&group, 0).is_none());
+        assert!(prune_group(&group, 5).is_none());
+        // best 3 in the group order
+        let pruned = prune_group(&group, 3).unwrap();
+        assert_eq!(pruned.id, group.id);
+        assert_eq!(profits(&pruned), vec![3, 4, 5]);
+
+        // tasks only get the pruned group
+        let selector = StrategySelector {
+            max_group_len: 3,
+            ..create_selector(None)
+        };
+        let tasks = selector.tasks_for_group(&group, TaskPriority::High);
+        assert!(tasks
+            .iter()
+            .all(|task| profits(&task.group) == vec![3, 4, 5]));
+        // 3 orders fit the exhaustive search
+        assert!(tasks
+            .iter()
+            .any(|task| task.algorithm.strategy() == ResolutionStrategy::Exhaustive));
+    }
+
+    #[test]
+    fn test_group_below_marginal_gas_value() {
+        let group = |id, profits: &[u64]| ConflictGroup {
+            id,
+            orders: Arc::new(profits.iter().copied().map(order).collect()),
+            conflicting_group_ids: Arc::new(HashSet::default()),
+        };
+        // mev gas prices 10, 10 and 1, only the rich orders fit the block
+        let rich = group(1, &[210_000, 210_000]);
+        let poor = group(2, &[21_000]);
+        let mut selector = create_selector(None).with_block_gas_limit(42_000);
+        selector.update_marginal_gas_price([&rich, &poor]);
+        assert_eq!(selector.marginal_gas_price, U256::from(10));
+
+        let tasks = selector.tasks_for_group(&rich, TaskPriority::High);
+        assert!(tasks
+            .iter()
+            .any(|task| task.algorithm.strategy() == ResolutionStrategy::Exhaustive));
+        // can't beat the marginal gas value, just greedy
+        let tasks = selector.tasks_for_group(&poor, TaskPriority::High);
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].algorithm, Algorithm::Greedy);
+
+        // everything fits, no pruning
+        let mut selector = create_selector(None).with_block_gas_limit(1_000_000);
+        selector.update_marginal_gas_price([&rich, &poor]);
+        assert_eq!(selector.tasks_for_group(&poor, TaskPriority::High).len(), 2);
+    }
+}
@@ -10,6 +10,9 @@ pub struct ConflictTask {
     pub priority: TaskPriority,
     pub group: ConflictGroup,
     pub created_at: Instant,
+    /// Set when the whole group doesn't fit in the block: orders that would go over this much gas are skipped so we
+    /// look for the best subset (and ordering) that fits.
+    pub gas_budget: Option<u64>,
 }
 
 /// TaskPriority provides a priority for a [ConflictTask].
@@ -57,7 +60,7 @@ impl Ord for ConflictTask {
 
 /// Algorithm provides an algorithm for resolving a [ConflictGroup].
 /// Initially these are all algorithms that produce a sequence of orders to execute.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Algorithm {
     /// `Greedy` checks the following ordrerings: max profit, mev gas price
     Greedy,
@@ -69,6 +72,52 @@ pub enum Algorithm {
     AllPermutations,
     /// `Random` checks random permutations of the group.
     Random { seed: u64, count: usize },
+    /// `NonceSort` checks a single deterministic ordering: max profit with the orders of each signer in nonce order.
+    NonceSort,
+    /// `Recurrent` checks the group in its given order: the best ordering found for the same orders on a previous slot.
+    Recurrent,
+}
+
+impl Algorithm {
+    /// Returns the [ResolutionStrategy] this algorithm belongs to.
+    pub fn strategy(&self) -> ResolutionStrategy {
+        match self {
+            Algorithm::NonceSort => ResolutionStrategy::NonceSort,
+            Algorithm::Recurrent => ResolutionStrategy::Recurrent,
+            Algorithm::AllPermutations => ResolutionStrategy::Exhaustive,
+            Algorithm::Greedy
+            | Algorithm::ReverseGreedy
+            | Algorithm::Length
+            | Algorithm::Random { .. } => ResolutionStrategy::Heuristic,
+        }
+    }
+}
+
+/// ResolutionStrategy groups the [Algorithm]s that are scheduled together for a [ConflictGroup].
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum ResolutionStrategy {
+    /// Deterministic nonce sort, cheap enough to run when we are out of time.
+    NonceSort,
+    /// Try every permutation of the group.
+    Exhaustive,
+    /// Greedy, length based and random orderings.
+    Heuristic,
+    /// Just the greedy orderings, for groups not worth searching.
+    GreedyOnly,
+    /// Replay the ordering found on a previous slot for the same orders (and greedy in case it got worse).
+    Recurrent,
+}
+
+impl ResolutionStrategy {
+    pub fn display(&self) -> &str {
+        match self {
+            ResolutionStrategy::NonceSort => "NonceSort",
+            ResolutionStrategy::Exhaustive => "Exhaustive",
+            ResolutionStrategy::Heuristic => "Heuristic",
+            ResolutionStrategy::GreedyOnly => "GreedyOnly",
+            ResolutionStrategy::Recurrent => "Recurrent",
+        }
+    }
 }
 
 #[cfg(test)]
@@ -96,5 +145,26 @@ mod tests {
         assert_ne!(TaskPriority::Low, TaskPriority::High);
     }
 
+    #[test]
+    fn test_algorithm_strategy() {
+        assert_eq!(
+            Algorithm::NonceSort.strategy(),
+            ResolutionStrategy::NonceSort
+        );
+        assert_eq!(
+            Algorithm::AllPermutations.strategy(),
+            ResolutionStrategy::Exhaustive
+        );
+        assert_eq!(Algorithm::Greedy.strategy(), ResolutionStrategy::Heuristic);
+        assert_eq!(
+            Algorithm::Random { seed: 0, count: 1 }.strategy(),
+            ResolutionStrategy::Heuristic
+        );
+        assert_eq!(
+            Algorithm::Recurrent.strategy(),
+            ResolutionStrategy::Recurrent
+        );
+    }
+
     // to-do: test equal priority ordering by created_at
 }
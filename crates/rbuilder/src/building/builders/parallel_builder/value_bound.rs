@@ -0,0 +1,118 @@
+//! Cheap upper bounds on what resolving a [ConflictGroup] can achieve, used to avoid spending search effort on groups
+//! that can't compete for block space.
+//!
+//! Conflicts can only destroy value (orders failing or paying less after others) so the sum of the standalone
+//! profits of the orders of a group bounds the profit of any ordering of it. If that bound is below what the same gas
+//! is worth at the marginal gas price (mev gas price of the last order that still fits in the block) the group would
+//! be displaced by other orders anyway and greedy treatment is enough.
+
+use super::ConflictGroup;
+use alloy_primitives::U256;
+use itertools::Itertools;
+
+/// Sum of the standalone profits of the orders.
+pub fn group_profit_upper_bound(group: &ConflictGroup) -> U256 {
+    group
+        .orders
+        .iter()
+        .map(|order| order.sim_value.coinbase_profit)
+        .sum()
+}
+
+/// Sum of the standalone gas of the orders.
+pub fn group_gas_upper_bound(group: &ConflictGroup) -> u64 {
+    group
+        .orders
+        .iter()
+        .map(|order| order.sim_value.gas_used)
+        .sum()
+}
+
+/// Mev gas price of the order (best mev gas price first) that fills block_gas_limit.
+/// Zero if all the orders fit, any gas is then worth using.
+pub fn marginal_gas_price<'a>(
+    groups: impl IntoIterator<Item = &'a ConflictGroup>,
+    block_gas_limit: u64,
+) -> U256 {
+    let mut gas_used = 0u64;
+    for sim_value in groups
+        .into_iter()
+        .flat_map(|group| group.orders.iter())
+        .map(|order| &order.sim_value)
+        .sorted_by(|a, b| b.mev_gas_price.cmp(&a.mev_gas_price))
+    {
+        gas_used = gas_used.saturating_add(sim_value.gas_used);
+        if gas_used >= block_gas_limit {
+            return sim_value.mev_gas_price;
+        }
+    }
+    U256::ZERO
+}
+
+/// True if the group can't beat the value of its gas at marginal_gas_price.
+pub fn is_below_marginal_value(group: &ConflictGroup, marginal_gas_price: U256) -> bool {
+    if marginal_gas_price.is_zero() {
+        return false;
+    }
+    group_profit_upper_bound(group)
+        < marginal_gas_price.saturating_mul(U256::from(group_gas_upper_bound(group)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{
+        MempoolTx, Order, SimValue, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs,
+    };
+    use ahash::HashSet;
+    use alloy_consensus::TxLegacy;
+    use alloy_primitives::Address;
+    use reth::primitives::{Transaction, TransactionSigned, TransactionSignedEcRecovered};
+    use std::sync::Arc;
+
+    fn order(profit: u64, gas_used: u64) -> SimulatedOrder {
+        let tx = TransactionSignedEcRecovered::from_signed_transaction(
+            TransactionSigned {
+                transaction: Transaction::Legacy(TxLegacy::default()),
+                ..Default::default()
+            },
+            Address::default(),
+        );
+        SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            )),
+            sim_value: SimValue::new(U256::from(profit), gas_used, 0, Vec::new()),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        }
+    }
+
+    fn group(id: usize, orders: Vec<SimulatedOrder>) -> ConflictGroup {
+        ConflictGroup {
+            id,
+            orders: Arc::new(orders),
+            conflicting_group_ids: Arc::new(HashSet::default()),
+        }
+    }
+
+    #[test]
+    fn test_group_value_bound() {
+        // mev gas prices 10, 5 and 1
+        let rich = group(0, vec![order(1_000, 100), order(500, 100)]);
+        let poor = group(1, vec![order(100, 100)]);
+        assert_eq!(group_profit_upper_bound(&rich), U256::from(1_500));
+        assert_eq!(group_gas_upper_bound(&rich), 200);
+
+        // everything fits
+        assert_eq!(marginal_gas_price([&rich, &poor], 1_000), U256::ZERO);
+        assert!(!is_below_marginal_value(&poor, U256::ZERO));
+
+        // only the first 2 orders fit
+        let price = marginal_gas_price([&rich, &poor], 200);
+        assert_eq!(price, U256::from(5));
+        assert!(!is_below_marginal_value(&rich, price));
+        assert!(is_below_marginal_value(&poor, price));
+    }
+}
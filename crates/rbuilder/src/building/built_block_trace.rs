@@ -1,7 +1,9 @@
-use super::{BundleErr, ExecutionError, ExecutionResult, OrderErr};
+use super::{BundleErr, ExecutionError, ExecutionResult, OrderErr, TransactionErr};
 use crate::primitives::{Order, OrderId, OrderReplacementKey};
 use ahash::{HashMap, HashSet};
 use alloy_primitives::{Address, TxHash, U256};
+use revm::primitives::InvalidTransaction;
+use serde::Serialize;
 use std::{collections::hash_map, time::Duration};
 use time::OffsetDateTime;
 
@@ -22,6 +24,80 @@ pub struct BuiltBlockTrace {
     pub fill_time: Duration,
     pub finalize_time: Duration,
     pub root_hash_time: Duration,
+    /// Orders we tried to commit but failed, in the order they were tried.
+    /// The same order may appear several times (retries) and may even be included later.
+    pub excluded_orders: Vec<(OrderId, ExclusionReason)>,
+}
+
+/// Why an order we tried to commit didn't make it into the block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "camelCase")]
+pub enum ExclusionReason {
+    /// The order doesn't work on top of the previously executed orders (nonces, reverts, lower value).
+    Conflict,
+    /// Negative profit or not enough refundable value.
+    Unprofitable,
+    /// Not enough gas or blob gas left in the block.
+    Gas,
+    /// The order was filtered by a builder rule.
+    #[serde(rename_all = "camelCase")]
+    FilterRule { rule_id: String },
+    /// Any other failure.
+    Invalid { error: String },
+}
+
+impl ExclusionReason {
+    pub fn from_execution_error(err: &ExecutionError) -> Self {
+        match err {
+            ExecutionError::LowerInsertedValue { .. } => ExclusionReason::Conflict,
+            ExecutionError::ExposureBudgetExhausted { .. } => ExclusionReason::FilterRule {
+                rule_id: "exposure_budget".to_string(),
+            },
+            ExecutionError::RejectedByRelay { .. } => ExclusionReason::FilterRule {
+                rule_id: "relay_rejection".to_string(),
+            },
+            ExecutionError::OrderError(OrderErr::NegativeProfit(_)) => {
+                ExclusionReason::Unprofitable
+            }
+            ExecutionError::OrderError(OrderErr::Transaction(err)) => {
+                Self::from_transaction_error(err)
+            }
+            ExecutionError::OrderError(OrderErr::Bundle(err)) => match err {
+                BundleErr::InvalidTransaction(_, err) => Self::from_transaction_error(err),
+                BundleErr::TransactionReverted(_) => ExclusionReason::Conflict,
+                BundleErr::NotEnoughRefundForGas { .. } => ExclusionReason::Unprofitable,
+                BundleErr::TargetBlockIncorrect { .. } => ExclusionReason::FilterRule {
+                    rule_id: "target_block".to_string(),
+                },
+                BundleErr::IncorrectTimestamp { .. } => ExclusionReason::FilterRule {
+                    rule_id: "timestamp".to_string(),
+                },
+                BundleErr::VictimTxWorsened(_) => ExclusionReason::FilterRule {
+                    rule_id: "victim_protection".to_string(),
+                },
+                _ => ExclusionReason::Invalid {
+                    error: err.to_string(),
+                },
+            },
+        }
+    }
+
+    fn from_transaction_error(err: &TransactionErr) -> Self {
+        match err {
+            TransactionErr::GasLeft | TransactionErr::BlobGasLeft => ExclusionReason::Gas,
+            TransactionErr::Blocklist => ExclusionReason::FilterRule {
+                rule_id: "blocklist".to_string(),
+            },
+            TransactionErr::InvalidTransaction(
+                InvalidTransaction::NonceTooLow { .. } | InvalidTransaction::NonceTooHigh { .. },
+            ) => ExclusionReason::Conflict,
+            TransactionErr::InvalidTransaction(_) | TransactionErr::PastMaxBlock { .. } => {
+                ExclusionReason::Invalid {
+                    error: err.to_string(),
+                }
+            }
+        }
+    }
 }
 
 impl Default for BuiltBlockTrace {
@@ -42,6 +118,22 @@ pub enum BuiltBlockTraceError {
         "Bundle tx reverted that is not revertable, order: {order_id:?}, tx_hash: {tx_hash:?}"
     )]
     BundleTxReverted { order_id: OrderId, tx_hash: TxHash },
+    #[error("Bundle txs executed out of order, order: {order_id:?}, tx_hash: {tx_hash:?}")]
+    BundleTxOutOfOrder { order_id: OrderId, tx_hash: TxHash },
+    #[error(
+        "Tx from order {other_order_id:?} executed inside order {order_id:?}, tx_hash: {tx_hash:?}"
+    )]
+    BundleInterleaved {
+        order_id: OrderId,
+        other_order_id: OrderId,
+        tx_hash: TxHash,
+    },
+    #[error("Block tx {index} is {found:?} but the included orders executed {expected:?}")]
+    BlockTxMismatch {
+        index: usize,
+        expected: TxHash,
+        found: Option<TxHash>,
+    },
 }
 
 impl BuiltBlockTrace {
@@ -56,6 +148,7 @@ impl BuiltBlockTrace {
             fill_time: Duration::from_secs(0),
             finalize_time: Duration::from_secs(0),
             root_hash_time: Duration::from_secs(0),
+            excluded_orders: Vec::new(),
         }
     }
 
@@ -72,6 +165,40 @@ impl BuiltBlockTrace {
         self.included_orders.push(execution_result);
     }
 
+    /// Call after a commit_order error
+    pub fn add_excluded_order(&mut self, order_id: OrderId, err: &ExecutionError) {
+        self.excluded_orders
+            .push((order_id, ExclusionReason::from_execution_error(err)));
+    }
+
+    /// Last exclusion reason of every order that was tried but is not included in the block.
+    pub fn final_exclusions(&self) -> Vec<(OrderId, ExclusionReason)> {
+        let mut included: HashSet<OrderId> = HashSet::default();
+        for res in &self.included_orders {
+            included.insert(res.order.id());
+            included.extend(
+                res.order
+                    .original_orders()
+                    .into_iter()
+                    .map(|order| order.id()),
+            );
+        }
+        let mut last_reason: HashMap<OrderId, &ExclusionReason> = HashMap::default();
+        for (order_id, reason) in &self.excluded_orders {
+            last_reason.insert(*order_id, reason);
+        }
+        let mut res = Vec::new();
+        for (order_id, _) in &self.excluded_orders {
+            if included.contains(order_id) {
+                continue;
+            }
+            if let Some(reason) = last_reason.remove(order_id) {
+                res.push((*order_id, reason.clone()));
+            }
+        }
+        res
+    }
+
     /// Call after a commit_order error
     pub fn modify_payment_when_no_signer_error(&mut self, err: &ExecutionError) {
         if let ExecutionError::OrderError(OrderErr::Bundle(BundleErr::NoSigner)) = err {
@@ -94,6 +221,8 @@ impl BuiltBlockTrace {
         &self,
         blocklist: &HashSet<Address>,
     ) -> Result<(), BuiltBlockTraceError> {
+        self.verify_bundle_atomicity()?;
+
         let mut replacement_data_count: HashSet<_> = HashSet::default();
         let mut bundle_txs_scratchpad = HashMap::default();
         let mut executed_tx_hashes_scratchpad = Vec::new();
@@ -160,4 +289,66 @@ impl BuiltBlockTrace {
 
         Ok(())
     }
+
+    /// Checks that each included order only executed its own txs (plus the ones we add like refunds) and in the same
+    /// order as they appear in the order. Some txs may be missing (eg: TxRevertBehavior::AllowedExcluded, merged sbundles).
+    pub fn verify_bundle_atomicity(&self) -> Result<(), BuiltBlockTraceError> {
+        let mut tx_order: HashMap<TxHash, OrderId> = HashMap::default();
+        for res in &self.included_orders {
+            for (tx, _) in res.order.list_txs() {
+                tx_order.entry(tx.hash()).or_insert_with(|| res.order.id());
+            }
+        }
+
+        for res in &self.included_orders {
+            let order_id = res.order.id();
+            // merged sbundles may repeat txs.
+            let order_txs: Vec<_> = res
+                .order
+                .list_txs()
+                .into_iter()
+                .map(|(tx, _)| tx.hash())
+                .collect();
+            let mut next = 0;
+            for tx in &res.txs {
+                let tx_hash = tx.hash();
+                if let Some(pos) = order_txs[next..].iter().position(|hash| *hash == tx_hash) {
+                    next += pos + 1;
+                } else if order_txs[..next].contains(&tx_hash) {
+                    return Err(BuiltBlockTraceError::BundleTxOutOfOrder { order_id, tx_hash });
+                } else if let Some(other_order_id) = tx_order.get(&tx_hash) {
+                    return Err(BuiltBlockTraceError::BundleInterleaved {
+                        order_id,
+                        other_order_id: *other_order_id,
+                        tx_hash,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the final block starts with the txs executed by the included orders in the same order.
+    /// Txs after them (eg: payout tx) are not checked.
+    pub fn verify_block_txs(
+        &self,
+        block_txs: impl IntoIterator<Item = TxHash>,
+    ) -> Result<(), BuiltBlockTraceError> {
+        let mut block_txs = block_txs.into_iter();
+        let executed_txs = self
+            .included_orders
+            .iter()
+            .flat_map(|res| res.txs.iter().map(|tx| tx.hash()));
+        for (index, expected) in executed_txs.enumerate() {
+            let found = block_txs.next();
+            if found != Some(expected) {
+                return Err(BuiltBlockTraceError::BlockTxMismatch {
+                    index,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
 }
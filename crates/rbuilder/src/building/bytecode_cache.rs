@@ -0,0 +1,157 @@
+//! Process wide LRU of contract bytecode.
+//! [`reth::revm::cached::CachedReads`] only lives for a slot (and a pipeline) so the code of popular contracts
+//! (routers, pools, tokens) is read from the DB again on every slot. Bytecode is immutable (keyed by code hash) so it
+//! can be shared by every slot, builder and simulation without invalidation.
+
+use crate::telemetry::inc_bytecode_cache_access;
+use alloy_primitives::{Address, B256, U256};
+use lazy_static::lazy_static;
+use lru::LruCache;
+use parking_lot::Mutex;
+use revm::{
+    primitives::{AccountInfo, Bytecode},
+    DatabaseRef,
+};
+use std::{num::NonZeroUsize, sync::Arc};
+
+/// code hash -> bytecode, bounded by number of entries.
+#[derive(Debug)]
+pub struct BytecodeCache {
+    cache: Mutex<LruCache<B256, Bytecode>>,
+}
+
+impl BytecodeCache {
+    pub fn new(max_entries: NonZeroUsize) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(max_entries)),
+        }
+    }
+
+    pub fn get(&self, code_hash: &B256) -> Option<Bytecode> {
+        let res = self.cache.lock().get(code_hash).cloned();
+        inc_bytecode_cache_access(res.is_some());
+        res
+    }
+
+    pub fn insert(&self, code_hash: B256, bytecode: Bytecode) {
+        self.cache.lock().put(code_hash, bytecode);
+    }
+}
+
+lazy_static! {
+    static ref BYTECODE_CACHE: Mutex<Option<Arc<BytecodeCache>>> = Mutex::new(None);
+}
+
+/// max_entries == 0 disables the cache.
+pub fn init_bytecode_cache(max_entries: usize) {
+    *BYTECODE_CACHE.lock() =
+        NonZeroUsize::new(max_entries).map(|max_entries| Arc::new(BytecodeCache::new(max_entries)));
+}
+
+/// None if the cache was not initialized.
+pub fn global_bytecode_cache() -> Option<Arc<BytecodeCache>> {
+    BYTECODE_CACHE.lock().clone()
+}
+
+/// [`DatabaseRef`] wrapper that serves code_by_hash_ref from a [`BytecodeCache`] (if any).
+#[derive(Debug, Clone)]
+pub struct BytecodeCachedDatabaseRef<DB> {
+    inner: DB,
+    cache: Option<Arc<BytecodeCache>>,
+}
+
+impl<DB> BytecodeCachedDatabaseRef<DB> {
+    pub fn new(inner: DB, cache: Option<Arc<BytecodeCache>>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl<DB: DatabaseRef> DatabaseRef for BytecodeCachedDatabaseRef<DB> {
+    type Error = DB::Error;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.inner.basic_ref(address)
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.inner.code_by_hash_ref(code_hash),
+        };
+        if let Some(bytecode) = cache.get(&code_hash) {
+            return Ok(bytecode);
+        }
+        let bytecode = self.inner.code_by_hash_ref(code_hash)?;
+        cache.insert(code_hash, bytecode.clone());
+        Ok(bytecode)
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.inner.storage_ref(address, index)
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.inner.block_hash_ref(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Bytes;
+    use revm::db::{CacheDB, EmptyDB};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the code reads that reach the DB.
+    #[derive(Debug, Default)]
+    struct CountingDB {
+        db: CacheDB<EmptyDB>,
+        code_reads: AtomicUsize,
+    }
+
+    impl DatabaseRef for CountingDB {
+        type Error = <CacheDB<EmptyDB> as DatabaseRef>::Error;
+
+        fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+            self.db.basic_ref(address)
+        }
+
+        fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+            self.code_reads.fetch_add(1, Ordering::Relaxed);
+            self.db.code_by_hash_ref(code_hash)
+        }
+
+        fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+            self.db.storage_ref(address, index)
+        }
+
+        fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+            self.db.block_hash_ref(number)
+        }
+    }
+
+    #[test]
+    fn test_bytecode_cache() {
+        let cache = Arc::new(BytecodeCache::new(NonZeroUsize::new(1).unwrap()));
+        let mut inner = CountingDB::default();
+        let code = Bytecode::new_raw(Bytes::from_static(&[0x60, 0x00]));
+        let code_hash = code.hash_slow();
+        inner.db.contracts.insert(code_hash, code.clone());
+        let db = BytecodeCachedDatabaseRef::new(inner, Some(cache.clone()));
+
+        assert_eq!(db.code_by_hash_ref(code_hash).unwrap(), code);
+        assert_eq!(db.code_by_hash_ref(code_hash).unwrap(), code);
+        assert_eq!(db.inner.code_reads.load(Ordering::Relaxed), 1);
+
+        // shared across dbs (slots)
+        let other_db = BytecodeCachedDatabaseRef::new(CountingDB::default(), Some(cache.clone()));
+        assert_eq!(other_db.code_by_hash_ref(code_hash).unwrap(), code);
+        assert_eq!(other_db.inner.code_reads.load(Ordering::Relaxed), 0);
+
+        // evicted by a newer entry
+        let other_code = Bytecode::new_raw(Bytes::from_static(&[0x60, 0x01]));
+        cache.insert(other_code.hash_slow(), other_code);
+        db.code_by_hash_ref(code_hash).unwrap();
+        assert_eq!(db.inner.code_reads.load(Ordering::Relaxed), 2);
+    }
+}
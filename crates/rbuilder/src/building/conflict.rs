@@ -1,14 +1,26 @@
-use super::{BlockBuildingContext, BlockState, PartialBlockFork};
-use crate::primitives::{Order, OrderId};
+use super::{
+    conflict_cache::ConflictCache,
+    evm_inspector::{SlotKey, UsedStateTrace},
+    order_index::OrderIndex,
+    standalone_profits::standalone_profit,
+    tracers::AccumulatorSimulationTracer,
+    BlockBuildingContext, BlockState, BundleErr, OrderErr, PartialBlockFork, TransactionErr,
+};
+use crate::{
+    primitives::{Order, OrderId},
+    telemetry::add_conflict_detection,
+};
 use alloy_primitives::Address;
-use itertools::Itertools;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use reth::providers::StateProviderBox;
 use reth_provider::StateProvider;
 use revm_primitives::U256;
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
+    time::Instant,
 };
+use tracing::trace;
 
 /// Conflict generated by executing an order before another.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -19,142 +31,1236 @@ pub enum Conflict {
     /// First order caused second one to fail.
     Fatal,
     /// Second order executed ok but with different profit.
+    /// Also used (with profit_with_conflict 0) when every tx of the second order failed but all of them were allowed to
+    /// (reverting_tx_hashes): the bundle is skipped, it's not broken.
     DifferentProfit {
         profit_alone: U256,
         profit_with_conflict: U256,
     },
+    /// Only from [find_conflict_fast]: first order writes state read by the second one, the effect is unknown.
+    AccessOverlap,
+    /// Both orders work alone but together they use more blob gas than the block allows so the second one can't be
+    /// included after the first one.
+    BlobGasLimit,
+    /// Both orders work alone but together they need more gas than the block gas limit.
+    GasLimit,
+}
+
+impl Conflict {
+    /// Short name for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Conflict::NoConflict => "no_conflict",
+            Conflict::Nonce(_) => "nonce",
+            Conflict::Fatal => "fatal",
+            Conflict::DifferentProfit { .. } => "different_profit",
+            Conflict::AccessOverlap => "access_overlap",
+            Conflict::BlobGasLimit => "blob_gas_limit",
+            Conflict::GasLimit => "gas_limit",
+        }
+    }
+
+    /// Conflicts that only exist because the block is full (gas or blob gas), executing the orders in another order
+    /// doesn't help, only leaving one out (knapsack rather than ordering problem).
+    pub fn is_capacity(&self) -> bool {
+        matches!(self, Conflict::BlobGasLimit | Conflict::GasLimit)
+    }
+
+    /// Conflicts where the first order changes the execution of the second one.
+    pub fn is_semantic(&self) -> bool {
+        !matches!(self, Conflict::NoConflict) && !self.is_capacity()
+    }
+}
+
+/// Every [Conflict::kind], kinds missing from a detection are reported with 0 pairs.
+const CONFLICT_KINDS: [&str; 7] = [
+    "no_conflict",
+    "nonce",
+    "fatal",
+    "different_profit",
+    "access_overlap",
+    "blob_gas_limit",
+    "gas_limit",
+];
+
+/// Reports the pairs by kind, the conflict set sizes and the time since started of a detection to the
+/// conflict metrics.
+fn record_conflict_detection(
+    detector: &str,
+    conflicts: &HashMap<(OrderId, OrderId), Conflict>,
+    started: Instant,
+) {
+    let duration = started.elapsed();
+    let mut pairs_by_kind = CONFLICT_KINDS.map(|kind| (kind, 0));
+    for conflict in conflicts.values() {
+        if let Some((_, pairs)) = pairs_by_kind
+            .iter_mut()
+            .find(|(kind, _)| *kind == conflict.kind())
+        {
+            *pairs += 1;
+        }
+    }
+    let set_sizes = get_conflict_sets(conflicts)
+        .iter()
+        .map(|set| set.len())
+        .collect::<Vec<_>>();
+    add_conflict_detection(detector, &pairs_by_kind, &set_sizes, duration);
 }
 
+/// Executes every ordered pair of orders (that work alone) on top of state_provider.
+/// Single threaded, see [ParallelConflictFinder] for big order sets.
+/// If deadline passes we stop and return the pairs analyzed so far. Pairs go from the ones involving the most
+/// profitable orders (alone) to the least (see [pairs_by_profit]) so the partial result covers the orders that matter.
+/// Profits alone are taken from (and added to) ctx.standalone_profits.
 pub fn find_conflict_slow(
     state_provider: StateProviderBox,
     ctx: &BlockBuildingContext,
     orders: &[Order],
+    deadline: Option<Instant>,
 ) -> eyre::Result<HashMap<(OrderId, OrderId), Conflict>> {
-    let mut state_provider = Arc::<dyn StateProvider>::from(state_provider);
-    let profits_alone = {
-        let mut profits_alone = HashMap::with_capacity(orders.len());
-        for order in orders {
-            let mut state = BlockState::new_arc(state_provider);
-            let mut fork = PartialBlockFork::new(&mut state);
-            if let Ok(res) = fork.commit_order(order, ctx, 0, 0, 0, true)? {
-                profits_alone.insert(order.id(), res.coinbase_profit);
-            };
-            state_provider = state.into_provider();
+    let started = Instant::now();
+    let deadline_passed = || deadline.map_or(false, |deadline| Instant::now() >= deadline);
+    let state_provider = Arc::<dyn StateProvider>::from(state_provider);
+    let mut profits_alone = vec![None; orders.len()];
+    for (profit_alone, order) in profits_alone.iter_mut().zip(orders) {
+        if deadline_passed() {
+            break;
         }
-        profits_alone
-    };
+        *profit_alone = standalone_profit(&state_provider, ctx, order)?;
+    }
 
     let mut results = HashMap::new();
+    let working_len = profits_alone.iter().filter(|p| p.is_some()).count();
+    let pairs_len = working_len * working_len.saturating_sub(1);
+    for (idx, (order1, order2)) in pairs_by_profit(&profits_alone).enumerate() {
+        if deadline_passed() {
+            trace!(
+                analyzed_pairs = idx,
+                pairs = pairs_len,
+                "find_conflict_slow deadline reached"
+            );
+            break;
+        }
+        let profit_alone = profits_alone[order2].unwrap_or_default();
+        let (order1, order2) = (&orders[order1], &orders[order2]);
+        if let Some(conflict) =
+            find_pair_conflict(&state_provider, ctx, order1, order2, profit_alone)?
+        {
+            results.insert((order1.id(), order2.id()), conflict);
+        }
+    }
+    record_conflict_detection("slow", &results, started);
+    Ok(results)
+}
+
+/// Ordered pairs (as indexes on profits_alone, None for the orders failing alone) of the orders working alone: all the
+/// pairs of the most profitable order first, then the remaining pairs of the second one and so on.
+fn pairs_by_profit(profits_alone: &[Option<U256>]) -> impl Iterator<Item = (usize, usize)> {
+    let mut by_profit = profits_alone
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, profit)| profit.map(|profit| (idx, profit)))
+        .collect::<Vec<_>>();
+    by_profit.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let by_profit = by_profit
+        .into_iter()
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    let len = by_profit.len();
+    // pairs are generated as they are consumed, never the n^2 of them at once
+    (0..len)
+        .flat_map(move |i| (i + 1..len).map(move |j| (i, j)))
+        .flat_map(move |(i, j)| {
+            let (order1, order2) = (by_profit[i], by_profit[j]);
+            [(order1, order2), (order2, order1)]
+        })
+}
+
+/// Same result as [find_conflict_slow] but the orders and then the pairs are executed on a dedicated thread pool.
+/// The pool is built once, reuse the finder for every detection.
+#[derive(Debug)]
+pub struct ParallelConflictFinder {
+    thread_pool: ThreadPool,
+}
 
-    for (order1, order2) in orders.iter().cartesian_product(orders.iter()) {
-        if !profits_alone.contains_key(&order1.id()) || !profits_alone.contains_key(&order2.id()) {
-            continue;
+impl ParallelConflictFinder {
+    /// num_threads: 0 means one per core.
+    pub fn new(num_threads: usize) -> eyre::Result<Self> {
+        Ok(Self {
+            thread_pool: ThreadPoolBuilder::new().num_threads(num_threads).build()?,
+        })
+    }
+
+    pub fn find_conflicts(
+        &self,
+        state_provider: StateProviderBox,
+        ctx: &BlockBuildingContext,
+        orders: &[Order],
+    ) -> eyre::Result<HashMap<(OrderId, OrderId), Conflict>> {
+        let started = Instant::now();
+        let state_provider = Arc::<dyn StateProvider>::from(state_provider);
+        let conflicts = self.thread_pool.install(|| {
+            let profits_alone = orders
+                .par_iter()
+                .map(|order| standalone_profit(&state_provider, ctx, order))
+                .collect::<eyre::Result<Vec<_>>>()?;
+
+            let working = (0..orders.len())
+                .filter(|idx| profits_alone[*idx].is_some())
+                .collect::<Vec<_>>();
+            // pairs are generated as they are consumed, never the n^2 of them at once
+            working
+                .par_iter()
+                .flat_map_iter(|order1| working.iter().map(move |order2| (*order1, *order2)))
+                .map(|(order1, order2)| {
+                    let profit_alone = profits_alone[order2].unwrap_or_default();
+                    let (order1, order2) = (&orders[order1], &orders[order2]);
+                    Ok(
+                        find_pair_conflict(&state_provider, ctx, order1, order2, profit_alone)?
+                            .map(|conflict| ((order1.id(), order2.id()), conflict)),
+                    )
+                })
+                .filter_map(|res: eyre::Result<_>| res.transpose())
+                .collect::<eyre::Result<HashMap<_, _>>>()
+        })?;
+        record_conflict_detection("slow_parallel", &conflicts, started);
+        Ok(conflicts)
+    }
+}
+
+/// Conflict of executing order1 before order2, None if it's the same order. Both orders must work alone,
+/// profit_alone is the one of order2.
+/// Results are shared via ctx.conflict_cache so state_provider must be the state of ctx.attributes.parent.
+fn find_pair_conflict(
+    state_provider: &Arc<dyn StateProvider>,
+    ctx: &BlockBuildingContext,
+    order1: &Order,
+    order2: &Order,
+    profit_alone: U256,
+) -> eyre::Result<Option<Conflict>> {
+    if order1.id() == order2.id() {
+        return Ok(None);
+    }
+
+    if let Some(address) = nonce_conflict(&nonce_sequences(order1), &nonce_sequences(order2)) {
+        return Ok(Some(Conflict::Nonce(address)));
+    }
+
+    let cache = &ctx.conflict_cache;
+    let context_key = ConflictCache::context_key(ctx);
+    if let Some(conflict) = cache.get(order1.id(), order2.id(), context_key) {
+        return Ok(Some(conflict));
+    }
+
+    let mut state = BlockState::new_arc(state_provider.clone());
+    let mut fork = PartialBlockFork::new(&mut state);
+    let mut gas_used = 0;
+    let mut blob_gas_used = 0;
+    if let Ok(res) = fork.commit_order(order1, ctx, gas_used, 0, blob_gas_used, true)? {
+        gas_used += res.gas_used;
+        blob_gas_used += res.blob_gas_used;
+    }
+    // allow_tx_skip: failing txs that order2 allows to revert are skipped so they only change its profit.
+    let conflict = match fork.commit_order(order2, ctx, gas_used, 0, blob_gas_used, true)? {
+        Ok(re) => profit_conflict(profit_alone, re.coinbase_profit),
+        Err(err) if is_blob_gas_limit(&err) => Conflict::BlobGasLimit,
+        Err(err) if is_gas_limit(&err) => Conflict::GasLimit,
+        Err(err) if only_revertible_txs_failed(order2, &err) => {
+            profit_conflict(profit_alone, U256::ZERO)
         }
+        Err(_) => Conflict::Fatal,
+    };
+    cache.insert(order1.id(), order2.id(), context_key, conflict.clone());
+    Ok(Some(conflict))
+}
 
-        if order1.id() == order2.id() {
-            continue;
+fn profit_conflict(profit_alone: U256, profit_with_conflict: U256) -> Conflict {
+    if profit_alone == profit_with_conflict {
+        Conflict::NoConflict
+    } else {
+        Conflict::DifferentProfit {
+            profit_alone,
+            profit_with_conflict,
         }
+    }
+}
 
-        let pair = (order1.id(), order2.id());
-        let mut nonce_map = HashMap::new();
-        order1.nonces().into_iter().for_each(|nonce| {
-            nonce_map.insert(nonce.address, nonce);
-        });
-        if let Some(nonce) = order2.nonces().into_iter().find(|nonce| {
-            if let Some(nonce_map) = nonce_map.get(&nonce.address) {
-                let optional = nonce.optional || nonce_map.optional;
-                !optional && nonce.address == nonce_map.address
-            } else {
-                false
-            }
-        }) {
-            results.insert(pair, Conflict::Nonce(nonce.address));
-            continue;
-        }
-
-        let mut state = BlockState::new_arc(state_provider);
-        let mut fork = PartialBlockFork::new(&mut state);
-        let mut gas_used = 0;
-        let mut blob_gas_used = 0;
-        match fork.commit_order(order1, ctx, gas_used, 0, blob_gas_used, true)? {
-            Ok(res) => {
-                gas_used += res.gas_used;
-                blob_gas_used += res.blob_gas_used;
-            }
-            Err(_) => {
-                results.insert(pair, Conflict::Fatal);
-            }
+/// Every tx of the bundle was skipped, only possible if all of them are in reverting_tx_hashes.
+fn only_revertible_txs_failed(order: &Order, err: &OrderErr) -> bool {
+    matches!(err, OrderErr::Bundle(BundleErr::EmptyBundle))
+        && order.list_txs().iter().all(|(_, can_revert)| *can_revert)
+}
+
+fn is_blob_gas_limit(err: &OrderErr) -> bool {
+    matches!(
+        err,
+        OrderErr::Transaction(TransactionErr::BlobGasLeft)
+            | OrderErr::Bundle(BundleErr::InvalidTransaction(
+                _,
+                TransactionErr::BlobGasLeft
+            ))
+    )
+}
+
+fn is_gas_limit(err: &OrderErr) -> bool {
+    matches!(
+        err,
+        OrderErr::Transaction(TransactionErr::GasLeft)
+            | OrderErr::Bundle(BundleErr::InvalidTransaction(_, TransactionErr::GasLeft))
+    )
+}
+
+/// Incremental [find_conflict_slow] + [get_conflict_sets]: orders are added as they get simulated during the slot and
+/// only the pairs with the new order are executed.
+pub struct IncrementalConflictFinder {
+    state_provider: Arc<dyn StateProvider>,
+    ctx: BlockBuildingContext,
+    /// Orders that work alone, index on added is the position.
+    orders: Vec<Order>,
+    added: OrderIndex,
+    /// Of orders (same position).
+    profits_alone: Vec<U256>,
+    conflicts: HashMap<(OrderId, OrderId), Conflict>,
+    conflict_sets: OrderDisjointSets,
+}
+
+impl IncrementalConflictFinder {
+    pub fn new(state_provider: StateProviderBox, ctx: BlockBuildingContext) -> Self {
+        Self {
+            state_provider: Arc::from(state_provider),
+            ctx,
+            orders: Vec::new(),
+            added: OrderIndex::default(),
+            profits_alone: Vec::new(),
+            conflicts: HashMap::new(),
+            conflict_sets: OrderDisjointSets::default(),
+        }
+    }
+
+    /// false if the order was already added or fails alone (it's ignored like in [find_conflict_slow]).
+    pub fn add_order(&mut self, order: Order) -> eyre::Result<bool> {
+        if self.added.get(&order.id()).is_some() {
+            return Ok(false);
+        }
+        let Some(profit) = standalone_profit(&self.state_provider, &self.ctx, &order)? else {
+            return Ok(false);
         };
-        match fork.commit_order(order2, ctx, gas_used, 0, blob_gas_used, true)? {
-            Ok(re) => {
-                let profit_alone = *profits_alone.get(&order2.id()).unwrap();
-                let profit_with_conflict = re.coinbase_profit;
-                let conflict = if profit_alone == profit_with_conflict {
-                    Conflict::NoConflict
-                } else {
-                    Conflict::DifferentProfit {
-                        profit_alone,
-                        profit_with_conflict,
-                    }
-                };
-                results.insert(pair, conflict);
+
+        let mut new_conflicts = Vec::with_capacity(self.orders.len() * 2);
+        for (other, other_profit) in self.orders.iter().zip(&self.profits_alone) {
+            for (order1, order2, profit_alone) in
+                [(&order, other, *other_profit), (other, &order, profit)]
+            {
+                if let Some(conflict) = find_pair_conflict(
+                    &self.state_provider,
+                    &self.ctx,
+                    order1,
+                    order2,
+                    profit_alone,
+                )? {
+                    new_conflicts.push(((order1.id(), order2.id()), conflict));
+                }
             }
-            Err(_) => {
-                results.insert(pair, Conflict::Fatal);
+        }
+        for ((id1, id2), conflict) in new_conflicts {
+            if !matches!(conflict, Conflict::NoConflict) {
+                self.conflict_sets.union(id1, id2);
             }
-        };
-        state_provider = state.into_provider();
+            self.conflicts.insert((id1, id2), conflict);
+        }
+        self.added.insert(order.id());
+        self.orders.push(order);
+        self.profits_alone.push(profit);
+        Ok(true)
     }
 
-    Ok(results)
+    pub fn conflicts(&self) -> &HashMap<(OrderId, OrderId), Conflict> {
+        &self.conflicts
+    }
+
+    /// Same as [get_conflict_sets] on [IncrementalConflictFinder::conflicts].
+    pub fn conflict_sets(&self) -> Vec<HashSet<OrderId>> {
+        self.conflict_sets.sets()
+    }
+}
+
+/// Nonces of one signer used by an order, in execution order.
+/// Bundles can chain several txs of the same signer and mix signers so we keep every nonce, not only the first one
+/// like [Order::nonces].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonceSequence {
+    pub address: Address,
+    /// (nonce, optional) for every tx of address.
+    pub nonces: Vec<(u64, bool)>,
+}
+
+impl NonceSequence {
+    /// Max nonce we know is used when the order lands, optional txs may be skipped.
+    fn last_mandatory(&self) -> Option<u64> {
+        self.nonces
+            .iter()
+            .filter(|(_, optional)| !optional)
+            .map(|(nonce, _)| *nonce)
+            .max()
+    }
+
+    fn first_mandatory(&self) -> Option<u64> {
+        self.nonces
+            .iter()
+            .filter(|(_, optional)| !optional)
+            .map(|(nonce, _)| *nonce)
+            .min()
+    }
+}
+
+/// Sorted by address.
+pub fn nonce_sequences(order: &Order) -> Vec<NonceSequence> {
+    let mut sequences: Vec<NonceSequence> = Vec::new();
+    for (tx, optional) in order.list_txs() {
+        let (address, nonce) = (tx.signer(), tx.nonce());
+        match sequences.iter_mut().find(|seq| seq.address == address) {
+            Some(seq) => seq.nonces.push((nonce, optional)),
+            None => sequences.push(NonceSequence {
+                address,
+                nonces: vec![(nonce, optional)],
+            }),
+        }
+    }
+    sequences.sort_by_key(|seq| seq.address);
+    sequences
+}
+
+/// Account for which executing the first order makes a mandatory tx of the second one fail: the first order
+/// (always) moves the nonce past a nonce the second one needs.
+/// If the second order only needs later nonces (eg: the next bundle of a searcher chaining its nonces) or the
+/// used nonces are optional there is no nonce conflict and the pair has to be executed.
+fn nonce_conflict(nonces1: &[NonceSequence], nonces2: &[NonceSequence]) -> Option<Address> {
+    nonces2
+        .iter()
+        .find(|seq2| {
+            nonces1
+                .iter()
+                .find(|seq1| seq1.address == seq2.address)
+                .and_then(|seq1| Some((seq1.last_mandatory()?, seq2.first_mandatory()?)))
+                .map_or(false, |(last1, first2)| first2 <= last1)
+        })
+        .map(|seq| seq.address)
+}
+
+/// State touched by an order executed alone (see [find_conflict_fast]).
+#[derive(Debug, Clone, Default)]
+pub struct OrderAccessSet {
+    nonces: Vec<NonceSequence>,
+    slot_reads: HashSet<SlotKey>,
+    slot_writes: HashSet<SlotKey>,
+    balance_reads: HashSet<Address>,
+    balance_writes: HashSet<Address>,
+    /// Contracts created or destructed.
+    code_writes: HashSet<Address>,
+    /// See [Order::max_blob_gas].
+    blob_gas: u64,
+    /// Gas used alone.
+    gas_used: u64,
+}
+
+impl OrderAccessSet {
+    pub fn new(
+        nonces: Vec<NonceSequence>,
+        used_state_trace: UsedStateTrace,
+        blob_gas: u64,
+        gas_used: u64,
+    ) -> Self {
+        Self {
+            nonces,
+            blob_gas,
+            gas_used,
+            slot_reads: used_state_trace.read_slot_values.into_keys().collect(),
+            slot_writes: used_state_trace.written_slot_values.into_keys().collect(),
+            balance_reads: used_state_trace.read_balances.into_keys().collect(),
+            balance_writes: used_state_trace
+                .sent_amount
+                .into_keys()
+                .chain(used_state_trace.received_amount.into_keys())
+                .collect(),
+            code_writes: used_state_trace
+                .created_contracts
+                .into_iter()
+                .chain(used_state_trace.destructed_contracts)
+                .collect(),
+        }
+    }
+
+    fn uses_contract(&self, address: &Address) -> bool {
+        self.code_writes.contains(address)
+            || self
+                .slot_reads
+                .iter()
+                .chain(self.slot_writes.iter())
+                .any(|slot| slot.address == *address)
+    }
+
+    /// true if executing self first can change the execution of other.
+    /// Pair loops use [IndexedAccessSets::affects] instead.
+    pub fn affects(&self, other: &Self) -> bool {
+        self.slot_writes
+            .iter()
+            .any(|slot| other.slot_reads.contains(slot))
+            || self
+                .balance_writes
+                .iter()
+                .any(|address| other.balance_reads.contains(address))
+            || self
+                .code_writes
+                .iter()
+                .any(|address| other.uses_contract(address))
+    }
+}
+
+/// [OrderAccessSet]s with the slots and addresses interned as dense u32 (sorted and deduplicated vectors) so the pair
+/// loop of [access_set_conflicts] intersects vectors instead of hashing every key of every pair.
+/// Indexes are the positions on the access sets it was built from.
+#[derive(Debug, Default)]
+pub struct IndexedAccessSets {
+    sets: Vec<IndexedAccessSet>,
+}
+
+#[derive(Debug, Default)]
+struct IndexedAccessSet {
+    slot_reads: Vec<u32>,
+    slot_writes: Vec<u32>,
+    balance_reads: Vec<u32>,
+    balance_writes: Vec<u32>,
+    code_writes: Vec<u32>,
+    /// code_writes and the addresses of slot_reads and slot_writes (see [OrderAccessSet::uses_contract]).
+    contracts_used: Vec<u32>,
+}
+
+impl IndexedAccessSets {
+    pub fn new<'a>(access_sets: impl IntoIterator<Item = &'a OrderAccessSet>) -> Self {
+        let mut slots: HashMap<&SlotKey, u32> = HashMap::new();
+        let mut addresses: HashMap<Address, u32> = HashMap::new();
+        let sets = access_sets
+            .into_iter()
+            .map(|set| IndexedAccessSet {
+                slot_reads: sorted_indexes(
+                    set.slot_reads.iter().map(|slot| intern(&mut slots, slot)),
+                ),
+                slot_writes: sorted_indexes(
+                    set.slot_writes.iter().map(|slot| intern(&mut slots, slot)),
+                ),
+                balance_reads: sorted_indexes(
+                    set.balance_reads
+                        .iter()
+                        .map(|address| intern(&mut addresses, *address)),
+                ),
+                balance_writes: sorted_indexes(
+                    set.balance_writes
+                        .iter()
+                        .map(|address| intern(&mut addresses, *address)),
+                ),
+                code_writes: sorted_indexes(
+                    set.code_writes
+                        .iter()
+                        .map(|address| intern(&mut addresses, *address)),
+                ),
+                contracts_used: sorted_indexes(
+                    set.code_writes
+                        .iter()
+                        .copied()
+                        .chain(
+                            set.slot_reads
+                                .iter()
+                                .chain(&set.slot_writes)
+                                .map(|slot| slot.address),
+                        )
+                        .map(|address| intern(&mut addresses, address)),
+                ),
+            })
+            .collect();
+        Self { sets }
+    }
+
+    /// [OrderAccessSet::affects] of the sets at index1 and index2.
+    pub fn affects(&self, index1: usize, index2: usize) -> bool {
+        let (set1, set2) = (&self.sets[index1], &self.sets[index2]);
+        intersects(&set1.slot_writes, &set2.slot_reads)
+            || intersects(&set1.balance_writes, &set2.balance_reads)
+            || intersects(&set1.code_writes, &set2.contracts_used)
+    }
+}
+
+fn intern<K: std::hash::Hash + Eq>(indexes: &mut HashMap<K, u32>, key: K) -> u32 {
+    let next_index = indexes.len() as u32;
+    *indexes.entry(key).or_insert(next_index)
+}
+
+fn sorted_indexes(indexes: impl Iterator<Item = u32>) -> Vec<u32> {
+    let mut indexes = indexes.collect::<Vec<_>>();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes
+}
+
+/// a and b sorted.
+fn intersects(a: &[u32], b: &[u32]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => return true,
+        }
+    }
+    false
+}
+
+/// Like [find_conflict_slow] but each order is executed only once (alone on top of state_provider) and pairs are
+/// checked by intersecting their read/write sets, cheap enough for the whole orderpool.
+/// It can give false positives ([Conflict::AccessOverlap] doesn't mean the second order changes) and misses
+/// conflicts that depend on execution paths not taken when the order runs alone.
+pub fn find_conflict_fast(
+    state_provider: StateProviderBox,
+    ctx: &BlockBuildingContext,
+    orders: &[Order],
+) -> eyre::Result<HashMap<(OrderId, OrderId), Conflict>> {
+    let started = Instant::now();
+    let state_provider = Arc::<dyn StateProvider>::from(state_provider);
+    let mut access_sets = Vec::with_capacity(orders.len());
+    for order in orders {
+        if let Some(access_set) = order_access_set(&state_provider, ctx, order)? {
+            access_sets.push((order.id(), access_set));
+        }
+    }
+    let conflicts = access_set_conflicts(
+        &access_sets,
+        ctx.block_env.gas_limit.to(),
+        ctx.max_blob_gas_per_block(),
+    );
+    record_conflict_detection("fast", &conflicts, started);
+    Ok(conflicts)
+}
+
+/// Access set of the order executed alone on top of state_provider, None if it fails.
+pub fn order_access_set(
+    state_provider: &Arc<dyn StateProvider>,
+    ctx: &BlockBuildingContext,
+    order: &Order,
+) -> eyre::Result<Option<OrderAccessSet>> {
+    let mut state = BlockState::new_arc(state_provider.clone());
+    let mut tracer = AccumulatorSimulationTracer::new();
+    let gas_used = PartialBlockFork::new(&mut state)
+        .with_tracer(&mut tracer)
+        .commit_order(order, ctx, 0, 0, 0, true)?
+        .ok()
+        .map(|res| res.gas_used);
+    Ok(gas_used.map(|gas_used| {
+        OrderAccessSet::new(
+            nonce_sequences(order),
+            tracer.used_state_trace,
+            order.max_blob_gas(),
+            gas_used,
+        )
+    }))
+}
+
+/// Capacity conflicts go before [Conflict::AccessOverlap]: if both orders don't fit in the block their ordering
+/// doesn't matter.
+/// [Conflict::GasLimit] is only reported when the gas used alone of both orders doesn't fit, execution with the tx
+/// gas limits can fail before that (see [find_conflict_slow]).
+pub fn access_set_conflicts(
+    access_sets: &[(OrderId, OrderAccessSet)],
+    block_gas_limit: u64,
+    max_blob_gas: u64,
+) -> HashMap<(OrderId, OrderId), Conflict> {
+    let indexed_sets = IndexedAccessSets::new(access_sets.iter().map(|(_, set)| set));
+    let mut results = HashMap::new();
+    for (index1, (id1, set1)) in access_sets.iter().enumerate() {
+        for (index2, (id2, set2)) in access_sets.iter().enumerate() {
+            if id1 == id2 {
+                continue;
+            }
+            let conflict = if let Some(address) = nonce_conflict(&set1.nonces, &set2.nonces) {
+                Conflict::Nonce(address)
+            } else if set1.blob_gas + set2.blob_gas > max_blob_gas {
+                Conflict::BlobGasLimit
+            } else if set1.gas_used + set2.gas_used > block_gas_limit {
+                Conflict::GasLimit
+            } else if indexed_sets.affects(index1, index2) {
+                Conflict::AccessOverlap
+            } else {
+                Conflict::NoConflict
+            };
+            results.insert((*id1, *id2), conflict);
+        }
+    }
+    results
 }
 
+/// Groups the orders with conflicts (anything but NoConflict) between them.
+/// Sets are sorted by size (max first) and then by their min OrderId so the result is the same on every run.
 pub fn get_conflict_sets(
     conflicts: &HashMap<(OrderId, OrderId), Conflict>,
 ) -> Vec<HashSet<OrderId>> {
-    let mut set_id = 0;
-    let mut conflict_sets = HashMap::<i32, HashSet<OrderId>>::new();
-    let mut order_to_conflict_set = HashMap::<OrderId, i32>::new();
-
-    for ((k1, k2), conflict) in conflicts {
-        if matches!(conflict, Conflict::NoConflict) {
-            continue;
-        }
-
-        let set1id = order_to_conflict_set.get(k1).copied();
-        let set2id = order_to_conflict_set.get(k2).copied();
-        match (conflict, set1id, set2id) {
-            (Conflict::NoConflict, _, _) => continue,
-            (_, Some(set1id), Some(set2id)) if set1id == set2id => continue,
-            (_, Some(set1id), Some(set2id)) => {
-                // mesge two conflic sets
-                let mut set1 = conflict_sets.remove(&set1id).unwrap();
-                let set2 = conflict_sets.remove(&set2id).unwrap();
-                for k in set2 {
-                    set1.insert(k);
-                    order_to_conflict_set.insert(k, set1id);
-                }
-                conflict_sets.insert(set1id, set1);
+    get_conflict_sets_with_mode(conflicts, ConflictSetMode::All)
+}
+
+/// Which conflicts group orders in [get_conflict_sets_with_mode].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictSetMode {
+    /// Anything but NoConflict.
+    #[default]
+    All,
+    /// Only [Conflict::is_semantic] ones, orders that just don't fit together (gas/blob gas) end up in different
+    /// sets and it's up to the packer to choose between them.
+    Semantic,
+}
+
+pub fn get_conflict_sets_with_mode(
+    conflicts: &HashMap<(OrderId, OrderId), Conflict>,
+    mode: ConflictSetMode,
+) -> Vec<HashSet<OrderId>> {
+    let mut conflict_sets = OrderDisjointSets::default();
+    for ((id1, id2), conflict) in conflicts {
+        let joins = match mode {
+            ConflictSetMode::All => !matches!(conflict, Conflict::NoConflict),
+            ConflictSetMode::Semantic => conflict.is_semantic(),
+        };
+        if joins {
+            conflict_sets.union(*id1, *id2);
+        }
+    }
+    conflict_sets.sets()
+}
+
+/// Union-find (path compression + union by size) over the orders of the conflict sets.
+#[derive(Debug, Default)]
+struct OrderDisjointSets {
+    index: OrderIndex,
+    /// By order index.
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl OrderDisjointSets {
+    fn index(&mut self, id: OrderId) -> usize {
+        let index = self.index.insert(id) as usize;
+        if index == self.parents.len() {
+            self.parents.push(index);
+            self.sizes.push(1);
+        }
+        index
+    }
+
+    fn root(&self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            index = self.parents[index];
+        }
+        index
+    }
+
+    /// root + path compression.
+    fn find(&mut self, mut index: usize) -> usize {
+        let root = self.root(index);
+        while self.parents[index] != root {
+            let parent = self.parents[index];
+            self.parents[index] = root;
+            index = parent;
+        }
+        root
+    }
+
+    fn union(&mut self, id1: OrderId, id2: OrderId) {
+        let (index1, index2) = (self.index(id1), self.index(id2));
+        let (root1, root2) = (self.find(index1), self.find(index2));
+        if root1 == root2 {
+            return;
+        }
+        let (big, small) = if self.sizes[root1] >= self.sizes[root2] {
+            (root1, root2)
+        } else {
+            (root2, root1)
+        };
+        self.parents[small] = big;
+        self.sizes[big] += self.sizes[small];
+    }
+
+    fn sets(&self) -> Vec<HashSet<OrderId>> {
+        // by root index
+        let mut sets: Vec<Vec<OrderId>> = vec![Vec::new(); self.parents.len()];
+        for (index, id) in self.index.ids().iter().enumerate() {
+            sets[self.root(index)].push(*id);
+        }
+        let mut sets = sets
+            .into_iter()
+            .filter(|set| !set.is_empty())
+            .map(|mut set| {
+                set.sort();
+                set
+            })
+            .collect::<Vec<_>>();
+        // sets are disjoint so the min ids are all different
+        sets.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].cmp(&b[0])));
+        sets.into_iter()
+            .map(|set| set.into_iter().collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::{Bundle, MempoolTx, TransactionSignedEcRecoveredWithBlobs},
+        utils::test_utils::{addr, hash, order_id},
+    };
+    use alloy_eips::eip4844::DATA_GAS_PER_BLOB;
+    use itertools::Itertools;
+    use reth_provider::StateProviderFactory;
+
+    #[test]
+    fn test_parallel_conflict_finder() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let order = |from: usize, nonce: u64, value: u64| -> eyre::Result<Order> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(
+                NamedAddr::User(from),
+                nonce,
+                value,
+            ))?;
+            Ok(Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            )))
+        };
+        // last one is a different tx with the first one's sender and nonce
+        let orders = vec![
+            order(1, 0, 5)?,
+            order(2, 0, 5)?,
+            order(3, 0, 5)?,
+            order(1, 0, 7)?,
+        ];
+        let ctx = test_chain.block_building_context();
+
+        let serial =
+            find_conflict_slow(test_chain.provider_factory().latest()?, ctx, &orders, None)?;
+        for num_threads in [1, 3] {
+            let finder = ParallelConflictFinder::new(num_threads)?;
+            // same pool for several detections
+            for _ in 0..2 {
+                let parallel =
+                    finder.find_conflicts(test_chain.provider_factory().latest()?, ctx, &orders)?;
+                assert_eq!(parallel, serial);
+            }
+        }
+        assert_eq!(serial.len(), 4 * 3);
+        assert_eq!(
+            serial.get(&(orders[0].id(), orders[1].id())),
+            Some(&Conflict::NoConflict)
+        );
+        assert_eq!(
+            serial.get(&(orders[0].id(), orders[3].id())),
+            Some(&Conflict::Nonce(
+                test_chain.named_address(NamedAddr::User(1))?
+            ))
+        );
+
+        // plain transfers only overlap on nonces
+        let fast = find_conflict_fast(test_chain.provider_factory().latest()?, ctx, &orders)?;
+        assert_eq!(fast, serial);
+
+        let mut conflict_finder =
+            IncrementalConflictFinder::new(test_chain.provider_factory().latest()?, ctx.clone());
+        for order in &orders {
+            assert!(conflict_finder.add_order(order.clone())?);
+        }
+        assert!(!conflict_finder.add_order(orders[0].clone())?);
+        assert_eq!(conflict_finder.conflicts(), &serial);
+        assert_eq!(
+            conflict_finder.conflict_sets(),
+            vec![HashSet::from([orders[0].id(), orders[3].id()])]
+        );
+        assert_eq!(conflict_finder.conflict_sets(), get_conflict_sets(&serial));
+
+        let timed_out = find_conflict_slow(
+            test_chain.provider_factory().latest()?,
+            ctx,
+            &orders,
+            Some(Instant::now()),
+        )?;
+        assert!(timed_out.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverting_tx_hashes_are_not_fatal() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let ctx = test_chain.block_building_context();
+        let tx = |from: usize, nonce: u64, value: u64| -> eyre::Result<_> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(
+                NamedAddr::User(from),
+                nonce,
+                value,
+            ))?;
+            Ok(TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap())
+        };
+        let bundle = |txs: Vec<TransactionSignedEcRecoveredWithBlobs>, reverting: &[usize]| {
+            let mut bundle = Bundle {
+                block: ctx.block(),
+                min_timestamp: None,
+                max_timestamp: None,
+                reverting_tx_hashes: reverting.iter().map(|idx| txs[*idx].hash()).collect(),
+                txs,
+                hash: Default::default(),
+                uuid: Default::default(),
+                replacement_data: None,
+                signer: None,
+                metadata: Default::default(),
+            };
+            bundle.hash_slow();
+            Order::Bundle(bundle)
+        };
+        // the first tx of the bundles fails after front_runner (nonce too low) but they allow it to
+        let front_runner = Order::Tx(MempoolTx::new(tx(1, 0, 5)?));
+        let all_revertible = bundle(vec![tx(1, 0, 7)?], &[0]);
+        let partially_revertible = bundle(vec![tx(1, 0, 7)?, tx(2, 0, 5)?], &[0]);
+        let strict = bundle(vec![tx(1, 0, 7)?, tx(2, 0, 5)?], &[]);
+        let orders = vec![
+            front_runner.clone(),
+            all_revertible.clone(),
+            partially_revertible.clone(),
+            strict.clone(),
+        ];
+        let conflicts =
+            find_conflict_slow(test_chain.provider_factory().latest()?, ctx, &orders, None)?;
+
+        match &conflicts[&(front_runner.id(), all_revertible.id())] {
+            Conflict::DifferentProfit {
+                profit_alone,
+                profit_with_conflict,
+            } => {
+                assert!(!profit_alone.is_zero());
+                assert!(profit_with_conflict.is_zero());
             }
-            (_, Some(set_id), None) | (_, None, Some(set_id)) => {
-                let set = conflict_sets.get_mut(&set_id).unwrap();
-                set.insert(*k1);
-                set.insert(*k2);
-                order_to_conflict_set.insert(*k1, set_id);
-                order_to_conflict_set.insert(*k2, set_id);
+            conflict => panic!("unexpected conflict {:?}", conflict),
+        }
+        match &conflicts[&(front_runner.id(), partially_revertible.id())] {
+            Conflict::DifferentProfit {
+                profit_alone,
+                profit_with_conflict,
+            } => {
+                assert!(!profit_with_conflict.is_zero());
+                assert!(profit_with_conflict < profit_alone);
+            }
+            conflict => panic!("unexpected conflict {:?}", conflict),
+        }
+        assert_eq!(
+            conflicts[&(front_runner.id(), strict.id())],
+            Conflict::Nonce(test_chain.named_address(NamedAddr::User(1))?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nonce_conflict_sequences() {
+        let seq = |address: usize, nonces: &[(u64, bool)]| NonceSequence {
+            address: addr(address),
+            nonces: nonces.to_vec(),
+        };
+        // same nonce
+        assert_eq!(
+            nonce_conflict(&[seq(1, &[(0, false)])], &[seq(1, &[(0, false)])]),
+            Some(addr(1))
+        );
+        // chained: the second one continues where the first one ends
+        assert_eq!(
+            nonce_conflict(
+                &[seq(1, &[(0, false), (1, false)])],
+                &[seq(1, &[(2, false)])]
+            ),
+            None
+        );
+        // the first one uses a later nonce of the signer
+        assert_eq!(
+            nonce_conflict(
+                &[seq(2, &[(3, false)]), seq(1, &[(0, true), (1, false)])],
+                &[seq(1, &[(0, false)])]
+            ),
+            Some(addr(1))
+        );
+        // optional only
+        assert_eq!(
+            nonce_conflict(&[seq(1, &[(0, true)])], &[seq(1, &[(0, false)])]),
+            None
+        );
+        assert_eq!(
+            nonce_conflict(
+                &[seq(1, &[(0, false)])],
+                &[seq(1, &[(0, true), (1, false)])]
+            ),
+            None
+        );
+        // other signer
+        assert_eq!(
+            nonce_conflict(&[seq(1, &[(0, false)])], &[seq(2, &[(0, false)])]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_multi_nonce_bundle_is_not_fatal() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let ctx = test_chain.block_building_context();
+        let tx = |from: usize, nonce: u64, value: u64| -> eyre::Result<_> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(
+                NamedAddr::User(from),
+                nonce,
+                value,
+            ))?;
+            Ok(TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap())
+        };
+        let user_tx = Order::Tx(MempoolTx::new(tx(1, 0, 5)?));
+        // searcher bundle with the user tx (optional) followed by its own tx, signed by the same user and another one
+        let txs = vec![tx(1, 0, 5)?, tx(1, 1, 7)?, tx(2, 0, 3)?];
+        let mut bundle = Bundle {
+            block: ctx.block(),
+            min_timestamp: None,
+            max_timestamp: None,
+            reverting_tx_hashes: vec![txs[0].hash()],
+            txs,
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        let bundle = Order::Bundle(bundle);
+        let orders = vec![user_tx.clone(), bundle.clone()];
+        let conflicts =
+            find_conflict_slow(test_chain.provider_factory().latest()?, ctx, &orders, None)?;
+        // the bundle moves the user nonce to 2
+        assert_eq!(
+            conflicts[&(bundle.id(), user_tx.id())],
+            Conflict::Nonce(test_chain.named_address(NamedAddr::User(1))?)
+        );
+        // the bundle skips the user tx and still lands
+        assert!(matches!(
+            conflicts[&(user_tx.id(), bundle.id())],
+            Conflict::DifferentProfit { .. }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pairs_by_profit() {
+        // order 3 fails alone
+        let profits_alone = [
+            Some(U256::from(1)),
+            Some(U256::from(3)),
+            Some(U256::from(2)),
+            None,
+        ];
+        assert_eq!(
+            pairs_by_profit(&profits_alone).collect::<Vec<_>>(),
+            vec![(1, 2), (2, 1), (1, 0), (0, 1), (2, 0), (0, 2)]
+        );
+    }
+
+    #[test]
+    fn test_access_set_overlap() {
+        let slot = |address: u64, key: u64| SlotKey {
+            address: addr(address),
+            key: hash(key),
+        };
+        let writer = OrderAccessSet {
+            slot_writes: HashSet::from([slot(1, 1)]),
+            balance_writes: HashSet::from([addr(10)]),
+            ..Default::default()
+        };
+        let reader = OrderAccessSet {
+            slot_reads: HashSet::from([slot(1, 1)]),
+            ..Default::default()
+        };
+        let other_slot_reader = OrderAccessSet {
+            slot_reads: HashSet::from([slot(1, 2)]),
+            ..Default::default()
+        };
+        let balance_reader = OrderAccessSet {
+            balance_reads: HashSet::from([addr(10)]),
+            ..Default::default()
+        };
+        let destructor = OrderAccessSet {
+            code_writes: HashSet::from([addr(1)]),
+            ..Default::default()
+        };
+        assert!(writer.affects(&reader));
+        assert!(!reader.affects(&writer));
+        assert!(!writer.affects(&other_slot_reader));
+        assert!(writer.affects(&balance_reader));
+        assert!(destructor.affects(&other_slot_reader));
+        assert!(!destructor.affects(&balance_reader));
+
+        // the indexed version gives the same result for every pair
+        let sets = [
+            writer,
+            reader,
+            other_slot_reader,
+            balance_reader,
+            destructor,
+        ];
+        let indexed_sets = IndexedAccessSets::new(&sets);
+        for (index1, set1) in sets.iter().enumerate() {
+            for (index2, set2) in sets.iter().enumerate() {
+                assert_eq!(
+                    indexed_sets.affects(index1, index2),
+                    set1.affects(set2),
+                    "{} {}",
+                    index1,
+                    index2
+                );
             }
-            (_, None, None) => {
-                let mut set = HashSet::new();
-                set.insert(*k1);
-                set.insert(*k2);
-                order_to_conflict_set.insert(*k1, set_id);
-                order_to_conflict_set.insert(*k2, set_id);
-                conflict_sets.insert(set_id, set);
-                set_id += 1;
+        }
+    }
+
+    #[test]
+    fn test_intersects() {
+        assert!(intersects(&[1, 3, 5], &[0, 5]));
+        assert!(!intersects(&[1, 3, 5], &[0, 2, 4, 6]));
+        assert!(!intersects(&[], &[1]));
+    }
+
+    #[test]
+    fn test_access_set_blob_gas_limit() {
+        let blobs = |id: u64, blob_count: u64| {
+            (
+                order_id(id),
+                OrderAccessSet {
+                    blob_gas: blob_count * DATA_GAS_PER_BLOB,
+                    ..Default::default()
+                },
+            )
+        };
+        let access_sets = vec![blobs(1, 4), blobs(2, 2), blobs(3, 3), blobs(4, 0)];
+        let conflicts = access_set_conflicts(&access_sets, 30_000_000, 6 * DATA_GAS_PER_BLOB);
+        assert_eq!(
+            conflicts[&(order_id(1), order_id(3))],
+            Conflict::BlobGasLimit
+        );
+        assert_eq!(
+            conflicts[&(order_id(3), order_id(1))],
+            Conflict::BlobGasLimit
+        );
+        assert_eq!(conflicts[&(order_id(1), order_id(2))], Conflict::NoConflict);
+        assert_eq!(conflicts[&(order_id(1), order_id(4))], Conflict::NoConflict);
+        assert_eq!(
+            get_conflict_sets(&conflicts)
+                .into_iter()
+                .map(|set| set.into_iter().sorted().collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            vec![vec![order_id(1), order_id(3)]]
+        );
+    }
+
+    #[test]
+    fn test_access_set_gas_limit() {
+        let gas = |id: u64, gas_used: u64, slot_writes: bool| {
+            let slot = SlotKey {
+                address: addr(100),
+                key: Default::default(),
+            };
+            let mut access_set = OrderAccessSet {
+                gas_used,
+                ..Default::default()
+            };
+            if slot_writes {
+                access_set.slot_writes.insert(slot.clone());
             }
+            access_set.slot_reads.insert(slot);
+            (order_id(id), access_set)
+        };
+        let access_sets = vec![
+            gas(1, 20_000_000, true),
+            gas(2, 15_000_000, false),
+            gas(3, 5_000_000, false),
+        ];
+        let conflicts = access_set_conflicts(&access_sets, 30_000_000, 0);
+        assert_eq!(conflicts[&(order_id(1), order_id(2))], Conflict::GasLimit);
+        assert_eq!(conflicts[&(order_id(2), order_id(1))], Conflict::GasLimit);
+        assert_eq!(
+            conflicts[&(order_id(1), order_id(3))],
+            Conflict::AccessOverlap
+        );
+        assert_eq!(conflicts[&(order_id(2), order_id(3))], Conflict::NoConflict);
+
+        let sorted_sets = |mode| {
+            get_conflict_sets_with_mode(&conflicts, mode)
+                .into_iter()
+                .map(|set| set.into_iter().sorted().collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sorted_sets(ConflictSetMode::All),
+            vec![vec![order_id(1), order_id(2), order_id(3)]]
+        );
+        assert_eq!(
+            sorted_sets(ConflictSetMode::Semantic),
+            vec![vec![order_id(1), order_id(3)]]
+        );
+    }
+
+    #[test]
+    fn test_conflict_kinds() {
+        let conflicts = [
+            Conflict::NoConflict,
+            Conflict::Nonce(addr(1)),
+            Conflict::Fatal,
+            Conflict::DifferentProfit {
+                profit_alone: U256::from(2),
+                profit_with_conflict: U256::from(1),
+            },
+            Conflict::AccessOverlap,
+            Conflict::BlobGasLimit,
+            Conflict::GasLimit,
+        ];
+        assert_eq!(
+            conflicts.iter().map(|c| c.kind()).collect::<Vec<_>>(),
+            CONFLICT_KINDS.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_get_conflict_sets() {
+        let sorted_sets = |conflicts: &HashMap<(OrderId, OrderId), Conflict>| {
+            get_conflict_sets(conflicts)
+                .into_iter()
+                .map(|set| set.into_iter().sorted().collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        let mut conflicts = HashMap::from([
+            ((order_id(5), order_id(6)), Conflict::Fatal),
+            ((order_id(1), order_id(2)), Conflict::Fatal),
+            ((order_id(3), order_id(4)), Conflict::NoConflict),
+            ((order_id(8), order_id(7)), Conflict::Nonce(addr(1))),
+            ((order_id(7), order_id(9)), Conflict::BlobGasLimit),
+        ]);
+        // same size sets go by min OrderId
+        let expected = vec![
+            vec![order_id(7), order_id(8), order_id(9)],
+            vec![order_id(1), order_id(2)],
+            vec![order_id(5), order_id(6)],
+        ];
+        for _ in 0..10 {
+            assert_eq!(sorted_sets(&conflicts), expected);
+        }
+
+        // long chain 10..20_000 joining the 1-2 set at the end
+        for id in 10..20_000 {
+            conflicts.insert((order_id(id), order_id(id + 1)), Conflict::Fatal);
         }
+        conflicts.insert((order_id(20_000), order_id(2)), Conflict::Fatal);
+        let sets = sorted_sets(&conflicts);
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0].len(), 20_000 - 10 + 1 + 2);
+        assert_eq!(sets[1], expected[0]);
+        assert_eq!(sets[2], expected[2]);
     }
-    let mut conflict_sets = conflict_sets.into_values().collect::<Vec<_>>();
-    conflict_sets.sort_by_key(|set| std::cmp::Reverse(set.len()));
-    conflict_sets
 }
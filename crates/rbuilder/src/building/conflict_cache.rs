@@ -0,0 +1,211 @@
+//! Cache of pair [`Conflict`]s so repeated conflict detection (several builder algorithms, several iterations, several
+//! slots on the same parent) doesn't execute the same pairs again.
+//! Accessed via [`BlockBuildingContext::conflict_cache`] (shared by every clone of the context). The live builder
+//! shares one cache between all its slots and the clean orderpool job drops the entries of the blocks that are
+//! already on chain on every new head ([`ConflictCache::head_updated`]).
+//! A conflict only depends on the orders and the block they execute on, clones changing the block env (eg: the
+//! coinbase) share the cache so entries are also keyed by the block and a hash of the parent and the block env
+//! ([`ConflictCache::context_key`]).
+//! Entries are spread over SHARDS LRUs so the parallel conflict detection doesn't serialize on a single lock.
+
+use super::{BlockBuildingContext, Conflict};
+use crate::{primitives::OrderId, telemetry::inc_conflict_cache_access};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+/// Past this the least recently used entries are dropped (~100 bytes each).
+const MAX_ENTRIES: usize = 1_000_000;
+const SHARDS: usize = 16;
+
+/// Identifies the block the conflicts are computed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContextKey {
+    pub block: u64,
+    /// Hash of the parent, the block env and the spec.
+    pub hash: u64,
+}
+
+/// (first order, second order, context key) -> conflict of executing first before second.
+type ConflictKey = (OrderId, OrderId, ContextKey);
+
+/// Cheap to clone, clones share the entries.
+#[derive(Debug, Clone)]
+pub struct ConflictCache {
+    shards: Arc<Vec<Mutex<LruCache<ConflictKey, Conflict>>>>,
+    shard_capacity: usize,
+}
+
+impl Default for ConflictCache {
+    fn default() -> Self {
+        Self::with_capacity(MAX_ENTRIES)
+    }
+}
+
+impl ConflictCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        // unbounded (we evict ourselves) since LruCache::new allocates the whole capacity upfront
+        Self {
+            shards: Arc::new(
+                (0..SHARDS)
+                    .map(|_| Mutex::new(LruCache::unbounded()))
+                    .collect(),
+            ),
+            shard_capacity: capacity.div_ceil(SHARDS).max(1),
+        }
+    }
+
+    pub fn context_key(ctx: &BlockBuildingContext) -> ContextKey {
+        let mut hasher = DefaultHasher::new();
+        ctx.attributes.parent.hash(&mut hasher);
+        ctx.block_env.hash(&mut hasher);
+        ctx.spec_id.hash(&mut hasher);
+        ContextKey {
+            block: ctx.block(),
+            hash: hasher.finish(),
+        }
+    }
+
+    fn shard(&self, key: &ConflictKey) -> &Mutex<LruCache<ConflictKey, Conflict>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    pub fn get(
+        &self,
+        order1: OrderId,
+        order2: OrderId,
+        context_key: ContextKey,
+    ) -> Option<Conflict> {
+        let key = (order1, order2, context_key);
+        let res = self.shard(&key).lock().get(&key).cloned();
+        inc_conflict_cache_access(res.is_some());
+        res
+    }
+
+    pub fn insert(
+        &self,
+        order1: OrderId,
+        order2: OrderId,
+        context_key: ContextKey,
+        conflict: Conflict,
+    ) {
+        let key = (order1, order2, context_key);
+        let mut shard = self.shard(&key).lock();
+        if shard.len() >= self.shard_capacity && !shard.contains(&key) {
+            shard.pop_lru();
+        }
+        shard.put(key, conflict);
+    }
+
+    /// A new head block_number landed: drops the conflicts computed for any block up to block_number since their
+    /// parent state is not the one we build on anymore.
+    pub fn head_updated(&self, block_number: u64) {
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock();
+            let stale: Vec<ConflictKey> = shard
+                .iter()
+                .filter(|(key, _)| key.2.block <= block_number)
+                .map(|(key, _)| *key)
+                .collect();
+            for key in stale {
+                shard.pop(&key);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.lock().is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, TestChainState},
+        utils::test_utils::order_id,
+    };
+    use alloy_primitives::Address;
+
+    fn key(block: u64) -> ContextKey {
+        ContextKey { block, hash: 0 }
+    }
+
+    #[test]
+    fn test_conflict_cache() {
+        let cache = ConflictCache::default();
+        cache.insert(order_id(1), order_id(2), key(10), Conflict::Fatal);
+        cache.insert(order_id(1), order_id(2), key(11), Conflict::NoConflict);
+        assert_eq!(
+            cache.get(order_id(1), order_id(2), key(10)),
+            Some(Conflict::Fatal)
+        );
+        assert_eq!(cache.get(order_id(2), order_id(1), key(10)), None);
+        assert_eq!(
+            cache.get(order_id(1), order_id(2), key(11)),
+            Some(Conflict::NoConflict)
+        );
+        assert_eq!(cache.len(), 2);
+        // clones share the entries
+        assert_eq!(
+            cache.clone().get(order_id(1), order_id(2), key(10)),
+            Some(Conflict::Fatal)
+        );
+    }
+
+    #[test]
+    fn test_conflict_cache_eviction() {
+        let cache = ConflictCache::with_capacity(SHARDS);
+        for id in 0..(10 * SHARDS as u64) {
+            cache.insert(order_id(id), order_id(id + 1), key(0), Conflict::NoConflict);
+        }
+        assert!(cache.len() <= SHARDS);
+        // the last one is always kept
+        let last = 10 * SHARDS as u64 - 1;
+        assert_eq!(
+            cache.get(order_id(last), order_id(last + 1), key(0)),
+            Some(Conflict::NoConflict)
+        );
+    }
+
+    #[test]
+    fn test_head_updated() {
+        let cache = ConflictCache::default();
+        cache.insert(order_id(1), order_id(2), key(10), Conflict::Fatal);
+        cache.insert(order_id(1), order_id(2), key(11), Conflict::Fatal);
+        cache.insert(order_id(3), order_id(4), key(12), Conflict::NoConflict);
+        // block 11 landed, only the conflicts computed for block 12 are still useful
+        cache.head_updated(11);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(order_id(1), order_id(2), key(11)), None);
+        assert_eq!(
+            cache.get(order_id(3), order_id(4), key(12)),
+            Some(Conflict::NoConflict)
+        );
+    }
+
+    #[test]
+    fn test_context_key() {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11)).unwrap();
+        let ctx = test_chain.block_building_context();
+        let key = ConflictCache::context_key(ctx);
+        assert_eq!(key.block, 11);
+        assert_eq!(ConflictCache::context_key(&ctx.clone()), key);
+        let mut other_coinbase = ctx.clone();
+        other_coinbase.block_env.coinbase = Address::with_last_byte(1);
+        assert_ne!(ConflictCache::context_key(&other_coinbase), key);
+        let mut other_parent = ctx.clone();
+        other_parent.attributes.parent = Default::default();
+        assert_ne!(ConflictCache::context_key(&other_parent), key);
+    }
+}
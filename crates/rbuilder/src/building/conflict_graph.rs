@@ -0,0 +1,215 @@
+//! Export of the conflicts found by [`super::find_conflict_slow`] as a graph (DOT for graphviz or JSON) to inspect
+//! why some orders never land together.
+//! Nodes are the orders (by their [`OrderId`] Display), there is an edge order1 -> order2 for every pair where
+//! executing order1 first affects order2 (NoConflict pairs are omitted).
+
+use super::Conflict;
+use crate::primitives::OrderId;
+use alloy_primitives::{utils::format_ether, Address, U256};
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    Nonce,
+    Fatal,
+    DifferentProfit,
+    AccessOverlap,
+    BlobGasLimit,
+    GasLimit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConflictGraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: ConflictKind,
+    /// Only for ConflictKind::Nonce.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce_address: Option<Address>,
+    /// Only for ConflictKind::DifferentProfit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profit_alone: Option<U256>,
+    /// Only for ConflictKind::DifferentProfit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profit_with_conflict: Option<U256>,
+    /// profit_with_conflict - profit_alone in ETH with sign (eg: "-0.001000000000000000").
+    /// Only for ConflictKind::DifferentProfit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profit_delta: Option<String>,
+}
+
+impl ConflictGraphEdge {
+    fn label(&self) -> String {
+        match self.kind {
+            ConflictKind::Nonce => match &self.nonce_address {
+                Some(address) => format!("nonce {:?}", address),
+                None => "nonce".to_string(),
+            },
+            ConflictKind::Fatal => "fatal".to_string(),
+            ConflictKind::DifferentProfit => format!(
+                "profit {} ETH",
+                self.profit_delta.as_deref().unwrap_or_default()
+            ),
+            ConflictKind::AccessOverlap => "access overlap".to_string(),
+            ConflictKind::BlobGasLimit => "blob gas limit".to_string(),
+            ConflictKind::GasLimit => "gas limit".to_string(),
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self.kind {
+            ConflictKind::Nonce => "blue",
+            ConflictKind::Fatal => "red",
+            ConflictKind::DifferentProfit => "orange",
+            ConflictKind::AccessOverlap => "gray",
+            ConflictKind::BlobGasLimit => "purple",
+            ConflictKind::GasLimit => "brown",
+        }
+    }
+}
+
+/// Nodes and edges are sorted so the same conflicts always give the same output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConflictGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<ConflictGraphEdge>,
+}
+
+impl ConflictGraph {
+    pub fn new(conflicts: &HashMap<(OrderId, OrderId), Conflict>) -> Self {
+        let mut nodes: Vec<OrderId> = conflicts
+            .keys()
+            .flat_map(|(order1, order2)| [*order1, *order2])
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+
+        let mut edges: Vec<_> = conflicts.iter().collect();
+        edges.sort_by_key(|(ids, _)| **ids);
+        let edges = edges
+            .into_iter()
+            .filter_map(|((order1, order2), conflict)| {
+                let mut edge = ConflictGraphEdge {
+                    from: order1.to_string(),
+                    to: order2.to_string(),
+                    kind: ConflictKind::Fatal,
+                    nonce_address: None,
+                    profit_alone: None,
+                    profit_with_conflict: None,
+                    profit_delta: None,
+                };
+                match conflict {
+                    Conflict::NoConflict => return None,
+                    Conflict::Nonce(address) => {
+                        edge.kind = ConflictKind::Nonce;
+                        edge.nonce_address = Some(*address);
+                    }
+                    Conflict::Fatal => {}
+                    Conflict::DifferentProfit {
+                        profit_alone,
+                        profit_with_conflict,
+                    } => {
+                        edge.kind = ConflictKind::DifferentProfit;
+                        edge.profit_alone = Some(*profit_alone);
+                        edge.profit_with_conflict = Some(*profit_with_conflict);
+                        edge.profit_delta =
+                            Some(profit_delta(*profit_alone, *profit_with_conflict));
+                    }
+                    Conflict::AccessOverlap => edge.kind = ConflictKind::AccessOverlap,
+                    Conflict::BlobGasLimit => edge.kind = ConflictKind::BlobGasLimit,
+                    Conflict::GasLimit => edge.kind = ConflictKind::GasLimit,
+                }
+                Some(edge)
+            })
+            .collect();
+
+        Self {
+            nodes: nodes.iter().map(|id| id.to_string()).collect(),
+            edges,
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph conflicts {\n");
+        for node in &self.nodes {
+            let _ = writeln!(dot, "  \"{}\";", node);
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\", color={}];",
+                edge.from,
+                edge.to,
+                edge.label(),
+                edge.color()
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> eyre::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn profit_delta(profit_alone: U256, profit_with_conflict: U256) -> String {
+    if profit_with_conflict >= profit_alone {
+        format!("+{}", format_ether(profit_with_conflict - profit_alone))
+    } else {
+        format!("-{}", format_ether(profit_alone - profit_with_conflict))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{addr, order_id};
+
+    #[test]
+    fn test_conflict_graph() {
+        let conflicts = HashMap::from([
+            ((order_id(1), order_id(2)), Conflict::Nonce(addr(7))),
+            ((order_id(2), order_id(1)), Conflict::NoConflict),
+            ((order_id(1), order_id(3)), Conflict::Fatal),
+            (
+                (order_id(3), order_id(2)),
+                Conflict::DifferentProfit {
+                    profit_alone: U256::from(3_000_000_000_000_000u64),
+                    profit_with_conflict: U256::from(1_000_000_000_000_000u64),
+                },
+            ),
+            ((order_id(4), order_id(5)), Conflict::NoConflict),
+        ]);
+        let graph = ConflictGraph::new(&conflicts);
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.edges.len(), 3);
+        let profit_edge = graph
+            .edges
+            .iter()
+            .find(|edge| edge.kind == ConflictKind::DifferentProfit)
+            .unwrap();
+        assert_eq!(profit_edge.from, order_id(3).to_string());
+        assert_eq!(
+            profit_edge.profit_delta.as_deref(),
+            Some("-0.002000000000000000")
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph conflicts {\n"));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [label=\"fatal\", color=red];",
+            order_id(1),
+            order_id(3)
+        )));
+        assert!(dot.contains(&format!("\"{}\";", order_id(5))));
+        assert_eq!(dot.matches("->").count(), 3);
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(json["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(json["edges"][0]["kind"], "nonce");
+        assert!(json["edges"][0].get("profit_delta").is_none());
+    }
+}
@@ -0,0 +1,378 @@
+//! Resolution of the conflict sets from [`super::get_conflict_sets`]: for every set we look for the execution order of its
+//! orders with the max combined coinbase profit.
+//! Small sets (up to [`ConflictResolver::max_exhaustive_len`] orders) try every permutation. Bigger ones start from the
+//! orders sorted by profit alone and improve it with simulated annealing (random swaps, worse sequences are accepted
+//! with decreasing probability to escape local maximums).
+//! Orders failing in a sequence are skipped (as a builder would do) so the result may contain less orders than the set.
+//! Sets with more than [`ConflictResolver::max_set_len`] orders are pruned first: only the max_set_len orders with the
+//! best profit alone are resolved, the profit alone of the dropped ones is reported as
+//! [`ResolvedConflictSet::pruned_value`].
+//! With [`ConflictSetMode::Semantic`] orders that only conflict because they don't fit together in the block are not
+//! grouped, each set is resolved on its own so they can all be part of the result and the caller picks what fits.
+
+use super::{
+    get_conflict_sets_with_mode, order_index::OrderIndex, standalone_profits::standalone_profit,
+    BlockBuildingContext, BlockState, Conflict, ConflictSetMode, PartialBlockFork,
+};
+use crate::{
+    primitives::{Order, OrderId},
+    telemetry::add_conflict_set_pruned,
+};
+use alloy_primitives::U256;
+use itertools::Itertools;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use reth::providers::StateProviderBox;
+use reth_provider::StateProvider;
+use std::{collections::HashMap, sync::Arc};
+
+/// 6! = 720 executions of 6 orders.
+pub const DEFAULT_MAX_EXHAUSTIVE_LEN: usize = 6;
+pub const DEFAULT_ANNEALING_ITERATIONS: usize = 500;
+pub const DEFAULT_MAX_SET_LEN: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedConflictSet {
+    /// Orders that executed ok, in execution order.
+    pub orders: Vec<Order>,
+    pub profit: U256,
+    /// Sum of the profits alone of the orders dropped because the set was too big (0 if not pruned).
+    pub pruned_value: U256,
+}
+
+pub struct ConflictResolver {
+    state_provider: Arc<dyn StateProvider>,
+    ctx: BlockBuildingContext,
+    max_exhaustive_len: usize,
+    annealing_iterations: usize,
+    max_set_len: usize,
+    set_mode: ConflictSetMode,
+    seed: u64,
+}
+
+impl ConflictResolver {
+    /// state_provider must be the state of ctx.attributes.parent.
+    pub fn new(state_provider: StateProviderBox, ctx: BlockBuildingContext) -> Self {
+        Self {
+            state_provider: Arc::from(state_provider),
+            ctx,
+            max_exhaustive_len: DEFAULT_MAX_EXHAUSTIVE_LEN,
+            annealing_iterations: DEFAULT_ANNEALING_ITERATIONS,
+            max_set_len: DEFAULT_MAX_SET_LEN,
+            set_mode: ConflictSetMode::default(),
+            seed: 0,
+        }
+    }
+
+    pub fn with_max_exhaustive_len(self, max_exhaustive_len: usize) -> Self {
+        Self {
+            max_exhaustive_len,
+            ..self
+        }
+    }
+
+    pub fn with_annealing_iterations(self, annealing_iterations: usize) -> Self {
+        Self {
+            annealing_iterations,
+            ..self
+        }
+    }
+
+    pub fn with_max_set_len(self, max_set_len: usize) -> Self {
+        Self {
+            max_set_len,
+            ..self
+        }
+    }
+
+    pub fn with_set_mode(self, set_mode: ConflictSetMode) -> Self {
+        Self { set_mode, ..self }
+    }
+
+    /// Seed for the random swaps of the annealing, same seed same result.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn max_exhaustive_len(&self) -> usize {
+        self.max_exhaustive_len
+    }
+
+    pub fn max_set_len(&self) -> usize {
+        self.max_set_len
+    }
+
+    /// Resolves every conflict set of conflicts (eg: from [super::find_conflict_slow] on orders).
+    /// Orders without conflicts are not included. Result is sorted by profit (max first).
+    pub fn resolve(
+        &self,
+        orders: &[Order],
+        conflicts: &HashMap<(OrderId, OrderId), Conflict>,
+    ) -> eyre::Result<Vec<ResolvedConflictSet>> {
+        let index = OrderIndex::from_ids(orders.iter().map(Order::id));
+        let mut resolved = Vec::new();
+        for conflict_set in get_conflict_sets_with_mode(conflicts, self.set_mode) {
+            // sorted so the result doesn't depend on the HashSet order
+            let set_orders = conflict_set
+                .iter()
+                .sorted()
+                .filter_map(|id| index.get(id).map(|idx| orders[idx as usize].clone()))
+                .collect::<Vec<_>>();
+            resolved.push(self.resolve_set(&set_orders)?);
+        }
+        resolved.sort_by(|a, b| b.profit.cmp(&a.profit));
+        Ok(resolved)
+    }
+
+    /// Best sequence for the orders of a single conflict set.
+    pub fn resolve_set(&self, orders: &[Order]) -> eyre::Result<ResolvedConflictSet> {
+        let (orders, pruned_value) = self.prune_set(orders)?;
+        let (profit, executed) = if orders.len() <= self.max_exhaustive_len {
+            self.exhaustive_search(&orders)?
+        } else {
+            self.annealing_search(&orders)?
+        };
+        Ok(ResolvedConflictSet {
+            orders: executed
+                .into_iter()
+                .map(|idx| orders[idx].clone())
+                .collect(),
+            profit,
+            pruned_value,
+        })
+    }
+
+    /// Keeps the max_set_len orders with the best profit alone (ties keep the input order).
+    /// Returns the kept orders and the sum of the profits alone of the dropped ones.
+    fn prune_set(&self, orders: &[Order]) -> eyre::Result<(Vec<Order>, U256)> {
+        if orders.len() <= self.max_set_len {
+            return Ok((orders.to_vec(), U256::ZERO));
+        }
+        let mut by_profit = Vec::with_capacity(orders.len());
+        for (idx, order) in orders.iter().enumerate() {
+            let profit = standalone_profit(&self.state_provider, &self.ctx, order)?;
+            by_profit.push((idx, profit.unwrap_or_default()));
+        }
+        by_profit.sort_by(|a, b| b.1.cmp(&a.1));
+        let pruned = by_profit.split_off(self.max_set_len);
+        let pruned_value = pruned.iter().map(|(_, profit)| *profit).sum();
+        add_conflict_set_pruned(pruned.len(), pruned_value);
+        by_profit.sort_by_key(|(idx, _)| *idx);
+        Ok((
+            by_profit
+                .into_iter()
+                .map(|(idx, _)| orders[idx].clone())
+                .collect(),
+            pruned_value,
+        ))
+    }
+
+    fn exhaustive_search(&self, orders: &[Order]) -> eyre::Result<(U256, Vec<usize>)> {
+        let mut best = (U256::ZERO, Vec::new());
+        for sequence in (0..orders.len()).permutations(orders.len()) {
+            let result = self.execute_sequence(orders, &sequence)?;
+            if result.0 > best.0 {
+                best = result;
+            }
+        }
+        Ok(best)
+    }
+
+    fn annealing_search(&self, orders: &[Order]) -> eyre::Result<(U256, Vec<usize>)> {
+        let mut profits_alone = Vec::with_capacity(orders.len());
+        for idx in 0..orders.len() {
+            profits_alone.push(self.execute_sequence(orders, &[idx])?.0);
+        }
+        let mut sequence: Vec<usize> = (0..orders.len()).collect();
+        sequence.sort_by(|a, b| profits_alone[*b].cmp(&profits_alone[*a]));
+
+        let mut best = self.execute_sequence(orders, &sequence)?;
+        if sequence.len() < 2 {
+            return Ok(best);
+        }
+        let mut current = best.0;
+        let initial_temperature = profit_as_f64(current).max(1.0) / 10.0;
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        for iteration in 0..self.annealing_iterations {
+            let (i, j) = (
+                rng.gen_range(0..sequence.len()),
+                rng.gen_range(0..sequence.len()),
+            );
+            if i == j {
+                continue;
+            }
+            sequence.swap(i, j);
+            let candidate = self.execute_sequence(orders, &sequence)?;
+            let delta = profit_as_f64(candidate.0) - profit_as_f64(current);
+            let temperature = initial_temperature
+                * (1.0 - iteration as f64 / self.annealing_iterations as f64)
+                + f64::EPSILON;
+            if delta >= 0.0 || rng.gen::<f64>() < (delta / temperature).exp() {
+                current = candidate.0;
+                if candidate.0 > best.0 {
+                    best = candidate;
+                }
+            } else {
+                sequence.swap(i, j);
+            }
+        }
+        Ok(best)
+    }
+
+    /// Executes the orders in sequence order on top of the parent state skipping the failed ones.
+    /// Returns the total profit and the executed orders.
+    fn execute_sequence(
+        &self,
+        orders: &[Order],
+        sequence: &[usize],
+    ) -> eyre::Result<(U256, Vec<usize>)> {
+        let mut state = BlockState::new_arc(self.state_provider.clone());
+        let mut fork = PartialBlockFork::new(&mut state);
+        let mut profit = U256::ZERO;
+        let mut executed = Vec::with_capacity(sequence.len());
+        let (mut cumulative_gas_used, mut cumulative_blob_gas_used) = (0, 0);
+        for idx in sequence {
+            if let Ok(res) = fork.commit_order(
+                &orders[*idx],
+                &self.ctx,
+                cumulative_gas_used,
+                0,
+                cumulative_blob_gas_used,
+                true,
+            )? {
+                profit += res.coinbase_profit;
+                cumulative_gas_used = res.cumulative_gas_used;
+                cumulative_blob_gas_used = res.cumulative_blob_gas_used;
+                executed.push(*idx);
+            }
+        }
+        Ok((profit, executed))
+    }
+}
+
+/// Only used to compare profits, precision loss above u128 is ok.
+fn profit_as_f64(profit: U256) -> f64 {
+    u128::try_from(profit).unwrap_or(u128::MAX) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::{
+            find_conflict_slow,
+            testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        },
+        primitives::{MempoolTx, TransactionSignedEcRecoveredWithBlobs},
+    };
+    use reth_provider::StateProviderFactory;
+
+    fn order(
+        test_chain: &TestChainState,
+        from: usize,
+        nonce: u64,
+        value: u64,
+    ) -> eyre::Result<Order> {
+        let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(
+            NamedAddr::User(from),
+            nonce,
+            value,
+        ))?;
+        Ok(Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+        )))
+    }
+
+    #[test]
+    fn test_resolve_set() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        // only nonce 0 works alone, the rest need the previous nonces first
+        let orders = vec![
+            order(&test_chain, 1, 2, 5)?,
+            order(&test_chain, 1, 1, 5)?,
+            order(&test_chain, 1, 0, 5)?,
+            order(&test_chain, 1, 3, 5)?,
+        ];
+        let expected: Vec<_> = [2, 1, 0, 3].iter().map(|idx| orders[*idx].id()).collect();
+        let ctx = test_chain.block_building_context().clone();
+
+        let exhaustive =
+            ConflictResolver::new(test_chain.provider_factory().latest()?, ctx.clone());
+        let resolved = exhaustive.resolve_set(&orders)?;
+        assert_eq!(
+            resolved.orders.iter().map(|o| o.id()).collect::<Vec<_>>(),
+            expected
+        );
+
+        let annealing = ConflictResolver::new(test_chain.provider_factory().latest()?, ctx)
+            .with_max_exhaustive_len(0)
+            .with_seed(1);
+        let annealed = annealing.resolve_set(&orders)?;
+        assert_eq!(annealed, resolved);
+
+        // greedy by profit alone only gets nonces 0 and 1
+        let no_annealing = annealing.with_annealing_iterations(0);
+        let greedy = no_annealing.resolve_set(&orders)?;
+        assert_eq!(greedy.orders.len(), 2);
+        assert!(greedy.profit < resolved.profit);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_set() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        // same sender and nonce, only one can land
+        let orders = vec![
+            order(&test_chain, 1, 0, 5)?,
+            order(&test_chain, 1, 0, 9)?,
+            order(&test_chain, 1, 0, 7)?,
+            order(&test_chain, 1, 0, 3)?,
+        ];
+        let ctx = test_chain.block_building_context().clone();
+        let resolver = ConflictResolver::new(test_chain.provider_factory().latest()?, ctx.clone());
+        let unpruned = resolver.resolve_set(&orders)?;
+        assert_eq!(unpruned.pruned_value, U256::ZERO);
+
+        let pruning =
+            ConflictResolver::new(test_chain.provider_factory().latest()?, ctx).with_max_set_len(2);
+        let (kept, pruned_value) = pruning.prune_set(&orders)?;
+        assert_eq!(
+            kept.iter().map(|o| o.id()).collect::<Vec<_>>(),
+            vec![orders[1].id(), orders[2].id()]
+        );
+        let profit = |idx: usize| pruning.execute_sequence(&orders, &[idx]).map(|res| res.0);
+        assert_eq!(pruned_value, profit(0)? + profit(3)?);
+
+        let pruned = pruning.resolve_set(&orders)?;
+        assert_eq!(pruned.orders, unpruned.orders);
+        assert_eq!(pruned.profit, unpruned.profit);
+        assert_eq!(pruned.pruned_value, pruned_value);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_conflict_sets() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        // same sender and nonce, only one can land
+        let orders = vec![
+            order(&test_chain, 1, 0, 5)?,
+            order(&test_chain, 2, 0, 5)?,
+            order(&test_chain, 1, 0, 7)?,
+        ];
+        let ctx = test_chain.block_building_context().clone();
+        let conflicts =
+            find_conflict_slow(test_chain.provider_factory().latest()?, &ctx, &orders, None)?;
+
+        let resolver = ConflictResolver::new(test_chain.provider_factory().latest()?, ctx);
+        let resolved = resolver.resolve(&orders, &conflicts)?;
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(
+            resolved[0]
+                .orders
+                .iter()
+                .map(|o| o.id())
+                .collect::<Vec<_>>(),
+            vec![orders[2].id()]
+        );
+        Ok(())
+    }
+}
@@ -32,6 +32,30 @@ pub struct UsedStateTrace {
     pub destructed_contracts: Vec<Address>,
 }
 
+impl UsedStateTrace {
+    /// Clears all the data keeping the allocated memory so it can be reused.
+    pub fn clear(&mut self) {
+        self.read_slot_values.clear();
+        self.written_slot_values.clear();
+        self.read_balances.clear();
+        self.received_amount.clear();
+        self.sent_amount.clear();
+        self.created_contracts.clear();
+        self.destructed_contracts.clear();
+    }
+
+    /// Sum of the capacities of all the containers.
+    pub fn capacity(&self) -> usize {
+        self.read_slot_values.capacity()
+            + self.written_slot_values.capacity()
+            + self.read_balances.capacity()
+            + self.received_amount.capacity()
+            + self.sent_amount.capacity()
+            + self.created_contracts.capacity()
+            + self.destructed_contracts.capacity()
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 enum NextStepAction {
     #[default]
@@ -0,0 +1,280 @@
+//! Privacy budget for sensitive orders.
+//! Every block we submit leaks its contents to the relays (and whoever they share bids with) even if it never lands,
+//! so an order that keeps showing up in losing blocks is exposed again and again. For orders with a budget we count
+//! the distinct submitted blocks containing them and once the budget is used [`PartialBlock::commit_order`](super::PartialBlock::commit_order)
+//! refuses them ([`ExecutionError::ExposureBudgetExhausted`](super::ExecutionError::ExposureBudgetExhausted)).
+//! Orders included in one of our landed blocks are forgotten.
+//! The submitted and landed blocks come from registering the budget (`Mutex<ExposureBudget>`) as a
+//! [`BlockInclusionObserver`].
+//! commit_order doesn't lock the budget: each slot's [`BlockBuildingContext`](super::BlockBuildingContext) gets the
+//! [`ExhaustedOrders`] of the budget when it's created (None if there's no budget) and only looks there, without locks
+//! while no order is exhausted.
+
+use super::ExecutionResult;
+use crate::{
+    live_builder::block_output::{
+        bidding::interfaces::LandedBlockInfo, block_inclusion_observer::BlockInclusionObserver,
+    },
+    primitives::{Order, OrderId},
+};
+use ahash::HashMap;
+use alloy_primitives::B256;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Orders not seen in a submitted block for this many blocks are forgotten (they landed somewhere else or expired).
+const ORDER_TTL_BLOCKS: u64 = 64;
+/// Block numbers for which we remember the orders of the submitted blocks.
+const MAX_TRACKED_BLOCK_NUMBERS: usize = 8;
+
+/// Max distinct blocks an order can be exposed in, None -> no limit.
+pub type MaxExposuresFn = dyn Fn(&Order) -> Option<usize> + Send + Sync;
+
+/// Orders that used their budget -> exposures. Cheap to clone, clones share the orders.
+#[derive(Debug, Clone, Default)]
+pub struct ExhaustedOrders {
+    orders: Arc<DashMap<OrderId, usize>>,
+    /// orders.len() (DashMap::len locks every shard).
+    len: Arc<AtomicUsize>,
+}
+
+impl ExhaustedOrders {
+    /// Some(exposures) if the order can't be included anymore.
+    pub fn get(&self, id: &OrderId) -> Option<usize> {
+        if self.len.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        self.orders.get(id).map(|exposures| *exposures)
+    }
+
+    fn insert(&self, id: OrderId, exposures: usize) {
+        if self.orders.insert(id, exposures).is_none() {
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn remove(&self, id: &OrderId) {
+        if self.orders.remove(id).is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OrderExposure {
+    max_exposures: usize,
+    /// Distinct blocks, at most max_exposures.
+    block_hashes: Vec<B256>,
+    last_block_number: u64,
+}
+
+impl OrderExposure {
+    fn is_exhausted(&self) -> bool {
+        self.block_hashes.len() >= self.max_exposures
+    }
+}
+
+pub struct ExposureBudget {
+    max_exposures: Box<MaxExposuresFn>,
+    orders: HashMap<OrderId, OrderExposure>,
+    /// block number -> (block hash, budgeted orders in it) for the blocks we submitted.
+    submitted_blocks: BTreeMap<u64, Vec<(B256, Vec<OrderId>)>>,
+    /// The exhausted ones of orders.
+    exhausted: ExhaustedOrders,
+}
+
+impl std::fmt::Debug for ExposureBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExposureBudget")
+            .field("orders", &self.orders)
+            .field("submitted_blocks", &self.submitted_blocks)
+            .field("exhausted", &self.exhausted)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExposureBudget {
+    pub fn new(max_exposures: Box<MaxExposuresFn>) -> Self {
+        Self {
+            max_exposures,
+            orders: HashMap::default(),
+            submitted_blocks: BTreeMap::new(),
+            exhausted: ExhaustedOrders::default(),
+        }
+    }
+
+    pub fn exhausted_orders(&self) -> ExhaustedOrders {
+        self.exhausted.clone()
+    }
+
+    pub fn record_submitted_block(
+        &mut self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    ) {
+        let mut budgeted_orders = Vec::new();
+        for executed in included_orders {
+            let Some(max_exposures) = (self.max_exposures)(&executed.order) else {
+                continue;
+            };
+            let id = executed.order.id();
+            let exposure = self.orders.entry(id).or_insert_with(|| OrderExposure {
+                max_exposures,
+                block_hashes: Vec::new(),
+                last_block_number: block_number,
+            });
+            exposure.last_block_number = exposure.last_block_number.max(block_number);
+            if !exposure.is_exhausted() && !exposure.block_hashes.contains(&block_hash) {
+                exposure.block_hashes.push(block_hash);
+                if exposure.is_exhausted() {
+                    self.exhausted.insert(id, exposure.block_hashes.len());
+                }
+            }
+            budgeted_orders.push(id);
+        }
+        if budgeted_orders.is_empty() {
+            return;
+        }
+        let blocks = self.submitted_blocks.entry(block_number).or_default();
+        if !blocks.iter().any(|(hash, _)| *hash == block_hash) {
+            blocks.push((block_hash, budgeted_orders));
+        }
+        while self.submitted_blocks.len() > MAX_TRACKED_BLOCK_NUMBERS {
+            self.submitted_blocks.pop_first();
+        }
+        let exhausted = &self.exhausted;
+        self.orders.retain(|id, exposure| {
+            let keep = exposure.last_block_number + ORDER_TTL_BLOCKS >= block_number;
+            if !keep {
+                exhausted.remove(id);
+            }
+            keep
+        });
+    }
+
+    /// landed_blocks: (block number, block hash) of the blocks we landed.
+    pub fn forget_landed_blocks(&mut self, landed_blocks: &[(u64, B256)]) {
+        for (block_number, block_hash) in landed_blocks {
+            let Some(blocks) = self.submitted_blocks.remove(block_number) else {
+                continue;
+            };
+            for (_, orders) in blocks.iter().filter(|(hash, _)| hash == block_hash) {
+                for id in orders {
+                    self.orders.remove(id);
+                    self.exhausted.remove(id);
+                }
+            }
+        }
+    }
+
+    /// Some(exposures) if the order can't be included anymore.
+    pub fn exhausted_exposures(&self, id: &OrderId) -> Option<usize> {
+        self.exhausted.get(id)
+    }
+}
+
+impl BlockInclusionObserver for Mutex<ExposureBudget> {
+    fn block_submitted(
+        &self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    ) {
+        self.lock()
+            .record_submitted_block(block_number, block_hash, included_orders);
+    }
+
+    fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]) {
+        self.lock().forget_landed_blocks(
+            &landed_blocks
+                .iter()
+                .filter(|landed| landed.beneficiary_is_builder)
+                .map(|landed| (landed.block_number, landed.block_hash))
+                .collect::<Vec<_>>(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Bundle, Metadata, SimValue};
+    use alloy_primitives::{Address, U256};
+
+    fn bundle(signer: Address, id: u128) -> Order {
+        Order::Bundle(Bundle {
+            block: 0,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: uuid::Uuid::from_u128(id),
+            replacement_data: None,
+            signer: Some(signer),
+            metadata: Metadata::default(),
+        })
+    }
+
+    fn executed(order: &Order) -> ExecutionResult {
+        ExecutionResult {
+            coinbase_profit: U256::ZERO,
+            inplace_sim: SimValue::default(),
+            gas_used: 0,
+            order: order.clone(),
+            txs: Vec::new(),
+            original_order_ids: Vec::new(),
+            receipts: Vec::new(),
+            nonces_updated: Vec::new(),
+            paid_kickbacks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_exposure_budget() {
+        let sensitive_signer = Address::with_last_byte(1);
+        let mut budget = ExposureBudget::new(Box::new(move |order: &Order| {
+            (order.signer() == Some(sensitive_signer)).then_some(2)
+        }));
+        let sensitive = bundle(sensitive_signer, 1);
+        let other = bundle(Address::with_last_byte(2), 2);
+        let block = [executed(&sensitive), executed(&other)];
+
+        budget.record_submitted_block(1, B256::with_last_byte(1), &block);
+        // same block submitted again
+        budget.record_submitted_block(1, B256::with_last_byte(1), &block);
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), None);
+        let exhausted = budget.exhausted_orders();
+        budget.record_submitted_block(2, B256::with_last_byte(2), &block);
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), Some(2));
+        // what the slots already running see
+        assert_eq!(exhausted.get(&sensitive.id()), Some(2));
+        assert_eq!(budget.exhausted_exposures(&other.id()), None);
+
+        // one of the blocks we didn't land
+        budget.forget_landed_blocks(&[(1, B256::with_last_byte(3))]);
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), Some(2));
+        budget.forget_landed_blocks(&[(2, B256::with_last_byte(2))]);
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), None);
+        assert_eq!(exhausted.get(&sensitive.id()), None);
+
+        budget.record_submitted_block(3, B256::with_last_byte(4), &block);
+        budget.record_submitted_block(4, B256::with_last_byte(5), &block);
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), Some(2));
+        // not seen for too long
+        budget.record_submitted_block(
+            5 + ORDER_TTL_BLOCKS,
+            B256::with_last_byte(6),
+            &[executed(&bundle(sensitive_signer, 3))],
+        );
+        assert_eq!(budget.exhausted_exposures(&sensitive.id()), None);
+    }
+}
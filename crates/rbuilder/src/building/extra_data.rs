@@ -0,0 +1,115 @@
+//! Extra data of our blocks from a template (config extra_data and the per relay extra_data overrides).
+//! Placeholders:
+//! - {builder_name}: first of the config builder_names ("rbuilder" if none).
+//! - {version}: rbuilder version.
+//! - {slot}: slot of the block.
+//!
+//! builder_name and version are fixed on parse and templates are rejected if their longest rendering (biggest slot)
+//! doesn't fit the 32 bytes extra data limit, so rendering never fails.
+
+use alloy_primitives::Bytes;
+use reth_primitives::SealedBlock;
+
+pub const MAX_EXTRA_DATA_SIZE: usize = 32;
+pub const DEFAULT_BUILDER_NAME: &str = "rbuilder";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Slot,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtraDataTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl ExtraDataTemplate {
+    pub fn parse(template: &str, builder_name: &str) -> eyre::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| eyre::eyre!("Unclosed placeholder in extra data {:?}", template))?;
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            parts.push(match &rest[start + 1..end] {
+                "builder_name" => TemplatePart::Literal(builder_name.to_string()),
+                "version" => TemplatePart::Literal(env!("CARGO_PKG_VERSION").to_string()),
+                "slot" => TemplatePart::Slot,
+                unknown => eyre::bail!("Unknown extra data placeholder {{{}}}", unknown),
+            });
+            rest = &rest[end + 1..];
+        }
+        parts.push(TemplatePart::Literal(rest.to_string()));
+        parts.retain(|part| *part != TemplatePart::Literal(String::new()));
+
+        let res = Self { parts };
+        let max_size = res.render(u64::MAX).len();
+        if max_size > MAX_EXTRA_DATA_SIZE {
+            eyre::bail!(
+                "Extra data {:?} is too long: up to {} bytes, max is {}",
+                template,
+                max_size,
+                MAX_EXTRA_DATA_SIZE
+            );
+        }
+        Ok(res)
+    }
+
+    pub fn render(&self, slot: u64) -> Vec<u8> {
+        let mut extra_data = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => extra_data.push_str(literal),
+                TemplatePart::Slot => extra_data.push_str(&slot.to_string()),
+            }
+        }
+        extra_data.into_bytes()
+    }
+}
+
+/// Same block with another extra data (and so another hash), extra data is not part of any root so nothing else
+/// changes.
+pub fn with_extra_data(block: &SealedBlock, extra_data: Bytes) -> SealedBlock {
+    let mut header = block.header.header().clone();
+    header.extra_data = extra_data;
+    SealedBlock::new(header.seal_slow(), block.body.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_data_template() {
+        let template = ExtraDataTemplate::parse("{builder_name}/s{slot}", "bob").unwrap();
+        assert_eq!(template.render(123), b"bob/s123".to_vec());
+        let plain = ExtraDataTemplate::parse("just text", "bob").unwrap();
+        assert_eq!(plain.render(1), b"just text".to_vec());
+        assert_eq!(
+            ExtraDataTemplate::parse("v{version}", "bob")
+                .unwrap()
+                .render(1),
+            format!("v{}", env!("CARGO_PKG_VERSION")).into_bytes()
+        );
+
+        assert!(ExtraDataTemplate::parse("{nope}", "bob").is_err());
+        assert!(ExtraDataTemplate::parse("{slot", "bob").is_err());
+        // 12 + 20 digits of the biggest slot fit...
+        assert!(ExtraDataTemplate::parse("123456789012{slot}", "bob").is_ok());
+        // ...13 don't, even if today's slots would
+        assert!(ExtraDataTemplate::parse("1234567890123{slot}", "bob").is_err());
+        assert!(ExtraDataTemplate::parse("{builder_name}", &"x".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn test_with_extra_data() {
+        let block = SealedBlock::default();
+        let changed = with_extra_data(&block, Bytes::from_static(b"relay"));
+        assert_eq!(changed.extra_data, Bytes::from_static(b"relay"));
+        assert_ne!(changed.hash(), block.hash());
+        assert_eq!(changed.state_root, block.state_root);
+    }
+}
@@ -0,0 +1,127 @@
+//! Gas pricing for the txs we create ourselves (proposer payout, mev-share refunds).
+//! Pricing them at exactly the block base fee is fine inside our block but makes them invalid as soon as they are
+//! simulated or included standalone on a later block (eg: rebroadcast after a missed slot) if the base fee goes up.
+//! The oracle can add headroom for the max base fee increase (12.5% per block) over a few blocks, none by default so
+//! the txs are priced as before unless configured.
+//!
+//! Inside our block the effective gas price is still base fee + priority fee and the priority fee goes back to us
+//! (we are the coinbase) so the payout math does not change. Only the upfront balance check (gas_limit * max fee) is
+//! stricter so the max fee is capped by what the sender can afford.
+
+use alloy_primitives::U256;
+
+/// EIP-1559 max base fee change per block is 1/8.
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u128 = 8;
+
+/// Default prices our txs at the base fee.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GasPriceOracle {
+    /// Our txs stay valid for this many blocks of max base fee increase.
+    pub base_fee_headroom_blocks: u32,
+    pub priority_fee_per_gas: u128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnTxGasPrice {
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+impl GasPriceOracle {
+    /// Highest base fee reachable base_fee_headroom_blocks after a block with base_fee.
+    pub fn max_base_fee(&self, base_fee: u128) -> u128 {
+        let mut res = base_fee;
+        for _ in 0..self.base_fee_headroom_blocks {
+            let increase = res.div_ceil(BASE_FEE_MAX_CHANGE_DENOMINATOR).max(1);
+            res = res.saturating_add(increase);
+        }
+        res
+    }
+
+    /// max_gas_cost: max upfront gas cost (gas_limit * max fee) the sender can afford (balance - tx value).
+    /// The max fee is never below base_fee so the tx is always valid for the current block.
+    pub fn gas_price(
+        &self,
+        base_fee: u128,
+        gas_limit: u64,
+        max_gas_cost: Option<U256>,
+    ) -> OwnTxGasPrice {
+        let mut max_fee_per_gas = self
+            .max_base_fee(base_fee)
+            .saturating_add(self.priority_fee_per_gas);
+        if let Some(max_gas_cost) = max_gas_cost {
+            if gas_limit > 0 {
+                let affordable = max_gas_cost / U256::from(gas_limit);
+                let affordable = u128::try_from(affordable).unwrap_or(u128::MAX);
+                max_fee_per_gas = max_fee_per_gas.min(affordable.max(base_fee));
+            }
+        }
+        OwnTxGasPrice {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: self
+                .priority_fee_per_gas
+                .min(max_fee_per_gas.saturating_sub(base_fee)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_base_fee() {
+        let oracle = |base_fee_headroom_blocks| GasPriceOracle {
+            base_fee_headroom_blocks,
+            priority_fee_per_gas: 0,
+        };
+        assert_eq!(oracle(0).max_base_fee(1000), 1000);
+        assert_eq!(oracle(1).max_base_fee(1000), 1125);
+        assert_eq!(oracle(2).max_base_fee(1000), 1266);
+        // always moves
+        assert_eq!(oracle(1).max_base_fee(1), 2);
+        assert_eq!(oracle(1).max_base_fee(u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn test_default_gas_price() {
+        assert_eq!(
+            GasPriceOracle::default().gas_price(1000, 21_000, None),
+            OwnTxGasPrice {
+                max_fee_per_gas: 1000,
+                max_priority_fee_per_gas: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_gas_price() {
+        let oracle = GasPriceOracle {
+            base_fee_headroom_blocks: 1,
+            priority_fee_per_gas: 10,
+        };
+        assert_eq!(
+            oracle.gas_price(1000, 21_000, None),
+            OwnTxGasPrice {
+                max_fee_per_gas: 1135,
+                max_priority_fee_per_gas: 10,
+            }
+        );
+        // capped by balance
+        assert_eq!(
+            oracle.gas_price(1000, 21_000, Some(U256::from(1005 * 21_000))),
+            OwnTxGasPrice {
+                max_fee_per_gas: 1005,
+                max_priority_fee_per_gas: 5,
+            }
+        );
+        // never below the base fee
+        assert_eq!(
+            oracle.gas_price(1000, 21_000, Some(U256::ZERO)),
+            OwnTxGasPrice {
+                max_fee_per_gas: 1000,
+                max_priority_fee_per_gas: 0,
+            }
+        );
+    }
+}
@@ -1,30 +1,65 @@
 pub mod block_orders;
+pub mod block_template;
 pub mod builders;
 pub mod built_block_trace;
-#[cfg(test)]
+pub mod bytecode_cache;
 pub mod conflict;
+pub mod conflict_cache;
+pub mod conflict_graph;
+pub mod conflict_resolver;
 pub mod evm_inspector;
+pub mod exposure_budget;
+pub mod extra_data;
 pub mod fmt;
+pub mod gas_price_oracle;
 pub mod order_commit;
+pub mod order_index;
+pub mod order_validity;
 pub mod payout_tx;
+pub mod recurrent_orders;
+pub mod relay_rejections;
+pub mod scratch;
 pub mod sim;
+pub mod sim_queue;
+pub mod standalone_profits;
+pub mod state_read_metrics;
 pub mod testing;
 pub mod tracers;
+pub mod victim_protection;
+pub mod withdrawals;
 use alloy_consensus::EMPTY_OMMER_ROOT_HASH;
-use alloy_primitives::{Address, Bytes, Sealable, U256};
+use alloy_primitives::{Address, Bytes, Sealable, B256, U256};
 pub use block_orders::BlockOrders;
+use block_template::BlockTemplateSource;
+use conflict_cache::ConflictCache;
 use eth_sparse_mpt::SparseTrieSharedCache;
+use exposure_budget::ExhaustedOrders;
 use reth_db::Database;
 use reth_primitives::BlockBody;
 use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
+use standalone_profits::StandaloneProfits;
+use victim_protection::VictimProtection;
 
 use crate::{
     primitives::{Order, OrderId, SimValue, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs},
-    roothash::{calculate_state_root, RootHashConfig, RootHashError},
-    utils::{a2r_withdrawal, calc_gas_limit, timestamp_as_u64, Signer},
+    roothash::{
+        calculate_state_root,
+        payment_proof::{generate_payment_proof, ProposerPaymentProof},
+        RootHashConfig, RootHashError, RootHashMode,
+    },
+    utils::{
+        a2r_withdrawal, calc_gas_limit,
+        clock::{system_clock, ClockRef},
+        timestamp_as_u64, Signer,
+    },
 };
 use ahash::HashSet;
-use alloy_eips::{calc_excess_blob_gas, eip7685::Requests, merge::BEACON_NONCE};
+use alloy_eips::{
+    calc_excess_blob_gas,
+    eip4844::{DATA_GAS_PER_BLOB, MAX_DATA_GAS_PER_BLOCK},
+    eip7685::Requests,
+    merge::BEACON_NONCE,
+};
 use alloy_rpc_types_beacon::events::PayloadAttributesEvent;
 use jsonrpsee::core::Serialize;
 use reth::{
@@ -57,11 +92,16 @@ use std::{
 use thiserror::Error;
 use time::OffsetDateTime;
 
-use self::tracers::SimulationTracer;
+use self::{
+    gas_price_oracle::GasPriceOracle,
+    tracers::SimulationTracer,
+    withdrawals::{
+        check_withdrawals, read_balances, withdrawal_balance_increments, WithdrawalsCheckError,
+    },
+};
 use crate::utils::default_cfg_env;
 pub use block_orders::*;
 pub use built_block_trace::*;
-#[cfg(test)]
 pub use conflict::*;
 pub use order_commit::*;
 pub use payout_tx::*;
@@ -82,9 +122,42 @@ pub struct BlockBuildingContext {
     pub extra_data: Vec<u8>,
     /// Excess blob gas calculated from the parent block header
     pub excess_blob_gas: Option<u64>,
+    /// Max blobs the proposer of the slot accepts (see [`crate::mev_boost::ValidatorPreferences`]).
+    /// None: protocol limit.
+    pub max_blob_count: Option<u64>,
+    /// Set for slots we are very unlikely to win (see [`crate::live_builder::slot_outcome_predictor`]).
+    /// Builders should spend as little as possible on them (eg: no exhaustive conflict search).
+    pub reduced_effort: bool,
+    /// Prices the txs we create (payouts, refunds).
+    pub gas_price_oracle: GasPriceOracle,
+    pub refund_settlement: RefundSettlementMode,
+    /// If set, sbundles that leave their user txs worse than alone are rejected (see [`victim_protection`]).
+    pub victim_protection: Option<VictimProtection>,
     /// Version of the EVM that we are going to use
     pub spec_id: SpecId,
     pub shared_sparse_mpt_cache: SparseTrieSharedCache,
+    /// Profits alone of the orders on top of attributes.parent (see [`standalone_profits`]), shared by every clone.
+    pub standalone_profits: StandaloneProfits,
+    /// Pair conflicts found so far (see [`conflict_cache`]), shared by every clone. The live builder shares one between
+    /// all its slots.
+    pub conflict_cache: ConflictCache,
+    /// Orders out of exposure budget (see [`exposure_budget`]), None if there's no budget. The live builder sets the one
+    /// of its budget.
+    pub exhausted_exposures: Option<ExhaustedOrders>,
+    /// Time source of the slot deadlines, the live builder one (see [`crate::utils::clock`]).
+    pub clock: ClockRef,
+}
+
+/// How mev-share refunds are paid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RefundSettlementMode {
+    /// A payout tx right after the sbundle.
+    #[default]
+    InBlock,
+    /// No payout tx, the refunds are owed to the users and paid later by a settlement tx from the builder wallet
+    /// (see [`crate::live_builder::block_output::refund_settlement`]).
+    /// The paid_kickbacks of the results are the owed refunds and they are already subtracted from the coinbase profit.
+    Deferred,
 }
 
 impl BlockBuildingContext {
@@ -161,8 +234,17 @@ impl BlockBuildingContext {
             blocklist,
             extra_data,
             excess_blob_gas,
+            max_blob_count: None,
+            reduced_effort: false,
+            gas_price_oracle: GasPriceOracle::default(),
+            refund_settlement: RefundSettlementMode::default(),
+            victim_protection: None,
             spec_id,
             shared_sparse_mpt_cache: Default::default(),
+            standalone_profits: Default::default(),
+            conflict_cache: Default::default(),
+            exhausted_exposures: None,
+            clock: system_clock(),
         })
     }
 
@@ -246,11 +328,30 @@ impl BlockBuildingContext {
             blocklist,
             extra_data: Vec::new(),
             excess_blob_gas: onchain_block.header.excess_blob_gas,
+            max_blob_count: None,
+            reduced_effort: false,
+            gas_price_oracle: GasPriceOracle::default(),
+            refund_settlement: RefundSettlementMode::default(),
+            victim_protection: None,
             spec_id,
             shared_sparse_mpt_cache: Default::default(),
+            standalone_profits: Default::default(),
+            conflict_cache: Default::default(),
+            exhausted_exposures: None,
+            clock: system_clock(),
         }
     }
 
+    /// Blob gas we can use on the block: protocol limit lowered by the proposer max_blob_count.
+    pub fn max_blob_gas_per_block(&self) -> u64 {
+        self.max_blob_count
+            .map_or(MAX_DATA_GAS_PER_BLOCK, |max_blob_count| {
+                max_blob_count
+                    .saturating_mul(DATA_GAS_PER_BLOB)
+                    .min(MAX_DATA_GAS_PER_BLOCK)
+            })
+    }
+
     /// Useless BlockBuildingContext for testing in contexts where we can't avoid having a BlockBuildingContext.
     pub fn dummy_for_testing() -> Self {
         let mut onchain_block: alloy_rpc_types::Block = Default::default();
@@ -388,9 +489,23 @@ pub enum ExecutionError {
     OrderError(#[from] OrderErr),
     #[error("Lower inserted value, before: {before:?}, inplace: {inplace:?}")]
     LowerInsertedValue { before: SimValue, inplace: SimValue },
+    #[error("Exposure budget exhausted, exposures: {exposures}")]
+    ExposureBudgetExhausted { exposures: usize },
+    #[error("Tx rejected by a relay: {tx:?}")]
+    RejectedByRelay { tx: B256 },
 }
 
 impl ExecutionError {
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ExecutionError::OrderError(err) => err.reason(),
+            ExecutionError::LowerInsertedValue { .. } => "lower_inserted_value",
+            ExecutionError::ExposureBudgetExhausted { .. } => "exposure_budget_exhausted",
+            ExecutionError::RejectedByRelay { .. } => "rejected_by_relay",
+        }
+    }
+
     /// If error is NonceTooHigh returns nonce of the transaction
     pub fn try_get_tx_too_high_error(&self, order: &Order) -> Option<(Address, u64)> {
         match self {
@@ -425,8 +540,14 @@ pub struct FinalizeResult {
     pub cached_reads: CachedReads,
     // sidecars for all txs in SealedBlock
     pub txs_blob_sidecars: Vec<Arc<BlobTransactionSidecar>>,
+    /// Canonical envelopes of the txs in SealedBlock, same buffers we got the txs in.
+    pub encoded_txs: Vec<Bytes>,
     /// The Pectra execution requests for this bid.
     pub execution_requests: Vec<Bytes>,
+    /// Only if RootHashConfig::generate_payment_proof.
+    pub payment_proof: Option<ProposerPaymentProof>,
+    /// Only if RootHashConfig::generate_block_template.
+    pub block_template_source: Option<BlockTemplateSource>,
 
     pub root_hash_time: Duration,
 }
@@ -435,6 +556,8 @@ pub struct FinalizeResult {
 pub enum FinalizeError {
     #[error("Root hash error: {0:?}")]
     RootHash(#[from] RootHashError),
+    #[error("Withdrawals check failed: {0}")]
+    Withdrawals(#[from] WithdrawalsCheckError),
     #[error("Other error: {0:?}")]
     Other(#[from] eyre::Report),
 }
@@ -444,7 +567,7 @@ impl FinalizeError {
     pub fn is_consistent_db_view_err(&self) -> bool {
         match self {
             FinalizeError::RootHash(root_hash) => root_hash.is_consistent_db_view_err(),
-            FinalizeError::Other(_) => false,
+            FinalizeError::Withdrawals(_) | FinalizeError::Other(_) => false,
         }
     }
 }
@@ -487,6 +610,18 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
                 BundleErr::NoSigner,
             ))));
         }
+        if let Some(exposures) = ctx
+            .exhausted_exposures
+            .as_ref()
+            .and_then(|exhausted| exhausted.get(&order.order.id()))
+        {
+            return Ok(Err(ExecutionError::ExposureBudgetExhausted { exposures }));
+        }
+        if let Some(tx) =
+            relay_rejections::rejected_tx(ctx.block(), ctx.attributes.parent, &order.order)
+        {
+            return Ok(Err(ExecutionError::RejectedByRelay { tx }));
+        }
 
         let mut fork = PartialBlockFork::new(state).with_tracer(&mut self.tracer);
         let rollback = fork.rollback_point();
@@ -568,9 +703,17 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
         let nonce = state
             .nonce(builder_signer.address)
             .map_err(CriticalCommitOrderError::Reth)?;
+        let balance = state
+            .balance(builder_signer.address)
+            .map_err(CriticalCommitOrderError::Reth)?;
+        let gas_price = ctx.gas_price_oracle.gas_price(
+            ctx.block_env.basefee.to(),
+            gas_limit,
+            Some(balance.saturating_sub(value)),
+        );
         let tx = create_payout_tx(
             ctx.chain_spec.as_ref(),
-            ctx.block_env.basefee,
+            gas_price,
             builder_signer,
             nonce,
             ctx.attributes.suggested_fee_recipient,
@@ -649,6 +792,11 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
 
         let (withdrawals_root, withdrawals) = {
             let mut db = state.new_db_ref();
+            let recipients: Vec<_> = withdrawal_balance_increments(&ctx.attributes.withdrawals)
+                .into_keys()
+                .collect();
+            let pre_balances = read_balances(db.as_mut(), recipients.iter().copied())
+                .map_err(|err| FinalizeError::Other(err.into()))?;
             let WithdrawalsOutcome {
                 withdrawals_root,
                 withdrawals,
@@ -659,6 +807,19 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
                 ctx.attributes.withdrawals.clone(),
             )
             .map_err(|err| FinalizeError::Other(err.into()))?;
+            let post_balances = read_balances(db.as_mut(), recipients)
+                .map_err(|err| FinalizeError::Other(err.into()))?;
+            check_withdrawals(
+                ctx.chain_spec.as_ref(),
+                ctx.attributes.timestamp,
+                &ctx.attributes.withdrawals,
+                withdrawals_root,
+                withdrawals
+                    .as_ref()
+                    .map(|withdrawals| withdrawals.as_slice()),
+                &pre_balances,
+                &post_balances,
+            )?;
             // merge all transitions into bundle state, this would apply the withdrawal balance changes
             // and 4788 contract call
             db.as_mut().merge_transitions(BundleRetention::Reverts);
@@ -687,6 +848,34 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
             .block_logs_bloom(block_number)
             .expect("Number is in range");
 
+        let payment_proof = if root_hash_config.generate_payment_proof
+            && !matches!(root_hash_config.mode, RootHashMode::SkipRootHash)
+        {
+            Some(
+                generate_payment_proof(
+                    &provider,
+                    ctx.attributes.parent,
+                    &execution_outcome,
+                    ctx.attributes.suggested_fee_recipient,
+                )
+                .map_err(|err| FinalizeError::Other(err.into()))?,
+            )
+        } else {
+            None
+        };
+
+        // the witness is generated only if the block is submitted
+        let block_template_source = (root_hash_config.generate_block_template
+            && !matches!(root_hash_config.mode, RootHashMode::SkipRootHash))
+        .then(|| {
+            BlockTemplateSource::new(
+                provider.clone(),
+                ctx.attributes.parent,
+                &execution_outcome,
+                &cached_reads,
+            )
+        });
+
         // calculate the state root
         let start = Instant::now();
         let state_root = calculate_state_root(
@@ -754,6 +943,12 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
             requests_hash,
         };
 
+        let encoded_txs = self
+            .executed_tx
+            .iter()
+            .map(|tx| tx.envelope_encoded_no_blobs())
+            .collect();
+
         // seal the block
         let block = Block {
             header,
@@ -772,8 +967,11 @@ impl<Tracer: SimulationTracer> PartialBlock<Tracer> {
             sealed_block: block.seal_slow(),
             cached_reads,
             txs_blob_sidecars,
+            encoded_txs,
             root_hash_time,
             execution_requests: requests.map(|er| er.take()).unwrap_or_default(),
+            payment_proof,
+            block_template_source,
         })
     }
 
@@ -858,6 +1056,16 @@ fn enforce_inplace_sim_result(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_max_blob_gas_per_block() {
+        let mut ctx = BlockBuildingContext::dummy_for_testing();
+        assert_eq!(ctx.max_blob_gas_per_block(), MAX_DATA_GAS_PER_BLOCK);
+        ctx.max_blob_count = Some(2);
+        assert_eq!(ctx.max_blob_gas_per_block(), 2 * DATA_GAS_PER_BLOB);
+        ctx.max_blob_count = Some(1000);
+        assert_eq!(ctx.max_blob_gas_per_block(), MAX_DATA_GAS_PER_BLOCK);
+    }
+
     #[test]
     fn test_enforce_inplace_sim_result_max_profit() {
         let sort = Sorting::MaxProfit;
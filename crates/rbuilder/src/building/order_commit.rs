@@ -1,8 +1,14 @@
 use super::{
     create_payout_tx, tracers::SimulationTracer, BlockBuildingContext, EstimatePayoutGasErr,
+    RefundSettlementMode,
 };
 use crate::{
-    building::estimate_payout_gas_limit,
+    building::{
+        bytecode_cache::{global_bytecode_cache, BytecodeCachedDatabaseRef},
+        estimate_payout_gas_limit,
+        state_read_metrics::TimedDatabaseRef,
+        victim_protection::{victim_txs, VictimTxOutcome},
+    },
     primitives::{
         Bundle, Order, OrderId, RefundConfig, ShareBundle, ShareBundleBody, ShareBundleInner,
         TransactionSignedEcRecoveredWithBlobs,
@@ -13,7 +19,7 @@ use crate::{
 use alloy_primitives::{Address, B256, U256};
 
 use alloy_consensus::{constants::KECCAK_EMPTY, Transaction};
-use alloy_eips::eip4844::{DATA_GAS_PER_BLOB, MAX_DATA_GAS_PER_BLOCK};
+use alloy_eips::eip4844::DATA_GAS_PER_BLOB;
 use reth::revm::{cached::CachedReads, database::StateProviderDatabase};
 use reth_errors::ProviderError;
 use reth_primitives::{transaction::FillTxEnv, Receipt};
@@ -26,7 +32,10 @@ use revm::{
 };
 
 use crate::building::evm_inspector::{RBuilderEVMInspector, UsedStateTrace};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use thiserror::Error;
 
 #[derive(Clone)]
@@ -79,7 +88,10 @@ impl BlockState {
     }
 
     pub fn new_db_ref(&mut self) -> BlockStateDBRef<impl Database<Error = ProviderError> + '_> {
-        let state_provider = StateProviderDatabase::new(&self.provider);
+        let state_provider = BytecodeCachedDatabaseRef::new(
+            TimedDatabaseRef::new(StateProviderDatabase::new(&self.provider)),
+            global_bytecode_cache(),
+        );
         let cachedb = WrapDatabaseRef(self.cached_reads.as_db(state_provider));
         let bundle_state = self.bundle_state.take().unwrap();
         let db = State::builder()
@@ -198,6 +210,30 @@ pub enum TransactionErr {
     GasLeft,
     #[error("Blob Gas left is too low")]
     BlobGasLeft,
+    #[error("Block {block} is past the private tx max block {max_block}")]
+    PastMaxBlock { block: u64, max_block: u64 },
+}
+
+impl TransactionErr {
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TransactionErr::InvalidTransaction(InvalidTransaction::NonceTooLow { .. }) => {
+                "nonce_too_low"
+            }
+            TransactionErr::InvalidTransaction(InvalidTransaction::NonceTooHigh { .. }) => {
+                "nonce_too_high"
+            }
+            TransactionErr::InvalidTransaction(InvalidTransaction::LackOfFundForMaxFee {
+                ..
+            }) => "insufficient_funds",
+            TransactionErr::InvalidTransaction(_) => "invalid_tx",
+            TransactionErr::Blocklist => "blocklist",
+            TransactionErr::GasLeft => "gas_left",
+            TransactionErr::BlobGasLeft => "blob_gas_left",
+            TransactionErr::PastMaxBlock { .. } => "past_max_block",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -259,6 +295,28 @@ pub enum BundleErr {
     IncorrectTimestamp { min: u64, max: u64, block: u64 },
     #[error("Mev-share without signer")]
     NoSigner,
+    #[error("Victim tx worse than alone: {0:?}")]
+    VictimTxWorsened(B256),
+}
+
+impl BundleErr {
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            BundleErr::InvalidTransaction(_, err) => err.reason(),
+            BundleErr::TransactionReverted(_) => "tx_reverted",
+            BundleErr::EmptyBundle => "empty_bundle",
+            BundleErr::TargetBlockIncorrect { .. } => "target_block_incorrect",
+            BundleErr::NotEnoughRefundForGas { .. } => "not_enough_refund_for_gas",
+            BundleErr::FailedToCommitPayoutTx { .. } => "payout_tx_failed",
+            BundleErr::EstimatePayoutGas(_) => "estimate_payout_gas",
+            BundleErr::PayoutTx(_) => "payout_tx_failed",
+            BundleErr::IncorrectRefundableElement(_) => "incorrect_refundable_element",
+            BundleErr::IncorrectTimestamp { .. } => "incorrect_timestamp",
+            BundleErr::NoSigner => "no_signer",
+            BundleErr::VictimTxWorsened(_) => "victim_tx_worsened",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -288,6 +346,17 @@ pub enum OrderErr {
     NegativeProfit(U256),
 }
 
+impl OrderErr {
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            OrderErr::Transaction(err) => err.reason(),
+            OrderErr::Bundle(err) => err.reason(),
+            OrderErr::NegativeProfit(_) => "negative_profit",
+        }
+    }
+}
+
 pub struct PartialBlockFork<'a, 'b, Tracer: SimulationTracer> {
     pub rollbacks: usize,
     pub state: &'a mut BlockState,
@@ -388,7 +457,7 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
     ) -> Result<Result<TransactionOk, TransactionErr>, CriticalCommitOrderError> {
         // Use blobs.len() instead of checking for tx type just in case in the future some other new txs have blobs
         let blob_gas_used = tx_with_blobs.blobs_sidecar.blobs.len() as u64 * DATA_GAS_PER_BLOB;
-        if cumulative_blob_gas_used + blob_gas_used > MAX_DATA_GAS_PER_BLOCK {
+        if cumulative_blob_gas_used + blob_gas_used > ctx.max_blob_gas_per_block() {
             return Ok(Err(TransactionErr::BlobGasLeft));
         }
 
@@ -646,6 +715,16 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
         cumulative_blob_gas_used: u64,
         allow_tx_skip: bool,
     ) -> Result<Result<BundleOk, BundleErr>, CriticalCommitOrderError> {
+        let victims_alone = match ctx.victim_protection {
+            Some(_) => self.victim_outcomes_alone(
+                &bundle.inner_bundle,
+                ctx,
+                cumulative_gas_used,
+                gas_reserved,
+                cumulative_blob_gas_used,
+            )?,
+            None => Vec::new(),
+        };
         let res = self.commit_share_bundle_inner(
             &bundle.inner_bundle,
             ctx,
@@ -663,6 +742,32 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
 
         let mut insert = res.bundle_ok;
 
+        if let Some(victim_protection) = &ctx.victim_protection {
+            for (hash, signer, alone) in &victims_alone {
+                // not included (eg: TxRevertBehavior::AllowedExcluded) can't be worse
+                let Some(idx) = insert.txs.iter().position(|tx| tx.hash() == *hash) else {
+                    continue;
+                };
+                let in_bundle = VictimTxOutcome::new(*signer, &insert.receipts[idx]);
+                if victim_protection.is_worsened(alone, &in_bundle) {
+                    return Ok(Err(BundleErr::VictimTxWorsened(*hash)));
+                }
+            }
+        }
+
+        if ctx.refund_settlement == RefundSettlementMode::Deferred {
+            if ctx.builder_signer.is_none() {
+                return Ok(Err(BundleErr::NoSigner));
+            }
+            // owed, paid later by the refund settlement
+            insert.paid_kickbacks.extend(
+                res.payouts_promissed
+                    .into_iter()
+                    .map(|(to, payout)| (to, payout.tx_value)),
+            );
+            return Ok(Ok(insert));
+        }
+
         // now pay all kickbacks
         for (
             to,
@@ -680,9 +785,15 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
             };
 
             let nonce = self.state.nonce(builder_signer.address)?;
+            let balance = self.state.balance(builder_signer.address)?;
+            let gas_price = ctx.gas_price_oracle.gas_price(
+                ctx.block_env.basefee.to(),
+                gas_limit,
+                Some(balance.saturating_sub(value)),
+            );
             let payout_tx = match create_payout_tx(
                 ctx.chain_spec.as_ref(),
-                ctx.block_env.basefee,
+                gas_price,
                 builder_signer,
                 nonce,
                 to,
@@ -735,6 +846,46 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
         Ok(Ok(insert))
     }
 
+    /// Executes every victim tx of bundle (see [`victim_txs`]) alone on top of the current state.
+    /// Victim txs failing alone are not included. Doesn't change the state and the executions are not traced.
+    fn victim_outcomes_alone(
+        &mut self,
+        bundle: &ShareBundleInner,
+        ctx: &BlockBuildingContext,
+        cumulative_gas_used: u64,
+        gas_reserved: u64,
+        cumulative_blob_gas_used: u64,
+    ) -> Result<Vec<(B256, Address, VictimTxOutcome)>, CriticalCommitOrderError> {
+        let tracer = self.tracer.take();
+        let mut outcomes = Vec::new();
+        let mut res = Ok(());
+        for tx in victim_txs(bundle) {
+            let rollback_point = self.rollback_point();
+            let tx_res = self.commit_tx(
+                tx,
+                ctx,
+                cumulative_gas_used,
+                gas_reserved,
+                cumulative_blob_gas_used,
+            );
+            self.rollback(rollback_point);
+            match tx_res {
+                Ok(Ok(tx_ok)) => outcomes.push((
+                    tx.hash(),
+                    tx.signer(),
+                    VictimTxOutcome::new(tx.signer(), &tx_ok.receipt),
+                )),
+                Ok(Err(_)) => {}
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+        }
+        self.tracer = tracer;
+        res.map(|_| outcomes)
+    }
+
     /// Only changes the state on Ok(Ok)
     fn commit_share_bundle_inner(
         &mut self,
@@ -778,11 +929,17 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
             original_order_ids: Vec::new(),
         };
         let coinbase_balance_before = self.state.balance(ctx.block_env.coinbase)?;
-        let refundable_elements = bundle
-            .refund
-            .iter()
-            .map(|r| (r.body_idx, r.percent))
-            .collect::<HashMap<_, _>>();
+        // several refunds can point to the same element
+        let refundable_elements =
+            bundle
+                .refund
+                .iter()
+                .fold(HashMap::<usize, usize>::new(), |mut elements, r| {
+                    *elements.entry(r.body_idx).or_default() += r.percent;
+                    elements
+                });
+        // refunds are only paid for the elements that made it to the block
+        let mut included_elements = HashSet::new();
         let mut refundable_profit = U256::from(0);
         let mut inner_payouts = HashMap::new();
         for (idx, body) in bundle.body.iter().enumerate() {
@@ -831,6 +988,7 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
                             insert.txs.push(res.tx);
                             update_nonce_list(&mut insert.nonces_updated, res.nonce_updated);
                             insert.receipts.push(res.receipt);
+                            included_elements.insert(idx);
                         }
                         Err(err) => {
                             // if optional transaction, skip
@@ -888,6 +1046,9 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
                                     })
                                     .or_insert(reserve.total_refundable_value);
                             }
+                            if !res.bundle_ok.txs.is_empty() {
+                                included_elements.insert(idx);
+                            }
                         }
                         Err(err) => {
                             if inner_bundle.can_skip {
@@ -908,6 +1069,9 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
                 } else {
                     return Ok(Err(BundleErr::IncorrectRefundableElement(idx)));
                 };
+            if !included_elements.contains(&idx) {
+                continue;
+            }
 
             let total_value = get_percent(refundable_profit, percent);
             for RefundConfig { address, percent } in refund_config {
@@ -1009,6 +1173,11 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
         let coinbase_balance_before = self.state.balance(ctx.block_env.coinbase)?;
         match order {
             Order::Tx(tx) => {
+                let block = ctx.block_env.number.to::<u64>();
+                if !tx.valid_for_block(block) {
+                    let max_block = tx.max_block.unwrap_or_default();
+                    return Ok(Err(TransactionErr::PastMaxBlock { block, max_block }.into()));
+                }
                 let res = self.commit_tx(
                     &tx.tx_with_blobs,
                     ctx,
@@ -1084,7 +1253,13 @@ impl<'a, 'b, Tracer: SimulationTracer> PartialBlockFork<'a, 'b, Tracer> {
                 )?;
                 match res {
                     Ok(ok) => {
-                        let coinbase_balance_after = self.state.balance(ctx.block_env.coinbase)?;
+                        let mut coinbase_balance_after =
+                            self.state.balance(ctx.block_env.coinbase)?;
+                        if ctx.refund_settlement == RefundSettlementMode::Deferred {
+                            // the owed refunds are not ours
+                            let owed = ok.paid_kickbacks.iter().map(|(_, value)| *value).sum();
+                            coinbase_balance_after = coinbase_balance_after.saturating_sub(owed);
+                        }
                         // Builder does sign txs in this code path, so do not allow negative coinbase
                         // profit.
                         let coinbase_profit = match coinbase_profit(
@@ -1158,3 +1333,44 @@ fn update_nonce_list_with_updates(
         update_nonce_list(nonces_updated, new_update);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::MempoolTx,
+    };
+    use reth_provider::StateProviderFactory;
+
+    #[test]
+    fn test_private_tx_past_max_block() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let ctx = test_chain.block_building_context();
+        let block = ctx.block_env.number.to::<u64>();
+        let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(NamedAddr::User(1), 0, 5))?;
+        let tx = MempoolTx::new(TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap());
+        let mut state = BlockState::new(test_chain.provider_factory().latest()?);
+        let mut fork = PartialBlockFork::new(&mut state);
+
+        let expired = Order::Tx(tx.clone().with_max_block(Some(block - 1)));
+        let err = fork
+            .commit_order(&expired, ctx, 0, 0, 0, false)?
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            OrderErr::Transaction(TransactionErr::PastMaxBlock { block: b, max_block })
+                if b == block && max_block == block - 1
+        ));
+
+        // its last block and no limit
+        let last_block = Order::Tx(tx.clone().with_max_block(Some(block)));
+        assert!(fork.commit_order(&last_block, ctx, 0, 0, 0, false)?.is_ok());
+        let mut state = BlockState::new(test_chain.provider_factory().latest()?);
+        let mut fork = PartialBlockFork::new(&mut state);
+        assert!(fork
+            .commit_order(&Order::Tx(tx), ctx, 0, 0, 0, false)?
+            .is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Dense per slot indexes for the orders so the quadratic parts of conflict detection and resolution work on
+//! vectors instead of hashing an [`OrderId`] on every lookup.
+//! An [`OrderIndex`] is only meaningful for the orders it was built from, indexes must not cross slots.
+
+use crate::primitives::OrderId;
+use ahash::HashMap;
+
+/// Interns OrderIds as consecutive u32 (first seen first).
+#[derive(Debug, Clone, Default)]
+pub struct OrderIndex {
+    indexes: HashMap<OrderId, u32>,
+    ids: Vec<OrderId>,
+}
+
+impl OrderIndex {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            indexes: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            ids: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Index of each id is its position (repeated ids keep the first one).
+    pub fn from_ids(ids: impl ExactSizeIterator<Item = OrderId>) -> Self {
+        let mut index = Self::with_capacity(ids.len());
+        for id in ids {
+            index.insert(id);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, id: OrderId) -> u32 {
+        if let Some(index) = self.indexes.get(&id) {
+            return *index;
+        }
+        let index = self.ids.len() as u32;
+        self.indexes.insert(id, index);
+        self.ids.push(id);
+        index
+    }
+
+    pub fn get(&self, id: &OrderId) -> Option<u32> {
+        self.indexes.get(id).copied()
+    }
+
+    pub fn id(&self, index: u32) -> OrderId {
+        self.ids[index as usize]
+    }
+
+    pub fn ids(&self) -> &[OrderId] {
+        &self.ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::order_id;
+
+    #[test]
+    fn test_order_index() {
+        let mut index = OrderIndex::default();
+        assert_eq!(index.insert(order_id(0x10)), 0);
+        assert_eq!(index.insert(order_id(0x20)), 1);
+        assert_eq!(index.insert(order_id(0x10)), 0);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&order_id(0x20)), Some(1));
+        assert_eq!(index.get(&order_id(0x30)), None);
+        assert_eq!(index.id(1), order_id(0x20));
+        assert_eq!(index.ids(), &[order_id(0x10), order_id(0x20)]);
+
+        let index = OrderIndex::from_ids([order_id(1), order_id(2), order_id(1)].into_iter());
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&order_id(2)), Some(1));
+    }
+}
@@ -0,0 +1,319 @@
+//! Cheap validity stage run on orders before the full (EVM) simulation.
+//! It only uses the tx fields and a couple of account reads so obviously invalid orders are rejected with a precise
+//! error without occupying a simulation worker.
+//!
+//! It must never reject an order the EVM would accept so:
+//! - Only txs that can't be dropped from the order are checked (all of them for mempool txs, non revertible ones for bundles).
+//! - Balance is only checked for mempool txs that don't depend on other orders since a bundle can fund its own txs.
+//!
+//! Signatures are not checked again, the signer was recovered from them when the order was decoded.
+//!
+//! Tx types not active on the chain are rejected before, at intake (see
+//! [`crate::live_builder::order_input::tx_type_forks`]).
+
+use crate::{
+    building::BlockBuildingContext,
+    primitives::{Order, TransactionSignedEcRecoveredWithBlobs},
+    utils::NonceCacheRef,
+};
+use alloy_primitives::{Address, TxHash, U256};
+use reth_errors::ProviderError;
+use revm::primitives::SpecId;
+use thiserror::Error;
+
+const TX_BASE_GAS: u64 = 21_000;
+const TX_CREATE_GAS: u64 = 32_000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_GAS: u64 = 16;
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+/// EIP-3860
+const INITCODE_WORD_GAS: u64 = 2;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum OrderValidityError {
+    #[error("Tx {tx:?} chain id {chain_id} does not match {expected}")]
+    WrongChainId {
+        tx: TxHash,
+        chain_id: u64,
+        expected: u64,
+    },
+    #[error("Tx {tx:?} gas limit {gas_limit} is below its intrinsic gas {intrinsic_gas}")]
+    IntrinsicGasTooLow {
+        tx: TxHash,
+        gas_limit: u64,
+        intrinsic_gas: u64,
+    },
+    #[error("Tx {tx:?} gas limit {gas_limit} is above the block gas limit {block_gas_limit}")]
+    GasLimitTooHigh {
+        tx: TxHash,
+        gas_limit: u64,
+        block_gas_limit: u64,
+    },
+    #[error("Tx {tx:?} max fee per gas {max_fee_per_gas} is below the base fee {base_fee}")]
+    FeeCapTooLow {
+        tx: TxHash,
+        max_fee_per_gas: u128,
+        base_fee: u128,
+    },
+    #[error("Tx {tx:?} nonce {nonce} is below the account nonce {account_nonce}")]
+    NonceTooLow {
+        tx: TxHash,
+        nonce: u64,
+        account_nonce: u64,
+    },
+    #[error("Tx {tx:?} sender {sender:?} balance {balance} can't pay the tx max cost {max_cost}")]
+    InsufficientBalance {
+        tx: TxHash,
+        sender: Address,
+        balance: U256,
+        max_cost: U256,
+    },
+}
+
+impl OrderValidityError {
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            OrderValidityError::WrongChainId { .. } => "wrong_chain_id",
+            OrderValidityError::IntrinsicGasTooLow { .. } => "intrinsic_gas_too_low",
+            OrderValidityError::GasLimitTooHigh { .. } => "gas_limit_too_high",
+            OrderValidityError::FeeCapTooLow { .. } => "fee_cap_too_low",
+            OrderValidityError::NonceTooLow { .. } => "nonce_too_low",
+            OrderValidityError::InsufficientBalance { .. } => "insufficient_balance",
+        }
+    }
+}
+
+/// Block fields the validity stage needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderValidityContext {
+    pub chain_id: u64,
+    pub base_fee: u128,
+    pub block_gas_limit: u64,
+    pub spec_id: SpecId,
+}
+
+impl OrderValidityContext {
+    pub fn new(ctx: &BlockBuildingContext) -> Self {
+        Self {
+            chain_id: ctx.chain_spec.chain.id(),
+            base_fee: ctx.block_env.basefee.to(),
+            block_gas_limit: ctx.block_env.gas_limit.to(),
+            spec_id: ctx.spec_id,
+        }
+    }
+}
+
+/// Yellow paper g0 plus EIP-2930 access list and EIP-3860 initcode costs.
+pub fn intrinsic_gas(tx: &TransactionSignedEcRecoveredWithBlobs, spec_id: SpecId) -> u64 {
+    let tx_inner = tx.internal_tx_unsecure();
+    let input = tx_inner.input();
+    let zero_bytes = input.iter().filter(|byte| **byte == 0).count() as u64;
+    let non_zero_bytes = input.len() as u64 - zero_bytes;
+    let mut gas =
+        TX_BASE_GAS + zero_bytes * TX_DATA_ZERO_GAS + non_zero_bytes * TX_DATA_NON_ZERO_GAS;
+    if tx.to().is_none() {
+        gas += TX_CREATE_GAS;
+        if SpecId::enabled(spec_id, SpecId::SHANGHAI) {
+            gas += INITCODE_WORD_GAS * (input.len() as u64).div_ceil(32);
+        }
+    }
+    if let Some(access_list) = tx_inner.access_list() {
+        for item in access_list.iter() {
+            gas += ACCESS_LIST_ADDRESS_GAS
+                + ACCESS_LIST_STORAGE_KEY_GAS * item.storage_keys.len() as u64;
+        }
+    }
+    gas
+}
+
+/// Checks that only need the tx itself.
+pub fn check_tx_validity(
+    tx: &TransactionSignedEcRecoveredWithBlobs,
+    ctx: &OrderValidityContext,
+) -> Result<(), OrderValidityError> {
+    let tx_hash = tx.hash();
+    let tx_inner = tx.internal_tx_unsecure();
+    if let Some(chain_id) = tx_inner.chain_id() {
+        if chain_id != ctx.chain_id {
+            return Err(OrderValidityError::WrongChainId {
+                tx: tx_hash,
+                chain_id,
+                expected: ctx.chain_id,
+            });
+        }
+    }
+    let gas_limit = tx_inner.gas_limit();
+    if gas_limit > ctx.block_gas_limit {
+        return Err(OrderValidityError::GasLimitTooHigh {
+            tx: tx_hash,
+            gas_limit,
+            block_gas_limit: ctx.block_gas_limit,
+        });
+    }
+    let intrinsic_gas = intrinsic_gas(tx, ctx.spec_id);
+    if gas_limit < intrinsic_gas {
+        return Err(OrderValidityError::IntrinsicGasTooLow {
+            tx: tx_hash,
+            gas_limit,
+            intrinsic_gas,
+        });
+    }
+    let max_fee_per_gas = tx_inner.max_fee_per_gas();
+    if max_fee_per_gas < ctx.base_fee {
+        return Err(OrderValidityError::FeeCapTooLow {
+            tx: tx_hash,
+            max_fee_per_gas,
+            base_fee: ctx.base_fee,
+        });
+    }
+    Ok(())
+}
+
+/// Max wei the tx can take from the sender.
+fn max_tx_cost(tx: &TransactionSignedEcRecoveredWithBlobs) -> U256 {
+    let tx_inner = tx.internal_tx_unsecure();
+    let gas_cost = U256::from(tx_inner.gas_limit()) * U256::from(tx_inner.max_fee_per_gas());
+    let blob_gas_cost = match (tx_inner.blob_gas_used(), tx_inner.max_fee_per_blob_gas()) {
+        (Some(blob_gas), Some(max_fee_per_blob_gas)) => {
+            U256::from(blob_gas) * U256::from(max_fee_per_blob_gas)
+        }
+        _ => U256::ZERO,
+    };
+    gas_cost + blob_gas_cost + tx.value()
+}
+
+/// Validity stage for a whole order.
+pub fn check_order_validity(
+    order: &Order,
+    ctx: &OrderValidityContext,
+    nonces: &NonceCacheRef,
+) -> Result<Result<(), OrderValidityError>, ProviderError> {
+    let mandatory_txs: Vec<_> = match order {
+        Order::Tx(tx) => vec![&tx.tx_with_blobs],
+        _ => order
+            .list_txs()
+            .into_iter()
+            .filter(|(_, can_revert)| !can_revert)
+            .map(|(tx, _)| tx)
+            .collect(),
+    };
+    for tx in mandatory_txs {
+        if let Err(err) = check_tx_validity(tx, ctx) {
+            return Ok(Err(err));
+        }
+        let account_nonce = nonces.nonce(tx.signer())?;
+        if tx.nonce() < account_nonce {
+            return Ok(Err(OrderValidityError::NonceTooLow {
+                tx: tx.hash(),
+                nonce: tx.nonce(),
+                account_nonce,
+            }));
+        }
+        if let Order::Tx(_) = order {
+            // With a higher nonce some other order executes first and may fund the sender.
+            if tx.nonce() == account_nonce {
+                let balance = nonces.balance(tx.signer())?;
+                let max_cost = max_tx_cost(tx);
+                if balance < max_cost {
+                    return Ok(Err(OrderValidityError::InsufficientBalance {
+                        tx: tx.hash(),
+                        sender: tx.signer(),
+                        balance,
+                        max_cost,
+                    }));
+                }
+            }
+        }
+    }
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::MempoolTx,
+        utils::NonceCache,
+    };
+
+    fn mempool_order(test_chain: &TestChainState, args: TxArgs) -> Order {
+        let tx = test_chain.sign_tx(args).unwrap();
+        Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let test_chain = TestChainState::new(BlockArgs::default()).unwrap();
+        let call = TransactionSignedEcRecoveredWithBlobs::new_no_blobs(
+            test_chain
+                .sign_tx(
+                    TxArgs::new(NamedAddr::User(0), 0)
+                        .to(NamedAddr::Dummy)
+                        .input(vec![0, 1, 2]),
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(intrinsic_gas(&call, SpecId::CANCUN), 21_000 + 4 + 2 * 16);
+        let create = TransactionSignedEcRecoveredWithBlobs::new_no_blobs(
+            test_chain
+                .sign_tx(TxArgs::new(NamedAddr::User(0), 0).input(vec![1; 33]))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            intrinsic_gas(&create, SpecId::CANCUN),
+            21_000 + 32_000 + 33 * 16 + 2 * 2
+        );
+        assert_eq!(
+            intrinsic_gas(&create, SpecId::MERGE),
+            21_000 + 32_000 + 33 * 16
+        );
+    }
+
+    #[test]
+    fn test_check_order_validity() {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11)).unwrap();
+        let block_ctx = test_chain.block_building_context();
+        let ctx = OrderValidityContext::new(block_ctx);
+        assert!(ctx.base_fee > 0);
+        let nonce_cache = NonceCache::new(
+            test_chain.provider_factory().clone(),
+            block_ctx.attributes.parent,
+        );
+        let nonces = nonce_cache.get_ref().unwrap();
+        let check = |args: TxArgs| {
+            check_order_validity(&mempool_order(&test_chain, args), &ctx, &nonces).unwrap()
+        };
+        let transfer = || {
+            TxArgs::new(NamedAddr::User(0), 0)
+                .to(NamedAddr::Dummy)
+                .max_fee_per_gas(ctx.base_fee)
+        };
+
+        assert_eq!(check(transfer()), Ok(()));
+        // depends on another order, can't check the balance
+        assert_eq!(check(transfer().nonce(1).value(u64::MAX)), Ok(()));
+        assert!(matches!(
+            check(transfer().gas_limit(20_000)),
+            Err(OrderValidityError::IntrinsicGasTooLow { .. })
+        ));
+        assert!(matches!(
+            check(transfer().gas_limit(ctx.block_gas_limit + 1)),
+            Err(OrderValidityError::GasLimitTooHigh { .. })
+        ));
+        assert!(matches!(
+            check(transfer().max_fee_per_gas(ctx.base_fee - 1)),
+            Err(OrderValidityError::FeeCapTooLow { .. })
+        ));
+        assert!(matches!(
+            check(transfer().value(u64::MAX)),
+            Err(OrderValidityError::InsufficientBalance { .. })
+        ));
+    }
+}
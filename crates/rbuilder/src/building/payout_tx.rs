@@ -1,6 +1,6 @@
 use crate::utils::Signer;
 
-use super::{BlockBuildingContext, BlockState};
+use super::{gas_price_oracle::OwnTxGasPrice, BlockBuildingContext, BlockState};
 use alloy_consensus::{constants::KECCAK_EMPTY, TxEip1559};
 use alloy_primitives::{Address, TxKind as TransactionKind, U256};
 use reth_chainspec::ChainSpec;
@@ -10,7 +10,7 @@ use revm_primitives::{EVMError, Env, ExecutionResult, TxEnv};
 
 pub fn create_payout_tx(
     chain_spec: &ChainSpec,
-    basefee: U256,
+    gas_price: OwnTxGasPrice,
     signer: &Signer,
     nonce: u64,
     to: Address,
@@ -21,8 +21,8 @@ pub fn create_payout_tx(
         chain_id: chain_spec.chain.id(),
         nonce,
         gas_limit,
-        max_fee_per_gas: basefee.to(),
-        max_priority_fee_per_gas: 0,
+        max_fee_per_gas: gas_price.max_fee_per_gas,
+        max_priority_fee_per_gas: gas_price.max_priority_fee_per_gas,
         to: TransactionKind::Call(to),
         value: U256::from(value),
         ..Default::default()
@@ -57,9 +57,13 @@ pub fn insert_test_payout_tx(
     // disable balance check so we can estimate the gas cost without having any funds
     cfg.disable_balance_check = true;
 
+    // balance check is disabled, price doesn't affect the estimation
+    let gas_price = ctx
+        .gas_price_oracle
+        .gas_price(ctx.block_env.basefee.to(), gas_limit, None);
     let tx = create_payout_tx(
         ctx.chain_spec.as_ref(),
-        ctx.block_env.basefee,
+        gas_price,
         builder_signer,
         nonce,
         to,
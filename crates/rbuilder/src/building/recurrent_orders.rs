@@ -0,0 +1,263 @@
+//! Detection of recurrent orders: some searchers send a functionally identical bundle every slot, only the nonces
+//! (and maybe the fees) change. Orders are matched by [`order_fingerprint`], which ignores nonces, fees, signatures
+//! and the target block, so what we learned from an occurrence (first time seen, how many slots...) is carried to the
+//! next ones.
+//!
+//! The parallel builder keeps one [`RecurrentOrders`] across slots: it also remembers the best ordering found for each
+//! conflict group (by [`group_fingerprint`]) so when the same group shows up on the next slot that ordering is replayed
+//! instead of searching again.
+
+use crate::primitives::{Order, OrderId};
+use alloy_primitives::{keccak256, B256};
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+/// Orders not seen for this many blocks are forgotten.
+pub const DEFAULT_MAX_BLOCK_GAP: u64 = 8;
+
+/// keccak of the order type, how many blocks (or seconds) it's valid for and the (signer, to, value, input,
+/// can_revert) of each tx.
+pub fn order_fingerprint(order: &Order) -> B256 {
+    let mut buf = Vec::new();
+    match order {
+        Order::Bundle(bundle) => {
+            buf.push(0);
+            let timestamps = bundle.min_timestamp.zip(bundle.max_timestamp);
+            buf.push(timestamps.is_some() as u8);
+            let window = timestamps.map_or(0, |(min, max)| max.saturating_sub(min));
+            buf.extend_from_slice(&window.to_be_bytes());
+        }
+        Order::Tx(tx) => {
+            buf.push(1);
+            buf.push(tx.max_block.is_some() as u8);
+        }
+        Order::ShareBundle(bundle) => {
+            buf.push(2);
+            buf.extend_from_slice(&bundle.max_block.saturating_sub(bundle.block).to_be_bytes());
+        }
+    }
+    for (tx, can_revert) in order.list_txs() {
+        buf.extend_from_slice(tx.signer().as_slice());
+        match tx.to() {
+            Some(to) => {
+                buf.push(1);
+                buf.extend_from_slice(to.as_slice());
+            }
+            None => buf.push(0),
+        }
+        buf.extend_from_slice(&tx.value().to_be_bytes::<32>());
+        let input = tx.internal_tx_unsecure().input();
+        buf.extend_from_slice(&(input.len() as u64).to_be_bytes());
+        buf.extend_from_slice(input);
+        buf.push(can_revert as u8);
+    }
+    keccak256(buf)
+}
+
+/// keccak of the sorted fingerprints of the orders of a group, so it doesn't depend on the order of the group.
+pub fn group_fingerprint(order_fingerprints: impl IntoIterator<Item = B256>) -> B256 {
+    let mut fingerprints: Vec<B256> = order_fingerprints.into_iter().collect();
+    fingerprints.sort_unstable();
+    let mut buf = Vec::with_capacity(fingerprints.len() * 32);
+    for fingerprint in fingerprints {
+        buf.extend_from_slice(fingerprint.as_slice());
+    }
+    keccak256(buf)
+}
+
+#[derive(Debug, Clone)]
+pub struct RecurrentOrderInfo {
+    pub first_seen_block: u64,
+    pub last_seen_block: u64,
+    /// Number of different blocks the order was seen for.
+    pub blocks_seen: u64,
+    pub last_order_id: OrderId,
+}
+
+impl RecurrentOrderInfo {
+    pub fn is_recurrent(&self) -> bool {
+        self.blocks_seen > 1
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RecurrentResolution {
+    block: u64,
+    /// Order fingerprints in the order they were executed.
+    ordering: Vec<B256>,
+}
+
+#[derive(Debug)]
+pub struct RecurrentOrders {
+    orders: HashMap<B256, RecurrentOrderInfo>,
+    /// By group fingerprint.
+    resolutions: HashMap<B256, RecurrentResolution>,
+    max_block_gap: u64,
+}
+
+pub type SharedRecurrentOrders = Arc<Mutex<RecurrentOrders>>;
+
+impl Default for RecurrentOrders {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BLOCK_GAP)
+    }
+}
+
+impl RecurrentOrders {
+    pub fn new(max_block_gap: u64) -> Self {
+        Self {
+            orders: HashMap::new(),
+            resolutions: HashMap::new(),
+            max_block_gap,
+        }
+    }
+
+    /// Records that order is used for block.
+    pub fn observe(&mut self, block: u64, order: &Order) -> &mut RecurrentOrderInfo {
+        let info = self
+            .orders
+            .entry(order_fingerprint(order))
+            .or_insert_with(|| RecurrentOrderInfo {
+                first_seen_block: block,
+                last_seen_block: block,
+                blocks_seen: 1,
+                last_order_id: order.id(),
+            });
+        if block > info.last_seen_block {
+            info.last_seen_block = block;
+            info.blocks_seen += 1;
+        }
+        info.last_order_id = order.id();
+        info
+    }
+
+    pub fn get(&self, order: &Order) -> Option<&RecurrentOrderInfo> {
+        self.orders.get(&order_fingerprint(order))
+    }
+
+    /// true if all the orders were seen on a previous block.
+    pub fn all_recurrent<'a>(&self, orders: impl IntoIterator<Item = &'a Order>) -> bool {
+        orders
+            .into_iter()
+            .all(|order| self.get(order).is_some_and(|info| info.is_recurrent()))
+    }
+
+    /// Remembers the best ordering (as order fingerprints) found on block for the group with fingerprint group.
+    pub fn record_resolution(&mut self, block: u64, group: B256, ordering: Vec<B256>) {
+        self.resolutions
+            .insert(group, RecurrentResolution { block, ordering });
+    }
+
+    /// Best ordering recorded for the group on a block before block.
+    pub fn previous_resolution(&self, block: u64, group: &B256) -> Option<&[B256]> {
+        self.resolutions
+            .get(group)
+            .filter(|resolution| resolution.block < block)
+            .map(|resolution| resolution.ordering.as_slice())
+    }
+
+    /// Forgets the orders and resolutions not seen in the last max_block_gap blocks.
+    pub fn prune(&mut self, block: u64) {
+        let max_block_gap = self.max_block_gap;
+        self.orders
+            .retain(|_, info| info.last_seen_block + max_block_gap >= block);
+        self.resolutions
+            .retain(|_, resolution| resolution.block + max_block_gap >= block);
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::{MempoolTx, TransactionSignedEcRecoveredWithBlobs},
+    };
+
+    #[test]
+    fn test_recurrent_orders() -> eyre::Result<()> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let order = |nonce: u64, value: u64| -> eyre::Result<Order> {
+            let tx = test_chain.sign_tx(TxArgs::new_send_to_coinbase(
+                NamedAddr::User(1),
+                nonce,
+                value,
+            ))?;
+            Ok(Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            )))
+        };
+        let (slot11, slot12, other) = (order(0, 5)?, order(1, 5)?, order(1, 7)?);
+        assert_ne!(slot11.id(), slot12.id());
+        assert_eq!(order_fingerprint(&slot11), order_fingerprint(&slot12));
+        assert_ne!(order_fingerprint(&slot11), order_fingerprint(&other));
+        // same tx as a private tx valid for some blocks
+        let mut private = slot11.clone();
+        if let Order::Tx(tx) = &mut private {
+            tx.max_block = Some(20);
+        }
+        assert_ne!(order_fingerprint(&slot11), order_fingerprint(&private));
+        // same tx in a bundle
+        let bundle = Order::Bundle(crate::primitives::Bundle {
+            block: 11,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: slot11
+                .list_txs()
+                .into_iter()
+                .map(|(tx, _)| tx.clone())
+                .collect(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        });
+        assert_ne!(order_fingerprint(&slot11), order_fingerprint(&bundle));
+
+        let mut recurrent_orders = RecurrentOrders::new(2);
+        assert!(!recurrent_orders.observe(11, &slot11).is_recurrent());
+        // resent on the same block
+        assert!(!recurrent_orders.observe(11, &slot11).is_recurrent());
+        let info = recurrent_orders.observe(12, &slot12);
+        assert!(info.is_recurrent());
+        assert_eq!(info.first_seen_block, 11);
+        assert_eq!(info.last_order_id, slot12.id());
+        assert!(!recurrent_orders.observe(12, &other).is_recurrent());
+        assert_eq!(recurrent_orders.len(), 2);
+
+        recurrent_orders.prune(14);
+        assert_eq!(recurrent_orders.len(), 2);
+        recurrent_orders.prune(15);
+        assert!(recurrent_orders.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recurrent_resolutions() {
+        let (a, b) = (B256::with_last_byte(1), B256::with_last_byte(2));
+        let group = group_fingerprint([a, b]);
+        assert_eq!(group, group_fingerprint([b, a]));
+        assert_ne!(group, group_fingerprint([a]));
+
+        let mut recurrent_orders = RecurrentOrders::new(2);
+        recurrent_orders.record_resolution(11, group, vec![b, a]);
+        // not reused on the block it was found for
+        assert_eq!(recurrent_orders.previous_resolution(11, &group), None);
+        assert_eq!(
+            recurrent_orders.previous_resolution(12, &group),
+            Some([b, a].as_slice())
+        );
+        recurrent_orders.prune(14);
+        assert!(recurrent_orders.previous_resolution(14, &group).is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! Txs a relay rejected our blocks for (see [`crate::mev_boost::Remediation::RebuildWithout`]).
+//! The relay submission records them by block (number and parent) and relay,
+//! [`PartialBlock::commit_order`](super::PartialBlock::commit_order) refuses the orders containing them
+//! ([`ExecutionError::RejectedByRelay`](super::ExecutionError::RejectedByRelay)) so the builders keep working without
+//! them instead of us giving up the slot. Blocks built before the rejection are not submitted again to the relays
+//! that rejected one of their txs ([`rejected_by_relay`]), the other relays still get them.
+//! The exclusion from new blocks ([`rejected_tx`]) is not per relay on purpose: we build a single block per iteration
+//! and submit it to every relay, so the only way to keep bidding on the relay that rejected the tx is to build
+//! without it for everyone. The relays that accepted the tx keep the blocks we already sent them (they are not
+//! resubmitted) so they lose at most the value of the tx on the blocks built after the rejection.
+//! Rejections are rare so commit_order only looks at the table (and the txs of the order) for the blocks that have any.
+
+use crate::primitives::{MevBoostRelayID, Order};
+use ahash::{HashMap, HashSet};
+use alloy_primitives::B256;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Block numbers for which we remember the rejected txs.
+const MAX_TRACKED_BLOCK_NUMBERS: usize = 4;
+
+/// (block number, parent hash) -> rejected tx -> relays that rejected it.
+type RejectedTxs = BTreeMap<(u64, B256), HashMap<B256, HashSet<MevBoostRelayID>>>;
+
+lazy_static! {
+    static ref REJECTED_TXS: Mutex<RejectedTxs> = Mutex::new(BTreeMap::new());
+}
+
+/// Highest block number with rejections (0 if none), lets [`rejected_tx`] skip the lock for the other blocks.
+static LAST_REJECTED_BLOCK: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_rejected_tx(
+    block_number: u64,
+    parent_hash: B256,
+    relay: &MevBoostRelayID,
+    tx_hash: B256,
+) {
+    let mut rejected_txs = REJECTED_TXS.lock();
+    rejected_txs
+        .entry((block_number, parent_hash))
+        .or_default()
+        .entry(tx_hash)
+        .or_default()
+        .insert(relay.clone());
+    let newest = rejected_txs
+        .last_key_value()
+        .map_or(block_number, |((newest, _), _)| *newest);
+    rejected_txs
+        .retain(|(block_number, _), _| *block_number + MAX_TRACKED_BLOCK_NUMBERS as u64 > newest);
+    LAST_REJECTED_BLOCK.fetch_max(block_number, Ordering::Relaxed);
+}
+
+/// Tracked blocks can only be at or below LAST_REJECTED_BLOCK, the rest of the blocks never take the lock.
+fn may_have_rejections(block_number: u64) -> bool {
+    block_number <= LAST_REJECTED_BLOCK.load(Ordering::Relaxed)
+}
+
+/// Some(tx hash) if order has a tx a relay rejected for the block.
+pub fn rejected_tx(block_number: u64, parent_hash: B256, order: &Order) -> Option<B256> {
+    if !may_have_rejections(block_number) {
+        return None;
+    }
+    let rejected_txs = REJECTED_TXS.lock();
+    let rejected_txs = rejected_txs.get(&(block_number, parent_hash))?;
+    order
+        .list_txs()
+        .into_iter()
+        .map(|(tx, _)| tx.hash())
+        .find(|tx_hash| rejected_txs.contains_key(tx_hash))
+}
+
+/// Serializes the tests using the (global) rejections and clears them when taken and when dropped.
+#[cfg(test)]
+pub(crate) struct RejectionsTestGuard(#[allow(dead_code)] parking_lot::MutexGuard<'static, ()>);
+
+#[cfg(test)]
+lazy_static! {
+    static ref REJECTIONS_TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(test)]
+impl RejectionsTestGuard {
+    pub(crate) fn new() -> Self {
+        let guard = REJECTIONS_TEST_LOCK.lock();
+        reset();
+        Self(guard)
+    }
+}
+
+#[cfg(test)]
+impl Drop for RejectionsTestGuard {
+    fn drop(&mut self) {
+        reset();
+    }
+}
+
+#[cfg(test)]
+fn reset() {
+    REJECTED_TXS.lock().clear();
+    LAST_REJECTED_BLOCK.store(0, Ordering::Relaxed);
+}
+
+/// If relay rejected one of the txs of a block (block_number, parent_hash) with these txs.
+pub fn rejected_by_relay<'a>(
+    block_number: u64,
+    parent_hash: B256,
+    relay: &MevBoostRelayID,
+    tx_hashes: impl IntoIterator<Item = &'a B256>,
+) -> bool {
+    if !may_have_rejections(block_number) {
+        return false;
+    }
+    let rejected_txs = REJECTED_TXS.lock();
+    let Some(rejected_txs) = rejected_txs.get(&(block_number, parent_hash)) else {
+        return false;
+    };
+    tx_hashes.into_iter().any(|tx_hash| {
+        rejected_txs
+            .get(tx_hash)
+            .is_some_and(|relays| relays.contains(relay))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{primitives::MempoolTx, utils::test_utils::tx};
+
+    #[test]
+    fn test_rejected_txs() {
+        let _guard = RejectionsTestGuard::new();
+        let block = 20_000_000;
+        let parent = B256::with_last_byte(1);
+        let (relay, other_relay) = ("relay".to_string(), "other".to_string());
+        let (rejected, ok) = (tx(1), tx(2));
+        let rejected_order = Order::Tx(MempoolTx::new(rejected.clone()));
+        let ok_order = Order::Tx(MempoolTx::new(ok.clone()));
+        assert_eq!(rejected_tx(block, parent, &rejected_order), None);
+
+        record_rejected_tx(block, parent, &relay, rejected.hash());
+        assert_eq!(
+            rejected_tx(block, parent, &rejected_order),
+            Some(rejected.hash())
+        );
+        assert_eq!(rejected_tx(block, parent, &ok_order), None);
+        assert_eq!(rejected_tx(block + 1, parent, &rejected_order), None);
+        // same number on another parent
+        assert_eq!(
+            rejected_tx(block, B256::with_last_byte(2), &rejected_order),
+            None
+        );
+
+        let block_txs = [ok.hash(), rejected.hash()];
+        assert!(rejected_by_relay(block, parent, &relay, &block_txs));
+        assert!(!rejected_by_relay(block, parent, &other_relay, &block_txs));
+        assert!(!rejected_by_relay(block, parent, &relay, &[ok.hash()]));
+
+        for later_block in block + 1..=block + MAX_TRACKED_BLOCK_NUMBERS as u64 {
+            record_rejected_tx(later_block, parent, &relay, rejected.hash());
+        }
+        assert_eq!(rejected_tx(block, parent, &rejected_order), None);
+        assert_eq!(
+            LAST_REJECTED_BLOCK.load(Ordering::Relaxed),
+            block + MAX_TRACKED_BLOCK_NUMBERS as u64
+        );
+    }
+}
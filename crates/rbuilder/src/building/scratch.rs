@@ -0,0 +1,165 @@
+//! Scratch memory for hot execution data structures.
+//! Every order execution fills a few hashmaps (eg: [`UsedStateTrace`]) that are thrown away right after, reallocating
+//! them on each order is a lot of allocation churn so instead we keep, per thread, a pool of cleared containers that keep
+//! their capacity.
+//! Pools are slot scoped: [`reset_scratch_pools`] (called on every new slot) makes every thread drop its pooled memory
+//! on the next use so a big slot does not pin memory forever.
+
+use super::evm_inspector::UsedStateTrace;
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Max number of pooled items per thread.
+const MAX_POOLED_ITEMS: usize = 8;
+/// Items that grew above this capacity are not pooled.
+const MAX_POOLED_ITEM_CAPACITY: usize = 16 * 1024;
+
+static SCRATCH_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static USED_STATE_TRACE_POOL: RefCell<ScratchPool<UsedStateTrace>> =
+        RefCell::new(ScratchPool::new(MAX_POOLED_ITEMS, MAX_POOLED_ITEM_CAPACITY));
+}
+
+/// Data structure that can be cleared keeping its allocated memory.
+pub trait Scratch: Default {
+    fn clear(&mut self);
+    fn capacity(&self) -> usize;
+}
+
+impl Scratch for UsedStateTrace {
+    fn clear(&mut self) {
+        UsedStateTrace::clear(self)
+    }
+
+    fn capacity(&self) -> usize {
+        UsedStateTrace::capacity(self)
+    }
+}
+
+/// Pool of cleared items ready to be reused.
+#[derive(Debug)]
+pub struct ScratchPool<T> {
+    free: Vec<T>,
+    max_items: usize,
+    max_item_capacity: usize,
+    generation: u64,
+}
+
+impl<T: Scratch> ScratchPool<T> {
+    pub fn new(max_items: usize, max_item_capacity: usize) -> Self {
+        Self {
+            free: Vec::new(),
+            max_items,
+            max_item_capacity,
+            generation: SCRATCH_GENERATION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns a cleared item, reusing a pooled one if available.
+    pub fn take(&mut self) -> T {
+        self.check_generation();
+        self.free.pop().unwrap_or_default()
+    }
+
+    /// Gives back an item so it can be reused by a future [`ScratchPool::take`].
+    pub fn recycle(&mut self, mut item: T) {
+        self.check_generation();
+        if self.free.len() >= self.max_items || item.capacity() > self.max_item_capacity {
+            return;
+        }
+        item.clear();
+        self.free.push(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    fn check_generation(&mut self) {
+        let generation = SCRATCH_GENERATION.load(Ordering::Relaxed);
+        if generation != self.generation {
+            self.free.clear();
+            self.generation = generation;
+        }
+    }
+}
+
+/// Makes all the pools drop their memory the next time they are used.
+pub fn reset_scratch_pools() {
+    SCRATCH_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Cleared [`UsedStateTrace`] from this thread's pool.
+pub fn take_used_state_trace() -> UsedStateTrace {
+    USED_STATE_TRACE_POOL.with(|pool| pool.borrow_mut().take())
+}
+
+/// Gives back a [`UsedStateTrace`] to this thread's pool.
+pub fn recycle_used_state_trace(trace: UsedStateTrace) {
+    USED_STATE_TRACE_POOL.with(|pool| pool.borrow_mut().recycle(trace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::evm_inspector::SlotKey;
+    use alloy_primitives::{Address, B256};
+
+    fn used_trace(slots: usize) -> UsedStateTrace {
+        let mut trace = UsedStateTrace::default();
+        for i in 0..slots {
+            trace.read_slot_values.insert(
+                SlotKey {
+                    address: Address::ZERO,
+                    key: B256::with_last_byte(i as u8),
+                },
+                B256::ZERO,
+            );
+        }
+        trace.created_contracts.push(Address::ZERO);
+        trace
+    }
+
+    #[test]
+    fn test_pool_reuses_cleared_items() {
+        let mut pool = ScratchPool::<UsedStateTrace>::new(2, 1024);
+        pool.recycle(used_trace(10));
+        assert_eq!(pool.len(), 1);
+
+        let trace = pool.take();
+        assert!(pool.is_empty());
+        assert_eq!(trace, UsedStateTrace::default());
+        // memory is kept
+        assert!(trace.read_slot_values.capacity() >= 10);
+
+        // pool is capped
+        pool.recycle(used_trace(1));
+        pool.recycle(used_trace(1));
+        pool.recycle(used_trace(1));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_pool_drops_big_items() {
+        let mut pool = ScratchPool::<UsedStateTrace>::new(2, 16);
+        pool.recycle(used_trace(100));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_reset_drops_pooled_items() {
+        let mut pool = ScratchPool::<UsedStateTrace>::new(2, 1024);
+        pool.recycle(used_trace(1));
+        // same as a reset_scratch_pools without affecting other tests.
+        pool.generation = pool.generation.wrapping_sub(1);
+        assert_eq!(pool.take().read_slot_values.capacity(), 0);
+        assert!(pool.is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use super::{
+    order_validity::{check_order_validity, OrderValidityContext, OrderValidityError},
+    sim_queue::{SimQueue, SimQueueWeights},
     tracers::{AccumulatorSimulationTracer, SimulationTracer},
     OrderErr, PartialBlockFork,
 };
 use crate::{
     building::{BlockBuildingContext, BlockState, CriticalCommitOrderError},
-    primitives::{Order, OrderId, SimValue, SimulatedOrder},
+    primitives::{ExecutionCost, Order, OrderId, SimValue, SimulatedOrder},
     utils::{NonceCache, NonceCacheRef},
 };
 use ahash::{HashMap, HashSet};
@@ -76,7 +78,12 @@ pub struct SimTree<P> {
     pending_orders: HashMap<OrderId, PendingOrder>,
     pending_nonces: HashMap<NonceKey, Vec<OrderId>>,
 
-    ready_orders: Vec<SimulationRequest>,
+    ready_orders: SimQueue,
+
+    /// If set orders go through the validity stage (see [`super::order_validity`]) before waiting for nonces.
+    validity_ctx: Option<OrderValidityContext>,
+    /// Orders rejected by the validity stage since the last take_rejected_orders.
+    rejected_orders: Vec<(OrderId, OrderValidityError)>,
 }
 
 #[derive(Debug)]
@@ -98,15 +105,43 @@ where
             sims_that_update_one_nonce: HashMap::default(),
             pending_orders: HashMap::default(),
             pending_nonces: HashMap::default(),
-            ready_orders: Vec::default(),
+            ready_orders: SimQueue::default(),
+            validity_ctx: None,
+            rejected_orders: Vec::new(),
+        }
+    }
+
+    pub fn with_validity_check(self, validity_ctx: OrderValidityContext) -> Self {
+        Self {
+            validity_ctx: Some(validity_ctx),
+            ..self
         }
     }
 
+    /// Weights of the order classes when popping ready orders (see [`super::sim_queue`]).
+    pub fn with_queue_weights(self, weights: SimQueueWeights) -> Self {
+        Self {
+            ready_orders: SimQueue::new(weights),
+            ..self
+        }
+    }
+
+    pub fn take_rejected_orders(&mut self) -> Vec<(OrderId, OrderValidityError)> {
+        std::mem::take(&mut self.rejected_orders)
+    }
+
     fn push_order(&mut self, order: Order, nonces: &NonceCacheRef) -> Result<(), ProviderError> {
         if self.pending_orders.contains_key(&order.id()) {
             return Ok(());
         }
 
+        if let Some(validity_ctx) = &self.validity_ctx {
+            if let Err(err) = check_order_validity(&order, validity_ctx, nonces)? {
+                self.rejected_orders.push((order.id(), err));
+                return Ok(());
+            }
+        }
+
         let order_nonce_state = self.get_order_nonce_state(&order, nonces)?;
 
         match order_nonce_state {
@@ -215,8 +250,17 @@ where
     }
 
     pub fn pop_simulation_tasks(&mut self, limit: usize) -> Vec<SimulationRequest> {
-        let limit = min(limit, self.ready_orders.len());
-        self.ready_orders.drain(..limit).collect()
+        self.ready_orders.pop_many(limit)
+    }
+
+    /// Gives back a popped task we couldn't send for simulation yet, it goes after the ready orders of its class.
+    pub fn requeue_simulation_task(&mut self, request: SimulationRequest) {
+        self.ready_orders.push(request);
+    }
+
+    /// Orders ready to be simulated not popped yet.
+    pub fn ready_orders_len(&self) -> usize {
+        self.ready_orders.len()
     }
 
     // we don't really need state here because nonces are cached but its smaller if we reuse pending state fn
@@ -403,21 +447,34 @@ where
 }
 
 /// Prepares context (fork + tracer) and calls simulate_order_using_fork
+/// Results of orders without parents are recorded in ctx.standalone_profits so state must be the state of
+/// ctx.attributes.parent.
 pub fn simulate_order(
     parent_orders: Vec<Order>,
     order: Order,
     ctx: &BlockBuildingContext,
     state: &mut BlockState,
 ) -> Result<OrderSimResultWithGas, CriticalCommitOrderError> {
-    let mut tracer = AccumulatorSimulationTracer::new();
+    let (parent_orders_len, order_id) = (parent_orders.len(), order.id());
+    let mut tracer = AccumulatorSimulationTracer::with_scratch();
     let mut fork = PartialBlockFork::new(state).with_tracer(&mut tracer);
     let rollback_point = fork.rollback_point();
     let sim_res = simulate_order_using_fork(parent_orders, order, ctx, &mut fork);
     fork.rollback(rollback_point);
+    let gas_used = tracer.used_gas;
+    tracer.recycle();
     let sim_res = sim_res?;
+    if parent_orders_len == 0 {
+        let profit = match &sim_res {
+            OrderSimResult::Success(sim_order, _) => Some(sim_order.sim_value.coinbase_profit),
+            OrderSimResult::Failed(_) => None,
+        };
+        ctx.standalone_profits
+            .insert(ctx.block_env.coinbase, order_id, profit);
+    }
     Ok(OrderSimResultWithGas {
         result: sim_res,
-        gas_used: tracer.used_gas,
+        gas_used,
     })
 }
 
@@ -452,7 +509,9 @@ pub fn simulate_order_using_fork<Tracer: SimulationTracer>(
     }
 
     // simulate
+    let start = Instant::now();
     let result = fork.commit_order(&order, ctx, gas_used, 0, blob_gas_used, true)?;
+    let evm_time = start.elapsed();
     match result {
         Ok(res) => {
             let sim_value = SimValue::new(
@@ -462,12 +521,14 @@ pub fn simulate_order_using_fork<Tracer: SimulationTracer>(
                 res.paid_kickbacks,
             );
             let new_nonces = res.nonces_updated.into_iter().collect::<Vec<_>>();
+            let execution_cost = ExecutionCost::new(evm_time, res.used_state_trace.as_ref());
             Ok(OrderSimResult::Success(
                 SimulatedOrder {
                     order,
                     sim_value,
                     prev_order,
                     used_state_trace: res.used_state_trace,
+                    execution_cost,
                 },
                 new_nonces,
             ))
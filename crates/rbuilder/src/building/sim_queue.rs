@@ -0,0 +1,266 @@
+//! Queue of the simulation requests ready to run (see [`super::sim::SimTree`]).
+//! Requests are split in [`OrderClass`]es and served with weighted round robin so a flood of one class (eg: bundles)
+//! can't starve the others (eg: the mempool txs we need for backfill): every class with queued requests gets at least
+//! weight / (sum of the weights of the classes with queued requests) of the popped requests.
+//! Inside a class requests are served in arrival order.
+//! A class with weight 0 is only served when the classes with weight > 0 are empty.
+
+use super::sim::SimulationRequest;
+use crate::primitives::Order;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderClass {
+    Bundle,
+    ShareBundle,
+    MempoolTx,
+}
+
+const CLASSES: usize = 3;
+
+impl OrderClass {
+    pub fn of(order: &Order) -> Self {
+        match order {
+            Order::Bundle(_) => OrderClass::Bundle,
+            Order::ShareBundle(_) => OrderClass::ShareBundle,
+            Order::Tx(_) => OrderClass::MempoolTx,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            OrderClass::Bundle => 0,
+            OrderClass::ShareBundle => 1,
+            OrderClass::MempoolTx => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimQueueWeights {
+    pub bundles: u32,
+    pub share_bundles: u32,
+    pub mempool_txs: u32,
+}
+
+impl Default for SimQueueWeights {
+    /// mempool txs get at least 1/4 of the simulations when everything is queued.
+    fn default() -> Self {
+        Self {
+            bundles: 2,
+            share_bundles: 1,
+            mempool_txs: 1,
+        }
+    }
+}
+
+impl SimQueueWeights {
+    fn as_array(&self) -> [u32; CLASSES] {
+        [self.bundles, self.share_bundles, self.mempool_txs]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimQueue {
+    queues: [VecDeque<SimulationRequest>; CLASSES],
+    weights: [u32; CLASSES],
+    /// Requests the current class can still pop in its turn.
+    credits: u32,
+    current: usize,
+}
+
+impl Default for SimQueue {
+    fn default() -> Self {
+        Self::new(SimQueueWeights::default())
+    }
+}
+
+impl SimQueue {
+    pub fn new(weights: SimQueueWeights) -> Self {
+        let weights = weights.as_array();
+        Self {
+            queues: Default::default(),
+            weights,
+            credits: weights[0],
+            current: 0,
+        }
+    }
+
+    pub fn push(&mut self, request: SimulationRequest) {
+        self.queues[OrderClass::of(&request.order).index()].push_back(request);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|queue| queue.is_empty())
+    }
+
+    pub fn class_len(&self, class: OrderClass) -> usize {
+        self.queues[class.index()].len()
+    }
+
+    pub fn pop(&mut self) -> Option<SimulationRequest> {
+        let queued_weight: u32 = (0..CLASSES)
+            .filter(|class| !self.queues[*class].is_empty())
+            .map(|class| self.weights[class])
+            .sum();
+        if queued_weight == 0 {
+            // empty or only classes with weight 0
+            return self.queues.iter_mut().find_map(|queue| queue.pop_front());
+        }
+        // ends: some queued class has weight > 0 so we get to it with credits in < CLASSES turns
+        loop {
+            if self.credits > 0 {
+                if let Some(request) = self.queues[self.current].pop_front() {
+                    self.credits -= 1;
+                    return Some(request);
+                }
+            }
+            self.current = (self.current + 1) % CLASSES;
+            self.credits = self.weights[self.current];
+        }
+    }
+
+    pub fn pop_many(&mut self, limit: usize) -> Vec<SimulationRequest> {
+        let mut requests = Vec::with_capacity(limit.min(self.len()));
+        while requests.len() < limit {
+            match self.pop() {
+                Some(request) => requests.push(request),
+                None => break,
+            }
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        primitives::{
+            Bundle, MempoolTx, ShareBundle, ShareBundleBody, ShareBundleInner, ShareBundleTx,
+            TransactionSignedEcRecoveredWithBlobs, TxRevertBehavior,
+        },
+    };
+
+    fn requests() -> eyre::Result<(SimulationRequest, SimulationRequest, SimulationRequest)> {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11))?;
+        let tx = TransactionSignedEcRecoveredWithBlobs::new_no_blobs(
+            test_chain.sign_tx(TxArgs::new_send_to_coinbase(NamedAddr::User(1), 0, 5))?,
+        )
+        .unwrap();
+        let request = |order: Order| SimulationRequest {
+            id: 0,
+            order,
+            parents: Vec::new(),
+        };
+        let bundle = Order::Bundle(Bundle {
+            block: 11,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: vec![tx.clone()],
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        });
+        let share_bundle = Order::ShareBundle(ShareBundle {
+            hash: Default::default(),
+            block: 11,
+            max_block: 11,
+            inner_bundle: ShareBundleInner {
+                body: vec![ShareBundleBody::Tx(ShareBundleTx {
+                    tx: tx.clone(),
+                    revert_behavior: TxRevertBehavior::NotAllowed,
+                })],
+                refund: Vec::new(),
+                refund_config: Vec::new(),
+                can_skip: false,
+                original_order_id: None,
+            },
+            signer: None,
+            replacement_data: None,
+            original_orders: Vec::new(),
+            privacy_hints: Vec::new(),
+            metadata: Default::default(),
+        });
+        Ok((
+            request(bundle),
+            request(share_bundle),
+            request(Order::Tx(MempoolTx::new(tx))),
+        ))
+    }
+
+    fn classes(requests: &[SimulationRequest]) -> Vec<OrderClass> {
+        requests
+            .iter()
+            .map(|request| OrderClass::of(&request.order))
+            .collect()
+    }
+
+    #[test]
+    fn test_weighted_fair_queue() -> eyre::Result<()> {
+        let (bundle, share_bundle, tx) = requests()?;
+        let mut queue = SimQueue::new(SimQueueWeights {
+            bundles: 2,
+            share_bundles: 1,
+            mempool_txs: 1,
+        });
+        // a flood of bundles before the tx
+        for _ in 0..100 {
+            queue.push(bundle.clone());
+        }
+        queue.push(share_bundle.clone());
+        queue.push(tx.clone());
+        assert_eq!(queue.len(), 102);
+        assert_eq!(
+            classes(&queue.pop_many(4)),
+            vec![
+                OrderClass::Bundle,
+                OrderClass::Bundle,
+                OrderClass::ShareBundle,
+                OrderClass::MempoolTx
+            ]
+        );
+        // only bundles left
+        assert_eq!(classes(&queue.pop_many(3)), vec![OrderClass::Bundle; 3]);
+        queue.push(tx.clone());
+        let popped = classes(&queue.pop_many(3));
+        assert!(popped.contains(&OrderClass::MempoolTx));
+        assert_eq!(queue.class_len(OrderClass::Bundle), 100 - 2 - 3 - 2);
+        assert_eq!(queue.pop_many(1000).len(), 100 - 7);
+        assert!(queue.is_empty());
+        assert!(queue.pop().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_weight_class() -> eyre::Result<()> {
+        let (bundle, _, tx) = requests()?;
+        let mut queue = SimQueue::new(SimQueueWeights {
+            bundles: 1,
+            share_bundles: 1,
+            mempool_txs: 0,
+        });
+        queue.push(tx.clone());
+        queue.push(bundle.clone());
+        queue.push(bundle.clone());
+        assert_eq!(
+            classes(&queue.pop_many(3)),
+            vec![
+                OrderClass::Bundle,
+                OrderClass::Bundle,
+                OrderClass::MempoolTx
+            ]
+        );
+        Ok(())
+    }
+}
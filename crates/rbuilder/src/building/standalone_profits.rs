@@ -0,0 +1,86 @@
+//! Profit of the orders executed alone on top of the parent block of a slot.
+//! The same number is needed by the simulation (orders without parents), conflict detection ([`super::find_conflict_slow`]
+//! and friends), conflict resolution (set pruning) and the late order fast path, so it's computed once per slot and
+//! shared via [`BlockBuildingContext::standalone_profits`].
+//! Coinbase profit depends on the coinbase and ctx clones can change it (eg: use_suggested_fee_recipient_as_coinbase)
+//! so profits are kept per coinbase.
+
+use super::{BlockBuildingContext, BlockState, PartialBlockFork};
+use crate::primitives::{Order, OrderId};
+use alloy_primitives::{Address, U256};
+use dashmap::DashMap;
+use reth_provider::StateProvider;
+use std::sync::Arc;
+
+/// Cheap to clone, clones share the table.
+#[derive(Debug, Clone, Default)]
+pub struct StandaloneProfits {
+    /// (coinbase, order) -> profit, None: the order fails alone.
+    /// Sharded since every simulation of an order without parents writes here.
+    profits: Arc<DashMap<(Address, OrderId), Option<U256>>>,
+}
+
+impl StandaloneProfits {
+    /// None if we don't know, Some(None) if the order fails alone.
+    pub fn get(&self, coinbase: Address, id: &OrderId) -> Option<Option<U256>> {
+        self.profits.get(&(coinbase, *id)).map(|profit| *profit)
+    }
+
+    pub fn insert(&self, coinbase: Address, id: OrderId, profit: Option<U256>) {
+        self.profits.insert((coinbase, id), profit);
+    }
+
+    pub fn len(&self) -> usize {
+        self.profits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profits.is_empty()
+    }
+}
+
+/// Profit of the order executed alone, None if it fails.
+/// Only executes the order if ctx.standalone_profits doesn't have it yet, state_provider must be the state of
+/// ctx.attributes.parent.
+pub fn standalone_profit(
+    state_provider: &Arc<dyn StateProvider>,
+    ctx: &BlockBuildingContext,
+    order: &Order,
+) -> eyre::Result<Option<U256>> {
+    let coinbase = ctx.block_env.coinbase;
+    if let Some(profit) = ctx.standalone_profits.get(coinbase, &order.id()) {
+        return Ok(profit);
+    }
+    let mut state = BlockState::new_arc(state_provider.clone());
+    let mut fork = PartialBlockFork::new(&mut state);
+    let profit = fork
+        .commit_order(order, ctx, 0, 0, 0, true)?
+        .ok()
+        .map(|res| res.coinbase_profit);
+    ctx.standalone_profits.insert(coinbase, order.id(), profit);
+    Ok(profit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::order_id;
+
+    #[test]
+    fn test_standalone_profits_are_shared() {
+        let (coinbase, other_coinbase) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        let profits = StandaloneProfits::default();
+        let shared = profits.clone();
+        assert_eq!(profits.get(coinbase, &order_id(1)), None);
+        shared.insert(coinbase, order_id(1), Some(U256::from(10)));
+        shared.insert(coinbase, order_id(2), None);
+        assert_eq!(
+            profits.get(coinbase, &order_id(1)),
+            Some(Some(U256::from(10)))
+        );
+        assert_eq!(profits.get(coinbase, &order_id(2)), Some(None));
+        // profits to another coinbase are not the same
+        assert_eq!(profits.get(other_coinbase, &order_id(1)), None);
+        assert_eq!(profits.len(), 2);
+    }
+}
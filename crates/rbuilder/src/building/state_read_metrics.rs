@@ -0,0 +1,127 @@
+//! Latency tracking of the reads that reach the state provider (cache misses of [`reth::revm::cached::CachedReads`]).
+//! Every read is timed, reads slower than [`SLOW_STATE_READ_THRESHOLD`] are flagged (on MDBX they are usually page-cache misses)
+//! and the total read time is accumulated so we can compare it against the time we had to build the slot and
+//! tell DB bound slots from CPU bound ones.
+
+use crate::telemetry;
+use alloy_primitives::{Address, B256, U256};
+use revm::{
+    primitives::{AccountInfo, Bytecode},
+    DatabaseRef,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// A hot read (page cache hit) takes a few us, anything above this probably went to disk.
+pub const SLOW_STATE_READ_THRESHOLD: Duration = Duration::from_micros(500);
+
+static SLOT_STATE_READS: AtomicU64 = AtomicU64::new(0);
+static SLOT_SLOW_STATE_READS: AtomicU64 = AtomicU64::new(0);
+static SLOT_STATE_READ_TIME_NS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateReadKind {
+    Account,
+    Storage,
+    Code,
+    BlockHash,
+}
+
+impl StateReadKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StateReadKind::Account => "account",
+            StateReadKind::Storage => "storage",
+            StateReadKind::Code => "code",
+            StateReadKind::BlockHash => "block_hash",
+        }
+    }
+}
+
+/// State reads accumulated since the last [`take_slot_state_reads`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlotStateReads {
+    pub reads: u64,
+    pub slow_reads: u64,
+    /// Sum over all threads.
+    pub read_time: Duration,
+}
+
+pub fn record_state_read(kind: StateReadKind, duration: Duration) {
+    let slow = duration >= SLOW_STATE_READ_THRESHOLD;
+    SLOT_STATE_READS.fetch_add(1, Ordering::Relaxed);
+    if slow {
+        SLOT_SLOW_STATE_READS.fetch_add(1, Ordering::Relaxed);
+    }
+    SLOT_STATE_READ_TIME_NS.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    telemetry::add_state_read_time(kind.as_str(), duration, slow);
+}
+
+/// Returns the reads accumulated since the last call and resets the counters.
+/// Expected to be called once per slot.
+pub fn take_slot_state_reads() -> SlotStateReads {
+    SlotStateReads {
+        reads: SLOT_STATE_READS.swap(0, Ordering::Relaxed),
+        slow_reads: SLOT_SLOW_STATE_READS.swap(0, Ordering::Relaxed),
+        read_time: Duration::from_nanos(SLOT_STATE_READ_TIME_NS.swap(0, Ordering::Relaxed)),
+    }
+}
+
+/// [`DatabaseRef`] wrapper that times every read.
+#[derive(Debug, Clone)]
+pub struct TimedDatabaseRef<DB> {
+    inner: DB,
+}
+
+impl<DB> TimedDatabaseRef<DB> {
+    pub fn new(inner: DB) -> Self {
+        Self { inner }
+    }
+
+    fn timed<T>(&self, kind: StateReadKind, read: impl FnOnce(&DB) -> T) -> T {
+        let start = Instant::now();
+        let res = read(&self.inner);
+        record_state_read(kind, start.elapsed());
+        res
+    }
+}
+
+impl<DB: DatabaseRef> DatabaseRef for TimedDatabaseRef<DB> {
+    type Error = DB::Error;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.timed(StateReadKind::Account, |db| db.basic_ref(address))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.timed(StateReadKind::Code, |db| db.code_by_hash_ref(code_hash))
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.timed(StateReadKind::Storage, |db| db.storage_ref(address, index))
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.timed(StateReadKind::BlockHash, |db| db.block_hash_ref(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm::db::EmptyDB;
+
+    #[test]
+    fn test_reads_are_accounted() {
+        let db = TimedDatabaseRef::new(EmptyDB::default());
+        db.basic_ref(Address::ZERO).unwrap();
+        db.storage_ref(Address::ZERO, U256::ZERO).unwrap();
+        db.block_hash_ref(1).unwrap();
+        // other tests may be reading state concurrently
+        let reads = take_slot_state_reads();
+        assert!(reads.reads >= 3);
+        assert!(reads.slow_reads <= reads.reads);
+    }
+}
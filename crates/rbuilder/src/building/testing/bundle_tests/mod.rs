@@ -6,7 +6,10 @@ use std::collections::HashSet;
 use uuid::Uuid;
 
 use crate::{
-    building::{testing::bundle_tests::setup::NonceValue, BuiltBlockTrace, BundleErr, OrderErr},
+    building::{
+        testing::bundle_tests::setup::NonceValue, victim_protection::VictimProtection,
+        BuiltBlockTrace, BundleErr, OrderErr, RefundSettlementMode,
+    },
     primitives::{
         Bundle, BundleReplacementData, BundleReplacementKey, Order, OrderId, Refund, RefundConfig,
         TxRevertBehavior,
@@ -415,6 +418,86 @@ fn test_mev_share_ok_refunds() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_mev_share_victim_protection() -> eyre::Result<()> {
+    let target_block = 11;
+    let mut test_setup = TestSetup::gen_test_setup(BlockArgs::default().number(target_block))?;
+    // User(0) increments slot 0 right before the user tx (User(2)) expecting it unchanged, the user tx reverts
+    let front_run = |test_setup: &mut TestSetup| -> eyre::Result<()> {
+        test_setup.begin_share_bundle_order(11, 11);
+        test_setup.add_mev_test_increment_value_tx_no_rev(CURR_NONCE, 0)?;
+        test_setup.add_mev_test_increment_value_tx_from(
+            NamedAddr::User(2),
+            CURR_NONCE,
+            TxRevertBehavior::AllowedIncluded,
+            0,
+        )?;
+        test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+        test_setup.set_inner_bundle_refund(vec![Refund {
+            body_idx: 1,
+            percent: 90,
+        }]);
+        Ok(())
+    };
+
+    test_setup.set_victim_protection(Some(VictimProtection { max_loss_bps: 0 }));
+    front_run(&mut test_setup)?;
+    test_setup.commit_order_err("victim tx worse than alone");
+
+    test_setup.set_victim_protection(None);
+    front_run(&mut test_setup)?;
+    let result = test_setup.commit_order_ok();
+    assert!(!result.receipts[1].success);
+
+    // user tx first, nothing to compare
+    test_setup.set_victim_protection(Some(VictimProtection { max_loss_bps: 0 }));
+    test_setup.begin_share_bundle_order(11, 11);
+    test_setup.add_mev_test_increment_value_tx_from(
+        NamedAddr::User(2),
+        CURR_NONCE,
+        TxRevertBehavior::NotAllowed,
+        1,
+    )?;
+    test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+    test_setup.set_inner_bundle_refund(vec![Refund {
+        body_idx: 0,
+        percent: 90,
+    }]);
+    test_setup.commit_order_ok();
+    Ok(())
+}
+
+#[test]
+fn test_mev_share_deferred_refunds() -> eyre::Result<()> {
+    let target_block = 11;
+    let mut test_setup = TestSetup::gen_test_setup(BlockArgs::default().number(target_block))?;
+    test_setup.set_refund_settlement(RefundSettlementMode::Deferred);
+
+    // same bundle without refunds to get its profit
+    test_setup.begin_share_bundle_order(11, 11);
+    test_setup.add_dummy_tx_0_1_no_rev()?;
+    test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+    let no_refund_profit = test_setup.commit_order_ok().coinbase_profit;
+
+    test_setup.begin_share_bundle_order(11, 11);
+    test_setup.add_dummy_tx_0_1_no_rev()?;
+    test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+    test_setup.set_inner_bundle_refund(vec![Refund {
+        body_idx: 0,
+        percent: 90,
+    }]);
+    let result = test_setup.commit_order_ok();
+    let owed = U256::from(90_000 - 21_000);
+    assert_eq!(
+        result.paid_kickbacks,
+        vec![(test_setup.named_address(NamedAddr::User(0))?, owed)]
+    );
+    // no payout tx
+    assert_eq!(result.txs.len(), 2);
+    assert_eq!(result.coinbase_profit, no_refund_profit - owed);
+    Ok(())
+}
+
 #[test]
 fn test_mev_share_failed_refunds() -> eyre::Result<()> {
     let target_block = 11;
@@ -458,6 +541,48 @@ fn test_mev_share_failed_refunds() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_mev_share_refund_constraints() -> eyre::Result<()> {
+    let target_block = 11;
+    let mut test_setup = TestSetup::gen_test_setup(BlockArgs::default().number(target_block))?;
+
+    // refunds for the same element add up
+    test_setup.begin_share_bundle_order(11, 11);
+    test_setup.add_dummy_tx_0_1_no_rev()?;
+    test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+    test_setup.set_inner_bundle_refund(vec![
+        Refund {
+            body_idx: 0,
+            percent: 45,
+        },
+        Refund {
+            body_idx: 0,
+            percent: 45,
+        },
+    ]);
+    let result = test_setup.commit_order_ok();
+    assert_eq!(
+        result.paid_kickbacks,
+        vec![(
+            test_setup.named_address(NamedAddr::User(0))?,
+            U256::from(90_000 - 21_000)
+        )]
+    );
+
+    // no refund for an element that didn't land
+    test_setup.begin_share_bundle_order(11, 11);
+    test_setup.add_revert(NamedAddr::User(0), TxRevertBehavior::AllowedExcluded)?;
+    test_setup.add_send_to_coinbase_tx(NamedAddr::User(1), 100_000)?;
+    test_setup.set_inner_bundle_refund(vec![Refund {
+        body_idx: 0,
+        percent: 90,
+    }]);
+    let result = test_setup.commit_order_ok();
+    assert!(result.paid_kickbacks.is_empty());
+
+    Ok(())
+}
+
 #[test]
 fn test_bundle_consistency_check() -> eyre::Result<()> {
     let mut test_setup = TestSetup::gen_test_setup(BlockArgs::default().number(11))?;
@@ -556,6 +681,73 @@ fn test_bundle_consistency_check() -> eyre::Result<()> {
         assert!(err.to_string().contains("blocked address"));
     }
 
+    // check bundle txs executed out of order
+    {
+        let mut built_block_trace = BuiltBlockTrace::new();
+
+        test_setup.begin_bundle_order(11);
+        test_setup.add_dummy_tx_0_1_no_rev()?;
+        test_setup.add_dummy_tx(
+            NamedAddr::User(1),
+            NamedAddr::User(2),
+            0,
+            TxRevertBehavior::NotAllowed,
+        )?;
+        let mut res = test_setup.commit_order_ok();
+        res.txs.swap(0, 1);
+        res.receipts.swap(0, 1);
+        built_block_trace.add_included_order(res);
+
+        let err = built_block_trace
+            .verify_bundle_consistency(&blocklist)
+            .expect_err("Expected error");
+        assert!(err.to_string().contains("out of order"));
+    }
+
+    // check tx of another bundle executed inside a bundle
+    {
+        let mut built_block_trace = BuiltBlockTrace::new();
+
+        test_setup.begin_bundle_order(11);
+        test_setup.add_dummy_tx_0_1_no_rev()?;
+        let mut first = test_setup.commit_order_ok();
+
+        test_setup.begin_bundle_order(11);
+        test_setup.add_dummy_tx(
+            NamedAddr::User(1),
+            NamedAddr::User(2),
+            0,
+            TxRevertBehavior::NotAllowed,
+        )?;
+        let second = test_setup.commit_order_ok();
+        first.txs.push(second.txs[0].clone());
+        first.receipts.push(second.receipts[0].clone());
+        built_block_trace.add_included_order(first);
+        built_block_trace.add_included_order(second);
+
+        let err = built_block_trace
+            .verify_bundle_consistency(&blocklist)
+            .expect_err("Expected error");
+        assert!(err.to_string().contains("executed inside order"));
+    }
+
+    // check final block txs against the included orders
+    {
+        let mut built_block_trace = BuiltBlockTrace::new();
+
+        test_setup.begin_bundle_order(11);
+        let tx_hash = test_setup.add_dummy_tx_0_1_no_rev()?;
+        let res = test_setup.commit_order_ok();
+        built_block_trace.add_included_order(res);
+
+        built_block_trace.verify_block_txs([tx_hash, B256::random()])?;
+        let err = built_block_trace
+            .verify_block_txs([B256::random()])
+            .expect_err("Expected error");
+        assert!(err.to_string().contains("Block tx 0"));
+        assert!(built_block_trace.verify_block_txs([B256::ZERO; 0]).is_err());
+    }
+
     Ok(())
 }
 
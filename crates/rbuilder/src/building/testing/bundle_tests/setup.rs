@@ -5,7 +5,8 @@
 use crate::{
     building::{
         testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
-        BlockState, ExecutionError, ExecutionResult, OrderErr, PartialBlock,
+        victim_protection::VictimProtection,
+        BlockState, ExecutionError, ExecutionResult, OrderErr, PartialBlock, RefundSettlementMode,
     },
     primitives::{
         order_builder::OrderBuilder, BundleReplacementData, OrderId, Refund, RefundConfig,
@@ -187,7 +188,22 @@ impl TestSetup {
         revert_behavior: TxRevertBehavior,
         current_value: u64,
     ) -> eyre::Result<TxHash> {
-        let from = NamedAddr::User(0);
+        self.add_mev_test_increment_value_tx_from(
+            NamedAddr::User(0),
+            nonce_value,
+            revert_behavior,
+            current_value,
+        )
+    }
+
+    /// add_mev_test_increment_value_tx from another account.
+    pub fn add_mev_test_increment_value_tx_from(
+        &mut self,
+        from: NamedAddr,
+        nonce_value: NonceValue,
+        revert_behavior: TxRevertBehavior,
+        current_value: u64,
+    ) -> eyre::Result<TxHash> {
         let tx =
             TxArgs::new_increment_value(from, self.nonce(from, nonce_value)?, 0, current_value);
         self.add_tx(tx, revert_behavior)
@@ -205,6 +221,18 @@ impl TestSetup {
             current_value,
         )
     }
+    pub fn set_refund_settlement(&mut self, refund_settlement: RefundSettlementMode) {
+        self.test_chain
+            .block_building_context_mut()
+            .refund_settlement = refund_settlement;
+    }
+
+    pub fn set_victim_protection(&mut self, victim_protection: Option<VictimProtection>) {
+        self.test_chain
+            .block_building_context_mut()
+            .victim_protection = victim_protection;
+    }
+
     fn try_commit_order(&mut self) -> eyre::Result<Result<ExecutionResult, ExecutionError>> {
         let state_provider = self.test_chain.provider_factory().latest()?;
         let mut block_state = BlockState::new(state_provider)
@@ -216,6 +244,7 @@ impl TestSetup {
             sim_value: Default::default(),
             prev_order: Default::default(),
             used_state_trace: Default::default(),
+            execution_cost: Default::default(),
         };
 
         let result = self.partial_block.commit_order(
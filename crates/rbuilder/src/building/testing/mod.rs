@@ -2,4 +2,5 @@
 pub mod bundle_tests;
 #[cfg(test)]
 pub mod evm_inspector_tests;
+pub mod scenario;
 pub mod test_chain_state;
@@ -0,0 +1,395 @@
+//! Config driven builder scenarios: a scenario file (toml or json) describes the initial state, the orders with their
+//! arrival times and what we expect from the built block. [`run_scenario`] executes it against any builder of a
+//! [`LiveBuilderConfig`] so behavioral regressions can be encoded as fixtures (see testing/scenarios).
+//!
+//! State is a [`TestChainState`]: users are NamedAddr::User(i) and txs use the MevTest contract.
+//! Sample:
+//! ```toml
+//! name = "higher paying bundle goes first"
+//! build_at_ms = 1000
+//!
+//! [[initial_state.user_balances]]
+//! user = 0
+//! balance = "2000000000000000000"
+//!
+//! [[orders]]
+//! name = "bundle"
+//! arrival_ms = 100
+//! bundle = true
+//! txs = [{ from_user = 0, nonce = 0, type = "send_to_coinbase", value = 1000000 }]
+//!
+//! [expect]
+//! included = ["bundle"]
+//! min_block_value = "1000000"
+//! ```
+
+use crate::{
+    building::{
+        builders::BacktestSimulateBlockInput,
+        sim::simulate_all_orders_with_sim_tree,
+        testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+    },
+    live_builder::cli::LiveBuilderConfig,
+    primitives::{Bundle, MempoolTx, Order, OrderId, TransactionSignedEcRecoveredWithBlobs},
+};
+use ahash::HashMap;
+use alloy_primitives::U256;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    /// Block we build, the parent is the genesis so any other value will probably fail.
+    #[serde(default = "default_block")]
+    pub block: u64,
+    /// Orders arriving after this (ms since the slot started) are not available to the builder.
+    #[serde(default)]
+    pub build_at_ms: Option<u64>,
+    #[serde(default)]
+    pub initial_state: ScenarioInitialState,
+    pub orders: Vec<ScenarioOrder>,
+    #[serde(default)]
+    pub expect: ScenarioExpectations,
+}
+
+fn default_block() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioInitialState {
+    /// Users not listed have 1 ETH.
+    #[serde(default)]
+    pub user_balances: Vec<ScenarioUserBalance>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioUserBalance {
+    pub user: usize,
+    /// wei
+    pub balance: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioOrder {
+    /// Used in the expectations.
+    pub name: String,
+    #[serde(default)]
+    pub arrival_ms: u64,
+    /// If false the order is a mempool tx and must have a single tx.
+    #[serde(default)]
+    pub bundle: bool,
+    pub txs: Vec<ScenarioTx>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScenarioTx {
+    pub from_user: usize,
+    pub nonce: u64,
+    #[serde(flatten)]
+    pub action: ScenarioTxAction,
+    #[serde(default)]
+    pub max_priority_fee: u128,
+    /// Bundle txs only.
+    #[serde(default)]
+    pub can_revert: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScenarioTxAction {
+    SendToCoinbase { value: u64 },
+    SendTo { value: u64, to_user: usize },
+    IncrementValue { slot: u64, current_value: u64 },
+    Revert,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioExpectations {
+    #[serde(default)]
+    pub included: Vec<String>,
+    #[serde(default)]
+    pub excluded: Vec<String>,
+    /// These orders must be included in this relative order.
+    #[serde(default)]
+    pub included_in_order: Vec<String>,
+    /// Block true value (coinbase profit before paying the validator).
+    #[serde(default)]
+    pub min_block_value: Option<U256>,
+    #[serde(default)]
+    pub max_block_value: Option<U256>,
+}
+
+impl Scenario {
+    /// Format by extension: .json is json, anything else toml.
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)?;
+        if path.extension().map_or(false, |ext| ext == "json") {
+            Ok(serde_json::from_str(&data)?)
+        } else {
+            Ok(toml::from_str(&data)?)
+        }
+    }
+
+    fn create_tx(
+        &self,
+        test_chain: &TestChainState,
+        tx: &ScenarioTx,
+    ) -> eyre::Result<TransactionSignedEcRecoveredWithBlobs> {
+        let from = NamedAddr::User(tx.from_user);
+        let args = match &tx.action {
+            ScenarioTxAction::SendToCoinbase { value } => {
+                TxArgs::new_send_to_coinbase(from, tx.nonce, *value)
+            }
+            ScenarioTxAction::SendTo { value, to_user } => TxArgs::new_send_to(
+                from,
+                tx.nonce,
+                *value,
+                test_chain.named_address(NamedAddr::User(*to_user))?,
+            ),
+            ScenarioTxAction::IncrementValue {
+                slot,
+                current_value,
+            } => TxArgs::new_increment_value(from, tx.nonce, *slot, *current_value),
+            ScenarioTxAction::Revert => TxArgs::new_revert(from, tx.nonce),
+        };
+        let base_fee = test_chain
+            .block_building_context()
+            .block_env
+            .basefee
+            .to::<u128>();
+        let args = args
+            .max_priority_fee(tx.max_priority_fee)
+            .max_fee_per_gas(base_fee + tx.max_priority_fee);
+        TransactionSignedEcRecoveredWithBlobs::new_no_blobs(test_chain.sign_tx(args)?)
+            .ok_or_else(|| eyre::eyre!("unexpected blob tx"))
+    }
+
+    fn create_order(
+        &self,
+        test_chain: &TestChainState,
+        order: &ScenarioOrder,
+    ) -> eyre::Result<Order> {
+        let txs = order
+            .txs
+            .iter()
+            .map(|tx| self.create_tx(test_chain, tx))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        if !order.bundle {
+            let [tx] = <[_; 1]>::try_from(txs)
+                .map_err(|_| eyre::eyre!("order {}: mempool tx must have 1 tx", order.name))?;
+            return Ok(Order::Tx(MempoolTx::new(tx)));
+        }
+        let reverting_tx_hashes = order
+            .txs
+            .iter()
+            .zip(txs.iter())
+            .filter(|(tx, _)| tx.can_revert)
+            .map(|(_, tx)| tx.hash())
+            .collect();
+        let mut bundle = Bundle {
+            block: self.block,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs,
+            reverting_tx_hashes,
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        Ok(Order::Bundle(bundle))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioOutcome {
+    pub scenario: String,
+    pub builder_name: String,
+    /// Scenario order names in block order.
+    pub included: Vec<String>,
+    pub block_value: U256,
+    /// Failed expectations, empty if the scenario passed.
+    pub failures: Vec<String>,
+}
+
+impl ScenarioOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Builds the scenario block with builder_name (one of config builders) and checks the expectations.
+/// Err is for scenarios that can't be run (bad scenario, builder error), failed expectations are in the outcome.
+pub fn run_scenario<ConfigType: LiveBuilderConfig>(
+    scenario: &Scenario,
+    config: &ConfigType,
+    builder_name: &str,
+) -> eyre::Result<ScenarioOutcome> {
+    let user_balances: Vec<_> = scenario
+        .initial_state
+        .user_balances
+        .iter()
+        .map(|user_balance| (user_balance.user, user_balance.balance))
+        .collect();
+    let test_chain = TestChainState::new_with_user_balances(
+        BlockArgs::default().number(scenario.block),
+        &user_balances,
+    )?;
+
+    let mut names = HashMap::<OrderId, String>::default();
+    let mut orders = Vec::new();
+    for order in &scenario.orders {
+        let created = scenario.create_order(&test_chain, order)?;
+        if names.insert(created.id(), order.name.clone()).is_some() {
+            eyre::bail!("order {} is a duplicate", order.name);
+        }
+        if scenario
+            .build_at_ms
+            .map_or(true, |build_at_ms| order.arrival_ms <= build_at_ms)
+        {
+            orders.push(created);
+        }
+    }
+
+    let ctx = test_chain.block_building_context().clone();
+    let provider = test_chain.provider_factory().clone();
+    let (sim_orders, _) =
+        simulate_all_orders_with_sim_tree(provider.clone(), &ctx, &orders, false)?;
+    let (block, _) = config.build_backtest_block(
+        builder_name,
+        BacktestSimulateBlockInput {
+            ctx,
+            builder_name: builder_name.to_string(),
+            sbundle_mergeabe_signers: Vec::new(),
+            sim_orders: &sim_orders,
+            provider,
+            cached_reads: None,
+        },
+    )?;
+
+    let included: Vec<String> = block
+        .trace
+        .included_orders
+        .iter()
+        .filter_map(|executed| names.get(&executed.order.id()).cloned())
+        .collect();
+    let block_value = block.trace.true_bid_value;
+    let failures = check_expectations(&scenario.expect, &included, block_value);
+    Ok(ScenarioOutcome {
+        scenario: scenario.name.clone(),
+        builder_name: builder_name.to_string(),
+        included,
+        block_value,
+        failures,
+    })
+}
+
+fn check_expectations(
+    expect: &ScenarioExpectations,
+    included: &[String],
+    block_value: U256,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    for name in &expect.included {
+        if !included.contains(name) {
+            failures.push(format!("{} not included", name));
+        }
+    }
+    for name in &expect.excluded {
+        if included.contains(name) {
+            failures.push(format!("{} included", name));
+        }
+    }
+    let positions: Vec<_> = expect
+        .included_in_order
+        .iter()
+        .filter_map(|name| included.iter().position(|included| included == name))
+        .collect();
+    if positions.len() != expect.included_in_order.len()
+        || !positions.windows(2).all(|pair| pair[0] < pair[1])
+    {
+        failures.push(format!(
+            "expected inclusion order {:?}, got {:?}",
+            expect.included_in_order, included
+        ));
+    }
+    if let Some(min_block_value) = expect.min_block_value {
+        if block_value < min_block_value {
+            failures.push(format!(
+                "block value {} < min {}",
+                block_value, min_block_value
+            ));
+        }
+    }
+    if let Some(max_block_value) = expect.max_block_value {
+        if block_value > max_block_value {
+            failures.push(format!(
+                "block value {} > max {}",
+                block_value, max_block_value
+            ));
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live_builder::config::Config;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_check_expectations() {
+        let included = vec!["a".to_string(), "b".to_string()];
+        let expect = ScenarioExpectations {
+            included: vec!["a".to_string()],
+            excluded: vec!["c".to_string()],
+            included_in_order: vec!["a".to_string(), "b".to_string()],
+            min_block_value: Some(U256::from(10)),
+            max_block_value: None,
+        };
+        assert!(check_expectations(&expect, &included, U256::from(10)).is_empty());
+        assert_eq!(
+            check_expectations(&expect, &included, U256::from(9)).len(),
+            1
+        );
+
+        let expect = ScenarioExpectations {
+            included: vec!["c".to_string()],
+            excluded: vec!["b".to_string()],
+            included_in_order: vec!["b".to_string(), "a".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(check_expectations(&expect, &included, U256::ZERO).len(), 3);
+    }
+
+    #[test]
+    fn test_scenario_fixtures() {
+        let config = Config::default();
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/building/testing/scenarios");
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            let scenario = Scenario::load(&path).unwrap();
+            for builder_name in ["mgp-ordering", "mp-ordering"] {
+                let outcome = run_scenario(&scenario, &config, builder_name).unwrap();
+                assert!(outcome.passed(), "{:?}: {:?}", path, outcome);
+            }
+        }
+    }
+}
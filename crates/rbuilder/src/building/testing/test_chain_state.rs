@@ -72,6 +72,14 @@ pub struct TestChainState {
 }
 impl TestChainState {
     pub fn new(block_args: BlockArgs) -> eyre::Result<Self> {
+        Self::new_with_user_balances(block_args, &[])
+    }
+
+    /// Like new but with custom initial balances for some NamedAddr::User(i) (the rest get 1 ETH).
+    pub fn new_with_user_balances(
+        block_args: BlockArgs,
+        user_balances: &[(usize, U256)],
+    ) -> eyre::Result<Self> {
         let blocklisted_address = Signer::random();
         let builder = Signer::random();
         let fee_recipient = Signer::random();
@@ -83,6 +91,12 @@ impl TestChainState {
             Signer::random(),
             Signer::random(),
         ];
+        if let Some((idx, _)) = user_balances
+            .iter()
+            .find(|(idx, _)| *idx >= test_accounts.len())
+        {
+            eyre::bail!("invalid user index {}", idx);
+        }
         let mev_test_address = Address::random();
         let dummy_test_address = Address::random();
         let test_contracts = TestContracts::load();
@@ -102,20 +116,27 @@ impl TestChainState {
                     .tx_ref()
                     .cursor_write::<tables::PlainAccountState>()
                     .unwrap();
+                let default_balance = parse_ether("1.0")?;
                 let user_addresses = {
                     let mut res = Vec::new();
-                    res.push(builder.address);
-                    res.push(fee_recipient.address);
-                    res.push(blocklisted_address.address);
-                    res.extend(test_accounts.iter().map(|s| s.address));
+                    res.push((builder.address, default_balance));
+                    res.push((fee_recipient.address, default_balance));
+                    res.push((blocklisted_address.address, default_balance));
+                    res.extend(test_accounts.iter().enumerate().map(|(idx, s)| {
+                        let balance = user_balances
+                            .iter()
+                            .find(|(user_idx, _)| *user_idx == idx)
+                            .map_or(default_balance, |(_, balance)| *balance);
+                        (s.address, balance)
+                    }));
                     res
                 };
-                for address in user_addresses {
+                for (address, balance) in user_addresses {
                     cursor.upsert(
                         address,
                         Account {
                             nonce: 0,
-                            balance: parse_ether("1.0")?,
+                            balance,
                             bytecode_hash: None,
                         },
                     )?;
@@ -214,6 +235,10 @@ impl TestChainState {
         &self.block_building_context
     }
 
+    pub fn block_building_context_mut(&mut self) -> &mut BlockBuildingContext {
+        &mut self.block_building_context
+    }
+
     pub fn provider_factory(&self) -> &ProviderFactory<MockNodeTypesWithDB> {
         &self.provider_factory
     }
@@ -1,4 +1,4 @@
-use crate::building::evm_inspector::UsedStateTrace;
+use crate::building::{evm_inspector::UsedStateTrace, scratch};
 
 /// Trait to trace ANY use of an EVM instance for metrics
 pub trait SimulationTracer {
@@ -40,6 +40,20 @@ impl AccumulatorSimulationTracer {
     }
 }
 
+impl AccumulatorSimulationTracer {
+    /// Same as new but uses a pooled used_state_trace, give it back via [`AccumulatorSimulationTracer::recycle`].
+    pub fn with_scratch() -> Self {
+        Self {
+            used_gas: 0,
+            used_state_trace: scratch::take_used_state_trace(),
+        }
+    }
+
+    pub fn recycle(self) {
+        scratch::recycle_used_state_trace(self.used_state_trace);
+    }
+}
+
 impl Default for AccumulatorSimulationTracer {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,178 @@
+//! Victim tx protection for mev-share bundles: the user txs of a sbundle (the body elements its refunds point to) must
+//! not end up worse than if they were executed alone right where the sbundle starts, eg: a searcher front running
+//! the user tx inside its own sbundle.
+//!
+//! "Worse" is measured on the success of the tx and on what its signer sends/receives in ERC20 Transfer logs (swaps):
+//! - A tx that works alone must not revert inside the sbundle.
+//! - For every token it receives alone it must receive at least (10000 - max_loss_bps) / 10000 of it.
+//! - For every token it sends alone it must send at most (10000 + max_loss_bps) / 10000 of it.
+//!
+//! Native ETH transfers are not tracked.
+
+use crate::primitives::{ShareBundleBody, ShareBundleInner, TransactionSignedEcRecoveredWithBlobs};
+use alloy_primitives::{b256, Address, Log, B256, U256};
+use reth_primitives::Receipt;
+use std::collections::HashMap;
+
+/// keccak256("Transfer(address,address,uint256)")
+const ERC20_TRANSFER_TOPIC: B256 =
+    b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+
+const MAX_BPS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VictimProtection {
+    /// How much worse (in basis points of the amounts) than alone a user tx can be.
+    pub max_loss_bps: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VictimTxOutcome {
+    pub success: bool,
+    /// token -> amount transferred to the signer.
+    pub received: HashMap<Address, U256>,
+    /// token -> amount transferred from the signer.
+    pub sent: HashMap<Address, U256>,
+}
+
+impl VictimTxOutcome {
+    pub fn new(signer: Address, receipt: &Receipt) -> Self {
+        Self::from_logs(signer, receipt.success, &receipt.logs)
+    }
+
+    pub fn from_logs(signer: Address, success: bool, logs: &[Log]) -> Self {
+        let mut outcome = Self {
+            success,
+            ..Default::default()
+        };
+        for log in logs {
+            let topics = log.data.topics();
+            if topics.len() != 3 || topics[0] != ERC20_TRANSFER_TOPIC || log.data.data.len() < 32 {
+                continue;
+            }
+            let (from, to) = (Address::from_word(topics[1]), Address::from_word(topics[2]));
+            let amount = U256::from_be_slice(&log.data.data[..32]);
+            if to == signer {
+                let received = outcome.received.entry(log.address).or_default();
+                *received = received.saturating_add(amount);
+            }
+            if from == signer {
+                let sent = outcome.sent.entry(log.address).or_default();
+                *sent = sent.saturating_add(amount);
+            }
+        }
+        outcome
+    }
+}
+
+impl VictimProtection {
+    /// true if in_bundle is worse than alone beyond max_loss_bps.
+    pub fn is_worsened(&self, alone: &VictimTxOutcome, in_bundle: &VictimTxOutcome) -> bool {
+        if alone.success && !in_bundle.success {
+            return true;
+        }
+        let min_bps = U256::from(MAX_BPS.saturating_sub(self.max_loss_bps));
+        let max_bps = U256::from(MAX_BPS.saturating_add(self.max_loss_bps));
+        let bps = U256::from(MAX_BPS);
+        let received_less = alone.received.iter().any(|(token, amount)| {
+            let got = in_bundle.received.get(token).copied().unwrap_or_default();
+            got.saturating_mul(bps) < amount.saturating_mul(min_bps)
+        });
+        let sent_more = in_bundle.sent.iter().any(|(token, amount)| {
+            let sent_alone = alone.sent.get(token).copied().unwrap_or_default();
+            amount.saturating_mul(bps) > sent_alone.saturating_mul(max_bps)
+        });
+        received_less || sent_more
+    }
+}
+
+/// Txs of the body elements pointed by the refunds of bundle (and its inner bundles), except the first tx of bundle
+/// since nothing can go before it.
+pub fn victim_txs(bundle: &ShareBundleInner) -> Vec<&TransactionSignedEcRecoveredWithBlobs> {
+    fn collect<'a>(
+        bundle: &'a ShareBundleInner,
+        victims: &mut Vec<&'a TransactionSignedEcRecoveredWithBlobs>,
+    ) {
+        for refund in &bundle.refund {
+            if let Some(body) = bundle.body.get(refund.body_idx) {
+                victims.extend(body.list_txs().into_iter().map(|(tx, _)| tx));
+            }
+        }
+        for body in &bundle.body {
+            if let ShareBundleBody::Bundle(inner) = body {
+                collect(inner, victims);
+            }
+        }
+    }
+
+    let mut victims = Vec::new();
+    collect(bundle, &mut victims);
+    let first_tx = bundle
+        .body
+        .first()
+        .and_then(|body| body.list_txs().first().map(|(tx, _)| tx.hash()));
+    let mut seen = Vec::with_capacity(victims.len());
+    victims.retain(|tx| {
+        let hash = tx.hash();
+        if Some(hash) == first_tx || seen.contains(&hash) {
+            return false;
+        }
+        seen.push(hash);
+        true
+    });
+    victims
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::addr;
+    use alloy_primitives::{Bytes, LogData};
+
+    fn transfer(token: Address, from: Address, to: Address, amount: u64) -> Log {
+        Log {
+            address: token,
+            data: LogData::new_unchecked(
+                vec![ERC20_TRANSFER_TOPIC, from.into_word(), to.into_word()],
+                Bytes::from(U256::from(amount).to_be_bytes::<32>().to_vec()),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_victim_tx_outcome() {
+        let (user, pool, token_in, token_out) = (addr(1), addr(2), addr(10), addr(11));
+        let swap = |amount_in: u64, amount_out: u64| {
+            VictimTxOutcome::from_logs(
+                user,
+                true,
+                &[
+                    transfer(token_in, user, pool, amount_in),
+                    transfer(token_out, pool, user, amount_out),
+                ],
+            )
+        };
+        let alone = swap(1000, 500);
+        assert_eq!(alone.sent, HashMap::from([(token_in, U256::from(1000))]));
+        assert_eq!(
+            alone.received,
+            HashMap::from([(token_out, U256::from(500))])
+        );
+
+        let strict = VictimProtection { max_loss_bps: 0 };
+        let tolerant = VictimProtection { max_loss_bps: 100 };
+        assert!(!strict.is_worsened(&alone, &swap(1000, 500)));
+        assert!(!strict.is_worsened(&alone, &swap(900, 600)));
+        // front run: less out
+        assert!(strict.is_worsened(&alone, &swap(1000, 499)));
+        assert!(!tolerant.is_worsened(&alone, &swap(1000, 495)));
+        assert!(tolerant.is_worsened(&alone, &swap(1000, 494)));
+        // more in
+        assert!(!tolerant.is_worsened(&alone, &swap(1010, 500)));
+        assert!(tolerant.is_worsened(&alone, &swap(1011, 500)));
+
+        let reverted = VictimTxOutcome::from_logs(user, false, &[]);
+        assert!(tolerant.is_worsened(&alone, &reverted));
+        assert!(!tolerant.is_worsened(&reverted, &reverted));
+    }
+}
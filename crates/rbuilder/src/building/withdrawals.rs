@@ -0,0 +1,301 @@
+//! Independent check of the withdrawals of a sealed block.
+//! A wrong withdrawals root or a missed balance increment makes relays reject the block, so before submitting we
+//! recompute both from the payload attributes alone and compare them with what the sealing produced.
+
+use ahash::HashMap;
+use alloy_eips::eip4895::Withdrawal;
+use alloy_primitives::{Address, B256, U256};
+use reth::primitives::proofs;
+use reth_chainspec::EthereumHardforks;
+use revm::Database;
+use thiserror::Error;
+
+const GWEI_TO_WEI: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum WithdrawalsCheckError {
+    #[error("Withdrawals root mismatch, expected: {expected:?}, got: {got:?}")]
+    RootMismatch {
+        expected: Option<B256>,
+        got: Option<B256>,
+    },
+    #[error("Block withdrawals differ from the payload attributes")]
+    WithdrawalsMismatch,
+    #[error("Post withdrawals balance mismatch for {address}, expected: {expected}, got: {got}")]
+    BalanceMismatch {
+        address: Address,
+        expected: U256,
+        got: U256,
+    },
+}
+
+/// None before Shanghai (no withdrawals in the header).
+pub fn expected_withdrawals_root<ChainSpec: EthereumHardforks>(
+    chain_spec: &ChainSpec,
+    timestamp: u64,
+    withdrawals: &[Withdrawal],
+) -> Option<B256> {
+    chain_spec
+        .is_shanghai_active_at_timestamp(timestamp)
+        .then(|| proofs::calculate_withdrawals_root(withdrawals))
+}
+
+/// Withdrawn wei per recipient.
+pub fn withdrawal_balance_increments(withdrawals: &[Withdrawal]) -> HashMap<Address, U256> {
+    let mut increments = HashMap::<Address, U256>::default();
+    for withdrawal in withdrawals {
+        *increments.entry(withdrawal.address).or_default() +=
+            U256::from(withdrawal.amount) * U256::from(GWEI_TO_WEI);
+    }
+    increments
+}
+
+/// Balance every recipient must have after the withdrawals are applied to pre_balances (missing means 0).
+pub fn expected_post_withdrawal_balances(
+    pre_balances: &HashMap<Address, U256>,
+    withdrawals: &[Withdrawal],
+) -> HashMap<Address, U256> {
+    withdrawal_balance_increments(withdrawals)
+        .into_iter()
+        .map(|(address, increment)| {
+            let pre_balance = pre_balances.get(&address).copied().unwrap_or_default();
+            (address, pre_balance.saturating_add(increment))
+        })
+        .collect()
+}
+
+/// Balances of addresses (0 for non existing accounts).
+pub fn read_balances<DB: Database>(
+    db: &mut DB,
+    addresses: impl IntoIterator<Item = Address>,
+) -> Result<HashMap<Address, U256>, DB::Error> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            let balance = db
+                .basic(address)?
+                .map(|account| account.balance)
+                .unwrap_or_default();
+            Ok((address, balance))
+        })
+        .collect()
+}
+
+/// Checks the withdrawals root/list of a block and the balances of the recipients (taken right before and after
+/// applying the withdrawals) against the payload attributes withdrawals.
+pub fn check_withdrawals<ChainSpec: EthereumHardforks>(
+    chain_spec: &ChainSpec,
+    timestamp: u64,
+    attributes_withdrawals: &[Withdrawal],
+    withdrawals_root: Option<B256>,
+    block_withdrawals: Option<&[Withdrawal]>,
+    pre_balances: &HashMap<Address, U256>,
+    post_balances: &HashMap<Address, U256>,
+) -> Result<(), WithdrawalsCheckError> {
+    let expected_root = expected_withdrawals_root(chain_spec, timestamp, attributes_withdrawals);
+    if expected_root != withdrawals_root {
+        return Err(WithdrawalsCheckError::RootMismatch {
+            expected: expected_root,
+            got: withdrawals_root,
+        });
+    }
+    if expected_root.is_none() {
+        // pre Shanghai: no withdrawals are applied at all
+        return if block_withdrawals.is_none() {
+            Ok(())
+        } else {
+            Err(WithdrawalsCheckError::WithdrawalsMismatch)
+        };
+    }
+    if block_withdrawals != Some(attributes_withdrawals) {
+        return Err(WithdrawalsCheckError::WithdrawalsMismatch);
+    }
+    for (address, expected) in
+        expected_post_withdrawal_balances(pre_balances, attributes_withdrawals)
+    {
+        let got = post_balances.get(&address).copied().unwrap_or_default();
+        if got != expected {
+            return Err(WithdrawalsCheckError::BalanceMismatch {
+                address,
+                expected,
+                got,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use reth::primitives::Withdrawals;
+    use reth_basic_payload_builder::commit_withdrawals;
+    use reth_chainspec::MAINNET;
+    use revm::{
+        db::{CacheDB, EmptyDB, State},
+        primitives::AccountInfo,
+    };
+
+    /// After Cancun on mainnet.
+    const TIMESTAMP: u64 = 1_720_000_000;
+
+    fn random_withdrawals(rng: &mut StdRng, recipients: &[Address]) -> Vec<Withdrawal> {
+        (0..rng.gen_range(0..20))
+            .map(|index| Withdrawal {
+                index,
+                validator_index: rng.gen(),
+                address: recipients[rng.gen_range(0..recipients.len())],
+                amount: rng.gen_range(0..64_000_000_000),
+            })
+            .collect()
+    }
+
+    /// Applies withdrawals like the sealing does, returns (root, withdrawals, pre balances, post balances).
+    fn seal_withdrawals(
+        withdrawals: &[Withdrawal],
+        balances: &[(Address, U256)],
+        timestamp: u64,
+    ) -> (
+        Option<B256>,
+        Option<Withdrawals>,
+        HashMap<Address, U256>,
+        HashMap<Address, U256>,
+    ) {
+        let mut cache_db = CacheDB::new(EmptyDB::default());
+        for (address, balance) in balances {
+            cache_db.insert_account_info(
+                *address,
+                AccountInfo {
+                    balance: *balance,
+                    ..Default::default()
+                },
+            );
+        }
+        let mut db = State::builder()
+            .with_database(cache_db)
+            .with_bundle_update()
+            .build();
+        let recipients: Vec<_> = withdrawal_balance_increments(withdrawals)
+            .into_keys()
+            .collect();
+        let pre_balances = read_balances(&mut db, recipients.clone()).unwrap();
+        let outcome = commit_withdrawals(
+            &mut db,
+            &*MAINNET,
+            timestamp,
+            Withdrawals::new(withdrawals.to_vec()),
+        )
+        .unwrap();
+        let post_balances = read_balances(&mut db, recipients).unwrap();
+        (
+            outcome.withdrawals_root,
+            outcome.withdrawals,
+            pre_balances,
+            post_balances,
+        )
+    }
+
+    #[test]
+    fn test_withdrawals_check_fuzz() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let recipients: Vec<_> = (1..=5).map(Address::with_last_byte).collect();
+        for _ in 0..200 {
+            let withdrawals = random_withdrawals(&mut rng, &recipients);
+            // some recipients don't exist before the block
+            let balances: Vec<_> = recipients
+                .iter()
+                .filter_map(|address| {
+                    rng.gen_bool(0.7)
+                        .then(|| (*address, U256::from(rng.gen::<u64>())))
+                })
+                .collect();
+            let (root, block_withdrawals, pre_balances, post_balances) =
+                seal_withdrawals(&withdrawals, &balances, TIMESTAMP);
+            let check = |root: Option<B256>,
+                         block_withdrawals: Option<&[Withdrawal]>,
+                         post_balances: &HashMap<Address, U256>| {
+                check_withdrawals(
+                    &*MAINNET,
+                    TIMESTAMP,
+                    &withdrawals,
+                    root,
+                    block_withdrawals,
+                    &pre_balances,
+                    post_balances,
+                )
+            };
+            let block_withdrawals = block_withdrawals.unwrap().to_vec();
+            assert_eq!(
+                check(root, Some(&block_withdrawals), &post_balances),
+                Ok(())
+            );
+
+            assert!(matches!(
+                check(
+                    Some(B256::random()),
+                    Some(&block_withdrawals),
+                    &post_balances
+                ),
+                Err(WithdrawalsCheckError::RootMismatch { .. })
+            ));
+            if withdrawals.is_empty() {
+                continue;
+            }
+            let mut tampered = block_withdrawals.clone();
+            let index = rng.gen_range(0..tampered.len());
+            tampered[index].amount += 1;
+            assert_eq!(
+                check(root, Some(&tampered), &post_balances),
+                Err(WithdrawalsCheckError::WithdrawalsMismatch)
+            );
+            let mut missed_increment = post_balances.clone();
+            let address = withdrawals[index].address;
+            *missed_increment.get_mut(&address).unwrap() = pre_balances[&address];
+            // zero amount withdrawals don't change the balance
+            if withdrawal_balance_increments(&withdrawals)[&address] != U256::ZERO {
+                assert!(matches!(
+                    check(root, Some(&block_withdrawals), &missed_increment),
+                    Err(WithdrawalsCheckError::BalanceMismatch { address: got, .. }) if got == address
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_withdrawals_check_pre_shanghai() {
+        let withdrawals = vec![Withdrawal {
+            index: 0,
+            validator_index: 1,
+            address: Address::with_last_byte(1),
+            amount: 1,
+        }];
+        let (root, block_withdrawals, pre_balances, post_balances) =
+            seal_withdrawals(&withdrawals, &[], 0);
+        assert_eq!(root, None);
+        assert_eq!(
+            check_withdrawals(
+                &*MAINNET,
+                0,
+                &withdrawals,
+                root,
+                block_withdrawals.as_ref().map(|w| w.as_slice()),
+                &pre_balances,
+                &post_balances,
+            ),
+            Ok(())
+        );
+        assert!(matches!(
+            check_withdrawals(
+                &*MAINNET,
+                TIMESTAMP,
+                &withdrawals,
+                root,
+                None,
+                &pre_balances,
+                &post_balances,
+            ),
+            Err(WithdrawalsCheckError::RootMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+//! Facade to embed the block building pipeline in other crates without going through the CLI/config code.
+//!
+//! Typical flow:
+//! 1. Create a [`BlockBuildingContext`] on top of a parent header via [`block_building_context`].
+//! 2. Create an [`EmbeddedBlockBuilder`], feed it [`Order`]s.
+//! 3. Call [`EmbeddedBlockBuilder::build`] to simulate the orders, run the building algorithm and get a [`Block`]
+//!    (sealed block + trace).
+//!
+//! The re-exported types are the ones the builders use internally (with their public fields), so this module gives
+//! no stability guarantees beyond the ones of the rest of the crate.
+//!
+//! Only one [`EmbeddedBlockBuilder`] can be alive at a time per process: the building pipeline keeps some state in
+//! process-wide singletons (live tuned algorithm params, conflict value history and relay rejections) that two
+//! builders would share. [`EmbeddedBlockBuilder::new`] fails while another one exists, a new one can be created once the previous one is built or dropped.
+
+use crate::building::{
+    builders::ordering_builder::build_block_from_sim_orders, sim::simulate_all_orders_with_sim_tree,
+};
+use alloy_primitives::{Address, B256};
+use alloy_rpc_types_beacon::events::{PayloadAttributesData, PayloadAttributesEvent};
+use reth::rpc::types::{engine::PayloadAttributes, Withdrawal};
+use reth_chainspec::ChainSpec;
+use reth_db::Database;
+use reth_primitives::SealedHeader;
+use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+pub use crate::{
+    building::{
+        builders::{ordering_builder::OrderingBuilderConfig, Block},
+        BlockBuildingContext, BuiltBlockTrace, Sorting,
+    },
+    primitives::{
+        Bundle, MempoolTx, Order, OrderId, ShareBundle, SimulatedOrder,
+        TransactionSignedEcRecoveredWithBlobs,
+    },
+    roothash::RootHashConfig,
+    utils::Signer,
+};
+
+/// Attributes of the block we want to build on top of a parent.
+#[derive(Debug, Clone, Default)]
+pub struct BlockAttributes {
+    pub slot: u64,
+    pub timestamp: u64,
+    pub prev_randao: B256,
+    /// Validator fee recipient, gets paid at the end of the block.
+    pub suggested_fee_recipient: Address,
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    pub parent_beacon_block_root: Option<B256>,
+    /// If None the gas limit is computed from the parent.
+    pub gas_limit: Option<u64>,
+    pub extra_data: Vec<u8>,
+    /// Txs from/to these addresses are never included.
+    pub blocklist: Vec<Address>,
+}
+
+/// Creates a context to build on top of parent.
+/// builder_signer is the coinbase of the block, it signs the payout tx to the suggested_fee_recipient.
+/// Returns None if the block env can't be created (eg: invalid header data for the chain_spec).
+pub fn block_building_context(
+    parent: &SealedHeader,
+    attributes: BlockAttributes,
+    chain_spec: Arc<ChainSpec>,
+    builder_signer: Signer,
+) -> Option<BlockBuildingContext> {
+    let payload_attributes_event = PayloadAttributesEvent {
+        version: Default::default(),
+        data: PayloadAttributesData {
+            proposal_slot: attributes.slot,
+            parent_block_root: Default::default(),
+            parent_block_number: parent.number,
+            parent_block_hash: parent.hash(),
+            proposer_index: 0,
+            payload_attributes: PayloadAttributes {
+                timestamp: attributes.timestamp,
+                prev_randao: attributes.prev_randao,
+                suggested_fee_recipient: attributes.suggested_fee_recipient,
+                withdrawals: attributes.withdrawals,
+                parent_beacon_block_root: attributes.parent_beacon_block_root,
+            },
+        },
+    };
+    BlockBuildingContext::from_attributes(
+        payload_attributes_event,
+        parent,
+        builder_signer,
+        chain_spec,
+        attributes.blocklist.into_iter().collect(),
+        attributes.gas_limit,
+        attributes.extra_data,
+        None,
+    )
+}
+
+/// Set while an [`EmbeddedBlockBuilder`] exists.
+static BUILDER_ALIVE: AtomicBool = AtomicBool::new(false);
+
+/// Clears BUILDER_ALIVE when the builder goes away.
+#[derive(Debug)]
+struct AliveGuard;
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        BUILDER_ALIVE.store(false, Ordering::Release);
+    }
+}
+
+/// Builds a single block from a set of orders.
+#[derive(Debug)]
+pub struct EmbeddedBlockBuilder<P> {
+    provider: P,
+    ctx: BlockBuildingContext,
+    orders: Vec<Order>,
+    config: OrderingBuilderConfig,
+    root_hash_config: RootHashConfig,
+    sbundle_mergeable_signers: Vec<Address>,
+    builder_name: String,
+    _alive: AliveGuard,
+}
+
+impl<P, DB> EmbeddedBlockBuilder<P>
+where
+    DB: Database + Clone + 'static,
+    P: DatabaseProviderFactory<DB = DB, Provider: BlockReader>
+        + StateProviderFactory
+        + Clone
+        + 'static,
+{
+    /// By default orders are sorted by max profit and the block is finalized with the correct state root.
+    /// Fails if another EmbeddedBlockBuilder is alive (see the [module docs](self)).
+    pub fn new(provider: P, ctx: BlockBuildingContext) -> eyre::Result<Self> {
+        if BUILDER_ALIVE
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            eyre::bail!("Only one EmbeddedBlockBuilder per process is supported");
+        }
+        Ok(Self {
+            provider,
+            ctx,
+            orders: Vec::new(),
+            config: OrderingBuilderConfig {
+                discard_txs: true,
+                sorting: Sorting::MaxProfit,
+                failed_order_retries: 1,
+                drop_failed_orders: true,
+                coinbase_payment: false,
+                build_duration_deadline_ms: None,
+                zero_profit_txs: Default::default(),
+                zero_profit_tx_max_gas: None,
+                value_per_cost_window_ms: None,
+            },
+            root_hash_config: RootHashConfig::live_config(false, false),
+            sbundle_mergeable_signers: Vec::new(),
+            builder_name: "embedded".to_string(),
+            _alive: AliveGuard,
+        })
+    }
+
+    pub fn with_config(self, config: OrderingBuilderConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn with_root_hash_config(self, root_hash_config: RootHashConfig) -> Self {
+        Self {
+            root_hash_config,
+            ..self
+        }
+    }
+
+    /// mev-share bundles from these signers can be merged (see `ShareBundleMerger`).
+    pub fn with_sbundle_mergeable_signers(self, sbundle_mergeable_signers: Vec<Address>) -> Self {
+        Self {
+            sbundle_mergeable_signers,
+            ..self
+        }
+    }
+
+    /// Name reported on the block trace.
+    pub fn with_builder_name(self, builder_name: String) -> Self {
+        Self {
+            builder_name,
+            ..self
+        }
+    }
+
+    pub fn add_order(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    pub fn add_orders(&mut self, orders: impl IntoIterator<Item = Order>) {
+        self.orders.extend(orders);
+    }
+
+    pub fn ctx(&self) -> &BlockBuildingContext {
+        &self.ctx
+    }
+
+    /// Simulates all the orders on top of the parent and builds the block.
+    pub fn build(self) -> eyre::Result<Block> {
+        let (sim_orders, _) = simulate_all_orders_with_sim_tree(
+            self.provider.clone(),
+            &self.ctx,
+            &self.orders,
+            false,
+        )?;
+        let input = crate::building::builders::BacktestSimulateBlockInput {
+            ctx: self.ctx,
+            builder_name: self.builder_name,
+            sbundle_mergeabe_signers: self.sbundle_mergeable_signers,
+            sim_orders: &sim_orders,
+            provider: self.provider,
+            cached_reads: None,
+        };
+        let (block, _) = build_block_from_sim_orders(self.config, input, self.root_hash_config)?;
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::testing::test_chain_state::{
+        BlockArgs, NamedAddr, TestChainState, TxArgs,
+    };
+
+    #[test]
+    fn test_build_block() {
+        let test_chain = TestChainState::new(BlockArgs::default().number(11)).unwrap();
+        let tx = test_chain
+            .sign_tx(TxArgs::new_send_to_coinbase(NamedAddr::User(1), 0, 5))
+            .unwrap();
+        let order = Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+        ));
+        let order_id = order.id();
+
+        let new_builder = || {
+            EmbeddedBlockBuilder::new(
+                test_chain.provider_factory().clone(),
+                test_chain.block_building_context().clone(),
+            )
+        };
+        let mut builder = new_builder()
+            .unwrap()
+            .with_root_hash_config(RootHashConfig::skip_root_hash());
+        // one at a time
+        assert!(new_builder().is_err());
+        builder.add_order(order);
+        let block = builder.build().unwrap();
+        drop(new_builder().unwrap());
+
+        assert!(block
+            .trace
+            .included_orders
+            .iter()
+            .any(|res| res.order.id() == order_id));
+        assert_eq!(
+            block.sealed_block.number,
+            test_chain.block_building_context().block()
+        );
+    }
+}
@@ -1,6 +1,7 @@
 pub mod backtest;
 pub mod beacon_api_client;
 pub mod building;
+pub mod embed;
 pub mod integration;
 pub mod live_builder;
 pub mod mev_boost;
@@ -0,0 +1,26 @@
+//! Admin rpc server for operator only endpoints (eg: signer reputation inspection/adjustment).
+//! It's served on its own address so it can be bound to a private interface, never expose it publicly.
+
+use jsonrpsee::{server::Server, RpcModule};
+use std::net::SocketAddr;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// Starts the admin rpc server serving `module` on `addr` until `global_cancel` is cancelled.
+pub async fn start_admin_rpc_server(
+    addr: SocketAddr,
+    module: RpcModule<()>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    let server = Server::builder().http_only().build(addr).await?;
+    let handle = server.start(module);
+
+    Ok(tokio::spawn(async move {
+        info!(?addr, "Admin RPC server job: started");
+        global_cancel.cancelled().await;
+        // We ignore the error since it only means the server was already stopped.
+        let _ = handle.stop();
+        info!("Admin RPC server job: finished");
+    }))
+}
@@ -0,0 +1,610 @@
+//! Long term retention of the audit logs and the order archive ([`order_archive`]) (journals) and the performance db.
+//! Everything is shipped to an [`ArchiveStorage`] so builder hosts don't need to keep (or clean) it on local disk:
+//! - [`DirArchiveStorage`]: a local (or mounted) directory.
+//! - [`S3ArchiveStorage`]: any S3 compatible object store.
+//!
+//! Other backends only need to implement [`ArchiveStorage`] and be given to [`spawn_archiver`].
+//!
+//! Every archive interval each journal is rotated (renamed to a pending segment, writers open the path on every write
+//! so they continue on a new file) and the segments rotated on the previous interval are uploaded and deleted, the
+//! extra interval gives in flight writes to the old file time to land. The last line of a rotated journal is kept in
+//! a tip file ([`journal_tip_path`]) so hash chained journals can continue the chain after a restart.
+//! Every segment is uploaded with a [`SegmentManifest`] (its sha256 and the one of the previous manifest), the
+//! manifests of a journal form a hash chain whose newest link is the HEAD object of the journal so the archive can be
+//! checked (missing, altered or reordered segments) from the storage alone ([`verify_archived_journal`]).
+//! The performance db is uploaded as a snapshot and, if configured, rows older than the retention are then deleted
+//! from the tables with a `recorded_at` column.
+
+pub mod order_archive;
+mod s3;
+
+pub use s3::S3ArchiveStorage;
+
+use alloy_primitives::B256;
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, SqliteConnection};
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const PENDING_SEGMENT_EXTENSION: &str = "archive-pending";
+
+/// Object storage for the archives. Calls are blocking.
+pub trait ArchiveStorage: Debug + Send + Sync {
+    /// Creates or replaces the object.
+    fn put(&self, key: &str, data: Vec<u8>) -> eyre::Result<()>;
+    /// None if the object does not exist.
+    fn get(&self, key: &str) -> eyre::Result<Option<Vec<u8>>>;
+}
+
+/// Objects are files under root, keys are relative paths.
+#[derive(Debug)]
+pub struct DirArchiveStorage {
+    root: PathBuf,
+}
+
+impl DirArchiveStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl ArchiveStorage for DirArchiveStorage {
+    fn put(&self, key: &str, data: Vec<u8>) -> eyre::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // write + rename so a crash never leaves a partial object
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> eyre::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.root.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveStorageConfig {
+    Dir(PathBuf),
+    S3 {
+        /// eg: https://s3.us-east-1.amazonaws.com, objects are addressed path style (endpoint/bucket/key).
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+    },
+}
+
+impl ArchiveStorageConfig {
+    pub fn create_storage(&self) -> eyre::Result<Arc<dyn ArchiveStorage>> {
+        Ok(match self {
+            ArchiveStorageConfig::Dir(path) => Arc::new(DirArchiveStorage::new(path.clone())),
+            ArchiveStorageConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+            } => Arc::new(S3ArchiveStorage::new(
+                endpoint,
+                bucket.clone(),
+                region.clone(),
+                access_key_id.clone(),
+                secret_access_key.clone(),
+            )?),
+        })
+    }
+}
+
+/// storage is the configured backend, [`spawn_archiver`] takes the [`ArchiveStorage`] itself (eg: from
+/// [`ArchiveStorageConfig::create_storage`]) so any backend can be plugged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiverConfig {
+    pub storage: ArchiveStorageConfig,
+    /// Append only line based logs (eg: submission and exclusion audits).
+    pub journals: Vec<PathBuf>,
+    pub performance_db: Option<PathBuf>,
+    pub interval: Duration,
+    /// Performance db rows older than this are deleted after being archived, None keeps them.
+    pub performance_db_retention: Option<Duration>,
+}
+
+/// Uploaded next to every journal segment, see the module doc.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentManifest {
+    pub segment_key: String,
+    pub segment_sha256: B256,
+    /// Non empty lines of the segment.
+    pub lines: usize,
+    /// None for the first segment of the journal.
+    pub prev_manifest_key: Option<String>,
+    pub prev_manifest_sha256: Option<B256>,
+}
+
+fn sha256(data: &[u8]) -> B256 {
+    B256::from(<[u8; 32]>::from(Sha256::digest(data)))
+}
+
+fn count_lines(data: &[u8]) -> usize {
+    data.split(|byte| *byte == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .count()
+}
+
+/// Object with the key of the newest manifest of the journal.
+fn journal_head_key(journal_name: &str) -> String {
+    format!("journals/{}/HEAD", journal_name)
+}
+
+/// (key, sha256) of the newest manifest of the journal, None if nothing was archived yet.
+fn last_manifest(
+    storage: &dyn ArchiveStorage,
+    journal_name: &str,
+) -> eyre::Result<Option<(String, B256)>> {
+    let Some(head) = storage.get(&journal_head_key(journal_name))? else {
+        return Ok(None);
+    };
+    let key = String::from_utf8(head)?;
+    let manifest = storage
+        .get(&key)?
+        .ok_or_else(|| eyre::eyre!("Missing manifest {}", key))?;
+    Ok(Some((key, sha256(&manifest))))
+}
+
+/// Checks the manifest chain of the archived journal (file name of the journal) and every segment against its
+/// manifest. Returns the keys of the segments, oldest first.
+pub fn verify_archived_journal(
+    storage: &dyn ArchiveStorage,
+    journal_name: &str,
+) -> eyre::Result<Vec<String>> {
+    let mut segments = Vec::new();
+    let Some(head) = storage.get(&journal_head_key(journal_name))? else {
+        return Ok(segments);
+    };
+    let mut next = Some((String::from_utf8(head)?, None));
+    while let Some((manifest_key, expected_sha256)) = next {
+        let data = storage
+            .get(&manifest_key)?
+            .ok_or_else(|| eyre::eyre!("Missing manifest {}", manifest_key))?;
+        if expected_sha256.is_some_and(|expected| sha256(&data) != expected) {
+            eyre::bail!("Manifest {} does not match the next manifest", manifest_key);
+        }
+        let manifest: SegmentManifest = serde_json::from_slice(&data)?;
+        let segment = storage
+            .get(&manifest.segment_key)?
+            .ok_or_else(|| eyre::eyre!("Missing segment {}", manifest.segment_key))?;
+        if sha256(&segment) != manifest.segment_sha256 || count_lines(&segment) != manifest.lines {
+            eyre::bail!(
+                "Segment {} does not match its manifest",
+                manifest.segment_key
+            );
+        }
+        segments.push(manifest.segment_key);
+        next = manifest
+            .prev_manifest_key
+            .map(|key| (key, manifest.prev_manifest_sha256));
+    }
+    segments.reverse();
+    Ok(segments)
+}
+
+/// Where the last line of the journal is kept after rotating it.
+pub fn journal_tip_path(journal: &Path) -> PathBuf {
+    let mut path = journal.as_os_str().to_owned();
+    path.push(".tip");
+    PathBuf::from(path)
+}
+
+fn file_name(path: &Path) -> eyre::Result<String> {
+    Ok(path
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("No file name in {:?}", path))?
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Segments of the journal waiting to be uploaded sorted by rotation time.
+fn pending_segments(journal: &Path) -> eyre::Result<Vec<(u64, PathBuf)>> {
+    let name = file_name(journal)?;
+    let dir = match journal.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(segment_name) = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+        else {
+            continue;
+        };
+        let rotated_at = segment_name
+            .strip_prefix(&name)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.strip_suffix(PENDING_SEGMENT_EXTENSION))
+            .and_then(|rest| rest.strip_suffix('.'))
+            .and_then(|rotated_at| rotated_at.parse::<u64>().ok());
+        if let Some(rotated_at) = rotated_at {
+            segments.push((rotated_at, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+fn last_line(path: &Path) -> eyre::Result<Option<String>> {
+    let mut last = None;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            last = Some(line);
+        }
+    }
+    Ok(last)
+}
+
+/// Uploads the pending segments of the journal (each one with its manifest) and then rotates it.
+/// HEAD is only moved once the segment and its manifest are stored so a failed upload is just retried.
+fn archive_journal(storage: &dyn ArchiveStorage, journal: &Path, now_ms: u64) -> eyre::Result<()> {
+    let name = file_name(journal)?;
+    let pending = pending_segments(journal)?;
+    let mut prev_manifest = if pending.is_empty() {
+        None
+    } else {
+        last_manifest(storage, &name)?
+    };
+    for (rotated_at, segment) in pending {
+        let data = std::fs::read(&segment)?;
+        let segment_key = format!("journals/{}/{:020}", name, rotated_at);
+        let manifest_key = format!("{}.manifest", segment_key);
+        let (prev_manifest_key, prev_manifest_sha256) = prev_manifest.unzip();
+        let manifest = serde_json::to_vec(&SegmentManifest {
+            segment_key: segment_key.clone(),
+            segment_sha256: sha256(&data),
+            lines: count_lines(&data),
+            prev_manifest_key,
+            prev_manifest_sha256,
+        })?;
+        let manifest_sha256 = sha256(&manifest);
+        storage.put(&segment_key, data)?;
+        storage.put(&manifest_key, manifest)?;
+        storage.put(&journal_head_key(&name), manifest_key.clone().into_bytes())?;
+        std::fs::remove_file(&segment)?;
+        prev_manifest = Some((manifest_key, manifest_sha256));
+    }
+    if !journal.exists() || std::fs::metadata(journal)?.len() == 0 {
+        return Ok(());
+    }
+    let segment =
+        journal.with_file_name(format!("{}.{}.{}", name, now_ms, PENDING_SEGMENT_EXTENSION));
+    std::fs::rename(journal, &segment)?;
+    if let Some(last) = last_line(&segment)? {
+        std::fs::write(journal_tip_path(journal), last)?;
+    }
+    Ok(())
+}
+
+async fn snapshot_performance_db(
+    storage: Arc<dyn ArchiveStorage>,
+    db_path: &Path,
+    retention: Option<Duration>,
+    now_ms: u64,
+) -> eyre::Result<()> {
+    if !db_path.exists() {
+        return Ok(());
+    }
+    let mut conn = SqliteConnectOptions::new()
+        .filename(db_path)
+        .connect()
+        .await?;
+    let snapshot_path =
+        db_path.with_file_name(format!("{}.{}.snapshot", file_name(db_path)?, now_ms));
+    sqlx::query("VACUUM INTO ?")
+        .bind(snapshot_path.to_string_lossy().to_string())
+        .execute(&mut conn)
+        .await?;
+    let key = format!("performance_db/{}/{:020}", file_name(db_path)?, now_ms);
+    let upload_path = snapshot_path.clone();
+    let upload = tokio::task::spawn_blocking(move || -> eyre::Result<()> {
+        storage.put(&key, std::fs::read(&upload_path)?)
+    })
+    .await;
+    std::fs::remove_file(&snapshot_path)?;
+    upload??;
+
+    if let Some(retention) = retention {
+        let min_recorded_at = (OffsetDateTime::now_utc() - retention).unix_timestamp();
+        prune_performance_db(&mut conn, min_recorded_at).await?;
+    }
+    Ok(())
+}
+
+/// Deletes the rows recorded before min_recorded_at (unix secs) from the tables with a recorded_at column.
+async fn prune_performance_db(
+    conn: &mut SqliteConnection,
+    min_recorded_at: i64,
+) -> eyre::Result<()> {
+    let tables: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&mut *conn)
+            .await?;
+    for table in tables {
+        let has_recorded_at: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = 'recorded_at'",
+        )
+        .bind(table.as_str())
+        .fetch_one(&mut *conn)
+        .await?;
+        if has_recorded_at == 0 {
+            continue;
+        }
+        sqlx::query(&format!(
+            "DELETE FROM \"{}\" WHERE recorded_at < ?",
+            table.replace('"', "\"\"")
+        ))
+        .bind(min_recorded_at)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64
+}
+
+/// Archives to storage every config.interval until global_cancel.
+pub fn spawn_archiver(
+    config: ArchiverConfig,
+    storage: Arc<dyn ArchiveStorage>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    if config.interval.is_zero() {
+        eyre::bail!("Archive interval can't be 0");
+    }
+    info!(journals = ?config.journals, performance_db = ?config.performance_db, "Archiver started");
+    Ok(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                _ = tokio::time::sleep(config.interval) => {},
+            }
+            let now_ms = now_ms();
+            for journal in &config.journals {
+                let storage = storage.clone();
+                let journal_path = journal.clone();
+                let res = tokio::task::spawn_blocking(move || {
+                    archive_journal(storage.as_ref(), &journal_path, now_ms)
+                })
+                .await;
+                match res {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => warn!(?err, ?journal, "Failed to archive journal"),
+                    Err(err) => warn!(?err, ?journal, "Journal archive task failed"),
+                }
+            }
+            if let Some(db_path) = &config.performance_db {
+                if let Err(err) = snapshot_performance_db(
+                    storage.clone(),
+                    db_path,
+                    config.performance_db_retention,
+                    now_ms,
+                )
+                .await
+                .with_context(|| format!("Archiving performance db {:?}", db_path))
+                {
+                    warn!(?err, "Failed to archive performance db");
+                }
+            }
+        }
+        info!("Archiver finished");
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live_builder::block_output::submission_audit::{
+        verify_submission_audit_chain, SubmissionAuditEntry, SubmissionAuditLog,
+        SubmissionAuditRecord,
+    };
+    use alloy_primitives::{Address, FixedBytes, B256, U256};
+    use sqlx::Executor;
+
+    fn record(slot: u64) -> SubmissionAuditRecord {
+        SubmissionAuditRecord {
+            timestamp_ms: 1_700_000_000_000,
+            slot,
+            relay: "relay1".to_string(),
+            optimistic: false,
+            block_hash: B256::with_last_byte(1),
+            proposer_fee_recipient: Address::with_last_byte(2),
+            value: U256::from(1_000),
+            payload_hash: None,
+            signature: FixedBytes::with_last_byte(4),
+            response_code: Some(200),
+            response_digest: None,
+            error: None,
+        }
+    }
+
+    fn entries(data: &[u8]) -> Vec<SubmissionAuditEntry> {
+        String::from_utf8_lossy(data)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_archive_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = DirArchiveStorage::new(dir.path().join("archive"));
+        let journal = dir.path().join("submissions.jsonl");
+
+        Arc::new(SubmissionAuditLog::open(&journal).unwrap())
+            .append(record(1))
+            .await;
+        archive_journal(&storage, &journal, 1).unwrap();
+        // rotated but not uploaded yet
+        assert!(!journal.exists());
+        assert_eq!(pending_segments(&journal).unwrap().len(), 1);
+        assert!(storage
+            .get("journals/submissions.jsonl/00000000000000000001")
+            .unwrap()
+            .is_none());
+
+        // restart after the rotation, the chain continues from the tip
+        let log = Arc::new(SubmissionAuditLog::open(&journal).unwrap());
+        log.append(record(2)).await;
+        archive_journal(&storage, &journal, 2).unwrap();
+        archive_journal(&storage, &journal, 3).unwrap();
+        assert!(pending_segments(&journal).unwrap().is_empty());
+
+        let segments = verify_archived_journal(&storage, "submissions.jsonl").unwrap();
+        assert_eq!(
+            segments,
+            [1, 2]
+                .map(|rotated_at| format!("journals/submissions.jsonl/{:020}", rotated_at))
+                .to_vec()
+        );
+        let mut archived = Vec::new();
+        for key in &segments {
+            archived.extend(entries(&storage.get(key).unwrap().unwrap()));
+        }
+        assert_eq!(archived.len(), 2);
+        assert!(verify_submission_audit_chain(&archived));
+        // nothing new to rotate
+        archive_journal(&storage, &journal, 4).unwrap();
+        assert!(pending_segments(&journal).unwrap().is_empty());
+        assert_eq!(
+            verify_archived_journal(&storage, "submissions.jsonl").unwrap(),
+            segments
+        );
+        assert!(verify_archived_journal(&storage, "other.jsonl")
+            .unwrap()
+            .is_empty());
+
+        // altered segment
+        let mut altered = storage.get(&segments[0]).unwrap().unwrap();
+        altered[0] ^= 1;
+        storage.put(&segments[0], altered).unwrap();
+        assert!(verify_archived_journal(&storage, "submissions.jsonl").is_err());
+    }
+
+    #[test]
+    fn test_verify_archived_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = DirArchiveStorage::new(dir.path().join("archive"));
+        let journal = dir.path().join("journal.jsonl");
+        for rotated_at in 1..=3 {
+            std::fs::write(&journal, format!("{{\"line\":{}}}\n", rotated_at)).unwrap();
+            archive_journal(&storage, &journal, rotated_at).unwrap();
+        }
+        archive_journal(&storage, &journal, 4).unwrap();
+        assert_eq!(
+            verify_archived_journal(&storage, "journal.jsonl")
+                .unwrap()
+                .len(),
+            3
+        );
+        let key = |rotated_at: u64| format!("journals/journal.jsonl/{:020}", rotated_at);
+
+        // a manifest replaced by one of another journal (or rewritten) breaks the chain
+        let first_manifest = storage
+            .get(&format!("{}.manifest", key(1)))
+            .unwrap()
+            .unwrap();
+        let mut manifest: SegmentManifest = serde_json::from_slice(&first_manifest).unwrap();
+        manifest.lines = 2;
+        storage
+            .put(
+                &format!("{}.manifest", key(1)),
+                serde_json::to_vec(&manifest).unwrap(),
+            )
+            .unwrap();
+        assert!(verify_archived_journal(&storage, "journal.jsonl").is_err());
+        storage
+            .put(&format!("{}.manifest", key(1)), first_manifest)
+            .unwrap();
+        assert!(verify_archived_journal(&storage, "journal.jsonl").is_ok());
+
+        // missing segment
+        std::fs::remove_file(dir.path().join("archive").join(key(2))).unwrap();
+        assert!(verify_archived_journal(&storage, "journal.jsonl").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_archive_performance_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(DirArchiveStorage::new(dir.path().join("archive")));
+        let db_path = dir.path().join("perf.sqlite");
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&db_path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        conn.execute("CREATE TABLE reports (recorded_at INTEGER NOT NULL)")
+            .await
+            .unwrap();
+        conn.execute("CREATE TABLE other (x INTEGER)")
+            .await
+            .unwrap();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for recorded_at in [now - 3 * 86_400, now] {
+            sqlx::query("INSERT INTO reports (recorded_at) VALUES (?)")
+                .bind(recorded_at)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+        }
+        conn.execute("INSERT INTO other (x) VALUES (1)")
+            .await
+            .unwrap();
+
+        snapshot_performance_db(
+            storage.clone(),
+            &db_path,
+            Some(Duration::from_secs(86_400)),
+            7,
+        )
+        .await
+        .unwrap();
+        assert!(storage
+            .get("performance_db/perf.sqlite/00000000000000000007")
+            .unwrap()
+            .is_some());
+        let reports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reports")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(reports, 1);
+        let other: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM other")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(other, 1);
+    }
+}
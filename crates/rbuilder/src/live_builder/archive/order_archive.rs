@@ -0,0 +1,155 @@
+//! Order archive: journal of every order command the orderpool gets (one json line per [`OrderArchiveEntry`], same
+//! messages as [`orderpool_sync`](crate::live_builder::order_input::orderpool_sync)) so the orderflow we built on can be audited or replayed long after the builder
+//! hosts are gone. It's archived (rotated, uploaded and verifiable) like the audit logs, see [`super`].
+//! Orders with blobs are not recorded (their messages can't carry the blobs).
+//!
+//! The orderpool loop only queues the entries, a task writes them in batches off the async threads. If the queue is
+//! full (disk too slow) entries are dropped instead of slowing down the intake.
+
+use crate::live_builder::order_input::{orderpool_sync::SyncMessage, ReplaceableOrderPoolCommand};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{error, info, warn};
+
+const QUEUE_SIZE: usize = 100_000;
+const WRITE_BATCH_SIZE: usize = 1_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderArchiveEntry {
+    pub received_at_ms: u64,
+    /// Got from an orderflow sharing peer.
+    pub from_peer: bool,
+    pub message: SyncMessage,
+}
+
+/// Cheap to clone, clones queue to the same writer.
+#[derive(Debug, Clone)]
+pub struct OrderArchive {
+    sender: mpsc::Sender<OrderArchiveEntry>,
+}
+
+impl OrderArchive {
+    /// Spawns the task appending to path, it finishes once every clone is dropped.
+    pub fn spawn(path: PathBuf) -> eyre::Result<(Self, JoinHandle<()>)> {
+        // fail on startup and not on the first write
+        OpenOptions::new().create(true).append(true).open(&path)?;
+        let (sender, mut receiver) = mpsc::channel(QUEUE_SIZE);
+        let handle = tokio::spawn(async move {
+            info!(?path, "Order archive started");
+            let mut entries = Vec::new();
+            while receiver.recv_many(&mut entries, WRITE_BATCH_SIZE).await != 0 {
+                let path = path.clone();
+                let batch = std::mem::take(&mut entries);
+                match tokio::task::spawn_blocking(move || write_entries(&path, &batch)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => error!(?err, "Failed to write order archive"),
+                    Err(err) => error!(?err, "Order archive write task failed"),
+                }
+            }
+            info!("Order archive finished");
+        });
+        Ok((Self { sender }, handle))
+    }
+
+    pub fn record(&self, command: &ReplaceableOrderPoolCommand, from_peer: bool) {
+        let Some(message) = SyncMessage::from_command(command) else {
+            return;
+        };
+        let entry = OrderArchiveEntry {
+            received_at_ms: (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64,
+            from_peer,
+            message,
+        };
+        if self.sender.try_send(entry).is_err() {
+            warn!("Order archive queue full, dropping entry");
+        }
+    }
+}
+
+/// Opens the path on every batch so a rotated journal continues on a new file.
+fn write_entries(path: &Path, entries: &[OrderArchiveEntry]) -> eyre::Result<()> {
+    let mut data = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut data, entry)?;
+        data.push(b'\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&data)?;
+    Ok(())
+}
+
+pub fn read_order_archive_entries(path: impl AsRef<Path>) -> eyre::Result<Vec<OrderArchiveEntry>> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::{
+            archive::{
+                archive_journal, verify_archived_journal, ArchiveStorage, DirArchiveStorage,
+            },
+            order_input::CancelBundleByHash,
+        },
+        primitives::{MempoolTx, Order},
+        utils::test_utils::tx,
+    };
+    use alloy_primitives::{Address, B256};
+
+    #[tokio::test]
+    async fn test_order_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.jsonl");
+        let (archive, handle) = OrderArchive::spawn(path.clone()).unwrap();
+        let order = ReplaceableOrderPoolCommand::Order(Order::Tx(MempoolTx::new(tx(1))));
+        let cancel = ReplaceableOrderPoolCommand::CancelBundleByHash(CancelBundleByHash {
+            hash: B256::with_last_byte(2),
+            signer: Address::with_last_byte(3),
+        });
+        archive.record(&order, false);
+        archive.record(&cancel, true);
+        drop(archive);
+        handle.await.unwrap();
+
+        let entries = read_order_archive_entries(&path).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.from_peer, entry.message.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (false, SyncMessage::from_command(&order).unwrap()),
+                (true, SyncMessage::from_command(&cancel).unwrap()),
+            ]
+        );
+
+        // archived and verifiable like the audit logs
+        let storage = DirArchiveStorage::new(dir.path().join("archive"));
+        archive_journal(&storage, &path, 1).unwrap();
+        archive_journal(&storage, &path, 2).unwrap();
+        let segments = verify_archived_journal(&storage, "orders.jsonl").unwrap();
+        assert_eq!(segments.len(), 1);
+        let archived = storage.get(&segments[0]).unwrap().unwrap();
+        let archived_path = dir.path().join("archived.jsonl");
+        std::fs::write(&archived_path, archived).unwrap();
+        assert_eq!(read_order_archive_entries(&archived_path).unwrap(), entries);
+    }
+}
@@ -0,0 +1,187 @@
+//! Minimal S3 client (path style PUT/GET signed with AWS SigV4), enough for the archives and compatible with most
+//! object stores (AWS, R2, MinIO...).
+//! Only the SigV4 canonical request is built here, the HMACs come from the hmac crate.
+
+use super::ArchiveStorage;
+use alloy_primitives::hex;
+use hmac::{Hmac, Mac};
+use reqwest::{blocking::Client, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use time::{macros::format_description, OffsetDateTime};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const SERVICE: &str = "s3";
+
+#[derive(Debug)]
+pub struct S3ArchiveStorage {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    HmacSha256::new_from_slice(key)
+        .expect("hmac takes keys of any size")
+        .chain_update(data)
+        .finalize()
+        .into_bytes()
+        .into()
+}
+
+/// date: yyyymmdd
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// RFC 3986 encoding of every path segment.
+fn uri_encode_path(path: &str) -> String {
+    let mut res = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                res.push(byte as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    res
+}
+
+impl S3ArchiveStorage {
+    pub fn new(
+        endpoint: &str,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            endpoint: Url::parse(endpoint)?,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+        })
+    }
+
+    /// (url, authorization, x-amz-date, x-amz-content-sha256)
+    fn signed_request(
+        &self,
+        method: &str,
+        key: &str,
+        payload: &[u8],
+        now: OffsetDateTime,
+    ) -> eyre::Result<(Url, String, String, String)> {
+        let canonical_uri = uri_encode_path(&format!("/{}/{}", self.bucket, key));
+        let mut url = self.endpoint.clone();
+        url.set_path(&canonical_uri);
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => eyre::bail!("No host in s3 endpoint {}", self.endpoint),
+        };
+        let amz_date = now.format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))?;
+        let date = &amz_date[..8];
+        let payload_hash = hex::encode(Sha256::digest(payload));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, canonical_uri, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hex::encode(hmac_sha256(
+            &signing_key(&self.secret_access_key, date, &self.region, SERVICE),
+            string_to_sign.as_bytes(),
+        ));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        );
+        Ok((url, authorization, amz_date, payload_hash))
+    }
+}
+
+impl ArchiveStorage for S3ArchiveStorage {
+    fn put(&self, key: &str, data: Vec<u8>) -> eyre::Result<()> {
+        let (url, authorization, amz_date, payload_hash) =
+            self.signed_request("PUT", key, &data, OffsetDateTime::now_utc())?;
+        let response = self
+            .client
+            .put(url)
+            .header("authorization", authorization)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .body(data)
+            .send()?;
+        if !response.status().is_success() {
+            eyre::bail!("S3 put {} failed: {}", key, response.status());
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> eyre::Result<Option<Vec<u8>>> {
+        let (url, authorization, amz_date, payload_hash) =
+            self.signed_request("GET", key, &[], OffsetDateTime::now_utc())?;
+        let response = self
+            .client
+            .get(url)
+            .header("authorization", authorization)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .send()?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes()?.to_vec())),
+            status => eyre::bail!("S3 get {} failed: {}", key, status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sigv4_primitives() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // example from the AWS SigV4 docs
+        assert_eq!(
+            hex::encode(signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+        assert_eq!(
+            uri_encode_path("/bucket/journals/a b.jsonl"),
+            "/bucket/journals/a%20b.jsonl"
+        );
+    }
+}
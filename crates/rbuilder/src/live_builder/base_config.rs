@@ -1,15 +1,39 @@
 //! Config should always be deserializable, default values should be used
 //!
 use crate::{
-    building::builders::UnfinishedBlockBuildingSinkFactory,
-    live_builder::{order_input::OrderInputConfig, LiveBuilder},
+    building::{
+        builders::UnfinishedBlockBuildingSinkFactory,
+        extra_data::{ExtraDataTemplate, DEFAULT_BUILDER_NAME},
+        gas_price_oracle::GasPriceOracle,
+        sim_queue::SimQueueWeights,
+        victim_protection::VictimProtection,
+    },
+    live_builder::{
+        archive::{ArchiveStorageConfig, ArchiverConfig},
+        block_output::refund_settlement::RefundSettlementConfig,
+        building::late_order_fast_path::LateOrderFastPathConfig,
+        leader_election::{LeaderElectionConfig, LeaseBackendConfig},
+        node_health::NodeHealthConfig,
+        order_input::{
+            orderflow_sharing::OrderflowSharingConfig,
+            orderpool_sync::OrderPoolSyncConfig,
+            tenants::TenantConfig,
+            txpool_fetcher::{Devp2pListenerConfig, MempoolSourceConfig},
+            OrderInputConfig, RpcTransport, DEFAULT_BLOCKING_DECODE_MIN_SIZE,
+            DEFAULT_MAX_BATCH_SIZE, DEFAULT_MAX_BLOCKING_DECODES, DEFAULT_MAX_BUNDLE_TXS,
+            DEFAULT_MAX_REQUEST_BODY_SIZE,
+        },
+        slot_outcome_predictor::SlotOutcomePredictorConfig,
+        LiveBuilder,
+    },
     roothash::RootHashConfig,
     telemetry::{setup_reloadable_tracing_subscriber, LoggerConfig},
-    utils::{http_provider, BoxedProvider, ProviderFactoryReopener, Signer},
+    utils::{clock::system_clock, http_provider, BoxedProvider, ProviderFactoryReopener, Signer},
 };
 use ahash::HashSet;
-use alloy_primitives::{Address, B256};
+use alloy_primitives::{utils::parse_ether, Address, B256};
 use eyre::{eyre, Context};
+use ipnet::IpNet;
 use jsonrpsee::RpcModule;
 use lazy_static::lazy_static;
 use reth::chainspec::chain_value_parser;
@@ -60,16 +84,135 @@ pub struct BaseConfig {
 
     pub error_storage_path: Option<PathBuf>,
 
+    /// sqlite performance db where per slot resource reports are persisted (see [`crate::live_builder::slot_resource_report`]).
+    pub slot_resource_report_db_path: Option<PathBuf>,
+
+    /// Audit logs, the order archive and performance db snapshots are archived (see [`crate::live_builder::archive`]) to
+    /// archive_dir or, if archive_s3_bucket is set, to an S3 compatible object store. Nothing is archived if neither is
+    /// set.
+    pub archive_dir: Option<PathBuf>,
+    pub archive_s3_endpoint: String,
+    pub archive_s3_bucket: Option<String>,
+    pub archive_s3_region: String,
+    pub archive_s3_access_key_id: Option<EnvOrValue<String>>,
+    pub archive_s3_secret_access_key: Option<EnvOrValue<String>>,
+    pub archive_interval_secs: u64,
+    /// Performance db rows older than this are deleted once archived, kept forever if not set.
+    pub performance_db_retention_days: Option<u64>,
+    /// If set every order command we get is journaled here (see [`crate::live_builder::archive::order_archive`]).
+    pub order_archive_path: Option<PathBuf>,
+
+    /// sqlite db where signer reputations are persisted. Reputation tracking is disabled if not set.
+    pub signer_reputation_db_path: Option<PathBuf>,
+
+    /// Live tuned algorithm params (see [`crate::building::builders::algorithm_params`]) are persisted here, without it
+    /// they are lost on restart.
+    pub algorithm_params_path: Option<PathBuf>,
+
+    /// Aggregates the state accesses of simulated orders per hour, served by the admin rpc.
+    pub state_access_heatmap: bool,
+
+    /// Max contract bytecodes kept in memory across slots, 0 disables the cache.
+    pub bytecode_cache_size: usize,
+
+    /// Admin rpc (operator only endpoints) is disabled if not set.
+    pub admin_rpc_server_port: Option<u16>,
+    /// Defaults to 127.0.0.1 since the admin rpc should not be public. An invalid ip fails the config parsing.
+    pub admin_rpc_server_ip: Option<Ipv4Addr>,
+
+    /// Leader election between replicas (see [`crate::live_builder::leader_election`]), only the leader submits to the relays.
+    /// Disabled unless one of leader_election_lease_file (replicas on the same host) or leader_election_redis_url is set.
+    pub leader_election_lease_file: Option<PathBuf>,
+    pub leader_election_redis_url: Option<EnvOrValue<String>>,
+    pub leader_election_redis_key: String,
+    /// Unique per replica, random if not set.
+    pub leader_election_replica_id: Option<String>,
+    pub leader_election_lease_ttl_ms: u64,
+
+    /// If set, relay submissions are paused while the head block of the node lags the head expected for the current
+    /// slot by more than this (node syncing or stalled) and resumed when it's back under half of it (see
+    /// [`crate::live_builder::node_health`]).
+    pub node_max_head_lag_secs: Option<u64>,
+
+    /// Start in warm standby: build and bid as usual but only record what would be submitted until promoted via
+    /// admin_promote or by becoming leader (see [`crate::live_builder::warm_standby`]).
+    pub warm_standby: bool,
+
     coinbase_secret_key: EnvOrValue<String>,
 
     pub flashbots_db: Option<EnvOrValue<String>>,
 
     pub el_node_ipc_path: PathBuf,
+    /// If false we don't take the mempool txs from the el node (el_node_ipc_path is still needed for the new heads),
+    /// only with devp2p_mempool.
+    pub el_node_mempool: bool,
+    /// Extra nodes (ipc or ws) to get mempool txs from besides the el node, txs are deduplicated.
+    pub mempool_sources: Vec<MempoolSourceConfig>,
+    /// Also get mempool txs by peering with the devp2p network ourselves (needs the devp2p feature).
+    pub devp2p_mempool: Option<Devp2pListenerConfig>,
     pub jsonrpc_server_port: u16,
     pub jsonrpc_server_ip: Option<String>,
+    /// http (default), ws or http-and-ws.
+    pub jsonrpc_server_transport: RpcTransport,
+    /// Reject eth_cancelBundle without a signature (or X-Flashbots-Signature) of signingAddress. Only disable it if the
+    /// input rpc is behind a proxy authenticating the signers.
+    pub jsonrpc_require_signed_cancellations: bool,
+    /// Reject bundles without a valid X-Flashbots-Signature. Set it if the input rpc is not behind a proxy
+    /// authenticating the signers (a good signature is always used, this only makes it mandatory).
+    /// WebSocket messages and gRPC calls can't be signed: ws bundles get rejected and grpc_server_port can't be set.
+    pub jsonrpc_require_flashbots_signature: bool,
+    /// Input RPC request limits (see [`crate::live_builder::order_input::RequestLimits`]).
+    pub jsonrpc_server_max_request_body_size: u32,
+    pub jsonrpc_server_max_batch_size: u32,
+    pub jsonrpc_server_max_bundle_txs: usize,
+    pub jsonrpc_server_blocking_decode_min_size: usize,
+    pub jsonrpc_server_max_blocking_decodes: usize,
+    /// Input RPC requests per second of each X-Flashbots-Signature signer (see
+    /// [`crate::live_builder::order_input::rpc_rate_limit`]). Unset: signed requests are limited by ip.
+    pub jsonrpc_signer_rate_limit: Option<u32>,
+    /// Input RPC requests per second of each client ip. Unset: unlimited.
+    pub jsonrpc_ip_rate_limit: Option<u32>,
+    /// CIDRs of the proxies in front of the input RPC, for them the client ip is taken from
+    /// X-Forwarded-For/X-Real-IP (e.g. ["10.0.0.0/8"]).
+    pub jsonrpc_trusted_proxies: Vec<String>,
+    /// If set we also take orders over gRPC (see [`crate::live_builder::order_input::grpc_server`]) on this port
+    /// (same ip as the json rpc). Needs the grpc feature.
+    pub grpc_server_port: Option<u16>,
+    /// gRPC calls are not authenticated so bundles with signing_address are rejected, set it if the gRPC server is
+    /// behind a proxy authenticating the signers.
+    pub grpc_trust_signing_address: bool,
 
     pub ignore_cancellable_orders: bool,
     pub ignore_blobs: bool,
+    /// Names we are known by in mev-share bundles privacy.builders. Bundles listing builders are only accepted if they
+    /// list one of these.
+    pub builder_names: Vec<String>,
+
+    /// Orderflow partners with isolated quotas (see [`crate::live_builder::order_input::tenants`]).
+    pub tenants: Vec<TenantConfig>,
+    /// Delegated cgroup v2 where the simulation workers of sandboxed tenants get their CPU capped cgroups
+    /// (see [`crate::live_builder::simulation::tenant_sandbox`]).
+    pub tenant_cgroup_root: Option<PathBuf>,
+
+    /// Orderpool warm sync for HA deployments (see [`crate::live_builder::order_input::orderpool_sync`]).
+    /// If set standby instances can follow our orderpool on this port.
+    pub orderpool_sync_server_port: Option<u16>,
+    pub orderpool_sync_server_ip: Option<String>,
+    /// host:port of the active instance orderpool sync server to follow.
+    pub orderpool_sync_peer: Option<String>,
+    /// Mandatory if the sync server or peer is set.
+    pub orderpool_sync_secret: Option<EnvOrValue<String>>,
+
+    /// Orderflow sharing with other builders (see [`crate::live_builder::order_input::orderflow_sharing`]).
+    /// Our name for the peers, defaults to the first builder_names.
+    pub orderflow_sharing_origin: Option<String>,
+    /// If set we accept the orderflow of the peers on this port (path /orderflow).
+    pub orderflow_sharing_ingress_port: Option<u16>,
+    pub orderflow_sharing_ingress_ip: Option<String>,
+    /// Ingress urls of the peers we forward our bundles and cancellations to, https (plain http only for loopback).
+    pub orderflow_sharing_peers: Vec<String>,
+    /// Mandatory if the ingress or some peer is set, same for all the peers.
+    pub orderflow_sharing_secret: Option<EnvOrValue<String>>,
 
     pub chain: String,
     pub reth_datadir: Option<PathBuf>,
@@ -77,6 +220,7 @@ pub struct BaseConfig {
     pub reth_static_files_path: Option<PathBuf>,
 
     pub blocklist_file_path: Option<PathBuf>,
+    /// Template (see [`crate::building::extra_data`]), relays can override it.
     pub extra_data: String,
 
     /// mev-share bundles coming from this address are treated in a special way(see [`ShareBundleMerger`])
@@ -85,6 +229,58 @@ pub struct BaseConfig {
     /// Number of threads used for incoming order simulation
     pub simulation_threads: usize,
 
+    /// Number of threads shared between incoming order simulation and block building.
+    /// They are rebalanced during the slot depending on queue depths and time left (see [`crate::live_builder::simulation::shared_workers`]).
+    pub shared_worker_threads: usize,
+
+    /// Weights of bundles, share bundles and mempool txs in the simulation queue, each class gets at least its share
+    /// of the simulations (see [`crate::building::sim_queue`]).
+    pub sim_queue_weights: SimQueueWeights,
+
+    /// If set, slots with a predicted win probability (see [`crate::live_builder::slot_outcome_predictor`]) below this
+    /// are built with reduced effort.
+    pub slot_outcome_min_win_probability_bps: Option<u64>,
+    /// Weight (in slots) of our global win rate when estimating the win rate for a fee recipient.
+    pub slot_outcome_prior_slots: u64,
+
+    /// Our own txs (payouts, refunds) stay valid for this many blocks of max base fee increase
+    /// (see [`crate::building::gas_price_oracle`]). 0 (default) prices them at the base fee.
+    pub own_tx_base_fee_headroom_blocks: u32,
+    /// Priority fee (wei) of our own txs. Inside our blocks it goes back to us.
+    pub own_tx_priority_fee_per_gas: u64,
+
+    /// If set, orders simulated in the last late_order_fast_path_window_ms before the slot are appended to the best block
+    /// instead of waiting for the builders (see [`crate::live_builder::building::late_order_fast_path`]).
+    pub late_order_fast_path_window_ms: Option<u64>,
+    /// Min simulated coinbase profit for an order to use the late order fast path.
+    pub late_order_fast_path_min_profit_eth: String,
+    /// Enables mev_simBundle on the order input rpc, bundles can be simulated on the parent block or on the best
+    /// block built so far (see [`crate::live_builder::building::sim_bundle`]).
+    pub sim_bundle: bool,
+    /// X-Flashbots-Signature signers allowed to simulate on the block being built (mev_simBundle pending option).
+    /// Empty: nobody.
+    pub sim_bundle_pending_signers: Vec<Address>,
+    /// mev_simBundle/eth_callBundle simulations running at once, the rest are rejected.
+    pub sim_bundle_max_concurrent_simulations: usize,
+    /// If set, building sessions pull new orders from the orderpool every orderpool_delta_interval_ms instead of
+    /// getting each one as it arrives (see [`crate::live_builder::order_input::orderpool_delta`]).
+    pub orderpool_delta_interval_ms: Option<u64>,
+
+    /// If set, mev-share refunds are not paid in our blocks, they accrue on this ledger and are paid periodically
+    /// (see [`crate::live_builder::block_output::refund_settlement`]).
+    pub refund_settlement_ledger_path: Option<PathBuf>,
+    pub refund_settlement_interval_secs: u64,
+    /// Accrued refunds below this are kept for later settlements.
+    pub refund_settlement_min_value_eth: String,
+    /// Node receiving the settlement txs (eth_sendRawTransaction).
+    pub refund_settlement_rpc_url: Option<String>,
+    /// Only log the settlements.
+    pub refund_settlement_dry_run: bool,
+
+    /// If set, sbundles leaving their user txs more than this (basis points) worse than executed alone are rejected
+    /// (see [`crate::building::victim_protection`]).
+    pub victim_protection_max_loss_bps: Option<u64>,
+
     /// uses cached sparse trie for root hash
     pub root_hash_use_sparse_trie: bool,
     /// compares result of root hash using sparse trie and reference root hash
@@ -104,6 +300,8 @@ pub struct BaseConfig {
     pub backtest_builders: Vec<String>,
     pub backtest_results_store_path: PathBuf,
     pub backtest_protect_bundle_signers: Vec<Address>,
+    /// Threads of the conflict detection of backtest-conflict-graph (0 = one per core).
+    pub backtest_conflict_detection_threads: usize,
 }
 
 lazy_static! {
@@ -160,6 +358,13 @@ impl BaseConfig {
         ))
     }
 
+    pub fn admin_rpc_server_address(&self) -> Option<SocketAddr> {
+        self.admin_rpc_server_port.map(|port| {
+            let ip = self.admin_rpc_server_ip.unwrap_or(Ipv4Addr::LOCALHOST);
+            SocketAddr::V4(SocketAddrV4::new(ip, port))
+        })
+    }
+
     pub fn full_telemetry_server_address(&self) -> SocketAddr {
         SocketAddr::V4(SocketAddrV4::new(
             self.full_telemetry_server_ip(),
@@ -207,12 +412,37 @@ impl BaseConfig {
         SlotSourceType: SlotSource,
     {
         let order_input_config = OrderInputConfig::from_config(self)?;
+        let sim_bundle = order_input_config
+            .sim_bundle
+            .as_ref()
+            .map(|service| service.simulators().clone());
         let (orderpool_sender, orderpool_receiver) =
             mpsc::channel(order_input_config.input_channel_buffer_size);
         Ok(LiveBuilder::<P, DB, SlotSourceType> {
             watchdog_timeout: self.watchdog_timeout(),
             error_storage_path: self.error_storage_path.clone(),
+            slot_resource_report_db_path: self.slot_resource_report_db_path.clone(),
+            signer_reputation_db_path: self.signer_reputation_db_path.clone(),
+            algorithm_params_path: self.algorithm_params_path.clone(),
+            admin_rpc_server_address: self.admin_rpc_server_address(),
+            leader_election: self.leader_election_config()?,
+            node_health: self
+                .node_max_head_lag_secs
+                .map(|secs| NodeHealthConfig::new(Duration::from_secs(secs))),
+            warm_standby: self.warm_standby,
+            slot_outcome_predictor: self.slot_outcome_predictor_config(),
+            state_access_heatmap: self.state_access_heatmap,
+            bytecode_cache_size: self.bytecode_cache_size,
+            gas_price_oracle: self.gas_price_oracle(),
+            refund_settlement: self.refund_settlement_config()?,
+            victim_protection: self
+                .victim_protection_max_loss_bps
+                .map(|max_loss_bps| VictimProtection { max_loss_bps }),
+            clock: system_clock(),
             simulation_threads: self.simulation_threads,
+            shared_worker_threads: self.shared_worker_threads,
+            tenant_cgroup_root: self.tenant_cgroup_root.clone(),
+            sim_queue_weights: self.sim_queue_weights,
             order_input_config,
             blocks_source: slot_source,
             chain_chain_spec: self.chain_spec()?,
@@ -226,9 +456,13 @@ impl BaseConfig {
 
             extra_rpc: RpcModule::new(()),
             sink_factory,
+            block_inclusion_observers: Default::default(),
             builders: Vec::new(),
 
             run_sparse_trie_prefetcher: self.root_hash_use_sparse_trie,
+            late_order_fast_path: self.late_order_fast_path_config()?,
+            sim_bundle,
+            orderpool_delta_interval: self.orderpool_delta_interval_ms.map(Duration::from_millis),
 
             orderpool_sender,
             orderpool_receiver,
@@ -239,6 +473,17 @@ impl BaseConfig {
         parse_ip(&self.jsonrpc_server_ip)
     }
 
+    pub fn jsonrpc_trusted_proxies(&self) -> eyre::Result<Vec<IpNet>> {
+        self.jsonrpc_trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy.parse().map_err(|err| {
+                    eyre::eyre!("invalid jsonrpc_trusted_proxies {}: {}", proxy, err)
+                })
+            })
+            .collect()
+    }
+
     pub fn redacted_telemetry_server_ip(&self) -> Ipv4Addr {
         parse_ip(&self.redacted_telemetry_server_ip)
     }
@@ -289,12 +534,15 @@ impl BaseConfig {
         coinbase_signer_from_secret_key(&self.coinbase_secret_key.value()?)
     }
 
-    pub fn extra_data(&self) -> eyre::Result<Vec<u8>> {
-        let extra_data = self.extra_data.clone().into_bytes();
-        if extra_data.len() > 32 {
-            return Err(eyre::eyre!("Extra data is too long"));
-        }
-        Ok(extra_data)
+    pub fn extra_data(&self) -> eyre::Result<ExtraDataTemplate> {
+        ExtraDataTemplate::parse(&self.extra_data, self.extra_data_builder_name())
+    }
+
+    /// {builder_name} of the extra data templates.
+    pub fn extra_data_builder_name(&self) -> &str {
+        self.builder_names
+            .first()
+            .map_or(DEFAULT_BUILDER_NAME, String::as_str)
     }
 
     pub fn blocklist(&self) -> eyre::Result<HashSet<Address>> {
@@ -329,6 +577,175 @@ impl BaseConfig {
         }
     }
 
+    pub fn late_order_fast_path_config(&self) -> eyre::Result<Option<LateOrderFastPathConfig>> {
+        let Some(window_ms) = self.late_order_fast_path_window_ms else {
+            return Ok(None);
+        };
+        Ok(Some(LateOrderFastPathConfig {
+            window: Duration::from_millis(window_ms),
+            min_coinbase_profit: parse_ether(&self.late_order_fast_path_min_profit_eth)?,
+        }))
+    }
+
+    pub fn refund_settlement_config(&self) -> eyre::Result<Option<RefundSettlementConfig>> {
+        let Some(ledger_path) = self.refund_settlement_ledger_path.clone() else {
+            return Ok(None);
+        };
+        Ok(Some(RefundSettlementConfig {
+            ledger_path,
+            interval: Duration::from_secs(self.refund_settlement_interval_secs),
+            min_value: parse_ether(&self.refund_settlement_min_value_eth)?,
+            rpc_url: self.refund_settlement_rpc_url.clone(),
+            dry_run: self.refund_settlement_dry_run,
+        }))
+    }
+
+    pub fn gas_price_oracle(&self) -> GasPriceOracle {
+        GasPriceOracle {
+            base_fee_headroom_blocks: self.own_tx_base_fee_headroom_blocks,
+            priority_fee_per_gas: self.own_tx_priority_fee_per_gas as u128,
+        }
+    }
+
+    pub fn slot_outcome_predictor_config(&self) -> Option<SlotOutcomePredictorConfig> {
+        self.slot_outcome_min_win_probability_bps
+            .map(|bps| SlotOutcomePredictorConfig {
+                min_win_probability: bps as f64 / 10_000.0,
+                prior_slots: self.slot_outcome_prior_slots,
+            })
+    }
+
+    /// journals: audit logs to archive besides the ones of the base config (the order archive).
+    pub fn archiver_config(
+        &self,
+        mut journals: Vec<PathBuf>,
+    ) -> eyre::Result<Option<ArchiverConfig>> {
+        let storage = match (&self.archive_dir, &self.archive_s3_bucket) {
+            (None, None) => return Ok(None),
+            (Some(path), None) => ArchiveStorageConfig::Dir(path.clone()),
+            (None, Some(bucket)) => {
+                let credential = |value: &Option<EnvOrValue<String>>, name: &str| {
+                    value
+                        .as_ref()
+                        .ok_or_else(|| eyre::eyre!("{} must be set to archive to s3", name))
+                        .and_then(|value| value.value())
+                };
+                ArchiveStorageConfig::S3 {
+                    endpoint: self.archive_s3_endpoint.clone(),
+                    bucket: bucket.clone(),
+                    region: self.archive_s3_region.clone(),
+                    access_key_id: credential(
+                        &self.archive_s3_access_key_id,
+                        "archive_s3_access_key_id",
+                    )?,
+                    secret_access_key: credential(
+                        &self.archive_s3_secret_access_key,
+                        "archive_s3_secret_access_key",
+                    )?,
+                }
+            }
+            (Some(_), Some(_)) => {
+                eyre::bail!("Only one of archive_dir and archive_s3_bucket can be set")
+            }
+        };
+        journals.extend(self.order_archive_path.clone());
+        Ok(Some(ArchiverConfig {
+            storage,
+            journals,
+            performance_db: self.slot_resource_report_db_path.clone(),
+            interval: Duration::from_secs(self.archive_interval_secs),
+            performance_db_retention: self
+                .performance_db_retention_days
+                .map(|days| Duration::from_secs(days * 24 * 3600)),
+        }))
+    }
+
+    pub fn leader_election_config(&self) -> eyre::Result<Option<LeaderElectionConfig>> {
+        let backend = match (
+            &self.leader_election_lease_file,
+            &self.leader_election_redis_url,
+        ) {
+            (None, None) => return Ok(None),
+            (Some(path), None) => LeaseBackendConfig::File(path.clone()),
+            (None, Some(url)) => LeaseBackendConfig::Redis {
+                url: url.value()?,
+                key: self.leader_election_redis_key.clone(),
+            },
+            (Some(_), Some(_)) => {
+                eyre::bail!(
+                    "Only one of leader_election_lease_file and leader_election_redis_url can be set"
+                )
+            }
+        };
+        Ok(Some(LeaderElectionConfig {
+            backend,
+            replica_id: self
+                .leader_election_replica_id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            lease_ttl: Duration::from_millis(self.leader_election_lease_ttl_ms),
+        }))
+    }
+
+    pub fn orderpool_sync_config(&self) -> eyre::Result<OrderPoolSyncConfig> {
+        let server_address = self.orderpool_sync_server_port.map(|port| {
+            SocketAddr::V4(SocketAddrV4::new(
+                parse_ip(&self.orderpool_sync_server_ip),
+                port,
+            ))
+        });
+        if server_address.is_none() && self.orderpool_sync_peer.is_none() {
+            return Ok(OrderPoolSyncConfig::default());
+        }
+        let secret = self
+            .orderpool_sync_secret
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("orderpool_sync_secret is required for orderpool sync"))?
+            .value()?;
+        if secret.is_empty() {
+            eyre::bail!("orderpool_sync_secret can't be empty");
+        }
+        Ok(OrderPoolSyncConfig {
+            server_address,
+            peer_address: self.orderpool_sync_peer.clone(),
+            secret,
+        })
+    }
+
+    pub fn orderflow_sharing_config(&self) -> eyre::Result<OrderflowSharingConfig> {
+        let ingress_address = self.orderflow_sharing_ingress_port.map(|port| {
+            SocketAddr::V4(SocketAddrV4::new(
+                parse_ip(&self.orderflow_sharing_ingress_ip),
+                port,
+            ))
+        });
+        if ingress_address.is_none() && self.orderflow_sharing_peers.is_empty() {
+            return Ok(OrderflowSharingConfig::default());
+        }
+        let secret = self
+            .orderflow_sharing_secret
+            .as_ref()
+            .ok_or_else(|| {
+                eyre::eyre!("orderflow_sharing_secret is required for orderflow sharing")
+            })?
+            .value()?;
+        let origin = self
+            .orderflow_sharing_origin
+            .clone()
+            .or_else(|| self.builder_names.first().cloned())
+            .ok_or_else(|| {
+                eyre::eyre!(
+                    "orderflow_sharing_origin (or builder_names) is required for orderflow sharing"
+                )
+            })?;
+        Ok(OrderflowSharingConfig {
+            origin,
+            ingress_address,
+            peers: self.orderflow_sharing_peers.clone(),
+            secret,
+        })
+    }
+
     pub fn backtest_fetch_mempool_data_dir(&self) -> eyre::Result<PathBuf> {
         let path = self.backtest_fetch_mempool_data_dir.value()?;
         let path_expanded = shellexpand::tilde(&path).to_string();
@@ -427,13 +844,64 @@ impl Default for BaseConfig {
             log_color: false,
             log_enable_dynamic: false,
             error_storage_path: None,
+            slot_resource_report_db_path: None,
+            archive_dir: None,
+            archive_s3_endpoint: "https://s3.us-east-1.amazonaws.com".to_string(),
+            archive_s3_bucket: None,
+            archive_s3_region: "us-east-1".to_string(),
+            archive_s3_access_key_id: None,
+            archive_s3_secret_access_key: None,
+            archive_interval_secs: 3600,
+            performance_db_retention_days: None,
+            order_archive_path: None,
+            signer_reputation_db_path: None,
+            algorithm_params_path: None,
+            state_access_heatmap: false,
+            bytecode_cache_size: 10_000,
+            admin_rpc_server_port: None,
+            admin_rpc_server_ip: None,
+            leader_election_lease_file: None,
+            leader_election_redis_url: None,
+            leader_election_redis_key: "rbuilder:leader".to_string(),
+            leader_election_replica_id: None,
+            leader_election_lease_ttl_ms: 3000,
+            node_max_head_lag_secs: None,
+            warm_standby: false,
             coinbase_secret_key: "".into(),
             flashbots_db: None,
             el_node_ipc_path: "/tmp/reth.ipc".parse().unwrap(),
+            el_node_mempool: true,
+            mempool_sources: Vec::new(),
+            devp2p_mempool: None,
             jsonrpc_server_port: DEFAULT_INCOMING_BUNDLES_PORT,
             jsonrpc_server_ip: None,
+            jsonrpc_server_transport: Default::default(),
+            jsonrpc_require_signed_cancellations: true,
+            jsonrpc_require_flashbots_signature: false,
+            jsonrpc_server_max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            jsonrpc_server_max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            jsonrpc_server_max_bundle_txs: DEFAULT_MAX_BUNDLE_TXS,
+            jsonrpc_server_blocking_decode_min_size: DEFAULT_BLOCKING_DECODE_MIN_SIZE,
+            jsonrpc_server_max_blocking_decodes: DEFAULT_MAX_BLOCKING_DECODES,
+            jsonrpc_signer_rate_limit: None,
+            jsonrpc_ip_rate_limit: None,
+            jsonrpc_trusted_proxies: Vec::new(),
+            grpc_server_port: None,
+            grpc_trust_signing_address: false,
             ignore_cancellable_orders: true,
             ignore_blobs: false,
+            builder_names: Vec::new(),
+            tenants: Vec::new(),
+            tenant_cgroup_root: None,
+            orderpool_sync_server_port: None,
+            orderpool_sync_server_ip: None,
+            orderpool_sync_peer: None,
+            orderpool_sync_secret: None,
+            orderflow_sharing_origin: None,
+            orderflow_sharing_ingress_port: None,
+            orderflow_sharing_ingress_ip: None,
+            orderflow_sharing_peers: Vec::new(),
+            orderflow_sharing_secret: None,
             chain: "mainnet".to_string(),
             reth_datadir: Some(DEFAULT_RETH_DB_PATH.parse().unwrap()),
             reth_db_path: None,
@@ -449,9 +917,28 @@ impl Default for BaseConfig {
             backtest_fetch_output_file: "/tmp/rbuilder-backtest.sqlite".parse().unwrap(),
             backtest_results_store_path: "/tmp/rbuilder-backtest-results.sqlite".parse().unwrap(),
             backtest_protect_bundle_signers: vec![],
+            backtest_conflict_detection_threads: 0,
             backtest_builders: Vec::new(),
             live_builders: vec!["mgp-ordering".to_string(), "mp-ordering".to_string()],
             simulation_threads: 1,
+            shared_worker_threads: 0,
+            sim_queue_weights: SimQueueWeights::default(),
+            late_order_fast_path_window_ms: None,
+            late_order_fast_path_min_profit_eth: "0.0".to_string(),
+            sim_bundle: false,
+            sim_bundle_pending_signers: Vec::new(),
+            sim_bundle_max_concurrent_simulations: 4,
+            orderpool_delta_interval_ms: None,
+            refund_settlement_ledger_path: None,
+            refund_settlement_interval_secs: 3600,
+            refund_settlement_min_value_eth: "0.001".to_string(),
+            refund_settlement_rpc_url: None,
+            refund_settlement_dry_run: false,
+            victim_protection_max_loss_bps: None,
+            slot_outcome_min_win_probability_bps: None,
+            slot_outcome_prior_slots: 20,
+            own_tx_base_fee_headroom_blocks: 0,
+            own_tx_priority_fee_per_gas: 0,
             sbundle_mergeabe_signers: None,
         }
     }
@@ -547,6 +1034,27 @@ mod test {
         assert_eq!(config, config_default);
     }
 
+    #[test]
+    fn test_admin_rpc_server_ip() {
+        let config: BaseConfig = serde_json::from_str(
+            r#"{"admin_rpc_server_port": 8650, "admin_rpc_server_ip": "0.0.0.0"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.admin_rpc_server_address(),
+            Some("0.0.0.0:8650".parse().unwrap())
+        );
+        let config: BaseConfig =
+            serde_json::from_str(r#"{"admin_rpc_server_port": 8650}"#).unwrap();
+        assert_eq!(
+            config.admin_rpc_server_address(),
+            Some("127.0.0.1:8650".parse().unwrap())
+        );
+        assert!(
+            serde_json::from_str::<BaseConfig>(r#"{"admin_rpc_server_ip": "localhost"}"#).is_err()
+        );
+    }
+
     #[test]
     fn test_reth_db() {
         // Setup and initialize a temp reth db (with static files)
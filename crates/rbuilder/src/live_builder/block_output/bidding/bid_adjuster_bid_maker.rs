@@ -0,0 +1,332 @@
+//! Off-process bidding logic: every bid is sent (as a [`BidCandidate`]) to an external "bid adjuster" sidecar over a
+//! websocket JSON-RPC connection before sealing and the sidecar can answer with a different payout.
+//! The sidecar must implement `bidAdjuster_adjustBid(candidate) -> {"payoutTxValue": "0x.." | null}`, null keeps our
+//! payout. If the sidecar is down or doesn't answer within the timeout (reconnecting included) the bid is sealed
+//! unadjusted so a broken sidecar never stops us from bidding. Adjusted payouts above the true block value are lowered
+//! to it, the sidecar can shave our margin but can't make us subsidize a bid.
+//! Only the last bid is kept while a request is in flight (same as [`super::sequential_sealer_bid_maker`]) so a slow
+//! sidecar delays our bids but never queues them.
+
+use super::interfaces::{Bid, BidMaker};
+use crate::{
+    building::builders::block_building_helper::BlockBuildingHelper, telemetry::inc_bid_adjustments,
+};
+use alloy_primitives::{Address, B256, U256};
+use jsonrpsee::{
+    core::client::ClientT,
+    rpc_params,
+    ws_client::{WsClient, WsClientBuilder},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{trace, warn};
+
+const ADJUST_BID_METHOD: &str = "bidAdjuster_adjustBid";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BidAdjusterConfig {
+    /// ws:// or wss:// url of the sidecar.
+    pub url: String,
+    /// Max time waiting for an adjustment (connecting included), the bid is sealed unadjusted after it.
+    pub timeout: Duration,
+}
+
+/// What the sidecar gets for each bid. Values are in wei.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BidCandidate {
+    pub slot: u64,
+    pub block_number: u64,
+    pub parent_hash: B256,
+    pub timestamp: u64,
+    pub fee_recipient: Address,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub base_fee: u64,
+    pub builder_name: String,
+    pub order_count: usize,
+    /// Coinbase profit of the block before paying the proposer.
+    pub true_block_value: U256,
+    /// Payout we want to make to the proposer.
+    pub payout_tx_value: U256,
+    /// true_block_value - payout_tx_value (negative margins, subsidies, are 0).
+    pub builder_margin: U256,
+}
+
+impl BidCandidate {
+    pub fn new(slot: u64, block: &dyn BlockBuildingHelper, payout_tx_value: U256) -> Option<Self> {
+        let true_block_value = block.true_block_value().ok()?;
+        let ctx = block.building_context();
+        let trace = block.built_block_trace();
+        Some(Self {
+            slot,
+            block_number: ctx.block(),
+            parent_hash: ctx.attributes.parent,
+            timestamp: ctx.attributes.timestamp,
+            fee_recipient: ctx.attributes.suggested_fee_recipient,
+            gas_limit: ctx.block_env.gas_limit.to(),
+            gas_used: trace.included_orders.iter().map(|o| o.gas_used).sum(),
+            base_fee: ctx.block_env.basefee.to(),
+            builder_name: block.builder_name().to_string(),
+            order_count: trace.included_orders.len(),
+            true_block_value,
+            payout_tx_value,
+            builder_margin: true_block_value.saturating_sub(payout_tx_value),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BidAdjustment {
+    /// None keeps the proposed payout.
+    pub payout_tx_value: Option<U256>,
+}
+
+/// Connection to the sidecar shared by all the slots, reconnects when needed.
+#[derive(Debug)]
+pub struct BidAdjusterClient {
+    config: BidAdjusterConfig,
+    client: tokio::sync::Mutex<Option<Arc<WsClient>>>,
+}
+
+impl BidAdjusterClient {
+    pub fn new(config: BidAdjusterConfig) -> Self {
+        Self {
+            config,
+            client: Default::default(),
+        }
+    }
+
+    async fn connection(&self) -> eyre::Result<Arc<WsClient>> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref().filter(|client| client.is_connected()) {
+            return Ok(client.clone());
+        }
+        let new_client = Arc::new(
+            WsClientBuilder::default()
+                .connection_timeout(self.config.timeout)
+                .request_timeout(self.config.timeout)
+                .build(&self.config.url)
+                .await?,
+        );
+        *client = Some(new_client.clone());
+        Ok(new_client)
+    }
+
+    /// Adjusted payout, None if the sidecar keeps ours. Err if it takes more than the timeout.
+    pub async fn adjust(&self, candidate: &BidCandidate) -> eyre::Result<Option<U256>> {
+        let adjust = async {
+            let client = self.connection().await?;
+            let adjustment: BidAdjustment = client
+                .request(ADJUST_BID_METHOD, rpc_params![candidate])
+                .await?;
+            Ok(adjustment.payout_tx_value)
+        };
+        tokio::time::timeout(self.config.timeout, adjust)
+            .await
+            .map_err(|_| eyre::eyre!("Bid adjuster timed out"))?
+    }
+}
+
+/// BidMaker wrapper asking the sidecar for the payout before forwarding the bids to the wrapped BidMaker.
+#[derive(Debug)]
+pub struct BidAdjusterBidMaker {
+    pending_bid: Arc<PendingBid>,
+}
+
+#[derive(Debug, Default)]
+struct PendingBid {
+    bid: Mutex<Option<Bid>>,
+    bid_notify: Notify,
+}
+
+impl BidAdjusterBidMaker {
+    /// Must be called from inside the tokio runtime.
+    pub fn new(
+        inner: Box<dyn BidMaker + Send + Sync>,
+        client: Arc<BidAdjusterClient>,
+        slot: u64,
+        cancel: CancellationToken,
+    ) -> Self {
+        let pending_bid: Arc<PendingBid> = Default::default();
+        let pending_bid_clone = pending_bid.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = pending_bid_clone.bid_notify.notified() => {},
+                    _ = cancel.cancelled() => return,
+                }
+                let bid = match pending_bid_clone.bid.lock().take() {
+                    Some(bid) => bid,
+                    None => continue,
+                };
+                inner.send_bid(adjust_bid(&client, slot, bid).await);
+            }
+        });
+        Self { pending_bid }
+    }
+}
+
+async fn adjust_bid(client: &BidAdjusterClient, slot: u64, bid: Bid) -> Bid {
+    // Blocks without payout tx (fee recipient as coinbase) can't be adjusted.
+    let Some(payout_tx_value) = bid.payout_tx_value() else {
+        return bid;
+    };
+    let block = bid.block();
+    let Some(candidate) = BidCandidate::new(slot, block.as_ref(), payout_tx_value) else {
+        return Bid::new(block, Some(payout_tx_value));
+    };
+    let adjustment = client.adjust(&candidate).await;
+    Bid::new(block, Some(adjusted_payout(slot, &candidate, adjustment)))
+}
+
+/// Payout to seal for the sidecar answer.
+fn adjusted_payout(
+    slot: u64,
+    candidate: &BidCandidate,
+    adjustment: eyre::Result<Option<U256>>,
+) -> U256 {
+    let payout_tx_value = candidate.payout_tx_value;
+    match adjustment {
+        Ok(Some(adjusted)) if adjusted > candidate.true_block_value => {
+            warn!(
+                slot,
+                ?adjusted,
+                true_block_value = ?candidate.true_block_value,
+                "Bid adjuster payout above the true block value, clamping"
+            );
+            inc_bid_adjustments("clamped");
+            candidate.true_block_value
+        }
+        Ok(Some(adjusted)) => {
+            trace!(
+                slot,
+                builder_name = candidate.builder_name,
+                ?payout_tx_value,
+                ?adjusted,
+                "Bid adjusted"
+            );
+            inc_bid_adjustments("adjusted");
+            adjusted
+        }
+        Ok(None) => {
+            inc_bid_adjustments("unchanged");
+            payout_tx_value
+        }
+        Err(err) => {
+            warn!(?err, slot, "Bid adjuster failed, sealing unadjusted bid");
+            inc_bid_adjustments("failed");
+            payout_tx_value
+        }
+    }
+}
+
+impl BidMaker for BidAdjusterBidMaker {
+    fn send_bid(&self, bid: Bid) {
+        *self.pending_bid.bid.lock() = Some(bid);
+        self.pending_bid.bid_notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::{server::Server, RpcModule};
+
+    fn candidate(payout_tx_value: u64) -> BidCandidate {
+        BidCandidate {
+            slot: 1,
+            block_number: 2,
+            parent_hash: B256::ZERO,
+            timestamp: 3,
+            fee_recipient: Address::ZERO,
+            gas_limit: 30_000_000,
+            gas_used: 21_000,
+            base_fee: 7,
+            builder_name: "test".to_string(),
+            order_count: 1,
+            true_block_value: U256::from(100),
+            payout_tx_value: U256::from(payout_tx_value),
+            builder_margin: U256::from(100 - payout_tx_value),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bid_adjuster_client() {
+        // sidecar keeping at most 10 wei of margin
+        let server = Server::builder().build("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut module = RpcModule::new(());
+        module
+            .register_method(ADJUST_BID_METHOD, |params, _| {
+                let candidate: serde_json::Value = params.one()?;
+                let margin: U256 = serde_json::from_value(candidate["builderMargin"].clone())
+                    .map_err(|err| {
+                        jsonrpsee::types::ErrorObject::owned(-32602, err.to_string(), None::<()>)
+                    })?;
+                let true_block_value: U256 =
+                    serde_json::from_value(candidate["trueBlockValue"].clone()).unwrap();
+                Ok::<_, jsonrpsee::types::ErrorObject<'static>>(BidAdjustment {
+                    payout_tx_value: (margin > U256::from(10))
+                        .then(|| true_block_value - U256::from(10)),
+                })
+            })
+            .unwrap();
+        let handle = server.start(module);
+
+        let client = BidAdjusterClient::new(BidAdjusterConfig {
+            url: format!("ws://{}", addr),
+            timeout: Duration::from_secs(5),
+        });
+        assert_eq!(
+            client.adjust(&candidate(50)).await.unwrap(),
+            Some(U256::from(90))
+        );
+        assert_eq!(client.adjust(&candidate(95)).await.unwrap(), None);
+
+        handle.stop().unwrap();
+        handle.stopped().await;
+        assert!(client.adjust(&candidate(50)).await.is_err());
+    }
+
+    #[test]
+    fn test_adjusted_payout() {
+        let candidate = candidate(50);
+        assert_eq!(
+            adjusted_payout(1, &candidate, Ok(Some(U256::from(90)))),
+            U256::from(90)
+        );
+        assert_eq!(
+            adjusted_payout(1, &candidate, Ok(Some(U256::from(150)))),
+            U256::from(100)
+        );
+        assert_eq!(adjusted_payout(1, &candidate, Ok(None)), U256::from(50));
+        assert_eq!(
+            adjusted_payout(1, &candidate, Err(eyre::eyre!("down"))),
+            U256::from(50)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bid_adjuster_timeout() {
+        // accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _connection = listener.accept().await;
+            std::future::pending::<()>().await;
+        });
+        let timeout = Duration::from_millis(100);
+        let client = BidAdjusterClient::new(BidAdjusterConfig {
+            url: format!("ws://{}", addr),
+            timeout,
+        });
+        let start = std::time::Instant::now();
+        assert!(client.adjust(&candidate(50)).await.is_err());
+        assert!(start.elapsed() < 2 * timeout);
+    }
+}
@@ -2,9 +2,12 @@ use std::sync::Arc;
 
 use crate::{
     building::builders::{block_building_helper::BlockBuildingHelper, UnfinishedBlockBuildingSink},
-    live_builder::block_output::bid_value_source::interfaces::BidValueObs,
+    live_builder::{
+        block_output::{bid_value_source::interfaces::BidValueObs, relay_data_poller::SlotOutcome},
+        proposer_overrides::ProposerOverride,
+    },
 };
-use alloy_primitives::{BlockNumber, U256};
+use alloy_primitives::{BlockNumber, B256, U256};
 use mockall::automock;
 use time::OffsetDateTime;
 use tokio_util::sync::CancellationToken;
@@ -49,6 +52,12 @@ impl Bid {
     pub fn payout_tx_value(&self) -> Option<U256> {
         self.payout_tx_value
     }
+
+    /// Value for the proposer (payout tx or, if none, the true block value), None if it can't be computed.
+    pub fn bid_value(&self) -> Option<U256> {
+        self.payout_tx_value
+            .or_else(|| self.block.true_block_value().ok())
+    }
 }
 
 /// Makes the actual bid (seal + send it to the relay).
@@ -60,6 +69,7 @@ pub trait BidMaker: std::fmt::Debug {
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct LandedBlockInfo {
     pub block_number: BlockNumber,
+    pub block_hash: B256,
     pub block_timestamp: OffsetDateTime,
     pub builder_balance: U256,
     /// true -> we landed this block.
@@ -78,6 +88,7 @@ pub trait BiddingService: std::fmt::Debug + Send + Sync {
         block: u64,
         slot: u64,
         slot_timestamp: OffsetDateTime,
+        proposer_override: Option<Arc<ProposerOverride>>,
         bid_maker: Box<dyn BidMaker + Send + Sync>,
         cancel: CancellationToken,
     ) -> Arc<dyn SlotBidder>;
@@ -92,6 +103,10 @@ pub trait BiddingService: std::fmt::Debug + Send + Sync {
 
     /// We let the BiddingService know we had some problem reading landed blocks just in case we wants to change his strategy (eg: stop bidding until next update_new_landed_blocks_detected)
     fn update_failed_reading_new_landed_blocks(&mut self);
+
+    /// Win/loss feedback from the relays for finished slots, in slot order.
+    /// Only called if the relay data poller is configured, by default it's ignored.
+    fn update_slot_outcomes(&mut self, _outcomes: &[SlotOutcome]) {}
 }
 
 /// Trait to control the must_win_block feature of the BiddingService.
@@ -1,5 +1,7 @@
+pub mod bid_adjuster_bid_maker;
 pub mod interfaces;
 pub mod parallel_sealer_bid_maker;
 pub mod sequential_sealer_bid_maker;
 pub mod true_block_value_bidder;
+pub mod urgent_reseal_bid_maker;
 pub mod wallet_balance_watcher;
@@ -3,21 +3,56 @@ use super::interfaces::{
 };
 use crate::{
     building::builders::{block_building_helper::BlockBuildingHelper, UnfinishedBlockBuildingSink},
-    live_builder::block_output::bid_value_source::interfaces::BidValueObs,
+    live_builder::{
+        block_output::bid_value_source::interfaces::BidValueObs,
+        proposer_overrides::ProposerOverride,
+    },
 };
 use alloy_primitives::U256;
 use std::sync::Arc;
 use time::OffsetDateTime;
 use tokio_util::sync::CancellationToken;
 
+/// Subsidy we are willing to add so bids reach the relays bid floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayFloorTopUp {
+    /// Lowest floor among the relays, reaching it is enough for some relay to take the bid.
+    pub floor: U256,
+    pub max_subsidy: U256,
+}
+
+impl RelayFloorTopUp {
+    /// Value to bid for a block worth true_block_value.
+    pub fn bid_value(&self, true_block_value: U256) -> U256 {
+        if true_block_value < self.floor && self.floor - true_block_value <= self.max_subsidy {
+            self.floor
+        } else {
+            true_block_value
+        }
+    }
+
+    /// Same floor, max_subsidy replaced if Some.
+    pub fn with_max_subsidy(self, max_subsidy: Option<U256>) -> Self {
+        Self {
+            max_subsidy: max_subsidy.unwrap_or(self.max_subsidy),
+            ..self
+        }
+    }
+}
+
 /// Bidding service giving a TrueBlockValueBidder
 #[derive(Debug)]
-pub struct TrueBlockValueBiddingService {}
+pub struct TrueBlockValueBiddingService {
+    relay_floor_top_up: Option<RelayFloorTopUp>,
+}
 
 impl TrueBlockValueBiddingService {
     /// _landed_blocks is passed to look like a real BiddingService...
-    pub fn new(_landed_blocks: &[LandedBlockInfo]) -> Self {
-        Self {}
+    pub fn new(
+        _landed_blocks: &[LandedBlockInfo],
+        relay_floor_top_up: Option<RelayFloorTopUp>,
+    ) -> Self {
+        Self { relay_floor_top_up }
     }
 }
 
@@ -27,10 +62,18 @@ impl BiddingService for TrueBlockValueBiddingService {
         _block: u64,
         _slot: u64,
         _slot_timestamp: OffsetDateTime,
+        proposer_override: Option<Arc<ProposerOverride>>,
         bid_maker: Box<dyn BidMaker + Send + Sync>,
         _cancel: CancellationToken,
     ) -> Arc<dyn SlotBidder> {
-        Arc::new(TrueBlockValueBidder { bid_maker })
+        let max_subsidy = proposer_override
+            .and_then(|proposer_override| proposer_override.max_relay_floor_subsidy);
+        Arc::new(TrueBlockValueBidder {
+            bid_maker,
+            relay_floor_top_up: self
+                .relay_floor_top_up
+                .map(|top_up| top_up.with_max_subsidy(max_subsidy)),
+        })
     }
 
     /// Dummy win control.
@@ -48,9 +91,11 @@ impl BiddingService for TrueBlockValueBiddingService {
 }
 
 /// Bidder that bids every block using its true block value ignoring competition bids.
+/// If relay_floor_top_up is set blocks slightly below the relays floor are subsidized up to it.
 #[derive(Debug)]
 struct TrueBlockValueBidder {
     bid_maker: Box<dyn BidMaker + Send + Sync>,
+    relay_floor_top_up: Option<RelayFloorTopUp>,
 }
 
 impl SlotBidder for TrueBlockValueBidder {}
@@ -59,7 +104,10 @@ impl UnfinishedBlockBuildingSink for TrueBlockValueBidder {
     fn new_block(&self, block: Box<dyn BlockBuildingHelper>) {
         let payout_tx_value = if block.can_add_payout_tx() {
             match block.true_block_value() {
-                Ok(tbv) => Some(tbv),
+                Ok(tbv) => Some(
+                    self.relay_floor_top_up
+                        .map_or(tbv, |top_up| top_up.bid_value(tbv)),
+                ),
                 Err(_) => return,
             }
         } else {
@@ -83,3 +131,26 @@ struct TrueBlockValueBiddingServiceWinControl {}
 impl BiddingServiceWinControl for TrueBlockValueBiddingServiceWinControl {
     fn must_win_block(&self, _block: u64) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_floor_top_up() {
+        let top_up = RelayFloorTopUp {
+            floor: U256::from(100),
+            max_subsidy: U256::from(10),
+        };
+        assert_eq!(top_up.bid_value(U256::from(95)), U256::from(100));
+        assert_eq!(top_up.bid_value(U256::from(90)), U256::from(100));
+        // too expensive, relays will skip it
+        assert_eq!(top_up.bid_value(U256::from(89)), U256::from(89));
+        assert_eq!(top_up.bid_value(U256::from(150)), U256::from(150));
+
+        // proposer with a bigger cap
+        let proposer_top_up = top_up.with_max_subsidy(Some(U256::from(20)));
+        assert_eq!(proposer_top_up.bid_value(U256::from(85)), U256::from(100));
+        assert_eq!(top_up.with_max_subsidy(None), top_up);
+    }
+}
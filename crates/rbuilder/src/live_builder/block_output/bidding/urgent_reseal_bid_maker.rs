@@ -0,0 +1,238 @@
+use alloy_primitives::U256;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, trace};
+
+use crate::{
+    live_builder::block_output::relay_submit::BlockBuildingSink, telemetry::inc_urgent_reseals,
+};
+
+use super::interfaces::{Bid, BidMaker};
+
+/// When a bid should skip the queue of the wrapped [BidMaker].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UrgentResealConfig {
+    /// Min improvement over the last bid.
+    pub min_delta: U256,
+    /// Min time between urgent seals, at least the relays submission rate limit (see
+    /// [`L1Config::urgent_reseal_config`](crate::live_builder::config::L1Config::urgent_reseal_config)) since the
+    /// relay submission skips what the limit doesn't allow.
+    pub min_interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct UrgentResealState {
+    /// Best bid value seen so far.
+    last_bid_value: U256,
+    last_urgent_seal: Option<Instant>,
+    urgent_seal_in_progress: bool,
+}
+
+impl UrgentResealState {
+    /// true -> the bid must be sealed now (and an urgent seal is considered in progress).
+    fn start_urgent_seal(
+        &mut self,
+        value: U256,
+        config: &UrgentResealConfig,
+        now: Instant,
+    ) -> bool {
+        let improvement = value.saturating_sub(self.last_bid_value);
+        self.last_bid_value = self.last_bid_value.max(value);
+        if improvement.is_zero() || improvement < config.min_delta || self.urgent_seal_in_progress {
+            return false;
+        }
+        if self
+            .last_urgent_seal
+            .is_some_and(|last| now.duration_since(last) < config.min_interval)
+        {
+            return false;
+        }
+        self.last_urgent_seal = Some(now);
+        self.urgent_seal_in_progress = true;
+        true
+    }
+}
+
+/// BidMaker wrapper sealing big improvements immediately.
+/// The wrapped BidMaker only starts a new seal once it has capacity so a much better block arriving while it's busy
+/// waits for the previous seal, we can lose a slot by those ms. Bids improving the best one by at least min_delta
+/// are sealed right away in the background instead (at most one at a time and one every min_interval), the rest go
+/// to the wrapped BidMaker.
+#[derive(Debug)]
+pub struct UrgentResealBidMaker {
+    inner: Box<dyn BidMaker + Send + Sync>,
+    sink: Arc<dyn BlockBuildingSink>,
+    config: UrgentResealConfig,
+    state: Arc<Mutex<UrgentResealState>>,
+    cancel: CancellationToken,
+    /// send_bid is called from the building threads.
+    runtime: Handle,
+}
+
+impl UrgentResealBidMaker {
+    /// Must be called from inside the tokio runtime.
+    pub fn new(
+        inner: Box<dyn BidMaker + Send + Sync>,
+        sink: Arc<dyn BlockBuildingSink>,
+        config: UrgentResealConfig,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            inner,
+            sink,
+            config,
+            state: Default::default(),
+            cancel,
+            runtime: Handle::current(),
+        }
+    }
+}
+
+impl BidMaker for UrgentResealBidMaker {
+    fn send_bid(&self, bid: Bid) {
+        let urgent = !self.cancel.is_cancelled()
+            && bid.bid_value().is_some_and(|value| {
+                self.state
+                    .lock()
+                    .start_urgent_seal(value, &self.config, Instant::now())
+            });
+        if !urgent {
+            self.inner.send_bid(bid);
+            return;
+        }
+        inc_urgent_reseals();
+        let payout_tx_val = bid.payout_tx_value();
+        let block = bid.block();
+        let block_number = block.building_context().block();
+        let builder_name = block.builder_name().to_string();
+        trace!(builder_name, block_number, "Urgent reseal");
+        let sink = self.sink.clone();
+        let state = self.state.clone();
+        self.runtime.spawn_blocking(move || {
+            match block.finalize_block(payout_tx_val) {
+                Ok(res) => sink.new_block(res.block),
+                Err(error) => {
+                    if error.is_critical() {
+                        error!(
+                            builder_name,
+                            block_number,
+                            ?error,
+                            "Error on finalize_block on UrgentResealBidMaker"
+                        )
+                    }
+                }
+            }
+            state.lock().urgent_seal_in_progress = false;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::builders::{mock_block_building_helper::MockBlockBuildingHelper, Block};
+    use tokio::sync::mpsc;
+
+    /// Bid values the wrapped sealer got.
+    #[derive(Debug, Default)]
+    struct RecordingBidMaker {
+        bid_values: Arc<Mutex<Vec<U256>>>,
+    }
+
+    impl BidMaker for RecordingBidMaker {
+        fn send_bid(&self, bid: Bid) {
+            self.bid_values.lock().extend(bid.bid_value());
+        }
+    }
+
+    /// Sends the bid value of the sealed blocks.
+    #[derive(Debug)]
+    struct ChannelSink(mpsc::UnboundedSender<U256>);
+
+    impl BlockBuildingSink for ChannelSink {
+        fn new_block(&self, block: Block) {
+            let _ = self.0.send(block.trace.bid_value);
+        }
+    }
+
+    fn bid(value: u64) -> Bid {
+        Bid::new(
+            Box::new(MockBlockBuildingHelper::new(U256::from(value), true)),
+            Some(U256::from(value)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_urgent_reseal_bid_maker() {
+        let inner = RecordingBidMaker::default();
+        let inner_bid_values = inner.bid_values.clone();
+        let (sender, mut sealed) = mpsc::unbounded_channel();
+        let bid_maker = UrgentResealBidMaker::new(
+            Box::new(inner),
+            Arc::new(ChannelSink(sender)),
+            UrgentResealConfig {
+                min_delta: U256::from(10),
+                min_interval: Duration::from_secs(3600),
+            },
+            CancellationToken::new(),
+        );
+
+        // first bid improves on nothing by 100
+        bid_maker.send_bid(bid(100));
+        assert_eq!(sealed.recv().await, Some(U256::from(100)));
+        // small improvement -> wrapped sealer
+        bid_maker.send_bid(bid(105));
+        // big improvement inside min_interval (the relays would skip it) -> wrapped sealer
+        bid_maker.send_bid(bid(1_000));
+        assert_eq!(
+            *inner_bid_values.lock(),
+            vec![U256::from(105), U256::from(1_000)]
+        );
+        assert!(sealed.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_urgent_reseal_decision() {
+        let config = UrgentResealConfig {
+            min_delta: U256::from(10),
+            min_interval: Duration::from_millis(50),
+        };
+        let start = Instant::now();
+        let mut state = UrgentResealState::default();
+
+        assert!(state.start_urgent_seal(U256::from(100), &config, start));
+        // one at a time
+        assert!(!state.start_urgent_seal(
+            U256::from(200),
+            &config,
+            start + Duration::from_millis(60)
+        ));
+        state.urgent_seal_in_progress = false;
+        // small improvement over the best one seen (200)
+        assert!(!state.start_urgent_seal(
+            U256::from(205),
+            &config,
+            start + Duration::from_millis(60)
+        ));
+        // big improvement but too soon
+        assert!(!state.start_urgent_seal(
+            U256::from(300),
+            &config,
+            start + Duration::from_millis(10)
+        ));
+        assert!(state.start_urgent_seal(
+            U256::from(400),
+            &config,
+            start + Duration::from_millis(60)
+        ));
+        state.urgent_seal_in_progress = false;
+        // worse bids never skip the queue
+        assert!(!state.start_urgent_seal(U256::from(1), &config, start + Duration::from_secs(1)));
+        assert_eq!(state.last_bid_value, U256::from(400));
+    }
+}
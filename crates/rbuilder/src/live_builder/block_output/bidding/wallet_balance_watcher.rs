@@ -1,12 +1,12 @@
 use std::time::Duration;
 
-use alloy_primitives::{utils::format_ether, Address, BlockNumber, U256};
+use alloy_primitives::{utils::format_ether, Address, BlockNumber, B256, U256};
 use reth::providers::{HeaderProvider, ProviderError};
 use reth_provider::StateProviderFactory;
 use time::{error, OffsetDateTime};
 use tracing::{error, info, warn};
 
-use crate::telemetry::{add_subsidy_value, inc_subsidized_blocks};
+use crate::telemetry::{add_subsidy_value, inc_subsidized_blocks, record_landed_block};
 
 use super::interfaces::LandedBlockInfo;
 
@@ -42,12 +42,14 @@ struct BlockInfo {
     builder_balance: U256,
     beneficiary: Address,
     block_number: BlockNumber,
+    block_hash: B256,
 }
 
 impl BlockInfo {
     fn as_landed_block_info(&self, buider_address: &Address) -> LandedBlockInfo {
         LandedBlockInfo {
             block_number: self.block_number,
+            block_hash: self.block_hash,
             block_timestamp: self.timestamp,
             builder_balance: self.builder_balance,
             beneficiary_is_builder: self.beneficiary == *buider_address,
@@ -117,12 +119,13 @@ where
             .unwrap_or_default();
         let header = self
             .provider
-            .header_by_number(block)?
+            .sealed_header(block)?
             .ok_or(WalletError::HeaderNotFound(block))?;
         Ok(BlockInfo {
             timestamp: OffsetDateTime::from_unix_timestamp(header.timestamp as i64)?,
             builder_balance,
             block_number: block,
+            block_hash: header.hash(),
             beneficiary: header.beneficiary,
         })
     }
@@ -168,6 +171,14 @@ where
                 add_subsidy_value(subsidy_value, true);
                 inc_subsidized_blocks(true);
             }
+            if landed_block_info.beneficiary_is_builder {
+                record_landed_block(
+                    landed_block_info.block_number,
+                    landed_block_info.block_timestamp,
+                    self.balance,
+                    landed_block_info.builder_balance,
+                );
+            }
             self.balance = landed_block_info.builder_balance;
             self.block_number = landed_block_info.block_number;
         }
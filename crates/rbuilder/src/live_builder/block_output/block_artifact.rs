@@ -0,0 +1,460 @@
+//! Json snapshot of a built block ([`BlockArtifact`]) as we submitted it (or validated it on dry run) and the diff of
+//! two of them ([`BlockDiff`], `rbuilder diff-blocks a.json b.json`) to see what changed between two runs of the
+//! algorithms: included orders, their ordering, gas used and where the value comes from.
+//! Artifacts are saved by a [`BlockArtifactStore`] which only keeps the ones of the last blocks.
+
+use crate::{building::builders::Block, primitives::OrderId};
+use alloy_primitives::{utils::format_ether, BlockHash, TxHash, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactOrder {
+    pub id: OrderId,
+    pub gas_used: u64,
+    pub coinbase_profit: U256,
+    pub paid_kickbacks: U256,
+    pub txs: Vec<TxHash>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockArtifact {
+    pub builder_name: String,
+    pub block_number: u64,
+    pub block_hash: BlockHash,
+    pub parent_hash: BlockHash,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub bid_value: U256,
+    pub true_bid_value: U256,
+    /// Hashes of the same block resealed with the relays extra_data overrides, also submitted.
+    #[serde(default)]
+    pub resealed_block_hashes: Vec<BlockHash>,
+    /// Included orders in block order.
+    pub orders: Vec<ArtifactOrder>,
+}
+
+impl BlockArtifact {
+    pub fn new(block: &Block) -> Self {
+        Self {
+            builder_name: block.builder_name.clone(),
+            block_number: block.sealed_block.number,
+            block_hash: block.sealed_block.hash(),
+            parent_hash: block.sealed_block.parent_hash,
+            gas_used: block.sealed_block.gas_used,
+            gas_limit: block.sealed_block.gas_limit,
+            bid_value: block.trace.bid_value,
+            true_bid_value: block.trace.true_bid_value,
+            resealed_block_hashes: Vec::new(),
+            orders: block
+                .trace
+                .included_orders
+                .iter()
+                .map(|res| ArtifactOrder {
+                    id: res.order.id(),
+                    gas_used: res.gas_used,
+                    coinbase_profit: res.coinbase_profit,
+                    paid_kickbacks: res.paid_kickbacks.iter().map(|(_, value)| *value).sum(),
+                    txs: res.txs.iter().map(|tx| tx.hash()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Saves to dir/{block_number}-{builder_name}-{block_hash}.json and returns the path.
+    pub fn save(&self, dir: &Path) -> eyre::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}-{}-{:?}.json",
+            self.block_number, self.builder_name, self.block_hash
+        ));
+        std::fs::write(&path, serde_json::to_vec(self)?)?;
+        Ok(path)
+    }
+
+    pub fn orders_coinbase_profit(&self) -> U256 {
+        self.orders.iter().map(|order| order.coinbase_profit).sum()
+    }
+
+    pub fn paid_kickbacks(&self) -> U256 {
+        self.orders.iter().map(|order| order.paid_kickbacks).sum()
+    }
+}
+
+/// Dir of the artifacts of the submitted blocks, the artifacts of blocks keep_blocks or more below the newest saved
+/// one are deleted (0 keeps everything).
+#[derive(Debug)]
+pub struct BlockArtifactStore {
+    dir: PathBuf,
+    keep_blocks: u64,
+    /// Newest block we pruned for, we only list the dir once per block.
+    pruned_for_block: AtomicU64,
+}
+
+impl BlockArtifactStore {
+    pub fn new(dir: PathBuf, keep_blocks: u64) -> Self {
+        Self {
+            dir,
+            keep_blocks,
+            pruned_for_block: AtomicU64::new(0),
+        }
+    }
+
+    pub fn save(&self, artifact: &BlockArtifact) -> eyre::Result<PathBuf> {
+        let path = artifact.save(&self.dir)?;
+        let block_number = artifact.block_number;
+        if self.keep_blocks != 0
+            && self
+                .pruned_for_block
+                .fetch_max(block_number, Ordering::Relaxed)
+                < block_number
+        {
+            self.prune(block_number)?;
+        }
+        Ok(path)
+    }
+
+    /// Deletes the artifacts of the blocks below newest_block - keep_blocks + 1, unknown files are left alone.
+    fn prune(&self, newest_block: u64) -> eyre::Result<()> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(block_number) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .filter(|name| name.ends_with(".json"))
+                .and_then(|name| name.split('-').next())
+                .and_then(|block_number| block_number.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if block_number + self.keep_blocks <= newest_block {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    warn!(?err, ?path, "Failed to delete old block artifact");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Order included in both blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommonOrderDiff {
+    pub id: OrderId,
+    pub position_a: usize,
+    pub position_b: usize,
+    pub gas_used_a: u64,
+    pub gas_used_b: u64,
+    pub coinbase_profit_a: U256,
+    pub coinbase_profit_b: U256,
+}
+
+impl CommonOrderDiff {
+    pub fn changed(&self) -> bool {
+        self.position_a != self.position_b
+            || self.gas_used_a != self.gas_used_b
+            || self.coinbase_profit_a != self.coinbase_profit_b
+    }
+}
+
+/// Value decomposition of one block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockValue {
+    pub bid_value: U256,
+    pub true_bid_value: U256,
+    /// Sum of the coinbase profit of the orders.
+    pub orders_coinbase_profit: U256,
+    pub paid_kickbacks: U256,
+    pub gas_used: u64,
+}
+
+impl BlockValue {
+    fn new(block: &BlockArtifact) -> Self {
+        Self {
+            bid_value: block.bid_value,
+            true_bid_value: block.true_bid_value,
+            orders_coinbase_profit: block.orders_coinbase_profit(),
+            paid_kickbacks: block.paid_kickbacks(),
+            gas_used: block.gas_used,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDiff {
+    pub value_a: BlockValue,
+    pub value_b: BlockValue,
+    /// In block order.
+    pub only_in_a: Vec<ArtifactOrder>,
+    /// In block order.
+    pub only_in_b: Vec<ArtifactOrder>,
+    /// In a's block order.
+    pub common: Vec<CommonOrderDiff>,
+}
+
+impl BlockDiff {
+    pub fn new(a: &BlockArtifact, b: &BlockArtifact) -> Self {
+        let positions_b: HashMap<OrderId, usize> = b
+            .orders
+            .iter()
+            .enumerate()
+            .map(|(position, order)| (order.id, position))
+            .collect();
+        let positions_a: HashMap<OrderId, usize> = a
+            .orders
+            .iter()
+            .enumerate()
+            .map(|(position, order)| (order.id, position))
+            .collect();
+        let common = a
+            .orders
+            .iter()
+            .enumerate()
+            .filter_map(|(position_a, order_a)| {
+                let position_b = *positions_b.get(&order_a.id)?;
+                let order_b = &b.orders[position_b];
+                Some(CommonOrderDiff {
+                    id: order_a.id,
+                    position_a,
+                    position_b,
+                    gas_used_a: order_a.gas_used,
+                    gas_used_b: order_b.gas_used,
+                    coinbase_profit_a: order_a.coinbase_profit,
+                    coinbase_profit_b: order_b.coinbase_profit,
+                })
+            })
+            .collect();
+        Self {
+            value_a: BlockValue::new(a),
+            value_b: BlockValue::new(b),
+            only_in_a: a
+                .orders
+                .iter()
+                .filter(|order| !positions_b.contains_key(&order.id))
+                .cloned()
+                .collect(),
+            only_in_b: b
+                .orders
+                .iter()
+                .filter(|order| !positions_a.contains_key(&order.id))
+                .cloned()
+                .collect(),
+            common,
+        }
+    }
+
+    /// Same orders in the same order with the same gas and profit.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && !self.common.iter().any(|order| order.changed())
+    }
+
+    /// Human readable report.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let _ = writeln!(report, "{:<24} {:>24} {:>24} {:>25}", "", "a", "b", "b - a");
+        let mut value_line = |name: &str, a: U256, b: U256| {
+            let _ = writeln!(
+                report,
+                "{:<24} {:>24} {:>24} {:>25}",
+                name,
+                format_ether(a),
+                format_ether(b),
+                signed_ether_delta(a, b)
+            );
+        };
+        value_line("bid_value", self.value_a.bid_value, self.value_b.bid_value);
+        value_line(
+            "true_bid_value",
+            self.value_a.true_bid_value,
+            self.value_b.true_bid_value,
+        );
+        value_line(
+            "orders_coinbase_profit",
+            self.value_a.orders_coinbase_profit,
+            self.value_b.orders_coinbase_profit,
+        );
+        value_line(
+            "paid_kickbacks",
+            self.value_a.paid_kickbacks,
+            self.value_b.paid_kickbacks,
+        );
+        let _ = writeln!(
+            report,
+            "{:<24} {:>24} {:>24} {:>25}",
+            "gas_used",
+            self.value_a.gas_used,
+            self.value_b.gas_used,
+            self.value_b.gas_used as i128 - self.value_a.gas_used as i128
+        );
+
+        let _ = writeln!(report, "\nonly in a ({}):", self.only_in_a.len());
+        for order in &self.only_in_a {
+            let _ = writeln!(
+                report,
+                "  {} gas {} profit {}",
+                order.id,
+                order.gas_used,
+                format_ether(order.coinbase_profit)
+            );
+        }
+        let _ = writeln!(report, "\nonly in b ({}):", self.only_in_b.len());
+        for order in &self.only_in_b {
+            let _ = writeln!(
+                report,
+                "  {} gas {} profit {}",
+                order.id,
+                order.gas_used,
+                format_ether(order.coinbase_profit)
+            );
+        }
+        let changed: Vec<_> = self.common.iter().filter(|order| order.changed()).collect();
+        let _ = writeln!(
+            report,
+            "\nchanged in both ({} of {}):",
+            changed.len(),
+            self.common.len()
+        );
+        for order in changed {
+            let _ = writeln!(
+                report,
+                "  {} position {} -> {} gas {} -> {} profit {}",
+                order.id,
+                order.position_a,
+                order.position_b,
+                order.gas_used_a,
+                order.gas_used_b,
+                signed_ether_delta(order.coinbase_profit_a, order.coinbase_profit_b)
+            );
+        }
+        report
+    }
+}
+
+/// b - a in ETH with sign.
+fn signed_ether_delta(a: U256, b: U256) -> String {
+    if b >= a {
+        format!("+{}", format_ether(b - a))
+    } else {
+        format!("-{}", format_ether(a - b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::order_id;
+
+    fn order(id: u64, gas_used: u64, profit: u64) -> ArtifactOrder {
+        ArtifactOrder {
+            id: order_id(id),
+            gas_used,
+            coinbase_profit: U256::from(profit),
+            paid_kickbacks: U256::ZERO,
+            txs: Vec::new(),
+        }
+    }
+
+    fn artifact(orders: Vec<ArtifactOrder>) -> BlockArtifact {
+        BlockArtifact {
+            builder_name: "mgp-ordering".to_string(),
+            block_number: 11,
+            block_hash: BlockHash::ZERO,
+            parent_hash: BlockHash::ZERO,
+            gas_used: orders.iter().map(|order| order.gas_used).sum(),
+            gas_limit: 30_000_000,
+            bid_value: orders.iter().map(|order| order.coinbase_profit).sum(),
+            true_bid_value: orders.iter().map(|order| order.coinbase_profit).sum(),
+            resealed_block_hashes: vec![BlockHash::with_last_byte(1)],
+            orders,
+        }
+    }
+
+    #[test]
+    fn test_block_diff() {
+        let a = artifact(vec![
+            order(1, 21000, 10),
+            order(2, 50000, 20),
+            order(3, 21000, 5),
+        ]);
+        let b = artifact(vec![
+            order(2, 50000, 20),
+            order(1, 21000, 8),
+            order(4, 30000, 9),
+        ]);
+        assert!(BlockDiff::new(&a, &a).is_empty());
+
+        let diff = BlockDiff::new(&a, &b);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.only_in_a, vec![order(3, 21000, 5)]);
+        assert_eq!(diff.only_in_b, vec![order(4, 30000, 9)]);
+        assert_eq!(
+            diff.common.iter().map(|order| order.id).collect::<Vec<_>>(),
+            vec![order_id(1), order_id(2)]
+        );
+        assert_eq!(diff.common[0].position_b, 1);
+        assert_eq!(diff.common[0].coinbase_profit_b, U256::from(8));
+        assert_eq!(diff.value_a.orders_coinbase_profit, U256::from(35));
+        assert_eq!(diff.value_b.orders_coinbase_profit, U256::from(37));
+
+        let report = diff.report();
+        assert!(report.contains("only in a (1):"));
+        assert!(report.contains("changed in both (2 of 2):"));
+        assert!(report.contains(&format!("  {} position 0 -> 1", order_id(1))));
+    }
+
+    #[test]
+    fn test_artifact_save_load() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let artifact = artifact(vec![order(1, 21000, 10)]);
+        let path = artifact.save(dir.path())?;
+        assert_eq!(BlockArtifact::load(&path)?, artifact);
+        // compact
+        assert!(!std::fs::read_to_string(&path)?.contains('\n'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_artifact_store_retention() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = BlockArtifactStore::new(dir.path().to_path_buf(), 2);
+        let at_block = |block_number: u64, hash: u8| BlockArtifact {
+            block_number,
+            block_hash: BlockHash::with_last_byte(hash),
+            ..artifact(vec![order(1, 21000, 10)])
+        };
+        let other_file = dir.path().join("notes.txt");
+        std::fs::write(&other_file, "keep me")?;
+        let first = store.save(&at_block(10, 1))?;
+        let second = store.save(&at_block(10, 2))?;
+        let third = store.save(&at_block(11, 3))?;
+        assert!(first.exists() && second.exists() && third.exists());
+
+        let fourth = store.save(&at_block(12, 4))?;
+        assert!(!first.exists() && !second.exists());
+        assert!(third.exists() && fourth.exists());
+        assert!(other_file.exists());
+
+        // 0 keeps everything
+        let keep_all = BlockArtifactStore::new(dir.path().to_path_buf(), 0);
+        keep_all.save(&at_block(100, 5))?;
+        assert!(third.exists());
+        Ok(())
+    }
+}
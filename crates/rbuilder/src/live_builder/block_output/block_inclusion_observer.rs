@@ -0,0 +1,114 @@
+//! Components that follow our blocks until we know which one (if any) landed, eg: the
+//! [`InclusionNotifier`](super::inclusion_notifier::InclusionNotifier) or the
+//! [`ExposureBudget`](crate::building::exposure_budget::ExposureBudget).
+//! They are registered on a [`BlockInclusionObservers`] shared by the relay submission (submitted blocks) and the
+//! [`BlockSealingBidderFactory`](super::block_sealing_bidder_factory::BlockSealingBidderFactory) (landed blocks).
+
+use crate::{
+    building::ExecutionResult, live_builder::block_output::bidding::interfaces::LandedBlockInfo,
+};
+use alloy_primitives::B256;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Trait that receives every block we submit to the relays and the landed blocks.
+pub trait BlockInclusionObserver: std::fmt::Debug + Send + Sync {
+    /// Called on every block we submit (resealed ones included).
+    /// This should NOT block since it's executed in the submitting thread.
+    fn block_submitted(
+        &self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    );
+
+    /// New landed blocks (sorted ascending) as detected by the WalletBalanceWatcher.
+    fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]);
+}
+
+/// List of observers. Cheap to clone, clones share the list so observers registered while the builder starts are seen
+/// by the already created sinks.
+#[derive(Debug, Clone, Default)]
+pub struct BlockInclusionObservers {
+    observers: Arc<RwLock<Vec<Arc<dyn BlockInclusionObserver>>>>,
+}
+
+impl BlockInclusionObservers {
+    pub fn register(&self, observer: Arc<dyn BlockInclusionObserver>) {
+        self.observers.write().push(observer);
+    }
+
+    pub fn block_submitted(
+        &self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    ) {
+        for observer in self.observers.read().iter() {
+            observer.block_submitted(block_number, block_hash, included_orders);
+        }
+    }
+
+    pub fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]) {
+        for observer in self.observers.read().iter() {
+            observer.blocks_landed(landed_blocks);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use time::OffsetDateTime;
+
+    #[derive(Debug, Default)]
+    struct RecordingObserver {
+        submitted: Mutex<Vec<(u64, B256)>>,
+        landed: Mutex<Vec<u64>>,
+    }
+
+    impl BlockInclusionObserver for RecordingObserver {
+        fn block_submitted(
+            &self,
+            block_number: u64,
+            block_hash: B256,
+            _included_orders: &[ExecutionResult],
+        ) {
+            self.submitted.lock().push((block_number, block_hash));
+        }
+
+        fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]) {
+            self.landed
+                .lock()
+                .extend(landed_blocks.iter().map(|block| block.block_number));
+        }
+    }
+
+    #[test]
+    fn test_observers_registered_after_clone() {
+        let observers = BlockInclusionObservers::default();
+        let sink_observers = observers.clone();
+        let first = Arc::new(RecordingObserver::default());
+        let second = Arc::new(RecordingObserver::default());
+        observers.register(first.clone());
+        observers.register(second.clone());
+
+        sink_observers.block_submitted(20_000_000, B256::with_last_byte(1), &[]);
+        sink_observers.blocks_landed(&[LandedBlockInfo {
+            block_number: 20_000_000,
+            block_hash: B256::with_last_byte(1),
+            block_timestamp: OffsetDateTime::UNIX_EPOCH,
+            builder_balance: Default::default(),
+            beneficiary_is_builder: true,
+        }]);
+
+        for observer in [first, second] {
+            assert_eq!(
+                *observer.submitted.lock(),
+                vec![(20_000_000, B256::with_last_byte(1))]
+            );
+            assert_eq!(*observer.landed.lock(), vec![20_000_000]);
+        }
+    }
+}
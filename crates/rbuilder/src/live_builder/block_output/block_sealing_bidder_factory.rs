@@ -1,6 +1,10 @@
 use crate::{
     building::builders::{UnfinishedBlockBuildingSink, UnfinishedBlockBuildingSinkFactory},
-    live_builder::payload_events::MevBoostSlotData,
+    live_builder::{
+        payload_events::MevBoostSlotData, proposer_overrides::BiddingStrategy,
+        slot_outcome_predictor,
+    },
+    utils::clock::ClockRef,
 };
 use alloy_primitives::U256;
 use reth_provider::{HeaderProvider, StateProviderFactory};
@@ -10,12 +14,16 @@ use tracing::error;
 use super::{
     bid_value_source::interfaces::{BidValueObs, BidValueSource},
     bidding::{
+        bid_adjuster_bid_maker::{BidAdjusterBidMaker, BidAdjusterClient},
         interfaces::{BidMaker, BiddingService, SlotBidder},
         parallel_sealer_bid_maker::ParallelSealerBidMaker,
         sequential_sealer_bid_maker::SequentialSealerBidMaker,
+        urgent_reseal_bid_maker::{UrgentResealBidMaker, UrgentResealConfig},
         wallet_balance_watcher::WalletBalanceWatcher,
     },
-    relay_submit::BuilderSinkFactory,
+    block_inclusion_observer::BlockInclusionObservers,
+    relay_data_poller,
+    relay_submit::{BlockBuildingSink, BuilderSinkFactory},
 };
 
 /// UnfinishedBlockBuildingSinkFactory to bid blocks against the competition.
@@ -33,6 +41,12 @@ pub struct BlockSealingBidderFactory<P> {
     wallet_balance_watcher: WalletBalanceWatcher<P>,
     /// See [ParallelSealerBidMaker]
     max_concurrent_seals: usize,
+    /// See [UrgentResealBidMaker], None disables it.
+    urgent_reseal: Option<UrgentResealConfig>,
+    /// See [BidAdjusterBidMaker], None disables it.
+    bid_adjuster: Option<Arc<BidAdjusterClient>>,
+    /// Get the landed blocks detected by the wallet_balance_watcher.
+    block_inclusion_observers: BlockInclusionObservers,
 }
 
 impl<P> Debug for BlockSealingBidderFactory<P> {
@@ -45,6 +59,9 @@ impl<P> Debug for BlockSealingBidderFactory<P> {
                 &self.competition_bid_value_source,
             )
             .field("max_concurrent_seals", &self.max_concurrent_seals)
+            .field("urgent_reseal", &self.urgent_reseal)
+            .field("bid_adjuster", &self.bid_adjuster)
+            .field("block_inclusion_observers", &self.block_inclusion_observers)
             .finish()
     }
 }
@@ -56,6 +73,7 @@ impl<P> BlockSealingBidderFactory<P> {
         competition_bid_value_source: Arc<dyn BidValueSource + Send + Sync>,
         wallet_balance_watcher: WalletBalanceWatcher<P>,
         max_concurrent_seals: usize,
+        urgent_reseal: Option<UrgentResealConfig>,
     ) -> Self {
         Self {
             bidding_service,
@@ -63,6 +81,26 @@ impl<P> BlockSealingBidderFactory<P> {
             competition_bid_value_source,
             wallet_balance_watcher,
             max_concurrent_seals,
+            urgent_reseal,
+            bid_adjuster: None,
+            block_inclusion_observers: Default::default(),
+        }
+    }
+
+    pub fn with_bid_adjuster(self, bid_adjuster: Option<Arc<BidAdjusterClient>>) -> Self {
+        Self {
+            bid_adjuster,
+            ..self
+        }
+    }
+
+    pub fn with_block_inclusion_observers(
+        self,
+        block_inclusion_observers: BlockInclusionObservers,
+    ) -> Self {
+        Self {
+            block_inclusion_observers,
+            ..self
         }
     }
 }
@@ -87,43 +125,80 @@ where
         &mut self,
         slot_data: MevBoostSlotData,
         cancel: tokio_util::sync::CancellationToken,
+        clock: ClockRef,
     ) -> std::sync::Arc<dyn crate::building::builders::UnfinishedBlockBuildingSink> {
         match self
             .wallet_balance_watcher
             .update_to_block(slot_data.block() - 1)
         {
-            Ok(landed_blocks) => self
-                .bidding_service
-                .update_new_landed_blocks_detected(&landed_blocks),
+            Ok(landed_blocks) => {
+                self.block_inclusion_observers.blocks_landed(&landed_blocks);
+                slot_outcome_predictor::record_landed_blocks(&landed_blocks);
+                self.bidding_service
+                    .update_new_landed_blocks_detected(&landed_blocks)
+            }
             Err(error) => {
                 error!(error=?error, "Error updating wallet state");
                 self.bidding_service
                     .update_failed_reading_new_landed_blocks()
             }
         }
+        let slot_outcomes = relay_data_poller::take_slot_outcomes();
+        if !slot_outcomes.is_empty() {
+            self.bidding_service.update_slot_outcomes(&slot_outcomes);
+        }
+        relay_data_poller::record_slot(slot_data.slot(), slot_data.block(), slot_data.timestamp());
 
-        let finished_block_sink = self.block_sink_factory.create_builder_sink(
-            slot_data.clone(),
-            self.competition_bid_value_source.clone(),
-            cancel.clone(),
-        );
-        let sealer: Box<dyn BidMaker + Send + Sync> = if self.max_concurrent_seals == 1 {
+        let finished_block_sink: Arc<dyn BlockBuildingSink> =
+            Arc::from(self.block_sink_factory.create_builder_sink(
+                slot_data.clone(),
+                self.competition_bid_value_source.clone(),
+                cancel.clone(),
+                clock,
+            ));
+        let mut sealer: Box<dyn BidMaker + Send + Sync> = if self.max_concurrent_seals == 1 {
             Box::new(SequentialSealerBidMaker::new(
-                Arc::from(finished_block_sink),
+                finished_block_sink.clone(),
                 cancel.clone(),
             ))
         } else {
             Box::new(ParallelSealerBidMaker::new(
                 self.max_concurrent_seals,
-                Arc::from(finished_block_sink),
+                finished_block_sink.clone(),
                 cancel.clone(),
             ))
         };
+        if let Some(urgent_reseal) = self.urgent_reseal {
+            sealer = Box::new(UrgentResealBidMaker::new(
+                sealer,
+                finished_block_sink,
+                urgent_reseal,
+                cancel.clone(),
+            ));
+        }
+        let proposer_bidding = slot_data
+            .proposer_override
+            .as_ref()
+            .map(|proposer_override| proposer_override.bidding)
+            .unwrap_or_default();
+        if let Some(bid_adjuster) = self
+            .bid_adjuster
+            .as_ref()
+            .filter(|_| proposer_bidding != BiddingStrategy::TrueBlockValue)
+        {
+            sealer = Box::new(BidAdjusterBidMaker::new(
+                sealer,
+                bid_adjuster.clone(),
+                slot_data.slot(),
+                cancel.clone(),
+            ));
+        }
 
         let slot_bidder: Arc<dyn SlotBidder> = self.bidding_service.create_slot_bidder(
             slot_data.block(),
             slot_data.slot(),
             slot_data.timestamp(),
+            slot_data.proposer_override.clone(),
             sealer,
             cancel.clone(),
         );
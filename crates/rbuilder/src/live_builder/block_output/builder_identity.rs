@@ -0,0 +1,388 @@
+//! Scheduled rotation of the builder BLS identities.
+//! Each identity (normal + optimistic key) is active from an epoch until the next one starts, so keys can be rotated
+//! (eg: a new key every N epochs) without restarting with a new config.
+//! Relays must know a pubkey before we submit with it so the upcoming identity is announced (logged and served by
+//! admin_builderIdentities) announce_epochs epochs before it becomes active.
+//! The rotation is enforced per relay: a relay rejecting the signature of the new identity (pubkey not registered
+//! there yet) gets the previous identity for the rest of the epoch and the new one is tried again on the next epoch,
+//! so a late registration costs us no slots on that relay.
+
+use crate::{mev_boost::BLSBlockSigner, primitives::mev_boost::MevBoostRelayID};
+use ahash::HashMap;
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use primitive_types::H384;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub const SLOTS_PER_EPOCH: u64 = 32;
+
+#[derive(Debug, Clone)]
+pub struct BuilderIdentity {
+    /// First epoch this identity signs for.
+    pub from_epoch: u64,
+    pub signer: BLSBlockSigner,
+    pub optimistic_signer: BLSBlockSigner,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuilderIdentityReport {
+    pub pubkey: H384,
+    pub optimistic_pubkey: H384,
+    pub from_epoch: u64,
+    pub from_slot: u64,
+    /// Exclusive, None for the last identity.
+    pub until_epoch: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuilderIdentitiesReport {
+    pub epoch: u64,
+    pub active: BuilderIdentityReport,
+    pub next: Option<BuilderIdentityReport>,
+    /// Whole schedule (past identities included) sorted by from_epoch.
+    pub identities: Vec<BuilderIdentityReport>,
+}
+
+#[derive(Debug)]
+pub struct BuilderIdentitySchedule {
+    /// Sorted by from_epoch, the first one starts at epoch 0.
+    identities: Vec<BuilderIdentity>,
+    announce_epochs: u64,
+    /// relay -> epoch in which it rejected the active identity.
+    relays_on_previous_identity: Mutex<HashMap<MevBoostRelayID, u64>>,
+}
+
+impl BuilderIdentitySchedule {
+    /// initial is used until the first rotation. Fails if 2 rotations start at the same epoch.
+    pub fn new(
+        initial_signer: BLSBlockSigner,
+        initial_optimistic_signer: BLSBlockSigner,
+        mut rotations: Vec<BuilderIdentity>,
+        announce_epochs: u64,
+    ) -> eyre::Result<Self> {
+        rotations.sort_by_key(|identity| identity.from_epoch);
+        if let Some(pair) = rotations
+            .windows(2)
+            .find(|pair| pair[0].from_epoch == pair[1].from_epoch)
+        {
+            eyre::bail!(
+                "Two builder identities start at epoch {}",
+                pair[0].from_epoch
+            );
+        }
+        let mut identities = Vec::with_capacity(rotations.len() + 1);
+        if rotations.first().map_or(true, |first| first.from_epoch > 0) {
+            identities.push(BuilderIdentity {
+                from_epoch: 0,
+                signer: initial_signer,
+                optimistic_signer: initial_optimistic_signer,
+            });
+        }
+        identities.extend(rotations);
+        Ok(Self {
+            identities,
+            announce_epochs,
+            relays_on_previous_identity: Default::default(),
+        })
+    }
+
+    /// Schedule that never rotates.
+    pub fn fixed(signer: BLSBlockSigner, optimistic_signer: BLSBlockSigner) -> Self {
+        Self {
+            identities: vec![BuilderIdentity {
+                from_epoch: 0,
+                signer,
+                optimistic_signer,
+            }],
+            announce_epochs: 0,
+            relays_on_previous_identity: Default::default(),
+        }
+    }
+
+    fn active_index(&self, epoch: u64) -> usize {
+        // identities[0].from_epoch == 0 so this is never 0
+        self.identities
+            .partition_point(|identity| identity.from_epoch <= epoch)
+            - 1
+    }
+
+    pub fn identity_for_slot(&self, slot: u64) -> &BuilderIdentity {
+        &self.identities[self.active_index(slot / SLOTS_PER_EPOCH)]
+    }
+
+    /// Identity to sign the submissions to relay for slot, the active one unless relay rejected it this epoch.
+    pub fn identity_for_relay(&self, slot: u64, relay: &MevBoostRelayID) -> &BuilderIdentity {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        let active = self.active_index(epoch);
+        if active > 0 && self.relays_on_previous_identity.lock().get(relay) == Some(&epoch) {
+            &self.identities[active - 1]
+        } else {
+            &self.identities[active]
+        }
+    }
+
+    /// relay rejected the signature of a submission for slot signed by builder_pubkey.
+    /// Returns true if relay falls back to the previous identity, false if the rejected pubkey is not a rotated
+    /// active one (eg: the previous identity was also rejected) so there is nothing to fall back to.
+    pub fn record_signature_rejected(
+        &self,
+        slot: u64,
+        relay: &MevBoostRelayID,
+        builder_pubkey: H384,
+    ) -> bool {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        let active = self.active_index(epoch);
+        let identity = &self.identities[active];
+        if active == 0
+            || (identity.signer.pub_key() != builder_pubkey
+                && identity.optimistic_signer.pub_key() != builder_pubkey)
+        {
+            return false;
+        }
+        if self
+            .relays_on_previous_identity
+            .lock()
+            .insert(relay.clone(), epoch)
+            != Some(epoch)
+        {
+            warn!(
+                relay,
+                epoch,
+                pubkey = ?builder_pubkey,
+                "Relay rejected the rotated builder identity, using the previous one for it until the next epoch"
+            );
+        }
+        true
+    }
+
+    /// Identity that will be used after the active one for slot.
+    pub fn next_identity(&self, slot: u64) -> Option<&BuilderIdentity> {
+        self.identities
+            .get(self.active_index(slot / SLOTS_PER_EPOCH) + 1)
+    }
+
+    pub fn identities(&self) -> &[BuilderIdentity] {
+        &self.identities
+    }
+
+    fn identity_report(&self, index: usize) -> BuilderIdentityReport {
+        let identity = &self.identities[index];
+        BuilderIdentityReport {
+            pubkey: identity.signer.pub_key(),
+            optimistic_pubkey: identity.optimistic_signer.pub_key(),
+            from_epoch: identity.from_epoch,
+            from_slot: identity.from_epoch * SLOTS_PER_EPOCH,
+            until_epoch: self
+                .identities
+                .get(index + 1)
+                .map(|identity| identity.from_epoch),
+        }
+    }
+
+    pub fn report(&self, slot: u64) -> BuilderIdentitiesReport {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        let active = self.active_index(epoch);
+        BuilderIdentitiesReport {
+            epoch,
+            active: self.identity_report(active),
+            next: (active + 1 < self.identities.len()).then(|| self.identity_report(active + 1)),
+            identities: (0..self.identities.len())
+                .map(|index| self.identity_report(index))
+                .collect(),
+        }
+    }
+
+    /// Next identity if it becomes active in at most announce_epochs epochs.
+    pub fn pending_announcement(&self, slot: u64) -> Option<&BuilderIdentity> {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        self.next_identity(slot)
+            .filter(|next| next.from_epoch - epoch <= self.announce_epochs)
+    }
+
+    /// Call on every slot, logs rotations and (on the first slot of each epoch) upcoming ones.
+    pub fn log_slot(&self, slot: u64) {
+        let epoch = slot / SLOTS_PER_EPOCH;
+        if slot % SLOTS_PER_EPOCH != 0 {
+            return;
+        }
+        let active = self.identity_for_slot(slot);
+        if active.from_epoch == epoch && epoch > 0 {
+            info!(
+                epoch,
+                pubkey = ?active.signer.pub_key(),
+                optimistic_pubkey = ?active.optimistic_signer.pub_key(),
+                "Builder identity rotated"
+            );
+        }
+        if let Some(next) = self.pending_announcement(slot) {
+            warn!(
+                epoch,
+                rotation_epoch = next.from_epoch,
+                rotation_slot = next.from_epoch * SLOTS_PER_EPOCH,
+                pubkey = ?next.signer.pub_key(),
+                optimistic_pubkey = ?next.optimistic_signer.pub_key(),
+                "Upcoming builder identity rotation, make sure the new pubkeys are registered on the relays"
+            );
+        }
+    }
+}
+
+lazy_static! {
+    static ref SCHEDULE: Mutex<Option<Arc<BuilderIdentitySchedule>>> = Mutex::new(None);
+}
+
+/// Makes the schedule visible to admin_builderIdentities.
+pub fn set_builder_identity_schedule(schedule: Arc<BuilderIdentitySchedule>) {
+    *SCHEDULE.lock() = Some(schedule);
+}
+
+/// Pubkeys (normal and optimistic) of all the scheduled identities, empty if we don't submit to relays.
+pub fn builder_pubkeys() -> Vec<H384> {
+    let Some(schedule) = SCHEDULE.lock().clone() else {
+        return Vec::new();
+    };
+    let mut pubkeys = Vec::new();
+    for identity in schedule.identities() {
+        for pubkey in [
+            identity.signer.pub_key(),
+            identity.optimistic_signer.pub_key(),
+        ] {
+            if !pubkeys.contains(&pubkey) {
+                pubkeys.push(pubkey);
+            }
+        }
+    }
+    pubkeys
+}
+
+/// - admin_builderIdentities(slot): active/next identity for slot and the whole schedule (null if we don't submit to
+///   relays).
+pub fn builder_identities_rpc_module() -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    module.register_method("admin_builderIdentities", |params, _| {
+        let slot: u64 = params.one()?;
+        Ok::<_, ErrorObject<'static>>(
+            SCHEDULE
+                .lock()
+                .as_ref()
+                .map(|schedule| schedule.report(slot)),
+        )
+    })?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_consensus::crypto::SecretKey;
+
+    fn signer(byte: u8) -> BLSBlockSigner {
+        let mut key = [0u8; 32];
+        key[31] = byte;
+        BLSBlockSigner::new(
+            SecretKey::try_from(key.as_slice()).unwrap(),
+            Default::default(),
+        )
+        .unwrap()
+    }
+
+    fn identity(from_epoch: u64, byte: u8) -> BuilderIdentity {
+        BuilderIdentity {
+            from_epoch,
+            signer: signer(byte),
+            optimistic_signer: signer(byte + 100),
+        }
+    }
+
+    #[test]
+    fn test_identity_schedule() {
+        let schedule = BuilderIdentitySchedule::new(
+            signer(1),
+            signer(101),
+            vec![identity(20, 3), identity(10, 2)],
+            2,
+        )
+        .unwrap();
+        let pub_key = |slot| schedule.identity_for_slot(slot).signer.pub_key();
+
+        assert_eq!(pub_key(0), signer(1).pub_key());
+        assert_eq!(pub_key(10 * SLOTS_PER_EPOCH - 1), signer(1).pub_key());
+        assert_eq!(pub_key(10 * SLOTS_PER_EPOCH), signer(2).pub_key());
+        assert_eq!(
+            schedule
+                .identity_for_slot(10 * SLOTS_PER_EPOCH)
+                .optimistic_signer
+                .pub_key(),
+            signer(102).pub_key()
+        );
+        assert_eq!(pub_key(1_000 * SLOTS_PER_EPOCH), signer(3).pub_key());
+
+        assert!(schedule.pending_announcement(7 * SLOTS_PER_EPOCH).is_none());
+        assert_eq!(
+            schedule
+                .pending_announcement(8 * SLOTS_PER_EPOCH)
+                .map(|next| next.from_epoch),
+            Some(10)
+        );
+
+        let report = schedule.report(15 * SLOTS_PER_EPOCH + 3);
+        assert_eq!(report.epoch, 15);
+        assert_eq!(report.active.from_epoch, 10);
+        assert_eq!(report.active.until_epoch, Some(20));
+        assert_eq!(report.next.as_ref().map(|next| next.from_slot), Some(640));
+        assert_eq!(report.identities.len(), 3);
+        assert!(schedule.report(20 * SLOTS_PER_EPOCH).next.is_none());
+    }
+
+    #[test]
+    fn test_identity_schedule_rotation_at_genesis() {
+        let schedule =
+            BuilderIdentitySchedule::new(signer(1), signer(101), vec![identity(0, 2)], 0).unwrap();
+        assert_eq!(schedule.identities().len(), 1);
+        assert_eq!(
+            schedule.identity_for_slot(0).signer.pub_key(),
+            signer(2).pub_key()
+        );
+
+        assert!(BuilderIdentitySchedule::new(
+            signer(1),
+            signer(101),
+            vec![identity(5, 2), identity(5, 3)],
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_relay_falls_back_to_previous_identity() {
+        let schedule =
+            BuilderIdentitySchedule::new(signer(1), signer(101), vec![identity(10, 2)], 0).unwrap();
+        let relay = "relay".to_string();
+        let slot = 10 * SLOTS_PER_EPOCH + 1;
+        let pub_key = |slot| schedule.identity_for_relay(slot, &relay).signer.pub_key();
+        assert_eq!(pub_key(slot), signer(2).pub_key());
+
+        // nothing to fall back to before the first rotation
+        assert!(!schedule.record_signature_rejected(1, &relay, signer(1).pub_key()));
+        // the rejected key is not the active one
+        assert!(!schedule.record_signature_rejected(slot, &relay, signer(1).pub_key()));
+        assert_eq!(pub_key(slot), signer(2).pub_key());
+
+        assert!(schedule.record_signature_rejected(slot, &relay, signer(102).pub_key()));
+        assert_eq!(pub_key(slot), signer(1).pub_key());
+        assert_eq!(
+            schedule
+                .identity_for_relay(slot, &"other".to_string())
+                .signer
+                .pub_key(),
+            signer(2).pub_key()
+        );
+        // new key tried again on the next epoch
+        assert_eq!(pub_key(slot + SLOTS_PER_EPOCH), signer(2).pub_key());
+    }
+}
@@ -0,0 +1,280 @@
+//! Exclusion audit trail.
+//! For every slot we record, for the last block we submitted, every order we tried and excluded together with the reason.
+//! Entries are hash-chained (each entry hash covers the previous one) starting from a seed derived from the slot and block hash,
+//! and the final hash is signed by the builder coinbase key so anybody can verify the log was not modified afterwards.
+//! Audits are appended as json lines to a file.
+
+use crate::{
+    building::{BuiltBlockTrace, ExclusionReason},
+    mev_boost::SubmitBlockRequest,
+    primitives::OrderId,
+    utils::Signer,
+};
+use alloy_primitives::{Address, Signature, B256, U256};
+use parking_lot::Mutex;
+use reth_primitives::SealedBlock;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::error;
+
+use super::bid_observer::BidObserver;
+
+const CHAIN_SEED_DOMAIN: &[u8] = b"rbuilder-exclusion-audit";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExclusionAuditEntry {
+    pub order_id: OrderId,
+    #[serde(flatten)]
+    pub reason: ExclusionReason,
+    /// Hash of the previous entry (or the chain seed for the first one).
+    pub prev_hash: B256,
+    /// sha256(prev_hash || json([order_id, reason]))
+    pub hash: B256,
+}
+
+/// All the exclusions of the last block we submitted for a slot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotExclusionAudit {
+    pub slot: u64,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub entries: Vec<ExclusionAuditEntry>,
+    /// Hash of the last entry, the chain seed if there are no entries.
+    pub chain_head: B256,
+    pub signer: Address,
+    /// Signature of chain_head.
+    pub signature: Signature,
+}
+
+fn chain_seed(slot: u64, block_hash: &B256) -> B256 {
+    let mut hasher = Sha256::new();
+    hasher.update(CHAIN_SEED_DOMAIN);
+    hasher.update(slot.to_be_bytes());
+    hasher.update(block_hash);
+    B256::from_slice(&hasher.finalize())
+}
+
+fn entry_hash(prev_hash: &B256, order_id: &OrderId, reason: &ExclusionReason) -> B256 {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash);
+    // serializing a tuple of serializable values can't fail
+    hasher.update(serde_json::to_vec(&(order_id, reason)).unwrap_or_default());
+    B256::from_slice(&hasher.finalize())
+}
+
+impl SlotExclusionAudit {
+    pub fn new(
+        slot: u64,
+        block_number: u64,
+        block_hash: B256,
+        exclusions: Vec<(OrderId, ExclusionReason)>,
+        signer: &Signer,
+    ) -> Result<Self, secp256k1::Error> {
+        let mut prev_hash = chain_seed(slot, &block_hash);
+        let mut entries = Vec::with_capacity(exclusions.len());
+        for (order_id, reason) in exclusions {
+            let hash = entry_hash(&prev_hash, &order_id, &reason);
+            entries.push(ExclusionAuditEntry {
+                order_id,
+                reason,
+                prev_hash,
+                hash,
+            });
+            prev_hash = hash;
+        }
+        let signature = signer.sign_message(prev_hash)?;
+        Ok(Self {
+            slot,
+            block_number,
+            block_hash,
+            entries,
+            chain_head: prev_hash,
+            signer: signer.address,
+            signature,
+        })
+    }
+
+    /// Recomputes the hash chain and checks the signature of the chain head.
+    pub fn verify(&self) -> bool {
+        let mut prev_hash = chain_seed(self.slot, &self.block_hash);
+        for entry in &self.entries {
+            if entry.prev_hash != prev_hash
+                || entry.hash != entry_hash(&prev_hash, &entry.order_id, &entry.reason)
+            {
+                return false;
+            }
+            prev_hash = entry.hash;
+        }
+        if prev_hash != self.chain_head {
+            return false;
+        }
+        self.signature
+            .recover_address_from_prehash(&self.chain_head)
+            .map_or(false, |address| address == self.signer)
+    }
+}
+
+#[derive(Debug)]
+struct LastSubmittedBlock {
+    slot: u64,
+    block_number: u64,
+    block_hash: B256,
+    exclusions: Vec<(OrderId, ExclusionReason)>,
+}
+
+/// BidObserver that writes a [`SlotExclusionAudit`] for the last block submitted on each slot.
+/// The audit for a slot is written when we see a bid for a new slot (or on drop).
+#[derive(Debug)]
+pub struct ExclusionAuditBidObserver {
+    signer: Signer,
+    path: PathBuf,
+    last_submitted_block: Mutex<Option<LastSubmittedBlock>>,
+}
+
+impl ExclusionAuditBidObserver {
+    pub fn new(signer: Signer, path: impl AsRef<Path>) -> Self {
+        Self {
+            signer,
+            path: path.as_ref().to_path_buf(),
+            last_submitted_block: Mutex::new(None),
+        }
+    }
+
+    fn write_audit(&self, block: LastSubmittedBlock) {
+        let audit = match SlotExclusionAudit::new(
+            block.slot,
+            block.block_number,
+            block.block_hash,
+            block.exclusions,
+            &self.signer,
+        ) {
+            Ok(audit) => audit,
+            Err(err) => {
+                error!(?err, slot = block.slot, "Failed to sign exclusion audit");
+                return;
+            }
+        };
+        let res = serde_json::to_string(&audit)
+            .map_err(eyre::Report::from)
+            .and_then(|line| {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                writeln!(file, "{}", line)?;
+                Ok(())
+            });
+        if let Err(err) = res {
+            error!(?err, slot = audit.slot, "Failed to write exclusion audit");
+        }
+    }
+}
+
+impl BidObserver for ExclusionAuditBidObserver {
+    fn block_submitted(
+        &self,
+        sealed_block: SealedBlock,
+        submit_block_request: SubmitBlockRequest,
+        built_block_trace: BuiltBlockTrace,
+        _builder_name: String,
+        _best_bid_value: U256,
+    ) {
+        let new_block = LastSubmittedBlock {
+            slot: submit_block_request.bid_trace().slot,
+            block_number: sealed_block.number,
+            block_hash: sealed_block.header.hash(),
+            exclusions: built_block_trace.final_exclusions(),
+        };
+        let finished_slot_block = {
+            let mut last_submitted_block = self.last_submitted_block.lock();
+            match last_submitted_block.take() {
+                Some(block) if block.slot != new_block.slot => {
+                    *last_submitted_block = Some(new_block);
+                    Some(block)
+                }
+                _ => {
+                    *last_submitted_block = Some(new_block);
+                    None
+                }
+            }
+        };
+        if let Some(block) = finished_slot_block {
+            self.write_audit(block);
+        }
+    }
+}
+
+impl Drop for ExclusionAuditBidObserver {
+    fn drop(&mut self) {
+        if let Some(block) = self.last_submitted_block.lock().take() {
+            self.write_audit(block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn exclusions() -> Vec<(OrderId, ExclusionReason)> {
+        vec![
+            (
+                OrderId::Bundle(Uuid::from_u128(1)),
+                ExclusionReason::Conflict,
+            ),
+            (OrderId::Tx(B256::with_last_byte(2)), ExclusionReason::Gas),
+            (
+                OrderId::Bundle(Uuid::from_u128(3)),
+                ExclusionReason::FilterRule {
+                    rule_id: "blocklist".to_string(),
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_audit_verifies() {
+        let signer = Signer::random();
+        let audit = SlotExclusionAudit::new(10, 20, B256::with_last_byte(1), exclusions(), &signer)
+            .unwrap();
+        assert_eq!(audit.entries.len(), 3);
+        assert_eq!(audit.entries[1].prev_hash, audit.entries[0].hash);
+        assert_eq!(audit.chain_head, audit.entries[2].hash);
+        assert!(audit.verify());
+
+        let empty =
+            SlotExclusionAudit::new(10, 20, B256::with_last_byte(1), vec![], &signer).unwrap();
+        assert!(empty.verify());
+    }
+
+    #[test]
+    fn test_tampered_audit_fails() {
+        let signer = Signer::random();
+        let audit = SlotExclusionAudit::new(10, 20, B256::with_last_byte(1), exclusions(), &signer)
+            .unwrap();
+
+        let mut changed_reason = audit.clone();
+        changed_reason.entries[1].reason = ExclusionReason::Unprofitable;
+        assert!(!changed_reason.verify());
+
+        let mut removed_entry = audit.clone();
+        removed_entry.entries.remove(2);
+        assert!(!removed_entry.verify());
+
+        let mut other_signer = audit.clone();
+        other_signer.signer = Signer::random().address;
+        assert!(!other_signer.verify());
+
+        let mut other_slot = audit;
+        other_slot.slot = 11;
+        assert!(!other_slot.verify());
+    }
+}
@@ -0,0 +1,385 @@
+//! Notifies orderflow partners (see [`crate::live_builder::order_input::tenants`]) which of their orders landed on chain
+//! in one of our blocks together with the refunds (kickbacks) paid to their users so they can settle automatically.
+//!
+//! The [`InclusionNotifier`] is a [`BlockInclusionObserver`]: we remember the tenant orders of every block we submit
+//! and when the [`WalletBalanceWatcher`](super::bidding::wallet_balance_watcher::WalletBalanceWatcher) detects that one
+//! of our blocks landed a json [`InclusionNotification`] is POSTed to the `inclusion_webhook_url` of each tenant with
+//! orders in it.
+
+use crate::{
+    building::ExecutionResult,
+    live_builder::{
+        block_output::{
+            bidding::interfaces::LandedBlockInfo, block_inclusion_observer::BlockInclusionObserver,
+        },
+        order_input::tenants::{TenantId, TenantRegistry},
+    },
+    primitives::OrderId,
+    telemetry::inc_inclusion_notifications,
+};
+use ahash::HashMap;
+use alloy_primitives::{Address, B256, U256};
+use parking_lot::Mutex;
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Block numbers for which we keep the submitted blocks.
+const MAX_TRACKED_BLOCK_NUMBERS: usize = 8;
+const MAX_DELIVERY_ATTEMPTS: u32 = 3;
+const DELIVERY_RETRY_DELAY: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderRefund {
+    pub recipient: Address,
+    pub value: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludedOrder {
+    pub order_id: OrderId,
+    pub tx_hashes: Vec<B256>,
+    pub coinbase_profit: U256,
+    /// Refunds paid in the block to the users of the order.
+    pub refunds: Vec<OrderRefund>,
+}
+
+impl From<&ExecutionResult> for IncludedOrder {
+    fn from(result: &ExecutionResult) -> Self {
+        Self {
+            order_id: result.order.id(),
+            tx_hashes: result.txs.iter().map(|tx| tx.hash()).collect(),
+            coinbase_profit: result.coinbase_profit,
+            refunds: result
+                .paid_kickbacks
+                .iter()
+                .map(|(recipient, value)| OrderRefund {
+                    recipient: *recipient,
+                    value: *value,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What a tenant gets for each of our landed blocks containing its orders.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionNotification {
+    pub tenant: String,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub orders: Vec<IncludedOrder>,
+}
+
+/// Remembers the tenant orders of the blocks we submitted until we know which one (if any) landed.
+#[derive(Debug)]
+struct SubmittedBlocks {
+    tenants: Arc<TenantRegistry>,
+    /// block_number -> block_hash -> notifications for the block.
+    blocks: BTreeMap<u64, HashMap<B256, Vec<(TenantId, InclusionNotification)>>>,
+}
+
+impl SubmittedBlocks {
+    fn new(tenants: Arc<TenantRegistry>) -> Self {
+        Self {
+            tenants,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, block_number: u64, block_hash: B256, included_orders: &[ExecutionResult]) {
+        let mut by_tenant: HashMap<TenantId, Vec<IncludedOrder>> = HashMap::default();
+        for result in included_orders {
+            let Some(tenant) = self.tenants.tenant_of(&result.order) else {
+                continue;
+            };
+            if self.tenants.config(tenant).inclusion_webhook_url.is_none() {
+                continue;
+            }
+            by_tenant.entry(tenant).or_default().push(result.into());
+        }
+        if by_tenant.is_empty() {
+            return;
+        }
+        let notifications = by_tenant
+            .into_iter()
+            .map(|(tenant, orders)| {
+                (
+                    tenant,
+                    InclusionNotification {
+                        tenant: self.tenants.name(tenant).to_string(),
+                        block_number,
+                        block_hash,
+                        orders,
+                    },
+                )
+            })
+            .collect();
+        self.blocks
+            .entry(block_number)
+            .or_default()
+            .insert(block_hash, notifications);
+        while self.blocks.len() > MAX_TRACKED_BLOCK_NUMBERS {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Notifications for the landed blocks that are ours. Forgets everything up to the last landed block.
+    fn take_landed(
+        &mut self,
+        landed_blocks: &[LandedBlockInfo],
+    ) -> Vec<(TenantId, InclusionNotification)> {
+        let mut res = Vec::new();
+        for landed_block in landed_blocks {
+            let mut submitted = self.blocks.split_off(&(landed_block.block_number + 1));
+            std::mem::swap(&mut submitted, &mut self.blocks);
+            if !landed_block.beneficiary_is_builder {
+                continue;
+            }
+            if let Some(mut blocks) = submitted.remove(&landed_block.block_number) {
+                if let Some(notifications) = blocks.remove(&landed_block.block_hash) {
+                    res.extend(notifications);
+                }
+            }
+        }
+        res
+    }
+}
+
+#[derive(Debug)]
+pub struct InclusionNotifier {
+    submitted_blocks: Mutex<SubmittedBlocks>,
+    /// (webhook url, notification) to the delivery task.
+    deliveries: mpsc::UnboundedSender<(String, InclusionNotification)>,
+}
+
+impl BlockInclusionObserver for InclusionNotifier {
+    fn block_submitted(
+        &self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    ) {
+        self.submitted_blocks
+            .lock()
+            .record(block_number, block_hash, included_orders);
+    }
+
+    fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]) {
+        let mut submitted_blocks = self.submitted_blocks.lock();
+        for (tenant, notification) in submitted_blocks.take_landed(landed_blocks) {
+            let Some(url) = submitted_blocks
+                .tenants
+                .config(tenant)
+                .inclusion_webhook_url
+                .clone()
+            else {
+                continue;
+            };
+            if self.deliveries.send((url, notification)).is_err() {
+                warn!("Inclusion notifier delivery task is gone");
+                return;
+            }
+        }
+    }
+}
+
+async fn deliver(client: reqwest::Client, url: String, notification: InclusionNotification) {
+    let body = match serde_json::to_vec(&notification) {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(?err, "Failed to serialize inclusion notification");
+            return;
+        }
+    };
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        let res = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match res {
+            Ok(_) => {
+                debug!(
+                    tenant = notification.tenant,
+                    block = notification.block_number,
+                    orders = notification.orders.len(),
+                    "Inclusion notification delivered"
+                );
+                inc_inclusion_notifications(&notification.tenant, "delivered");
+                return;
+            }
+            Err(err) => {
+                warn!(
+                    ?err,
+                    tenant = notification.tenant,
+                    block = notification.block_number,
+                    attempt,
+                    "Failed to deliver inclusion notification"
+                );
+                if attempt < MAX_DELIVERY_ATTEMPTS {
+                    tokio::time::sleep(DELIVERY_RETRY_DELAY * attempt).await;
+                }
+            }
+        }
+    }
+    inc_inclusion_notifications(&notification.tenant, "failed");
+}
+
+/// Spawns the task delivering the notifications until `global_cancel` is cancelled.
+/// Returns the notifier to register as a [`BlockInclusionObserver`].
+pub fn spawn_inclusion_notifier(
+    tenants: Arc<TenantRegistry>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<Arc<InclusionNotifier>> {
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()?;
+    let (deliveries, mut deliveries_rx) = mpsc::unbounded_channel();
+    let notifier = Arc::new(InclusionNotifier {
+        submitted_blocks: Mutex::new(SubmittedBlocks::new(tenants)),
+        deliveries,
+    });
+    tokio::spawn(async move {
+        loop {
+            let (url, notification) = tokio::select! {
+                _ = global_cancel.cancelled() => return,
+                delivery = deliveries_rx.recv() => match delivery {
+                    Some(delivery) => delivery,
+                    None => return,
+                },
+            };
+            // one slow tenant should not delay the others
+            tokio::spawn(deliver(client.clone(), url, notification));
+        }
+    });
+    Ok(notifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::order_input::tenants::TenantConfig,
+        primitives::{Bundle, Metadata, Order, SimValue},
+    };
+    use time::OffsetDateTime;
+
+    fn tenant_registry() -> Arc<TenantRegistry> {
+        Arc::new(
+            TenantRegistry::new(vec![
+                TenantConfig {
+                    name: "notified".to_string(),
+                    signers: vec![Address::with_last_byte(1)],
+                    inclusion_webhook_url: Some("http://localhost/notify".to_string()),
+                    ..Default::default()
+                },
+                TenantConfig {
+                    name: "silent".to_string(),
+                    signers: vec![Address::with_last_byte(2)],
+                    ..Default::default()
+                },
+            ])
+            .unwrap(),
+        )
+    }
+
+    fn result(signer: u8, id: u128) -> ExecutionResult {
+        ExecutionResult {
+            coinbase_profit: U256::from(10),
+            inplace_sim: SimValue::default(),
+            gas_used: 21_000,
+            order: Order::Bundle(Bundle {
+                block: 1,
+                min_timestamp: None,
+                max_timestamp: None,
+                txs: Vec::new(),
+                reverting_tx_hashes: Vec::new(),
+                hash: Default::default(),
+                uuid: uuid::Uuid::from_u128(id),
+                replacement_data: None,
+                signer: Some(Address::with_last_byte(signer)),
+                metadata: Metadata::default(),
+            }),
+            txs: Vec::new(),
+            original_order_ids: Vec::new(),
+            receipts: Vec::new(),
+            nonces_updated: Vec::new(),
+            paid_kickbacks: vec![(Address::with_last_byte(9), U256::from(3))],
+        }
+    }
+
+    fn landed(block_number: u64, block_hash: B256, ours: bool) -> LandedBlockInfo {
+        LandedBlockInfo {
+            block_number,
+            block_hash,
+            block_timestamp: OffsetDateTime::UNIX_EPOCH,
+            builder_balance: U256::ZERO,
+            beneficiary_is_builder: ours,
+        }
+    }
+
+    #[test]
+    fn test_landed_block_notifications() {
+        let mut blocks = SubmittedBlocks::new(tenant_registry());
+        let hash_a = B256::with_last_byte(1);
+        let hash_b = B256::with_last_byte(2);
+        blocks.record(10, hash_a, &[result(1, 1), result(2, 2), result(3, 3)]);
+        blocks.record(10, hash_b, &[result(1, 4), result(1, 5)]);
+        // nothing from notified tenants
+        blocks.record(11, hash_a, &[result(2, 6)]);
+        assert_eq!(blocks.blocks.len(), 1);
+
+        let notifications = blocks.take_landed(&[landed(10, hash_b, true)]);
+        assert_eq!(notifications.len(), 1);
+        let notification = &notifications[0].1;
+        assert_eq!(notification.tenant, "notified");
+        assert_eq!(notification.block_hash, hash_b);
+        assert_eq!(notification.orders.len(), 2);
+        assert_eq!(
+            notification.orders[0].refunds,
+            vec![OrderRefund {
+                recipient: Address::with_last_byte(9),
+                value: U256::from(3)
+            }]
+        );
+        assert!(blocks.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_not_our_block() {
+        let mut blocks = SubmittedBlocks::new(tenant_registry());
+        let hash = B256::with_last_byte(1);
+        blocks.record(10, hash, &[result(1, 1)]);
+        blocks.record(12, hash, &[result(1, 2)]);
+        assert!(blocks.take_landed(&[landed(10, hash, false)]).is_empty());
+        // other hash
+        assert!(blocks
+            .take_landed(&[landed(12, B256::with_last_byte(2), true)])
+            .is_empty());
+        assert!(blocks.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_notification_json() {
+        let notification = InclusionNotification {
+            tenant: "notified".to_string(),
+            block_number: 10,
+            block_hash: B256::with_last_byte(1),
+            orders: vec![(&result(1, 1)).into()],
+        };
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["blockNumber"], 10);
+        assert!(!json["orders"][0]["orderId"].is_null());
+        assert!(json["orders"][0]["refunds"][0]["recipient"].is_string());
+    }
+}
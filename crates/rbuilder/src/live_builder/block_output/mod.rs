@@ -1,5 +1,14 @@
 pub mod bid_observer;
 pub mod bid_value_source;
 pub mod bidding;
+pub mod block_artifact;
+pub mod block_inclusion_observer;
 pub mod block_sealing_bidder_factory;
+pub mod builder_identity;
+pub mod exclusion_audit;
+pub mod inclusion_notifier;
+pub mod payload_signer;
+pub mod refund_settlement;
+pub mod relay_data_poller;
 pub mod relay_submit;
+pub mod submission_audit;
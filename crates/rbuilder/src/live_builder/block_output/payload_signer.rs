@@ -0,0 +1,92 @@
+//! Final payload assembly ([`crate::mev_boost::sign_block_for_relay`]: tx and blobs encoding, BLS signature) runs
+//! on a dedicated thread instead of the tokio runtime so a busy executor can't delay the last submissions of a slot.
+//! The thread tries to raise its priority (needs CAP_SYS_NICE, if it fails we keep the default one) and holds
+//! at most [`SIGNING_QUEUE_SIZE`] pending jobs, callers wait for a free place.
+
+use std::{
+    io,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+pub const SIGNING_QUEUE_SIZE: usize = 4;
+/// Nice value for the signing thread (default is 0, lower is higher priority).
+pub const SIGNING_THREAD_NICE: i32 = -10;
+
+type SigningJob = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+pub struct PayloadSigningThread {
+    jobs: flume::Sender<SigningJob>,
+}
+
+impl PayloadSigningThread {
+    /// The thread ends when self is dropped.
+    pub fn spawn() -> io::Result<Self> {
+        let (jobs, receiver) = flume::bounded::<SigningJob>(SIGNING_QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("payload_signer".to_string())
+            .spawn(move || {
+                raise_thread_priority();
+                for job in receiver.iter() {
+                    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        error!("Payload signing job panicked");
+                    }
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+
+    /// Runs job on the signing thread and waits for its result.
+    /// None if the job panicked or the thread is gone.
+    pub async fn run<T, F>(&self, job: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let job: SigningJob = Box::new(move || {
+            let _ = result_sender.send(job());
+        });
+        self.jobs.send_async(job).await.ok()?;
+        result_receiver.await.ok()
+    }
+}
+
+fn raise_thread_priority() {
+    // On linux PRIO_PROCESS + 0 is the calling thread, not the whole process.
+    let res = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, SIGNING_THREAD_NICE) };
+    if res == 0 {
+        info!(
+            nice = SIGNING_THREAD_NICE,
+            "Payload signing thread priority raised"
+        );
+    } else {
+        warn!(
+            err = ?io::Error::last_os_error(),
+            "Could not raise payload signing thread priority"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_payload_signing_thread() {
+        let signer = PayloadSigningThread::spawn().unwrap();
+        let caller = std::thread::current().id();
+        let (value, thread) = signer
+            .run(|| (21 * 2, std::thread::current().id()))
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+        assert_ne!(thread, caller);
+
+        // a panicking job doesn't take the thread down
+        assert_eq!(signer.run(|| -> u64 { panic!("bad payload") }).await, None);
+        assert_eq!(signer.run(|| 7).await, Some(7));
+    }
+}
@@ -0,0 +1,747 @@
+//! Deferred mev-share refund settlement ([`RefundSettlementMode::Deferred`]).
+//! Our blocks don't contain refund txs, the refunds of the included sbundles are owed to their users instead.
+//! When one of our blocks lands its refunds accrue on a ledger persisted as json and every `interval` a settlement
+//! pays (one plain transfer per recipient from the builder wallet) the accrued refunds above `min_value`.
+//! This saves the gas of one payout tx per sbundle and the block space they use.
+//!
+//! Sent payments stay pending until we find their receipt. If the nonce is consumed by another tx (eg: the proposer
+//! payout of one of our blocks) the payment was dropped and its value accrues again. No new settlement is sent while
+//! payments are pending.
+//! In dry run mode nothing is sent, the settlement we would send is only logged. admin_refundSettlementReport always
+//! returns the ledger and the next settlement.
+//!
+//! [`RefundSettlementMode::Deferred`]: crate::building::RefundSettlementMode::Deferred
+
+use crate::{
+    building::{
+        gas_price_oracle::{GasPriceOracle, OwnTxGasPrice},
+        payout_tx::create_payout_tx,
+        ExecutionResult,
+    },
+    live_builder::block_output::{
+        bidding::interfaces::LandedBlockInfo, block_inclusion_observer::BlockInclusionObserver,
+    },
+    telemetry::{inc_refund_settlement_payments, set_accrued_refunds},
+    utils::{constants::BASE_TX_GAS, Signer},
+};
+use ahash::HashMap;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{Address, Bytes, B256, U256};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use parking_lot::Mutex;
+use reqwest::header::CONTENT_TYPE;
+use reth_chainspec::ChainSpec;
+use reth_provider::{BlockReader, DatabaseProviderFactory, HeaderProvider, StateProviderFactory};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Block numbers for which we keep the submitted blocks.
+const MAX_TRACKED_BLOCK_NUMBERS: usize = 8;
+/// Settled payments we keep on the ledger.
+const MAX_SETTLED_HISTORY: usize = 10_000;
+/// Transfers to contracts run their receive logic.
+const CONTRACT_TRANSFER_GAS_LIMIT: u64 = 100_000;
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct RefundSettlementConfig {
+    pub ledger_path: PathBuf,
+    pub interval: Duration,
+    /// Smaller accrued refunds wait for the next settlements.
+    pub min_value: U256,
+    /// Node where we send the settlement txs (eth_sendRawTransaction). Mandatory unless dry_run.
+    pub rpc_url: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccruedRefund {
+    pub value: U256,
+    /// Last block that added to the refund.
+    pub last_block: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementPayment {
+    pub recipient: Address,
+    pub value: U256,
+    pub nonce: u64,
+    pub gas_limit: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentPayment {
+    #[serde(flatten)]
+    pub payment: SettlementPayment,
+    pub tx_hash: B256,
+    pub sent_at_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettledPayment {
+    #[serde(flatten)]
+    pub sent: SentPayment,
+    /// false if the transfer reverted (contract recipient), the refund is NOT accrued again and must be handled
+    /// manually.
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementPlan {
+    pub payments: Vec<SettlementPayment>,
+    pub total_value: U256,
+    /// Upper bound of the gas we pay.
+    pub max_gas_cost: U256,
+    pub builder_balance: U256,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+impl SettlementPlan {
+    pub fn affordable(&self) -> bool {
+        self.total_value.saturating_add(self.max_gas_cost) <= self.builder_balance
+    }
+
+    fn gas_price(&self) -> OwnTxGasPrice {
+        OwnTxGasPrice {
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundLedger {
+    pub accrued: BTreeMap<Address, AccruedRefund>,
+    /// Last landed block whose refunds were accrued so a block is never accrued twice.
+    pub last_accrued_block: u64,
+    pub pending: Vec<SentPayment>,
+    /// Oldest first.
+    pub settled: Vec<SettledPayment>,
+}
+
+impl RefundLedger {
+    /// Empty ledger if the file does not exist.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Write + rename so a crash never leaves a half written ledger.
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn total_accrued(&self) -> U256 {
+        self.accrued.values().map(|refund| refund.value).sum()
+    }
+
+    /// false if the block was already accrued.
+    pub fn accrue(&mut self, block_number: u64, refunds: &[(Address, U256)]) -> bool {
+        if block_number <= self.last_accrued_block {
+            return false;
+        }
+        self.last_accrued_block = block_number;
+        for (recipient, value) in refunds {
+            self.add(*recipient, *value, block_number);
+        }
+        true
+    }
+
+    fn add(&mut self, recipient: Address, value: U256, block_number: u64) {
+        let refund = self.accrued.entry(recipient).or_default();
+        refund.value += value;
+        refund.last_block = refund.last_block.max(block_number);
+    }
+
+    /// Payments (consecutive nonces from first_nonce) for every accrued refund >= min_value.
+    pub fn plan(
+        &self,
+        min_value: U256,
+        first_nonce: u64,
+        gas_price: OwnTxGasPrice,
+        builder_balance: U256,
+        gas_limit: impl Fn(&Address) -> u64,
+    ) -> SettlementPlan {
+        let payments: Vec<_> = self
+            .accrued
+            .iter()
+            .filter(|(_, refund)| !refund.value.is_zero() && refund.value >= min_value)
+            .zip(first_nonce..)
+            .map(|((recipient, refund), nonce)| SettlementPayment {
+                recipient: *recipient,
+                value: refund.value,
+                nonce,
+                gas_limit: gas_limit(recipient),
+            })
+            .collect();
+        let total_gas: u64 = payments.iter().map(|payment| payment.gas_limit).sum();
+        SettlementPlan {
+            total_value: payments.iter().map(|payment| payment.value).sum(),
+            max_gas_cost: U256::from(total_gas) * U256::from(gas_price.max_fee_per_gas),
+            payments,
+            builder_balance,
+            max_fee_per_gas: gas_price.max_fee_per_gas,
+            max_priority_fee_per_gas: gas_price.max_priority_fee_per_gas,
+        }
+    }
+
+    /// The value is no longer accrued while the payment is pending.
+    pub fn mark_sent(&mut self, payment: SettlementPayment, tx_hash: B256, sent_at_ms: u64) {
+        if let Some(refund) = self.accrued.get_mut(&payment.recipient) {
+            refund.value = refund.value.saturating_sub(payment.value);
+            if refund.value.is_zero() {
+                self.accrued.remove(&payment.recipient);
+            }
+        }
+        self.pending.push(SentPayment {
+            payment,
+            tx_hash,
+            sent_at_ms,
+        });
+    }
+
+    /// receipt_success: None if the tx is not on chain (yet).
+    /// Pending payments with a receipt are settled, the ones whose nonce was used by another tx are accrued again.
+    /// Returns (settled, dropped).
+    pub fn reconcile(
+        &mut self,
+        builder_nonce: u64,
+        receipt_success: impl Fn(&B256) -> Option<bool>,
+    ) -> (usize, usize) {
+        let (mut settled, mut dropped) = (0, 0);
+        for sent in std::mem::take(&mut self.pending) {
+            if let Some(success) = receipt_success(&sent.tx_hash) {
+                settled += 1;
+                self.settled.push(SettledPayment { sent, success });
+            } else if sent.payment.nonce < builder_nonce {
+                dropped += 1;
+                let block = self.last_accrued_block;
+                self.add(sent.payment.recipient, sent.payment.value, block);
+            } else {
+                self.pending.push(sent);
+            }
+        }
+        if self.settled.len() > MAX_SETTLED_HISTORY {
+            let excess = self.settled.len() - MAX_SETTLED_HISTORY;
+            self.settled.drain(..excess);
+        }
+        (settled, dropped)
+    }
+}
+
+/// Refunds owed by the blocks we submitted until we know which one (if any) landed.
+#[derive(Debug, Default)]
+struct SubmittedRefunds {
+    /// block_number -> block_hash -> refunds.
+    blocks: BTreeMap<u64, HashMap<B256, Vec<(Address, U256)>>>,
+}
+
+impl SubmittedRefunds {
+    fn record(&mut self, block_number: u64, block_hash: B256, included_orders: &[ExecutionResult]) {
+        let refunds: Vec<_> = included_orders
+            .iter()
+            .flat_map(|result| result.paid_kickbacks.iter().cloned())
+            .collect();
+        if refunds.is_empty() {
+            return;
+        }
+        self.blocks
+            .entry(block_number)
+            .or_default()
+            .insert(block_hash, refunds);
+        while self.blocks.len() > MAX_TRACKED_BLOCK_NUMBERS {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Refunds of the landed blocks that are ours. Forgets everything up to the last landed block.
+    fn take_landed(
+        &mut self,
+        landed_blocks: &[LandedBlockInfo],
+    ) -> Vec<(u64, Vec<(Address, U256)>)> {
+        let mut res = Vec::new();
+        for landed_block in landed_blocks {
+            let mut submitted = self.blocks.split_off(&(landed_block.block_number + 1));
+            std::mem::swap(&mut submitted, &mut self.blocks);
+            if !landed_block.beneficiary_is_builder {
+                continue;
+            }
+            if let Some(refunds) = submitted
+                .remove(&landed_block.block_number)
+                .and_then(|mut blocks| blocks.remove(&landed_block.block_hash))
+            {
+                res.push((landed_block.block_number, refunds));
+            }
+        }
+        res
+    }
+}
+
+/// [`BlockInclusionObserver`] accruing the refunds of our landed blocks.
+#[derive(Debug)]
+pub struct RefundSettler {
+    ledger_path: PathBuf,
+    ledger: Mutex<RefundLedger>,
+    submitted: Mutex<SubmittedRefunds>,
+}
+
+impl RefundSettler {
+    fn save(&self, ledger: &RefundLedger) {
+        set_accrued_refunds(ledger.total_accrued());
+        if let Err(err) = ledger.save(&self.ledger_path) {
+            error!(?err, path = ?self.ledger_path, "Failed to save refund ledger");
+        }
+    }
+
+    fn notify_landed_blocks(&self, landed_blocks: &[LandedBlockInfo]) {
+        let landed = self.submitted.lock().take_landed(landed_blocks);
+        if landed.is_empty() {
+            return;
+        }
+        let mut ledger = self.ledger.lock();
+        for (block_number, refunds) in landed {
+            if ledger.accrue(block_number, &refunds) {
+                info!(
+                    block_number,
+                    refunds = refunds.len(),
+                    "Accrued deferred refunds"
+                );
+            }
+        }
+        self.save(&ledger);
+    }
+}
+
+impl BlockInclusionObserver for RefundSettler {
+    fn block_submitted(
+        &self,
+        block_number: u64,
+        block_hash: B256,
+        included_orders: &[ExecutionResult],
+    ) {
+        self.submitted
+            .lock()
+            .record(block_number, block_hash, included_orders);
+    }
+
+    fn blocks_landed(&self, landed_blocks: &[LandedBlockInfo]) {
+        self.notify_landed_blocks(landed_blocks);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundSettlementReport {
+    pub dry_run: bool,
+    pub total_accrued: U256,
+    pub ledger: RefundLedger,
+    /// What the next settlement would send. None if it could not be computed.
+    pub next_settlement: Option<SettlementPlan>,
+}
+
+#[derive(Debug)]
+struct SettlementContext<P> {
+    config: RefundSettlementConfig,
+    settler: Arc<RefundSettler>,
+    provider: P,
+    signer: Signer,
+    chain_spec: Arc<ChainSpec>,
+    gas_price_oracle: GasPriceOracle,
+}
+
+impl<P> SettlementContext<P>
+where
+    P: DatabaseProviderFactory<Provider: BlockReader>
+        + StateProviderFactory
+        + HeaderProvider
+        + Clone
+        + 'static,
+{
+    fn builder_nonce(&self) -> eyre::Result<u64> {
+        Ok(self
+            .provider
+            .latest()?
+            .account_nonce(self.signer.address)?
+            .unwrap_or_default())
+    }
+
+    fn reconcile(&self) -> eyre::Result<()> {
+        let builder_nonce = self.builder_nonce()?;
+        let db = self.provider.database_provider_ro()?;
+        let mut ledger = self.settler.ledger.lock();
+        if ledger.pending.is_empty() {
+            return Ok(());
+        }
+        let (settled, dropped) = ledger.reconcile(builder_nonce, |tx_hash| {
+            db.receipt_by_hash(*tx_hash)
+                .ok()
+                .flatten()
+                .map(|receipt| receipt.success)
+        });
+        for _ in 0..settled {
+            inc_refund_settlement_payments("settled");
+        }
+        for _ in 0..dropped {
+            inc_refund_settlement_payments("dropped");
+        }
+        if settled + dropped > 0 {
+            info!(
+                settled,
+                dropped,
+                pending = ledger.pending.len(),
+                "Reconciled refund payments"
+            );
+            self.settler.save(&ledger);
+        }
+        Ok(())
+    }
+
+    fn plan(&self) -> eyre::Result<SettlementPlan> {
+        let last_block = self.provider.last_block_number()?;
+        let header = self
+            .provider
+            .header_by_number(last_block)?
+            .ok_or_else(|| eyre::eyre!("Header not found for block {}", last_block))?;
+        let state = self.provider.latest()?;
+        let nonce = state
+            .account_nonce(self.signer.address)?
+            .unwrap_or_default();
+        let balance = state
+            .account_balance(self.signer.address)?
+            .unwrap_or_default();
+        let gas_price = self.gas_price_oracle.gas_price(
+            header.base_fee_per_gas.unwrap_or_default() as u128,
+            BASE_TX_GAS,
+            None,
+        );
+        let gas_limit = |recipient: &Address| match state.account_code(*recipient) {
+            Ok(Some(code)) if code.is_empty() => BASE_TX_GAS,
+            Ok(None) => BASE_TX_GAS,
+            _ => CONTRACT_TRANSFER_GAS_LIMIT,
+        };
+        Ok(self.settler.ledger.lock().plan(
+            self.config.min_value,
+            nonce,
+            gas_price,
+            balance,
+            gas_limit,
+        ))
+    }
+
+    fn report(&self) -> RefundSettlementReport {
+        let next_settlement = self
+            .plan()
+            .map_err(|err| warn!(?err, "Failed to compute refund settlement"))
+            .ok();
+        let ledger = self.settler.ledger.lock().clone();
+        RefundSettlementReport {
+            dry_run: self.config.dry_run,
+            total_accrued: ledger.total_accrued(),
+            ledger,
+            next_settlement,
+        }
+    }
+
+    async fn settle(&self, client: &reqwest::Client) -> eyre::Result<()> {
+        self.reconcile()?;
+        if !self.settler.ledger.lock().pending.is_empty() {
+            info!("Refund payments still pending, skipping settlement");
+            return Ok(());
+        }
+        let plan = self.plan()?;
+        if plan.payments.is_empty() {
+            return Ok(());
+        }
+        info!(
+            payments = plan.payments.len(),
+            total_value = %plan.total_value,
+            max_gas_cost = %plan.max_gas_cost,
+            dry_run = self.config.dry_run,
+            "Refund settlement"
+        );
+        let rpc_url = match (&self.config.rpc_url, self.config.dry_run) {
+            (Some(rpc_url), false) => rpc_url,
+            _ => {
+                for payment in &plan.payments {
+                    info!(recipient = ?payment.recipient, value = %payment.value, nonce = payment.nonce, "Refund settlement dry run payment");
+                }
+                return Ok(());
+            }
+        };
+        if !plan.affordable() {
+            warn!(builder_balance = %plan.builder_balance, "Builder balance too low for refund settlement");
+            return Ok(());
+        }
+        for payment in plan.payments.iter().cloned() {
+            let value: u128 = payment
+                .value
+                .try_into()
+                .map_err(|_| eyre::eyre!("Refund value too big: {}", payment.value))?;
+            let tx = create_payout_tx(
+                self.chain_spec.as_ref(),
+                plan.gas_price(),
+                &self.signer,
+                payment.nonce,
+                payment.recipient,
+                payment.gas_limit,
+                value,
+            )?;
+            let mut raw_tx = Vec::new();
+            tx.as_signed().encode_2718(&mut raw_tx);
+            if let Err(err) = send_raw_transaction(client, rpc_url, raw_tx.into()).await {
+                // next nonces would be stuck
+                inc_refund_settlement_payments("failed");
+                warn!(?err, recipient = ?payment.recipient, nonce = payment.nonce, "Failed to send refund payment");
+                break;
+            }
+            inc_refund_settlement_payments("sent");
+            let sent_at_ms = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64;
+            let mut ledger = self.settler.ledger.lock();
+            ledger.mark_sent(payment, tx.hash(), sent_at_ms);
+            self.settler.save(&ledger);
+        }
+        Ok(())
+    }
+}
+
+async fn send_raw_transaction(
+    client: &reqwest::Client,
+    url: &str,
+    raw_tx: Bytes,
+) -> eyre::Result<()> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_sendRawTransaction",
+        "params": [raw_tx],
+    });
+    let response = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&request)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let response: serde_json::Value = serde_json::from_slice(&response)?;
+    if let Some(err) = response.get("error") {
+        eyre::bail!("eth_sendRawTransaction error: {}", err);
+    }
+    Ok(())
+}
+
+/// Loads the ledger and spawns the settlement task until `global_cancel` is cancelled.
+/// Returns the admin rpc module with admin_refundSettlementReport and the settler to register as a
+/// [`BlockInclusionObserver`].
+pub fn spawn_refund_settlement<P>(
+    config: RefundSettlementConfig,
+    provider: P,
+    signer: Signer,
+    chain_spec: Arc<ChainSpec>,
+    gas_price_oracle: GasPriceOracle,
+    global_cancel: CancellationToken,
+) -> eyre::Result<(RpcModule<()>, Arc<RefundSettler>)>
+where
+    P: DatabaseProviderFactory<Provider: BlockReader>
+        + StateProviderFactory
+        + HeaderProvider
+        + Clone
+        + 'static,
+{
+    if config.rpc_url.is_none() && !config.dry_run {
+        eyre::bail!("Refund settlement needs a rpc url unless in dry run");
+    }
+    let ledger = RefundLedger::load(&config.ledger_path)?;
+    set_accrued_refunds(ledger.total_accrued());
+    let settler = Arc::new(RefundSettler {
+        ledger_path: config.ledger_path.clone(),
+        ledger: Mutex::new(ledger),
+        submitted: Default::default(),
+    });
+
+    let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build()?;
+    let ctx = Arc::new(SettlementContext {
+        config,
+        settler: settler.clone(),
+        provider,
+        signer,
+        chain_spec,
+        gas_price_oracle,
+    });
+
+    let mut module = RpcModule::new(());
+    let rpc_ctx = ctx.clone();
+    module.register_method("admin_refundSettlementReport", move |_, _| {
+        Ok::<_, ErrorObject<'static>>(rpc_ctx.report())
+    })?;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ctx.config.interval);
+        // first tick is immediate, give the builder time to start
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = global_cancel.cancelled() => return,
+                _ = interval.tick() => {},
+            }
+            if let Err(err) = ctx.settle(&client).await {
+                error!(?err, "Refund settlement failed");
+            }
+        }
+    });
+    Ok((module, settler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gas_price() -> OwnTxGasPrice {
+        OwnTxGasPrice {
+            max_fee_per_gas: 10,
+            max_priority_fee_per_gas: 1,
+        }
+    }
+
+    #[test]
+    fn test_refund_ledger() {
+        let user1 = Address::with_last_byte(1);
+        let user2 = Address::with_last_byte(2);
+        let contract = Address::with_last_byte(3);
+        let mut ledger = RefundLedger::default();
+        assert!(ledger.accrue(10, &[(user1, U256::from(100)), (user2, U256::from(5))]));
+        assert!(ledger.accrue(11, &[(user1, U256::from(50)), (contract, U256::from(20))]));
+        // already accrued
+        assert!(!ledger.accrue(11, &[(user1, U256::from(50))]));
+        assert_eq!(ledger.total_accrued(), U256::from(175));
+        assert_eq!(ledger.accrued[&user1].last_block, 11);
+
+        let gas_limit = |address: &Address| {
+            if *address == contract {
+                CONTRACT_TRANSFER_GAS_LIMIT
+            } else {
+                BASE_TX_GAS
+            }
+        };
+        let plan = ledger.plan(U256::from(10), 7, gas_price(), U256::MAX, gas_limit);
+        // user2 below min value
+        assert_eq!(
+            plan.payments,
+            vec![
+                SettlementPayment {
+                    recipient: user1,
+                    value: U256::from(150),
+                    nonce: 7,
+                    gas_limit: BASE_TX_GAS,
+                },
+                SettlementPayment {
+                    recipient: contract,
+                    value: U256::from(20),
+                    nonce: 8,
+                    gas_limit: CONTRACT_TRANSFER_GAS_LIMIT,
+                },
+            ]
+        );
+        assert_eq!(plan.total_value, U256::from(170));
+        assert_eq!(
+            plan.max_gas_cost,
+            U256::from((BASE_TX_GAS + CONTRACT_TRANSFER_GAS_LIMIT) * 10)
+        );
+        assert!(plan.affordable());
+        assert!(!ledger
+            .plan(U256::from(10), 7, gas_price(), U256::from(170), gas_limit)
+            .affordable());
+
+        for (i, payment) in plan.payments.into_iter().enumerate() {
+            ledger.mark_sent(payment, B256::with_last_byte(i as u8), 0);
+        }
+        assert_eq!(ledger.total_accrued(), U256::from(5));
+        assert_eq!(ledger.pending.len(), 2);
+
+        // nothing on chain yet
+        assert_eq!(ledger.reconcile(7, |_| None), (0, 0));
+        assert_eq!(ledger.pending.len(), 2);
+        // first landed, the nonce of the second was used by another tx
+        assert_eq!(
+            ledger.reconcile(9, |hash| (*hash == B256::with_last_byte(0)).then_some(true)),
+            (1, 1)
+        );
+        assert!(ledger.pending.is_empty());
+        assert_eq!(ledger.settled[0].sent.payment.recipient, user1);
+        assert_eq!(ledger.accrued[&contract].value, U256::from(20));
+        assert_eq!(ledger.total_accrued(), U256::from(25));
+    }
+
+    #[test]
+    fn test_refund_ledger_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("refunds.json");
+        assert_eq!(RefundLedger::load(&path).unwrap(), RefundLedger::default());
+        let mut ledger = RefundLedger::default();
+        ledger.accrue(1, &[(Address::with_last_byte(1), U256::from(100))]);
+        ledger.save(&path).unwrap();
+        assert_eq!(RefundLedger::load(&path).unwrap(), ledger);
+    }
+
+    #[test]
+    fn test_submitted_refunds() {
+        let landed = |block_number, block_hash, beneficiary_is_builder| LandedBlockInfo {
+            block_number,
+            block_hash,
+            block_timestamp: OffsetDateTime::UNIX_EPOCH,
+            builder_balance: U256::ZERO,
+            beneficiary_is_builder,
+        };
+        let refunds = vec![(Address::with_last_byte(1), U256::from(7))];
+        let mut submitted = SubmittedRefunds::default();
+        submitted
+            .blocks
+            .entry(1)
+            .or_default()
+            .insert(B256::with_last_byte(1), refunds.clone());
+        submitted
+            .blocks
+            .entry(2)
+            .or_default()
+            .insert(B256::with_last_byte(2), refunds.clone());
+        submitted
+            .blocks
+            .entry(3)
+            .or_default()
+            .insert(B256::with_last_byte(3), refunds.clone());
+
+        assert_eq!(
+            submitted.take_landed(&[
+                landed(1, B256::with_last_byte(9), true),
+                landed(2, B256::with_last_byte(2), true),
+            ]),
+            vec![(2, refunds.clone())]
+        );
+        // not ours
+        assert!(submitted
+            .take_landed(&[landed(3, B256::with_last_byte(3), false)])
+            .is_empty());
+        assert!(submitted.blocks.is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+//! Win/loss feedback from the relays data APIs.
+//! Shortly after each slot we built for (poll_delay after the slot timestamp) we ask every relay for the delivered
+//! payload and for the bids of our builder pubkeys (see [`builder_pubkeys`]), so we know if we won, the winner's value and by how much we lost.
+//! [`SlotOutcome`]s are exported as metrics, persisted in the performance db (if configured, written by the
+//! [`slot_resource_report`](crate::live_builder::slot_resource_report) writer) and handed to the
+//! [`BiddingService`](super::bidding::interfaces::BiddingService) on the next slot (see [`take_slot_outcomes`]).
+//! Like the inclusion notifier, everything is done via free functions that do nothing if the poller was not spawned.
+
+use super::builder_identity::builder_pubkeys;
+use crate::{
+    live_builder::slot_resource_report::write_slot_outcome,
+    mev_boost::{BuilderBlockReceived, ProposerPayloadDelivered, RelayClient},
+    telemetry::{add_slot_loss_margin, inc_slot_outcomes, record_relay_win},
+};
+use alloy_primitives::{BlockHash, U256};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use primitive_types::H384;
+use serde::Serialize;
+use sqlx::{Executor, SqliteConnection};
+use std::{collections::VecDeque, time::Duration};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Slots waiting to be polled, if we are this far behind new slots are dropped.
+const MAX_PENDING_SLOTS: usize = 64;
+/// Outcomes not taken by the bidding service are dropped (oldest first) past this.
+const MAX_UNCONSUMED_OUTCOMES: usize = 64;
+
+lazy_static! {
+    static ref SLOT_SENDER: Mutex<Option<mpsc::Sender<PendingSlot>>> = Mutex::new(None);
+    static ref SLOT_OUTCOMES: Mutex<VecDeque<SlotOutcome>> = Mutex::new(VecDeque::new());
+}
+
+#[derive(Debug, Clone)]
+pub struct RelayDataPollerConfig {
+    /// (relay name, client)
+    pub relays: Vec<(String, RelayClient)>,
+    /// Time after the slot timestamp to wait before polling, relays need some time to publish the data.
+    pub poll_delay: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingSlot {
+    slot: u64,
+    block: u64,
+    slot_timestamp: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotOutcome {
+    pub slot: u64,
+    pub block: u64,
+    /// None if none of our relays delivered the payload (missed slot, local block or a relay we don't use).
+    pub winner_relay: Option<String>,
+    pub winner_builder_pubkey: Option<H384>,
+    pub winner_block_hash: Option<BlockHash>,
+    pub winner_value: Option<U256>,
+    pub won: bool,
+    /// Best of our bids received by any relay.
+    pub our_best_bid: Option<U256>,
+    /// winner_value - our_best_bid if we lost and know both.
+    pub lost_by: Option<U256>,
+}
+
+impl SlotOutcome {
+    /// our_bids: bids received by the relays from any of our pubkeys.
+    pub fn new(
+        slot: u64,
+        block: u64,
+        delivered: Option<(String, ProposerPayloadDelivered)>,
+        our_bids: &[BuilderBlockReceived],
+        builder_pubkeys: &[H384],
+    ) -> Self {
+        let our_best_bid = our_bids.iter().map(|bid| bid.value).max();
+        let won = delivered
+            .as_ref()
+            .is_some_and(|(_, payload)| builder_pubkeys.contains(&payload.builder_pubkey));
+        let winner_value = delivered.as_ref().map(|(_, payload)| payload.value);
+        let lost_by = match (won, winner_value, our_best_bid) {
+            (false, Some(winner_value), Some(our_best_bid)) => {
+                Some(winner_value.saturating_sub(our_best_bid))
+            }
+            _ => None,
+        };
+        let (winner_relay, winner_builder_pubkey, winner_block_hash) = match delivered {
+            Some((relay, payload)) => (
+                Some(relay),
+                Some(payload.builder_pubkey),
+                Some(payload.block_hash),
+            ),
+            None => (None, None, None),
+        };
+        Self {
+            slot,
+            block,
+            winner_relay,
+            winner_builder_pubkey,
+            winner_block_hash,
+            winner_value,
+            won,
+            our_best_bid,
+            lost_by,
+        }
+    }
+
+    fn result_label(&self) -> &'static str {
+        if self.won {
+            "won"
+        } else if self.winner_value.is_some() {
+            "lost"
+        } else {
+            "unknown"
+        }
+    }
+}
+
+/// Call once per slot we build for, the slot is polled poll_delay after slot_timestamp.
+pub fn record_slot(slot: u64, block: u64, slot_timestamp: OffsetDateTime) {
+    if let Some(sender) = SLOT_SENDER.lock().as_ref() {
+        let pending = PendingSlot {
+            slot,
+            block,
+            slot_timestamp,
+        };
+        if sender.try_send(pending).is_err() {
+            warn!(slot, "Slot dropped, relay data poller is behind");
+        }
+    }
+}
+
+/// Outcomes polled since the last call, oldest first.
+pub fn take_slot_outcomes() -> Vec<SlotOutcome> {
+    SLOT_OUTCOMES.lock().drain(..).collect()
+}
+
+fn push_slot_outcome(outcome: SlotOutcome) {
+    let mut outcomes = SLOT_OUTCOMES.lock();
+    outcomes.push_back(outcome);
+    while outcomes.len() > MAX_UNCONSUMED_OUTCOMES {
+        outcomes.pop_front();
+    }
+}
+
+/// Relay errors are logged and the relay skipped.
+async fn poll_slot(config: &RelayDataPollerConfig, slot: &PendingSlot) -> SlotOutcome {
+    let pubkeys = builder_pubkeys();
+    let mut delivered = None;
+    let mut our_bids = Vec::new();
+    for (relay, client) in &config.relays {
+        if delivered.is_none() {
+            match client.proposer_payload_delivered_slot(slot.slot).await {
+                Ok(Some(payload)) => delivered = Some((relay.clone(), payload)),
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        relay = relay.as_str(),
+                        slot = slot.slot,
+                        "Error getting delivered payload"
+                    )
+                }
+            }
+        }
+        for pubkey in &pubkeys {
+            match client
+                .builder_blocks_received_slot(slot.slot, *pubkey)
+                .await
+            {
+                Ok(bids) => our_bids.extend(bids),
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        relay = relay.as_str(),
+                        slot = slot.slot,
+                        "Error getting received bids"
+                    )
+                }
+            }
+        }
+    }
+    SlotOutcome::new(slot.slot, slot.block, delivered, &our_bids, &pubkeys)
+}
+
+fn publish_slot_outcome(outcome: &SlotOutcome) {
+    debug!(?outcome, "Slot outcome");
+    inc_slot_outcomes(outcome.result_label());
+    if let (true, Some(relay)) = (outcome.won, &outcome.winner_relay) {
+        record_relay_win(relay);
+    }
+    if let Some(lost_by) = outcome.lost_by {
+        add_slot_loss_margin(lost_by);
+    }
+}
+
+/// Spawns the task polling the slots given to [`record_slot`] until `global_cancel` is cancelled.
+pub fn spawn_relay_data_poller(config: RelayDataPollerConfig, global_cancel: CancellationToken) {
+    let (sender, mut receiver) = mpsc::channel(MAX_PENDING_SLOTS);
+    *SLOT_SENDER.lock() = Some(sender);
+    tokio::spawn(async move {
+        let mut last_polled_slot = 0;
+        loop {
+            let slot = tokio::select! {
+                _ = global_cancel.cancelled() => return,
+                slot = receiver.recv() => match slot {
+                    Some(slot) => slot,
+                    None => return,
+                },
+            };
+            // create_sink may be called more than once per slot
+            if slot.slot <= last_polled_slot {
+                continue;
+            }
+            last_polled_slot = slot.slot;
+            let wait: Duration = (slot.slot_timestamp + config.poll_delay
+                - OffsetDateTime::now_utc())
+            .try_into()
+            .unwrap_or_default();
+            tokio::select! {
+                _ = global_cancel.cancelled() => return,
+                _ = tokio::time::sleep(wait) => {},
+            }
+            let outcome = poll_slot(&config, &slot).await;
+            publish_slot_outcome(&outcome);
+            write_slot_outcome(outcome.clone());
+            push_slot_outcome(outcome);
+        }
+    });
+}
+
+pub(crate) async fn create_slot_outcomes_table(conn: &mut SqliteConnection) -> eyre::Result<()> {
+    conn.execute(
+        r#"
+            CREATE TABLE IF NOT EXISTS slot_outcomes (
+                slot INTEGER NOT NULL,
+                block INTEGER NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                winner_relay TEXT,
+                winner_builder_pubkey TEXT,
+                winner_block_hash TEXT,
+                winner_value TEXT,
+                won INTEGER NOT NULL,
+                our_best_bid TEXT,
+                lost_by TEXT
+            );
+            "#,
+    )
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn insert_slot_outcome(
+    conn: &mut SqliteConnection,
+    outcome: &SlotOutcome,
+) -> eyre::Result<()> {
+    sqlx::query(
+            r#"
+            INSERT INTO slot_outcomes (slot, block, recorded_at, winner_relay, winner_builder_pubkey, winner_block_hash,
+                winner_value, won, our_best_bid, lost_by)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(outcome.slot as i64)
+        .bind(outcome.block as i64)
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .bind(outcome.winner_relay.clone())
+        .bind(
+            outcome
+                .winner_builder_pubkey
+                .map(|pubkey| format!("{:?}", pubkey)),
+        )
+        .bind(outcome.winner_block_hash.map(|hash| hash.to_string()))
+        .bind(outcome.winner_value.map(|value| value.to_string()))
+        .bind(outcome.won)
+        .bind(outcome.our_best_bid.map(|value| value.to_string()))
+        .bind(outcome.lost_by.map(|value| value.to_string()))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Address;
+    use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions};
+
+    fn delivered(builder_pubkey: H384, value: u64) -> ProposerPayloadDelivered {
+        ProposerPayloadDelivered {
+            slot: 10,
+            parent_hash: Default::default(),
+            block_hash: BlockHash::with_last_byte(1),
+            builder_pubkey,
+            proposer_pubkey: Default::default(),
+            proposer_fee_recipient: Address::default(),
+            gas_limit: 30_000_000,
+            gas_used: 0,
+            value: U256::from(value),
+            block_number: 100,
+            num_tx: 0,
+        }
+    }
+
+    fn bid(builder_pubkey: H384, value: u64) -> BuilderBlockReceived {
+        BuilderBlockReceived {
+            slot: 10,
+            parent_hash: Default::default(),
+            block_hash: Default::default(),
+            builder_pubkey,
+            proposer_pubkey: Default::default(),
+            proposer_fee_recipient: Address::default(),
+            gas_limit: 30_000_000,
+            gas_used: 0,
+            value: U256::from(value),
+            num_tx: 0,
+            block_number: 100,
+            timestamp: 0,
+            timestamp_ms: 0,
+            optimistic_submission: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slot_outcome() {
+        let ours = H384::repeat_byte(1);
+        let rotated = H384::repeat_byte(2);
+        let competitor = H384::repeat_byte(3);
+        let pubkeys = [ours, rotated];
+        let our_bids = [bid(ours, 50), bid(rotated, 70)];
+
+        let lost = SlotOutcome::new(
+            10,
+            100,
+            Some(("relay".to_string(), delivered(competitor, 100))),
+            &our_bids,
+            &pubkeys,
+        );
+        assert!(!lost.won);
+        assert_eq!(lost.result_label(), "lost");
+        assert_eq!(lost.our_best_bid, Some(U256::from(70)));
+        assert_eq!(lost.lost_by, Some(U256::from(30)));
+        assert_eq!(lost.winner_builder_pubkey, Some(competitor));
+
+        let won = SlotOutcome::new(
+            10,
+            100,
+            Some(("relay".to_string(), delivered(rotated, 70))),
+            &our_bids,
+            &pubkeys,
+        );
+        assert!(won.won);
+        assert_eq!(won.lost_by, None);
+
+        let unknown = SlotOutcome::new(10, 100, None, &[], &pubkeys);
+        assert_eq!(unknown.result_label(), "unknown");
+        assert_eq!(unknown.our_best_bid, None);
+
+        let mut conn = SqliteConnectOptions::new().connect().await.unwrap();
+        create_slot_outcomes_table(&mut conn).await.unwrap();
+        for outcome in [&lost, &won, &unknown] {
+            insert_slot_outcome(&mut conn, outcome).await.unwrap();
+        }
+        let won_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM slot_outcomes WHERE won")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(won_count, 1);
+    }
+}
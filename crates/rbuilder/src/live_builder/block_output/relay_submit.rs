@@ -1,26 +1,43 @@
 use crate::{
-    building::builders::Block,
-    live_builder::payload_events::MevBoostSlotData,
+    building::{
+        block_template::record_block_template, builders::Block, extra_data::with_extra_data,
+        relay_rejections,
+    },
+    live_builder::{
+        fault_injection::{self, FaultPoint},
+        leader_election, node_health,
+        payload_events::MevBoostSlotData,
+        signer_reputation, warm_standby,
+    },
     mev_boost::{
-        sign_block_for_relay, BLSBlockSigner, RelayError, SubmitBlockErr, SubmitBlockRequest,
+        sign_block_for_relay, BLSBlockSigner, RelayError, RelayRejection, Remediation,
+        SubmitBlockErr, SubmitBlockReceipt, SubmitBlockRequest,
     },
     primitives::mev_boost::{MevBoostRelay, MevBoostRelayID},
+    roothash::payment_proof::ProposerPaymentProof,
     telemetry::{
-        add_relay_submit_time, add_subsidy_value, inc_conn_relay_errors,
-        inc_failed_block_simulations, inc_initiated_submissions, inc_other_relay_errors,
-        inc_relay_accepted_submissions, inc_subsidized_blocks, inc_too_many_req_relay_errors,
-        measure_block_e2e_latency,
+        add_relay_submit_body_peak_bytes, add_relay_submit_time, add_subsidy_value,
+        inc_conn_relay_errors, inc_failed_block_simulations, inc_initiated_submissions,
+        inc_other_relay_errors, inc_relay_accepted_submissions, inc_relay_bids_below_floor,
+        inc_relay_rejections, inc_subsidized_blocks, inc_too_many_req_relay_errors,
+        measure_block_e2e_latency, record_bid_submitted, relay_priority,
+    },
+    utils::{
+        clock::{Clock, ClockRef},
+        error_storage::store_error_event,
+        tracing::dynamic_event,
     },
-    utils::{error_storage::store_error_event, tracing::dynamic_event},
     validation_api_client::{ValidationAPIClient, ValidationError},
 };
-use ahash::HashMap;
-use alloy_primitives::{utils::format_ether, U256};
+use ahash::{HashMap, HashSet};
+use alloy_primitives::{utils::format_ether, Address, BlockHash, B256, U256};
 use mockall::automock;
 use parking_lot::Mutex;
+use primitive_types::H384;
 use reth_chainspec::ChainSpec;
 use reth_primitives::SealedBlock;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use time::OffsetDateTime;
 use tokio::{sync::Notify, time::Instant};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, event, info_span, trace, warn, Instrument, Level};
@@ -28,11 +45,114 @@ use tracing::{debug, error, event, info_span, trace, warn, Instrument, Level};
 use super::{
     bid_observer::BidObserver,
     bid_value_source::{best_bid_sync_source::BestBidSyncSource, interfaces::BidValueSource},
+    block_artifact::{BlockArtifact, BlockArtifactStore},
+    block_inclusion_observer::BlockInclusionObservers,
+    builder_identity::BuilderIdentitySchedule,
+    payload_signer::PayloadSigningThread,
+    submission_audit::{SubmissionAuditLog, SubmissionAuditRecord},
 };
 
 const SIM_ERROR_CATEGORY: &str = "submit_block_simulation";
 const VALIDATION_ERROR_CATEGORY: &str = "validate_block_simulation";
 
+/// Relays that rejected our blocks in a way only they will (see [`Remediation::SkipRelay`]), we stop submitting to
+/// them for the rest of the slot.
+type SkippedRelays = Arc<Mutex<HashSet<MevBoostRelayID>>>;
+
+/// What a relay rejection of a block can change for the next blocks of the slot.
+#[derive(Debug, Clone)]
+struct RejectionFeedback {
+    slot: u64,
+    identities: Arc<BuilderIdentitySchedule>,
+    block_number: u64,
+    parent_hash: BlockHash,
+    /// Hashes of the txs of the block.
+    block_txs: Arc<HashSet<B256>>,
+    skipped_relays: SkippedRelays,
+}
+
+impl RejectionFeedback {
+    fn new(
+        slot: u64,
+        identities: Arc<BuilderIdentitySchedule>,
+        block: &SealedBlock,
+        skipped_relays: SkippedRelays,
+    ) -> Self {
+        Self {
+            slot,
+            identities,
+            block_number: block.number,
+            parent_hash: block.parent_hash,
+            block_txs: Arc::new(block.body.transactions.iter().map(|tx| tx.hash()).collect()),
+            skipped_relays,
+        }
+    }
+
+    /// Applies the remediation, false if the slot should be stopped.
+    /// builder_pubkey: the one that signed the rejected submission.
+    fn apply(
+        &self,
+        relay: &MevBoostRelay,
+        rejection: RelayRejection,
+        builder_pubkey: H384,
+    ) -> bool {
+        inc_relay_rejections(&relay.id, rejection.reason());
+        match rejection.remediation() {
+            Remediation::RebuildWithout(tx_hash) if self.block_txs.contains(&tx_hash) => {
+                warn!(
+                    ?tx_hash,
+                    "Relay rejected a tx of our block, building without it"
+                );
+                relay_rejections::record_rejected_tx(
+                    self.block_number,
+                    self.parent_hash,
+                    &relay.id,
+                    tx_hash,
+                );
+                true
+            }
+            Remediation::RebuildWithout(tx_hash) => {
+                warn!(
+                    ?tx_hash,
+                    "Relay rejected a tx that is not in our block, cancelling"
+                );
+                false
+            }
+            Remediation::SkipRelay => {
+                if rejection == RelayRejection::InvalidSignature {
+                    if self.identities.record_signature_rejected(
+                        self.slot,
+                        &relay.id,
+                        builder_pubkey,
+                    ) {
+                        // the next blocks go to this relay signed by the previous identity
+                        return true;
+                    }
+                    error!("Relay rejected the signature of our submission, check the relay signing key and the signing domain of the chain");
+                }
+                warn!(
+                    ?rejection,
+                    "Relay rejected our block, not submitting to it for the rest of the slot"
+                );
+                self.skipped_relays.lock().insert(relay.id.clone());
+                true
+            }
+            Remediation::StopSlot => false,
+        }
+    }
+
+    /// The relay rejected our blocks this slot or one of the txs of this one.
+    fn skips(&self, relay: &MevBoostRelay) -> bool {
+        self.skipped_relays.lock().contains(&relay.id)
+            || relay_rejections::rejected_by_relay(
+                self.block_number,
+                self.parent_hash,
+                &relay.id,
+                self.block_txs.iter(),
+            )
+    }
+}
+
 /// Contains the best block so far.
 /// Building updates via compare_and_update while relay submitter polls via take_best_block.
 /// A new block can be waited without polling via wait_for_change.
@@ -59,6 +179,11 @@ impl BestBlockCell {
         self.block.lock().take()
     }
 
+    /// Looks at the best block without consuming it.
+    pub fn peek_best_block<R>(&self, f: impl FnOnce(&Block) -> R) -> Option<R> {
+        self.block.lock().as_ref().map(f)
+    }
+
     pub async fn wait_for_change(&self) {
         self.block_notify.notified().await
     }
@@ -86,28 +211,96 @@ pub trait BlockBuildingSink: std::fmt::Debug + Send + Sync {
 pub trait BuilderSinkFactory: std::fmt::Debug + Send + Sync {
     /// # Arguments
     /// slot_bidder: Not always needed but simplifies the design.
+    /// clock: Time source of the slot, used for the deadline decisions.
     fn create_builder_sink(
         &self,
         slot_data: MevBoostSlotData,
         competition_bid_value_source: Arc<dyn BidValueSource + Send + Sync>,
         cancel: CancellationToken,
+        clock: ClockRef,
     ) -> Box<dyn BlockBuildingSink>;
 }
 
 #[derive(Debug)]
 pub struct SubmissionConfig {
     pub chain_spec: Arc<ChainSpec>,
-    pub signer: BLSBlockSigner,
+    /// Normal and optimistic signers for each slot.
+    pub identities: Arc<BuilderIdentitySchedule>,
 
     pub dry_run: bool,
     pub validation_api: ValidationAPIClient,
 
     pub optimistic_enabled: bool,
-    pub optimistic_signer: BLSBlockSigner,
     pub optimistic_max_bid_value: U256,
     pub optimistic_prevalidate_optimistic_blocks: bool,
 
     pub bid_observer: Box<dyn BidObserver + Send + Sync>,
+    /// Get every submitted block (main and resealed versions).
+    pub block_inclusion_observers: BlockInclusionObservers,
+    /// If set every relay submission is recorded (see [`super::submission_audit`]).
+    pub submission_audit_log: Option<Arc<SubmissionAuditLog>>,
+    /// If set every submitted block is saved here as a [`BlockArtifact`].
+    pub block_artifacts: Option<Arc<BlockArtifactStore>>,
+    /// If set the [`crate::building::block_template::BlockTemplate`] of every submitted block is saved here.
+    pub block_templates_dir: Option<PathBuf>,
+    /// Submit to the relays in [`relay_priority`] order instead of config order.
+    pub relay_auto_priority: bool,
+    /// Only with relay_auto_priority.
+    pub relay_priority_budget: Option<RelayPriorityBudget>,
+    /// Assembles and signs the payloads off the async runtime.
+    pub payload_signer: Arc<PayloadSigningThread>,
+}
+
+/// Close to the slot deadline only the highest priority relays get our blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct RelayPriorityBudget {
+    /// Time before the slot deadline the budget applies in.
+    pub deadline: Duration,
+    /// Relays of each group (optimistic and not) submitted to within the deadline.
+    pub relays: usize,
+}
+
+impl RelayPriorityBudget {
+    /// Number of relays of a group (sorted by priority) to submit to with time_left until the slot deadline.
+    fn relays_to_submit(budget: Option<Self>, group_len: usize, time_left: Duration) -> usize {
+        match budget {
+            Some(budget) if time_left < budget.deadline => budget.relays.min(group_len),
+            _ => group_len,
+        }
+    }
+
+    /// [`Self::relays_to_submit`] with the time left until slot_timestamp read from clock (so replays and
+    /// backtests get the same budget every time).
+    fn relays_to_submit_at(
+        budget: Option<Self>,
+        group_len: usize,
+        clock: &dyn Clock,
+        slot_timestamp: OffsetDateTime,
+    ) -> usize {
+        Self::relays_to_submit(budget, group_len, clock.duration_until(slot_timestamp))
+    }
+}
+
+/// Normal and optimistic signed submissions of a block.
+#[derive(Debug, Clone)]
+struct SignedSubmissions {
+    block_hash: BlockHash,
+    normal: Arc<SubmitBlockRequest>,
+    optimistic: Arc<SubmitBlockRequest>,
+    /// (normal, optimistic) signed by the previous builder identity, only if some relay still gets it
+    /// (see [`BuilderIdentitySchedule::identity_for_relay`]).
+    previous: Option<(Arc<SubmitBlockRequest>, Arc<SubmitBlockRequest>)>,
+}
+
+impl SignedSubmissions {
+    fn request(&self, optimistic: bool, previous_identity: bool) -> Arc<SubmitBlockRequest> {
+        match (&self.previous, previous_identity, optimistic) {
+            (Some((normal, _)), true, false) => normal.clone(),
+            (Some((_, optimistic)), true, true) => optimistic.clone(),
+            (_, _, false) => self.normal.clone(),
+            (_, _, true) => self.optimistic.clone(),
+        }
+    }
 }
 
 /// Values from [`BuiltBlockTrace`]
@@ -119,7 +312,9 @@ struct BuiltBlockInfo {
 /// Every 50ms It will take a new best block produced by builders and submit it.
 ///
 /// How submission works:
-/// 0. We divide relays into optimistic and non-optimistic (defined in config file)
+/// 0. We divide relays into optimistic and non-optimistic (defined in config file), if relay_auto_priority is set each
+///    group is sorted by [`relay_priority`] and within the [`RelayPriorityBudget`] only the first relays of each group
+///    are submitted to.
 /// 1. If we are in dry run mode we validate the payload and skip submission to the relays
 /// 2. We schedule submissions with non-optimistic key for all non-optimistic relays.
 ///    3.1 If "optimistic_enabled" is false or bid_value >= "optimistic_max_bid_value" we schedule submissions with non-optimistic key
@@ -134,6 +329,7 @@ async fn run_submit_to_relays_job(
     config: Arc<SubmissionConfig>,
     cancel: CancellationToken,
     competition_bid_value_source: Arc<dyn BidValueSource + Send + Sync>,
+    clock: ClockRef,
 ) -> Option<BuiltBlockInfo> {
     let best_bid_sync_source = BestBidSyncSource::new(
         competition_bid_value_source,
@@ -142,6 +338,16 @@ async fn run_submit_to_relays_job(
     );
     let mut res = None;
 
+    let mut relays = relays;
+    if config.relay_auto_priority {
+        let priority = relay_priority(
+            &relays
+                .iter()
+                .map(|relay| relay.id.clone())
+                .collect::<Vec<_>>(),
+        );
+        relays.sort_by_key(|relay| priority.iter().position(|id| *id == relay.id));
+    }
     let (normal_relays, optimistic_relays) = {
         let mut normal_relays = Vec::new();
         let mut optimistic_relays = Vec::new();
@@ -155,13 +361,61 @@ async fn run_submit_to_relays_job(
         (normal_relays, optimistic_relays)
     };
 
+    config.identities.log_slot(slot_data.slot());
+    let identity = config.identities.identity_for_slot(slot_data.slot());
+
+    let skipped_relays = SkippedRelays::default();
     let mut last_bid_value = U256::from(0);
+    // Last bid value recorded as a would-be submission while in warm standby.
+    let mut last_standby_bid_value = U256::from(0);
+    // (signer, profit) of the orders in the last block we submitted, used to update signer reputations at the end of the slot.
+    let mut last_submitted_signed_orders: Vec<(Address, U256)> = Vec::new();
     'submit: loop {
         if cancel.is_cancelled() {
+            signer_reputation::record_included_orders(&last_submitted_signed_orders);
             break 'submit res;
         }
 
         best_bid.wait_for_change().await;
+        if !leader_election::is_leader() && !warm_standby::is_enabled() {
+            // Standby replica: keep building but leave the submissions to the leader.
+            trace!("Not the leader, skipping submission");
+            continue 'submit;
+        }
+        if node_health::is_degraded() {
+            trace!("Node is behind, skipping submission");
+            continue 'submit;
+        }
+        if !leader_election::is_leader() || !warm_standby::is_promoted() {
+            // Warm standby: remember what we would have sent but leave the block and the bid state
+            // untouched so we submit it as soon as we get promoted.
+            let standby_bid = best_bid.peek_best_block(|block| {
+                (
+                    block.sealed_block.number,
+                    block.sealed_block.header.hash(),
+                    block.trace.bid_value,
+                    block.trace.true_bid_value,
+                    block.builder_name.clone(),
+                )
+            });
+            if let Some((block_number, block_hash, bid_value, true_bid_value, builder_name)) =
+                standby_bid
+            {
+                if bid_value > last_standby_bid_value {
+                    last_standby_bid_value = bid_value;
+                    warm_standby::record_would_be_submission(
+                        slot_data.slot(),
+                        block_number,
+                        block_hash,
+                        bid_value,
+                        true_bid_value,
+                        builder_name,
+                    );
+                    trace!(?block_hash, "Warm standby, not submitting bid");
+                }
+            }
+            continue 'submit;
+        }
         let block = if let Some(new_block) = best_bid.take_best_block() {
             if new_block.trace.bid_value > last_bid_value {
                 last_bid_value = new_block.trace.bid_value;
@@ -208,43 +462,167 @@ async fn run_submit_to_relays_job(
             "Submitting bid",
         );
         inc_initiated_submissions(submission_optimistic);
+        record_bid_submitted(
+            slot_data.slot(),
+            block.sealed_block.number,
+            block.trace.bid_value,
+        );
+
+        // relays with their own extra data get the same block resealed with it
+        let mut extra_data_overrides: Vec<Vec<u8>> = normal_relays
+            .iter()
+            .chain(&optimistic_relays)
+            .filter_map(|relay| {
+                relay.extra_data_override(slot_data.slot(), &block.sealed_block.extra_data)
+            })
+            .collect();
+        extra_data_overrides.sort();
+        extra_data_overrides.dedup();
 
-        let (normal_signed_submission, optimistic_signed_submission) = {
-            let normal_signed_submission = match sign_block_for_relay(
-                &config.signer,
-                &block.sealed_block,
-                &block.txs_blobs_sidecars,
-                &block.execution_requests,
-                &config.chain_spec,
-                &slot_data.payload_attributes_event.data,
-                slot_data.slot_data.pubkey,
-                block.trace.bid_value,
-            ) {
-                Ok(res) => res,
-                Err(err) => {
+        // relays that rejected the rotated identity this epoch, they still get the previous one
+        let relays_on_previous_identity: HashSet<MevBoostRelayID> = normal_relays
+            .iter()
+            .chain(&optimistic_relays)
+            .filter(|relay| {
+                config
+                    .identities
+                    .identity_for_relay(slot_data.slot(), &relay.id)
+                    .from_epoch
+                    != identity.from_epoch
+            })
+            .map(|relay| relay.id.clone())
+            .collect();
+        let signing_job = {
+            let signer = identity.signer.clone();
+            let optimistic_signer = identity.optimistic_signer.clone();
+            let previous_signers = relays_on_previous_identity.iter().next().map(|relay| {
+                let previous = config
+                    .identities
+                    .identity_for_relay(slot_data.slot(), relay);
+                (previous.signer.clone(), previous.optimistic_signer.clone())
+            });
+            let chain_spec = config.chain_spec.clone();
+            let attrs = slot_data.payload_attributes_event.data.clone();
+            let pubkey = slot_data.slot_data.pubkey;
+            move || {
+                let sign = |sealed_block: &SealedBlock| -> eyre::Result<SignedSubmissions> {
+                    let sign_with = |signer: &BLSBlockSigner| {
+                        sign_block_for_relay(
+                            signer,
+                            sealed_block,
+                            &block.encoded_txs,
+                            &block.txs_blobs_sidecars,
+                            &block.execution_requests,
+                            &chain_spec,
+                            &attrs,
+                            pubkey,
+                            block.trace.bid_value,
+                        )
+                        .map(Arc::new)
+                    };
+                    let previous = match &previous_signers {
+                        Some((signer, optimistic_signer)) => {
+                            Some((sign_with(signer)?, sign_with(optimistic_signer)?))
+                        }
+                        None => None,
+                    };
+                    Ok(SignedSubmissions {
+                        block_hash: sealed_block.hash(),
+                        normal: sign_with(&signer)?,
+                        optimistic: sign_with(&optimistic_signer)?,
+                        previous,
+                    })
+                };
+                let signed_submissions = sign(&block.sealed_block).and_then(|main| {
+                    let overrides = extra_data_overrides
+                        .into_iter()
+                        .map(|extra_data| {
+                            let sealed_block =
+                                with_extra_data(&block.sealed_block, extra_data.clone().into());
+                            Ok((extra_data, sign(&sealed_block)?))
+                        })
+                        .collect::<eyre::Result<HashMap<_, _>>>()?;
+                    Ok((main, overrides))
+                });
+                (block, signed_submissions)
+            }
+        };
+        let (block, (main_submissions, override_submissions)) =
+            match config.payload_signer.run(signing_job).await {
+                Some((block, Ok(signed_submissions))) => (block, signed_submissions),
+                Some((_, Err(err))) => {
                     error!(parent: &submission_span, err = ?err, "Error signing block for relay");
                     continue 'submit;
                 }
-            };
-            let optimistic_signed_submission = match sign_block_for_relay(
-                &config.optimistic_signer,
-                &block.sealed_block,
-                &block.txs_blobs_sidecars,
-                &block.execution_requests,
-                &config.chain_spec,
-                &slot_data.payload_attributes_event.data,
-                slot_data.slot_data.pubkey,
-                block.trace.bid_value,
-            ) {
-                Ok(res) => res,
-                Err(err) => {
-                    error!(parent: &submission_span, err = ?err, "Error signing block for relay");
+                None => {
+                    error!(parent: &submission_span, "Payload signing thread failed");
                     continue 'submit;
                 }
             };
-            (normal_signed_submission, optimistic_signed_submission)
+        let (normal_signed_submission, optimistic_signed_submission) = (
+            main_submissions.normal.clone(),
+            main_submissions.optimistic.clone(),
+        );
+        let submission_for = |relay: &MevBoostRelay, optimistic: bool| {
+            relay
+                .extra_data_override(slot_data.slot(), &block.sealed_block.extra_data)
+                .and_then(|extra_data| override_submissions.get(&extra_data))
+                .unwrap_or(&main_submissions)
+                .request(optimistic, relays_on_previous_identity.contains(&relay.id))
         };
 
+        // (extra data, hash) of the versions of the block resealed for the relays overrides
+        let mut resealed_blocks: Vec<(Vec<u8>, BlockHash)> = override_submissions
+            .iter()
+            .map(|(extra_data, submissions)| (extra_data.clone(), submissions.block_hash))
+            .collect();
+        resealed_blocks.sort();
+
+        if let Some(block_artifacts) = config.block_artifacts.clone() {
+            let artifact = BlockArtifact {
+                resealed_block_hashes: resealed_blocks.iter().map(|(_, hash)| *hash).collect(),
+                ..BlockArtifact::new(&block)
+            };
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = block_artifacts.save(&artifact) {
+                    warn!(err = ?err, "Failed to save block artifact");
+                }
+            });
+        }
+        if let Some(source) = block.block_template_source.clone() {
+            let sealed_block = block.sealed_block.clone();
+            let encoded_txs = block.encoded_txs.clone();
+            let execution_requests = block.execution_requests.clone();
+            let dir = config.block_templates_dir.clone();
+            let resealed_extra_data: Vec<Vec<u8>> = resealed_blocks
+                .iter()
+                .map(|(extra_data, _)| extra_data.clone())
+                .collect();
+            tokio::task::spawn_blocking(move || {
+                let template =
+                    match source.generate(&sealed_block, &encoded_txs, &execution_requests) {
+                        Ok(template) => template,
+                        Err(err) => {
+                            warn!(err = ?err, "Failed to generate block template");
+                            return;
+                        }
+                    };
+                let resealed_templates: Vec<_> = resealed_extra_data
+                    .into_iter()
+                    .map(|extra_data| template.with_extra_data(extra_data.into()))
+                    .collect();
+                for template in std::iter::once(template).chain(resealed_templates) {
+                    let template = Arc::new(template);
+                    record_block_template(template.clone());
+                    if let Some(dir) = &dir {
+                        if let Err(err) = template.save(dir) {
+                            warn!(err = ?err, "Failed to save block template");
+                        }
+                    }
+                }
+            });
+        }
+
         if config.dry_run {
             validate_block(
                 &slot_data,
@@ -260,15 +638,45 @@ async fn run_submit_to_relays_job(
         }
 
         measure_block_e2e_latency(&block.trace.included_orders);
+        let feedback = RejectionFeedback::new(
+            slot_data.slot(),
+            config.identities.clone(),
+            &block.sealed_block,
+            skipped_relays.clone(),
+        );
+        let payment_proof = block.payment_proof.clone().map(Arc::new);
+        let relays_to_submit = |group: &[MevBoostRelay]| {
+            RelayPriorityBudget::relays_to_submit_at(
+                config.relay_priority_budget,
+                group.len(),
+                clock.as_ref(),
+                slot_data.timestamp(),
+            )
+        };
 
-        for relay in &normal_relays {
+        for relay in normal_relays.iter().take(relays_to_submit(&normal_relays)) {
+            if skip_relay(relay, block.trace.bid_value, &feedback) {
+                continue;
+            }
             let span = info_span!(parent: &submission_span, "relay_submit", relay = &relay.id, optimistic = false);
+            let submission = submission_for(relay, false);
             let relay = relay.clone();
             let cancel = cancel.clone();
-            let submission = normal_signed_submission.clone();
+            let payment_proof = payment_proof.clone();
+            let audit_log = config.submission_audit_log.clone();
+            let feedback = feedback.clone();
             tokio::spawn(
                 async move {
-                    submit_bid_to_the_relay(&relay, cancel.clone(), submission, false).await;
+                    submit_bid_to_the_relay(
+                        &relay,
+                        cancel.clone(),
+                        submission,
+                        payment_proof,
+                        false,
+                        audit_log,
+                        feedback,
+                    )
+                    .await;
                 }
                 .instrument(span),
             );
@@ -291,14 +699,32 @@ async fn run_submit_to_relays_job(
             };
 
             if can_submit {
-                for relay in &optimistic_relays {
+                for relay in optimistic_relays
+                    .iter()
+                    .take(relays_to_submit(&optimistic_relays))
+                {
+                    if skip_relay(relay, block.trace.bid_value, &feedback) {
+                        continue;
+                    }
                     let span = info_span!(parent: &submission_span, "relay_submit", relay = &relay.id, optimistic = true);
+                    let submission = submission_for(relay, true);
                     let relay = relay.clone();
                     let cancel = cancel.clone();
-                    let submission = optimistic_signed_submission.clone();
+                    let payment_proof = payment_proof.clone();
+                    let audit_log = config.submission_audit_log.clone();
+                    let feedback = feedback.clone();
                     tokio::spawn(
                         async move {
-                            submit_bid_to_the_relay(&relay, cancel.clone(), submission, true).await;
+                            submit_bid_to_the_relay(
+                                &relay,
+                                cancel.clone(),
+                                submission,
+                                payment_proof,
+                                true,
+                                audit_log,
+                                feedback,
+                            )
+                            .await;
                         }
                         .instrument(span),
                     );
@@ -306,25 +732,64 @@ async fn run_submit_to_relays_job(
             }
         } else {
             // non-optimistic submission to optimistic relays
-            for relay in &optimistic_relays {
+            for relay in optimistic_relays
+                .iter()
+                .take(relays_to_submit(&optimistic_relays))
+            {
+                if skip_relay(relay, block.trace.bid_value, &feedback) {
+                    continue;
+                }
                 let span = info_span!(parent: &submission_span, "relay_submit", relay = &relay.id, optimistic = false);
+                let submission = submission_for(relay, false);
                 let relay = relay.clone();
                 let cancel = cancel.clone();
-                let submission = normal_signed_submission.clone();
+                let payment_proof = payment_proof.clone();
+                let audit_log = config.submission_audit_log.clone();
+                let feedback = feedback.clone();
                 tokio::spawn(
                     async move {
-                        submit_bid_to_the_relay(&relay, cancel.clone(), submission, false).await;
+                        submit_bid_to_the_relay(
+                            &relay,
+                            cancel.clone(),
+                            submission,
+                            payment_proof,
+                            false,
+                            audit_log,
+                            feedback,
+                        )
+                        .await;
                     }
                     .instrument(span),
                 );
             }
         }
 
+        // any of the versions can land
+        for block_hash in std::iter::once(main_submissions.block_hash)
+            .chain(resealed_blocks.iter().map(|(_, hash)| *hash))
+        {
+            config.block_inclusion_observers.block_submitted(
+                block.sealed_block.number,
+                block_hash,
+                &block.trace.included_orders,
+            );
+        }
+        last_submitted_signed_orders = block
+            .trace
+            .included_orders
+            .iter()
+            .filter_map(|res| {
+                res.order
+                    .signer()
+                    .map(|signer| (signer, res.coinbase_profit))
+            })
+            .collect();
+
         submission_span.in_scope(|| {
             // NOTE: we only notify normal submission here because they have the same contents but different pubkeys
             config.bid_observer.block_submitted(
                 block.sealed_block,
-                normal_signed_submission,
+                Arc::unwrap_or_clone(normal_signed_submission),
                 block.trace,
                 builder_name,
                 best_bid_value,
@@ -340,6 +805,7 @@ pub async fn run_submit_to_relays_job_and_metrics(
     config: Arc<SubmissionConfig>,
     cancel: CancellationToken,
     competition_bid_value_source: Arc<dyn BidValueSource + Send + Sync>,
+    clock: ClockRef,
 ) {
     let last_build_block_info = run_submit_to_relays_job(
         best_bid,
@@ -348,6 +814,7 @@ pub async fn run_submit_to_relays_job_and_metrics(
         config,
         cancel,
         competition_bid_value_source,
+        clock,
     )
     .await;
     if let Some(last_build_block_info) = last_build_block_info {
@@ -417,11 +884,35 @@ async fn validate_block(
     }
 }
 
+/// Submitting below the relay floor only gets us a rejection.
+fn skip_relay(relay: &MevBoostRelay, bid_value: U256, feedback: &RejectionFeedback) -> bool {
+    if feedback.skips(relay) {
+        trace!(
+            relay = &relay.id,
+            "Relay rejected our blocks this slot, skipping submission"
+        );
+        return true;
+    }
+    if relay.accepts_bid_value(bid_value) {
+        return false;
+    }
+    trace!(
+        relay = &relay.id,
+        bid_value = format_ether(bid_value),
+        "Bid below relay floor, skipping submission"
+    );
+    inc_relay_bids_below_floor(&relay.id);
+    true
+}
+
 async fn submit_bid_to_the_relay(
     relay: &MevBoostRelay,
     cancel: CancellationToken,
-    signed_submit_request: SubmitBlockRequest,
+    signed_submit_request: Arc<SubmitBlockRequest>,
+    payment_proof: Option<Arc<ProposerPaymentProof>>,
     optimistic: bool,
+    audit_log: Option<Arc<SubmissionAuditLog>>,
+    feedback: RejectionFeedback,
 ) {
     let submit_start = Instant::now();
 
@@ -432,13 +923,50 @@ async fn submit_bid_to_the_relay(
         }
     }
 
-    let relay_result = tokio::select! {
-        _ = cancel.cancelled() => {
-            return;
-        },
-        res = relay.submit_block(&signed_submit_request) => res
+    if let Some(fault) = fault_injection::triggered(FaultPoint::RelaySubmitDelay) {
+        tokio::time::sleep(Duration::from_millis(fault.delay_ms)).await;
+    }
+
+    let mut receipt = SubmitBlockReceipt::default();
+    let relay_result = if fault_injection::triggered(FaultPoint::RelaySubmitFail).is_some() {
+        Err(SubmitBlockErr::RelayError(RelayError::ConnectionError))
+    } else {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                if let Some(audit_log) = audit_log {
+                    audit_log
+                        .append(SubmissionAuditRecord::cancelled(
+                            &relay.id,
+                            optimistic,
+                            &signed_submit_request,
+                            &receipt,
+                        ))
+                        .await;
+                }
+                return;
+            },
+            res = relay.submit_block(&signed_submit_request, payment_proof.as_deref(), &mut receipt) => res
+        }
     };
+    if let Some(audit_log) = audit_log {
+        audit_log
+            .append(SubmissionAuditRecord::new(
+                &relay.id,
+                optimistic,
+                &signed_submit_request,
+                &receipt,
+                &relay_result,
+            ))
+            .await;
+    }
     let submit_time = submit_start.elapsed();
+    if let Some(body_peak_bytes) = receipt.body_peak_bytes {
+        add_relay_submit_body_peak_bytes(
+            &relay.id,
+            relay.client.streaming_submit(),
+            body_peak_bytes,
+        );
+    }
     match relay_result {
         Ok(()) => {
             trace!("Block submitted to the relay successfully");
@@ -455,18 +983,25 @@ async fn submit_bid_to_the_relay(
                 "Block not accepted by the relay"
             );
         }
-        Err(SubmitBlockErr::SimError(_)) => {
+        Err(SubmitBlockErr::SimError { rejection, .. }) => {
             inc_failed_block_simulations();
             store_error_event(
                 SIM_ERROR_CATEGORY,
                 relay_result.as_ref().unwrap_err().to_string().as_str(),
-                &signed_submit_request,
+                signed_submit_request.as_ref(),
             );
-            error!(
-                err = ?relay_result.unwrap_err(),
-                "Error block simulation fail, cancelling"
-            );
-            cancel.cancel();
+            if !feedback.apply(relay, rejection, signed_submit_request.builder_pubkey()) {
+                error!(
+                    err = ?relay_result.unwrap_err(),
+                    "Error block simulation fail, cancelling"
+                );
+                cancel.cancel();
+            }
+        }
+        Err(SubmitBlockErr::Rejected { rejection, .. }) => {
+            warn!(err = ?relay_result.unwrap_err(), "Block rejected by the relay");
+            // outside the simulation the block itself is fine, never stop the slot
+            feedback.apply(relay, rejection, signed_submit_request.builder_pubkey());
         }
         Err(SubmitBlockErr::RelayError(RelayError::TooManyRequests)) => {
             trace!("Too many requests error submitting block to the relay");
@@ -499,6 +1034,9 @@ async fn submit_bid_to_the_relay(
         Err(SubmitBlockErr::InvalidHeader) => {
             error!("Invalid authorization header submitting block to the relay");
         }
+        Err(SubmitBlockErr::MissingPaymentProof) => {
+            error!("Relay requires payment proofs but the block has none (is payment proof generation enabled?)");
+        }
     }
 }
 
@@ -528,6 +1066,7 @@ impl BuilderSinkFactory for RelaySubmitSinkFactory {
         slot_data: MevBoostSlotData,
         competition_bid_value_source: Arc<dyn BidValueSource + Send + Sync>,
         cancel: CancellationToken,
+        clock: ClockRef,
     ) -> Box<dyn BlockBuildingSink> {
         let best_block_cell = Arc::new(BestBlockCell::default());
 
@@ -548,7 +1087,155 @@ impl BuilderSinkFactory for RelaySubmitSinkFactory {
             self.submission_config.clone(),
             cancel,
             competition_bid_value_source,
+            clock,
         ));
         Box::new(BestBlockCellToBlockBuildingSink { best_block_cell })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mev_boost::{rpc::TestDataGenerator, RelayClient},
+        utils::clock::SimulatedClock,
+    };
+    use warp::Filter;
+
+    /// Relay answering every submission with a failed simulation of tx_hash.
+    fn spawn_rejecting_relay(tx_hash: B256) -> url::Url {
+        let body = format!(
+            r#"{{"code":400,"message":"simulation failed: could not apply tx 0 [{:?}]: nonce too low"}}"#,
+            tx_hash
+        );
+        let route = warp::post()
+            .and(warp::path!("relay" / "v1" / "builder" / "blocks"))
+            .map(move || {
+                warp::reply::with_status(body.clone(), warp::http::StatusCode::BAD_REQUEST)
+            });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}", address).parse().unwrap()
+    }
+
+    fn test_relay(url: url::Url) -> MevBoostRelay {
+        MevBoostRelay {
+            id: "relay".to_string(),
+            client: RelayClient::from_url(url, None, None, None),
+            priority: 0,
+            use_ssz_for_submit: false,
+            use_gzip_for_submit: false,
+            optimistic: false,
+            requires_payment_proof: false,
+            submission_rate_limiter: None,
+            min_bid_value: None,
+            extra_data: None,
+        }
+    }
+
+    async fn submit(relay: &MevBoostRelay, feedback: RejectionFeedback) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        let request = SubmitBlockRequest::Deneb(
+            TestDataGenerator::default().create_deneb_submit_block_request(),
+        );
+        submit_bid_to_the_relay(
+            relay,
+            cancel.clone(),
+            Arc::new(request),
+            None,
+            false,
+            None,
+            feedback,
+        )
+        .await;
+        cancel
+    }
+
+    #[test]
+    fn test_relays_to_submit() {
+        let budget = Some(RelayPriorityBudget {
+            deadline: Duration::from_millis(500),
+            relays: 2,
+        });
+        let far = Duration::from_secs(2);
+        let close = Duration::from_millis(100);
+        assert_eq!(RelayPriorityBudget::relays_to_submit(None, 5, close), 5);
+        assert_eq!(RelayPriorityBudget::relays_to_submit(budget, 5, far), 5);
+        assert_eq!(RelayPriorityBudget::relays_to_submit(budget, 5, close), 2);
+        assert_eq!(RelayPriorityBudget::relays_to_submit(budget, 1, close), 1);
+        // past the deadline
+        assert_eq!(
+            RelayPriorityBudget::relays_to_submit(budget, 5, Duration::ZERO),
+            2
+        );
+    }
+
+    #[test]
+    fn test_relays_to_submit_with_clock() {
+        let budget = Some(RelayPriorityBudget {
+            deadline: Duration::from_millis(500),
+            relays: 2,
+        });
+        let slot_timestamp = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let clock = SimulatedClock::new(slot_timestamp - Duration::from_secs(2));
+        assert_eq!(
+            RelayPriorityBudget::relays_to_submit_at(budget, 5, &clock, slot_timestamp),
+            5
+        );
+        clock.advance(Duration::from_millis(1600));
+        assert_eq!(
+            RelayPriorityBudget::relays_to_submit_at(budget, 5, &clock, slot_timestamp),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_submit_applies_rejection_feedback() {
+        let _rejections = relay_rejections::RejectionsTestGuard::new();
+        let block_number = 20_000_000;
+        let parent_hash = BlockHash::with_last_byte(1);
+        let rejected_tx = B256::with_last_byte(0xab);
+        let other_tx = B256::with_last_byte(0xcd);
+        let feedback = RejectionFeedback {
+            slot: 0,
+            identities: Arc::new(BuilderIdentitySchedule::fixed(
+                BLSBlockSigner::test_signer(),
+                BLSBlockSigner::test_signer(),
+            )),
+            block_number,
+            parent_hash,
+            block_txs: Arc::new([rejected_tx, other_tx].into_iter().collect()),
+            skipped_relays: SkippedRelays::default(),
+        };
+
+        let relay = test_relay(spawn_rejecting_relay(rejected_tx));
+        let cancel = submit(&relay, feedback.clone()).await;
+        assert!(!cancel.is_cancelled());
+        assert!(relay_rejections::rejected_by_relay(
+            block_number,
+            parent_hash,
+            &relay.id,
+            &[rejected_tx]
+        ));
+        assert!(!relay_rejections::rejected_by_relay(
+            block_number,
+            parent_hash,
+            &"other_relay".to_string(),
+            &[rejected_tx]
+        ));
+        // the block is not submitted again to the relay that rejected it
+        assert!(feedback.skips(&relay));
+
+        // a hash that is not one of our txs (eg: the block hash) can't be rebuilt without
+        let unknown_tx = B256::with_last_byte(0xef);
+        let relay = test_relay(spawn_rejecting_relay(unknown_tx));
+        let cancel = submit(&relay, feedback).await;
+        assert!(cancel.is_cancelled());
+        assert!(!relay_rejections::rejected_by_relay(
+            block_number,
+            parent_hash,
+            &relay.id,
+            &[unknown_tx]
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+//! Relay submission audit log.
+//! Every submission attempt is appended as a json line with what we sent (bid trace values, hash of the exact payload
+//! and its BLS signature) and what the relay answered (status code and a digest of the body). Useful on payment
+//! disputes with relays and required by some orderflow agreements.
+//! Entries are hash-chained (like [`super::exclusion_audit`]), the chain continues from the last entry of the file
+//! when it's reopened so removing or editing an entry breaks the chain.
+//! The file is opened on every append so it can be rotated by the [archiver](crate::live_builder::archive), after a
+//! rotation the chain continues from the journal tip. Opening the log checks the chain of what's in the file.
+//! Submissions cancelled while waiting for the relay are recorded too (the relay may have got them).
+//! Writes happen on the blocking thread pool, never on the submission task.
+
+use crate::{
+    live_builder::archive::journal_tip_path,
+    mev_boost::{SubmitBlockErr, SubmitBlockReceipt, SubmitBlockRequest},
+    primitives::mev_boost::MevBoostRelayID,
+};
+use alloy_primitives::{Address, BlockHash, FixedBytes, B256, U256};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use time::OffsetDateTime;
+use tracing::error;
+
+const CHAIN_SEED_DOMAIN: &[u8] = b"rbuilder-submission-audit";
+/// error of the submissions cancelled before the relay answered.
+pub const CANCELLED_SUBMISSION_ERROR: &str = "cancelled";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionAuditRecord {
+    /// Unix timestamp (ms) of the response (or failure).
+    pub timestamp_ms: u64,
+    pub slot: u64,
+    pub relay: MevBoostRelayID,
+    pub optimistic: bool,
+    pub block_hash: BlockHash,
+    pub proposer_fee_recipient: Address,
+    pub value: U256,
+    /// sha256 of the request body before compression.
+    pub payload_hash: Option<B256>,
+    pub signature: FixedBytes<96>,
+    pub response_code: Option<u16>,
+    /// sha256 of the response body.
+    pub response_digest: Option<B256>,
+    /// None if the relay accepted the submission.
+    pub error: Option<String>,
+}
+
+impl SubmissionAuditRecord {
+    pub fn new(
+        relay: &MevBoostRelayID,
+        optimistic: bool,
+        request: &SubmitBlockRequest,
+        receipt: &SubmitBlockReceipt,
+        result: &Result<(), SubmitBlockErr>,
+    ) -> Self {
+        Self::with_error(
+            relay,
+            optimistic,
+            request,
+            receipt,
+            result.as_ref().err().map(|err| err.to_string()),
+        )
+    }
+
+    /// Submission we stopped waiting for because the slot was cancelled.
+    pub fn cancelled(
+        relay: &MevBoostRelayID,
+        optimistic: bool,
+        request: &SubmitBlockRequest,
+        receipt: &SubmitBlockReceipt,
+    ) -> Self {
+        Self::with_error(
+            relay,
+            optimistic,
+            request,
+            receipt,
+            Some(CANCELLED_SUBMISSION_ERROR.to_string()),
+        )
+    }
+
+    fn with_error(
+        relay: &MevBoostRelayID,
+        optimistic: bool,
+        request: &SubmitBlockRequest,
+        receipt: &SubmitBlockReceipt,
+        error: Option<String>,
+    ) -> Self {
+        let bid_trace = request.bid_trace();
+        Self {
+            timestamp_ms: (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64,
+            slot: bid_trace.slot,
+            relay: relay.clone(),
+            optimistic,
+            block_hash: bid_trace.block_hash,
+            proposer_fee_recipient: bid_trace.proposer_fee_recipient,
+            value: bid_trace.value,
+            payload_hash: receipt.payload_hash,
+            signature: request.signature(),
+            response_code: receipt.status,
+            response_digest: receipt.response_digest,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionAuditEntry {
+    #[serde(flatten)]
+    pub record: SubmissionAuditRecord,
+    /// Hash of the previous entry (or the chain seed for the first one).
+    pub prev_hash: B256,
+    /// sha256(prev_hash || json(record))
+    pub hash: B256,
+}
+
+fn chain_seed() -> B256 {
+    B256::from_slice(&Sha256::digest(CHAIN_SEED_DOMAIN))
+}
+
+fn entry_hash(prev_hash: &B256, record: &SubmissionAuditRecord) -> B256 {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash);
+    // serializing a plain struct can't fail
+    hasher.update(serde_json::to_vec(record).unwrap_or_default());
+    B256::from_slice(&hasher.finalize())
+}
+
+/// Recomputes the hash chain of the entries of a log, false if it was tampered with.
+pub fn verify_submission_audit_chain(entries: &[SubmissionAuditEntry]) -> bool {
+    verify_chain_from(chain_seed(), entries)
+}
+
+/// Same for entries following the one with hash prev_hash (eg: the journal after a rotation).
+fn verify_chain_from(mut prev_hash: B256, entries: &[SubmissionAuditEntry]) -> bool {
+    for entry in entries {
+        if entry.prev_hash != prev_hash || entry.hash != entry_hash(&prev_hash, &entry.record) {
+            return false;
+        }
+        prev_hash = entry.hash;
+    }
+    true
+}
+
+#[derive(Debug)]
+struct AuditFile {
+    path: PathBuf,
+    last_hash: B256,
+}
+
+/// Entries of the file, none if it doesn't exist.
+pub fn read_submission_audit_entries(path: &Path) -> eyre::Result<Vec<SubmissionAuditEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+fn last_entry_hash(path: &Path) -> eyre::Result<Option<B256>> {
+    Ok(read_submission_audit_entries(path)?
+        .last()
+        .map(|entry| entry.hash))
+}
+
+#[derive(Debug)]
+pub struct SubmissionAuditLog {
+    file: Mutex<AuditFile>,
+}
+
+impl SubmissionAuditLog {
+    /// Opens (or creates) the log, new entries are chained to the last one in the file.
+    /// Fails if the entries in the file don't chain (from the journal tip if it was rotated).
+    pub fn open(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let first_prev_hash = last_entry_hash(&journal_tip_path(path))?.unwrap_or_else(chain_seed);
+        let entries = read_submission_audit_entries(path)?;
+        if !verify_chain_from(first_prev_hash, &entries) {
+            eyre::bail!("Submission audit log {:?} hash chain is broken", path);
+        }
+        let last_hash = entries.last().map_or(first_prev_hash, |entry| entry.hash);
+        // fail early if we can't write
+        OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(AuditFile {
+                path: path.to_path_buf(),
+                last_hash,
+            }),
+        })
+    }
+
+    /// Writes the entry from the blocking thread pool.
+    pub async fn append(self: &Arc<Self>, record: SubmissionAuditRecord) {
+        let log = self.clone();
+        if let Err(err) = tokio::task::spawn_blocking(move || log.write(record)).await {
+            error!(?err, "Failed to write submission audit");
+        }
+    }
+
+    fn write(&self, record: SubmissionAuditRecord) {
+        let mut file = self.file.lock();
+        let prev_hash = file.last_hash;
+        let hash = entry_hash(&prev_hash, &record);
+        let entry = SubmissionAuditEntry {
+            record,
+            prev_hash,
+            hash,
+        };
+        let res = serde_json::to_string(&entry)
+            .map_err(|err| err.to_string())
+            .and_then(|line| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file.path)
+                    .and_then(|mut f| writeln!(f, "{}", line))
+                    .map_err(|err| err.to_string())
+            });
+        match res {
+            Ok(()) => file.last_hash = hash,
+            Err(err) => error!(%err, slot = entry.record.slot, "Failed to write submission audit"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(slot: u64) -> SubmissionAuditRecord {
+        SubmissionAuditRecord {
+            timestamp_ms: 1_700_000_000_000,
+            slot,
+            relay: "relay1".to_string(),
+            optimistic: false,
+            block_hash: B256::with_last_byte(1),
+            proposer_fee_recipient: Address::with_last_byte(2),
+            value: U256::from(1_000),
+            payload_hash: Some(B256::with_last_byte(3)),
+            signature: FixedBytes::with_last_byte(4),
+            response_code: Some(400),
+            response_digest: Some(B256::with_last_byte(5)),
+            error: Some("Bid below floor".to_string()),
+        }
+    }
+
+    fn read_entries(path: &Path) -> Vec<SubmissionAuditEntry> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_submission_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("submissions.jsonl");
+        Arc::new(SubmissionAuditLog::open(&path).unwrap())
+            .append(record(1))
+            .await;
+        // reopening continues the chain
+        let log = Arc::new(SubmissionAuditLog::open(&path).unwrap());
+        log.append(record(2)).await;
+        log.append(record(3)).await;
+
+        let mut entries = read_entries(&path);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].record, record(3));
+        assert!(verify_submission_audit_chain(&entries));
+
+        entries[1].record.value = U256::from(1);
+        assert!(!verify_submission_audit_chain(&entries));
+        entries.remove(1);
+        assert!(!verify_submission_audit_chain(&entries));
+    }
+
+    #[test]
+    fn test_open_verifies_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("submissions.jsonl");
+        let log = SubmissionAuditLog::open(&path).unwrap();
+        log.write(record(1));
+        log.write(record(2));
+        drop(log);
+
+        // rotated: the journal restarts after the tip
+        let entries = read_entries(&path);
+        std::fs::write(
+            journal_tip_path(&path),
+            serde_json::to_string(&entries[1]).unwrap() + "\n",
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let log = SubmissionAuditLog::open(&path).unwrap();
+        log.write(record(3));
+        drop(log);
+        let rotated = read_entries(&path);
+        assert_eq!(rotated[0].prev_hash, entries[1].hash);
+        assert!(SubmissionAuditLog::open(&path).is_ok());
+
+        let mut tampered = rotated[0].clone();
+        tampered.record.value = U256::from(1);
+        std::fs::write(&path, serde_json::to_string(&tampered).unwrap() + "\n").unwrap();
+        assert!(SubmissionAuditLog::open(&path).is_err());
+    }
+}
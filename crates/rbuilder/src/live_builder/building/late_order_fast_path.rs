@@ -0,0 +1,335 @@
+//! Fast path for orders arriving at the very end of the slot.
+//! Orders that arrive in the last few hundred ms don't have time to go through the builders (sorting, conflict
+//! resolution, filling and sealing from scratch) so instead we take the best block built so far, commit the late order
+//! at the end of it and, if the block is now worth more, send it straight to the sink to be sealed.
+//! Committing on top of the best block is our conflict check: if the order fails or loses a big part
+//! of its profit alone (see [`crate::building::standalone_profits`], the simulated profit if we don't have it for
+//! the coinbase of the block) it touches state already modified by the block and we leave it to the normal path.
+
+use crate::{
+    building::{
+        builders::{block_building_helper::BlockBuildingHelper, UnfinishedBlockBuildingSink},
+        BlockBuildingContext, CriticalCommitOrderError,
+    },
+    live_builder::simulation::SimulatedOrderCommand,
+    primitives::SimulatedOrder,
+    telemetry::inc_late_order_fast_path,
+    utils::clock::Clock,
+};
+use alloy_primitives::U256;
+use parking_lot::Mutex;
+use std::{sync::Arc, thread::sleep, time::Duration};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Executed on top of the best block a late order must keep at least this percent of its profit alone.
+const MIN_KEPT_SIM_PROFIT_PERCENT: u64 = 90;
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone)]
+pub struct LateOrderFastPathConfig {
+    /// Orders simulated when we are closer than this to the slot time go through the fast path.
+    pub window: Duration,
+    /// Only orders with at least this simulated coinbase profit are considered.
+    pub min_coinbase_profit: U256,
+}
+
+impl Default for LateOrderFastPathConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(500),
+            min_coinbase_profit: U256::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LateOrderOutcome {
+    /// New block sent to the sink.
+    Injected {
+        true_block_value: U256,
+    },
+    /// No builder produced a block yet.
+    NoBestBlock,
+    AlreadyIncluded,
+    /// Failed or lost too much profit on top of the best block.
+    Conflicting,
+    /// The block is not worth more with the order.
+    Unprofitable,
+}
+
+impl LateOrderOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LateOrderOutcome::Injected { .. } => "injected",
+            LateOrderOutcome::NoBestBlock => "no_best_block",
+            LateOrderOutcome::AlreadyIncluded => "already_included",
+            LateOrderOutcome::Conflicting => "conflicting",
+            LateOrderOutcome::Unprofitable => "unprofitable",
+        }
+    }
+}
+
+struct BestBlock {
+    true_block_value: U256,
+    block: Box<dyn BlockBuildingHelper>,
+}
+
+/// UnfinishedBlockBuildingSink wrapper that keeps a copy of the most valuable block it has seen.
+pub struct BestBlockTracker {
+    sink: Arc<dyn UnfinishedBlockBuildingSink>,
+    best_block: Mutex<Option<BestBlock>>,
+}
+
+impl std::fmt::Debug for BestBlockTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BestBlockTracker")
+            .field("sink", &self.sink)
+            .field(
+                "best_block_value",
+                &self.best_block.lock().as_ref().map(|b| b.true_block_value),
+            )
+            .finish()
+    }
+}
+
+impl BestBlockTracker {
+    pub fn new(sink: Arc<dyn UnfinishedBlockBuildingSink>) -> Self {
+        Self {
+            sink,
+            best_block: Mutex::new(None),
+        }
+    }
+
+    /// Copy of the best block and its value.
+    pub fn best_block(&self) -> Option<(U256, Box<dyn BlockBuildingHelper>)> {
+        self.best_block
+            .lock()
+            .as_ref()
+            .map(|best| (best.true_block_value, best.block.box_clone()))
+    }
+}
+
+impl UnfinishedBlockBuildingSink for BestBlockTracker {
+    fn new_block(&self, block: Box<dyn BlockBuildingHelper>) {
+        if let Ok(true_block_value) = block.true_block_value() {
+            let mut best_block = self.best_block.lock();
+            if best_block
+                .as_ref()
+                .map_or(true, |best| true_block_value > best.true_block_value)
+            {
+                *best_block = Some(BestBlock {
+                    true_block_value,
+                    block: block.box_clone(),
+                });
+            }
+        }
+        self.sink.new_block(block);
+    }
+
+    fn can_use_suggested_fee_recipient_as_coinbase(&self) -> bool {
+        self.sink.can_use_suggested_fee_recipient_as_coinbase()
+    }
+}
+
+/// Tries to append order at the end of the best block.
+pub fn inject_late_order(
+    best_block: &BestBlockTracker,
+    order: &SimulatedOrder,
+) -> Result<LateOrderOutcome, CriticalCommitOrderError> {
+    let Some((prev_block_value, mut block)) = best_block.best_block() else {
+        return Ok(LateOrderOutcome::NoBestBlock);
+    };
+    let order_id = order.id();
+    if block
+        .built_block_trace()
+        .included_orders
+        .iter()
+        .any(|included| included.order.id() == order_id)
+    {
+        return Ok(LateOrderOutcome::AlreadyIncluded);
+    }
+    let ctx = block.building_context();
+    let profit_alone = ctx
+        .standalone_profits
+        .get(ctx.block_env.coinbase, &order_id)
+        .flatten()
+        .unwrap_or(order.sim_value.coinbase_profit);
+    match block.commit_order(order)? {
+        Ok(res) => {
+            let min_profit =
+                profit_alone * U256::from(MIN_KEPT_SIM_PROFIT_PERCENT) / U256::from(100);
+            if res.coinbase_profit < min_profit {
+                return Ok(LateOrderOutcome::Conflicting);
+            }
+        }
+        Err(_) => return Ok(LateOrderOutcome::Conflicting),
+    }
+    let true_block_value = match block.true_block_value() {
+        Ok(value) if value > prev_block_value => value,
+        _ => return Ok(LateOrderOutcome::Unprofitable),
+    };
+    best_block.new_block(block);
+    Ok(LateOrderOutcome::Injected { true_block_value })
+}
+
+/// Runs until cancel or the input is closed.
+/// Orders that are simulated inside the configured window before the slot are injected on the best block.
+pub fn run_late_order_fast_path(
+    config: LateOrderFastPathConfig,
+    ctx: BlockBuildingContext,
+    mut input: broadcast::Receiver<SimulatedOrderCommand>,
+    best_block: Arc<BestBlockTracker>,
+    cancel: CancellationToken,
+) {
+    let slot_time = ctx.timestamp();
+    while !cancel.is_cancelled() {
+        let order = match input.try_recv() {
+            Ok(SimulatedOrderCommand::Simulation(order)) => order,
+            Ok(SimulatedOrderCommand::Cancellation(_)) => continue,
+            Err(TryRecvError::Empty) => {
+                sleep(INPUT_POLL_INTERVAL);
+                continue;
+            }
+            Err(TryRecvError::Closed) => break,
+            Err(TryRecvError::Lagged(msg)) => {
+                warn!(
+                    "Late order fast path lagging on sim orders channel: {}",
+                    msg
+                );
+                continue;
+            }
+        };
+        let time_to_slot = ctx.clock.duration_until(slot_time);
+        if time_to_slot > config.window
+            || order.sim_value.coinbase_profit < config.min_coinbase_profit
+        {
+            continue;
+        }
+        match inject_late_order(&best_block, &order) {
+            Ok(outcome) => {
+                inc_late_order_fast_path(outcome.as_str());
+                debug!(
+                    order_id = ?order.id(),
+                    outcome = outcome.as_str(),
+                    time_to_slot_ms = time_to_slot.as_millis(),
+                    "Late order fast path"
+                );
+            }
+            Err(err) => {
+                warn!(?err, order_id = ?order.id(), "Critical error on late order fast path");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::{
+            builders::{
+                block_building_helper::BlockBuildingHelperFromProvider,
+                mock_block_building_helper::MockBlockBuildingHelper,
+            },
+            testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        },
+        primitives::{MempoolTx, Order, SimValue, TransactionSignedEcRecoveredWithBlobs},
+        roothash::RootHashConfig,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct CountingSink {
+        blocks: AtomicUsize,
+    }
+
+    impl UnfinishedBlockBuildingSink for CountingSink {
+        fn new_block(&self, _block: Box<dyn BlockBuildingHelper>) {
+            self.blocks.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn can_use_suggested_fee_recipient_as_coinbase(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_tracker_keeps_best_block() {
+        let sink = Arc::new(CountingSink::default());
+        let tracker = BestBlockTracker::new(sink.clone());
+        assert!(tracker.best_block().is_none());
+
+        tracker.new_block(Box::new(MockBlockBuildingHelper::new(U256::from(10), true)));
+        tracker.new_block(Box::new(MockBlockBuildingHelper::new(U256::from(5), true)));
+        assert_eq!(tracker.best_block().unwrap().0, U256::from(10));
+        tracker.new_block(Box::new(MockBlockBuildingHelper::new(U256::from(20), true)));
+        assert_eq!(tracker.best_block().unwrap().0, U256::from(20));
+        // every block is forwarded
+        assert_eq!(sink.blocks.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_inject_late_order() {
+        // no payout tx so an empty block is worth 0
+        let test_chain = TestChainState::new(
+            BlockArgs::default()
+                .number(11)
+                .use_suggested_fee_recipient_as_coinbase(true),
+        )
+        .unwrap();
+        let sink = Arc::new(CountingSink::default());
+        let tracker = BestBlockTracker::new(sink.clone());
+
+        let tx = test_chain
+            .sign_tx(TxArgs::new_send_to_coinbase(NamedAddr::User(1), 0, 5))
+            .unwrap();
+        let order = SimulatedOrder {
+            order: Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            )),
+            sim_value: SimValue::new(U256::from(5), 21_000, 0, Vec::new()),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        assert_eq!(
+            inject_late_order(&tracker, &order).unwrap(),
+            LateOrderOutcome::NoBestBlock
+        );
+
+        let empty_block = BlockBuildingHelperFromProvider::new(
+            test_chain.provider_factory().clone(),
+            RootHashConfig::skip_root_hash(),
+            test_chain.block_building_context().clone(),
+            None,
+            "test".to_string(),
+            false,
+            None,
+            CancellationToken::new(),
+        )
+        .unwrap();
+        tracker.new_block(Box::new(empty_block));
+
+        // worth more alone (for the coinbase of the block) than on the block
+        let ctx = test_chain.block_building_context();
+        ctx.standalone_profits
+            .insert(ctx.block_env.coinbase, order.id(), Some(U256::from(100)));
+        assert_eq!(
+            inject_late_order(&tracker, &order).unwrap(),
+            LateOrderOutcome::Conflicting
+        );
+        ctx.standalone_profits
+            .insert(ctx.block_env.coinbase, order.id(), Some(U256::from(5)));
+
+        let outcome = inject_late_order(&tracker, &order).unwrap();
+        assert!(matches!(outcome, LateOrderOutcome::Injected { .. }));
+        assert_eq!(sink.blocks.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            inject_late_order(&tracker, &order).unwrap(),
+            LateOrderOutcome::AlreadyIncluded
+        );
+    }
+}
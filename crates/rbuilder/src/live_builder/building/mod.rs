@@ -1,14 +1,30 @@
-use std::{marker::PhantomData, sync::Arc, time::Duration};
+pub mod late_order_fast_path;
+pub mod session_reaper;
+pub mod sim_bundle;
+
+use std::{
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
     building::{
         builders::{
-            BlockBuildingAlgorithm, BlockBuildingAlgorithmInput, UnfinishedBlockBuildingSinkFactory,
+            BlockBuildingAlgorithm, BlockBuildingAlgorithmInput, UnfinishedBlockBuildingSink,
+            UnfinishedBlockBuildingSinkFactory,
         },
+        scratch::reset_scratch_pools,
+        state_read_metrics::take_slot_state_reads,
         BlockBuildingContext,
     },
-    live_builder::{payload_events::MevBoostSlotData, simulation::SlotOrderSimResults},
+    live_builder::{
+        payload_events::MevBoostSlotData,
+        simulation::SlotOrderSimResults,
+        slot_resource_report::{publish_slot_resource_report, SlotResourceTracker},
+    },
     roothash::run_trie_prefetcher,
+    telemetry::{add_slot_state_read_time, record_slot_started, slot_bids},
 };
 use reth_db::Database;
 use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
@@ -16,9 +32,17 @@ use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, trace};
 
+use late_order_fast_path::{run_late_order_fast_path, BestBlockTracker, LateOrderFastPathConfig};
+use session_reaper::{BuildingSession, SESSION_REAP_GRACE};
+use sim_bundle::{SlotBundleSimulators, SlotSimulator};
+
 use super::{
     order_input::{
-        self, order_replacement_manager::OrderReplacementManager, orderpool::OrdersForBlock,
+        self,
+        order_replacement_manager::OrderReplacementManager,
+        orderpool::OrdersForBlock,
+        orderpool_delta::{spawn_delta_pull_job, OrderPoolDeltas},
+        replaceable_order_sink::ReplaceableOrderSink,
     },
     payload_events,
     simulation::OrderSimulationPool,
@@ -32,6 +56,11 @@ pub struct BlockBuildingPool<P, DB> {
     orderpool_subscriber: order_input::OrderPoolSubscriber,
     order_simulation_pool: OrderSimulationPool<P>,
     run_sparse_trie_prefetcher: bool,
+    late_order_fast_path: Option<LateOrderFastPathConfig>,
+    /// If set, the sessions register their mev_simBundle simulator here.
+    sim_bundle: Option<Arc<SlotBundleSimulators>>,
+    /// See [`order_input::orderpool_delta`], None: orders are pushed as they arrive.
+    orderpool_delta_interval: Option<Duration>,
     phantom: PhantomData<DB>,
 }
 
@@ -50,6 +79,8 @@ where
         orderpool_subscriber: order_input::OrderPoolSubscriber,
         order_simulation_pool: OrderSimulationPool<P>,
         run_sparse_trie_prefetcher: bool,
+        late_order_fast_path: Option<LateOrderFastPathConfig>,
+        sim_bundle: Option<Arc<SlotBundleSimulators>>,
     ) -> Self {
         BlockBuildingPool {
             provider,
@@ -58,10 +89,20 @@ where
             orderpool_subscriber,
             order_simulation_pool,
             run_sparse_trie_prefetcher,
+            late_order_fast_path,
+            sim_bundle,
+            orderpool_delta_interval: None,
             phantom: PhantomData,
         }
     }
 
+    pub fn with_orderpool_delta_interval(self, orderpool_delta_interval: Option<Duration>) -> Self {
+        Self {
+            orderpool_delta_interval,
+            ..self
+        }
+    }
+
     /// Connects OrdersForBlock->OrderReplacementManager->Simulations and calls start_building_job
     pub fn start_block_building(
         &mut self,
@@ -73,30 +114,85 @@ where
         let block_cancellation = global_cancellation.child_token();
 
         let cancel = block_cancellation.clone();
-        tokio::spawn(async move {
-            tokio::time::sleep(max_time_to_build).await;
-            cancel.cancel();
-        });
+        record_slot_started(payload.slot(), block_ctx.block_env.number.to());
+        let session = BuildingSession::new(
+            payload.slot(),
+            block_ctx.block_env.number.to(),
+            block_cancellation.clone(),
+        );
+        // Start accounting state reads and resources for this slot
+        take_slot_state_reads();
+        let mut resource_tracker =
+            SlotResourceTracker::start(payload.slot(), block_ctx.block_env.number.to());
+        reset_scratch_pools();
 
         let (orders_for_block, sink) = OrdersForBlock::new_with_sink();
         // add OrderReplacementManager to manage replacements and cancellations
-        let order_replacement_manager = OrderReplacementManager::new(Box::new(sink));
+        let order_replacement_manager: Box<dyn ReplaceableOrderSink> =
+            Box::new(OrderReplacementManager::new(Box::new(sink)));
+        let orderpool_sink = match self.orderpool_delta_interval {
+            Some(interval) => {
+                let deltas = OrderPoolDeltas::default();
+                spawn_delta_pull_job(
+                    deltas.clone(),
+                    interval,
+                    order_replacement_manager,
+                    block_cancellation.clone(),
+                );
+                deltas.sink()
+            }
+            None => order_replacement_manager,
+        };
         // sink removal is automatic via OrderSink::is_alive false
-        let _block_sub = self.orderpool_subscriber.add_sink(
-            block_ctx.block_env.number.to(),
-            Box::new(order_replacement_manager),
-        );
+        let _block_sub = self
+            .orderpool_subscriber
+            .add_sink(block_ctx.block_env.number.to(), orderpool_sink);
 
         let simulations_for_block = self.order_simulation_pool.spawn_simulation_job(
             block_ctx.clone(),
             orders_for_block,
             block_cancellation.clone(),
+            resource_tracker.counters(),
         );
+        {
+            let session = session.clone();
+            let sim_contexts = self.order_simulation_pool.current_contexts();
+            let context_id = simulations_for_block.context_id;
+            let slot = payload.slot();
+            let deadline = Instant::now() + max_time_to_build;
+            tokio::spawn(async move {
+                resource_tracker.sample_memory_until(deadline).await;
+                cancel.cancel();
+                let state_reads = take_slot_state_reads();
+                add_slot_state_read_time(state_reads.read_time, max_time_to_build);
+                debug!(
+                    reads = state_reads.reads,
+                    slow_reads = state_reads.slow_reads,
+                    read_time_ms = state_reads.read_time.as_millis(),
+                    "Slot state reads"
+                );
+                let (bid_submissions, best_bid_value) = slot_bids(slot, session.block());
+                publish_slot_resource_report(resource_tracker.finish(
+                    state_reads,
+                    bid_submissions,
+                    best_bid_value,
+                ));
+                tokio::time::sleep(SESSION_REAP_GRACE).await;
+                if session.reap_if_stuck().is_some() {
+                    let released = sim_contexts.lock().release_context(context_id);
+                    debug!(
+                        block = session.block(),
+                        context_id, released, "Released simulation context of reaped session"
+                    );
+                }
+            });
+        }
         self.start_building_job(
             block_ctx,
             payload,
             simulations_for_block,
             block_cancellation,
+            &session,
         );
     }
 
@@ -107,13 +203,67 @@ where
         slot_data: MevBoostSlotData,
         input: SlotOrderSimResults,
         cancel: CancellationToken,
+        session: &Arc<BuildingSession>,
     ) {
-        let builder_sink = self.sink_factory.create_sink(slot_data, cancel.clone());
+        let proposer_override = slot_data.proposer_override.clone();
+        let builder_sink =
+            self.sink_factory
+                .create_sink(slot_data, cancel.clone(), ctx.clock.clone());
         let (broadcast_input, _) = broadcast::channel(10_000);
 
         let block_number = ctx.block_env.number.to::<u64>();
 
-        for builder in self.builders.iter() {
+        // Late orders and pending bundle simulations both work on the best block of the slot.
+        let best_block_tracker = (self.late_order_fast_path.is_some() || self.sim_bundle.is_some())
+            .then(|| Arc::new(BestBlockTracker::new(builder_sink.clone())));
+        if let (Some(simulators), Some(best_block_tracker)) =
+            (&self.sim_bundle, &best_block_tracker)
+        {
+            simulators.register(
+                block_number,
+                cancel.clone(),
+                Arc::new(SlotSimulator::new(
+                    self.provider.clone(),
+                    ctx.clone(),
+                    best_block_tracker.clone(),
+                    cancel.clone(),
+                )),
+            );
+        }
+        if let (Some(config), Some(best_block_tracker)) =
+            (&self.late_order_fast_path, &best_block_tracker)
+        {
+            let config = config.clone();
+            let ctx = ctx.clone();
+            let input = broadcast_input.subscribe();
+            let best_block = best_block_tracker.clone();
+            let cancel = cancel.clone();
+            let job_guard = session.job_guard("late_order_fast_path");
+            tokio::task::spawn_blocking(move || {
+                let _job_guard = job_guard;
+                run_late_order_fast_path(config, ctx, input, best_block, cancel);
+                debug!(block = block_number, "Stopped late order fast path job");
+            });
+        }
+        let builder_sink: Arc<dyn UnfinishedBlockBuildingSink> = match best_block_tracker {
+            Some(best_block_tracker) => best_block_tracker,
+            None => builder_sink,
+        };
+
+        // On reduced effort slots only the first builder runs.
+        let builders_to_run = if ctx.reduced_effort {
+            1
+        } else {
+            self.builders.len()
+        };
+        let builders = self.builders.iter().filter(|builder| {
+            proposer_override
+                .as_ref()
+                .map_or(true, |proposer_override| {
+                    proposer_override.runs_builder(builder.name())
+                })
+        });
+        for builder in builders.take(builders_to_run) {
             let builder_name = builder.name();
             debug!(block = block_number, builder_name, "Spawning builder job");
             let input = BlockBuildingAlgorithmInput::<P> {
@@ -124,7 +274,9 @@ where
                 cancel: cancel.clone(),
             };
             let builder = builder.clone();
+            let job_guard = session.job_guard(format!("builder:{}", builder_name));
             tokio::task::spawn_blocking(move || {
+                let _job_guard = job_guard;
                 builder.build_blocks(input);
                 debug!(block = block_number, builder_name, "Stopped builder job");
             });
@@ -133,7 +285,9 @@ where
         if self.run_sparse_trie_prefetcher {
             let input = broadcast_input.subscribe();
             let provider = self.provider.clone();
+            let job_guard = session.job_guard("trie_prefetcher");
             tokio::task::spawn_blocking(move || {
+                let _job_guard = job_guard;
                 run_trie_prefetcher(
                     ctx.attributes.parent,
                     ctx.shared_sparse_mpt_cache,
@@ -0,0 +1,143 @@
+//! Watchdog for building sessions (all the jobs we start for a slot).
+//! Every job started for a slot holds a [`SessionJobGuard`]. If some are still alive [`SESSION_REAP_GRACE`] after the
+//! slot deadline the session is reaped: its cancellation token is cancelled (should already be), its simulation
+//! context is released so sim workers move to the current slot and a forensics record is stored
+//! (see [`crate::utils::error_storage`]).
+//! Threads can't be killed, a stuck builder thread keeps running but it can't hog shared resources anymore.
+
+use crate::{telemetry::inc_reaped_building_sessions, utils::error_storage::store_error_event};
+use ahash::HashMap;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+/// Time jobs have to notice the slot cancellation and finish.
+pub const SESSION_REAP_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub struct BuildingSession {
+    slot: u64,
+    block: u64,
+    started_at: Instant,
+    cancel: CancellationToken,
+    /// job id -> job name
+    jobs: Mutex<HashMap<u64, String>>,
+    next_job_id: AtomicU64,
+}
+
+/// Forensics record for a reaped session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StuckSessionRecord {
+    pub slot: u64,
+    pub block: u64,
+    pub session_age_ms: u64,
+    pub running_jobs: Vec<String>,
+}
+
+impl BuildingSession {
+    pub fn new(slot: u64, block: u64, cancel: CancellationToken) -> Arc<Self> {
+        Arc::new(Self {
+            slot,
+            block,
+            started_at: Instant::now(),
+            cancel,
+            jobs: Mutex::new(HashMap::default()),
+            next_job_id: AtomicU64::new(0),
+        })
+    }
+
+    pub fn block(&self) -> u64 {
+        self.block
+    }
+
+    /// The job is considered running until the guard is dropped.
+    pub fn job_guard(self: &Arc<Self>, name: impl Into<String>) -> SessionJobGuard {
+        let job_id = self.next_job_id.fetch_add(1, Ordering::Relaxed);
+        self.jobs.lock().insert(job_id, name.into());
+        SessionJobGuard {
+            session: self.clone(),
+            job_id,
+        }
+    }
+
+    pub fn running_jobs(&self) -> Vec<String> {
+        let mut jobs: Vec<_> = self.jobs.lock().values().cloned().collect();
+        jobs.sort();
+        jobs
+    }
+
+    /// Call after the slot deadline + grace.
+    /// If some job is still running cancels the session and returns the forensics record, the caller must release
+    /// any shared state the session holds.
+    pub fn reap_if_stuck(&self) -> Option<StuckSessionRecord> {
+        let running_jobs = self.running_jobs();
+        if running_jobs.is_empty() {
+            return None;
+        }
+        self.cancel.cancel();
+        let record = StuckSessionRecord {
+            slot: self.slot,
+            block: self.block,
+            session_age_ms: self.started_at.elapsed().as_millis() as u64,
+            running_jobs,
+        };
+        error!(
+            slot = record.slot,
+            block = record.block,
+            session_age_ms = record.session_age_ms,
+            running_jobs = ?record.running_jobs,
+            "Reaping stuck building session"
+        );
+        inc_reaped_building_sessions();
+        store_error_event(
+            "stuck_building_session",
+            "jobs alive after deadline",
+            &record,
+        );
+        Some(record)
+    }
+}
+
+#[derive(Debug)]
+pub struct SessionJobGuard {
+    session: Arc<BuildingSession>,
+    job_id: u64,
+}
+
+impl Drop for SessionJobGuard {
+    fn drop(&mut self) {
+        self.session.jobs.lock().remove(&self.job_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reap_if_stuck() {
+        let cancel = CancellationToken::new();
+        let session = BuildingSession::new(10, 100, cancel.clone());
+        let builder = session.job_guard("builder:mp-ordering");
+        let simulation = session.job_guard("simulation");
+        drop(simulation);
+        assert_eq!(session.running_jobs(), vec!["builder:mp-ordering"]);
+
+        let record = session.reap_if_stuck().unwrap();
+        assert!(cancel.is_cancelled());
+        assert_eq!(record.block, 100);
+        assert_eq!(record.running_jobs, vec!["builder:mp-ordering"]);
+
+        drop(builder);
+        assert_eq!(session.reap_if_stuck(), None);
+    }
+}
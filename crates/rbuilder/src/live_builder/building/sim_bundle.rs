@@ -0,0 +1,521 @@
+//! Backend of mev_simBundle.
+//! Bundles are simulated on top of the parent block like any other order or, if the request asks for
+//! [`SimBundleOptions::pending`], at the end of the best block built so far in the current slot so searchers colocated
+//! with us can construct backruns of the block we are about to submit.
+//! Pending simulations run on a copy of the best block, they never change what the builders or the sink see.
+//! They show what's in the block we are about to submit so they are only served to the X-Flashbots-Signature signers
+//! allowed by the operator.
+//! Every building session registers its simulator on [`SlotBundleSimulators`], requests go to the newest live session
+//! for the block they target. At most max_concurrent_simulations run at once (counting from before the request is
+//! decoded, see [`SimBundleService::reserve`]), the rest are rejected.
+
+use super::late_order_fast_path::BestBlockTracker;
+use crate::{
+    building::{BlockBuildingContext, BlockState, CriticalCommitOrderError, PartialBlockFork},
+    primitives::{Order, SimulatedOrder, TransactionSignedEcRecoveredWithBlobs},
+};
+use ahash::HashSet;
+use alloy_primitives::{Address, B256, U256};
+use parking_lot::Mutex;
+use reth_errors::ProviderError;
+use reth_primitives::Receipt;
+use reth_provider::StateProviderFactory;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SimBundleOptions {
+    /// Simulate at the end of the best in-progress block instead of on top of the parent block.
+    pub pending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimBundleResult {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Block whose post state the bundle was simulated on (for pending simulations the block being built).
+    pub state_block: u64,
+    pub pending: bool,
+    /// Builder of the in-progress block (pending simulations only).
+    pub pending_block_builder: Option<String>,
+    pub profit: U256,
+    pub gas_used: u64,
+    pub mev_gas_price: U256,
+    /// Executed txs (none if the simulation failed), only served by eth_callBundle.
+    #[serde(skip)]
+    pub txs: Vec<SimBundleTxResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimBundleTxResult {
+    pub tx_hash: B256,
+    pub from_address: Address,
+    pub to_address: Option<Address>,
+    pub gas_used: u64,
+    pub success: bool,
+}
+
+impl SimBundleTxResult {
+    /// gas_used: of all the txs.
+    fn from_receipts(
+        gas_used: u64,
+        txs: &[TransactionSignedEcRecoveredWithBlobs],
+        receipts: &[Receipt],
+    ) -> Vec<Self> {
+        // the receipts of the pending simulations continue the ones of the block
+        let mut cumulative_gas_used = receipts
+            .last()
+            .map_or(0, |receipt| receipt.cumulative_gas_used)
+            .saturating_sub(gas_used);
+        txs.iter()
+            .zip(receipts)
+            .map(|(tx, receipt)| {
+                let gas_used = receipt
+                    .cumulative_gas_used
+                    .saturating_sub(cumulative_gas_used);
+                cumulative_gas_used = receipt.cumulative_gas_used;
+                Self {
+                    tx_hash: tx.hash(),
+                    from_address: tx.signer(),
+                    to_address: tx.to(),
+                    gas_used,
+                    success: receipt.success,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SimBundleError {
+    #[error("mev_simBundle disabled or no slot is being built")]
+    NoSlot,
+    #[error("No block built yet for the current slot")]
+    NoPendingBlock,
+    #[error("Pending simulations need an allowed X-Flashbots-Signature signer")]
+    PendingNotAllowed,
+    #[error("Too many simulations in progress")]
+    Busy,
+    #[error("Simulation task failed: {0}")]
+    Task(String),
+    #[error("Provider error: {0}")]
+    Provider(#[from] ProviderError),
+    #[error("Critical commit error: {0}")]
+    Critical(#[from] CriticalCommitOrderError),
+}
+
+pub trait SlotBundleSimulator: std::fmt::Debug + Send + Sync {
+    /// Blocking.
+    fn simulate(&self, order: Order, pending: bool) -> Result<SimBundleResult, SimBundleError>;
+}
+
+#[derive(Debug)]
+struct SimulatorSession {
+    id: u64,
+    block: u64,
+    cancel: CancellationToken,
+    simulator: Arc<dyn SlotBundleSimulator>,
+}
+
+/// Simulators of the live building sessions, a session is dropped once its cancel token is cancelled.
+#[derive(Debug, Default)]
+pub struct SlotBundleSimulators {
+    next_session_id: AtomicU64,
+    sessions: Mutex<Vec<SimulatorSession>>,
+}
+
+impl SlotBundleSimulators {
+    pub fn register(
+        &self,
+        block: u64,
+        cancel: CancellationToken,
+        simulator: Arc<dyn SlotBundleSimulator>,
+    ) {
+        let id = self.next_session_id.fetch_add(1, Ordering::Relaxed);
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|session| !session.cancel.is_cancelled());
+        sessions.push(SimulatorSession {
+            id,
+            block,
+            cancel,
+            simulator,
+        });
+    }
+
+    /// Newest live session building block (any block if None).
+    pub fn simulator(
+        &self,
+        block: Option<u64>,
+    ) -> Result<Arc<dyn SlotBundleSimulator>, SimBundleError> {
+        self.sessions
+            .lock()
+            .iter()
+            .filter(|session| !session.cancel.is_cancelled())
+            .filter(|session| block.map_or(true, |block| session.block == block))
+            .max_by_key(|session| session.id)
+            .map(|session| session.simulator.clone())
+            .ok_or(SimBundleError::NoSlot)
+    }
+}
+
+/// Slot for a simulation (see [`SimBundleService::reserve`]).
+#[derive(Debug)]
+pub struct SimBundlePermit(OwnedSemaphorePermit);
+
+/// What the input rpc needs to serve mev_simBundle and eth_callBundle.
+#[derive(Debug, Clone)]
+pub struct SimBundleService {
+    simulators: Arc<SlotBundleSimulators>,
+    /// Request signers allowed to simulate on the pending block.
+    pending_signers: Arc<HashSet<Address>>,
+    simulations: Arc<Semaphore>,
+}
+
+impl SimBundleService {
+    pub fn new(
+        simulators: Arc<SlotBundleSimulators>,
+        pending_signers: &[Address],
+        max_concurrent_simulations: usize,
+    ) -> Self {
+        Self {
+            simulators,
+            pending_signers: Arc::new(pending_signers.iter().copied().collect()),
+            simulations: Arc::new(Semaphore::new(max_concurrent_simulations.max(1))),
+        }
+    }
+
+    pub fn simulators(&self) -> &Arc<SlotBundleSimulators> {
+        &self.simulators
+    }
+
+    /// Takes a simulation slot, call it before decoding the request so the decoding is bounded too.
+    pub fn reserve(&self) -> Result<SimBundlePermit, SimBundleError> {
+        self.simulations
+            .clone()
+            .try_acquire_owned()
+            .map(SimBundlePermit)
+            .map_err(|_| SimBundleError::Busy)
+    }
+
+    /// request_signer: verified X-Flashbots-Signature signer of the request.
+    pub async fn simulate(
+        &self,
+        permit: SimBundlePermit,
+        order: Order,
+        pending: bool,
+        request_signer: Option<Address>,
+    ) -> Result<SimBundleResult, SimBundleError> {
+        if pending && !request_signer.is_some_and(|signer| self.pending_signers.contains(&signer)) {
+            return Err(SimBundleError::PendingNotAllowed);
+        }
+        let simulator = self.simulators.simulator(order.target_block())?;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            simulator.simulate(order, pending)
+        })
+        .await
+        .map_err(|err| SimBundleError::Task(err.to_string()))?
+    }
+}
+
+pub struct SlotSimulator<P> {
+    provider: P,
+    ctx: BlockBuildingContext,
+    best_block: Arc<BestBlockTracker>,
+    cancel: CancellationToken,
+}
+
+impl<P> std::fmt::Debug for SlotSimulator<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlotSimulator")
+            .field("block", &self.ctx.block())
+            .field("best_block", &self.best_block)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> SlotSimulator<P> {
+    pub fn new(
+        provider: P,
+        ctx: BlockBuildingContext,
+        best_block: Arc<BestBlockTracker>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            provider,
+            ctx,
+            best_block,
+            cancel,
+        }
+    }
+
+    fn result(
+        &self,
+        pending: bool,
+        pending_block_builder: Option<String>,
+        outcome: Result<(U256, u64, Vec<SimBundleTxResult>), String>,
+    ) -> SimBundleResult {
+        let block = self.ctx.block();
+        let state_block = if pending { block } else { block - 1 };
+        let (success, error, profit, gas_used, txs) = match outcome {
+            Ok((profit, gas_used, txs)) => (true, None, profit, gas_used, txs),
+            Err(error) => (false, Some(error), U256::ZERO, 0, Vec::new()),
+        };
+        SimBundleResult {
+            success,
+            error,
+            state_block,
+            pending,
+            pending_block_builder,
+            profit,
+            gas_used,
+            mev_gas_price: if gas_used != 0 {
+                profit / U256::from(gas_used)
+            } else {
+                U256::ZERO
+            },
+            txs,
+        }
+    }
+}
+
+impl<P> SlotBundleSimulator for SlotSimulator<P>
+where
+    P: StateProviderFactory + Send + Sync,
+{
+    fn simulate(&self, order: Order, pending: bool) -> Result<SimBundleResult, SimBundleError> {
+        if self.cancel.is_cancelled() {
+            return Err(SimBundleError::NoSlot);
+        }
+        if !pending {
+            let state = self
+                .provider
+                .history_by_block_hash(self.ctx.attributes.parent)?;
+            let mut block_state = BlockState::new(state);
+            // the state is dropped afterwards, no need to roll back
+            let mut fork = PartialBlockFork::new(&mut block_state);
+            let outcome = match fork.commit_order(&order, &self.ctx, 0, 0, 0, true)? {
+                Ok(res) => Ok((
+                    res.coinbase_profit,
+                    res.gas_used,
+                    SimBundleTxResult::from_receipts(res.gas_used, &res.txs, &res.receipts),
+                )),
+                Err(err) => Err(err.to_string()),
+            };
+            return Ok(self.result(false, None, outcome));
+        }
+        let (_, mut block) = self
+            .best_block
+            .best_block()
+            .ok_or(SimBundleError::NoPendingBlock)?;
+        let builder = block.builder_name().to_string();
+        // zero sim value so any profit on top of the block is accepted
+        let sim_order = SimulatedOrder {
+            order,
+            sim_value: Default::default(),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        let outcome = match block.commit_order(&sim_order)? {
+            Ok(res) => Ok((
+                res.coinbase_profit,
+                res.gas_used,
+                SimBundleTxResult::from_receipts(res.gas_used, &res.txs, &res.receipts),
+            )),
+            Err(err) => Err(err.to_string()),
+        };
+        Ok(self.result(true, Some(builder), outcome))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        building::{
+            builders::{
+                block_building_helper::{BlockBuildingHelper, BlockBuildingHelperFromProvider},
+                UnfinishedBlockBuildingSink,
+            },
+            testing::test_chain_state::{BlockArgs, NamedAddr, TestChainState, TxArgs},
+        },
+        primitives::{MempoolTx, TransactionSignedEcRecoveredWithBlobs},
+        roothash::RootHashConfig,
+        utils::test_utils::tx,
+    };
+
+    #[derive(Debug)]
+    struct NullSink;
+
+    impl UnfinishedBlockBuildingSink for NullSink {
+        fn new_block(&self, _block: Box<dyn BlockBuildingHelper>) {}
+
+        fn can_use_suggested_fee_recipient_as_coinbase(&self) -> bool {
+            true
+        }
+    }
+
+    /// Answers with its id as state_block.
+    #[derive(Debug)]
+    struct FixedSimulator(u64);
+
+    impl SlotBundleSimulator for FixedSimulator {
+        fn simulate(
+            &self,
+            _order: Order,
+            pending: bool,
+        ) -> Result<SimBundleResult, SimBundleError> {
+            Ok(SimBundleResult {
+                success: true,
+                error: None,
+                state_block: self.0,
+                pending,
+                pending_block_builder: None,
+                profit: U256::ZERO,
+                gas_used: 0,
+                mev_gas_price: U256::ZERO,
+                txs: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sim_bundle_service() {
+        let simulators = Arc::new(SlotBundleSimulators::default());
+        let old_session = CancellationToken::new();
+        simulators.register(11, old_session.clone(), Arc::new(FixedSimulator(1)));
+        simulators.register(11, CancellationToken::new(), Arc::new(FixedSimulator(2)));
+        let order = || Order::Tx(MempoolTx::new(tx(1)));
+        let state_block = |block| {
+            simulators
+                .simulator(block)
+                .unwrap()
+                .simulate(order(), false)
+                .unwrap()
+                .state_block
+        };
+        assert_eq!(state_block(Some(11)), 2);
+        // the old session ending doesn't take the new one with it
+        old_session.cancel();
+        assert_eq!(state_block(Some(11)), 2);
+        simulators.register(12, CancellationToken::new(), Arc::new(FixedSimulator(3)));
+        assert_eq!(state_block(Some(11)), 2);
+        assert_eq!(state_block(None), 3);
+        assert!(matches!(
+            simulators.simulator(Some(13)),
+            Err(SimBundleError::NoSlot)
+        ));
+
+        let allowed = Address::with_last_byte(1);
+        let service = SimBundleService::new(simulators, &[allowed], 1);
+        let simulate = |pending, signer| {
+            let permit = service.reserve().unwrap();
+            service.simulate(permit, order(), pending, signer)
+        };
+        assert!(simulate(false, None).await.is_ok());
+        for signer in [None, Some(Address::with_last_byte(2))] {
+            assert!(matches!(
+                simulate(true, signer).await,
+                Err(SimBundleError::PendingNotAllowed)
+            ));
+        }
+        assert!(simulate(true, Some(allowed)).await.unwrap().pending);
+        let running = service.reserve().unwrap();
+        assert!(matches!(service.reserve(), Err(SimBundleError::Busy)));
+        drop(running);
+        assert!(service.reserve().is_ok());
+    }
+
+    #[test]
+    fn test_sim_bundle_on_pending_block() {
+        let test_chain = TestChainState::new(
+            BlockArgs::default()
+                .number(11)
+                .use_suggested_fee_recipient_as_coinbase(true),
+        )
+        .unwrap();
+        let tracker = Arc::new(BestBlockTracker::new(Arc::new(NullSink)));
+        let simulator = SlotSimulator::new(
+            test_chain.provider_factory().clone(),
+            test_chain.block_building_context().clone(),
+            tracker.clone(),
+            CancellationToken::new(),
+        );
+        let order = |nonce| {
+            let tx = test_chain
+                .sign_tx(TxArgs::new_send_to_coinbase(NamedAddr::User(1), nonce, 5))
+                .unwrap();
+            Order::Tx(MempoolTx::new(
+                TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+            ))
+        };
+
+        let head = simulator.simulate(order(0), false).unwrap();
+        assert!(head.success);
+        assert_eq!(head.state_block, 10);
+        assert_eq!(head.profit, U256::from(5));
+        let [head_tx] = head.txs.as_slice() else {
+            panic!("expected one tx result: {:?}", head.txs);
+        };
+        let head_order = order(0);
+        assert_eq!(head_tx.tx_hash, head_order.list_txs()[0].0.hash());
+        assert_eq!(head_tx.gas_used, head.gas_used);
+        assert!(head_tx.success);
+        assert!(matches!(
+            simulator.simulate(order(1), true),
+            Err(SimBundleError::NoPendingBlock)
+        ));
+
+        // in-progress block already spending nonce 0
+        let mut block = BlockBuildingHelperFromProvider::new(
+            test_chain.provider_factory().clone(),
+            RootHashConfig::skip_root_hash(),
+            test_chain.block_building_context().clone(),
+            None,
+            "test".to_string(),
+            false,
+            None,
+            CancellationToken::new(),
+        )
+        .unwrap();
+        let sim_order = SimulatedOrder {
+            order: order(0),
+            sim_value: Default::default(),
+            prev_order: None,
+            used_state_trace: None,
+            execution_cost: Default::default(),
+        };
+        assert!(block.commit_order(&sim_order).unwrap().is_ok());
+        tracker.new_block(Box::new(block));
+
+        let backrun = simulator.simulate(order(1), true).unwrap();
+        assert!(backrun.success);
+        // gas of the backrun only, not of the block under it
+        assert_eq!(backrun.txs.len(), 1);
+        assert_eq!(backrun.txs[0].gas_used, backrun.gas_used);
+        assert_eq!(backrun.state_block, 11);
+        assert_eq!(backrun.pending_block_builder.as_deref(), Some("test"));
+        assert!(!simulator.simulate(order(1), false).unwrap().success);
+        assert!(!simulator.simulate(order(0), true).unwrap().success);
+        // the pending block itself is untouched
+        assert_eq!(
+            tracker
+                .best_block()
+                .unwrap()
+                .1
+                .built_block_trace()
+                .included_orders
+                .len(),
+            1
+        );
+    }
+}
@@ -12,7 +12,9 @@ use tokio_util::sync::CancellationToken;
 use crate::{
     building::builders::{BacktestSimulateBlockInput, Block},
     live_builder::{
-        base_config::load_config_toml_and_env, payload_events::MevBoostSlotDataGenerator,
+        base_config::load_config_toml_and_env,
+        block_output::block_artifact::{BlockArtifact, BlockDiff},
+        payload_events::MevBoostSlotDataGenerator,
     },
     telemetry,
     utils::build_info::Version,
@@ -26,6 +28,11 @@ enum Cli {
     Run(RunCmd),
     #[clap(name = "config", about = "Print the current config")]
     Config(RunCmd),
+    #[clap(
+        name = "diff-blocks",
+        about = "Compare two block artifacts (see l1_config block_artifacts_dir)"
+    )]
+    DiffBlocks(DiffBlocksCmd),
     #[clap(name = "version", about = "Print version information")]
     Version,
 }
@@ -36,6 +43,16 @@ struct RunCmd {
     config: PathBuf,
 }
 
+#[derive(Parser, Debug)]
+struct DiffBlocksCmd {
+    #[clap(help = "Block artifact a")]
+    a: PathBuf,
+    #[clap(help = "Block artifact b")]
+    b: PathBuf,
+    #[clap(long, help = "Print the diff as json")]
+    json: bool,
+}
+
 /// Basic stuff needed to call cli::run
 pub trait LiveBuilderConfig: Debug + DeserializeOwned + Sync {
     fn base_config(&self) -> &BaseConfig;
@@ -88,6 +105,15 @@ where
             println!("{:#?}", config);
             return Ok(());
         }
+        Cli::DiffBlocks(cli) => {
+            let diff = BlockDiff::new(&BlockArtifact::load(&cli.a)?, &BlockArtifact::load(&cli.b)?);
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print!("{}", diff.report());
+            }
+            return Ok(());
+        }
         Cli::Version => {
             print_version_info();
             return Ok(());
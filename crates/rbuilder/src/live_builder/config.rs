@@ -2,22 +2,36 @@
 //!
 //!
 use super::{
+    archive::spawn_archiver,
     base_config::BaseConfig,
     block_output::{
         bid_observer::{BidObserver, NullBidObserver},
         bid_value_source::null_bid_value_source::NullBidValueSource,
         bidding::{
-            interfaces::BiddingService, true_block_value_bidder::TrueBlockValueBiddingService,
+            bid_adjuster_bid_maker::{BidAdjusterClient, BidAdjusterConfig},
+            interfaces::BiddingService,
+            true_block_value_bidder::{RelayFloorTopUp, TrueBlockValueBiddingService},
+            urgent_reseal_bid_maker::UrgentResealConfig,
             wallet_balance_watcher::WalletBalanceWatcher,
         },
+        block_artifact::BlockArtifactStore,
+        block_inclusion_observer::BlockInclusionObservers,
         block_sealing_bidder_factory::BlockSealingBidderFactory,
-        relay_submit::{RelaySubmitSinkFactory, SubmissionConfig},
+        builder_identity::{
+            set_builder_identity_schedule, BuilderIdentity, BuilderIdentitySchedule,
+        },
+        exclusion_audit::ExclusionAuditBidObserver,
+        payload_signer::PayloadSigningThread,
+        relay_data_poller::{spawn_relay_data_poller, RelayDataPollerConfig},
+        relay_submit::{RelayPriorityBudget, RelaySubmitSinkFactory, SubmissionConfig},
+        submission_audit::SubmissionAuditLog,
     },
 };
 use crate::{
     beacon_api_client::Client,
     building::{
         builders::{
+            algorithm_params::register_builder,
             ordering_builder::{OrderingBuilderConfig, OrderingBuildingAlgorithm},
             parallel_builder::{
                 parallel_build_backtest, ParallelBuilderConfig, ParallelBuildingAlgorithm,
@@ -27,8 +41,11 @@ use crate::{
         Sorting,
     },
     live_builder::{
-        base_config::EnvOrValue, block_output::relay_submit::BuilderSinkFactory,
-        cli::LiveBuilderConfig, payload_events::MevBoostSlotDataGenerator,
+        base_config::EnvOrValue,
+        block_output::relay_submit::BuilderSinkFactory,
+        cli::LiveBuilderConfig,
+        payload_events::MevBoostSlotDataGenerator,
+        proposer_overrides::{ProposerOverrideConfig, ProposerOverrides},
     },
     mev_boost::BLSBlockSigner,
     primitives::mev_boost::{MevBoostRelay, RelayConfig},
@@ -39,7 +56,7 @@ use crate::{
 use alloy_chains::ChainKind;
 use alloy_primitives::{
     utils::{format_ether, parse_ether},
-    Address, FixedBytes, B256,
+    Address, FixedBytes, B256, U256,
 };
 use ethereum_consensus::{
     builder::compute_builder_domain, crypto::SecretKey, primitives::Version,
@@ -73,6 +90,8 @@ use url::Url;
 pub const WALLET_INIT_HISTORY_SIZE: Duration = Duration::from_secs(60 * 60 * 24);
 /// 1 is easier for debugging.
 pub const DEFAULT_MAX_CONCURRENT_SEALS: u64 = 1;
+/// ~1 day.
+pub const DEFAULT_RELAY_KEY_ROTATION_ANNOUNCE_EPOCHS: u64 = 225;
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 #[serde(tag = "algo", rename_all = "kebab-case", deny_unknown_fields)]
@@ -133,6 +152,76 @@ pub struct L1Config {
 
     /// Genesis fork version for the chain. If not provided it will be fetched from the beacon client.
     pub genesis_fork_version: Option<String>,
+
+    /// If set, for every slot a signed hash-chained record of the orders excluded from our last submitted block
+    /// (and why) is appended to this file.
+    pub exclusion_audit_log_path: Option<PathBuf>,
+
+    /// If set, bids below the relays bid floor (see relay min_bid_value_eth) are subsidized up to it when it costs
+    /// at most this. Bids still below the floor of a relay are not submitted to it.
+    pub max_relay_floor_subsidy_eth: Option<String>,
+
+    /// Builders, bidding and subsidy cap for the slots of some proposers (see
+    /// [`crate::live_builder::proposer_overrides`]).
+    pub proposer_overrides: Vec<ProposerOverrideConfig>,
+
+    /// If set, every relay submission (payload hash, signature, relay response) is appended to this file.
+    pub submission_audit_log_path: Option<PathBuf>,
+
+    /// If set, every block we submit (or validate on dry run) is saved to this dir as json, compare two of them with
+    /// `rbuilder diff-blocks`.
+    pub block_artifacts_dir: Option<PathBuf>,
+    /// Artifacts of the blocks this many or more below the newest submitted one are deleted, 0 keeps everything.
+    pub block_artifacts_keep_blocks: u64,
+
+    /// If set, every block we submit (or validate on dry run) is exported here as a block template (header, txs,
+    /// state diff and witness, see [`crate::building::block_template`]).
+    pub block_templates_dir: Option<PathBuf>,
+
+    /// If true block templates are generated and the ones of the last submitted blocks can be fetched with
+    /// admin_blockTemplate (admin rpc).
+    pub block_templates_on_demand: bool,
+
+    /// If true relays are submitted to by priority (most slots won through them and fastest first) instead of in config
+    /// order, see /status for the current order.
+    pub relay_auto_priority: bool,
+    /// With relay_auto_priority, once the slot deadline is closer than this only the first
+    /// relay_priority_deadline_relays relays (by priority) of each group (optimistic and not) get our blocks so the
+    /// last submissions of the slot reach the relays that matter first without competing for bandwidth.
+    pub relay_priority_deadline_ms: Option<u64>,
+    pub relay_priority_deadline_relays: usize,
+
+    /// Scheduled builder identities, relay_secret_key/optimistic_relay_secret_key are used until the first one starts.
+    pub relay_key_rotations: Vec<RelayKeyRotationConfig>,
+    /// Upcoming rotations are announced (warning log + admin_builderIdentities) this many epochs in advance so the new
+    /// pubkeys can be registered on the relays.
+    pub relay_key_rotation_announce_epochs: u64,
+
+    /// If set, bids improving the best bid by at least this are sealed and submitted right away instead of waiting
+    /// for the sealer to be free.
+    pub urgent_reseal_min_delta_eth: Option<String>,
+    /// Min time between urgent seals.
+    pub urgent_reseal_min_interval_ms: u64,
+
+    /// If set, this long after each slot the relays data APIs are polled to know if we won the slot or by how much
+    /// we lost (see [`crate::live_builder::block_output::relay_data_poller`]).
+    pub relay_data_poll_delay_ms: Option<u64>,
+
+    /// If set, payouts are adjusted by this external service (ws url) before sealing (see
+    /// [`crate::live_builder::block_output::bidding::bid_adjuster_bid_maker`]).
+    pub bid_adjuster_url: Option<String>,
+    /// Bids are sealed unadjusted if the bid adjuster takes longer than this.
+    pub bid_adjuster_timeout_ms: u64,
+}
+
+/// Builder identity active from from_epoch until the next rotation.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RelayKeyRotationConfig {
+    pub from_epoch: u64,
+    pub relay_secret_key: EnvOrValue<String>,
+    /// If not set relay_secret_key is also used for optimistic submissions.
+    pub optimistic_relay_secret_key: Option<EnvOrValue<String>>,
 }
 
 impl Default for L1Config {
@@ -149,6 +238,25 @@ impl Default for L1Config {
             cl_node_url: vec![EnvOrValue::from("http://127.0.0.1:3500")],
             max_concurrent_seals: DEFAULT_MAX_CONCURRENT_SEALS,
             genesis_fork_version: None,
+            exclusion_audit_log_path: None,
+            max_relay_floor_subsidy_eth: None,
+            proposer_overrides: vec![],
+            submission_audit_log_path: None,
+            block_artifacts_dir: None,
+            // ~1 day
+            block_artifacts_keep_blocks: 7200,
+            block_templates_dir: None,
+            block_templates_on_demand: false,
+            relay_auto_priority: false,
+            relay_priority_deadline_ms: None,
+            relay_priority_deadline_relays: 1,
+            relay_key_rotations: vec![],
+            relay_key_rotation_announce_epochs: DEFAULT_RELAY_KEY_ROTATION_ANNOUNCE_EPOCHS,
+            urgent_reseal_min_delta_eth: None,
+            urgent_reseal_min_interval_ms: 50,
+            relay_data_poll_delay_ms: None,
+            bid_adjuster_url: None,
+            bid_adjuster_timeout_ms: 50,
         }
     }
 }
@@ -168,10 +276,79 @@ impl L1Config {
             .collect()
     }
 
-    pub fn create_relays(&self) -> eyre::Result<Vec<MevBoostRelay>> {
+    /// None if no subsidy is configured (nor by any proposer override) or some relay has no floor.
+    pub fn relay_floor_top_up(
+        &self,
+        relays: &[MevBoostRelay],
+    ) -> eyre::Result<Option<RelayFloorTopUp>> {
+        let max_subsidy = match &self.max_relay_floor_subsidy_eth {
+            Some(max_subsidy) => parse_ether(max_subsidy)?,
+            None if self
+                .proposer_overrides
+                .iter()
+                .any(|config| config.max_relay_floor_subsidy_eth.is_some()) =>
+            {
+                U256::ZERO
+            }
+            None => return Ok(None),
+        };
+        let floor = relays
+            .iter()
+            .map(|relay| relay.min_bid_value)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|floors| floors.into_iter().min());
+        Ok(floor.map(|floor| RelayFloorTopUp { floor, max_subsidy }))
+    }
+
+    /// Urgent seals are never more frequent than the slowest relay submission rate limit
+    /// (interval_between_submissions_ms), the relay submission would skip them.
+    pub fn urgent_reseal_config(&self) -> eyre::Result<Option<UrgentResealConfig>> {
+        let relays_min_interval_ms = self
+            .relays
+            .iter()
+            .filter_map(|relay| relay.interval_between_submissions_ms)
+            .max()
+            .unwrap_or_default();
+        self.urgent_reseal_min_delta_eth
+            .as_ref()
+            .map(|min_delta| {
+                Ok::<_, eyre::Report>(UrgentResealConfig {
+                    min_delta: parse_ether(min_delta)?,
+                    min_interval: Duration::from_millis(
+                        self.urgent_reseal_min_interval_ms
+                            .max(relays_min_interval_ms),
+                    ),
+                })
+            })
+            .transpose()
+    }
+
+    pub fn bid_adjuster_config(&self) -> Option<BidAdjusterConfig> {
+        self.bid_adjuster_url.as_ref().map(|url| BidAdjusterConfig {
+            url: url.clone(),
+            timeout: Duration::from_millis(self.bid_adjuster_timeout_ms),
+        })
+    }
+
+    pub fn relay_data_poller_config(
+        &self,
+        relays: &[MevBoostRelay],
+    ) -> Option<RelayDataPollerConfig> {
+        self.relay_data_poll_delay_ms
+            .map(|poll_delay_ms| RelayDataPollerConfig {
+                relays: relays
+                    .iter()
+                    .map(|relay| (relay.id.clone(), relay.client.clone()))
+                    .collect(),
+                poll_delay: Duration::from_millis(poll_delay_ms),
+            })
+    }
+
+    /// extra_data_builder_name: see [`BaseConfig::extra_data_builder_name`].
+    pub fn create_relays(&self, extra_data_builder_name: &str) -> eyre::Result<Vec<MevBoostRelay>> {
         let mut results = Vec::new();
         for relay in &self.relays {
-            results.push(MevBoostRelay::from_config(relay)?);
+            results.push(MevBoostRelay::from_config(relay, extra_data_builder_name)?);
         }
         Ok(results)
     }
@@ -203,10 +380,43 @@ impl L1Config {
         BLSBlockSigner::new(secret_key, signing_domain)
     }
 
+    fn scheduled_identities(&self, chain_spec: &ChainSpec) -> eyre::Result<Vec<BuilderIdentity>> {
+        if self.relay_key_rotations.is_empty() {
+            return Ok(Vec::new());
+        }
+        let signing_domain = get_signing_domain(
+            chain_spec.chain,
+            self.beacon_clients()?,
+            self.genesis_fork_version.clone(),
+        )?;
+        let signer = |key: &EnvOrValue<String>| -> eyre::Result<BLSBlockSigner> {
+            let secret_key = SecretKey::try_from(key.value()?).map_err(|e| {
+                eyre::eyre!("Failed to parse rotation relay key: {:?}", e.to_string())
+            })?;
+            BLSBlockSigner::new(secret_key, signing_domain)
+        };
+        self.relay_key_rotations
+            .iter()
+            .map(|rotation| {
+                let rotation_signer = signer(&rotation.relay_secret_key)?;
+                let optimistic_signer = match &rotation.optimistic_relay_secret_key {
+                    Some(key) => signer(key)?,
+                    None => rotation_signer.clone(),
+                };
+                Ok(BuilderIdentity {
+                    from_epoch: rotation.from_epoch,
+                    signer: rotation_signer,
+                    optimistic_signer,
+                })
+            })
+            .collect()
+    }
+
     fn submission_config(
         &self,
         chain_spec: Arc<ChainSpec>,
         bid_observer: Box<dyn BidObserver + Send + Sync>,
+        block_inclusion_observers: BlockInclusionObservers,
     ) -> eyre::Result<SubmissionConfig> {
         if (self.dry_run || self.optimistic_prevalidate_optimistic_blocks)
             && self.dry_run_validation_url.is_empty()
@@ -241,17 +451,53 @@ impl L1Config {
         };
 
         let signer = self.bls_signer(&chain_spec)?;
+        let identities = Arc::new(BuilderIdentitySchedule::new(
+            signer,
+            optimistic_signer,
+            self.scheduled_identities(&chain_spec)?,
+            self.relay_key_rotation_announce_epochs,
+        )?);
+        set_builder_identity_schedule(identities.clone());
+
+        let submission_audit_log = self
+            .submission_audit_log_path
+            .as_ref()
+            .map(|path| {
+                SubmissionAuditLog::open(path)
+                    .map(Arc::new)
+                    .with_context(|| format!("Opening submission audit log {:?}", path))
+            })
+            .transpose()?;
 
         Ok(SubmissionConfig {
             chain_spec,
-            signer,
+            identities,
             dry_run: self.dry_run,
             validation_api,
             optimistic_enabled: self.optimistic_enabled,
-            optimistic_signer,
             optimistic_max_bid_value: parse_ether(&self.optimistic_max_bid_value_eth)?,
             optimistic_prevalidate_optimistic_blocks: self.optimistic_prevalidate_optimistic_blocks,
             bid_observer,
+            block_inclusion_observers,
+            submission_audit_log,
+            block_artifacts: self.block_artifacts_dir.clone().map(|dir| {
+                Arc::new(BlockArtifactStore::new(
+                    dir,
+                    self.block_artifacts_keep_blocks,
+                ))
+            }),
+            block_templates_dir: self.block_templates_dir.clone(),
+            relay_auto_priority: self.relay_auto_priority,
+            relay_priority_budget: self
+                .relay_priority_deadline_ms
+                .filter(|_| self.relay_auto_priority)
+                .map(|deadline_ms| RelayPriorityBudget {
+                    deadline: Duration::from_millis(deadline_ms),
+                    relays: self.relay_priority_deadline_relays,
+                }),
+            payload_signer: Arc::new(
+                PayloadSigningThread::spawn().context("Spawning payload signing thread")?,
+            ),
         })
     }
 
@@ -260,16 +506,19 @@ impl L1Config {
         &self,
         chain_spec: Arc<ChainSpec>,
         bid_observer: Box<dyn BidObserver + Send + Sync>,
+        block_inclusion_observers: BlockInclusionObservers,
+        extra_data_builder_name: &str,
     ) -> eyre::Result<(Box<dyn BuilderSinkFactory>, Vec<MevBoostRelay>)> {
-        let submission_config = self.submission_config(chain_spec, bid_observer)?;
-        info!(
-            "Builder mev boost normal relay pubkey: {:?}",
-            submission_config.signer.pub_key()
-        );
-        info!(
-            "Builder mev boost optimistic relay pubkey: {:?}",
-            submission_config.optimistic_signer.pub_key()
-        );
+        let submission_config =
+            self.submission_config(chain_spec, bid_observer, block_inclusion_observers)?;
+        for identity in submission_config.identities.identities() {
+            info!(
+                from_epoch = identity.from_epoch,
+                "Builder mev boost normal relay pubkey: {:?}, optimistic relay pubkey: {:?}",
+                identity.signer.pub_key(),
+                identity.optimistic_signer.pub_key()
+            );
+        }
         info!(
             "Optimistic mode, enabled: {}, prevalidate: {}, max_value: {}",
             submission_config.optimistic_enabled,
@@ -277,7 +526,7 @@ impl L1Config {
             format_ether(submission_config.optimistic_max_bid_value),
         );
 
-        let relays = self.create_relays()?;
+        let relays = self.create_relays(extra_data_builder_name)?;
         let sink_factory: Box<dyn BuilderSinkFactory> = Box::new(RelaySubmitSinkFactory::new(
             submission_config,
             relays.clone(),
@@ -303,33 +552,78 @@ impl LiveBuilderConfig for Config {
             + Clone
             + 'static,
     {
+        let bid_observer: Box<dyn BidObserver + Send + Sync> =
+            match &self.l1_config.exclusion_audit_log_path {
+                Some(path) => Box::new(ExclusionAuditBidObserver::new(
+                    self.base_config.coinbase_signer()?,
+                    path,
+                )),
+                None => Box::new(NullBidObserver {}),
+            };
+        let block_inclusion_observers = BlockInclusionObservers::default();
         let (sink_sealed_factory, relays) = self.l1_config.create_relays_sealed_sink_factory(
             self.base_config.chain_spec()?,
-            Box::new(NullBidObserver {}),
+            bid_observer,
+            block_inclusion_observers.clone(),
+            self.base_config.extra_data_builder_name(),
         )?;
 
+        let journals = [
+            &self.l1_config.submission_audit_log_path,
+            &self.l1_config.exclusion_audit_log_path,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+        if let Some(archiver_config) = self.base_config.archiver_config(journals)? {
+            let storage = archiver_config.storage.create_storage()?;
+            spawn_archiver(archiver_config, storage, cancellation_token.clone())
+                .with_context(|| "Error spawning archiver")?;
+        }
+
+        if let Some(poller_config) = self.l1_config.relay_data_poller_config(&relays) {
+            spawn_relay_data_poller(poller_config, cancellation_token.clone());
+        }
+
         let (wallet_balance_watcher, wallet_history) = WalletBalanceWatcher::new(
             provider.clone(),
             self.base_config.coinbase_signer()?.address,
             WALLET_INIT_HISTORY_SIZE,
         )?;
-        let bidding_service: Box<dyn BiddingService> =
-            Box::new(TrueBlockValueBiddingService::new(&wallet_history));
-
-        let sink_factory = Box::new(BlockSealingBidderFactory::new(
-            bidding_service,
-            sink_sealed_factory,
-            Arc::new(NullBidValueSource {}),
-            wallet_balance_watcher,
-            self.l1_config.max_concurrent_seals as usize,
+        let bidding_service: Box<dyn BiddingService> = Box::new(TrueBlockValueBiddingService::new(
+            &wallet_history,
+            self.l1_config.relay_floor_top_up(&relays)?,
         ));
 
+        let sink_factory = Box::new(
+            BlockSealingBidderFactory::new(
+                bidding_service,
+                sink_sealed_factory,
+                Arc::new(NullBidValueSource {}),
+                wallet_balance_watcher,
+                self.l1_config.max_concurrent_seals as usize,
+                self.l1_config.urgent_reseal_config()?,
+            )
+            .with_bid_adjuster(
+                self.l1_config
+                    .bid_adjuster_config()
+                    .map(|config| Arc::new(BidAdjusterClient::new(config))),
+            )
+            .with_block_inclusion_observers(block_inclusion_observers.clone()),
+        );
+
+        let proposer_overrides = ProposerOverrides::from_config(
+            &self.l1_config.proposer_overrides,
+            &self.base_config.live_builders,
+        )?;
         let payload_event = MevBoostSlotDataGenerator::new(
             self.l1_config.beacon_clients()?,
             relays,
             self.base_config.blocklist()?,
             cancellation_token.clone(),
-        );
+        )
+        .with_proposer_overrides(proposer_overrides);
         let live_builder = self
             .base_config
             .create_builder_with_provider_factory(
@@ -338,8 +632,21 @@ impl LiveBuilderConfig for Config {
                 payload_event,
                 provider,
             )
-            .await?;
-        let root_hash_config = self.base_config.live_root_hash_config()?;
+            .await?
+            .with_block_inclusion_observers(block_inclusion_observers);
+        let root_hash_config = self
+            .base_config
+            .live_root_hash_config()?
+            .with_payment_proof(
+                self.l1_config
+                    .relays
+                    .iter()
+                    .any(|relay| relay.requires_payment_proof),
+            )
+            .with_block_template(
+                self.l1_config.block_templates_dir.is_some()
+                    || self.l1_config.block_templates_on_demand,
+            );
         let builders = create_builders(
             self.live_builders()?,
             root_hash_config,
@@ -409,6 +716,9 @@ impl Default for Config {
                         drop_failed_orders: true,
                         coinbase_payment: false,
                         build_duration_deadline_ms: None,
+                        zero_profit_txs: Default::default(),
+                        zero_profit_tx_max_gas: None,
+                        value_per_cost_window_ms: None,
                     }),
                 },
                 BuilderConfig {
@@ -420,6 +730,9 @@ impl Default for Config {
                         drop_failed_orders: true,
                         coinbase_payment: false,
                         build_duration_deadline_ms: None,
+                        zero_profit_txs: Default::default(),
+                        zero_profit_tx_max_gas: None,
+                        value_per_cost_window_ms: None,
                     }),
                 },
             ],
@@ -508,6 +821,7 @@ where
 {
     match cfg.builder {
         SpecificBuilderConfig::OrderingBuilder(order_cfg) => {
+            register_builder(&cfg.name, &order_cfg);
             Arc::new(OrderingBuildingAlgorithm::new(
                 root_hash_config.clone(),
                 sbundle_mergeabe_signers.to_vec(),
@@ -516,6 +830,7 @@ where
             ))
         }
         SpecificBuilderConfig::ParallelBuilder(parallel_cfg) => {
+            register_builder(&cfg.name, &parallel_cfg);
             Arc::new(ParallelBuildingAlgorithm::new(
                 root_hash_config.clone(),
                 sbundle_mergeabe_signers.to_vec(),
@@ -628,6 +943,27 @@ mod test {
             .contains(&"http://localhost:3500".to_string()));
     }
 
+    #[test]
+    fn test_urgent_reseal_config() {
+        let relay = |interval_between_submissions_ms| RelayConfig {
+            interval_between_submissions_ms,
+            ..Default::default()
+        };
+        let mut config = L1Config {
+            urgent_reseal_min_interval_ms: 50,
+            relays: vec![relay(None)],
+            ..Default::default()
+        };
+        assert_eq!(config.urgent_reseal_config().unwrap(), None);
+
+        config.urgent_reseal_min_delta_eth = Some("0.01".to_string());
+        let min_interval =
+            |config: &L1Config| config.urgent_reseal_config().unwrap().unwrap().min_interval;
+        assert_eq!(min_interval(&config), Duration::from_millis(50));
+        config.relays = vec![relay(Some(200)), relay(Some(100)), relay(None)];
+        assert_eq!(min_interval(&config), Duration::from_millis(200));
+    }
+
     #[test]
     fn test_parse_backtest_example_config() {
         let mut p = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -0,0 +1,179 @@
+//! Fault injection points to exercise the retry/fallback logic in staging (chaos testing).
+//! Only active when built with the `fault-injection` feature, without it [`triggered`] is always None and the points
+//! cost nothing.
+//! Faults are armed/disarmed at runtime via the admin rpc (see [`fault_injection_rpc_module`]):
+//! - admin_injectFault(point, {"probability": 0.5, "delayMs": 200, "count": 10})
+//! - admin_clearFault(point)
+//! - admin_faults() -> armed faults
+
+use jsonrpsee::RpcModule;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultPoint {
+    /// Relay submissions wait delay_ms before being sent.
+    RelaySubmitDelay,
+    /// Relay submissions fail with a connection error without being sent.
+    RelaySubmitFail,
+    /// New heads are not forwarded to the orderpool.
+    DropHeadUpdate,
+    /// Successful simulations report 10x the real coinbase profit.
+    CorruptSimulation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultConfig {
+    /// Chance of triggering each time the point is reached.
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+    /// Only for FaultPoint::RelaySubmitDelay.
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// The fault is disarmed after triggering this many times, None to keep it.
+    #[serde(default)]
+    pub count: Option<u64>,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+#[cfg(feature = "fault-injection")]
+mod armed {
+    use super::{FaultConfig, FaultPoint};
+    use lazy_static::lazy_static;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    lazy_static! {
+        pub static ref FAULTS: Mutex<HashMap<FaultPoint, FaultConfig>> = Mutex::new(HashMap::new());
+    }
+
+    pub fn triggered(point: FaultPoint) -> Option<FaultConfig> {
+        let mut faults = FAULTS.lock();
+        let fault = *faults.get(&point)?;
+        if rand::random::<f64>() >= fault.probability {
+            return None;
+        }
+        match fault.count {
+            Some(count) if count <= 1 => {
+                faults.remove(&point);
+            }
+            Some(count) => {
+                faults.insert(
+                    point,
+                    FaultConfig {
+                        count: Some(count - 1),
+                        ..fault
+                    },
+                );
+            }
+            None => {}
+        }
+        tracing::warn!(?point, "Injecting fault");
+        Some(fault)
+    }
+}
+
+/// Some(config) if the fault armed on point must be injected now.
+#[inline]
+pub fn triggered(point: FaultPoint) -> Option<FaultConfig> {
+    #[cfg(feature = "fault-injection")]
+    {
+        armed::triggered(point)
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    {
+        let _ = point;
+        None
+    }
+}
+
+/// admin_* methods to arm the faults. Methods fail if the feature is not enabled.
+pub fn fault_injection_rpc_module() -> eyre::Result<RpcModule<()>> {
+    use jsonrpsee::types::ErrorObject;
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn disabled_error() -> ErrorObject<'static> {
+        ErrorObject::owned(
+            -32000,
+            "rbuilder built without the fault-injection feature",
+            None::<()>,
+        )
+    }
+
+    let mut module = RpcModule::new(());
+    module.register_method("admin_injectFault", |params, _| {
+        let (point, config): (FaultPoint, FaultConfig) = params.parse()?;
+        if !(0.0..=1.0).contains(&config.probability) {
+            return Err(ErrorObject::owned(
+                -32602,
+                "probability must be in [0, 1]",
+                None::<()>,
+            ));
+        }
+        #[cfg(feature = "fault-injection")]
+        {
+            armed::FAULTS.lock().insert(point, config);
+            tracing::warn!(?point, ?config, "Fault armed");
+            Ok(())
+        }
+        #[cfg(not(feature = "fault-injection"))]
+        {
+            let _ = (point, config);
+            Err(disabled_error())
+        }
+    })?;
+    module.register_method("admin_clearFault", |params, _| {
+        let point: FaultPoint = params.one()?;
+        #[cfg(feature = "fault-injection")]
+        {
+            Ok::<_, ErrorObject<'static>>(armed::FAULTS.lock().remove(&point).is_some())
+        }
+        #[cfg(not(feature = "fault-injection"))]
+        {
+            let _ = point;
+            Err::<bool, _>(disabled_error())
+        }
+    })?;
+    module.register_method("admin_faults", |_, _| {
+        #[cfg(feature = "fault-injection")]
+        {
+            Ok::<_, ErrorObject<'static>>(
+                armed::FAULTS
+                    .lock()
+                    .iter()
+                    .map(|(point, config)| (*point, *config))
+                    .collect::<Vec<_>>(),
+            )
+        }
+        #[cfg(not(feature = "fault-injection"))]
+        {
+            Err::<Vec<(FaultPoint, FaultConfig)>, _>(disabled_error())
+        }
+    })?;
+    Ok(module)
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_count() {
+        armed::FAULTS.lock().insert(
+            FaultPoint::CorruptSimulation,
+            FaultConfig {
+                probability: 1.0,
+                delay_ms: 0,
+                count: Some(2),
+            },
+        );
+        assert!(triggered(FaultPoint::CorruptSimulation).is_some());
+        assert!(triggered(FaultPoint::CorruptSimulation).is_some());
+        assert!(triggered(FaultPoint::CorruptSimulation).is_none());
+        assert!(triggered(FaultPoint::DropHeadUpdate).is_none());
+    }
+}
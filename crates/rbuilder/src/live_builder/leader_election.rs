@@ -0,0 +1,392 @@
+//! Leader election for active-passive HA deployments: several replicas build blocks but only the leader submits
+//! them to the relays.
+//! Leadership is a lease with a ttl kept in a shared [`LeaseBackend`]:
+//! - [`FileLeaseBackend`]: flock protected file, for replicas on the same host (or a shared fs with working locks).
+//! - [`RedisLeaseBackend`]: a redis key, for replicas on different hosts.
+//!
+//! The leader renews the lease every ttl/3. If it dies or can't reach the backend the lease expires and another replica
+//! takes over. A leader that can't renew stops submitting ttl/3 before its lease expires so two replicas never submit
+//! at the same time (the file backend assumes reasonably synced clocks).
+//!
+//! With [`super::warm_standby`] enabled replicas that are not the leader record their would-be submissions and
+//! acquiring/losing the lease promotes/demotes the replica.
+//!
+//! When leader election is not configured [`is_leader`] is always true.
+
+use super::warm_standby;
+use crate::telemetry::{inc_leadership_changes, set_is_leader};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Storage for the lease. Calls are blocking.
+pub trait LeaseBackend: Debug + Send + Sync {
+    /// Takes the lease for holder_id if it's free or expired, renews it if holder_id already has it.
+    /// Returns true if holder_id holds the lease for the next ttl.
+    fn try_acquire(&self, holder_id: &str, ttl: Duration) -> eyre::Result<bool>;
+    /// Frees the lease if holder_id has it.
+    fn release(&self, holder_id: &str) -> eyre::Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileLease {
+    holder_id: String,
+    /// unix ms
+    expires_at: u64,
+}
+
+#[derive(Debug)]
+pub struct FileLeaseBackend {
+    path: PathBuf,
+}
+
+impl FileLeaseBackend {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The lock is released when the file is dropped (or if the process dies).
+    fn open_locked(&self) -> eyre::Result<File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        // SAFETY: fd is valid while file is alive.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(file)
+    }
+
+    fn read_lease(file: &mut File) -> eyre::Result<Option<FileLease>> {
+        let mut content = String::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_string(&mut content)?;
+        if content.trim().is_empty() {
+            return Ok(None);
+        }
+        match serde_json::from_str(&content) {
+            Ok(lease) => Ok(Some(lease)),
+            Err(err) => {
+                warn!(?err, "Invalid lease file, overwriting it");
+                Ok(None)
+            }
+        }
+    }
+
+    fn write_lease(file: &mut File, lease: Option<&FileLease>) -> eyre::Result<()> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        if let Some(lease) = lease {
+            file.write_all(&serde_json::to_vec(lease)?)?;
+        }
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn try_acquire_at(&self, holder_id: &str, ttl: Duration, now_ms: u64) -> eyre::Result<bool> {
+        let mut file = self.open_locked()?;
+        if let Some(lease) = Self::read_lease(&mut file)? {
+            if lease.holder_id != holder_id && lease.expires_at > now_ms {
+                return Ok(false);
+            }
+        }
+        Self::write_lease(
+            &mut file,
+            Some(&FileLease {
+                holder_id: holder_id.to_string(),
+                expires_at: now_ms + ttl.as_millis() as u64,
+            }),
+        )?;
+        Ok(true)
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl LeaseBackend for FileLeaseBackend {
+    fn try_acquire(&self, holder_id: &str, ttl: Duration) -> eyre::Result<bool> {
+        self.try_acquire_at(holder_id, ttl, unix_ms())
+    }
+
+    fn release(&self, holder_id: &str) -> eyre::Result<()> {
+        let mut file = self.open_locked()?;
+        if let Some(lease) = Self::read_lease(&mut file)? {
+            if lease.holder_id == holder_id {
+                Self::write_lease(&mut file, None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets the key to holder_id if it's free or already ours.
+const REDIS_ACQUIRE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current == false or current == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+const REDIS_RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Lease stored on a redis key (value = holder id) with a PX expiration.
+#[derive(Debug)]
+pub struct RedisLeaseBackend {
+    client: redis::Client,
+    key: String,
+    timeout: Duration,
+}
+
+impl RedisLeaseBackend {
+    /// timeout applies to connecting and to every command.
+    pub fn new(url: &str, key: String, timeout: Duration) -> eyre::Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            key,
+            timeout,
+        })
+    }
+
+    fn connection(&self) -> eyre::Result<redis::Connection> {
+        let connection = self.client.get_connection_with_timeout(self.timeout)?;
+        connection.set_read_timeout(Some(self.timeout))?;
+        connection.set_write_timeout(Some(self.timeout))?;
+        Ok(connection)
+    }
+}
+
+impl LeaseBackend for RedisLeaseBackend {
+    fn try_acquire(&self, holder_id: &str, ttl: Duration) -> eyre::Result<bool> {
+        let mut connection = self.connection()?;
+        let acquired: i64 = redis::Script::new(REDIS_ACQUIRE_SCRIPT)
+            .key(&self.key)
+            .arg(holder_id)
+            .arg(ttl.as_millis() as u64)
+            .invoke(&mut connection)?;
+        Ok(acquired == 1)
+    }
+
+    fn release(&self, holder_id: &str) -> eyre::Result<()> {
+        let mut connection = self.connection()?;
+        let _: i64 = redis::Script::new(REDIS_RELEASE_SCRIPT)
+            .key(&self.key)
+            .arg(holder_id)
+            .invoke(&mut connection)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeaseBackendConfig {
+    File(PathBuf),
+    Redis { url: String, key: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderElectionConfig {
+    pub backend: LeaseBackendConfig,
+    /// Must be unique among the replicas.
+    pub replica_id: String,
+    pub lease_ttl: Duration,
+}
+
+impl LeaderElectionConfig {
+    fn renew_interval(&self) -> Duration {
+        self.lease_ttl / 3
+    }
+
+    fn create_backend(&self) -> eyre::Result<Arc<dyn LeaseBackend>> {
+        Ok(match &self.backend {
+            LeaseBackendConfig::File(path) => Arc::new(FileLeaseBackend::new(path.clone())),
+            LeaseBackendConfig::Redis { url, key } => Arc::new(RedisLeaseBackend::new(
+                url,
+                key.clone(),
+                self.renew_interval(),
+            )?),
+        })
+    }
+}
+
+/// Until when (ms since `base`) we can submit.
+#[derive(Debug)]
+struct Leadership {
+    base: Instant,
+    deadline_ms: AtomicU64,
+}
+
+impl Leadership {
+    fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            deadline_ms: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    fn is_leader_at(&self, now: Instant) -> bool {
+        (now.saturating_duration_since(self.base).as_millis() as u64)
+            < self.deadline_ms.load(Ordering::Relaxed)
+    }
+
+    fn set_deadline(&self, deadline: Instant) {
+        self.deadline_ms.store(
+            deadline.saturating_duration_since(self.base).as_millis() as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    fn step_down(&self) {
+        self.deadline_ms.store(0, Ordering::Relaxed);
+    }
+}
+
+lazy_static! {
+    static ref LEADERSHIP: Leadership = Leadership::new();
+}
+
+/// true if this replica should submit to the relays.
+pub fn is_leader() -> bool {
+    LEADERSHIP.is_leader_at(Instant::now())
+}
+
+/// Competes for the lease until global_cancel, then releases it.
+pub fn spawn_leader_election(
+    config: LeaderElectionConfig,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    if config.renew_interval().is_zero() {
+        eyre::bail!("Leader election lease ttl too small");
+    }
+    let backend = config.create_backend()?;
+    LEADERSHIP.step_down();
+    set_is_leader(false);
+    info!(
+        replica_id = config.replica_id,
+        backend = ?config.backend,
+        "Leader election started"
+    );
+    Ok(tokio::spawn(async move {
+        let renew_interval = config.renew_interval();
+        let mut was_leader = false;
+        loop {
+            let attempt_start = Instant::now();
+            let res = {
+                let backend = backend.clone();
+                let replica_id = config.replica_id.clone();
+                let ttl = config.lease_ttl;
+                tokio::task::spawn_blocking(move || backend.try_acquire(&replica_id, ttl)).await
+            };
+            match res {
+                Ok(Ok(true)) => {
+                    LEADERSHIP.set_deadline(attempt_start + config.lease_ttl - renew_interval)
+                }
+                Ok(Ok(false)) => LEADERSHIP.step_down(),
+                // We keep the current deadline, if we don't manage to renew before it we stop submitting.
+                Ok(Err(err)) => warn!(?err, "Failed to renew leader lease"),
+                Err(err) => warn!(?err, "Leader lease renew task failed"),
+            }
+            let is_leader = LEADERSHIP.is_leader_at(Instant::now());
+            if is_leader != was_leader {
+                if is_leader {
+                    info!(replica_id = config.replica_id, "Became leader");
+                    inc_leadership_changes("acquired");
+                    warm_standby::promote();
+                } else {
+                    warn!(replica_id = config.replica_id, "Lost leadership");
+                    inc_leadership_changes("lost");
+                    warm_standby::demote();
+                }
+                was_leader = is_leader;
+            }
+            set_is_leader(is_leader);
+
+            tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                _ = tokio::time::sleep(renew_interval) => {},
+            }
+        }
+
+        LEADERSHIP.step_down();
+        set_is_leader(false);
+        if was_leader {
+            inc_leadership_changes("lost");
+            let replica_id = config.replica_id.clone();
+            match tokio::task::spawn_blocking(move || backend.release(&replica_id)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => warn!(?err, "Failed to release leader lease"),
+                Err(err) => warn!(?err, "Leader lease release task failed"),
+            }
+        }
+        info!("Leader election finished");
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_millis(3000);
+
+    #[test]
+    fn test_file_lease() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = FileLeaseBackend::new(dir.path().join("leader.lock"));
+        let now = 1_000_000;
+
+        assert!(backend.try_acquire_at("a", TTL, now).unwrap());
+        assert!(!backend.try_acquire_at("b", TTL, now + 1000).unwrap());
+        // renew
+        assert!(backend.try_acquire_at("a", TTL, now + 2000).unwrap());
+        assert!(!backend.try_acquire_at("b", TTL, now + 4000).unwrap());
+        // a died, lease expired
+        assert!(backend.try_acquire_at("b", TTL, now + 5001).unwrap());
+        assert!(!backend.try_acquire_at("a", TTL, now + 5002).unwrap());
+
+        // release only works for the holder
+        backend.release("a").unwrap();
+        assert!(!backend.try_acquire_at("a", TTL, now + 5003).unwrap());
+        backend.release("b").unwrap();
+        assert!(backend.try_acquire_at("a", TTL, now + 5004).unwrap());
+    }
+
+    #[test]
+    fn test_leadership_deadline() {
+        let leadership = Leadership::new();
+        let now = Instant::now();
+        assert!(leadership.is_leader_at(now));
+        leadership.step_down();
+        assert!(!leadership.is_leader_at(now));
+        leadership.set_deadline(now + TTL);
+        assert!(leadership.is_leader_at(now));
+        assert!(!leadership.is_leader_at(now + TTL));
+    }
+}
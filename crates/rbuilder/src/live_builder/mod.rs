@@ -1,38 +1,87 @@
+pub mod admin_rpc;
+pub mod archive;
 pub mod base_config;
 pub mod block_output;
 pub mod building;
 pub mod cli;
 pub mod config;
+pub mod fault_injection;
+pub mod leader_election;
+pub mod node_health;
 pub mod order_input;
 pub mod payload_events;
+pub mod proposer_overrides;
+pub mod signer_reputation;
 pub mod simulation;
+pub mod slot_outcome_predictor;
+pub mod slot_resource_report;
+pub mod state_access_heatmap;
+pub mod warm_standby;
 pub mod watchdog;
 
 use crate::{
     building::{
-        builders::{BlockBuildingAlgorithm, UnfinishedBlockBuildingSinkFactory},
-        BlockBuildingContext,
+        block_template::block_template_rpc_module,
+        builders::{
+            algorithm_params::{algorithm_params_rpc_module, init_algorithm_params},
+            BlockBuildingAlgorithm, UnfinishedBlockBuildingSinkFactory,
+        },
+        bytecode_cache::init_bytecode_cache,
+        conflict_cache::ConflictCache,
+        exposure_budget::ExposureBudget,
+        extra_data::ExtraDataTemplate,
+        gas_price_oracle::GasPriceOracle,
+        sim_queue::SimQueueWeights,
+        victim_protection::VictimProtection,
+        BlockBuildingContext, RefundSettlementMode,
     },
     live_builder::{
+        admin_rpc::start_admin_rpc_server,
+        block_output::{
+            block_inclusion_observer::BlockInclusionObservers,
+            builder_identity::builder_identities_rpc_module,
+            inclusion_notifier::spawn_inclusion_notifier,
+            refund_settlement::{spawn_refund_settlement, RefundSettlementConfig},
+        },
+        fault_injection::fault_injection_rpc_module,
+        leader_election::{spawn_leader_election, LeaderElectionConfig},
+        node_health::{spawn_node_health_monitor, NodeHealthConfig},
         order_input::{start_orderpool_jobs, OrderInputConfig},
+        signer_reputation::{signer_reputation_rpc_module, spawn_signer_reputation_store},
         simulation::OrderSimulationPool,
+        slot_outcome_predictor::{
+            init_slot_outcome_predictor, record_slot, should_reduce_effort,
+            SlotOutcomePredictorConfig,
+        },
+        slot_resource_report::spawn_slot_resource_report_writer,
+        state_access_heatmap::{init_state_access_heatmap, state_access_heatmap_rpc_module},
+        warm_standby::{init_warm_standby, warm_standby_rpc_module},
         watchdog::spawn_watchdog_thread,
     },
-    telemetry::inc_active_slots,
-    utils::{error_storage::spawn_error_storage_writer, Signer},
+    primitives::Order,
+    telemetry::{inc_active_slots, order_drops_rpc_module},
+    utils::{
+        clock::{Clock, ClockRef},
+        error_storage::spawn_error_storage_writer,
+        Signer,
+    },
 };
 use ahash::HashSet;
 use alloy_primitives::{Address, B256};
-use building::BlockBuildingPool;
+use building::{
+    late_order_fast_path::LateOrderFastPathConfig, sim_bundle::SlotBundleSimulators,
+    BlockBuildingPool,
+};
 use eyre::Context;
 use jsonrpsee::RpcModule;
 use order_input::ReplaceableOrderPoolCommand;
+use parking_lot::Mutex;
 use payload_events::MevBoostSlotData;
 use reth::{primitives::Header, providers::HeaderProvider};
 use reth_chainspec::ChainSpec;
 use reth_db::Database;
 use reth_provider::{BlockReader, DatabaseProviderFactory, StateProviderFactory};
-use std::{cmp::min, fmt::Debug, path::PathBuf, sync::Arc, time::Duration};
+use std::{cmp::min, fmt::Debug, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use time::OffsetDateTime;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
@@ -89,21 +138,64 @@ where
 {
     pub watchdog_timeout: Option<Duration>,
     pub error_storage_path: Option<PathBuf>,
+    /// If set, per slot resource reports are persisted in this sqlite db.
+    pub slot_resource_report_db_path: Option<PathBuf>,
+    /// If set, signer reputations are tracked and persisted here.
+    pub signer_reputation_db_path: Option<PathBuf>,
+    /// Where live tuned algorithm params are persisted.
+    pub algorithm_params_path: Option<PathBuf>,
+    /// If set, the admin rpc server is started on this address.
+    pub admin_rpc_server_address: Option<SocketAddr>,
+    /// If set, we only submit to the relays while we hold the leader lease.
+    pub leader_election: Option<LeaderElectionConfig>,
+    /// If set, submissions are paused while the node head is behind (see [`node_health`]).
+    pub node_health: Option<NodeHealthConfig>,
+    /// If set, we start in warm standby and only submit once promoted (see [`warm_standby`]).
+    pub warm_standby: bool,
+    /// If set, slots we are very unlikely to win are built with reduced effort.
+    pub slot_outcome_predictor: Option<SlotOutcomePredictorConfig>,
+    /// If set, state accesses of simulated orders are aggregated (see [`state_access_heatmap`]).
+    pub state_access_heatmap: bool,
+    /// See [`crate::building::bytecode_cache`].
+    pub bytecode_cache_size: usize,
+    pub gas_price_oracle: GasPriceOracle,
+    /// If set, mev-share refunds are deferred and paid by periodic settlements.
+    pub refund_settlement: Option<RefundSettlementConfig>,
+    /// See [`crate::building::victim_protection`].
+    pub victim_protection: Option<VictimProtection>,
+    /// Time source for the slot timings, wall clock except on tests.
+    pub clock: ClockRef,
     pub simulation_threads: usize,
+    /// Threads that move between simulation and building depending on demand.
+    pub shared_worker_threads: usize,
+    /// See [`simulation::tenant_sandbox`].
+    pub tenant_cgroup_root: Option<PathBuf>,
+    /// See [`crate::building::sim_queue`].
+    pub sim_queue_weights: SimQueueWeights,
     pub order_input_config: OrderInputConfig,
     pub blocks_source: BlocksSourceType,
     pub run_sparse_trie_prefetcher: bool,
+    /// If set, late orders are injected on the best block (see [`building::late_order_fast_path`]).
+    pub late_order_fast_path: Option<LateOrderFastPathConfig>,
+    /// If set, mev_simBundle is served for the slots being built (see [`building::sim_bundle`]), must be the one the
+    /// order_input_config SimBundleService uses.
+    pub sim_bundle: Option<Arc<SlotBundleSimulators>>,
+    /// If set, building sessions pull orderpool deltas at this interval (see [`order_input::orderpool_delta`]).
+    pub orderpool_delta_interval: Option<Duration>,
 
     pub chain_chain_spec: Arc<ChainSpec>,
     pub provider: P,
 
     pub coinbase_signer: Signer,
-    pub extra_data: Vec<u8>,
+    pub extra_data: ExtraDataTemplate,
     pub blocklist: HashSet<Address>,
 
     pub global_cancellation: CancellationToken,
 
     pub sink_factory: Box<dyn UnfinishedBlockBuildingSinkFactory>,
+    /// Shared with the sink_factory, the exposure budget, inclusion notifier and refund settlement are registered here
+    /// on run.
+    pub block_inclusion_observers: BlockInclusionObservers,
     pub builders: Vec<Arc<dyn BlockBuildingAlgorithm<P, DB>>>,
     pub extra_rpc: RpcModule<()>,
 
@@ -130,6 +222,16 @@ where
         Self { builders, ..self }
     }
 
+    pub fn with_block_inclusion_observers(
+        self,
+        block_inclusion_observers: BlockInclusionObservers,
+    ) -> Self {
+        Self {
+            block_inclusion_observers,
+            ..self
+        }
+    }
+
     pub async fn run(self) -> eyre::Result<()> {
         // We keep the last block to avoid going back in time since we are now very robust about reorgs or this kind of behavior.
         let mut last_processed_block: Option<u64> = None;
@@ -145,10 +247,121 @@ where
                 .await
                 .with_context(|| "Error spawning error storage writer")?;
         }
+        if let Some(slot_resource_report_db_path) = self.slot_resource_report_db_path {
+            spawn_slot_resource_report_writer(
+                slot_resource_report_db_path,
+                self.global_cancellation.clone(),
+            )
+            .await
+            .with_context(|| "Error spawning slot resource report writer")?;
+        }
 
         let mut inner_jobs_handles = Vec::new();
+
+        let mut admin_rpc = RpcModule::new(());
+        admin_rpc.merge(builder_identities_rpc_module()?)?;
+        admin_rpc.merge(block_template_rpc_module()?)?;
+        init_algorithm_params(self.algorithm_params_path)
+            .with_context(|| "Error loading algorithm params")?;
+        admin_rpc.merge(algorithm_params_rpc_module()?)?;
+        admin_rpc.merge(fault_injection_rpc_module()?)?;
+        if self.warm_standby {
+            init_warm_standby();
+        }
+        admin_rpc.merge(warm_standby_rpc_module()?)?;
+        admin_rpc.merge(order_drops_rpc_module()?)?;
+        if let Some(signer_reputation_db_path) = self.signer_reputation_db_path {
+            let store = spawn_signer_reputation_store(
+                signer_reputation_db_path,
+                self.global_cancellation.clone(),
+            )
+            .await
+            .with_context(|| "Error spawning signer reputation store")?;
+            admin_rpc.merge(signer_reputation_rpc_module(store)?)?;
+        }
+        init_bytecode_cache(self.bytecode_cache_size);
+        if self.state_access_heatmap {
+            init_state_access_heatmap();
+            admin_rpc.merge(state_access_heatmap_rpc_module()?)?;
+        }
+        let refund_settlement_mode = match self.refund_settlement {
+            Some(refund_settlement) => {
+                let (refund_settlement_rpc, refund_settler) = spawn_refund_settlement(
+                    refund_settlement,
+                    self.provider.clone(),
+                    self.coinbase_signer.clone(),
+                    self.chain_chain_spec.clone(),
+                    self.gas_price_oracle,
+                    self.global_cancellation.clone(),
+                )
+                .with_context(|| "Error spawning refund settlement")?;
+                admin_rpc.merge(refund_settlement_rpc)?;
+                self.block_inclusion_observers.register(refund_settler);
+                RefundSettlementMode::Deferred
+            }
+            None => RefundSettlementMode::InBlock,
+        };
+        if let Some(admin_rpc_server_address) = self.admin_rpc_server_address {
+            inner_jobs_handles.push(
+                start_admin_rpc_server(
+                    admin_rpc_server_address,
+                    admin_rpc,
+                    self.global_cancellation.clone(),
+                )
+                .await
+                .with_context(|| "Error starting admin rpc server")?,
+            );
+        }
+
+        if let Some(slot_outcome_predictor) = self.slot_outcome_predictor {
+            init_slot_outcome_predictor(slot_outcome_predictor);
+        }
+
+        if let Some(leader_election) = self.leader_election {
+            inner_jobs_handles.push(
+                spawn_leader_election(leader_election, self.global_cancellation.clone())
+                    .with_context(|| "Error spawning leader election")?,
+            );
+        }
+
+        if let Some(node_health) = self.node_health {
+            inner_jobs_handles.push(
+                spawn_node_health_monitor(
+                    node_health,
+                    self.provider.clone(),
+                    self.clock.clone(),
+                    self.global_cancellation.clone(),
+                )
+                .with_context(|| "Error spawning node health monitor")?,
+            );
+        }
+
+        let exposure_budget = if self.order_input_config.tenants.has_exposure_budgets() {
+            let tenants = self.order_input_config.tenants.clone();
+            let exposure_budget = Arc::new(Mutex::new(ExposureBudget::new(Box::new(
+                move |order: &Order| tenants.max_block_exposures(order),
+            ))));
+            self.block_inclusion_observers
+                .register(exposure_budget.clone());
+            Some(exposure_budget)
+        } else {
+            None
+        };
+
+        if self.order_input_config.tenants.has_inclusion_webhooks() {
+            self.block_inclusion_observers.register(
+                spawn_inclusion_notifier(
+                    self.order_input_config.tenants.clone(),
+                    self.global_cancellation.clone(),
+                )
+                .with_context(|| "Error spawning inclusion notifier")?,
+            );
+        }
+
         let mut payload_events_channel = self.blocks_source.recv_slot_channel();
 
+        // shared by all the slots, cleaned on every new head
+        let conflict_cache = ConflictCache::default();
         let orderpool_subscriber = {
             let (handle, sub) = start_orderpool_jobs(
                 self.order_input_config,
@@ -157,6 +370,7 @@ where
                 self.global_cancellation.clone(),
                 self.orderpool_sender,
                 self.orderpool_receiver,
+                conflict_cache.clone(),
             )
             .await?;
             inner_jobs_handles.push(handle);
@@ -167,6 +381,13 @@ where
             OrderSimulationPool::new(
                 self.provider.clone(),
                 self.simulation_threads,
+                self.shared_worker_threads,
+                self.global_cancellation.clone(),
+            )
+            .with_sim_queue_weights(self.sim_queue_weights)
+            .with_tenant_sandboxes(
+                self.order_input_config.tenants.clone(),
+                self.tenant_cgroup_root.as_deref(),
                 self.global_cancellation.clone(),
             )
         };
@@ -178,7 +399,10 @@ where
             orderpool_subscriber,
             order_simulation_pool,
             self.run_sparse_trie_prefetcher,
-        );
+            self.late_order_fast_path,
+            self.sim_bundle,
+        )
+        .with_orderpool_delta_interval(self.orderpool_delta_interval);
 
         let watchdog_sender = match self.watchdog_timeout {
             Some(duration) => Some(spawn_watchdog_thread(
@@ -206,7 +430,7 @@ where
             }) {
                 continue;
             }
-            let current_time = OffsetDateTime::now_utc();
+            let current_time = self.clock.now_utc();
             // see if we can get parent header in a reasonable time
             let time_to_slot = payload.timestamp() - current_time;
             debug!(
@@ -231,7 +455,14 @@ where
                 // @Nicer
                 let parent_block = payload.parent_block_hash();
                 let timestamp = payload.timestamp();
-                match wait_for_block_header(parent_block, timestamp, &self.provider, &timings).await
+                match wait_for_block_header(
+                    parent_block,
+                    timestamp,
+                    &self.provider,
+                    &timings,
+                    self.clock.as_ref(),
+                )
+                .await
                 {
                     Ok(header) => header,
                     Err(err) => {
@@ -250,16 +481,27 @@ where
             inc_active_slots();
             last_processed_block = Some(payload.block());
 
-            if let Some(block_ctx) = BlockBuildingContext::from_attributes(
+            if let Some(mut block_ctx) = BlockBuildingContext::from_attributes(
                 payload.payload_attributes_event.clone(),
                 &parent_header,
                 self.coinbase_signer.clone(),
                 self.chain_chain_spec.clone(),
                 self.blocklist.clone(),
                 Some(payload.suggested_gas_limit),
-                self.extra_data.clone(),
+                self.extra_data.render(payload.slot()),
                 None,
             ) {
+                block_ctx.max_blob_count = payload.slot_data.preferences.max_blob_count;
+                block_ctx.reduced_effort = should_reduce_effort(&payload);
+                record_slot(&payload, block_ctx.reduced_effort);
+                block_ctx.gas_price_oracle = self.gas_price_oracle;
+                block_ctx.refund_settlement = refund_settlement_mode;
+                block_ctx.victim_protection = self.victim_protection;
+                block_ctx.clock = self.clock.clone();
+                block_ctx.conflict_cache = conflict_cache.clone();
+                block_ctx.exhausted_exposures = exposure_budget
+                    .as_ref()
+                    .map(|exposure_budget| exposure_budget.lock().exhausted_orders());
                 builder_pool.start_block_building(
                     payload,
                     block_ctx,
@@ -302,23 +544,21 @@ async fn wait_for_block_header<P>(
     slot_time: OffsetDateTime,
     provider: P,
     timings: &TimingsConfig,
+    clock: &dyn Clock,
 ) -> eyre::Result<Header>
 where
     P: HeaderProvider,
 {
     let deadline = slot_time + timings.block_header_deadline_delta;
-    while OffsetDateTime::now_utc() < deadline {
+    while clock.now_utc() < deadline {
         if let Some(header) = provider.header(&block)? {
             return Ok(header);
         } else {
-            let time_to_sleep = min(
-                deadline - OffsetDateTime::now_utc(),
-                timings.get_block_header_period,
-            );
+            let time_to_sleep = min(deadline - clock.now_utc(), timings.get_block_header_period);
             if time_to_sleep.is_negative() {
                 break;
             }
-            tokio::time::sleep(time_to_sleep.try_into().unwrap()).await;
+            clock.sleep(time_to_sleep.try_into().unwrap()).await;
         }
     }
     Err(eyre::eyre!("Block header not found"))
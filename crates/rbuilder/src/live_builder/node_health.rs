@@ -0,0 +1,210 @@
+//! Degraded mode for when the local node falls behind the chain (syncing, stalled...).
+//! Blocks built on a stale head can't win (and relays reject them) so while the head block of the node lags the head
+//! the consensus clock expects by more than [`NodeHealthConfig::max_head_lag`] we stop submitting. Everything else
+//! (order intake, simulation, building) keeps running so we are ready as soon as the node catches up. Submissions
+//! resume automatically once the lag is below [`NodeHealthConfig::resume_head_lag`], the gap between both avoids
+//! flapping on a slow block.
+//!
+//! The expected head is the block of the last slot whose block should already be on the node
+//! ([`HEAD_ARRIVAL_DEADLINE`] after the slot starts), the lag is the time of the slots between the node head and it.
+//! So the lag doesn't grow while we wait for the block of the current slot and every missed slot counts as a full
+//! slot (empty slots look like a lag too, a few in a row are rare).
+//!
+//! When not configured [`is_degraded`] is always false.
+
+use crate::{
+    telemetry::{inc_node_degraded_changes, set_node_degraded},
+    utils::clock::ClockRef,
+};
+use reth::providers::HeaderProvider;
+use reth_provider::StateProviderFactory;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+pub const DEFAULT_SLOT_DURATION: Duration = Duration::from_secs(12);
+/// The block of a slot should be on the node by the attestation deadline.
+pub const HEAD_ARRIVAL_DEADLINE: Duration = Duration::from_secs(4);
+
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// true if submissions should be paused because the node is behind.
+pub fn is_degraded() -> bool {
+    DEGRADED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeHealthConfig {
+    /// Enter degraded mode when the head block is older than this.
+    pub max_head_lag: Duration,
+    /// Leave degraded mode when the head block is younger than this (<= max_head_lag).
+    pub resume_head_lag: Duration,
+    pub check_interval: Duration,
+    /// Blocks are one slot apart.
+    pub slot_duration: Duration,
+}
+
+impl NodeHealthConfig {
+    /// Resumes at half max_head_lag.
+    pub fn new(max_head_lag: Duration) -> Self {
+        Self {
+            max_head_lag,
+            resume_head_lag: max_head_lag / 2,
+            check_interval: Duration::from_secs(1),
+            slot_duration: DEFAULT_SLOT_DURATION,
+        }
+    }
+
+    fn next_degraded(&self, degraded: bool, head_lag: Duration) -> bool {
+        if degraded {
+            head_lag >= self.resume_head_lag
+        } else {
+            head_lag > self.max_head_lag
+        }
+    }
+}
+
+/// Time of the slots between a node head with head_timestamp and the head expected at now (see module doc).
+fn slots_lag(head_timestamp: u64, now: OffsetDateTime, slot_duration: Duration) -> Duration {
+    let Ok(head_time) = OffsetDateTime::from_unix_timestamp(head_timestamp as i64) else {
+        return Duration::MAX;
+    };
+    let since_head = Duration::try_from(now - head_time).unwrap_or_default();
+    if slot_duration.is_zero() {
+        return since_head;
+    }
+    let missed_slots = since_head
+        .saturating_sub(HEAD_ARRIVAL_DEADLINE)
+        .as_secs_f64()
+        / slot_duration.as_secs_f64();
+    slot_duration * missed_slots.floor() as u32
+}
+
+/// Lag of the head block of the node (see [`slots_lag`]). None if we can't read it (treated as behind).
+fn head_lag<P>(provider: &P, now: OffsetDateTime, slot_duration: Duration) -> Option<Duration>
+where
+    P: StateProviderFactory + HeaderProvider,
+{
+    let head = match provider
+        .last_block_number()
+        .and_then(|number| provider.header_by_number(number))
+    {
+        Ok(Some(head)) => head,
+        Ok(None) => return None,
+        Err(err) => {
+            error!(?err, "Failed to read node head");
+            return None;
+        }
+    };
+    Some(slots_lag(head.timestamp, now, slot_duration))
+}
+
+/// Checks the node head every check_interval until global_cancel.
+pub fn spawn_node_health_monitor<P>(
+    config: NodeHealthConfig,
+    provider: P,
+    clock: ClockRef,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>>
+where
+    P: StateProviderFactory + HeaderProvider + Clone + 'static,
+{
+    if config.resume_head_lag > config.max_head_lag {
+        eyre::bail!("Node resume head lag must be <= max head lag");
+    }
+    info!(
+        max_head_lag = ?config.max_head_lag,
+        resume_head_lag = ?config.resume_head_lag,
+        "Node health monitor started"
+    );
+    Ok(tokio::spawn(async move {
+        loop {
+            let res = {
+                let provider = provider.clone();
+                let now = clock.now_utc();
+                tokio::task::spawn_blocking(move || head_lag(&provider, now, config.slot_duration))
+                    .await
+            };
+            let lag = match res {
+                Ok(lag) => lag,
+                Err(err) => {
+                    warn!(?err, "Node head check task failed");
+                    None
+                }
+            };
+            let was_degraded = is_degraded();
+            let degraded = config.next_degraded(was_degraded, lag.unwrap_or(Duration::MAX));
+            if degraded != was_degraded {
+                DEGRADED.store(degraded, Ordering::Relaxed);
+                if degraded {
+                    error!(
+                        head_lag = ?lag,
+                        "Node is behind, entering degraded mode: submissions paused"
+                    );
+                    inc_node_degraded_changes("entered");
+                } else {
+                    info!(
+                        head_lag = ?lag,
+                        "Node caught up, leaving degraded mode: submissions resumed"
+                    );
+                    inc_node_degraded_changes("exited");
+                }
+            }
+            set_node_degraded(degraded, lag.unwrap_or_default());
+
+            tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                _ = tokio::time::sleep(config.check_interval) => {},
+            }
+        }
+        DEGRADED.store(false, Ordering::Relaxed);
+        info!("Node health monitor finished");
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degraded_hysteresis() {
+        let config = NodeHealthConfig::new(Duration::from_secs(36));
+        let secs = Duration::from_secs;
+        assert!(!config.next_degraded(false, secs(12)));
+        assert!(!config.next_degraded(false, secs(36)));
+        assert!(config.next_degraded(false, secs(37)));
+        // still catching up
+        assert!(config.next_degraded(true, secs(30)));
+        assert!(config.next_degraded(true, secs(18)));
+        assert!(!config.next_degraded(true, secs(5)));
+        // unreadable head
+        assert!(config.next_degraded(false, Duration::MAX));
+    }
+
+    #[test]
+    fn test_slots_lag() {
+        let slot = DEFAULT_SLOT_DURATION;
+        let head_timestamp = 1_700_000_000;
+        let at = |secs_after_head: u64| {
+            OffsetDateTime::from_unix_timestamp((head_timestamp + secs_after_head) as i64).unwrap()
+        };
+        // head is the block of the current slot
+        assert_eq!(slots_lag(head_timestamp, at(0), slot), Duration::ZERO);
+        assert_eq!(slots_lag(head_timestamp, at(11), slot), Duration::ZERO);
+        // next block not due yet
+        assert_eq!(slots_lag(head_timestamp, at(15), slot), Duration::ZERO);
+        assert_eq!(slots_lag(head_timestamp, at(16), slot), slot);
+        assert_eq!(slots_lag(head_timestamp, at(27), slot), slot);
+        assert_eq!(slots_lag(head_timestamp, at(28), slot), slot * 2);
+        // head from the future (clock skew)
+        assert_eq!(slots_lag(head_timestamp + 5, at(0), slot), Duration::ZERO);
+        // 3 missed slots: degraded, even if the head is only ~40s old
+        let config = NodeHealthConfig::new(slot * 2);
+        assert!(config.next_degraded(false, slots_lag(head_timestamp, at(40), slot)));
+    }
+}
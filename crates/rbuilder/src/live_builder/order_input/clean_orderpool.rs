@@ -1,6 +1,10 @@
 use super::OrderInputConfig;
 use crate::{
-    live_builder::order_input::orderpool::OrderPool,
+    building::conflict_cache::ConflictCache,
+    live_builder::{
+        fault_injection::{self, FaultPoint},
+        order_input::orderpool::OrderPool,
+    },
     telemetry::{set_current_block, set_ordepool_count},
 };
 use alloy_provider::{IpcConnect, Provider, ProviderBuilder};
@@ -12,12 +16,14 @@ use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
-/// Performs maintenance operations on every new header by calling OrderPool::head_updated.
+/// Performs maintenance operations on every new header by calling OrderPool::head_updated and
+/// ConflictCache::head_updated.
 /// Also calls some functions to generate metrics.
 pub async fn spawn_clean_orderpool_job<P>(
     config: OrderInputConfig,
     provider_factory: P,
     orderpool: Arc<Mutex<OrderPool>>,
+    conflict_cache: ConflictCache,
     global_cancellation: CancellationToken,
 ) -> eyre::Result<JoinHandle<()>>
 where
@@ -43,7 +49,11 @@ where
 
         while let Some(block) = new_block_stream.next().await {
             let block_number = block.header.number;
+            if fault_injection::triggered(FaultPoint::DropHeadUpdate).is_some() {
+                continue;
+            }
             set_current_block(block_number);
+            conflict_cache.head_updated(block_number);
             let state = match provider_factory.latest() {
                 Ok(state) => state,
                 Err(err) => {
@@ -56,7 +66,11 @@ where
             let mut orderpool = orderpool.lock();
             let start = Instant::now();
 
-            orderpool.head_updated(block_number, &state);
+            orderpool.head_updated(
+                block_number,
+                block.header.base_fee_per_gas.unwrap_or_default(),
+                &state,
+            );
 
             let update_time = start.elapsed();
             let (tx_count, bundle_count) = orderpool.content_count();
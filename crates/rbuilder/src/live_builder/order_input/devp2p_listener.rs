@@ -0,0 +1,440 @@
+//! Mempool txs straight from the devp2p tx gossip, without depending on the el node subscription.
+//! We run a reth network (discovery + eth protocol) of our own with no chain behind it: peers broadcast us full txs
+//! (Transactions) and announce hashes (NewPooledTransactionHashes) that we fetch with GetPooledTransactions. We never
+//! serve txs nor propagate them.
+//! Txs go through the same dedup as the other mempool sources ([`DEVP2P_SOURCE_NAME`] on metrics), a tx the el node
+//! already gave us is neither requested nor sent again.
+//! Having no chain, our status claims the Paris block as head. After the merge forks are activated by timestamp so the
+//! fork id peers check is still right.
+//! reth hands us the network events on an unbounded channel, we move them right away to a bounded one and drop what
+//! doesn't fit (as we do with the GetPooledTransactions requests past MAX_INFLIGHT_POOLED_REQUESTS) so a flood of
+//! gossip can't grow our memory: dropped hashes are forgotten and will be fetched from the next source announcing them.
+//! Only built with the devp2p feature.
+
+use super::{
+    txpool_fetcher::{
+        is_first_announcement, send_mempool_tx, Devp2pListenerConfig, SharedMempoolDedup,
+    },
+    OrderInputConfig, ReplaceableOrderPoolCommand,
+};
+use crate::{primitives::TransactionSignedEcRecoveredWithBlobs, telemetry::inc_devp2p_dropped};
+use alloy_primitives::TxHash;
+use reth_chainspec::{ChainSpec, Head};
+use reth_eth_wire_types::{GetPooledTransactions, PooledTransactions};
+use reth_network::{
+    config::rng_secret_key, message::PeerRequest, transactions::NetworkTransactionEvent,
+    NetworkConfig, NetworkManager, PeersConfig,
+};
+use reth_network_peers::NodeRecord;
+use secp256k1::SecretKey;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, trace, warn};
+
+/// Name of the devp2p source on metrics.
+pub const DEVP2P_SOURCE_NAME: &str = "devp2p";
+/// Not 30303 so we can run next to the node.
+pub const DEFAULT_DEVP2P_PORT: u16 = 30304;
+pub const DEFAULT_DEVP2P_MAX_PEERS: usize = 50;
+/// Soft limit of hashes per GetPooledTransactions request.
+const MAX_HASHES_PER_REQUEST: usize = 256;
+/// Network events waiting to be processed.
+const EVENTS_BUFFER_SIZE: usize = 10_000;
+const MAX_INFLIGHT_POOLED_REQUESTS: usize = 256;
+
+impl Devp2pListenerConfig {
+    pub fn to_listener(&self, chain_spec: Arc<ChainSpec>) -> eyre::Result<Devp2pListener> {
+        let secret_key = match &self.secret_key {
+            Some(secret_key) => {
+                let secret_key = secret_key.value()?;
+                SecretKey::from_str(secret_key.trim_start_matches("0x"))
+                    .map_err(|err| eyre::eyre!("Invalid devp2p secret_key: {}", err))?
+            }
+            None => rng_secret_key(),
+        };
+        let bootnodes = if self.bootnodes.is_empty() {
+            chain_spec.bootnodes().unwrap_or_default()
+        } else {
+            self.bootnodes
+                .iter()
+                .map(|enode| {
+                    NodeRecord::from_str(enode)
+                        .map_err(|err| eyre::eyre!("Invalid devp2p bootnode {}: {}", enode, err))
+                })
+                .collect::<eyre::Result<_>>()?
+        };
+        if bootnodes.is_empty() {
+            eyre::bail!("No devp2p bootnodes configured and the chain has none");
+        }
+        Ok(Devp2pListener {
+            secret_key,
+            port: self.port.unwrap_or(DEFAULT_DEVP2P_PORT),
+            bootnodes,
+            max_peers: self.max_peers.unwrap_or(DEFAULT_DEVP2P_MAX_PEERS),
+            chain_spec,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Devp2pListener {
+    secret_key: SecretKey,
+    port: u16,
+    bootnodes: Vec<NodeRecord>,
+    max_peers: usize,
+    chain_spec: Arc<ChainSpec>,
+}
+
+impl Devp2pListener {
+    /// Status head: the Paris block with the current time.
+    fn head(&self) -> Head {
+        let (number, total_difficulty) = self
+            .chain_spec
+            .paris_block_and_final_difficulty
+            .unwrap_or_default();
+        Head {
+            number,
+            total_difficulty,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Starts the network and forwards the txs we get from peers to results until global_cancel.
+pub async fn start_devp2p_listener(
+    listener: Devp2pListener,
+    dedup: SharedMempoolDedup,
+    config: OrderInputConfig,
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    let peers_config = PeersConfig::default()
+        .with_max_outbound(listener.max_peers)
+        .with_max_inbound(listener.max_peers);
+    let network_config = NetworkConfig::builder(listener.secret_key)
+        .boot_nodes(listener.bootnodes.clone())
+        .listener_port(listener.port)
+        .discovery_port(listener.port)
+        .peer_config(peers_config)
+        .set_head(listener.head())
+        .build_with_noop_provider(listener.chain_spec.clone());
+    let mut network: NetworkManager = NetworkManager::new(network_config).await?;
+    let (network_events_sender, mut network_events) = mpsc::unbounded_channel();
+    network.set_transactions(network_events_sender);
+    let (events_sender, mut events) = mpsc::channel(EVENTS_BUFFER_SIZE);
+    tokio::spawn(async move {
+        while let Some(event) = network_events.recv().await {
+            match events_sender.try_send(event) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => inc_devp2p_dropped("event"),
+                Err(mpsc::error::TrySendError::Closed(_)) => break,
+            }
+        }
+    });
+    let inflight_requests = Arc::new(Semaphore::new(MAX_INFLIGHT_POOLED_REQUESTS));
+    let handle = network.handle().clone();
+    info!(
+        port = listener.port,
+        peer_id = ?handle.peer_id(),
+        "Devp2p mempool listener started"
+    );
+    let network = tokio::spawn(network);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                event = events.recv() => event,
+            };
+            let Some(event) = event else {
+                warn!("Devp2p network closed");
+                break;
+            };
+            let results_closed = match event {
+                NetworkTransactionEvent::IncomingTransactions { peer_id: _, msg } => {
+                    on_full_txs(msg.0, &dedup, &config, &results).await
+                }
+                NetworkTransactionEvent::IncomingPooledTransactionHashes { peer_id, msg } => {
+                    let start = Instant::now();
+                    let hashes: Vec<TxHash> = msg
+                        .iter_hashes()
+                        .copied()
+                        .filter(|tx_hash| {
+                            is_first_announcement(DEVP2P_SOURCE_NAME, &dedup, *tx_hash, start)
+                        })
+                        .collect();
+                    for hashes in hashes.chunks(MAX_HASHES_PER_REQUEST) {
+                        let Ok(permit) = inflight_requests.clone().try_acquire_owned() else {
+                            inc_devp2p_dropped("pooled_request");
+                            let mut dedup = dedup.lock();
+                            for tx_hash in hashes {
+                                dedup.forget(tx_hash);
+                            }
+                            continue;
+                        };
+                        let (response, pooled_txs) = oneshot::channel();
+                        handle.send_request(
+                            peer_id,
+                            PeerRequest::GetPooledTransactions {
+                                request: GetPooledTransactions(hashes.to_vec()),
+                                response,
+                            },
+                        );
+                        let (hashes, dedup, config, results) = (
+                            hashes.to_vec(),
+                            dedup.clone(),
+                            config.clone(),
+                            results.clone(),
+                        );
+                        tokio::spawn(async move {
+                            on_pooled_txs(hashes, pooled_txs, start, dedup, config, results).await;
+                            drop(permit);
+                        });
+                    }
+                    false
+                }
+                NetworkTransactionEvent::GetPooledTransactions { response, .. } => {
+                    let _ = response.send(Ok(PooledTransactions(Vec::new())));
+                    false
+                }
+                _ => false,
+            };
+            if results_closed {
+                break;
+            }
+        }
+        network.abort();
+        info!("Devp2p mempool listener finished");
+    }))
+}
+
+/// Txs broadcast in full (never blob txs). Returns true if results was closed.
+async fn on_full_txs(
+    txs: Vec<reth_primitives::TransactionSigned>,
+    dedup: &SharedMempoolDedup,
+    config: &OrderInputConfig,
+    results: &mpsc::Sender<ReplaceableOrderPoolCommand>,
+) -> bool {
+    for tx in txs {
+        let start = Instant::now();
+        let tx_hash = tx.hash();
+        if !is_first_announcement(DEVP2P_SOURCE_NAME, dedup, tx_hash, start) {
+            continue;
+        }
+        let Some(tx_with_blobs) = tx
+            .into_ecrecovered()
+            .and_then(TransactionSignedEcRecoveredWithBlobs::new_no_blobs)
+        else {
+            trace!(?tx_hash, "Invalid devp2p broadcast tx");
+            dedup.lock().forget(&tx_hash);
+            continue;
+        };
+        if send_mempool_tx(DEVP2P_SOURCE_NAME, tx_with_blobs, start, config, results).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Answer to our GetPooledTransactions for hashes. Hashes the peer didn't return are forgotten so other
+/// announcements can fetch them.
+async fn on_pooled_txs<E: std::fmt::Debug>(
+    hashes: Vec<TxHash>,
+    pooled_txs: oneshot::Receiver<Result<PooledTransactions, E>>,
+    start: Instant,
+    dedup: SharedMempoolDedup,
+    config: OrderInputConfig,
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+) {
+    let pooled_txs = match pooled_txs.await {
+        Ok(Ok(pooled_txs)) => pooled_txs.0,
+        Ok(Err(err)) => {
+            trace!(?err, "Devp2p GetPooledTransactions failed");
+            Vec::new()
+        }
+        Err(_) => Vec::new(),
+    };
+    let mut missing = hashes;
+    for pooled_tx in pooled_txs {
+        let tx_hash = *pooled_tx.hash();
+        let Some(pos) = missing.iter().position(|hash| *hash == tx_hash) else {
+            // not what we asked for
+            continue;
+        };
+        missing.swap_remove(pos);
+        let tx_with_blobs = match TransactionSignedEcRecoveredWithBlobs::from_pooled_tx(pooled_tx) {
+            Ok(tx_with_blobs) => tx_with_blobs,
+            Err(err) => {
+                error!(?tx_hash, ?err, "Failed to decode devp2p pooled tx");
+                dedup.lock().forget(&tx_hash);
+                continue;
+            }
+        };
+        if send_mempool_tx(DEVP2P_SOURCE_NAME, tx_with_blobs, start, &config, &results).await {
+            return;
+        }
+    }
+    let mut dedup = dedup.lock();
+    for tx_hash in &missing {
+        dedup.forget(tx_hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::{base_config::EnvOrValue, order_input::txpool_fetcher::MempoolDedup},
+        primitives::Order,
+        utils::Signer,
+    };
+    use alloy_consensus::TxEip1559;
+    use alloy_primitives::{Address, TxKind, U256};
+    use parking_lot::Mutex;
+    use reth_chainspec::MAINNET;
+    use reth_primitives::{PooledTransactionsElement, Transaction, TransactionSigned};
+
+    fn signed_tx(signer: &Signer, nonce: u64) -> TransactionSigned {
+        let tx = TxEip1559 {
+            chain_id: 1,
+            nonce,
+            gas_limit: 21_000,
+            max_fee_per_gas: 10,
+            max_priority_fee_per_gas: 1,
+            to: TxKind::Call(Address::with_last_byte(1)),
+            value: U256::from(1),
+            ..Default::default()
+        };
+        signer.sign_tx(tx.into()).unwrap().into_signed()
+    }
+
+    fn sent_tx_hash(results: &mut mpsc::Receiver<ReplaceableOrderPoolCommand>) -> Option<TxHash> {
+        match results.try_recv().ok()? {
+            ReplaceableOrderPoolCommand::Order(Order::Tx(tx)) => Some(tx.tx_with_blobs.hash()),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_on_full_txs_dedup() {
+        let dedup: SharedMempoolDedup = Arc::new(Mutex::new(MempoolDedup::new(10)));
+        let config = OrderInputConfig::default_e2e();
+        let (sender, mut results) = mpsc::channel(10);
+        let signer = Signer::random();
+        let (tx, other_tx) = (signed_tx(&signer, 0), signed_tx(&signer, 1));
+
+        // another source already gave us other_tx
+        assert!(is_first_announcement(
+            "other",
+            &dedup,
+            other_tx.hash(),
+            Instant::now()
+        ));
+        assert!(!on_full_txs(vec![tx.clone(), other_tx], &dedup, &config, &sender).await);
+        assert_eq!(sent_tx_hash(&mut results), Some(tx.hash()));
+        assert_eq!(sent_tx_hash(&mut results), None);
+
+        // broadcast again by another peer
+        on_full_txs(vec![tx], &dedup, &config, &sender).await;
+        assert_eq!(sent_tx_hash(&mut results), None);
+    }
+
+    #[tokio::test]
+    async fn test_on_pooled_txs_forgets_missing() {
+        let dedup: SharedMempoolDedup = Arc::new(Mutex::new(MempoolDedup::new(10)));
+        let config = OrderInputConfig::default_e2e();
+        let (sender, mut results) = mpsc::channel(10);
+        let signer = Signer::random();
+        let (returned, missing) = (signed_tx(&signer, 0), signed_tx(&signer, 1));
+        let hashes = vec![returned.hash(), missing.hash()];
+        let now = Instant::now();
+        for tx_hash in &hashes {
+            assert!(is_first_announcement(
+                DEVP2P_SOURCE_NAME,
+                &dedup,
+                *tx_hash,
+                now
+            ));
+        }
+
+        let TransactionSigned {
+            transaction: Transaction::Eip1559(transaction),
+            signature,
+            hash,
+        } = returned.clone()
+        else {
+            unreachable!()
+        };
+        let (response, pooled_txs) = oneshot::channel::<Result<PooledTransactions, ()>>();
+        response
+            .send(Ok(PooledTransactions(vec![
+                PooledTransactionsElement::Eip1559 {
+                    transaction,
+                    signature,
+                    hash,
+                },
+            ])))
+            .unwrap();
+        on_pooled_txs(hashes, pooled_txs, now, dedup.clone(), config, sender).await;
+        assert_eq!(sent_tx_hash(&mut results), Some(returned.hash()));
+        assert_eq!(sent_tx_hash(&mut results), None);
+
+        // the one we got is still deduplicated, the missing one can be fetched from the next announcement
+        assert!(!is_first_announcement(
+            "other",
+            &dedup,
+            returned.hash(),
+            now
+        ));
+        assert!(is_first_announcement("other", &dedup, missing.hash(), now));
+    }
+
+    #[test]
+    fn test_devp2p_listener_config() {
+        let listener = Devp2pListenerConfig::default()
+            .to_listener(MAINNET.clone())
+            .unwrap();
+        assert_eq!(listener.port, DEFAULT_DEVP2P_PORT);
+        assert_eq!(listener.bootnodes, MAINNET.bootnodes().unwrap());
+        assert_eq!(listener.head().number, 15537394);
+
+        let enode = "enode://6f8a80d14311c39f35f516fa664deaaaa13e85b2f7493f37f6144d86991ec012937307647bd3b9a82abe2974e1407241d54947bbb39763a4cac9f77166ad92a0@10.3.58.6:30303";
+        let secret_key = "0x".to_string() + &"11".repeat(32);
+        let listener = Devp2pListenerConfig {
+            port: Some(1234),
+            bootnodes: vec![enode.to_string()],
+            max_peers: Some(5),
+            secret_key: Some(EnvOrValue::from(secret_key.as_str())),
+        }
+        .to_listener(MAINNET.clone())
+        .unwrap();
+        assert_eq!(
+            listener.bootnodes,
+            vec![NodeRecord::from_str(enode).unwrap()]
+        );
+        assert_eq!(listener.secret_key.secret_bytes(), [0x11; 32]);
+
+        let bad_bootnode = Devp2pListenerConfig {
+            bootnodes: vec!["enode://nope".to_string()],
+            ..Default::default()
+        };
+        assert!(bad_bootnode.to_listener(MAINNET.clone()).is_err());
+        let bad_key = Devp2pListenerConfig {
+            secret_key: Some(EnvOrValue::from("0x1234")),
+            ..Default::default()
+        };
+        assert!(bad_key.to_listener(MAINNET.clone()).is_err());
+    }
+}
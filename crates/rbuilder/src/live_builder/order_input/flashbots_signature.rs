@@ -0,0 +1,183 @@
+//! X-Flashbots-Signature authentication of the input RPC.
+//! The header is `signer:signature`, signature being the EIP-191 signature of the 0x hex keccak256 of the body.
+//! [`FlashbotsSignatureLayer`] (http middleware) checks it before the methods run, the result is available to the
+//! methods via [`request_signature`] and to the next middlewares as a request extension.
+//! The recovered signer becomes the signer of the orders of the request ([`crate::primitives::Metadata::signer`]
+//! and, for bundles, the signingAddress) so it's what prioritization, refunds and banning see.
+//! WebSocket messages have no headers so they are never signed (with require_flashbots_signature their bundles are
+//! rejected), neither are gRPC calls (the gRPC server can't be enabled with require_flashbots_signature).
+
+use super::rpc_server::recover_message_signer;
+use alloy_primitives::{keccak256, Address, Bytes};
+use hyper_0_14::{header::CONTENT_LENGTH, Body, Request};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+pub const FLASHBOTS_SIGNATURE_HEADER: &str = "x-flashbots-signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestSignature {
+    Missing,
+    Valid(Address),
+    /// Bad signature, signature of someone else or a body we didn't read (too big).
+    Invalid,
+}
+
+impl RequestSignature {
+    pub fn signer(&self) -> Option<Address> {
+        match self {
+            RequestSignature::Valid(signer) => Some(*signer),
+            _ => None,
+        }
+    }
+}
+
+tokio::task_local! {
+    static REQUEST_SIGNATURE: RequestSignature;
+}
+
+/// Signature of the http request being handled (Missing outside the http middleware).
+pub fn request_signature() -> RequestSignature {
+    REQUEST_SIGNATURE
+        .try_with(|signature| *signature)
+        .unwrap_or(RequestSignature::Missing)
+}
+
+/// Signer of the X-Flashbots-Signature header value if the signature matches the body.
+pub fn recover_flashbots_signer(header: &str, body: &[u8]) -> Option<Address> {
+    let (signer, signature) = header.split_once(':')?;
+    let signer: Address = signer.parse().ok()?;
+    let signature: Bytes = signature.parse().ok()?;
+    let message = keccak256(body).to_string();
+    (recover_message_signer(&message, &signature)? == signer).then_some(signer)
+}
+
+/// jsonrpsee http middleware.
+#[derive(Debug, Clone)]
+pub struct FlashbotsSignatureLayer {
+    /// Bigger bodies are not read (the server will reject them anyway).
+    max_body_size: u64,
+}
+
+impl FlashbotsSignatureLayer {
+    pub fn new(max_body_size: u32) -> Self {
+        Self {
+            max_body_size: max_body_size as u64,
+        }
+    }
+}
+
+impl<S> Layer<S> for FlashbotsSignatureLayer {
+    type Service = FlashbotsSignature<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FlashbotsSignature {
+            inner,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FlashbotsSignature<S> {
+    inner: S,
+    max_body_size: u64,
+}
+
+impl<S> Service<Request<Body>> for FlashbotsSignature<S>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // the clone is not ready, keep the one that is
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let max_body_size = self.max_body_size;
+        Box::pin(async move {
+            let header = request
+                .headers()
+                .get(FLASHBOTS_SIGNATURE_HEADER)
+                .map(|value| value.to_str().unwrap_or_default().to_string());
+            let body_size = request
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            let (mut request, signature) = match (header, body_size) {
+                (None, _) => (request, RequestSignature::Missing),
+                (Some(header), Some(body_size)) if body_size <= max_body_size => {
+                    let (parts, body) = request.into_parts();
+                    let body = hyper_0_14::body::to_bytes(body).await.unwrap_or_default();
+                    let signature = match recover_flashbots_signer(&header, &body) {
+                        Some(signer) => RequestSignature::Valid(signer),
+                        None => RequestSignature::Invalid,
+                    };
+                    (Request::from_parts(parts, Body::from(body)), signature)
+                }
+                (Some(_), _) => (request, RequestSignature::Invalid),
+            };
+            request.extensions_mut().insert(signature);
+            REQUEST_SIGNATURE
+                .scope(signature, inner.call(request))
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Signer;
+    use alloy_primitives::eip191_hash_message;
+
+    #[test]
+    fn test_recover_flashbots_signer() {
+        let signer = Signer::random();
+        let body = br#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+        let signature = signer
+            .sign_message(eip191_hash_message(keccak256(body).to_string()))
+            .unwrap()
+            .as_bytes();
+        let header = format!("{}:{}", signer.address, Bytes::from(signature.to_vec()));
+        assert_eq!(
+            recover_flashbots_signer(&header, body),
+            Some(signer.address)
+        );
+        // other body
+        assert_eq!(recover_flashbots_signer(&header, b"{}"), None);
+        // claims to be someone else
+        let header = format!(
+            "{}:{}",
+            Address::with_last_byte(1),
+            Bytes::from(signature.to_vec())
+        );
+        assert_eq!(recover_flashbots_signer(&header, body), None);
+        assert_eq!(recover_flashbots_signer("garbage", body), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_signature_scope() {
+        assert_eq!(request_signature(), RequestSignature::Missing);
+        let signer = Address::with_last_byte(1);
+        let seen = REQUEST_SIGNATURE
+            .scope(RequestSignature::Valid(signer), async {
+                tokio::task::yield_now().await;
+                request_signature()
+            })
+            .await;
+        assert_eq!(seen.signer(), Some(signer));
+    }
+}
@@ -0,0 +1,507 @@
+//! gRPC alternative to the json rpc input ([`super::rpc_server`]) for orderflow providers that prefer typed messages
+//! (proto/orderflow.proto) and streaming their bundles over a single call (StreamBundles).
+//! Every call goes through the same checks and limits as its json rpc counterpart and ends up on the same channel:
+//! max_request_body_size bounds every message, max_bundle_txs the bundles and big bundles are decoded on the blocking
+//! thread pool ([`SizeAwareDecoder`]).
+//! Calls share the json rpc rate limits ([`super::rpc_rate_limit`]) by client ip, every bundle of a StreamBundles
+//! call takes a token.
+//! Calls are not authenticated so signing_address (which decides who can replace or cancel the bundle) is rejected
+//! unless grpc_trust_signing_address says the server is behind a proxy authenticating the signers.
+
+use super::{
+    rpc_rate_limit::{RpcRateLimiter, RATE_LIMITED_ERROR_CODE},
+    rpc_server::{
+        cancel_bundle_key, check_bundle_txs, next_replacement_nonce, send_command, send_order,
+        unix_now, RawCancelBundle, SignedCancellations, SizeAwareDecoder,
+    },
+    tx_type_forks::TxTypeForks,
+    OrderInputConfig, ReplaceableOrderPoolCommand,
+};
+use crate::primitives::{
+    serialize::{RawBundle, RawTx, TxEncoding},
+    Order,
+};
+use alloy_primitives::{Address, Bytes, B256, U64};
+use jsonrpsee::types::ErrorObject;
+use prost::Message;
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_util::sync::CancellationToken;
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status, Streaming,
+};
+use tracing::{info, trace, warn};
+use uuid::Uuid;
+
+pub mod proto {
+    tonic::include_proto!("rbuilder.orderflow.v1");
+}
+
+use proto::order_flow_server::{OrderFlow, OrderFlowServer};
+
+/// Responses of a StreamBundles call waiting for the client, we stop reading its bundles when full.
+const STREAM_RESPONSES_BUFFER: usize = 64;
+
+#[derive(Debug, Clone)]
+struct OrderFlowService {
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    timeout: Duration,
+    max_bundle_txs: usize,
+    decoder: Arc<SizeAwareDecoder>,
+    /// If false bundles with signing_address are rejected.
+    trust_signing_address: bool,
+    require_signed_cancellations: bool,
+    signed_cancellations: Arc<SignedCancellations>,
+    rate_limiter: Option<Arc<RpcRateLimiter>>,
+    tx_type_forks: Arc<TxTypeForks>,
+}
+
+fn invalid_argument(err: impl ToString) -> Status {
+    Status::invalid_argument(err.to_string())
+}
+
+fn rpc_error_status(err: ErrorObject<'static>) -> Status {
+    Status::invalid_argument(err.message())
+}
+
+fn address(bytes: &[u8]) -> Result<Address, Status> {
+    Address::try_from(bytes).map_err(|_| invalid_argument("address must be 20 bytes"))
+}
+
+fn raw_bundle(bundle: proto::Bundle) -> Result<RawBundle, Status> {
+    let reverting_tx_hashes = bundle
+        .reverting_tx_hashes
+        .iter()
+        .map(|hash| {
+            B256::try_from(hash.as_slice())
+                .map_err(|_| invalid_argument("reverting tx hash must be 32 bytes"))
+        })
+        .collect::<Result<_, _>>()?;
+    let replacement_uuid = bundle
+        .replacement_uuid
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(invalid_argument)?;
+    let signing_address = bundle.signing_address.as_deref().map(address).transpose()?;
+    Ok(RawBundle {
+        block_number: U64::from(bundle.block_number),
+        txs: bundle.txs.into_iter().map(Bytes::from).collect(),
+        reverting_tx_hashes,
+        replacement_uuid,
+        signing_address,
+        min_timestamp: bundle.min_timestamp,
+        max_timestamp: bundle.max_timestamp,
+        replacement_nonce: bundle.replacement_nonce,
+    })
+}
+
+impl OrderFlowService {
+    /// Client ip of the call for the rate limiter, None if we don't limit.
+    fn rate_limited_ip<T>(&self, request: &Request<T>) -> Option<IpAddr> {
+        let rate_limiter = self.rate_limiter.as_ref()?;
+        let peer = request.remote_addr()?;
+        let metadata = request.metadata();
+        Some(rate_limiter.client_ip(peer.ip(), |name| {
+            metadata.get(name).and_then(|value| value.to_str().ok())
+        }))
+    }
+
+    fn check_rate_limit(&self, ip: Option<IpAddr>) -> Result<(), Status> {
+        let (Some(rate_limiter), Some(ip)) = (&self.rate_limiter, ip) else {
+            return Ok(());
+        };
+        if rate_limiter.check(&rate_limiter.key(None, ip), 1) {
+            Ok(())
+        } else {
+            Err(Status::resource_exhausted(format!(
+                "rate limit exceeded ({})",
+                RATE_LIMITED_ERROR_CODE
+            )))
+        }
+    }
+
+    fn check_tx_types(&self, order: &Order) -> Result<(), Status> {
+        self.tx_type_forks
+            .check_order(order, unix_now())
+            .map_err(invalid_argument)
+    }
+
+    async fn handle_bundle(
+        &self,
+        bundle: proto::Bundle,
+    ) -> Result<proto::SubmitBundleResponse, Status> {
+        if bundle.signing_address.is_some() && !self.trust_signing_address {
+            return Err(Status::permission_denied(
+                "signing_address is only accepted from an authenticating proxy",
+            ));
+        }
+        let size = bundle.encoded_len();
+        let mut raw_bundle = raw_bundle(bundle)?;
+        check_bundle_txs(raw_bundle.txs.len(), self.max_bundle_txs).map_err(rpc_error_status)?;
+        if raw_bundle.replacement_uuid.is_some() && raw_bundle.replacement_nonce.is_none() {
+            raw_bundle.replacement_nonce = Some(next_replacement_nonce());
+        }
+        let bundle = self
+            .decoder
+            .decode(size, move || raw_bundle.try_into(TxEncoding::WithBlobData))
+            .await
+            .map_err(|err| Status::internal(err.message()))?
+            .map_err(|err| invalid_argument(format!("failed to decode bundle: {}", err)))?;
+        let bundle_hash = bundle.hash;
+        let order = Order::Bundle(bundle);
+        self.check_tx_types(&order)?;
+        let response = proto::SubmitBundleResponse {
+            bundle_hash: bundle_hash.to_vec(),
+            order_id: order.id().to_string(),
+            error: None,
+        };
+        let target_block = order.target_block().unwrap_or_default();
+        trace!(order = ?order.id(), target_block, "Received bundle over gRPC");
+        send_order(order, &self.results, self.timeout).await;
+        Ok(response)
+    }
+
+    async fn handle_transaction(
+        &self,
+        tx: proto::Transaction,
+    ) -> Result<proto::SubmitTransactionResponse, Status> {
+        let size = tx.raw.len();
+        let raw_tx = RawTx { tx: tx.raw.into() };
+        let tx = self
+            .decoder
+            .decode(size, move || raw_tx.decode(TxEncoding::WithBlobData))
+            .await
+            .map_err(|err| Status::internal(err.message()))?
+            .map_err(|err| {
+                warn!(?err, "Failed to decode raw transaction");
+                invalid_argument("failed to verify transaction")
+            })?;
+        let tx_hash = tx.tx_with_blobs.hash();
+        let order = Order::Tx(tx);
+        self.check_tx_types(&order)?;
+        let response = proto::SubmitTransactionResponse {
+            tx_hash: tx_hash.to_vec(),
+            order_id: order.id().to_string(),
+        };
+        trace!(order = ?order.id(), "Received mempool tx over gRPC");
+        send_order(order, &self.results, self.timeout).await;
+        Ok(response)
+    }
+
+    async fn handle_cancel_bundle(&self, cancel: proto::CancelBundleRequest) -> Result<(), Status> {
+        let cancel = RawCancelBundle {
+            replacement_uuid: Uuid::parse_str(&cancel.replacement_uuid)
+                .map_err(invalid_argument)?,
+            signing_address: address(&cancel.signing_address)?,
+            timestamp: cancel.timestamp,
+            signature: cancel.signature.map(Bytes::from),
+        };
+        // no X-Flashbots-Signature over gRPC, only signatures in the request authorize
+        let key = cancel_bundle_key(
+            &cancel,
+            None,
+            self.require_signed_cancellations,
+            &self.signed_cancellations,
+            unix_now(),
+        )
+        .map_err(rpc_error_status)?;
+        send_command(
+            ReplaceableOrderPoolCommand::CancelBundle(key),
+            &self.results,
+            self.timeout,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl OrderFlow for OrderFlowService {
+    async fn submit_bundle(
+        &self,
+        request: Request<proto::Bundle>,
+    ) -> Result<Response<proto::SubmitBundleResponse>, Status> {
+        self.check_rate_limit(self.rate_limited_ip(&request))?;
+        self.handle_bundle(request.into_inner())
+            .await
+            .map(Response::new)
+    }
+
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::SubmitTransactionResponse>, Status> {
+        self.check_rate_limit(self.rate_limited_ip(&request))?;
+        self.handle_transaction(request.into_inner())
+            .await
+            .map(Response::new)
+    }
+
+    async fn cancel_bundle(
+        &self,
+        request: Request<proto::CancelBundleRequest>,
+    ) -> Result<Response<proto::CancelBundleResponse>, Status> {
+        self.check_rate_limit(self.rate_limited_ip(&request))?;
+        self.handle_cancel_bundle(request.into_inner()).await?;
+        Ok(Response::new(proto::CancelBundleResponse {}))
+    }
+
+    type StreamBundlesStream =
+        Pin<Box<dyn Stream<Item = Result<proto::SubmitBundleResponse, Status>> + Send + 'static>>;
+
+    async fn stream_bundles(
+        &self,
+        request: Request<Streaming<proto::Bundle>>,
+    ) -> Result<Response<Self::StreamBundlesStream>, Status> {
+        let ip = self.rate_limited_ip(&request);
+        let mut bundles = request.into_inner();
+        let (responses, receiver) = mpsc::channel(STREAM_RESPONSES_BUFFER);
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let response = match bundles.message().await {
+                    Ok(Some(bundle)) => {
+                        let response = match service.check_rate_limit(ip) {
+                            Ok(()) => service.handle_bundle(bundle).await,
+                            Err(status) => Err(status),
+                        };
+                        Ok(
+                            response.unwrap_or_else(|status| proto::SubmitBundleResponse {
+                                error: Some(status.message().to_string()),
+                                ..Default::default()
+                            }),
+                        )
+                    }
+                    Ok(None) => break,
+                    // broken request stream, tell the client and stop
+                    Err(status) => Err(status),
+                };
+                let stop = response.is_err();
+                if responses.send(response).await.is_err() || stop {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+/// Like [`super::rpc_server::start_server_accepting_bundles`] the spawned task cancels global_cancel if the server
+/// stops.
+pub async fn start_grpc_server_accepting_orders(
+    address: SocketAddr,
+    config: OrderInputConfig,
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    rate_limiter: Option<Arc<RpcRateLimiter>>,
+    signed_cancellations: Arc<SignedCancellations>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    let limits = config.request_limits;
+    let service = OrderFlowService {
+        results,
+        timeout: config.results_channel_timeout,
+        max_bundle_txs: limits.max_bundle_txs,
+        decoder: Arc::new(SizeAwareDecoder::new(
+            limits.blocking_decode_min_size,
+            limits.max_blocking_decodes,
+        )),
+        trust_signing_address: config.grpc_trust_signing_address,
+        require_signed_cancellations: config.require_signed_cancellations,
+        signed_cancellations,
+        rate_limiter,
+        tx_type_forks: config.tx_type_forks.clone(),
+    };
+    let incoming = TcpIncoming::new(address, true, None)
+        .map_err(|err| eyre::eyre!("failed to bind gRPC server on {}: {}", address, err))?;
+    let server = Server::builder().add_service(
+        OrderFlowServer::new(service)
+            .max_decoding_message_size(limits.max_request_body_size as usize),
+    );
+
+    Ok(tokio::spawn(async move {
+        info!(%address, "gRPC server job: started");
+        let shutdown = global_cancel.clone();
+        if let Err(err) = server
+            .serve_with_incoming_shutdown(incoming, async move { shutdown.cancelled().await })
+            .await
+        {
+            warn!(?err, "gRPC server failed");
+        }
+        global_cancel.cancel();
+        info!("gRPC server job: finished");
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{live_builder::order_input::rpc_rate_limit::RpcRateLimits, utils::Signer};
+    use alloy_consensus::TxEip1559;
+    use alloy_eips::eip2718::Encodable2718;
+    use alloy_primitives::TxKind;
+    use proto::order_flow_client::OrderFlowClient;
+
+    #[test]
+    fn test_raw_bundle() {
+        let uuid = Uuid::from_u128(7);
+        let bundle = proto::Bundle {
+            block_number: 12,
+            txs: vec![vec![1, 2, 3]],
+            reverting_tx_hashes: vec![B256::with_last_byte(1).to_vec()],
+            replacement_uuid: Some(uuid.to_string()),
+            signing_address: Some(Address::with_last_byte(2).to_vec()),
+            min_timestamp: None,
+            max_timestamp: Some(100),
+            replacement_nonce: None,
+        };
+        let raw = raw_bundle(bundle.clone()).unwrap();
+        assert_eq!(raw.block_number, U64::from(12));
+        assert_eq!(raw.txs, vec![Bytes::from(vec![1, 2, 3])]);
+        assert_eq!(raw.reverting_tx_hashes, vec![B256::with_last_byte(1)]);
+        assert_eq!(raw.replacement_uuid, Some(uuid));
+        assert_eq!(raw.signing_address, Some(Address::with_last_byte(2)));
+        assert_eq!(raw.max_timestamp, Some(100));
+
+        let bad_address = proto::Bundle {
+            signing_address: Some(vec![1; 19]),
+            ..bundle.clone()
+        };
+        assert_eq!(
+            raw_bundle(bad_address).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+        let bad_uuid = proto::Bundle {
+            replacement_uuid: Some("nope".to_string()),
+            ..bundle
+        };
+        assert!(raw_bundle(bad_uuid).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let (results, _receiver) = mpsc::channel(1);
+        let service = OrderFlowService {
+            results,
+            timeout: Duration::from_millis(1),
+            max_bundle_txs: 10,
+            decoder: Arc::new(SizeAwareDecoder::new(1024, 1)),
+            trust_signing_address: false,
+            require_signed_cancellations: true,
+            signed_cancellations: Default::default(),
+            rate_limiter: Some(Arc::new(RpcRateLimiter::new(&RpcRateLimits {
+                per_ip: Some(1),
+                ..Default::default()
+            }))),
+            tx_type_forks: Default::default(),
+        };
+        let ip = Some("1.2.3.4".parse().unwrap());
+        assert!(service.check_rate_limit(ip).is_ok());
+        assert_eq!(
+            service.check_rate_limit(ip).unwrap_err().code(),
+            tonic::Code::ResourceExhausted
+        );
+        assert!(service
+            .check_rate_limit(Some("1.2.3.5".parse().unwrap()))
+            .is_ok());
+    }
+
+    fn raw_tx(signer: &Signer, nonce: u64) -> Vec<u8> {
+        let tx = TxEip1559 {
+            chain_id: 1,
+            nonce,
+            gas_limit: 21_000,
+            max_fee_per_gas: 10,
+            to: TxKind::Call(Address::with_last_byte(1)),
+            ..Default::default()
+        };
+        let mut raw_tx = Vec::new();
+        signer
+            .sign_tx(tx.into())
+            .unwrap()
+            .as_signed()
+            .encode_2718(&mut raw_tx);
+        raw_tx
+    }
+
+    /// Calls through a real server and client.
+    #[tokio::test]
+    async fn test_grpc_service() {
+        // free port for the server
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut config = OrderInputConfig::default_e2e();
+        config.request_limits.max_bundle_txs = 2;
+        // every bundle goes through the blocking decode
+        config.request_limits.blocking_decode_min_size = 0;
+        let (results, mut orders) = mpsc::channel(10);
+        let cancel = CancellationToken::new();
+        let server = start_grpc_server_accepting_orders(
+            address,
+            config,
+            results,
+            None,
+            Default::default(),
+            cancel.clone(),
+        )
+        .await
+        .unwrap();
+        // the server is listening once started
+        let mut client = OrderFlowClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+
+        let signer = Signer::random();
+        let bundle = proto::Bundle {
+            block_number: 12,
+            txs: vec![raw_tx(&signer, 0), raw_tx(&signer, 1)],
+            ..Default::default()
+        };
+        let response = client
+            .submit_bundle(bundle.clone())
+            .await
+            .unwrap()
+            .into_inner();
+        match orders.recv().await.unwrap() {
+            ReplaceableOrderPoolCommand::Order(order) => {
+                assert_eq!(order.id().to_string(), response.order_id);
+                assert_eq!(order.list_txs().len(), 2);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let too_big = proto::Bundle {
+            txs: vec![raw_tx(&signer, 0), raw_tx(&signer, 1), raw_tx(&signer, 2)],
+            ..bundle.clone()
+        };
+        assert_eq!(
+            client.submit_bundle(too_big).await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+        let signed_by_client = proto::Bundle {
+            signing_address: Some(Address::with_last_byte(1).to_vec()),
+            ..bundle
+        };
+        assert_eq!(
+            client
+                .submit_bundle(signed_by_client)
+                .await
+                .unwrap_err()
+                .code(),
+            tonic::Code::PermissionDenied
+        );
+        assert!(orders.try_recv().is_err());
+
+        cancel.cancel();
+        server.await.unwrap();
+    }
+}
@@ -1,22 +1,55 @@
 //! order_input handles receiving new orders from the ipc mempool subscription and json rpc server
 //!
 pub mod clean_orderpool;
+#[cfg(feature = "devp2p")]
+pub mod devp2p_listener;
+pub mod flashbots_signature;
+#[cfg(feature = "grpc")]
+pub mod grpc_server;
 pub mod order_replacement_manager;
+pub mod order_schema;
 pub mod order_sink;
+pub mod orderflow_sharing;
 pub mod orderpool;
+pub mod orderpool_delta;
+pub mod orderpool_sync;
 pub mod replaceable_order_sink;
+pub mod rpc_connection_metrics;
+pub mod rpc_rate_limit;
 pub mod rpc_server;
+pub mod tenants;
+pub mod tx_type_forks;
 pub mod txpool_fetcher;
 
+#[cfg(feature = "devp2p")]
+use self::devp2p_listener::Devp2pListener;
 use self::{
+    orderflow_sharing::{spawn_orderflow_ingress, OrderflowEgress, OrderflowSharingConfig},
     orderpool::{OrderPool, OrderPoolSubscriptionId},
+    orderpool_sync::OrderPoolSyncConfig,
     replaceable_order_sink::ReplaceableOrderSink,
+    rpc_rate_limit::{RpcRateLimiter, RpcRateLimits},
+    rpc_server::SignedCancellations,
+    tenants::TenantRegistry,
+    tx_type_forks::TxTypeForks,
+    txpool_fetcher::MempoolSource,
 };
-use crate::primitives::{serialize::CancelShareBundle, BundleReplacementKey, Order};
+use crate::{
+    building::conflict_cache::ConflictCache,
+    live_builder::{archive::order_archive::OrderArchive, building::sim_bundle::SimBundleService},
+    primitives::{serialize::CancelShareBundle, BundleReplacementKey, Order},
+};
+use alloy_primitives::{Address, B256};
 use jsonrpsee::RpcModule;
 use parking_lot::Mutex;
 use reth_provider::StateProviderFactory;
-use std::{net::Ipv4Addr, path::PathBuf, sync::Arc, time::Duration};
+use serde::Deserialize;
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{sync::mpsc, task::JoinHandle};
 use tokio_util::sync::CancellationToken;
 use tracing::{info, trace, warn};
@@ -81,18 +114,101 @@ pub struct OrderInputConfig {
     ignore_blobs: bool,
     /// Path to reth ipc
     ipc_path: PathBuf,
+    /// Extra nodes we get mempool txs from.
+    mempool_sources: Vec<MempoolSource>,
+    /// If false we don't subscribe to the el node mempool (ipc_path is still used for the head updates).
+    el_node_mempool: bool,
+    /// If set we also get mempool txs from the devp2p tx gossip (see [`devp2p_listener`]).
+    #[cfg(feature = "devp2p")]
+    devp2p_listener: Option<Devp2pListener>,
     /// Input RPC port
     server_port: u16,
     /// Input RPC ip
     server_ip: Ipv4Addr,
     /// Input RPC max connections
     serve_max_connections: u32,
+    /// Input RPC transports.
+    pub server_transport: RpcTransport,
+    /// Our identities for mev-share privacy.builders targeting.
+    pub builder_names: Vec<String>,
+    /// If true eth_cancelBundle must be signed (or X-Flashbots-Signature signed) by signingAddress (see [`rpc_server`]).
+    pub require_signed_cancellations: bool,
+    /// If true eth_sendBundle/mev_sendBundle must have a valid X-Flashbots-Signature (see [`flashbots_signature`]), so
+    /// WebSocket bundles are rejected and there's no gRPC server.
+    pub require_flashbots_signature: bool,
+    pub request_limits: RequestLimits,
+    /// Per submitter request rate limits of the input RPC (see [`rpc_rate_limit`]).
+    pub rate_limits: RpcRateLimits,
+    /// gRPC order submission server, same limits as the json rpc.
+    pub grpc_server_address: Option<SocketAddr>,
+    /// If false gRPC bundles with signing_address are rejected (see [`grpc_server`]).
+    pub grpc_trust_signing_address: bool,
     /// All order sources send new ReplaceableOrderPoolCommands through an mpsc::Sender bounded channel.
     /// Timeout to wait when sending to that channel (after that the ReplaceableOrderPoolCommand is lost).
     results_channel_timeout: Duration,
     /// Size of the bounded channel.
     pub input_channel_buffer_size: usize,
+    /// Orderflow partners quotas and visibility.
+    pub tenants: Arc<TenantRegistry>,
+    /// Warm sync with other instances.
+    pub sync: OrderPoolSyncConfig,
+    /// Orderflow sharing with other builders.
+    pub orderflow_sharing: OrderflowSharingConfig,
+    /// If set, mev_simBundle and eth_callBundle simulate on the slots being built.
+    pub sim_bundle: Option<SimBundleService>,
+    /// Tx types the input RPC accepts (see [`tx_type_forks`]).
+    pub tx_type_forks: Arc<TxTypeForks>,
+    /// Journal of every command we get (see [`crate::live_builder::archive::order_archive`]).
+    pub order_archive_path: Option<PathBuf>,
+}
+/// Transports the input RPC is served on (same port). WebSocket lets searchers keep a connection open and stream
+/// their eth_sendBundle/mev_sendBundle calls without a new http request each time.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RpcTransport {
+    #[default]
+    Http,
+    Ws,
+    HttpAndWs,
+}
+
+/// Limits of the input RPC requests so a single request can't exhaust our memory.
+/// The server never decompresses bodies (Content-Encoding is ignored and a compressed body is just a parse error) so
+/// max_request_body_size bounds what we parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Whole http body (all the calls of a batch). Bigger requests get a -32701 "Request is too big" error.
+    pub max_request_body_size: u32,
+    /// Calls per json rpc batch, bigger batches get a -32010 error. 0 disables batches.
+    pub max_batch_size: u32,
+    /// Txs per bundle (including the ones on nested share bundles), bigger bundles get a -32602 error.
+    pub max_bundle_txs: usize,
+    /// eth_sendBundle/mev_sendBundle calls with params of at least this many bytes are decoded (json, txs and signer
+    /// recovery) on a blocking thread instead of the server reactor so huge bundles don't delay the small ones.
+    /// Reading the body and parsing the request envelope still happen on the reactor (bounded by max_request_body_size).
+    pub blocking_decode_min_size: usize,
+    /// Max decodes running on blocking threads at once, the rest wait for their turn.
+    pub max_blocking_decodes: usize,
 }
+
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: u32 = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_BATCH_SIZE: u32 = 100;
+pub const DEFAULT_MAX_BUNDLE_TXS: usize = 1000;
+pub const DEFAULT_BLOCKING_DECODE_MIN_SIZE: usize = 128 * 1024;
+pub const DEFAULT_MAX_BLOCKING_DECODES: usize = 4;
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            max_bundle_txs: DEFAULT_MAX_BUNDLE_TXS,
+            blocking_decode_min_size: DEFAULT_BLOCKING_DECODE_MIN_SIZE,
+            max_blocking_decodes: DEFAULT_MAX_BLOCKING_DECODES,
+        }
+    }
+}
+
 pub const DEFAULT_SERVE_MAX_CONNECTIONS: u32 = 4096;
 pub const DEFAULT_RESULTS_CHANNEL_TIMEOUT: Duration = Duration::from_millis(50);
 pub const DEFAULT_INPUT_CHANNEL_BUFFER_SIZE: usize = 10_000;
@@ -112,43 +228,146 @@ impl OrderInputConfig {
             ignore_cancellable_orders,
             ignore_blobs,
             ipc_path,
+            mempool_sources: Vec::new(),
+            el_node_mempool: true,
+            #[cfg(feature = "devp2p")]
+            devp2p_listener: None,
             server_port,
             server_ip,
             serve_max_connections,
+            server_transport: Default::default(),
+            builder_names: Vec::new(),
+            require_signed_cancellations: true,
+            require_flashbots_signature: false,
+            request_limits: Default::default(),
+            rate_limits: Default::default(),
+            grpc_server_address: None,
+            grpc_trust_signing_address: false,
             results_channel_timeout,
             input_channel_buffer_size,
+            tenants: Default::default(),
+            sync: Default::default(),
+            orderflow_sharing: Default::default(),
+            sim_bundle: None,
+            tx_type_forks: Default::default(),
+            order_archive_path: None,
         }
     }
 
+    pub fn with_tenants(self, tenants: Arc<TenantRegistry>) -> Self {
+        Self { tenants, ..self }
+    }
+
     pub fn from_config(config: &BaseConfig) -> eyre::Result<Self> {
         let el_node_ipc_path = expand_path(config.el_node_ipc_path.clone())?;
+        if !config.el_node_mempool && config.devp2p_mempool.is_none() {
+            eyre::bail!("el_node_mempool = false needs devp2p_mempool");
+        }
+        if config.jsonrpc_require_flashbots_signature && config.grpc_server_port.is_some() {
+            eyre::bail!(
+                "grpc_server_port can't be used with jsonrpc_require_flashbots_signature, gRPC calls are not signed"
+            );
+        }
+        #[cfg(not(feature = "devp2p"))]
+        if config.devp2p_mempool.is_some() {
+            eyre::bail!("devp2p_mempool is set but rbuilder was built without the devp2p feature");
+        }
 
         Ok(OrderInputConfig {
             ignore_cancellable_orders: config.ignore_cancellable_orders,
             ignore_blobs: config.ignore_blobs,
             ipc_path: el_node_ipc_path,
+            mempool_sources: config
+                .mempool_sources
+                .iter()
+                .map(|source| source.to_source())
+                .collect::<eyre::Result<_>>()?,
+            el_node_mempool: config.el_node_mempool,
+            #[cfg(feature = "devp2p")]
+            devp2p_listener: match &config.devp2p_mempool {
+                Some(listener) => Some(listener.to_listener(config.chain_spec()?)?),
+                None => None,
+            },
             server_port: config.jsonrpc_server_port,
             server_ip: config.jsonrpc_server_ip(),
             serve_max_connections: 4096,
+            server_transport: config.jsonrpc_server_transport,
+            builder_names: config.builder_names.clone(),
+            require_signed_cancellations: config.jsonrpc_require_signed_cancellations,
+            require_flashbots_signature: config.jsonrpc_require_flashbots_signature,
+            request_limits: RequestLimits {
+                max_request_body_size: config.jsonrpc_server_max_request_body_size,
+                max_batch_size: config.jsonrpc_server_max_batch_size,
+                max_bundle_txs: config.jsonrpc_server_max_bundle_txs,
+                blocking_decode_min_size: config.jsonrpc_server_blocking_decode_min_size,
+                max_blocking_decodes: config.jsonrpc_server_max_blocking_decodes,
+            },
+            rate_limits: RpcRateLimits {
+                per_signer: config.jsonrpc_signer_rate_limit,
+                per_ip: config.jsonrpc_ip_rate_limit,
+                trusted_proxies: config.jsonrpc_trusted_proxies()?,
+            },
+            grpc_server_address: config
+                .grpc_server_port
+                .map(|port| SocketAddr::V4(SocketAddrV4::new(config.jsonrpc_server_ip(), port))),
+            grpc_trust_signing_address: config.grpc_trust_signing_address,
             results_channel_timeout: Duration::from_millis(50),
             input_channel_buffer_size: 10_000,
+            tenants: Arc::new(TenantRegistry::new(config.tenants.clone())?),
+            sync: config.orderpool_sync_config()?,
+            orderflow_sharing: config.orderflow_sharing_config()?,
+            sim_bundle: config.sim_bundle.then(|| {
+                SimBundleService::new(
+                    Default::default(),
+                    &config.sim_bundle_pending_signers,
+                    config.sim_bundle_max_concurrent_simulations,
+                )
+            }),
+            tx_type_forks: Arc::new(TxTypeForks::new(&config.chain_spec()?)),
+            order_archive_path: config.order_archive_path.clone(),
         })
     }
 
     pub fn default_e2e() -> Self {
         Self {
             ipc_path: PathBuf::from("/tmp/anvil.ipc"),
+            mempool_sources: Vec::new(),
+            el_node_mempool: true,
+            #[cfg(feature = "devp2p")]
+            devp2p_listener: None,
             results_channel_timeout: Duration::new(5, 0),
             ignore_cancellable_orders: false,
             ignore_blobs: false,
             input_channel_buffer_size: 10,
             serve_max_connections: 4096,
+            server_transport: Default::default(),
+            builder_names: Vec::new(),
+            require_signed_cancellations: true,
+            require_flashbots_signature: false,
+            request_limits: Default::default(),
+            rate_limits: Default::default(),
+            grpc_server_address: None,
+            grpc_trust_signing_address: false,
             server_ip: Ipv4Addr::new(127, 0, 0, 1),
             server_port: 0,
+            tenants: Default::default(),
+            sync: Default::default(),
+            orderflow_sharing: Default::default(),
+            sim_bundle: None,
+            tx_type_forks: Default::default(),
+            order_archive_path: None,
         }
     }
 }
 
+/// Cancellation of the bundles/sbundles with this hash sent by signer (mev_cancelBundleByHash) or of the private tx
+/// with this tx hash sent by signer (eth_cancelPrivateTransaction).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CancelBundleByHash {
+    pub hash: B256,
+    pub signer: Address,
+}
+
 /// Commands we can get from RPC or mempool fetcher.
 #[derive(Debug, Clone)]
 pub enum ReplaceableOrderPoolCommand {
@@ -157,6 +376,7 @@ pub enum ReplaceableOrderPoolCommand {
     /// Cancellation for sbundle
     CancelShareBundle(CancelShareBundle),
     CancelBundle(BundleReplacementKey),
+    CancelBundleByHash(CancelBundleByHash),
 }
 
 impl ReplaceableOrderPoolCommand {
@@ -164,7 +384,8 @@ impl ReplaceableOrderPoolCommand {
         match self {
             ReplaceableOrderPoolCommand::Order(o) => o.target_block(),
             ReplaceableOrderPoolCommand::CancelShareBundle(c) => Some(c.block),
-            ReplaceableOrderPoolCommand::CancelBundle(_) => None,
+            ReplaceableOrderPoolCommand::CancelBundle(_)
+            | ReplaceableOrderPoolCommand::CancelBundleByHash(_) => None,
         }
     }
 }
@@ -182,6 +403,7 @@ pub async fn start_orderpool_jobs<P>(
     global_cancel: CancellationToken,
     order_sender: mpsc::Sender<ReplaceableOrderPoolCommand>,
     order_receiver: mpsc::Receiver<ReplaceableOrderPoolCommand>,
+    conflict_cache: ConflictCache,
 ) -> eyre::Result<(JoinHandle<()>, OrderPoolSubscriber)>
 where
     P: StateProviderFactory + 'static,
@@ -193,7 +415,12 @@ where
         warn!("ignore_blobs is set to true, some order input is ignored");
     }
 
-    let orderpool = Arc::new(Mutex::new(OrderPool::new()));
+    if !config.tenants.is_empty() {
+        tenants::set_tenant_registry(config.tenants.clone());
+    }
+    let orderpool = Arc::new(Mutex::new(
+        OrderPool::new().with_tenants(config.tenants.clone()),
+    ));
     let subscriber = OrderPoolSubscriber {
         orderpool: orderpool.clone(),
     };
@@ -202,13 +429,22 @@ where
         config.clone(),
         provider_factory,
         orderpool.clone(),
+        conflict_cache,
         global_cancel.clone(),
     )
     .await?;
+    // shared by the json rpc and the gRPC server
+    let rate_limiter = config
+        .rate_limits
+        .is_enabled()
+        .then(|| Arc::new(RpcRateLimiter::new(&config.rate_limits)));
+    let signed_cancellations = Arc::new(SignedCancellations::default());
     let rpc_server = rpc_server::start_server_accepting_bundles(
         config.clone(),
         order_sender.clone(),
         extra_rpc,
+        rate_limiter.clone(),
+        signed_cancellations.clone(),
         global_cancel.clone(),
     )
     .await?;
@@ -218,6 +454,74 @@ where
         global_cancel.clone(),
     )
     .await?;
+    let mut handles = vec![clean_job, rpc_server, txpool_fetcher];
+    if let Some(address) = config.grpc_server_address {
+        #[cfg(feature = "grpc")]
+        handles.push(
+            grpc_server::start_grpc_server_accepting_orders(
+                address,
+                config.clone(),
+                order_sender.clone(),
+                rate_limiter.clone(),
+                signed_cancellations.clone(),
+                global_cancel.clone(),
+            )
+            .await?,
+        );
+        #[cfg(not(feature = "grpc"))]
+        warn!(%address, "grpc_server_port is set but rbuilder was built without the grpc feature, ignoring");
+    }
+    if let Some(address) = config.sync.server_address {
+        handles.push(
+            orderpool_sync::spawn_orderpool_sync_server(
+                address,
+                config.sync.secret.clone(),
+                orderpool.clone(),
+                global_cancel.clone(),
+            )
+            .await?,
+        );
+    }
+    if let Some(peer_address) = config.sync.peer_address.clone() {
+        handles.push(orderpool_sync::spawn_orderpool_sync_client(
+            peer_address,
+            config.sync.secret.clone(),
+            order_sender.clone(),
+            config.results_channel_timeout,
+            global_cancel.clone(),
+        ));
+    }
+
+    // Orders from the orderflow sharing peers come on their own channel so we never forward them again.
+    let (peer_order_sender, mut peer_order_receiver) =
+        mpsc::channel(config.input_channel_buffer_size);
+    if let Some(address) = config.orderflow_sharing.ingress_address {
+        handles.push(spawn_orderflow_ingress(
+            &config.orderflow_sharing,
+            address,
+            config.request_limits.max_request_body_size as u64,
+            peer_order_sender.clone(),
+            config.results_channel_timeout,
+            global_cancel.clone(),
+        )?);
+    }
+    drop(peer_order_sender);
+    let orderflow_egress = if config.orderflow_sharing.peers.is_empty() {
+        None
+    } else {
+        let (egress, egress_handles) =
+            OrderflowEgress::spawn(&config.orderflow_sharing, global_cancel.clone())?;
+        handles.extend(egress_handles);
+        Some(egress)
+    };
+    let order_archive = match &config.order_archive_path {
+        Some(path) => {
+            let (order_archive, order_archive_handle) = OrderArchive::spawn(path.clone())?;
+            handles.push(order_archive_handle);
+            Some(order_archive)
+        }
+        None => None,
+    };
 
     let handle = tokio::spawn(async move {
         info!("OrderPoolJobs: started");
@@ -225,8 +529,10 @@ where
         // @Maybe we should add sleep here because each new order will trigger locking
         let mut new_commands = Vec::new();
         let mut order_receiver = order_receiver;
+        let mut peers_open = true;
 
         loop {
+            let mut from_peers = false;
             tokio::select! {
                 _ = global_cancel.cancelled() => { break; },
                 n = order_receiver.recv_many(&mut new_commands, 100) => {
@@ -234,6 +540,13 @@ where
                         break;
                     }
                 },
+                n = peer_order_receiver.recv_many(&mut new_commands, 100), if peers_open => {
+                    if n == 0 {
+                        peers_open = false;
+                        continue;
+                    }
+                    from_peers = true;
+                },
             };
 
             // Ignore orders with cancellations if we can't support them
@@ -246,7 +559,7 @@ where
                             }
                             o.replacement_key().is_some()
                         },
-                        ReplaceableOrderPoolCommand::CancelShareBundle(_)|ReplaceableOrderPoolCommand::CancelBundle(_) => true
+                        ReplaceableOrderPoolCommand::CancelShareBundle(_)|ReplaceableOrderPoolCommand::CancelBundle(_)|ReplaceableOrderPoolCommand::CancelBundleByHash(_) => true
                     };
                     !cancellable_order
                 })
@@ -261,12 +574,23 @@ where
                             }
                             o.has_blobs()
                         },
-                        ReplaceableOrderPoolCommand::CancelShareBundle(_)|ReplaceableOrderPoolCommand::CancelBundle(_) => false
+                        ReplaceableOrderPoolCommand::CancelShareBundle(_)|ReplaceableOrderPoolCommand::CancelBundle(_)|ReplaceableOrderPoolCommand::CancelBundleByHash(_) => false
                     };
                     !has_blobs
                 })
             }
 
+            if let Some(egress) = orderflow_egress.as_ref().filter(|_| !from_peers) {
+                for command in &new_commands {
+                    egress.forward(command);
+                }
+            }
+            if let Some(order_archive) = &order_archive {
+                for command in &new_commands {
+                    order_archive.record(command, from_peers);
+                }
+            }
+
             {
                 let mut orderpool = orderpool.lock();
                 orderpool.process_commands(new_commands.clone());
@@ -274,7 +598,9 @@ where
             new_commands.clear();
         }
 
-        for handle in [clean_job, rpc_server, txpool_fetcher] {
+        // the archive task finishes once it's dropped
+        drop(order_archive);
+        for handle in handles {
             handle
                 .await
                 .map_err(|err| {
@@ -1,68 +1,184 @@
 use ahash::HashMap;
+use std::collections::VecDeque;
 
 use crate::primitives::{Order, OrderId, OrderReplacementKey};
 
 use super::{order_sink::OrderSink, replaceable_order_sink::ReplaceableOrderSink};
 
+/// Max number of [`ReplacementHistoryEntry`] we keep per replacement key, older ones are dropped.
+pub const MAX_REPLACEMENT_HISTORY_LEN: usize = 32;
+
 /// Handles all replacement and cancellation for bundles and sbundles by receiving
 /// low level orderflow data via ReplaceableOrderSink and forwarding to an OrderSink.
 /// The OrderReplacementManager works for a single block.
 /// IMPORTANT: Due to infra problems we can get notifications our of order, we must always honor the one
 /// with higher sequence_number or the cancel.
+/// Sequence numbers are enforced to be strictly increasing: an update with a sequence_number lower or equal than the
+/// current one is never forwarded, no matter the order in which they arrive.
 /// Although all the structs and fields say "bundle" we always reefer to Bundle or ShareBundle
-/// For each bundle we keep the current BundleReplacementState
+/// For each bundle we keep the current BundleReplacementState and a capped history of what we did with each update.
+/// Replacement uuids are scoped per signer (the key is (uuid, signer)) so a signer reusing someone else's uuid gets
+/// an independent key: it can't replace, cancel or block (by arriving first) the other signer's bundle.
+/// Since the OrderId of a bundle only depends on its content two keys can forward the same order (eg: a signer
+/// replaying a bundle copied from another one), the order stays in the sink until every key holding it lets it go.
 #[derive(Debug)]
 pub struct OrderReplacementManager {
-    sink: Box<dyn OrderSink>,
+    forwarded: ForwardedOrders,
     replacement_states: HashMap<OrderReplacementKey, BundleReplacementState>,
+    replacement_histories: HashMap<OrderReplacementKey, VecDeque<ReplacementHistoryEntry>>,
+}
+
+/// What we did with an update for a replacement key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacementOutcome {
+    /// Newest version, forwarded to the sink.
+    Applied,
+    /// sequence_number <= current one, ignored.
+    Stale,
+    /// Arrived after the cancellation, ignored.
+    AfterCancellation,
+    /// Cancellation
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplacementHistoryEntry {
+    /// None for cancellations
+    pub sequence_number: Option<u64>,
+    /// None for cancellations
+    pub order_id: Option<OrderId>,
+    pub outcome: ReplacementOutcome,
 }
 
 impl OrderReplacementManager {
     pub fn new(sink: Box<dyn OrderSink>) -> Self {
         Self {
-            sink,
+            forwarded: ForwardedOrders {
+                sink,
+                holders: Default::default(),
+            },
             replacement_states: Default::default(),
+            replacement_histories: Default::default(),
+        }
+    }
+
+    /// Updates received for key (oldest first), capped to [`MAX_REPLACEMENT_HISTORY_LEN`].
+    pub fn replacement_history(
+        &self,
+        key: &OrderReplacementKey,
+    ) -> Option<&VecDeque<ReplacementHistoryEntry>> {
+        self.replacement_histories.get(key)
+    }
+
+    fn push_history(&mut self, key: OrderReplacementKey, entry: ReplacementHistoryEntry) {
+        let history = self.replacement_histories.entry(key).or_default();
+        if history.len() >= MAX_REPLACEMENT_HISTORY_LEN {
+            history.pop_front();
         }
+        history.push_back(entry);
     }
 }
 
 impl ReplaceableOrderSink for OrderReplacementManager {
     fn insert_order(&mut self, order: Order) -> bool {
         if let Some((rep_key, sequence_number)) = order.replacement_key_and_sequence_number() {
-            match self.replacement_states.entry(rep_key) {
+            let order_id = order.id();
+            let (ret, outcome) = match self.replacement_states.entry(rep_key.clone()) {
                 std::collections::hash_map::Entry::Occupied(mut e) => {
                     e.get_mut()
-                        .insert_order(order, sequence_number, &mut self.sink)
+                        .insert_order(&rep_key, order, sequence_number, &mut self.forwarded)
                 }
                 std::collections::hash_map::Entry::Vacant(e) => {
                     // New element
                     e.insert(BundleReplacementState::Valid(ValidBundleState {
                         sequence_number,
-                        order_id: order.id(),
+                        order_id,
                     }));
-                    self.sink.insert_order(order)
+                    (
+                        self.forwarded.insert_order(&rep_key, order),
+                        ReplacementOutcome::Applied,
+                    )
                 }
-            }
+            };
+            self.push_history(
+                rep_key,
+                ReplacementHistoryEntry {
+                    sequence_number: Some(sequence_number),
+                    order_id: Some(order_id),
+                    outcome,
+                },
+            );
+            ret
         } else {
-            self.sink.insert_order(order)
+            self.forwarded.sink.insert_order(order)
         }
     }
 
     fn remove_bundle(&mut self, key: OrderReplacementKey) -> bool {
-        match self.replacement_states.entry(key) {
+        let ret = match self.replacement_states.entry(key.clone()) {
             std::collections::hash_map::Entry::Occupied(mut e) => {
-                e.get_mut().cancel_order(&mut self.sink)
+                e.get_mut().cancel_order(&key, &mut self.forwarded)
             }
             std::collections::hash_map::Entry::Vacant(e) => {
                 // New cancelled element (usually out of order notification)
                 e.insert(BundleReplacementState::Cancelled);
                 true
             }
-        }
+        };
+        self.push_history(
+            key,
+            ReplacementHistoryEntry {
+                sequence_number: None,
+                order_id: None,
+                outcome: ReplacementOutcome::Cancelled,
+            },
+        );
+        ret
+    }
+
+    fn remove_tx(&mut self, id: OrderId) -> bool {
+        self.forwarded.sink.remove_order(id)
     }
 
     fn is_alive(&self) -> bool {
-        self.sink.is_alive()
+        self.forwarded.sink.is_alive()
+    }
+}
+
+/// Sink wrapper counting which replacement keys currently hold each forwarded order.
+#[derive(Debug)]
+struct ForwardedOrders {
+    sink: Box<dyn OrderSink>,
+    holders: HashMap<OrderId, Vec<OrderReplacementKey>>,
+}
+
+impl ForwardedOrders {
+    /// Only the first holder of an order inserts it on the sink.
+    fn insert_order(&mut self, key: &OrderReplacementKey, order: Order) -> bool {
+        let holders = self.holders.entry(order.id()).or_default();
+        let first = holders.is_empty();
+        if !holders.contains(key) {
+            holders.push(key.clone());
+        }
+        if first {
+            self.sink.insert_order(order)
+        } else {
+            true
+        }
+    }
+
+    /// Only the last holder of an order removes it from the sink.
+    fn remove_order(&mut self, key: &OrderReplacementKey, order_id: OrderId) -> bool {
+        let Some(holders) = self.holders.get_mut(&order_id) else {
+            return true;
+        };
+        holders.retain(|holder| holder != key);
+        if holders.is_empty() {
+            self.holders.remove(&order_id);
+            self.sink.remove_order(order_id)
+        } else {
+            true
+        }
     }
 }
 
@@ -91,35 +207,37 @@ impl BundleReplacementState {
     /// returns false if some operation on the sink returned false
     fn insert_order(
         &mut self,
+        key: &OrderReplacementKey,
         order: Order,
         sequence_number: u64,
-        sink: &mut Box<dyn OrderSink>,
-    ) -> bool {
+        forwarded: &mut ForwardedOrders,
+    ) -> (bool, ReplacementOutcome) {
         match self {
             BundleReplacementState::Valid(valid) => {
                 //Update only newer
                 if sequence_number > valid.sequence_number {
                     let order_id = order.id();
-                    let mut ret = sink.remove_order(valid.order_id);
-                    if !sink.insert_order(order) {
+                    let mut ret = forwarded.remove_order(key, valid.order_id);
+                    if !forwarded.insert_order(key, order) {
                         ret = false;
                     }
                     valid.sequence_number = sequence_number;
                     valid.order_id = order_id;
-                    ret
+                    (ret, ReplacementOutcome::Applied)
                 } else {
-                    true
+                    (true, ReplacementOutcome::Stale)
                 }
             }
-            BundleReplacementState::Cancelled => true, //cancelled -> no more updates
+            //cancelled -> no more updates
+            BundleReplacementState::Cancelled => (true, ReplacementOutcome::AfterCancellation),
         }
     }
 
     /// returns false if some operation on the sink returned false
-    fn cancel_order(&mut self, sink: &mut Box<dyn OrderSink>) -> bool {
+    fn cancel_order(&mut self, key: &OrderReplacementKey, forwarded: &mut ForwardedOrders) -> bool {
         match self {
             BundleReplacementState::Valid(valid) => {
-                let ret = sink.remove_order(valid.order_id);
+                let ret = forwarded.remove_order(key, valid.order_id);
                 *self = BundleReplacementState::Cancelled;
                 ret
             }
@@ -146,7 +264,10 @@ mod test {
         },
     };
 
-    use super::OrderReplacementManager;
+    use super::{
+        OrderReplacementManager, ReplacementHistoryEntry, ReplacementOutcome,
+        MAX_REPLACEMENT_HISTORY_LEN,
+    };
 
     struct TestDataGenerator {
         base: crate::primitives::TestDataGenerator,
@@ -192,6 +313,20 @@ mod test {
                 sequence_number: 0,
             }
         }
+
+        /// Same uuid as replacement_data but another signer.
+        fn create_squatter_replacement_data(
+            &mut self,
+            replacement_data: &BundleReplacementData,
+        ) -> BundleReplacementData {
+            BundleReplacementData {
+                key: BundleReplacementKey::new(
+                    replacement_data.key.key().id,
+                    self.base.base.create_address(),
+                ),
+                sequence_number: replacement_data.sequence_number,
+            }
+        }
     }
 
     /// non_replaceable should pass
@@ -381,4 +516,190 @@ mod test {
             sbundle_replacement_data.key,
         ));
     }
+
+    /// Out of order delivery of several versions should only forward the newest and never let an older one overwrite it.
+    #[test]
+    fn test_out_of_order_seq() {
+        let mut data_gen = TestDataGenerator::new();
+        let replacement_data_0 = data_gen.create_bundle_replacement_data();
+        let replacement_data_1 = replacement_data_0.next();
+        let replacement_data_2 = replacement_data_1.next();
+        let bundle_0 = Order::Bundle(data_gen.create_bundle(Some(replacement_data_0.clone())));
+        let bundle_1 = Order::Bundle(data_gen.create_bundle(Some(replacement_data_1)));
+        let bundle_2 = Order::Bundle(data_gen.create_bundle(Some(replacement_data_2)));
+        let bundle_0_id = bundle_0.id();
+        let bundle_1_id = bundle_1.id();
+        let bundle_2_id = bundle_2.id();
+
+        let mut order_sink = MockOrderSink::new();
+        // only version 1 and later version 2 reach the sink
+        order_sink
+            .expect_insert_order()
+            .times(1)
+            .withf(move |o| o.id() == bundle_1_id)
+            .return_const(true);
+        order_sink
+            .expect_remove_order()
+            .times(1)
+            .with(eq(bundle_1_id))
+            .return_const(true);
+        order_sink
+            .expect_insert_order()
+            .times(1)
+            .withf(move |o| o.id() == bundle_2_id)
+            .return_const(true);
+
+        let mut manager = OrderReplacementManager::new(Box::new(order_sink));
+        manager.insert_order(bundle_1.clone());
+        manager.insert_order(bundle_0);
+        manager.insert_order(bundle_2);
+        // redelivery of an old version
+        manager.insert_order(bundle_1);
+
+        let key = OrderReplacementKey::Bundle(replacement_data_0.key);
+        let history: Vec<_> = manager
+            .replacement_history(&key)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        let entry = |sequence_number, order_id, outcome| ReplacementHistoryEntry {
+            sequence_number: Some(sequence_number),
+            order_id: Some(order_id),
+            outcome,
+        };
+        assert_eq!(
+            history,
+            vec![
+                entry(1, bundle_1_id, ReplacementOutcome::Applied),
+                entry(0, bundle_0_id, ReplacementOutcome::Stale),
+                entry(2, bundle_2_id, ReplacementOutcome::Applied),
+                entry(1, bundle_1_id, ReplacementOutcome::Stale),
+            ]
+        );
+    }
+
+    /// History records late inserts after a cancellation and is capped.
+    #[test]
+    fn test_replacement_history() {
+        let mut data_gen = TestDataGenerator::new();
+        let mut replacement_data = data_gen.create_bundle_replacement_data();
+        let key = OrderReplacementKey::Bundle(replacement_data.key.clone());
+
+        let mut order_sink = MockOrderSink::new();
+        order_sink.expect_insert_order().return_const(true);
+        order_sink.expect_remove_order().return_const(true);
+        let mut manager = OrderReplacementManager::new(Box::new(order_sink));
+
+        manager.remove_bundle(key.clone());
+        manager.insert_order(Order::Bundle(
+            data_gen.create_bundle(Some(replacement_data.clone())),
+        ));
+        let history = manager.replacement_history(&key).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].outcome, ReplacementOutcome::Cancelled);
+        assert_eq!(history[0].sequence_number, None);
+        assert_eq!(history[1].outcome, ReplacementOutcome::AfterCancellation);
+
+        for _ in 0..MAX_REPLACEMENT_HISTORY_LEN {
+            replacement_data = replacement_data.next();
+            manager.insert_order(Order::Bundle(
+                data_gen.create_bundle(Some(replacement_data.clone())),
+            ));
+        }
+        let history = manager.replacement_history(&key).unwrap();
+        assert_eq!(history.len(), MAX_REPLACEMENT_HISTORY_LEN);
+        assert_eq!(
+            history.back().unwrap().sequence_number,
+            Some(replacement_data.sequence_number)
+        );
+        assert!(history
+            .iter()
+            .all(|entry| entry.outcome == ReplacementOutcome::AfterCancellation));
+    }
+
+    /// Another signer using the same uuid (even before the owner and with higher sequence numbers) can't replace,
+    /// cancel or block the owner's bundle.
+    #[test]
+    fn test_cross_signer_uuid_squatting() {
+        let mut data_gen = TestDataGenerator::new();
+        let replacement_data = data_gen.create_bundle_replacement_data();
+        let mut squatter_data = data_gen.create_squatter_replacement_data(&replacement_data);
+        squatter_data.sequence_number = 10;
+        let bundle = Order::Bundle(data_gen.create_bundle(Some(replacement_data.clone())));
+        let squatter_bundle = Order::Bundle(data_gen.create_bundle(Some(squatter_data.clone())));
+        let squatter_update = Order::Bundle(data_gen.create_bundle(Some(squatter_data.next())));
+        let bundle_id = bundle.id();
+        let squatter_bundle_id = squatter_bundle.id();
+
+        let mut order_sink = MockOrderSink::new();
+        order_sink
+            .expect_insert_order()
+            .times(1)
+            .withf(move |o| o.id() == squatter_bundle_id)
+            .return_const(true);
+        order_sink
+            .expect_insert_order()
+            .times(1)
+            .withf(move |o| o.id() == bundle_id)
+            .return_const(true);
+        // only the squatter's own bundle goes away
+        order_sink
+            .expect_remove_order()
+            .times(1)
+            .with(eq(squatter_bundle_id))
+            .return_const(true);
+
+        let mut manager = OrderReplacementManager::new(Box::new(order_sink));
+        manager.insert_order(squatter_bundle);
+        manager.insert_order(bundle);
+        manager.remove_bundle(OrderReplacementKey::Bundle(squatter_data.key));
+        // replayed cancellation and late update of the squatter
+        manager.remove_bundle(OrderReplacementKey::Bundle(squatter_data.key));
+        manager.insert_order(squatter_update);
+
+        let history = manager
+            .replacement_history(&OrderReplacementKey::Bundle(replacement_data.key))
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].outcome, ReplacementOutcome::Applied);
+    }
+
+    /// A bundle copied by another signer has the same OrderId, it must stay until its owner cancels it.
+    #[test]
+    fn test_copied_bundle_across_signers() {
+        let mut data_gen = TestDataGenerator::new();
+        let replacement_data = data_gen.create_bundle_replacement_data();
+        let copier_data = data_gen.create_squatter_replacement_data(&replacement_data);
+        let bundle = data_gen.create_bundle(Some(replacement_data.clone()));
+        let mut copied_bundle = bundle.clone();
+        copied_bundle.replacement_data = Some(copier_data.clone());
+        let (bundle, copied_bundle) = (Order::Bundle(bundle), Order::Bundle(copied_bundle));
+        assert_eq!(bundle.id(), copied_bundle.id());
+        let bundle_id = bundle.id();
+
+        let mut order_sink = MockOrderSink::new();
+        order_sink
+            .expect_insert_order()
+            .times(1)
+            .withf(move |o| o.id() == bundle_id)
+            .return_const(true);
+        order_sink
+            .expect_remove_order()
+            .times(1)
+            .with(eq(bundle_id))
+            .return_const(true);
+
+        let mut manager = OrderReplacementManager::new(Box::new(order_sink));
+        // copier first: the owner's version is not forwarded twice
+        manager.insert_order(copied_bundle);
+        manager.insert_order(bundle);
+        // copier cancelling does not remove the owner's bundle
+        manager.remove_bundle(OrderReplacementKey::Bundle(copier_data.key));
+        // replayed copier cancellation neither
+        manager.remove_bundle(OrderReplacementKey::Bundle(copier_data.key));
+        manager.remove_bundle(OrderReplacementKey::Bundle(replacement_data.key));
+        // replayed owner cancellation
+        manager.remove_bundle(OrderReplacementKey::Bundle(replacement_data.key));
+    }
 }
@@ -0,0 +1,624 @@
+//! Order schema negotiation of the input RPC (see [`super::rpc_server`]) so orderflow providers can find out what this
+//! build understands instead of having their fields silently dropped when the bundle formats evolve.
+//! rbuilder_capabilities lists the schema versions, the bundle versions and fields of each intake method and the
+//! privacy hints we know. Every http request can pick a schema version with the X-Order-Schema-Version header:
+//! - 1 (the default, also for WebSocket messages which have no headers): unknown fields and hints are ignored.
+//! - 2: eth_sendBundle/mev_sendBundle/eth_sendPrivateTransaction with fields, hints or bundle versions we don't
+//!   know are rejected (-32602) listing them.
+//!
+//! Requests asking for a version we don't support are rejected the same way.
+//! The field lists mirror the serde definitions in [`crate::primitives::serialize`], the tests compare them with the
+//! fields serde derives.
+
+use crate::primitives::{serialize::SHARE_BUNDLE_VERSIONS, PrivacyHint};
+use hyper_0_14::{header::HeaderMap, Body, Request};
+use jsonrpsee::types::{ErrorObject, Params};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+pub const ORDER_SCHEMA_HEADER: &str = "x-order-schema-version";
+/// Unknown fields are ignored (what we always did).
+pub const LENIENT_SCHEMA_VERSION: u32 = 1;
+/// Unknown fields are rejected.
+pub const STRICT_SCHEMA_VERSION: u32 = 2;
+pub const SUPPORTED_SCHEMA_VERSIONS: &[u32] = &[LENIENT_SCHEMA_VERSION, STRICT_SCHEMA_VERSION];
+
+/// [`crate::primitives::serialize::RawBundle`]
+const BUNDLE_FIELDS: &[&str] = &[
+    "blockNumber",
+    "txs",
+    "revertingTxHashes",
+    "replacementUuid",
+    "signingAddress",
+    "minTimestamp",
+    "maxTimestamp",
+    "replacementNonce",
+];
+/// [`crate::primitives::serialize::RawShareBundle`] and its parts.
+const SHARE_BUNDLE_FIELDS: &[&str] = &[
+    "version",
+    "inclusion",
+    "body",
+    "validity",
+    "metadata",
+    "privacy",
+    "replacementUuid",
+];
+const INCLUSION_FIELDS: &[&str] = &["block", "maxBlock"];
+const BODY_FIELDS: &[&str] = &["tx", "canRevert", "revertMode", "bundle"];
+const VALIDITY_FIELDS: &[&str] = &["refund", "refundConfig"];
+const REFUND_FIELDS: &[&str] = &["bodyIdx", "percent"];
+const REFUND_CONFIG_FIELDS: &[&str] = &["address", "percent"];
+const METADATA_FIELDS: &[&str] = &["signer", "replacementNonce", "cancelled"];
+const PRIVACY_FIELDS: &[&str] = &["hints", "builders"];
+/// [`crate::primitives::serialize::RawPrivateTx`], privacy has PRIVACY_FIELDS.
+const PRIVATE_TX_FIELDS: &[&str] = &["tx", "maxBlockNumber", "preferences"];
+const PREFERENCES_FIELDS: &[&str] = &["fast", "privacy"];
+
+/// X-Order-Schema-Version of the http request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestedSchema {
+    Default,
+    Version(u32),
+    /// Not a number.
+    Invalid,
+}
+
+impl RequestedSchema {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers.get(ORDER_SCHEMA_HEADER) {
+            None => RequestedSchema::Default,
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .map_or(RequestedSchema::Invalid, RequestedSchema::Version),
+        }
+    }
+
+    /// The version we'll use for the request, Err if we don't support the one asked for.
+    pub fn negotiate(&self) -> Result<u32, ErrorObject<'static>> {
+        let unsupported = |requested: String| {
+            ErrorObject::owned(
+                -32602,
+                format!(
+                    "unsupported order schema version {}, supported: {:?}",
+                    requested, SUPPORTED_SCHEMA_VERSIONS
+                ),
+                Some(SUPPORTED_SCHEMA_VERSIONS),
+            )
+        };
+        match self {
+            RequestedSchema::Default => Ok(LENIENT_SCHEMA_VERSION),
+            RequestedSchema::Version(version) if SUPPORTED_SCHEMA_VERSIONS.contains(version) => {
+                Ok(*version)
+            }
+            RequestedSchema::Version(version) => Err(unsupported(version.to_string())),
+            RequestedSchema::Invalid => Err(unsupported("(not a number)".to_string())),
+        }
+    }
+}
+
+tokio::task_local! {
+    static REQUESTED_SCHEMA: RequestedSchema;
+}
+
+/// Schema asked for by the http request being handled (Default outside the http middleware).
+pub fn requested_schema() -> RequestedSchema {
+    REQUESTED_SCHEMA
+        .try_with(|requested| *requested)
+        .unwrap_or(RequestedSchema::Default)
+}
+
+/// Version the http request being handled negotiated, Err if it asked for one we don't support.
+/// Has to be called on the request task (the blocking decodes don't see the header).
+pub fn negotiated_schema_version() -> Result<u32, ErrorObject<'static>> {
+    requested_schema().negotiate()
+}
+
+/// eth_sendBundle params check, call before decoding (on the decoding thread, it parses the params).
+pub fn check_bundle_fields(
+    version: u32,
+    params: &Params<'static>,
+) -> Result<(), ErrorObject<'static>> {
+    check_fields(version, params, |bundle, unknown| {
+        // the positional format has no field names
+        object_unknown_fields(bundle, "", BUNDLE_FIELDS, unknown);
+    })
+}
+
+/// mev_sendBundle params check, see [`check_bundle_fields`].
+pub fn check_share_bundle_fields(
+    version: u32,
+    params: &Params<'static>,
+) -> Result<(), ErrorObject<'static>> {
+    check_fields(version, params, |bundle, unknown| {
+        share_bundle_unknown_fields(bundle, "", unknown)
+    })
+}
+
+/// eth_sendPrivateTransaction params check, see [`check_bundle_fields`].
+pub fn check_private_tx_fields(
+    version: u32,
+    params: &Params<'static>,
+) -> Result<(), ErrorObject<'static>> {
+    check_fields(version, params, |private_tx, unknown| {
+        let Some(private_tx) = object_unknown_fields(private_tx, "", PRIVATE_TX_FIELDS, unknown)
+        else {
+            return;
+        };
+        if let Some(preferences) = private_tx.get("preferences") {
+            if let Some(preferences) =
+                object_unknown_fields(preferences, "preferences", PREFERENCES_FIELDS, unknown)
+            {
+                if let Some(privacy) = preferences.get("privacy") {
+                    privacy_unknown_fields(privacy, "preferences.privacy", unknown);
+                }
+            }
+        }
+    })
+}
+
+/// Rejects the request if version is the strict schema and the first param has anything unknown_fields finds.
+/// Params we can't parse are left to the decoding.
+fn check_fields(
+    version: u32,
+    params: &Params<'static>,
+    unknown_fields: impl FnOnce(&Value, &mut Vec<String>),
+) -> Result<(), ErrorObject<'static>> {
+    if version < STRICT_SCHEMA_VERSION {
+        return Ok(());
+    }
+    let Some(Ok(Value::Array(params))) = params.as_str().map(serde_json::from_str::<Value>) else {
+        return Ok(());
+    };
+    let mut unknown = Vec::new();
+    if let Some(order) = params.first() {
+        unknown_fields(order, &mut unknown);
+    }
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(ErrorObject::owned(
+        -32602,
+        format!(
+            "unsupported on order schema version {}: {}",
+            version,
+            unknown.join(", ")
+        ),
+        Some(unknown),
+    ))
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+/// Adds the fields of value (at path) not in known to unknown. Returns value if it's an object.
+fn object_unknown_fields<'a>(
+    value: &'a Value,
+    path: &str,
+    known: &[&str],
+    unknown: &mut Vec<String>,
+) -> Option<&'a Map<String, Value>> {
+    let object = value.as_object()?;
+    for field in object.keys() {
+        if !known.contains(&field.as_str()) {
+            unknown.push(join(path, field));
+        }
+    }
+    Some(object)
+}
+
+/// Calls check on every element of the array field of object.
+fn array_field(
+    object: &Map<String, Value>,
+    path: &str,
+    field: &str,
+    mut check: impl FnMut(&Value, &str),
+) {
+    if let Some(Value::Array(elements)) = object.get(field) {
+        for (idx, element) in elements.iter().enumerate() {
+            check(element, &format!("{}[{}]", join(path, field), idx));
+        }
+    }
+}
+
+fn share_bundle_unknown_fields(bundle: &Value, path: &str, unknown: &mut Vec<String>) {
+    let Some(bundle) = object_unknown_fields(bundle, path, SHARE_BUNDLE_FIELDS, unknown) else {
+        return;
+    };
+    if let Some(version) = bundle.get("version").and_then(Value::as_str) {
+        if !SHARE_BUNDLE_VERSIONS.contains(&version) {
+            unknown.push(format!("{} ({})", join(path, "version"), version));
+        }
+    }
+    if let Some(inclusion) = bundle.get("inclusion") {
+        object_unknown_fields(
+            inclusion,
+            &join(path, "inclusion"),
+            INCLUSION_FIELDS,
+            unknown,
+        );
+    }
+    array_field(bundle, path, "body", |body, body_path| {
+        if let Some(body) = object_unknown_fields(body, body_path, BODY_FIELDS, unknown) {
+            if let Some(inner) = body.get("bundle") {
+                share_bundle_unknown_fields(inner, &join(body_path, "bundle"), unknown);
+            }
+        }
+    });
+    if let Some(validity) = bundle.get("validity") {
+        let validity_path = join(path, "validity");
+        if let Some(validity) =
+            object_unknown_fields(validity, &validity_path, VALIDITY_FIELDS, unknown)
+        {
+            array_field(validity, &validity_path, "refund", |refund, refund_path| {
+                object_unknown_fields(refund, refund_path, REFUND_FIELDS, unknown);
+            });
+            array_field(
+                validity,
+                &validity_path,
+                "refundConfig",
+                |config, config_path| {
+                    object_unknown_fields(config, config_path, REFUND_CONFIG_FIELDS, unknown);
+                },
+            );
+        }
+    }
+    if let Some(metadata) = bundle.get("metadata") {
+        object_unknown_fields(metadata, &join(path, "metadata"), METADATA_FIELDS, unknown);
+    }
+    if let Some(privacy) = bundle.get("privacy") {
+        privacy_unknown_fields(privacy, &join(path, "privacy"), unknown);
+    }
+}
+
+/// Also reports the hints we don't know.
+fn privacy_unknown_fields(privacy: &Value, path: &str, unknown: &mut Vec<String>) {
+    if let Some(privacy) = object_unknown_fields(privacy, path, PRIVACY_FIELDS, unknown) {
+        array_field(privacy, path, "hints", |hint, hint_path| {
+            if let Some(hint) = hint.as_str() {
+                if PrivacyHint::parse(hint).is_none() {
+                    unknown.push(format!("{} ({})", hint_path, hint));
+                }
+            }
+        });
+    }
+}
+
+/// Fields of an object whose fields (at path) are object_fields.
+fn nested_fields(path: &str, object_fields: &[&str]) -> Vec<String> {
+    object_fields
+        .iter()
+        .map(|field| join(path, field))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodCapabilities {
+    pub method: &'static str,
+    /// Accepted values of the version field of the param (empty if it has none).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<&'static str>,
+    /// Besides the object, the positional params of mev-geth are accepted.
+    pub positional_params: bool,
+    /// Every field we read, nested ones as paths ("body[].bundle" has the fields of the top level bundle).
+    pub fields: Vec<String>,
+}
+
+/// Response of rbuilder_capabilities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderSchemaCapabilities {
+    pub rbuilder_version: &'static str,
+    pub schema_versions: &'static [u32],
+    /// Used when the request doesn't send X-Order-Schema-Version.
+    pub default_schema_version: u32,
+    pub methods: Vec<MethodCapabilities>,
+    pub privacy_hints: Vec<&'static str>,
+}
+
+pub fn capabilities() -> OrderSchemaCapabilities {
+    let mut share_bundle_fields = nested_fields("", SHARE_BUNDLE_FIELDS);
+    share_bundle_fields.extend(nested_fields("inclusion", INCLUSION_FIELDS));
+    share_bundle_fields.extend(nested_fields("body[]", BODY_FIELDS));
+    share_bundle_fields.extend(nested_fields("validity", VALIDITY_FIELDS));
+    share_bundle_fields.extend(nested_fields("validity.refund[]", REFUND_FIELDS));
+    share_bundle_fields.extend(nested_fields(
+        "validity.refundConfig[]",
+        REFUND_CONFIG_FIELDS,
+    ));
+    share_bundle_fields.extend(nested_fields("metadata", METADATA_FIELDS));
+    share_bundle_fields.extend(nested_fields("privacy", PRIVACY_FIELDS));
+    let mut private_tx_fields = nested_fields("", PRIVATE_TX_FIELDS);
+    private_tx_fields.extend(nested_fields("preferences", PREFERENCES_FIELDS));
+    private_tx_fields.extend(nested_fields("preferences.privacy", PRIVACY_FIELDS));
+    OrderSchemaCapabilities {
+        rbuilder_version: env!("CARGO_PKG_VERSION"),
+        schema_versions: SUPPORTED_SCHEMA_VERSIONS,
+        default_schema_version: LENIENT_SCHEMA_VERSION,
+        methods: vec![
+            MethodCapabilities {
+                method: "eth_sendBundle",
+                versions: Vec::new(),
+                positional_params: true,
+                fields: nested_fields("", BUNDLE_FIELDS),
+            },
+            MethodCapabilities {
+                method: "mev_sendBundle",
+                versions: SHARE_BUNDLE_VERSIONS.to_vec(),
+                positional_params: false,
+                fields: share_bundle_fields,
+            },
+            MethodCapabilities {
+                method: "eth_sendPrivateTransaction",
+                versions: Vec::new(),
+                positional_params: false,
+                fields: private_tx_fields,
+            },
+        ],
+        privacy_hints: PrivacyHint::ALL.iter().map(PrivacyHint::as_str).collect(),
+    }
+}
+
+/// jsonrpsee http middleware making the X-Order-Schema-Version header available via [`requested_schema`].
+#[derive(Debug, Clone, Default)]
+pub struct OrderSchemaLayer;
+
+impl<S> Layer<S> for OrderSchemaLayer {
+    type Service = OrderSchema<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OrderSchema { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderSchema<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for OrderSchema<S>
+where
+    S: Service<Request<Body>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = tokio::task::futures::TaskLocalFuture<RequestedSchema, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let requested = RequestedSchema::from_headers(request.headers());
+        REQUESTED_SCHEMA.scope(requested, self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{
+        serialize::{
+            RawBundle, RawPrivateTx, RawPrivateTxPreferences, RawShareBundle, RawShareBundleBody,
+            RawShareBundleInclusion, RawShareBundleMetadatada, RawShareBundlePrivacy,
+            RawShareBundleValidity,
+        },
+        Refund, RefundConfig,
+    };
+    use alloy_primitives::{Address, Bytes, B256, U64};
+    use serde::{
+        de::{self, DeserializeOwned, Visitor},
+        Deserializer,
+    };
+    use uuid::Uuid;
+
+    /// Field names serde derives for T (what it passes to deserialize_struct).
+    fn serde_fields<T: DeserializeOwned>() -> Vec<&'static str> {
+        struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+        impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+            type Error = de::value::Error;
+
+            fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+                Err(de::Error::custom("not a struct"))
+            }
+
+            fn deserialize_struct<V: Visitor<'de>>(
+                self,
+                _name: &'static str,
+                fields: &'static [&'static str],
+                _visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                *self.0 = fields;
+                Err(de::Error::custom("fields captured"))
+            }
+
+            serde::forward_to_deserialize_any! {
+                bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+                unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+            }
+        }
+
+        let mut fields: &'static [&'static str] = &[];
+        let _ = T::deserialize(FieldsDeserializer(&mut fields));
+        let mut fields = fields.to_vec();
+        fields.sort_unstable();
+        fields
+    }
+
+    fn sorted(fields: &[&'static str]) -> Vec<&'static str> {
+        let mut fields = fields.to_vec();
+        fields.sort_unstable();
+        fields
+    }
+
+    /// The hand written lists must be exactly what serde reads.
+    #[test]
+    fn test_known_fields_are_serde_fields() {
+        assert_eq!(sorted(BUNDLE_FIELDS), serde_fields::<RawBundle>());
+        assert_eq!(
+            sorted(SHARE_BUNDLE_FIELDS),
+            serde_fields::<RawShareBundle>()
+        );
+        assert_eq!(
+            sorted(INCLUSION_FIELDS),
+            serde_fields::<RawShareBundleInclusion>()
+        );
+        assert_eq!(sorted(BODY_FIELDS), serde_fields::<RawShareBundleBody>());
+        assert_eq!(
+            sorted(VALIDITY_FIELDS),
+            serde_fields::<RawShareBundleValidity>()
+        );
+        assert_eq!(sorted(REFUND_FIELDS), serde_fields::<Refund>());
+        assert_eq!(sorted(REFUND_CONFIG_FIELDS), serde_fields::<RefundConfig>());
+        assert_eq!(
+            sorted(METADATA_FIELDS),
+            serde_fields::<RawShareBundleMetadatada>()
+        );
+        assert_eq!(
+            sorted(PRIVACY_FIELDS),
+            serde_fields::<RawShareBundlePrivacy>()
+        );
+        assert_eq!(sorted(PRIVATE_TX_FIELDS), serde_fields::<RawPrivateTx>());
+        assert_eq!(
+            sorted(PREFERENCES_FIELDS),
+            serde_fields::<RawPrivateTxPreferences>()
+        );
+    }
+
+    fn share_bundle_unknown(bundle: &Value) -> Vec<String> {
+        let mut unknown = Vec::new();
+        share_bundle_unknown_fields(bundle, "", &mut unknown);
+        unknown
+    }
+
+    #[test]
+    fn test_requested_schema() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            RequestedSchema::from_headers(&headers).negotiate().unwrap(),
+            LENIENT_SCHEMA_VERSION
+        );
+        headers.insert(ORDER_SCHEMA_HEADER, "2".parse().unwrap());
+        assert_eq!(
+            RequestedSchema::from_headers(&headers).negotiate().unwrap(),
+            STRICT_SCHEMA_VERSION
+        );
+        headers.insert(ORDER_SCHEMA_HEADER, "3".parse().unwrap());
+        assert!(RequestedSchema::from_headers(&headers).negotiate().is_err());
+        headers.insert(ORDER_SCHEMA_HEADER, "v2".parse().unwrap());
+        assert_eq!(
+            RequestedSchema::from_headers(&headers),
+            RequestedSchema::Invalid
+        );
+    }
+
+    /// The field lists must cover everything our own serialization produces.
+    #[test]
+    fn test_known_fields_follow_serialize() {
+        let bundle = RawBundle {
+            block_number: U64::from(1),
+            txs: vec![Bytes::from(vec![1])],
+            reverting_tx_hashes: vec![B256::ZERO],
+            replacement_uuid: Some(Uuid::from_u128(1)),
+            signing_address: Some(Address::ZERO),
+            min_timestamp: Some(1),
+            max_timestamp: Some(2),
+            replacement_nonce: Some(3),
+        };
+        let mut unknown = Vec::new();
+        object_unknown_fields(
+            &serde_json::to_value(&bundle).unwrap(),
+            "",
+            BUNDLE_FIELDS,
+            &mut unknown,
+        );
+        assert!(unknown.is_empty(), "{:?}", unknown);
+
+        let inner = RawShareBundle {
+            version: "v0.1".to_string(),
+            inclusion: RawShareBundleInclusion {
+                block: U64::from(1),
+                max_block: Some(U64::from(2)),
+            },
+            body: vec![RawShareBundleBody {
+                tx: Some(Bytes::from(vec![1])),
+                can_revert: true,
+                revert_mode: Some("allow".to_string()),
+                bundle: None,
+            }],
+            validity: Some(RawShareBundleValidity {
+                refund: vec![Refund {
+                    body_idx: 0,
+                    percent: 10,
+                }],
+                refund_config: vec![RefundConfig {
+                    address: Address::ZERO,
+                    percent: 100,
+                }],
+            }),
+            metadata: Some(RawShareBundleMetadatada {
+                signer: Some(Address::ZERO),
+                replacement_nonce: Some(1),
+                cancelled: false,
+            }),
+            privacy: Some(RawShareBundlePrivacy {
+                hints: Some(vec!["calldata".to_string()]),
+                builders: Some(vec!["us".to_string()]),
+            }),
+            replacement_uuid: Some(Uuid::from_u128(1)),
+        };
+        let mut outer = inner.clone();
+        outer.body[0].bundle = Some(Box::new(inner));
+        let unknown = share_bundle_unknown(&serde_json::to_value(&outer).unwrap());
+        assert!(unknown.is_empty(), "{:?}", unknown);
+    }
+
+    #[test]
+    fn test_share_bundle_unknown_fields() {
+        let bundle = serde_json::json!({
+            "version": "v0.1",
+            "inclusion": {"block": "0x1", "minBlock": "0x1"},
+            "body": [
+                {"tx": "0x01"},
+                {"bundle": {"version": "v0.2", "inclusion": {"block": "0x1"}, "body": [{"tx": "0x02", "hint": true}]}}
+            ],
+            "validity": {"refund": [{"bodyIdx": 0, "percent": 10, "extra": 1}]},
+            "privacy": {"hints": ["calldata", "blobs"], "wantRefund": 90},
+            "extensions": {}
+        });
+        assert_eq!(
+            share_bundle_unknown(&bundle),
+            vec![
+                "extensions",
+                "inclusion.minBlock",
+                "body[1].bundle.version (v0.2)",
+                "body[1].bundle.body[0].hint",
+                "validity.refund[0].extra",
+                "privacy.wantRefund",
+                "privacy.hints[1] (blobs)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = capabilities();
+        let mev_send_bundle = &capabilities.methods[1];
+        assert_eq!(mev_send_bundle.versions, vec!["v0.1", "version-1"]);
+        assert!(mev_send_bundle
+            .fields
+            .contains(&"validity.refundConfig[].address".to_string()));
+        assert_eq!(capabilities.privacy_hints.len(), PrivacyHint::ALL.len());
+    }
+}
@@ -0,0 +1,421 @@
+//! Builder to builder orderflow sharing so several instances (eg: one per region) build on the same orderflow.
+//! Egress: the bundles, share bundles and cancellations we receive are POSTed in batches to every peer.
+//! Ingress: we accept the batches of the peers on an http endpoint and put them in our orderpool.
+//!
+//! Requests carry the shared secret (Authorization: Bearer, checked before reading the body) so peer urls must be
+//! https (the ingress goes behind a tls terminating proxy), only loopback peers can be plain http.
+//! Every batch is tagged with its origin (the instance that received the orders from the searchers). To avoid loops orders we got from a peer are never forwarded again
+//! and batches tagged with our own origin (eg: a peer url pointing back to us) are dropped.
+//! Mempool txs are not shared (every instance has its own mempool) nor orders with blobs, the messages are the same
+//! as [`super::orderpool_sync`].
+//! Peers take our cancellations as they are, we only take (and so share) the authorized ones (see
+//! [`super::rpc_server::cancel_bundle_key`]).
+
+use super::{orderpool_sync::SyncMessage, ReplaceableOrderPoolCommand};
+use crate::{primitives::Order, telemetry::inc_orderflow_shared};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+use subtle::ConstantTimeEq;
+use tokio::{
+    sync::mpsc::{self, error::SendTimeoutError},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, trace, warn};
+use url::{Host, Url};
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+pub const ORDERFLOW_SHARING_PATH: &str = "orderflow";
+const EGRESS_BATCH_SIZE: usize = 100;
+/// Messages queued per peer, if a peer is slower than this new messages for it are dropped.
+const EGRESS_QUEUE_SIZE: usize = 10_000;
+const EGRESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderflowSharingConfig {
+    /// Our name on the batches we send, must be unique among the peers.
+    pub origin: String,
+    /// If set we accept the orderflow of the peers here.
+    pub ingress_address: Option<SocketAddr>,
+    /// Urls of the ingress endpoints of the peers we forward our orderflow to.
+    pub peers: Vec<String>,
+    /// Shared by all the peers.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedOrderflow {
+    pub origin: String,
+    pub messages: Vec<SyncMessage>,
+}
+
+/// None for commands we don't share.
+pub fn shared_message(command: &ReplaceableOrderPoolCommand) -> Option<SyncMessage> {
+    if matches!(command, ReplaceableOrderPoolCommand::Order(Order::Tx(_))) {
+        return None;
+    }
+    SyncMessage::from_command(command)
+}
+
+/// Queues of the tasks posting to the peers.
+#[derive(Debug, Clone)]
+pub struct OrderflowEgress {
+    peers: Vec<(String, mpsc::Sender<SyncMessage>)>,
+}
+
+impl OrderflowEgress {
+    /// Spawns a task per peer posting until global_cancel.
+    pub fn spawn(
+        config: &OrderflowSharingConfig,
+        global_cancel: CancellationToken,
+    ) -> eyre::Result<(Self, Vec<JoinHandle<()>>)> {
+        let client = reqwest::Client::builder().timeout(EGRESS_TIMEOUT).build()?;
+        let mut peers = Vec::new();
+        let mut handles = Vec::new();
+        for url in &config.peers {
+            check_peer_url(url)?;
+            let (sender, receiver) = mpsc::channel(EGRESS_QUEUE_SIZE);
+            handles.push(tokio::spawn(run_peer_egress(
+                client.clone(),
+                url.clone(),
+                config.origin.clone(),
+                config.secret.clone(),
+                receiver,
+                global_cancel.clone(),
+            )));
+            peers.push((url.clone(), sender));
+        }
+        Ok((Self { peers }, handles))
+    }
+
+    /// Call only for commands we received ourselves (never for the ones from the peers).
+    pub fn forward(&self, command: &ReplaceableOrderPoolCommand) {
+        let Some(message) = shared_message(command) else {
+            return;
+        };
+        for (url, sender) in &self.peers {
+            if sender.try_send(message.clone()).is_err() {
+                trace!(peer = url, "Orderflow sharing queue full, dropping message");
+                inc_orderflow_shared(url, "egress", "dropped", 1);
+            }
+        }
+    }
+}
+
+async fn run_peer_egress(
+    client: reqwest::Client,
+    url: String,
+    origin: String,
+    secret: String,
+    mut receiver: mpsc::Receiver<SyncMessage>,
+    global_cancel: CancellationToken,
+) {
+    let mut messages = Vec::new();
+    loop {
+        tokio::select! {
+            _ = global_cancel.cancelled() => break,
+            n = receiver.recv_many(&mut messages, EGRESS_BATCH_SIZE) => {
+                if n == 0 {
+                    break;
+                }
+            },
+        };
+        let batch = SharedOrderflow {
+            origin: origin.clone(),
+            messages: std::mem::take(&mut messages),
+        };
+        let count = batch.messages.len();
+        let body = match serde_json::to_vec(&batch) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(?err, "Failed to serialize shared orderflow");
+                continue;
+            }
+        };
+        let res = client
+            .post(&url)
+            .bearer_auth(&secret)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match res {
+            Ok(_) => inc_orderflow_shared(&url, "egress", "ok", count),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    peer = url,
+                    count,
+                    "Failed to share orderflow with peer"
+                );
+                inc_orderflow_shared(&url, "egress", "failed", count);
+            }
+        }
+    }
+    info!(peer = url, "Orderflow sharing egress finished");
+}
+
+/// The secret goes in every request so it must be https unless the peer is on this host.
+fn check_peer_url(url: &str) -> eyre::Result<()> {
+    let parsed = Url::parse(url)
+        .map_err(|err| eyre::eyre!("invalid orderflow sharing peer url {}: {}", url, err))?;
+    let loopback = match parsed.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    };
+    match parsed.scheme() {
+        "https" => Ok(()),
+        "http" if loopback => Ok(()),
+        _ => Err(eyre::eyre!(
+            "orderflow sharing peer url {} must be https",
+            url
+        )),
+    }
+}
+
+/// Constant time check of the Authorization header.
+fn is_authorized(secret: &str, authorization: Option<&str>) -> bool {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(secret.as_bytes())))
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+async fn recover_unauthorized(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(warp::reply::with_status("", StatusCode::UNAUTHORIZED))
+    } else {
+        Err(rejection)
+    }
+}
+
+/// Checks a batch from an authorized peer, Err is the http status to answer.
+fn accept_batch(
+    origin: &str,
+    batch: SharedOrderflow,
+) -> Result<Vec<ReplaceableOrderPoolCommand>, StatusCode> {
+    if batch.origin == origin {
+        warn!("Got our own orderflow back, check the orderflow sharing peers");
+        return Err(StatusCode::CONFLICT);
+    }
+    let mut commands = Vec::with_capacity(batch.messages.len());
+    for message in batch.messages {
+        match message.into_command() {
+            Ok(command) => commands.push(command),
+            Err(err) => warn!(?err, origin = batch.origin, "Failed to decode shared order"),
+        }
+    }
+    Ok(commands)
+}
+
+/// Accepts the orderflow of the peers sending it to order_sender until global_cancel.
+/// order_sender must not be forwarded to the peers again (see [`OrderflowEgress::forward`]).
+pub fn spawn_orderflow_ingress(
+    config: &OrderflowSharingConfig,
+    address: SocketAddr,
+    max_body_size: u64,
+    order_sender: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    send_timeout: Duration,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    let origin = config.origin.clone();
+    let secret = config.secret.clone();
+    // before the body filters so unauthorized requests are never read
+    let authorization = warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let authorized = is_authorized(&secret, authorization.as_deref());
+            async move {
+                if authorized {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one();
+    let route = warp::post()
+        .and(warp::path(ORDERFLOW_SHARING_PATH))
+        .and(warp::path::end())
+        .and(authorization)
+        .and(warp::body::content_length_limit(max_body_size))
+        .and(warp::body::json())
+        .then(move |batch: SharedOrderflow| {
+            let origin = origin.clone();
+            let order_sender = order_sender.clone();
+            async move {
+                let peer = batch.origin.clone();
+                let commands = match accept_batch(&origin, batch) {
+                    Ok(commands) => commands,
+                    Err(status) => return warp::reply::with_status("", status),
+                };
+                inc_orderflow_shared(&peer, "ingress", "ok", commands.len());
+                for command in commands {
+                    match order_sender.send_timeout(command, send_timeout).await {
+                        Ok(()) => {}
+                        Err(SendTimeoutError::Timeout(_)) => {
+                            warn!(peer, "Failed to send shared order, timeout");
+                        }
+                        Err(SendTimeoutError::Closed(_)) => {
+                            return warp::reply::with_status("", StatusCode::SERVICE_UNAVAILABLE);
+                        }
+                    }
+                }
+                warp::reply::with_status("", StatusCode::OK)
+            }
+        })
+        .recover(recover_unauthorized);
+    let (address, server) = warp::serve(route)
+        .try_bind_with_graceful_shutdown(address, async move { global_cancel.cancelled().await })?;
+    info!(?address, "Orderflow sharing ingress listening");
+    Ok(tokio::spawn(async move {
+        server.await;
+        info!("Orderflow sharing ingress finished");
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{BundleReplacementKey, MempoolTx};
+    use crate::utils::test_utils::tx;
+    use alloy_primitives::Address;
+    use uuid::Uuid;
+
+    fn cancel(uuid: u128) -> ReplaceableOrderPoolCommand {
+        ReplaceableOrderPoolCommand::CancelBundle(BundleReplacementKey::new(
+            Uuid::from_u128(uuid),
+            Address::with_last_byte(1),
+        ))
+    }
+
+    #[test]
+    fn test_accept_batch() {
+        let batch = SharedOrderflow {
+            origin: "eu".to_string(),
+            messages: vec![shared_message(&cancel(1)).unwrap()],
+        };
+        // mempool txs are not shared
+        assert_eq!(
+            shared_message(&ReplaceableOrderPoolCommand::Order(Order::Tx(
+                MempoolTx::new(tx(1))
+            ))),
+            None
+        );
+
+        let commands = accept_batch("us", batch.clone()).unwrap();
+        assert!(matches!(
+            commands.as_slice(),
+            [ReplaceableOrderPoolCommand::CancelBundle(_)]
+        ));
+        // loop
+        assert_eq!(accept_batch("eu", batch).unwrap_err(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized("secret", Some("Bearer secret")));
+        assert!(!is_authorized("secret", Some("Bearer nope")));
+        assert!(!is_authorized("secret", Some("Bearer secret2")));
+        assert!(!is_authorized("secret", Some("secret")));
+        assert!(!is_authorized("secret", None));
+    }
+
+    #[test]
+    fn test_check_peer_url() {
+        check_peer_url("https://builder.example.com/orderflow").unwrap();
+        check_peer_url("http://127.0.0.1:1234/orderflow").unwrap();
+        check_peer_url("http://localhost:1234/orderflow").unwrap();
+        check_peer_url("http://[::1]:1234/orderflow").unwrap();
+        assert!(check_peer_url("http://builder.example.com/orderflow").is_err());
+        assert!(check_peer_url("http://10.0.0.1/orderflow").is_err());
+        assert!(check_peer_url("builder.example.com").is_err());
+    }
+
+    fn free_address() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn sharing_config(origin: &str, peers: Vec<String>, secret: &str) -> OrderflowSharingConfig {
+        OrderflowSharingConfig {
+            origin: origin.to_string(),
+            ingress_address: None,
+            peers,
+            secret: secret.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_egress_to_ingress() {
+        let cancel_token = CancellationToken::new();
+        let address = free_address();
+        let url = format!("http://{}/{}", address, ORDERFLOW_SHARING_PATH);
+        let (ingress_sender, mut ingress_receiver) = mpsc::channel(10);
+        spawn_orderflow_ingress(
+            &sharing_config("us", Vec::new(), "secret"),
+            address,
+            1024 * 1024,
+            ingress_sender,
+            Duration::from_secs(1),
+            cancel_token.clone(),
+        )
+        .unwrap();
+
+        // a peer
+        let (eu, _) = OrderflowEgress::spawn(
+            &sharing_config("eu", vec![url.clone()], "secret"),
+            cancel_token.clone(),
+        )
+        .unwrap();
+        eu.forward(&cancel(1));
+        let received = tokio::time::timeout(Duration::from_secs(5), ingress_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shared_message(&received), shared_message(&cancel(1)));
+
+        // our own orderflow back
+        let client = reqwest::Client::new();
+        let post = |secret: &str, body: Vec<u8>| {
+            client
+                .post(&url)
+                .bearer_auth(secret)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+        };
+        let own = serde_json::to_vec(&SharedOrderflow {
+            origin: "us".to_string(),
+            messages: vec![shared_message(&cancel(2)).unwrap()],
+        })
+        .unwrap();
+        assert_eq!(
+            post("secret", own.clone()).await.unwrap().status().as_u16(),
+            StatusCode::CONFLICT.as_u16()
+        );
+        assert_eq!(
+            post("nope", own).await.unwrap().status().as_u16(),
+            StatusCode::UNAUTHORIZED.as_u16()
+        );
+        // the secret is checked before the body
+        assert_eq!(
+            post("nope", b"not json".to_vec())
+                .await
+                .unwrap()
+                .status()
+                .as_u16(),
+            StatusCode::UNAUTHORIZED.as_u16()
+        );
+        assert!(ingress_receiver.try_recv().is_err());
+        cancel_token.cancel();
+    }
+}
@@ -1,9 +1,18 @@
-use crate::primitives::{
-    serialize::CancelShareBundle, BundleReplacementKey, Order, OrderId, OrderReplacementKey,
-    ShareBundleReplacementKey,
+use crate::{
+    primitives::{
+        serialize::CancelShareBundle, Bundle, BundleReplacementKey, MempoolTx, Order, OrderId,
+        OrderReplacementKey, ShareBundleReplacementKey,
+    },
+    telemetry::{
+        inc_mempool_tx_replacements, inc_tenant_orders, record_order_drop, OrderDropStage,
+        INTAKE_CANCELLED, INTAKE_REPLACED, INTAKE_STALE_REPLACEMENT, INTAKE_TENANT_POOL_QUOTA,
+        INTAKE_UNDERPRICED_REPLACEMENT,
+    },
 };
 use ahash::HashMap;
+use alloy_consensus::Transaction as _;
 use alloy_eips::merge::SLOT_DURATION;
+use alloy_primitives::Address;
 use lru::LruCache;
 use reth::providers::StateProviderBox;
 use std::{
@@ -11,19 +20,81 @@ use std::{
     num::NonZeroUsize,
     time::{Duration, Instant},
 };
-use tokio::sync::mpsc::{self};
-use tracing::{error, trace};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{error, trace, warn};
 
 use super::{
     order_sink::{OrderPoolCommand, OrderSender2OrderSink},
     replaceable_order_sink::ReplaceableOrderSink,
-    ReplaceableOrderPoolCommand,
+    tenants::{TenantId, TenantRegistry},
+    CancelBundleByHash, ReplaceableOrderPoolCommand,
 };
+use std::sync::Arc;
 
 const BLOCKS_TO_KEEP_TXS: u32 = 5;
 const TIME_TO_KEEP_TXS: Duration = SLOT_DURATION.saturating_mul(BLOCKS_TO_KEEP_TXS);
 
 const TIME_TO_KEEP_BUNDLE_CANCELLATIONS: Duration = Duration::from_secs(60);
+/// Commands a sync subscriber can be behind before we drop it.
+const SYNC_SUBSCRIBER_BUFFER: usize = 10_000;
+
+fn can_sync(tenants: &TenantRegistry, command: &ReplaceableOrderPoolCommand) -> bool {
+    match command {
+        ReplaceableOrderPoolCommand::Order(order) => tenants.can_sync(order),
+        _ => true,
+    }
+}
+
+/// What to do with a mempool tx given the one we have for the same sender and nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MempoolTxReplacement {
+    /// No tx for sender/nonce (or it's this same tx).
+    New,
+    /// Pays more than the tx we had, the old one must be removed.
+    Replaces(OrderId),
+    /// Does not pay at least [`REPLACEMENT_TIP_BUMP_PERCENT`] more than the tx we have.
+    Underpriced,
+}
+
+/// What to do with a bundle given the versions we have for its replacement key (uuid, signer) and target block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BundleReplacement {
+    /// Not replaceable or first version for the key.
+    New,
+    /// Higher sequence number than the version we had, the old one must be removed.
+    Replaces(OrderId),
+    /// sequence number <= the one of the version we have.
+    Stale,
+    /// The key was cancelled.
+    Cancelled,
+}
+
+/// Minimum increase of the effective tip for a same nonce mempool tx to replace the one we have (like the mempools).
+const REPLACEMENT_TIP_BUMP_PERCENT: u128 = 10;
+
+/// (max priority fee, max fee). Legacy txs use the gas price for both.
+fn mempool_tx_fees(tx: &MempoolTx) -> (u128, u128) {
+    let tx = &tx.tx_with_blobs.tx;
+    let max_fee = tx.max_fee_per_gas();
+    (tx.max_priority_fee_per_gas().unwrap_or(max_fee), max_fee)
+}
+
+/// Tip per gas we get from a tx with these fees, the priority fee capped by what's left of the max fee after the base
+/// fee.
+fn effective_tip((max_priority_fee, max_fee): (u128, u128), base_fee: u128) -> u128 {
+    max_priority_fee.min(max_fee.saturating_sub(base_fee))
+}
+
+/// If a tx with new_fees can replace one with old_fees: it must pay a higher effective tip, at least
+/// [`REPLACEMENT_TIP_BUMP_PERCENT`] more.
+fn pays_replacement_bump(new_fees: (u128, u128), old_fees: (u128, u128), base_fee: u128) -> bool {
+    let (new_tip, old_tip) = (
+        effective_tip(new_fees, base_fee),
+        effective_tip(old_fees, base_fee),
+    );
+    new_tip > old_tip
+        && new_tip.saturating_mul(100) >= old_tip.saturating_mul(100 + REPLACEMENT_TIP_BUMP_PERCENT)
+}
 /// Push to pull for OrderSink. Just poll de UnboundedReceiver to get the orders.
 #[derive(Debug)]
 pub struct OrdersForBlock {
@@ -44,11 +115,18 @@ impl OrdersForBlock {
     }
 }
 
+/// (id, target block, replacement key). The replacement key is needed since the OrderId only depends on the content,
+/// a copy of a bundle sent first by another signer must not hide the original one.
+type KnownOrderKey = (OrderId, u64, Option<OrderReplacementKey>);
+
 /// Events (orders/cancellations) for a single block
 #[derive(Debug, Default)]
 struct BundleBlockStore {
     /// Bundles and SharedBundles
     bundles: Vec<Order>,
+    /// Current version (order id, sequence number) of each replaceable bundle in bundles, older versions are removed
+    /// from bundles when replaced.
+    replaceable_bundles: HashMap<BundleReplacementKey, (OrderId, u64)>,
     cancelled_sbundles: Vec<ShareBundleReplacementKey>,
 }
 
@@ -70,12 +148,22 @@ pub struct OrderPoolSubscriptionId(u64);
 #[derive(Debug)]
 pub struct OrderPool {
     mempool_txs: Vec<(Order, Instant)>,
+    /// (sender, nonce) -> the only mempool tx we keep for it, same nonce replacements paying more replace it.
+    mempool_tx_by_nonce: HashMap<(Address, u64), (OrderId, (u128, u128))>,
+    /// Base fee of the last block, to compare the effective tip of same nonce replacements.
+    base_fee: u128,
     /// cancelled bundle, cancellation arrival time
     bundle_cancellations: VecDeque<(BundleReplacementKey, Instant)>,
     bundles_by_target_block: HashMap<u64, BundleBlockStore>,
-    known_orders: LruCache<(OrderId, u64), ()>,
+    /// See [`OrderPool::known_order_key`].
+    known_orders: LruCache<KnownOrderKey, ()>,
     sinks: HashMap<OrderPoolSubscriptionId, SinkSubscription>,
     next_sink_id: u64,
+    tenants: Arc<TenantRegistry>,
+    /// Orders currently stored for each tenant (for max_pool_orders).
+    tenant_order_count: HashMap<TenantId, usize>,
+    /// See [`OrderPool::add_sync_subscriber`].
+    sync_subscribers: Vec<mpsc::Sender<ReplaceableOrderPoolCommand>>,
 }
 
 impl Default for OrderPool {
@@ -88,34 +176,181 @@ impl OrderPool {
     pub fn new() -> Self {
         OrderPool {
             mempool_txs: Vec::new(),
+            mempool_tx_by_nonce: HashMap::default(),
+            base_fee: 0,
             bundles_by_target_block: HashMap::default(),
             known_orders: LruCache::new(NonZeroUsize::new(10_000).unwrap()),
             sinks: Default::default(),
             next_sink_id: 0,
             bundle_cancellations: Default::default(),
+            tenants: Default::default(),
+            tenant_order_count: Default::default(),
+            sync_subscribers: Default::default(),
         }
     }
 
+    pub fn with_tenants(self, tenants: Arc<TenantRegistry>) -> Self {
+        Self { tenants, ..self }
+    }
+
     pub fn process_commands(&mut self, commands: Vec<ReplaceableOrderPoolCommand>) {
         commands.into_iter().for_each(|oc| self.process_command(oc));
     }
 
-    fn process_order(&mut self, order: &Order) {
-        let target_block = order.target_block();
-        let order_id = order.id();
+    fn mempool_tx_key(tx: &MempoolTx) -> (Address, u64) {
+        (tx.tx_with_blobs.tx.signer(), tx.tx_with_blobs.tx.nonce())
+    }
+
+    fn mempool_tx_replacement(&self, tx: &MempoolTx) -> MempoolTxReplacement {
+        match self.mempool_tx_by_nonce.get(&Self::mempool_tx_key(tx)) {
+            None => MempoolTxReplacement::New,
+            Some((id, _)) if *id == OrderId::Tx(tx.tx_with_blobs.hash()) => {
+                MempoolTxReplacement::New
+            }
+            Some((id, fees)) => {
+                if pays_replacement_bump(mempool_tx_fees(tx), *fees, self.base_fee) {
+                    MempoolTxReplacement::Replaces(*id)
+                } else {
+                    MempoolTxReplacement::Underpriced
+                }
+            }
+        }
+    }
+
+    fn bundle_replacement(&self, bundle: &Bundle) -> BundleReplacement {
+        let Some(replacement_data) = &bundle.replacement_data else {
+            return BundleReplacement::New;
+        };
         if self
-            .known_orders
-            .contains(&(order_id, target_block.unwrap_or_default()))
+            .bundle_cancellations
+            .iter()
+            .any(|(cancelled, _)| *cancelled == replacement_data.key)
+        {
+            return BundleReplacement::Cancelled;
+        }
+        match self
+            .bundles_by_target_block
+            .get(&bundle.block)
+            .and_then(|store| store.replaceable_bundles.get(&replacement_data.key))
         {
+            None => BundleReplacement::New,
+            Some((_, sequence_number)) if replacement_data.sequence_number <= *sequence_number => {
+                BundleReplacement::Stale
+            }
+            Some((id, _)) => BundleReplacement::Replaces(*id),
+        }
+    }
+
+    /// Removes the version of the bundle key holds in the store, the same order might still be there for other keys.
+    fn remove_bundle_version(
+        store: &mut BundleBlockStore,
+        key: &BundleReplacementKey,
+    ) -> Vec<Order> {
+        let Some((id, _)) = store.replaceable_bundles.remove(key) else {
+            return Vec::new();
+        };
+        let key = Some(OrderReplacementKey::Bundle(*key));
+        let mut removed = Vec::new();
+        store.bundles.retain(|order| {
+            if order.id() == id && order.replacement_key() == key {
+                removed.push(order.clone());
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    /// Removes a replaced (or cancelled) tx from the pool and from every sink (which invalidates its simulations).
+    fn remove_replaced_mempool_tx(&mut self, id: OrderId) {
+        self.mempool_txs.retain(|(order, _)| order.id() != id);
+        self.sinks
+            .retain(|_, sub| sub.sink.is_alive() && sub.sink.remove_tx(id));
+    }
+
+    /// Returns false if the order was dropped because its tenant is over quota or because it's a mempool tx not paying
+    /// more than the one we have with the same sender and nonce.
+    fn process_order(&mut self, order: &Order) -> bool {
+        let order_id = order.id();
+        // before the known check so replaced txs can't come back
+        let replaced_tx = match order {
+            Order::Tx(tx) => match self.mempool_tx_replacement(tx) {
+                MempoolTxReplacement::New => None,
+                MempoolTxReplacement::Replaces(replaced) => Some(replaced),
+                MempoolTxReplacement::Underpriced => {
+                    trace!(?order_id, "Underpriced same nonce mempool tx, dropping");
+                    inc_mempool_tx_replacements("underpriced");
+                    record_order_drop(
+                        order_id,
+                        OrderDropStage::Intake,
+                        INTAKE_UNDERPRICED_REPLACEMENT,
+                    );
+                    return false;
+                }
+            },
+            _ => None,
+        };
+        if self.known_orders.contains(&Self::known_order_key(order)) {
             trace!(?order_id, "Order known, dropping");
-            return;
+            return true;
+        }
+        let bundle_replacement = match order {
+            Order::Bundle(bundle) => self.bundle_replacement(bundle),
+            _ => BundleReplacement::New,
+        };
+        match bundle_replacement {
+            BundleReplacement::Stale => {
+                trace!(?order_id, "Stale bundle replacement, dropping");
+                record_order_drop(order_id, OrderDropStage::Intake, INTAKE_STALE_REPLACEMENT);
+                return false;
+            }
+            BundleReplacement::Cancelled => {
+                trace!(?order_id, "Bundle replacement after cancellation, dropping");
+                record_order_drop(order_id, OrderDropStage::Intake, INTAKE_CANCELLED);
+                return false;
+            }
+            BundleReplacement::New | BundleReplacement::Replaces(_) => {}
+        }
+        // a replacement takes the place of the version it replaces
+        let takes_pool_place = !matches!(bundle_replacement, BundleReplacement::Replaces(_));
+        if let Some(tenant) = self.tenants.tenant_of(order) {
+            let tenant_name = self.tenants.name(tenant);
+            let count = self.tenant_order_count.entry(tenant).or_default();
+            if takes_pool_place
+                && self
+                    .tenants
+                    .config(tenant)
+                    .max_pool_orders
+                    .map_or(false, |max| *count >= max)
+            {
+                trace!(
+                    ?order_id,
+                    tenant = tenant_name,
+                    "Tenant pool quota reached, dropping"
+                );
+                inc_tenant_orders(tenant_name, "dropped_pool_quota");
+                record_order_drop(order_id, OrderDropStage::Intake, INTAKE_TENANT_POOL_QUOTA);
+                return false;
+            }
+            if takes_pool_place {
+                *count += 1;
+            }
+            inc_tenant_orders(tenant_name, "received");
         }
         trace!(?order_id, "Adding order");
 
-        let (order, target_block) = match &order {
-            Order::Tx(..) => {
+        match order {
+            Order::Tx(tx) => {
+                if let Some(replaced) = replaced_tx {
+                    trace!(?order_id, ?replaced, "Replacing same nonce mempool tx");
+                    inc_mempool_tx_replacements("replaced");
+                    record_order_drop(replaced, OrderDropStage::Intake, INTAKE_REPLACED);
+                    self.remove_replaced_mempool_tx(replaced);
+                }
+                self.mempool_tx_by_nonce
+                    .insert(Self::mempool_tx_key(tx), (order_id, mempool_tx_fees(tx)));
                 self.mempool_txs.push((order.clone(), Instant::now()));
-                (order, None)
             }
             Order::Bundle(bundle) => {
                 let target_block = bundle.block;
@@ -123,8 +358,21 @@ impl OrderPool {
                     .bundles_by_target_block
                     .entry(target_block)
                     .or_default();
+                if let Some(replacement_data) = &bundle.replacement_data {
+                    // sinks get the new version and replace the old one themselves
+                    // (see OrderReplacementManager), here we only keep the new one for future sinks.
+                    for replaced in
+                        Self::remove_bundle_version(bundles_store, &replacement_data.key)
+                    {
+                        trace!(?order_id, replaced = ?replaced.id(), "Replacing bundle");
+                        record_order_drop(replaced.id(), OrderDropStage::Intake, INTAKE_REPLACED);
+                    }
+                    bundles_store.replaceable_bundles.insert(
+                        replacement_data.key,
+                        (order_id, replacement_data.sequence_number),
+                    );
+                }
                 bundles_store.bundles.push(order.clone());
-                (order, Some(target_block))
             }
             Order::ShareBundle(bundle) => {
                 let target_block = bundle.block;
@@ -133,11 +381,10 @@ impl OrderPool {
                     .entry(target_block)
                     .or_default();
                 bundles_store.bundles.push(order.clone());
-                (order, Some(target_block))
             }
-        };
-        self.known_orders
-            .put((order.id(), target_block.unwrap_or_default()), ());
+        }
+        self.known_orders.put(Self::known_order_key(order), ());
+        true
     }
 
     fn process_remove_sbundle(&mut self, cancellation: &CancelShareBundle) {
@@ -148,21 +395,164 @@ impl OrderPool {
         bundles_store.cancelled_sbundles.push(cancellation.key);
     }
 
-    fn process_remove_bundle(&mut self, key: &BundleReplacementKey) {
+    /// Returns false if we already had the cancellation (replayed message), it's not forwarded again so it can't
+    /// bounce between synced instances or extend its lifetime.
+    fn process_remove_bundle(&mut self, key: &BundleReplacementKey) -> bool {
+        if self
+            .bundle_cancellations
+            .iter()
+            .any(|(cancelled, _)| cancelled == key)
+        {
+            trace!(?key, "Bundle cancellation known, dropping");
+            return false;
+        }
         self.bundle_cancellations.push_back((*key, Instant::now()));
+        // cancellations have no target block, the bundle goes away for all of them
+        let removed: Vec<_> = self
+            .bundles_by_target_block
+            .values_mut()
+            .flat_map(|store| Self::remove_bundle_version(store, key))
+            .collect();
+        for order in removed {
+            self.forget_cancelled_order(&order);
+        }
+        true
+    }
+
+    fn forget_cancelled_order(&mut self, order: &Order) {
+        record_order_drop(order.id(), OrderDropStage::Intake, INTAKE_CANCELLED);
+        if let Some(tenant) = self.tenants.tenant_of(order) {
+            let count = self.tenant_order_count.entry(tenant).or_default();
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// Removes the bundles with the hash sent by the signer. Replaceable ones are cancelled by key (as
+    /// eth_cancelBundle/mev_sendBundle cancellations do) so later versions are rejected too, the others are removed
+    /// by id from the pool and every sink (which drops their pending simulations).
+    /// Private txs with the hash sent by the signer are removed too (public mempool txs have no signer so they can't be
+    /// cancelled).
+    /// Returns false if we didn't have any.
+    fn process_remove_by_hash(&mut self, cancel: &CancelBundleByHash) -> bool {
+        let private_txs: Vec<Order> = self
+            .mempool_txs
+            .iter()
+            .map(|(order, _)| order)
+            .filter(|order| match order {
+                Order::Tx(tx) => {
+                    tx.tx_with_blobs.hash() == cancel.hash && order.signer() == Some(cancel.signer)
+                }
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        for order in &private_txs {
+            self.remove_replaced_mempool_tx(order.id());
+            self.forget_cancelled_order(order);
+        }
+        let matching: Vec<(u64, Order)> = self
+            .bundles_by_target_block
+            .iter()
+            .flat_map(|(block, store)| {
+                store
+                    .bundles
+                    .iter()
+                    .filter(|order| {
+                        order.bundle_hash() == Some(cancel.hash)
+                            && order.signer() == Some(cancel.signer)
+                    })
+                    .map(|order| (*block, order.clone()))
+            })
+            .collect();
+        if matching.is_empty() {
+            if private_txs.is_empty() {
+                trace!(?cancel, "No bundle or private tx to cancel by hash");
+                return false;
+            }
+            return true;
+        }
+        for (block, order) in matching {
+            match order.replacement_key() {
+                Some(OrderReplacementKey::Bundle(key)) => {
+                    self.process_command(ReplaceableOrderPoolCommand::CancelBundle(key))
+                }
+                Some(OrderReplacementKey::ShareBundle(key)) => {
+                    self.process_command(ReplaceableOrderPoolCommand::CancelShareBundle(
+                        CancelShareBundle { block, key },
+                    ))
+                }
+                None => {
+                    let id = order.id();
+                    if let Some(store) = self.bundles_by_target_block.get_mut(&block) {
+                        store.bundles.retain(|order| order.id() != id);
+                    }
+                    self.forget_cancelled_order(&order);
+                    self.sinks.retain(|_, sub| {
+                        sub.sink.is_alive() && (sub.block_number != block || sub.sink.remove_tx(id))
+                    });
+                }
+            }
+        }
+        true
+    }
+
+    fn known_order_key(order: &Order) -> KnownOrderKey {
+        (
+            order.id(),
+            order.target_block().unwrap_or_default(),
+            order.replacement_key(),
+        )
+    }
+
+    fn is_known(&self, order: &Order) -> bool {
+        self.known_orders.contains(&Self::known_order_key(order))
     }
 
     fn process_command(&mut self, command: ReplaceableOrderPoolCommand) {
+        let mut sync = true;
         match &command {
-            ReplaceableOrderPoolCommand::Order(order) => self.process_order(order),
+            ReplaceableOrderPoolCommand::Order(order) => {
+                // orders we already had are not synced again so an order can't bounce between instances.
+                sync = !self.is_known(order);
+                if !self.process_order(order) {
+                    return;
+                }
+            }
             ReplaceableOrderPoolCommand::CancelShareBundle(c) => self.process_remove_sbundle(c),
-            ReplaceableOrderPoolCommand::CancelBundle(key) => self.process_remove_bundle(key),
+            ReplaceableOrderPoolCommand::CancelBundle(key) => {
+                if !self.process_remove_bundle(key) {
+                    return;
+                }
+            }
+            ReplaceableOrderPoolCommand::CancelBundleByHash(cancel) => {
+                if !self.process_remove_by_hash(cancel) {
+                    return;
+                }
+            }
+        }
+        if sync && !self.sync_subscribers.is_empty() && can_sync(&self.tenants, &command) {
+            self.sync_subscribers.retain(|subscriber| {
+                match subscriber.try_send(command.clone()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        // it would miss orders, better to reconnect and get a new snapshot
+                        warn!("Orderpool sync subscriber is too slow, dropping it");
+                        false
+                    }
+                    Err(TrySendError::Closed(_)) => false,
+                }
+            });
         }
         let target_block = command.target_block();
         self.sinks.retain(|_, sub| {
             if !sub.sink.is_alive() {
                 return false;
             }
+            if let ReplaceableOrderPoolCommand::Order(order) = &command {
+                if !order.valid_for_block(sub.block_number) {
+                    return true;
+                }
+            }
             if target_block.is_none() || target_block == Some(sub.block_number) {
                 let send_ok = match command.clone() {
                     ReplaceableOrderPoolCommand::Order(o) => sub.sink.insert_order(o),
@@ -172,6 +562,8 @@ impl OrderPool {
                     ReplaceableOrderPoolCommand::CancelBundle(key) => {
                         sub.sink.remove_bundle(OrderReplacementKey::Bundle(key))
                     }
+                    // already applied to the sinks by process_remove_by_hash
+                    ReplaceableOrderPoolCommand::CancelBundleByHash(_) => true,
                 };
                 if !send_ok {
                     return false;
@@ -181,13 +573,19 @@ impl OrderPool {
         });
     }
 
-    /// Adds a sink and pushes the current state for the block
+    /// Adds a sink that sees all the orders and pushes the current state for the block
     pub fn add_sink(
         &mut self,
         block_number: u64,
         mut sink: Box<dyn ReplaceableOrderSink>,
     ) -> OrderPoolSubscriptionId {
-        for order in self.mempool_txs.iter().map(|(order, _)| order.clone()) {
+        for order in self
+            .mempool_txs
+            .iter()
+            .map(|(order, _)| order)
+            .filter(|order| order.valid_for_block(block_number))
+            .cloned()
+        {
             sink.insert_order(order);
         }
         for cancellation_key in self.bundle_cancellations.iter().map(|(key, _)| key) {
@@ -209,6 +607,48 @@ impl OrderPool {
         res
     }
 
+    /// Subscription to everything stored and arriving at the pool for all blocks (see [`super::orderpool_sync`]).
+    /// Returns the current content and the receiver of what arrives after it. Orders of private tenants are left out
+    /// unless the tenant allows it (see [`TenantRegistry::can_sync`]).
+    /// If the subscriber falls more than SYNC_SUBSCRIBER_BUFFER commands behind its receiver is closed.
+    pub fn add_sync_subscriber(
+        &mut self,
+    ) -> (
+        Vec<ReplaceableOrderPoolCommand>,
+        mpsc::Receiver<ReplaceableOrderPoolCommand>,
+    ) {
+        let (sender, receiver) = mpsc::channel(SYNC_SUBSCRIBER_BUFFER);
+        let orders = self.mempool_txs.iter().map(|(order, _)| order).chain(
+            self.bundles_by_target_block
+                .values()
+                .flat_map(|store| store.bundles.iter()),
+        );
+        let sbundle_cancellations =
+            self.bundles_by_target_block
+                .iter()
+                .flat_map(|(block, store)| {
+                    store.cancelled_sbundles.iter().map(|key| {
+                        ReplaceableOrderPoolCommand::CancelShareBundle(CancelShareBundle {
+                            block: *block,
+                            key: *key,
+                        })
+                    })
+                });
+        let bundle_cancellations = self
+            .bundle_cancellations
+            .iter()
+            .map(|(key, _)| ReplaceableOrderPoolCommand::CancelBundle(*key));
+        let snapshot = orders
+            .cloned()
+            .map(ReplaceableOrderPoolCommand::Order)
+            .chain(sbundle_cancellations)
+            .chain(bundle_cancellations)
+            .filter(|command| can_sync(&self.tenants, command))
+            .collect();
+        self.sync_subscribers.push(sender);
+        (snapshot, receiver)
+    }
+
     /// Removes the sink. If present returns it
     pub fn remove_sink(
         &mut self,
@@ -220,14 +660,21 @@ impl OrderPool {
     /// Should be called when last block is updated.
     /// It's slow but since it only happens at the start of the block it does now matter.
     /// It clears old txs from the mempool and old bundle_cancellations.
-    pub fn head_updated(&mut self, new_block_number: u64, new_state: &StateProviderBox) {
+    /// base_fee: base fee of the new block.
+    pub fn head_updated(
+        &mut self,
+        new_block_number: u64,
+        base_fee: u64,
+        new_state: &StateProviderBox,
+    ) {
+        self.base_fee = base_fee as u128;
         // remove from bundles by target block
         self.bundles_by_target_block
             .retain(|block_number, _| *block_number > new_block_number);
 
-        // remove mempool txs by nonce, time
+        // remove mempool txs by nonce, time, max block (private txs)
         self.mempool_txs.retain(|(order, time)| {
-            if time.elapsed() > TIME_TO_KEEP_TXS {
+            if time.elapsed() > TIME_TO_KEEP_TXS || !order.valid_for_block(new_block_number + 1) {
                 return false;
             }
             for nonce in order.nonces() {
@@ -245,6 +692,16 @@ impl OrderPool {
             }
             true
         });
+        self.mempool_tx_by_nonce = self
+            .mempool_txs
+            .iter()
+            .filter_map(|(order, _)| match order {
+                Order::Tx(tx) => {
+                    Some((Self::mempool_tx_key(tx), (order.id(), mempool_tx_fees(tx))))
+                }
+                _ => None,
+            })
+            .collect();
         //remove old bundle cancellations
         while let Some((_, oldest_time)) = self.bundle_cancellations.front() {
             if oldest_time.elapsed() < TIME_TO_KEEP_BUNDLE_CANCELLATIONS {
@@ -252,6 +709,24 @@ impl OrderPool {
             }
             self.bundle_cancellations.pop_front();
         }
+        self.recount_tenant_orders();
+    }
+
+    fn recount_tenant_orders(&mut self) {
+        self.tenant_order_count.clear();
+        if self.tenants.is_empty() {
+            return;
+        }
+        let orders = self.mempool_txs.iter().map(|(order, _)| order).chain(
+            self.bundles_by_target_block
+                .values()
+                .flat_map(|store| store.bundles.iter()),
+        );
+        for order in orders {
+            if let Some(tenant) = self.tenants.tenant_of(order) {
+                *self.tenant_order_count.entry(tenant).or_default() += 1;
+            }
+        }
     }
 
     /// Does NOT take in account cancellations
@@ -265,3 +740,405 @@ impl OrderPool {
         (tx_count, bundle_count)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::order_input::tenants::TenantConfig,
+        primitives::{
+            Bundle, BundleReplacementData, Metadata, TransactionSignedEcRecoveredWithBlobs,
+        },
+        utils::test_utils,
+    };
+    use alloy_consensus::TxEip1559;
+    use alloy_primitives::Signature;
+    use parking_lot::Mutex;
+    use reth_primitives::{Transaction, TransactionSigned, TransactionSignedEcRecovered};
+
+    #[derive(Debug, Default, Clone)]
+    struct CollectingSink {
+        orders: Arc<Mutex<Vec<OrderId>>>,
+        removed_txs: Arc<Mutex<Vec<OrderId>>>,
+    }
+
+    impl ReplaceableOrderSink for CollectingSink {
+        fn insert_order(&mut self, order: Order) -> bool {
+            self.orders.lock().push(order.id());
+            true
+        }
+
+        fn remove_bundle(&mut self, _key: OrderReplacementKey) -> bool {
+            true
+        }
+
+        fn remove_tx(&mut self, id: OrderId) -> bool {
+            self.removed_txs.lock().push(id);
+            true
+        }
+
+        fn is_alive(&self) -> bool {
+            true
+        }
+    }
+
+    fn bundle(signer: u8, id: u128) -> Order {
+        Order::Bundle(Bundle {
+            block: 1,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: uuid::Uuid::from_u128(id),
+            replacement_data: None,
+            signer: Some(Address::with_last_byte(signer)),
+            metadata: Metadata::default(),
+        })
+    }
+
+    fn mempool_tx(hash: u64, nonce: u64, max_priority_fee_per_gas: u128) -> Order {
+        let tx = TransactionSignedEcRecovered::from_signed_transaction(
+            TransactionSigned {
+                hash: test_utils::hash(hash),
+                signature: Signature::test_signature(),
+                transaction: Transaction::Eip1559(TxEip1559 {
+                    nonce,
+                    max_fee_per_gas: 100,
+                    max_priority_fee_per_gas,
+                    ..Default::default()
+                }),
+            },
+            Address::with_last_byte(1),
+        );
+        Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_for_testing(tx),
+        ))
+    }
+
+    #[test]
+    fn test_mempool_tx_replacement() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(sink.clone()));
+        let (original, other_nonce, underpriced, repriced) = (
+            mempool_tx(1, 0, 2),
+            mempool_tx(2, 1, 2),
+            mempool_tx(3, 0, 2),
+            mempool_tx(4, 0, 3),
+        );
+        pool.process_commands(
+            [&original, &other_nonce, &underpriced, &repriced]
+                .into_iter()
+                .cloned()
+                .map(ReplaceableOrderPoolCommand::Order)
+                .collect(),
+        );
+        assert_eq!(pool.content_count(), (2, 0));
+        assert_eq!(
+            *sink.orders.lock(),
+            vec![original.id(), other_nonce.id(), repriced.id()]
+        );
+        assert_eq!(*sink.removed_txs.lock(), vec![original.id()]);
+
+        // the replaced tx can't come back
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::Order(original.clone())]);
+        assert_eq!(sink.orders.lock().len(), 3);
+        let new_sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(new_sink.clone()));
+        assert_eq!(
+            *new_sink.orders.lock(),
+            vec![other_nonce.id(), repriced.id()]
+        );
+    }
+
+    #[test]
+    fn test_pays_replacement_bump() {
+        // 10% more tip
+        assert!(pays_replacement_bump((110, 1000), (100, 1000), 0));
+        assert!(!pays_replacement_bump((109, 1000), (100, 1000), 0));
+        // always more than the old tip, even for tiny ones
+        assert!(pays_replacement_bump((1, 1000), (0, 1000), 0));
+        assert!(!pays_replacement_bump((1, 1000), (1, 1000), 0));
+        // higher priority fee but less left over the base fee
+        assert!(!pays_replacement_bump((50, 96), (2, 100), 95));
+        assert!(pays_replacement_bump((50, 100), (2, 100), 95));
+        // legacy (gas price for both)
+        assert!(pays_replacement_bump((120, 120), (100, 100), 10));
+    }
+
+    #[test]
+    fn test_private_tx_max_block() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(2, Box::new(sink.clone()));
+        let Order::Tx(tx) = mempool_tx(1, 0, 2) else {
+            unreachable!()
+        };
+        let private_tx = Order::Tx(tx.with_max_block(Some(1)));
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::Order(private_tx.clone())]);
+        assert!(sink.orders.lock().is_empty());
+
+        let old_block_sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(old_block_sink.clone()));
+        assert_eq!(*old_block_sink.orders.lock(), vec![private_tx.id()]);
+        let new_block_sink = CollectingSink::default();
+        pool.add_sink(2, Box::new(new_block_sink.clone()));
+        assert!(new_block_sink.orders.lock().is_empty());
+    }
+
+    #[test]
+    fn test_tenant_quota_and_visibility() {
+        let tenants = TenantRegistry::new(vec![
+            TenantConfig {
+                name: "a".to_string(),
+                signers: vec![Address::with_last_byte(1)],
+                max_pool_orders: Some(2),
+                private: true,
+                ..Default::default()
+            },
+            TenantConfig {
+                name: "b".to_string(),
+                signers: vec![Address::with_last_byte(2)],
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let mut pool = OrderPool::new().with_tenants(Arc::new(tenants));
+        let builder_sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(builder_sink.clone()));
+
+        let orders = [bundle(1, 1), bundle(1, 2), bundle(1, 3), bundle(2, 4)];
+        pool.process_commands(
+            orders
+                .iter()
+                .cloned()
+                .map(ReplaceableOrderPoolCommand::Order)
+                .collect(),
+        );
+        // third order of "a" is over quota
+        assert_eq!(pool.content_count(), (0, 3));
+        assert_eq!(
+            *builder_sink.orders.lock(),
+            vec![orders[0].id(), orders[1].id(), orders[3].id()]
+        );
+    }
+
+    #[test]
+    fn test_sync_subscriber() {
+        let mut pool = OrderPool::new();
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::Order(bundle(1, 1))]);
+        let (snapshot, mut sync) = pool.add_sync_subscriber();
+        assert!(matches!(
+            snapshot.as_slice(),
+            [ReplaceableOrderPoolCommand::Order(order)] if order.id() == bundle(1, 1).id()
+        ));
+        assert!(sync.try_recv().is_err());
+
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(bundle(1, 2)),
+            // known orders are not synced again
+            ReplaceableOrderPoolCommand::Order(bundle(1, 1)),
+        ]);
+        assert!(matches!(
+            sync.try_recv(),
+            Ok(ReplaceableOrderPoolCommand::Order(order)) if order.id() == bundle(1, 2).id()
+        ));
+        assert!(sync.try_recv().is_err());
+
+        drop(sync);
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::Order(bundle(1, 3))]);
+        assert!(pool.sync_subscribers.is_empty());
+
+        // slow subscribers are dropped
+        let (_, _sync) = pool.add_sync_subscriber();
+        pool.process_commands(
+            (0..=SYNC_SUBSCRIBER_BUFFER as u128)
+                .map(|id| ReplaceableOrderPoolCommand::Order(bundle(1, 100 + id)))
+                .collect(),
+        );
+        assert!(pool.sync_subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_sync_subscriber_private_tenants() {
+        let tenants = TenantRegistry::new(vec![TenantConfig {
+            name: "a".to_string(),
+            signers: vec![Address::with_last_byte(1)],
+            private: true,
+            ..Default::default()
+        }])
+        .unwrap();
+        let mut pool = OrderPool::new().with_tenants(Arc::new(tenants));
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(bundle(1, 1)),
+            ReplaceableOrderPoolCommand::Order(bundle(2, 2)),
+        ]);
+        let (snapshot, mut sync) = pool.add_sync_subscriber();
+        assert!(matches!(
+            snapshot.as_slice(),
+            [ReplaceableOrderPoolCommand::Order(order)] if order.id() == bundle(2, 2).id()
+        ));
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(bundle(1, 3)),
+            ReplaceableOrderPoolCommand::Order(bundle(2, 4)),
+        ]);
+        assert!(matches!(
+            sync.try_recv(),
+            Ok(ReplaceableOrderPoolCommand::Order(order)) if order.id() == bundle(2, 4).id()
+        ));
+        assert!(sync.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_cross_signer_copies_and_replayed_cancellations() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(sink.clone()));
+        let (_, mut sync) = pool.add_sync_subscriber();
+
+        let uuid = uuid::Uuid::from_u128(7);
+        let with_replacement = |signer: u8| {
+            let mut order = bundle(1, 1);
+            if let Order::Bundle(bundle) = &mut order {
+                bundle.replacement_data = Some(BundleReplacementData {
+                    key: BundleReplacementKey::new(uuid, Address::with_last_byte(signer)),
+                    sequence_number: 0,
+                });
+            }
+            order
+        };
+        // same content and uuid, the copy from signer 2 arrives first but must not hide the original
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(with_replacement(2)),
+            ReplaceableOrderPoolCommand::Order(with_replacement(1)),
+        ]);
+        assert_eq!(sink.orders.lock().len(), 2);
+
+        let cancel = BundleReplacementKey::new(uuid, Address::with_last_byte(1));
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::CancelBundle(cancel),
+            // replayed
+            ReplaceableOrderPoolCommand::CancelBundle(cancel),
+        ]);
+        assert_eq!(pool.bundle_cancellations.len(), 1);
+        let synced: Vec<_> = std::iter::from_fn(|| sync.try_recv().ok()).collect();
+        assert_eq!(synced.len(), 3);
+        assert!(matches!(
+            &synced[2],
+            ReplaceableOrderPoolCommand::CancelBundle(key) if *key == cancel
+        ));
+    }
+
+    #[test]
+    fn test_bundle_replacement_and_cancellation() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(sink.clone()));
+        let key = BundleReplacementKey::new(uuid::Uuid::from_u128(7), Address::with_last_byte(1));
+        let version = |id: u128, sequence_number: u64| {
+            let mut order = bundle(1, id);
+            if let Order::Bundle(bundle) = &mut order {
+                bundle.replacement_data = Some(BundleReplacementData {
+                    key,
+                    sequence_number,
+                });
+            }
+            order
+        };
+        let (v1, v2, stale) = (version(1, 1), version(2, 2), version(3, 1));
+        pool.process_commands(
+            [&v1, &v2, &stale]
+                .into_iter()
+                .cloned()
+                .map(ReplaceableOrderPoolCommand::Order)
+                .collect(),
+        );
+        // sinks get every newer version, the pool only keeps the last one
+        assert_eq!(*sink.orders.lock(), vec![v1.id(), v2.id()]);
+        assert_eq!(pool.content_count(), (0, 1));
+        let new_sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(new_sink.clone()));
+        assert_eq!(*new_sink.orders.lock(), vec![v2.id()]);
+
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::CancelBundle(key),
+            ReplaceableOrderPoolCommand::Order(version(4, 3)),
+        ]);
+        assert_eq!(pool.content_count(), (0, 0));
+        assert_eq!(sink.orders.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_bundle_by_hash() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(sink.clone()));
+        let hash = test_utils::hash(1);
+        let with_hash = |signer: u8, id: u128| {
+            let mut order = bundle(signer, id);
+            if let Order::Bundle(bundle) = &mut order {
+                bundle.hash = hash;
+            }
+            order
+        };
+        let (ours, copy) = (with_hash(1, 1), with_hash(2, 2));
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(ours.clone()),
+            ReplaceableOrderPoolCommand::Order(copy.clone()),
+        ]);
+        assert_eq!(pool.content_count(), (0, 2));
+
+        // only the bundle of the signer goes away
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::CancelBundleByHash(
+            CancelBundleByHash {
+                hash,
+                signer: Address::with_last_byte(1),
+            },
+        )]);
+        assert_eq!(pool.content_count(), (0, 1));
+        assert_eq!(*sink.removed_txs.lock(), vec![ours.id()]);
+
+        // unknown hash or signer
+        for (hash, signer) in [(test_utils::hash(2), 2), (hash, 3)] {
+            pool.process_commands(vec![ReplaceableOrderPoolCommand::CancelBundleByHash(
+                CancelBundleByHash {
+                    hash,
+                    signer: Address::with_last_byte(signer),
+                },
+            )]);
+        }
+        assert_eq!(pool.content_count(), (0, 1));
+        assert_eq!(sink.removed_txs.lock().len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_private_tx() {
+        let mut pool = OrderPool::new();
+        let sink = CollectingSink::default();
+        pool.add_sink(1, Box::new(sink.clone()));
+        let mut private_tx = mempool_tx(1, 0, 2);
+        private_tx.metadata_mut().signer = Some(Address::with_last_byte(1));
+        let public_tx = mempool_tx(2, 1, 2);
+        pool.process_commands(vec![
+            ReplaceableOrderPoolCommand::Order(private_tx.clone()),
+            ReplaceableOrderPoolCommand::Order(public_tx.clone()),
+        ]);
+        assert_eq!(pool.content_count(), (2, 0));
+
+        let cancel = |hash: u64, signer: u8| {
+            ReplaceableOrderPoolCommand::CancelBundleByHash(CancelBundleByHash {
+                hash: test_utils::hash(hash),
+                signer: Address::with_last_byte(signer),
+            })
+        };
+        // other signer, public tx
+        pool.process_commands(vec![cancel(1, 2), cancel(2, 1)]);
+        assert_eq!(pool.content_count(), (2, 0));
+        assert!(sink.removed_txs.lock().is_empty());
+
+        pool.process_commands(vec![cancel(1, 1)]);
+        assert_eq!(pool.content_count(), (1, 0));
+        assert_eq!(*sink.removed_txs.lock(), vec![private_tx.id()]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Pull mode for the orderpool subscription of a building session.
+//! Instead of getting every order/cancellation as soon as it arrives, the session subscribes an [`OrderPoolDeltas`]
+//! buffer and pulls what accumulated since the last pull ([`OrderPoolDelta`]) every interval. The first delta has the
+//! pool contents at subscription time so the session never has to start over when new orders arrive.
+//!
+//! Consistency guarantees of a delta:
+//! - Commands keep the order in which they reached the pool.
+//! - An order cancelled (by replacement key) or replaced (same nonce mempool tx) before the session pulled it is not
+//!   delivered at all. The cancellation itself is always delivered so versions pulled on previous deltas are removed.
+//! - Nothing is lost between deltas: the pool pushes into the buffer under its lock and a pull takes the whole buffer.
+//!
+//! Pushing runs under the orderpool lock so the buffered inserts are indexed by id and replacement key, a cancellation
+//! only touches the inserts it drops.
+
+use super::replaceable_order_sink::ReplaceableOrderSink;
+use crate::primitives::{Order, OrderId, OrderReplacementKey};
+use ahash::HashMap;
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::trace;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderPoolDeltaCommand {
+    Insert(Order),
+    RemoveBundle(OrderReplacementKey),
+    RemoveTx(OrderId),
+}
+
+#[derive(Debug, Default)]
+pub struct OrderPoolDelta {
+    /// 0 for the first delta of the subscription.
+    pub sequence: u64,
+    pub commands: Vec<OrderPoolDeltaCommand>,
+}
+
+impl OrderPoolDelta {
+    /// false if the sink failed (it's not alive anymore).
+    pub fn apply(self, sink: &mut dyn ReplaceableOrderSink) -> bool {
+        self.commands.into_iter().all(|command| match command {
+            OrderPoolDeltaCommand::Insert(order) => sink.insert_order(order),
+            OrderPoolDeltaCommand::RemoveBundle(key) => sink.remove_bundle(key),
+            OrderPoolDeltaCommand::RemoveTx(id) => sink.remove_tx(id),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct DeltaBuffer {
+    /// None for the inserts dropped by a later cancellation.
+    commands: Vec<Option<OrderPoolDeltaCommand>>,
+    /// Positions in commands of the buffered inserts.
+    inserts_by_id: HashMap<OrderId, Vec<usize>>,
+    inserts_by_replacement_key: HashMap<OrderReplacementKey, Vec<usize>>,
+    next_sequence: u64,
+    closed: bool,
+}
+
+impl DeltaBuffer {
+    fn push(&mut self, command: OrderPoolDeltaCommand) {
+        let dropped = match &command {
+            OrderPoolDeltaCommand::Insert(order) => {
+                let position = self.commands.len();
+                self.inserts_by_id
+                    .entry(order.id())
+                    .or_default()
+                    .push(position);
+                if let Some(key) = order.replacement_key() {
+                    self.inserts_by_replacement_key
+                        .entry(key)
+                        .or_default()
+                        .push(position);
+                }
+                None
+            }
+            OrderPoolDeltaCommand::RemoveBundle(key) => self.inserts_by_replacement_key.remove(key),
+            OrderPoolDeltaCommand::RemoveTx(id) => self.inserts_by_id.remove(id),
+        };
+        // the other index may still point to the dropped positions, taking them again is a no-op
+        for position in dropped.into_iter().flatten() {
+            self.commands[position] = None;
+        }
+        self.commands.push(Some(command));
+    }
+
+    fn take_commands(&mut self) -> Vec<OrderPoolDeltaCommand> {
+        self.inserts_by_id.clear();
+        self.inserts_by_replacement_key.clear();
+        std::mem::take(&mut self.commands)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// Cheap to clone, clones share the buffer.
+#[derive(Debug, Clone, Default)]
+pub struct OrderPoolDeltas {
+    buffer: Arc<Mutex<DeltaBuffer>>,
+}
+
+impl OrderPoolDeltas {
+    /// Sink to subscribe to the orderpool.
+    pub fn sink(&self) -> Box<dyn ReplaceableOrderSink> {
+        Box::new(DeltaSink {
+            buffer: self.buffer.clone(),
+        })
+    }
+
+    /// Everything that arrived since the last call.
+    pub fn take_delta(&self) -> OrderPoolDelta {
+        let mut buffer = self.buffer.lock();
+        let sequence = buffer.next_sequence;
+        buffer.next_sequence += 1;
+        OrderPoolDelta {
+            sequence,
+            commands: buffer.take_commands(),
+        }
+    }
+
+    /// The orderpool will drop the subscription on the next command.
+    pub fn close(&self) {
+        let mut buffer = self.buffer.lock();
+        buffer.closed = true;
+        buffer.take_commands();
+    }
+}
+
+#[derive(Debug)]
+struct DeltaSink {
+    buffer: Arc<Mutex<DeltaBuffer>>,
+}
+
+impl DeltaSink {
+    fn push(&mut self, command: OrderPoolDeltaCommand) -> bool {
+        let mut buffer = self.buffer.lock();
+        if buffer.closed {
+            return false;
+        }
+        buffer.push(command);
+        true
+    }
+}
+
+impl ReplaceableOrderSink for DeltaSink {
+    fn insert_order(&mut self, order: Order) -> bool {
+        self.push(OrderPoolDeltaCommand::Insert(order))
+    }
+
+    fn remove_bundle(&mut self, key: OrderReplacementKey) -> bool {
+        self.push(OrderPoolDeltaCommand::RemoveBundle(key))
+    }
+
+    fn remove_tx(&mut self, id: OrderId) -> bool {
+        self.push(OrderPoolDeltaCommand::RemoveTx(id))
+    }
+
+    fn is_alive(&self) -> bool {
+        !self.buffer.lock().closed
+    }
+}
+
+/// Pulls a delta from deltas every interval (the first one right away) and applies it to sink until cancel or the
+/// sink dies.
+pub fn spawn_delta_pull_job(
+    deltas: OrderPoolDeltas,
+    interval: Duration,
+    mut sink: Box<dyn ReplaceableOrderSink>,
+    cancel: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            let delta = deltas.take_delta();
+            if delta.commands.is_empty() {
+                continue;
+            }
+            trace!(
+                sequence = delta.sequence,
+                commands = delta.commands.len(),
+                "Applying orderpool delta"
+            );
+            if !delta.apply(sink.as_mut()) {
+                break;
+            }
+        }
+        deltas.close();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Bundle, BundleReplacementData, BundleReplacementKey, Metadata};
+    use alloy_primitives::Address;
+    use uuid::Uuid;
+
+    fn bundle(id: u128, replacement_key: Option<BundleReplacementKey>) -> Order {
+        Order::Bundle(Bundle {
+            block: 1,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Uuid::from_u128(id),
+            replacement_data: replacement_key.map(|key| BundleReplacementData {
+                key,
+                sequence_number: 0,
+            }),
+            signer: Some(Address::with_last_byte(1)),
+            metadata: Metadata::default(),
+        })
+    }
+
+    #[test]
+    fn test_deltas() {
+        let deltas = OrderPoolDeltas::default();
+        let mut sink = deltas.sink();
+        let key = BundleReplacementKey::new(Uuid::from_u128(100), Address::with_last_byte(1));
+        let (plain, replaceable) = (bundle(1, None), bundle(2, Some(key)));
+
+        assert!(sink.insert_order(plain.clone()));
+        assert!(sink.insert_order(replaceable.clone()));
+        let delta = deltas.take_delta();
+        assert_eq!(delta.sequence, 0);
+        assert_eq!(
+            delta.commands,
+            vec![
+                OrderPoolDeltaCommand::Insert(plain.clone()),
+                OrderPoolDeltaCommand::Insert(replaceable.clone())
+            ]
+        );
+        assert!(deltas.take_delta().commands.is_empty());
+
+        // cancelled before the pull: only the cancellation is delivered
+        let replaceable_v2 = bundle(3, Some(key));
+        let other = bundle(4, None);
+        assert!(sink.insert_order(replaceable_v2));
+        assert!(sink.insert_order(other.clone()));
+        assert!(sink.remove_bundle(OrderReplacementKey::Bundle(key)));
+        assert!(sink.remove_tx(plain.id()));
+        let delta = deltas.take_delta();
+        assert_eq!(delta.sequence, 2);
+        assert_eq!(
+            delta.commands,
+            vec![
+                OrderPoolDeltaCommand::Insert(other.clone()),
+                OrderPoolDeltaCommand::RemoveBundle(OrderReplacementKey::Bundle(key)),
+                OrderPoolDeltaCommand::RemoveTx(plain.id()),
+            ]
+        );
+
+        // an order removed before the pull is never delivered
+        assert!(sink.insert_order(plain.clone()));
+        assert!(sink.remove_tx(plain.id()));
+        assert_eq!(
+            deltas.take_delta().commands,
+            vec![OrderPoolDeltaCommand::RemoveTx(plain.id())]
+        );
+
+        deltas.close();
+        assert!(!sink.is_alive());
+        assert!(!sink.insert_order(other));
+        assert!(deltas.take_delta().commands.is_empty());
+    }
+
+    #[test]
+    fn test_deltas_dropped_by_both_indexes() {
+        let deltas = OrderPoolDeltas::default();
+        let mut sink = deltas.sink();
+        let key = BundleReplacementKey::new(Uuid::from_u128(100), Address::with_last_byte(1));
+        let replaceable = bundle(1, Some(key));
+        let kept = bundle(2, None);
+        assert!(sink.insert_order(replaceable.clone()));
+        assert!(sink.insert_order(kept.clone()));
+        // dropped by id, then its key points to an already dropped insert
+        assert!(sink.remove_tx(replaceable.id()));
+        assert!(sink.remove_bundle(OrderReplacementKey::Bundle(key)));
+        assert_eq!(
+            deltas.take_delta().commands,
+            vec![
+                OrderPoolDeltaCommand::Insert(kept),
+                OrderPoolDeltaCommand::RemoveTx(replaceable.id()),
+                OrderPoolDeltaCommand::RemoveBundle(OrderReplacementKey::Bundle(key)),
+            ]
+        );
+
+        // the indexes don't outlive the pull
+        assert!(sink.insert_order(replaceable.clone()));
+        assert_eq!(
+            deltas.take_delta().commands,
+            vec![OrderPoolDeltaCommand::Insert(replaceable)]
+        );
+    }
+}
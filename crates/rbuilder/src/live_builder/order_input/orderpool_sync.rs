@@ -0,0 +1,528 @@
+//! Warm sync of the orderpool between rbuilder instances for HA deployments.
+//! The active instance runs a sync server. A standby connects to it, gets a snapshot of the [`OrderPool`] and then
+//! every new order/cancellation as it arrives, so on failover it already has all the flow (private tenant orders
+//! included) for the slot in progress.
+//!
+//! Protocol: newline delimited frames over tcp, the shared secret never goes over the wire.
+//! - The server sends a random hex nonce, the standby answers with the hex HMAC-SHA256 of it (see [`auth_tag`]).
+//! - Then the server only sends `<hex tag> <json SyncMessage>` frames, tag being the HMAC of nonce, frame number and
+//!   json (see [`frame_tag`]). The standby drops the connection on the first bad tag so frames can't be injected,
+//!   replayed or reordered.
+//!
+//! Frames are authenticated, NOT encrypted: run the sync on a private network or through a tunnel.
+//! Orders keep their signer so the standby applies the same tenant privacy rules as long as it uses the same tenants config.
+//! Orders of private tenants are only synced if the tenant allows it ([`super::tenants::TenantRegistry::can_sync`]).
+//! Orders with blobs are not synced (RawOrder can't carry them), the standby gets them from its own mempool.
+//! Never configure two instances as peers of each other: cancellations would bounce forever.
+
+use super::{orderpool::OrderPool, CancelBundleByHash, ReplaceableOrderPoolCommand};
+use crate::primitives::{
+    serialize::{CancelShareBundle, RawOrder, RawOrderConvertError, TxEncoding},
+    BundleReplacementKey, ShareBundleReplacementKey,
+};
+use alloy_primitives::{hex, Address, B256};
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use subtle::ConstantTimeEq;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, error::SendTimeoutError},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Time a peer has to send the secret after connecting.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderPoolSyncConfig {
+    /// If set we serve our orderpool to standby instances here.
+    pub server_address: Option<SocketAddr>,
+    /// If set (host:port) we follow the orderpool of the active instance.
+    pub peer_address: Option<String>,
+    /// Shared between the active instance and the standbys, can't be empty.
+    pub secret: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+const AUTH_DOMAIN: &[u8] = b"rbuilder-orderpool-sync-auth";
+
+fn hmac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size")
+}
+
+/// Answer of the standby to the nonce of the server.
+pub fn auth_tag(secret: &str, nonce: &[u8]) -> [u8; 32] {
+    let mut mac = hmac(secret);
+    mac.update(AUTH_DOMAIN);
+    mac.update(nonce);
+    mac.finalize().into_bytes().into()
+}
+
+/// Tag of the frame_number-th frame (0 based) of the connection with that nonce.
+pub fn frame_tag(secret: &str, nonce: &[u8], frame_number: u64, payload: &[u8]) -> [u8; 32] {
+    let mut mac = hmac(secret);
+    mac.update(nonce);
+    mac.update(&frame_number.to_be_bytes());
+    mac.update(payload);
+    mac.finalize().into_bytes().into()
+}
+
+fn tag_matches(tag: &str, expected: &[u8; 32]) -> bool {
+    hex::decode(tag.trim()).is_ok_and(|tag| bool::from(tag.ct_eq(expected.as_slice())))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SyncMessage {
+    Order {
+        order: RawOrder,
+    },
+    #[serde(rename_all = "camelCase")]
+    CancelShareBundle {
+        block: u64,
+        replacement_uuid: Uuid,
+        signing_address: Address,
+    },
+    #[serde(rename_all = "camelCase")]
+    CancelBundle {
+        replacement_uuid: Uuid,
+        signing_address: Address,
+    },
+    #[serde(rename_all = "camelCase")]
+    CancelBundleByHash {
+        bundle_hash: B256,
+        signing_address: Address,
+    },
+}
+
+impl SyncMessage {
+    /// None for commands we don't sync.
+    pub fn from_command(command: &ReplaceableOrderPoolCommand) -> Option<Self> {
+        Some(match command {
+            ReplaceableOrderPoolCommand::Order(order) => {
+                if order.has_blobs() {
+                    return None;
+                }
+                SyncMessage::Order {
+                    order: order.clone().into(),
+                }
+            }
+            ReplaceableOrderPoolCommand::CancelShareBundle(cancel) => {
+                SyncMessage::CancelShareBundle {
+                    block: cancel.block,
+                    replacement_uuid: cancel.key.key().id,
+                    signing_address: cancel.key.key().signer,
+                }
+            }
+            ReplaceableOrderPoolCommand::CancelBundle(key) => SyncMessage::CancelBundle {
+                replacement_uuid: key.key().id,
+                signing_address: key.key().signer,
+            },
+            ReplaceableOrderPoolCommand::CancelBundleByHash(cancel) => {
+                SyncMessage::CancelBundleByHash {
+                    bundle_hash: cancel.hash,
+                    signing_address: cancel.signer,
+                }
+            }
+        })
+    }
+
+    pub fn into_command(self) -> Result<ReplaceableOrderPoolCommand, RawOrderConvertError> {
+        Ok(match self {
+            SyncMessage::Order { order } => {
+                ReplaceableOrderPoolCommand::Order(order.decode(TxEncoding::NoBlobData)?)
+            }
+            SyncMessage::CancelShareBundle {
+                block,
+                replacement_uuid,
+                signing_address,
+            } => ReplaceableOrderPoolCommand::CancelShareBundle(CancelShareBundle {
+                block,
+                key: ShareBundleReplacementKey::new(replacement_uuid, signing_address),
+            }),
+            SyncMessage::CancelBundle {
+                replacement_uuid,
+                signing_address,
+            } => ReplaceableOrderPoolCommand::CancelBundle(BundleReplacementKey::new(
+                replacement_uuid,
+                signing_address,
+            )),
+            SyncMessage::CancelBundleByHash {
+                bundle_hash,
+                signing_address,
+            } => ReplaceableOrderPoolCommand::CancelBundleByHash(CancelBundleByHash {
+                hash: bundle_hash,
+                signer: signing_address,
+            }),
+        })
+    }
+}
+
+/// Serves the orderpool to standby instances until global_cancel.
+pub async fn spawn_orderpool_sync_server(
+    address: SocketAddr,
+    secret: String,
+    orderpool: Arc<Mutex<OrderPool>>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<JoinHandle<()>> {
+    eyre::ensure!(!secret.is_empty(), "Orderpool sync secret can't be empty");
+    let listener = TcpListener::bind(address).await?;
+    info!(?address, "Orderpool sync server listening");
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                res = listener.accept() => match res {
+                    Ok(res) => res,
+                    Err(err) => {
+                        warn!(?err, "Orderpool sync server failed to accept connection");
+                        continue;
+                    }
+                },
+            };
+            tokio::spawn(serve_peer(
+                stream,
+                peer,
+                secret.clone(),
+                orderpool.clone(),
+                global_cancel.clone(),
+            ));
+        }
+        info!("Orderpool sync server finished");
+    }))
+}
+
+async fn serve_peer(
+    stream: TcpStream,
+    peer: SocketAddr,
+    secret: String,
+    orderpool: Arc<Mutex<OrderPool>>,
+    global_cancel: CancellationToken,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let nonce: [u8; 32] = rand::random();
+    let authenticated = async {
+        writer
+            .write_all(format!("{}\n", hex::encode(nonce)).as_bytes())
+            .await
+            .ok()?;
+        let answer = lines.next_line().await.ok()??;
+        tag_matches(&answer, &auth_tag(&secret, &nonce)).then_some(())
+    };
+    if !matches!(
+        tokio::time::timeout(AUTH_TIMEOUT, authenticated).await,
+        Ok(Some(()))
+    ) {
+        warn!(?peer, "Orderpool sync peer failed to authenticate");
+        return;
+    }
+    info!(?peer, "Orderpool sync peer connected");
+    let (snapshot, mut commands) = orderpool.lock().add_sync_subscriber();
+    let mut frames = FrameWriter {
+        writer,
+        secret,
+        nonce,
+        frame_number: 0,
+    };
+    for command in snapshot {
+        if let Err(err) = frames.send(&command).await {
+            info!(?peer, ?err, "Orderpool sync peer disconnected");
+            return;
+        }
+    }
+    loop {
+        let command = tokio::select! {
+            _ = global_cancel.cancelled() => break,
+            command = commands.recv() => match command {
+                Some(command) => command,
+                None => {
+                    warn!(?peer, "Orderpool sync peer fell behind, disconnecting");
+                    break;
+                }
+            },
+        };
+        if let Err(err) = frames.send(&command).await {
+            info!(?peer, ?err, "Orderpool sync peer disconnected");
+            break;
+        }
+    }
+}
+
+struct FrameWriter<W> {
+    writer: W,
+    secret: String,
+    nonce: [u8; 32],
+    frame_number: u64,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    async fn send(&mut self, command: &ReplaceableOrderPoolCommand) -> std::io::Result<()> {
+        let Some(message) = SyncMessage::from_command(command) else {
+            return Ok(());
+        };
+        let payload = match serde_json::to_vec(&message) {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!(?err, "Failed to serialize orderpool sync message");
+                return Ok(());
+            }
+        };
+        let tag = frame_tag(&self.secret, &self.nonce, self.frame_number, &payload);
+        self.frame_number += 1;
+        let mut frame = format!("{} ", hex::encode(tag)).into_bytes();
+        frame.extend_from_slice(&payload);
+        frame.push(b'\n');
+        self.writer.write_all(&frame).await
+    }
+}
+
+/// Follows the orderpool of the active instance at peer_address sending everything to order_sender.
+/// Reconnects until global_cancel.
+pub fn spawn_orderpool_sync_client(
+    peer_address: String,
+    secret: String,
+    order_sender: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    send_timeout: Duration,
+    global_cancel: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while !global_cancel.is_cancelled() {
+            if let Err(err) = follow_peer(
+                &peer_address,
+                &secret,
+                &order_sender,
+                send_timeout,
+                &global_cancel,
+            )
+            .await
+            {
+                warn!(?err, peer_address, "Orderpool sync with peer failed");
+            }
+            tokio::select! {
+                _ = global_cancel.cancelled() => break,
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {},
+            }
+        }
+        info!("Orderpool sync client finished");
+    })
+}
+
+async fn follow_peer(
+    peer_address: &str,
+    secret: &str,
+    order_sender: &mpsc::Sender<ReplaceableOrderPoolCommand>,
+    send_timeout: Duration,
+    global_cancel: &CancellationToken,
+) -> eyre::Result<()> {
+    let stream = TcpStream::connect(peer_address).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let nonce = match tokio::time::timeout(AUTH_TIMEOUT, lines.next_line()).await {
+        Ok(Ok(Some(nonce))) => hex::decode(nonce.trim())?,
+        _ => eyre::bail!("Orderpool sync peer didn't send its nonce"),
+    };
+    writer
+        .write_all(format!("{}\n", hex::encode(auth_tag(secret, &nonce))).as_bytes())
+        .await?;
+    info!(peer_address, "Connected to orderpool sync peer");
+    let mut frame_number = 0;
+    loop {
+        let line = tokio::select! {
+            _ = global_cancel.cancelled() => return Ok(()),
+            line = lines.next_line() => line?,
+        };
+        let Some(line) = line else {
+            eyre::bail!("Orderpool sync peer closed the connection");
+        };
+        let Some((tag, payload)) = line.split_once(' ') else {
+            eyre::bail!("Orderpool sync peer sent a frame without tag");
+        };
+        if !tag_matches(
+            tag,
+            &frame_tag(secret, &nonce, frame_number, payload.as_bytes()),
+        ) {
+            eyre::bail!("Orderpool sync peer sent a frame with a bad tag");
+        }
+        frame_number += 1;
+        let command = match serde_json::from_str::<SyncMessage>(payload)
+            .map_err(eyre::Report::from)
+            .and_then(|message| message.into_command().map_err(eyre::Report::from))
+        {
+            Ok(command) => command,
+            Err(err) => {
+                warn!(?err, "Failed to decode orderpool sync message");
+                continue;
+            }
+        };
+        match order_sender.send_timeout(command, send_timeout).await {
+            Ok(()) => {}
+            Err(SendTimeoutError::Timeout(_)) => {
+                warn!("Failed to send synced order, timeout");
+            }
+            Err(SendTimeoutError::Closed(_)) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Bundle, BundleReplacementData, Order};
+
+    fn roundtrip(command: &ReplaceableOrderPoolCommand) -> ReplaceableOrderPoolCommand {
+        let message = SyncMessage::from_command(command).unwrap();
+        let json = serde_json::to_string(&message).unwrap();
+        let decoded: SyncMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, message);
+        decoded.into_command().unwrap()
+    }
+
+    #[test]
+    fn test_sync_message_roundtrip() {
+        let signer = Address::with_last_byte(1);
+        let mut bundle = Bundle {
+            block: 10,
+            min_timestamp: None,
+            max_timestamp: Some(100),
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Default::default(),
+            replacement_data: Some(BundleReplacementData {
+                key: BundleReplacementKey::new(Uuid::from_u128(7), signer),
+                sequence_number: 2,
+            }),
+            signer: Some(signer),
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        let order = Order::Bundle(bundle);
+        match roundtrip(&ReplaceableOrderPoolCommand::Order(order.clone())) {
+            ReplaceableOrderPoolCommand::Order(synced) => {
+                assert_eq!(synced.id(), order.id());
+                assert_eq!(synced.signer(), Some(signer));
+                assert_eq!(synced.replacement_key(), order.replacement_key());
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+
+        let key = BundleReplacementKey::new(Uuid::from_u128(7), signer);
+        assert!(matches!(
+            roundtrip(&ReplaceableOrderPoolCommand::CancelBundle(key)),
+            ReplaceableOrderPoolCommand::CancelBundle(synced) if synced == key
+        ));
+
+        let cancel = CancelBundleByHash {
+            hash: B256::with_last_byte(9),
+            signer,
+        };
+        assert!(matches!(
+            roundtrip(&ReplaceableOrderPoolCommand::CancelBundleByHash(cancel)),
+            ReplaceableOrderPoolCommand::CancelBundleByHash(synced) if synced == cancel
+        ));
+
+        let key = ShareBundleReplacementKey::new(Uuid::from_u128(8), signer);
+        match roundtrip(&ReplaceableOrderPoolCommand::CancelShareBundle(
+            CancelShareBundle { block: 11, key },
+        )) {
+            ReplaceableOrderPoolCommand::CancelShareBundle(synced) => {
+                assert_eq!(synced.block, 11);
+                assert_eq!(synced.key, key);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        let nonce = [1u8; 32];
+        let tag = frame_tag("secret", &nonce, 0, b"{}");
+        assert!(tag_matches(&hex::encode(tag), &tag));
+        // replayed as another frame, on another connection or with another key
+        assert_ne!(tag, frame_tag("secret", &nonce, 1, b"{}"));
+        assert_ne!(tag, frame_tag("secret", &[2u8; 32], 0, b"{}"));
+        assert_ne!(tag, frame_tag("other", &nonce, 0, b"{}"));
+        assert_ne!(auth_tag("secret", &nonce), auth_tag("other", &nonce));
+        assert!(!tag_matches("garbage", &tag));
+        assert!(!tag_matches("", &tag));
+    }
+
+    fn pool_with_bundle(id: u128) -> (Arc<Mutex<OrderPool>>, Order) {
+        let mut bundle = Bundle {
+            block: 10,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: Uuid::from_u128(id),
+            replacement_data: None,
+            signer: None,
+            metadata: Default::default(),
+        };
+        bundle.hash_slow();
+        let order = Order::Bundle(bundle);
+        let mut pool = OrderPool::new();
+        pool.process_commands(vec![ReplaceableOrderPoolCommand::Order(order.clone())]);
+        (Arc::new(Mutex::new(pool)), order)
+    }
+
+    #[tokio::test]
+    async fn test_sync_server_and_client() {
+        let cancel = CancellationToken::new();
+        let (pool, order) = pool_with_bundle(1);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(
+            spawn_orderpool_sync_server(address, String::new(), pool.clone(), cancel.clone())
+                .await
+                .is_err()
+        );
+        spawn_orderpool_sync_server(address, "secret".to_string(), pool, cancel.clone())
+            .await
+            .unwrap();
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        spawn_orderpool_sync_client(
+            address.to_string(),
+            "secret".to_string(),
+            sender,
+            Duration::from_secs(1),
+            cancel.clone(),
+        );
+        let synced = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(
+            synced,
+            Some(ReplaceableOrderPoolCommand::Order(synced)) if synced.id() == order.id()
+        ));
+
+        // wrong secret: nothing arrives
+        let (sender, mut receiver) = mpsc::channel(10);
+        spawn_orderpool_sync_client(
+            address.to_string(),
+            "other".to_string(),
+            sender,
+            Duration::from_secs(1),
+            cancel.clone(),
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), receiver.recv())
+                .await
+                .is_err()
+        );
+        cancel.cancel();
+    }
+}
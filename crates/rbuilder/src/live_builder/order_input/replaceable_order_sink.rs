@@ -1,6 +1,6 @@
 use tracing::info;
 
-use crate::primitives::{Order, OrderReplacementKey};
+use crate::primitives::{Order, OrderId, OrderReplacementKey};
 use core::fmt::Debug;
 
 /// Receiver of order commands in a low level order stream (mempool + RPC calls).
@@ -13,6 +13,8 @@ use core::fmt::Debug;
 pub trait ReplaceableOrderSink: Debug + Send {
     fn insert_order(&mut self, order: Order) -> bool;
     fn remove_bundle(&mut self, key: OrderReplacementKey) -> bool;
+    /// Mempool tx replaced by one with the same sender and nonce paying more.
+    fn remove_tx(&mut self, id: OrderId) -> bool;
     /// @Pending remove this ugly hack to check if we can stop sending data.
     /// It should be replaced for a better control over object destruction
     fn is_alive(&self) -> bool;
@@ -37,6 +39,11 @@ impl ReplaceableOrderSink for ReplaceableOrderPrinter {
         true
     }
 
+    fn remove_tx(&mut self, id: OrderId) -> bool {
+        info!(order_id = ?id, "Replaced tx");
+        true
+    }
+
     fn is_alive(&self) -> bool {
         true
     }
@@ -0,0 +1,156 @@
+//! Connection and call metrics of the input RPC server (see [`super::rpc_server`]).
+//! Every connection is tracked from on_connect to on_disconnect: its lifetime goes to the metrics and a log line with
+//! its peer, transport and lifetime is written when it closes (WebSocket searchers keep theirs open for a long time).
+//! Metric labels are only the transport (http/ws), peers would be unbounded labels.
+//! jsonrpsee doesn't tell the logger which connection a call comes from so calls are only counted by method and
+//! transport.
+
+use crate::telemetry::{add_rpc_connection_closed, inc_rpc_calls, inc_rpc_connections};
+use jsonrpsee::{
+    core::server::helpers::MethodResponseResult,
+    server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol},
+};
+use parking_lot::Mutex;
+use std::{cell::Cell, collections::HashMap, net::SocketAddr, sync::Arc, time::Instant};
+use tracing::debug;
+
+thread_local! {
+    /// Peer of the last on_connect of this thread.
+    static CONNECTING_PEER: Cell<Option<SocketAddr>> = const { Cell::new(None) };
+}
+
+/// Peer of the request last handed to the jsonrpsee service on this thread. jsonrpsee calls the logger's on_connect
+/// synchronously when the service is called so a middleware calling the service directly reads its request's peer
+/// right after the call (see [`super::rpc_rate_limit`]).
+pub fn take_connecting_peer() -> Option<SocketAddr> {
+    CONNECTING_PEER.with(|peer| peer.take())
+}
+
+pub fn transport_label(transport: TransportProtocol) -> &'static str {
+    match transport {
+        TransportProtocol::Http => "http",
+        TransportProtocol::WebSocket => "ws",
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenConnection {
+    connected_at: Instant,
+    transport: TransportProtocol,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RpcConnectionMetrics {
+    /// Open connections by remote address.
+    connections: Arc<Mutex<HashMap<SocketAddr, OpenConnection>>>,
+}
+
+impl RpcConnectionMetrics {
+    /// Open connections of transport.
+    pub fn open_connections(&self, transport: TransportProtocol) -> usize {
+        self.connections
+            .lock()
+            .values()
+            .filter(|connection| {
+                transport_label(connection.transport) == transport_label(transport)
+            })
+            .count()
+    }
+}
+
+impl Logger for RpcConnectionMetrics {
+    type Instant = Instant;
+
+    fn on_connect(&self, remote_addr: SocketAddr, _request: &HttpRequest, t: TransportProtocol) {
+        CONNECTING_PEER.with(|peer| peer.set(Some(remote_addr)));
+        self.connections.lock().insert(
+            remote_addr,
+            OpenConnection {
+                connected_at: Instant::now(),
+                transport: t,
+            },
+        );
+        inc_rpc_connections(transport_label(t));
+    }
+
+    fn on_request(&self, _transport: TransportProtocol) -> Self::Instant {
+        Instant::now()
+    }
+
+    fn on_call(
+        &self,
+        method_name: &str,
+        _params: Params,
+        kind: MethodKind,
+        transport: TransportProtocol,
+    ) {
+        // method names of unknown methods come from the clients, don't let them create labels
+        let method = if matches!(kind, MethodKind::NotFound) {
+            "not_found"
+        } else {
+            method_name
+        };
+        inc_rpc_calls(method, transport_label(transport));
+    }
+
+    fn on_result(
+        &self,
+        _method_name: &str,
+        _success_or_error: MethodResponseResult,
+        _started_at: Self::Instant,
+        _transport: TransportProtocol,
+    ) {
+    }
+
+    fn on_response(
+        &self,
+        _result: &str,
+        _started_at: Self::Instant,
+        _transport: TransportProtocol,
+    ) {
+    }
+
+    fn on_disconnect(&self, remote_addr: SocketAddr, transport: TransportProtocol) {
+        let duration = self
+            .connections
+            .lock()
+            .remove(&remote_addr)
+            .map(|connection| connection.connected_at.elapsed());
+        debug!(
+            peer = %remote_addr,
+            transport = transport_label(transport),
+            duration_ms = duration.map(|duration| duration.as_millis() as u64),
+            "Input RPC connection closed"
+        );
+        add_rpc_connection_closed(transport_label(transport), duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_connections() {
+        let metrics = RpcConnectionMetrics::default();
+        let request = HttpRequest::default();
+        let (ws_peer, http_peer): (SocketAddr, SocketAddr) = (
+            "127.0.0.1:1000".parse().unwrap(),
+            "127.0.0.1:1001".parse().unwrap(),
+        );
+        metrics.on_connect(ws_peer, &request, TransportProtocol::WebSocket);
+        assert_eq!(take_connecting_peer(), Some(ws_peer));
+        metrics.on_connect(http_peer, &request, TransportProtocol::Http);
+        assert_eq!(metrics.open_connections(TransportProtocol::WebSocket), 1);
+        assert_eq!(metrics.open_connections(TransportProtocol::Http), 1);
+
+        metrics.on_disconnect(ws_peer, TransportProtocol::WebSocket);
+        assert_eq!(metrics.open_connections(TransportProtocol::WebSocket), 0);
+        assert_eq!(metrics.open_connections(TransportProtocol::Http), 1);
+        // clones share the connections
+        metrics
+            .clone()
+            .on_disconnect(http_peer, TransportProtocol::Http);
+        assert_eq!(metrics.open_connections(TransportProtocol::Http), 0);
+    }
+}
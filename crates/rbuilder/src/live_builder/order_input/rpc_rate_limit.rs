@@ -0,0 +1,468 @@
+//! Token bucket rate limiting of the input RPC (see [`super::rpc_server`]) and of the gRPC server by submitter.
+//! Submitters are identified by the X-Flashbots-Signature signer (checked by [`super::flashbots_signature`], which
+//! must be the outer middleware). Requests without a valid signature fall back to the client ip: the peer address
+//! of the connection, or the X-Forwarded-For/X-Real-IP client if the peer is one of our trusted proxies.
+//! Every call of a JSON-RPC batch takes a token.
+//! Throttled requests never reach the methods, they get a -32005 JSON-RPC error with http status 429.
+//! On WebSocket connections only the upgrade request is limited.
+//!
+//! jsonrpsee doesn't give the peer address to the middlewares, we get it from the
+//! [`super::rpc_connection_metrics::RpcConnectionMetrics`] logger which jsonrpsee calls synchronously when we hand it
+//! the request (see [`take_connecting_peer`]) and before anything runs. [`RateLimitLayer`] must be the inner
+//! middleware for that.
+
+use super::{
+    flashbots_signature::RequestSignature,
+    rpc_connection_metrics::{take_connecting_peer, RpcConnectionMetrics},
+};
+use crate::telemetry::inc_rpc_rate_limited;
+use alloy_primitives::Address;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use hyper_0_14::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, UPGRADE},
+    Body, Request, Response, StatusCode,
+};
+use ipnet::IpNet;
+use jsonrpsee::server::logger::{Logger, TransportProtocol};
+use serde::de::IgnoredAny;
+use std::{
+    future::Future,
+    net::IpAddr,
+    num::NonZeroU32,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+use tracing::trace;
+
+pub const RATE_LIMITED_ERROR_CODE: i32 = -32005;
+/// Every this many checks we forget the submitters whose buckets are full again.
+const RETAIN_RECENT_INTERVAL: u64 = 10_000;
+
+/// Requests per second, unset means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcRateLimits {
+    /// Of each signer. If unset signed requests are limited by ip.
+    pub per_signer: Option<u32>,
+    /// Of each ip.
+    pub per_ip: Option<u32>,
+    /// Proxies whose X-Forwarded-For/X-Real-IP we believe. Other peers are limited by their own address.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl RpcRateLimits {
+    pub fn is_enabled(&self) -> bool {
+        self.per_signer.is_some() || self.per_ip.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubmitterKey {
+    Signer(Address),
+    Ip(IpAddr),
+}
+
+impl SubmitterKey {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubmitterKey::Signer(_) => "signer",
+            SubmitterKey::Ip(_) => "ip",
+        }
+    }
+}
+
+fn keyed_limiter(per_second: Option<u32>) -> Option<DefaultKeyedRateLimiter<SubmitterKey>> {
+    per_second
+        .and_then(NonZeroU32::new)
+        .map(|per_second| RateLimiter::keyed(Quota::per_second(per_second)))
+}
+
+#[derive(Debug)]
+pub struct RpcRateLimiter {
+    signers: Option<DefaultKeyedRateLimiter<SubmitterKey>>,
+    ips: Option<DefaultKeyedRateLimiter<SubmitterKey>>,
+    trusted_proxies: Vec<IpNet>,
+    checks: AtomicU64,
+}
+
+impl RpcRateLimiter {
+    pub fn new(limits: &RpcRateLimits) -> Self {
+        Self {
+            signers: keyed_limiter(limits.per_signer),
+            ips: keyed_limiter(limits.per_ip),
+            trusted_proxies: limits.trusted_proxies.clone(),
+            checks: AtomicU64::new(0),
+        }
+    }
+
+    fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|proxy| proxy.contains(ip))
+    }
+
+    /// Ip of the client behind the peer of the connection.
+    /// X-Forwarded-For/X-Real-IP are only used if the peer is a trusted proxy, from X-Forwarded-For we take the
+    /// last address not added by one of our proxies (the ones before it are whatever the client sent).
+    pub fn client_ip<'a>(&self, peer: IpAddr, header: impl Fn(&str) -> Option<&'a str>) -> IpAddr {
+        if !self.is_trusted_proxy(&peer) {
+            return peer;
+        }
+        if let Some(forwarded_for) = header("x-forwarded-for") {
+            let mut client = peer;
+            for ip in forwarded_for.rsplit(',') {
+                match ip.trim().parse() {
+                    Ok(ip) => client = ip,
+                    Err(_) => break,
+                }
+                if !self.is_trusted_proxy(&client) {
+                    break;
+                }
+            }
+            return client;
+        }
+        header("x-real-ip")
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+
+    pub fn key(&self, signer: Option<Address>, ip: IpAddr) -> SubmitterKey {
+        match signer {
+            Some(signer) if self.signers.is_some() => SubmitterKey::Signer(signer),
+            _ => SubmitterKey::Ip(ip),
+        }
+    }
+
+    /// false if the submitter went over its limit, the request (of `calls` calls) must be rejected.
+    pub fn check(&self, key: &SubmitterKey, calls: u32) -> bool {
+        if self.checks.fetch_add(1, Ordering::Relaxed) % RETAIN_RECENT_INTERVAL == 0 {
+            self.retain_recent();
+        }
+        let limiter = match key {
+            SubmitterKey::Signer(_) => &self.signers,
+            SubmitterKey::Ip(_) => &self.ips,
+        };
+        let calls = NonZeroU32::new(calls).unwrap_or(NonZeroU32::MIN);
+        let allowed = limiter.as_ref().map_or(true, |limiter| {
+            matches!(limiter.check_key_n(key, calls), Ok(Ok(())))
+        });
+        if !allowed {
+            trace!(?key, "Rate limited input RPC request");
+            inc_rpc_rate_limited(key.label());
+        }
+        allowed
+    }
+
+    fn retain_recent(&self) {
+        for limiter in self.signers.iter().chain(&self.ips) {
+            limiter.retain_recent();
+        }
+    }
+}
+
+fn rate_limited_response(id: serde_json::Value) -> Response<Body> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": RATE_LIMITED_ERROR_CODE, "message": "rate limit exceeded"},
+    });
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid rate limited response")
+}
+
+/// Number of calls of a JSON-RPC request (1 if it's not a batch).
+fn call_count(body: &[u8]) -> u32 {
+    let is_batch = body
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .is_some_and(|byte| *byte == b'[');
+    if !is_batch {
+        return 1;
+    }
+    serde_json::from_slice::<Vec<IgnoredAny>>(body)
+        .map_or(1, |calls| calls.len().try_into().unwrap_or(u32::MAX))
+}
+
+fn request_id(body: &[u8]) -> serde_json::Value {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|request| request.get("id").cloned())
+        .unwrap_or_default()
+}
+
+/// jsonrpsee http middleware, must be the inner one (see the module doc).
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RpcRateLimiter>,
+    /// The logger of the server, tells us the peer of the request.
+    metrics: RpcConnectionMetrics,
+    /// Bigger bodies are not read (the server will reject them anyway).
+    max_body_size: u64,
+}
+
+impl RateLimitLayer {
+    pub fn new(
+        limiter: Arc<RpcRateLimiter>,
+        metrics: RpcConnectionMetrics,
+        max_body_size: u32,
+    ) -> Self {
+        Self {
+            limiter,
+            metrics,
+            max_body_size: max_body_size as u64,
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request<Body>> for RateLimit<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // the clone is not ready, keep the one that is
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let RateLimitLayer {
+            limiter,
+            metrics,
+            max_body_size,
+        } = self.layer.clone();
+        Box::pin(async move {
+            let signer = request
+                .extensions()
+                .get::<RequestSignature>()
+                .and_then(RequestSignature::signer);
+            let body_size = request
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            let (parts, body) = request.into_parts();
+            // bodies we don't read (too big or no length) count as a single call
+            let (body, calls, id) = if body_size.is_some_and(|body_size| body_size <= max_body_size)
+            {
+                let body = hyper_0_14::body::to_bytes(body).await.unwrap_or_default();
+                let calls = call_count(&body);
+                let id = (calls == 1).then(|| request_id(&body)).unwrap_or_default();
+                (Body::from(body), calls, id)
+            } else {
+                (body, 1, serde_json::Value::Null)
+            };
+            let header = |name: &str| {
+                parts
+                    .headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let forwarded_for = header("x-forwarded-for");
+            let real_ip = header("x-real-ip");
+            let is_upgrade = parts.headers.contains_key(UPGRADE);
+            // Nothing runs until we poll the response, we only learn the peer.
+            take_connecting_peer();
+            let response = inner.call(Request::from_parts(parts, body));
+            let Some(peer) = take_connecting_peer() else {
+                trace!("Unknown peer, rate limit middleware is not in front of the server");
+                return response.await;
+            };
+            let ip = limiter.client_ip(peer.ip(), |name| match name {
+                "x-forwarded-for" => forwarded_for.as_deref(),
+                "x-real-ip" => real_ip.as_deref(),
+                _ => None,
+            });
+            if limiter.check(&limiter.key(signer, ip), calls) {
+                return response.await;
+            }
+            drop(response);
+            let transport = if is_upgrade {
+                TransportProtocol::WebSocket
+            } else {
+                TransportProtocol::Http
+            };
+            metrics.on_disconnect(peer, transport);
+            Ok(rate_limited_response(id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_signer: Option<u32>, per_ip: Option<u32>) -> RpcRateLimiter {
+        RpcRateLimiter::new(&RpcRateLimits {
+            per_signer,
+            per_ip,
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        })
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let limiter = limiter(None, Some(1));
+        let client: IpAddr = "1.2.3.4".parse().unwrap();
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let headers = |forwarded_for: Option<&'static str>, real_ip: Option<&'static str>| {
+            move |name: &str| match name {
+                "x-forwarded-for" => forwarded_for,
+                "x-real-ip" => real_ip,
+                _ => None,
+            }
+        };
+        // untrusted peers can't choose their key
+        assert_eq!(
+            limiter.client_ip(client, headers(Some("5.5.5.5"), Some("6.6.6.6"))),
+            client
+        );
+        assert_eq!(limiter.client_ip(proxy, headers(None, None)), proxy);
+        assert_eq!(
+            limiter.client_ip(proxy, headers(None, Some("1.2.3.4"))),
+            client
+        );
+        // the client prepends garbage, our proxies append the real client and themselves
+        assert_eq!(
+            limiter.client_ip(
+                proxy,
+                headers(Some("5.5.5.5, 1.2.3.4, 10.0.0.2"), Some("6.6.6.6"))
+            ),
+            client
+        );
+        assert_eq!(
+            limiter.client_ip(proxy, headers(Some("nope, 10.0.0.2"), None)),
+            "10.0.0.2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_call_count() {
+        assert_eq!(call_count(br#"{"id":1}"#), 1);
+        assert_eq!(call_count(br#" [{"id":1},{"id":2},{"id":3}]"#), 3);
+        assert_eq!(call_count(b"[garbage"), 1);
+        assert_eq!(call_count(b""), 1);
+        assert_eq!(request_id(br#"{"id":7}"#), serde_json::json!(7));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = limiter(Some(2), Some(1));
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let signer = limiter.key(Some(Address::with_last_byte(1)), ip);
+        assert_eq!(signer, SubmitterKey::Signer(Address::with_last_byte(1)));
+        assert!(limiter.check(&signer, 1));
+        assert!(limiter.check(&signer, 1));
+        assert!(!limiter.check(&signer, 1));
+        // every signer has its own bucket
+        assert!(limiter.check(&limiter.key(Some(Address::with_last_byte(2)), ip), 1));
+        // a batch takes a token per call
+        assert!(!limiter.check(&limiter.key(Some(Address::with_last_byte(3)), ip), 3));
+
+        let key = limiter.key(None, ip);
+        assert!(limiter.check(&key, 1));
+        assert!(!limiter.check(&key, 1));
+        // every ip has its own bucket
+        assert!(limiter.check(&limiter.key(None, "1.2.3.5".parse().unwrap()), 1));
+
+        // no signer limit: signed requests are limited by ip
+        let limiter = self::limiter(None, Some(1));
+        assert_eq!(
+            limiter.key(Some(Address::with_last_byte(1)), ip),
+            SubmitterKey::Ip(ip)
+        );
+    }
+
+    /// Stands for the jsonrpsee service: logs the connection when called, answers when polled.
+    #[derive(Debug, Clone)]
+    struct FakeServer {
+        metrics: RpcConnectionMetrics,
+        peer: std::net::SocketAddr,
+    }
+
+    impl Service<Request<Body>> for FakeServer {
+        type Response = Response<Body>;
+        type Error = std::convert::Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            self.metrics
+                .on_connect(self.peer, &request, TransportProtocol::Http);
+            Box::pin(async { Ok(Response::new(Body::empty())) })
+        }
+    }
+
+    async fn send(
+        service: &mut RateLimit<FakeServer>,
+        body: &'static str,
+        forwarded_for: &str,
+    ) -> StatusCode {
+        let request = Request::builder()
+            .header(CONTENT_LENGTH, body.len())
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(body))
+            .unwrap();
+        service.call(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_middleware() {
+        let metrics = RpcConnectionMetrics::default();
+        let layer = RateLimitLayer::new(Arc::new(limiter(None, Some(2))), metrics.clone(), 1024);
+        let mut service = layer.layer(FakeServer {
+            metrics,
+            peer: "1.2.3.4:1000".parse().unwrap(),
+        });
+        let call = r#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]},{"jsonrpc":"2.0","id":2,"method":"eth_sendBundle","params":[]}]"#;
+        assert_eq!(send(&mut service, call, "5.5.5.5").await, StatusCode::OK);
+        // faking another client doesn't give a new bucket
+        assert_eq!(send(&mut service, call, "6.6.6.6").await, StatusCode::OK);
+        assert_eq!(
+            send(&mut service, call, "7.7.7.7").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let mut service = layer.layer(FakeServer {
+            metrics: RpcConnectionMetrics::default(),
+            peer: "1.2.3.5:1000".parse().unwrap(),
+        });
+        assert_eq!(send(&mut service, batch, "").await, StatusCode::OK);
+        assert_eq!(
+            send(&mut service, call, "").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
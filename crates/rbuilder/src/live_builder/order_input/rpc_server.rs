@@ -1,23 +1,111 @@
-use super::{OrderInputConfig, ReplaceableOrderPoolCommand};
-use crate::primitives::{
-    serialize::{RawBundle, RawShareBundle, RawShareBundleDecodeResult, RawTx, TxEncoding},
-    Bundle, BundleReplacementKey, MempoolTx, Order,
+use super::{
+    flashbots_signature::{request_signature, FlashbotsSignatureLayer, RequestSignature},
+    order_schema::{
+        capabilities, check_bundle_fields, check_private_tx_fields, check_share_bundle_fields,
+        negotiated_schema_version, OrderSchemaLayer,
+    },
+    rpc_connection_metrics::RpcConnectionMetrics,
+    rpc_rate_limit::{RateLimitLayer, RpcRateLimiter},
+    tx_type_forks::TxTypeForks,
+    CancelBundleByHash, OrderInputConfig, ReplaceableOrderPoolCommand, RpcTransport,
 };
-use alloy_primitives::{Address, Bytes};
-use jsonrpsee::{server::Server, types::ErrorObject, RpcModule};
-use serde::Deserialize;
+use crate::{
+    live_builder::building::sim_bundle::{
+        SimBundleError, SimBundleOptions, SimBundleResult, SimBundleService, SimBundleTxResult,
+    },
+    primitives::{
+        serialize::{
+            LegacyRawBundle, RawBundle, RawCallBundle, RawOrderSubmission, RawPrivateTx,
+            RawShareBundle, RawShareBundleDecodeResult, RawShareBundleMetadatada, RawTx,
+            TxEncoding,
+        },
+        Bundle, BundleReplacementKey, MempoolTx, Order,
+    },
+    telemetry::inc_share_bundles_not_targeted,
+};
+use alloy_primitives::{Address, Bytes, Signature, B256, U256};
+use jsonrpsee::{
+    server::{BatchRequestConfig, Server},
+    types::ErrorObject,
+    RpcModule,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     net::{SocketAddr, SocketAddrV4},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
+use time::OffsetDateTime;
 use tokio::{
-    sync::{mpsc, mpsc::error::SendTimeoutError},
+    sync::{mpsc, mpsc::error::SendTimeoutError, Semaphore},
     task::JoinHandle,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{info, trace, warn};
 use uuid::Uuid;
 
+/// Response of eth_sendBundle and mev_sendBundle (null if the bundle is dropped or is a cancellation).
+/// orderId (see [crate::primitives::OrderId]) is the id the order has on every other API and status stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SendBundleResponse {
+    bundle_hash: B256,
+    order_id: String,
+}
+
+impl SendBundleResponse {
+    fn new(bundle_hash: B256, order: &Order) -> Self {
+        Self {
+            bundle_hash,
+            order_id: order.id().to_string(),
+        }
+    }
+}
+
+/// Response of eth_callBundle, a subset of the flashbots one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallBundleResponse {
+    bundle_hash: B256,
+    /// Empty if the bundle failed (see error).
+    results: Vec<CallBundleTxResult>,
+    coinbase_diff: U256,
+    bundle_gas_price: U256,
+    total_gas_used: u64,
+    state_block_number: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallBundleTxResult {
+    tx_hash: B256,
+    from_address: Address,
+    to_address: Option<Address>,
+    gas_used: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<&SimBundleTxResult> for CallBundleTxResult {
+    fn from(tx: &SimBundleTxResult) -> Self {
+        Self {
+            tx_hash: tx.tx_hash,
+            from_address: tx.from_address,
+            to_address: tx.to_address,
+            gas_used: tx.gas_used,
+            error: (!tx.success).then(|| "execution reverted".to_string()),
+        }
+    }
+}
+
 /// Creates a jsonrpsee::server::Server configuring the handling for our RPC calls.
 /// Spawns a task that cancels global_cancel if the RPC stops (it's reasonable to shutdown and restart if we don't get orders!).
 /// @Pending reengineering to modularize rpc, block_subsidy_selector here is a patch.
@@ -25,64 +113,180 @@ pub async fn start_server_accepting_bundles(
     config: OrderInputConfig,
     results: mpsc::Sender<ReplaceableOrderPoolCommand>,
     extra_rpc: RpcModule<()>,
+    rate_limiter: Option<Arc<RpcRateLimiter>>,
+    signed_cancellations: Arc<SignedCancellations>,
     global_cancel: CancellationToken,
 ) -> eyre::Result<JoinHandle<()>> {
     let addr = SocketAddr::V4(SocketAddrV4::new(config.server_ip, config.server_port));
     let timeout = config.results_channel_timeout;
+    let builder_names = Arc::new(config.builder_names.clone());
+    let limits = config.request_limits;
+    let batch_request_config = match limits.max_batch_size {
+        0 => BatchRequestConfig::Disabled,
+        max_batch_size => BatchRequestConfig::Limit(max_batch_size),
+    };
 
+    let connection_metrics = RpcConnectionMetrics::default();
     let server = Server::builder()
         .max_connections(config.serve_max_connections)
-        .http_only()
-        .build(addr)
-        .await?;
+        .max_request_body_size(limits.max_request_body_size)
+        .set_batch_request_config(batch_request_config)
+        .set_logger(connection_metrics.clone())
+        .set_middleware(
+            tower::ServiceBuilder::new()
+                .layer(FlashbotsSignatureLayer::new(limits.max_request_body_size))
+                .layer(OrderSchemaLayer)
+                // must be the inner one, it needs the peer the server logs
+                .option_layer(rate_limiter.map(|rate_limiter| {
+                    RateLimitLayer::new(
+                        rate_limiter,
+                        connection_metrics,
+                        limits.max_request_body_size,
+                    )
+                })),
+        );
+    let server = match config.server_transport {
+        RpcTransport::Http => server.http_only(),
+        RpcTransport::Ws => server.ws_only(),
+        RpcTransport::HttpAndWs => server,
+    }
+    .build(addr)
+    .await?;
 
     let mut module = RpcModule::new(());
 
+    let decoder = Arc::new(SizeAwareDecoder::new(
+        limits.blocking_decode_min_size,
+        limits.max_blocking_decodes,
+    ));
+
+    let require_flashbots_signature = config.require_flashbots_signature;
+    let tx_type_forks = config.tx_type_forks.clone();
     let results_clone = results.clone();
+    let decoder_clone = decoder.clone();
+    let tx_type_forks_clone = tx_type_forks.clone();
     module.register_async_method("eth_sendBundle", move |params, _| {
         let results = results_clone.clone();
+        let decoder = decoder_clone.clone();
+        let tx_type_forks = tx_type_forks_clone.clone();
         async move {
             let start = Instant::now();
-            let raw_bundle: RawBundle = match params.one() {
-                Ok(raw_bundle) => raw_bundle,
-                Err(err) => {
-                    warn!(?err, "Failed to parse raw bundle");
-                    // @Metric
-                    return;
-                }
+            let signer = request_signer(require_flashbots_signature)?;
+            let schema_version = negotiated_schema_version()?;
+            let size = params_size(&params);
+            // taken on arrival, big bundles decoded later on a blocking thread must not win over newer versions
+            let arrival_nonce = next_replacement_nonce();
+            let Some(bundle) = decoder
+                .decode(size, move || {
+                    check_bundle_fields(schema_version, &params).and_then(|()| {
+                        decode_bundle(&params, limits.max_bundle_txs, signer, arrival_nonce)
+                    })
+                })
+                .await??
+            else {
+                return Ok(None);
             };
-
-            let bundle: Bundle = match raw_bundle.try_into(TxEncoding::WithBlobData) {
-                Ok(bundle) => bundle,
-                Err(err) => {
-                    warn!(?err, "Failed to decode raw bundle");
-                    // @Metric
-                    return;
-                }
-            };
-            let order = Order::Bundle(bundle);
+            let bundle_hash = bundle.hash;
+            let mut order = Order::Bundle(bundle);
+            check_tx_types(&tx_type_forks, &order)?;
+            order.metadata_mut().signer = signer;
+            let response = SendBundleResponse::new(bundle_hash, &order);
             let parse_duration = start.elapsed();
             let target_block = order.target_block().unwrap_or_default();
-            trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), target_block, "Received bundle");
+            trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), size, target_block, "Received bundle");
             send_order(order, &results, timeout).await;
+            Ok(Some(response))
         }
     })?;
 
     let results_clone = results.clone();
+    let builder_names_clone = builder_names.clone();
+    let tx_type_forks_clone = tx_type_forks.clone();
+    let decoder_clone = decoder.clone();
+    module.register_async_method("eth_sendPrivateTransaction", move |params, _| {
+        handle_send_private_tx(
+            results_clone.clone(),
+            timeout,
+            builder_names_clone.clone(),
+            tx_type_forks_clone.clone(),
+            decoder_clone.clone(),
+            params,
+        )
+    })?;
+
+    let results_clone = results.clone();
+    module.register_async_method("eth_cancelPrivateTransaction", move |params, _| {
+        handle_cancel_private_tx(results_clone.clone(), timeout, params)
+    })?;
+
+    let results_clone = results.clone();
+    let tx_type_forks_clone = tx_type_forks.clone();
     module.register_async_method("mev_sendBundle", move |params, _| {
-        handle_mev_send_bundle(results_clone.clone(), timeout, params)
+        handle_mev_send_bundle(
+            results_clone.clone(),
+            timeout,
+            builder_names.clone(),
+            limits.max_bundle_txs,
+            decoder.clone(),
+            require_flashbots_signature,
+            tx_type_forks_clone.clone(),
+            params,
+        )
+    })?;
+
+    let sim_bundle = config.sim_bundle.clone();
+    module.register_async_method("mev_simBundle", move |params, _| {
+        handle_mev_sim_bundle(sim_bundle.clone(), limits.max_bundle_txs, params)
+    })?;
+
+    let sim_bundle = config.sim_bundle.clone();
+    module.register_async_method("eth_callBundle", move |params, _| {
+        handle_call_bundle(sim_bundle.clone(), limits.max_bundle_txs, params)
+    })?;
+
+    // What this build accepts (see super::order_schema).
+    module.register_method("rbuilder_capabilities", |_, _| {
+        Ok::<_, ErrorObject<'static>>(capabilities())
+    })?;
+
+    module.register_method("rbuilder_orderId", |params, _| {
+        let submission: RawOrderSubmission = params.one()?;
+        submission
+            .order_id()
+            .map(|id| id.to_string())
+            .map_err(|err| ErrorObject::owned(-32602, err.to_string(), None::<()>))
     })?;
 
     let results_clone = results.clone();
+    let require_signed_cancellations = config.require_signed_cancellations;
+    let signed_cancellations_clone = signed_cancellations.clone();
     module.register_async_method("eth_cancelBundle", move |params, _| {
-        handle_cancel_bundle(results_clone.clone(), timeout, params)
+        handle_cancel_bundle(
+            results_clone.clone(),
+            timeout,
+            require_signed_cancellations,
+            signed_cancellations_clone.clone(),
+            params,
+        )
+    })?;
+
+    let results_clone = results.clone();
+    module.register_async_method("mev_cancelBundleByHash", move |params, _| {
+        handle_cancel_bundle_by_hash(
+            results_clone.clone(),
+            timeout,
+            signed_cancellations.clone(),
+            params,
+        )
     })?;
 
     let results_clone = results.clone();
     module.register_async_method("eth_sendRawTransaction", move |params, _| {
         let start = Instant::now();
         let results = results_clone.clone();
+        let tx_type_forks = tx_type_forks.clone();
         async move {
+            let signer = request_signer(false)?;
             let raw_tx: Bytes = match params.one() {
                 Ok(raw_tx) => raw_tx,
                 Err(err) => {
@@ -102,7 +306,9 @@ pub async fn start_server_accepting_bundles(
                 }
             };
             let hash = tx.tx_with_blobs.hash();
-            let order = Order::Tx(tx);
+            let mut order = Order::Tx(tx);
+            check_tx_types(&tx_type_forks, &order)?;
+            order.metadata_mut().signer = signer;
             let parse_duration = start.elapsed();
             trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), "Received mempool tx from API");
             send_order(order, &results, timeout).await;
@@ -116,8 +322,13 @@ pub async fn start_server_accepting_bundles(
     Ok(tokio::spawn(async move {
         info!("RPC server job: started");
         tokio::select! {
-            _ = global_cancel.cancelled() => {},
-            _ = handle.stopped() => {
+            _ = global_cancel.cancelled() => {
+                // closes the ws connections with a close frame instead of just dropping them
+                if handle.stop().is_ok() {
+                    handle.stopped().await;
+                }
+            },
+            _ = handle.clone().stopped() => {
                 info!("RPC Server stopped");
                 global_cancel.cancel();
             },
@@ -129,35 +340,44 @@ pub async fn start_server_accepting_bundles(
 
 /// Parses a mev share bundle packet and forwards it to the results.
 /// Here we can have NewShareBundle or CancelShareBundle (identified using a "cancel" field (a little ugly)).
+/// Bundles whose privacy.builders don't include any of builder_names are dropped before decoding so they never reach
+/// the orderpool (and so are never built, forwarded to sync peers or exposed through any API).
+#[allow(clippy::too_many_arguments)]
 async fn handle_mev_send_bundle(
     results: mpsc::Sender<ReplaceableOrderPoolCommand>,
     timeout: Duration,
+    builder_names: Arc<Vec<String>>,
+    max_bundle_txs: usize,
+    decoder: Arc<SizeAwareDecoder>,
+    require_signature: bool,
+    tx_type_forks: Arc<TxTypeForks>,
     params: jsonrpsee::types::Params<'static>,
-) {
+) -> Result<Option<SendBundleResponse>, ErrorObject<'static>> {
     let start = Instant::now();
-    let raw_bundle: RawShareBundle = match params.one() {
-        Ok(raw_bundle) => raw_bundle,
-        Err(err) => {
-            warn!(?err, "Failed to parse raw share bundle");
-            // @Metric
-            return;
-        }
-    };
-    let decode_res = match raw_bundle.decode(TxEncoding::WithBlobData) {
-        Ok(res) => res,
-        Err(err) => {
-            warn!(?err, "Failed to decode raw share bundle");
-            // @Metric
-            return;
-        }
+    let signer = request_signer(require_signature)?;
+    let schema_version = negotiated_schema_version()?;
+    let size = params_size(&params);
+    let Some(decode_res) = decoder
+        .decode(size, move || {
+            check_share_bundle_fields(schema_version, &params)
+                .and_then(|()| decode_share_bundle(&params, &builder_names, max_bundle_txs, signer))
+        })
+        .await??
+    else {
+        return Ok(None);
     };
     match decode_res {
         RawShareBundleDecodeResult::NewShareBundle(bundle) => {
-            let order = Order::ShareBundle(*bundle);
+            let bundle_hash = bundle.hash;
+            let mut order = Order::ShareBundle(*bundle);
+            check_tx_types(&tx_type_forks, &order)?;
+            order.metadata_mut().signer = signer;
+            let response = SendBundleResponse::new(bundle_hash, &order);
             let parse_duration = start.elapsed();
             let target_block = order.target_block().unwrap_or_default();
-            trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), target_block, "Received share bundle");
+            trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), size, target_block, "Received share bundle");
             send_order(order, &results, timeout).await;
+            Ok(Some(response))
         }
         RawShareBundleDecodeResult::CancelShareBundle(cancel) => {
             trace!(cancel = ?cancel, "Received share bundle cancellation");
@@ -167,11 +387,349 @@ async fn handle_mev_send_bundle(
                 timeout,
             )
             .await;
+            Ok(None)
+        }
+    }
+}
+
+/// eth_sendPrivateTransaction: a mempool tx valid up to maxBlockNumber (see [MempoolTx::max_block]) that we don't
+/// share with other builders. Decoded like the bundles (see [`SizeAwareDecoder`]). Returns the tx hash, null if preferences.privacy.builders doesn't include any of builder_names.
+async fn handle_send_private_tx(
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    timeout: Duration,
+    builder_names: Arc<Vec<String>>,
+    tx_type_forks: Arc<TxTypeForks>,
+    decoder: Arc<SizeAwareDecoder>,
+    params: jsonrpsee::types::Params<'static>,
+) -> Result<Option<B256>, ErrorObject<'static>> {
+    let start = Instant::now();
+    let signer = request_signer(false)?;
+    let schema_version = negotiated_schema_version()?;
+    let size = params_size(&params);
+    let Some(tx) = decoder
+        .decode(size, move || {
+            check_private_tx_fields(schema_version, &params)
+                .and_then(|()| decode_private_tx(&params, &builder_names))
+        })
+        .await??
+    else {
+        return Ok(None);
+    };
+    let hash = tx.tx_with_blobs.hash();
+    let max_block = tx.max_block;
+    let mut order = Order::Tx(tx);
+    check_tx_types(&tx_type_forks, &order)?;
+    order.metadata_mut().signer = signer;
+    let parse_duration = start.elapsed();
+    trace!(order = ?order.id(), ?max_block, parse_duration_mus = parse_duration.as_micros(), size, "Received private tx");
+    send_order(order, &results, timeout).await;
+    Ok(Some(hash))
+}
+
+/// eth_sendPrivateTransaction params to MempoolTx, None if the tx targets other builders.
+fn decode_private_tx(
+    params: &jsonrpsee::types::Params<'static>,
+    builder_names: &[String],
+) -> Result<Option<MempoolTx>, ErrorObject<'static>> {
+    let raw_tx: RawPrivateTx = match params.one() {
+        Ok(raw_tx) => raw_tx,
+        Err(err) => {
+            warn!(?err, "Failed to parse private transaction");
+            // @Metric
+            return Err(err);
         }
     };
+    if !raw_tx.targets_builder(builder_names) {
+        trace!("Private tx targeted to other builders, ignoring");
+        return Ok(None);
+    }
+    match raw_tx.decode(TxEncoding::WithBlobData) {
+        Ok(tx) => Ok(Some(tx)),
+        Err(err) => {
+            warn!(?err, "Failed to decode private transaction");
+            // @Metric
+            Err(ErrorObject::owned(
+                -32602,
+                "failed to verify transaction",
+                None::<()>,
+            ))
+        }
+    }
+}
+
+/// params for eth_cancelPrivateTransaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawCancelPrivateTx {
+    pub tx_hash: B256,
+}
+
+/// Cancels the private tx sent with the same X-Flashbots-Signature signer (required), like flashbots it returns true
+/// once the cancellation is taken (the tx may have been included already).
+async fn handle_cancel_private_tx(
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    timeout: Duration,
+    params: jsonrpsee::types::Params<'static>,
+) -> Result<bool, ErrorObject<'static>> {
+    let signer = request_signer(true)?.ok_or_else(invalid_signature)?;
+    let cancel: RawCancelPrivateTx = params.one()?;
+    trace!(tx_hash = ?cancel.tx_hash, ?signer, "Received cancel private tx");
+    send_command(
+        ReplaceableOrderPoolCommand::CancelBundleByHash(CancelBundleByHash {
+            hash: cancel.tx_hash,
+            signer,
+        }),
+        &results,
+        timeout,
+    )
+    .await;
+    Ok(true)
+}
+
+/// Simulates a mev share bundle (params: bundle, optional [SimBundleOptions]) on the slot being built.
+/// The bundle is not added to the orderpool.
+async fn handle_mev_sim_bundle(
+    sim_bundle: Option<SimBundleService>,
+    max_bundle_txs: usize,
+    params: jsonrpsee::types::Params<'static>,
+) -> Result<SimBundleResult, ErrorObject<'static>> {
+    let signer = request_signer(false)?;
+    let sim_bundle = sim_bundle.ok_or_else(|| sim_error(SimBundleError::NoSlot))?;
+    let permit = sim_bundle.reserve().map_err(sim_error)?;
+    let invalid_params = |err: String| ErrorObject::owned(-32602, err, None::<()>);
+    let mut seq = params.sequence();
+    let raw_bundle: RawShareBundle = seq.next()?;
+    let options: SimBundleOptions = seq.optional_next()?.unwrap_or_default();
+    check_bundle_txs(raw_bundle.tx_count(), max_bundle_txs)?;
+    let bundle = raw_bundle
+        .decode_new_bundle(TxEncoding::WithBlobData)
+        .map_err(|err| invalid_params(format!("failed to decode bundle: {}", err)))?;
+    let order = Order::ShareBundle(bundle);
+    trace!(order = ?order.id(), pending = options.pending, "Received share bundle simulation");
+    sim_bundle
+        .simulate(permit, order, options.pending, signer)
+        .await
+        .map_err(sim_error)
 }
 
-async fn send_order(
+fn sim_error(err: SimBundleError) -> ErrorObject<'static> {
+    ErrorObject::owned(-32000, err.to_string(), None::<()>)
+}
+
+/// Runs the decoding of big requests (see [`super::RequestLimits::blocking_decode_min_size`]) on the blocking thread pool,
+/// at most max_blocking_decodes at once. Small ones are decoded in place, moving them would cost more than decoding.
+/// This is not a streaming decode: jsonrpsee still reads the whole body and parses the request envelope (finding the
+/// raw params) on the reactor, only parsing the params, decoding the txs and recovering the signers is moved.
+#[derive(Debug)]
+pub(super) struct SizeAwareDecoder {
+    blocking_decode_min_size: usize,
+    blocking_decodes: Semaphore,
+}
+
+impl SizeAwareDecoder {
+    pub(super) fn new(blocking_decode_min_size: usize, max_blocking_decodes: usize) -> Self {
+        Self {
+            blocking_decode_min_size,
+            blocking_decodes: Semaphore::new(max_blocking_decodes.max(1)),
+        }
+    }
+
+    /// size: bytes of the request params.
+    pub(super) async fn decode<T, F>(
+        &self,
+        size: usize,
+        decode: F,
+    ) -> Result<T, ErrorObject<'static>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let internal_error = |err: String| ErrorObject::owned(-32603, err, None::<()>);
+        if size < self.blocking_decode_min_size {
+            return Ok(decode());
+        }
+        let _permit = self
+            .blocking_decodes
+            .acquire()
+            .await
+            .map_err(|err| internal_error(err.to_string()))?;
+        trace!(size, "Decoding request on a blocking thread");
+        tokio::task::spawn_blocking(decode)
+            .await
+            .map_err(|err| internal_error(err.to_string()))
+    }
+}
+
+/// Rejects orders with txs whose type is not active on the chain yet (see [`TxTypeForks`]).
+fn check_tx_types(tx_type_forks: &TxTypeForks, order: &Order) -> Result<(), ErrorObject<'static>> {
+    tx_type_forks
+        .check_order(order, unix_now())
+        .map_err(|err| ErrorObject::owned(-32602, err.to_string(), None::<()>))
+}
+
+/// Bytes of the raw json params.
+fn params_size(params: &jsonrpsee::types::Params<'static>) -> usize {
+    params.as_str().map_or(0, str::len)
+}
+
+/// eth_sendBundle params to Bundle, None if they can't be parsed or decoded (the caller answers null).
+/// arrival_nonce: replacementNonce of the bundle if it has a replacementUuid but no nonce.
+fn decode_bundle(
+    params: &jsonrpsee::types::Params<'static>,
+    max_bundle_txs: usize,
+    request_signer: Option<Address>,
+    arrival_nonce: u64,
+) -> Result<Option<Bundle>, ErrorObject<'static>> {
+    let mut raw_bundle: RawBundle = match parse_raw_bundle(params) {
+        Ok(raw_bundle) => raw_bundle,
+        Err(err) => {
+            warn!(?err, "Failed to parse raw bundle");
+            // @Metric
+            return Ok(None);
+        }
+    };
+    check_bundle_txs(raw_bundle.txs.len(), max_bundle_txs)?;
+    apply_request_signer(&mut raw_bundle.signing_address, request_signer)?;
+    if raw_bundle.replacement_uuid.is_some() && raw_bundle.replacement_nonce.is_none() {
+        raw_bundle.replacement_nonce = Some(arrival_nonce);
+    }
+
+    match raw_bundle.try_into(TxEncoding::WithBlobData) {
+        Ok(bundle) => Ok(Some(bundle)),
+        Err(err) => {
+            warn!(?err, "Failed to decode raw bundle");
+            // @Metric
+            Ok(None)
+        }
+    }
+}
+
+/// mev_sendBundle params to a new share bundle or cancellation, None if they can't be parsed/decoded or the bundle
+/// targets other builders.
+fn decode_share_bundle(
+    params: &jsonrpsee::types::Params<'static>,
+    builder_names: &[String],
+    max_bundle_txs: usize,
+    request_signer: Option<Address>,
+) -> Result<Option<RawShareBundleDecodeResult>, ErrorObject<'static>> {
+    let mut raw_bundle: RawShareBundle = match params.one() {
+        Ok(raw_bundle) => raw_bundle,
+        Err(err) => {
+            warn!(?err, "Failed to parse raw share bundle");
+            // @Metric
+            return Ok(None);
+        }
+    };
+    check_bundle_txs(raw_bundle.tx_count(), max_bundle_txs)?;
+    if request_signer.is_some() {
+        let metadata = raw_bundle.metadata.get_or_insert(RawShareBundleMetadatada {
+            signer: None,
+            replacement_nonce: None,
+            cancelled: false,
+        });
+        apply_request_signer(&mut metadata.signer, request_signer)?;
+    }
+    if !raw_bundle.targets_builder(builder_names) {
+        trace!(replacement_uuid = ?raw_bundle.replacement_uuid, "Share bundle targeted to other builders, ignoring");
+        inc_share_bundles_not_targeted();
+        return Ok(None);
+    }
+    match raw_bundle.decode(TxEncoding::WithBlobData) {
+        Ok(res) => Ok(Some(res)),
+        Err(err) => {
+            warn!(?err, "Failed to decode raw share bundle");
+            // @Metric
+            Ok(None)
+        }
+    }
+}
+
+lazy_static! {
+    static ref LAST_REPLACEMENT_NONCE: AtomicU64 = AtomicU64::new(0);
+}
+
+/// Sequence number for bundles with replacementUuid but no replacementNonce (searchers don't send it, only the proxies
+/// in front of us might), the last one we parse wins.
+/// It's the arrival time in us (bumped if needed to keep it strictly increasing) so the nonces of the versions that
+/// arrived at different instances (orderpool sync, orderflow sharing) compare by arrival too (up to the clock skew
+/// of the instances).
+pub(super) fn next_replacement_nonce() -> u64 {
+    let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1000).max(0) as u64;
+    let last = LAST_REPLACEMENT_NONCE
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(last + 1)
+}
+
+/// eth_sendBundle params: a bundle object or the positional params of mev-geth v0.1 (see [LegacyRawBundle]).
+fn parse_raw_bundle(
+    params: &jsonrpsee::types::Params<'static>,
+) -> Result<RawBundle, ErrorObject<'static>> {
+    params.one::<RawBundle>().or_else(|err| {
+        params
+            .parse::<LegacyRawBundle>()
+            .map(RawBundle::from)
+            .map_err(|_| err)
+    })
+}
+
+/// eth_callBundle for older tooling: simulates the bundle like mev_simBundle (on top of the parent block of the slot
+/// being built). The bundle is not added to the orderpool.
+async fn handle_call_bundle(
+    sim_bundle: Option<SimBundleService>,
+    max_bundle_txs: usize,
+    params: jsonrpsee::types::Params<'static>,
+) -> Result<CallBundleResponse, ErrorObject<'static>> {
+    let sim_bundle = sim_bundle.ok_or_else(|| sim_error(SimBundleError::NoSlot))?;
+    let permit = sim_bundle.reserve().map_err(sim_error)?;
+    let call_bundle: RawCallBundle = params.one()?;
+    check_bundle_txs(call_bundle.txs.len(), max_bundle_txs)?;
+    let bundle = RawBundle::from(call_bundle)
+        .try_into(TxEncoding::WithBlobData)
+        .map_err(|err| {
+            ErrorObject::owned(
+                -32602,
+                format!("failed to decode bundle: {}", err),
+                None::<()>,
+            )
+        })?;
+    let bundle_hash = bundle.hash;
+    let order = Order::Bundle(bundle);
+    trace!(order = ?order.id(), "Received call bundle");
+    let result = sim_bundle
+        .simulate(permit, order, false, None)
+        .await
+        .map_err(sim_error)?;
+    Ok(CallBundleResponse {
+        bundle_hash,
+        results: result.txs.iter().map(CallBundleTxResult::from).collect(),
+        coinbase_diff: result.profit,
+        bundle_gas_price: result.mev_gas_price,
+        total_gas_used: result.gas_used,
+        state_block_number: result.state_block,
+        error: result.error,
+    })
+}
+
+/// -32602 if the bundle has more than max_bundle_txs.
+pub(super) fn check_bundle_txs(
+    txs: usize,
+    max_bundle_txs: usize,
+) -> Result<(), ErrorObject<'static>> {
+    if txs > max_bundle_txs {
+        warn!(txs, max_bundle_txs, "Bundle with too many txs");
+        return Err(ErrorObject::owned(
+            -32602,
+            format!("bundle has {} txs, max is {}", txs, max_bundle_txs),
+            None::<()>,
+        ));
+    }
+    Ok(())
+}
+
+pub(super) async fn send_order(
     order: Order,
     channel: &mpsc::Sender<ReplaceableOrderPoolCommand>,
     timeout: Duration,
@@ -180,7 +738,7 @@ async fn send_order(
 }
 
 /// Eats the errors and traces them.
-async fn send_command(
+pub(super) async fn send_command(
     command: ReplaceableOrderPoolCommand,
     channel: &mpsc::Sender<ReplaceableOrderPoolCommand>,
     timeout: Duration,
@@ -200,30 +758,684 @@ async fn send_command(
 pub struct RawCancelBundle {
     pub replacement_uuid: Uuid,
     pub signing_address: Address,
+    /// Unix seconds, required by signed cancellations (see [`SignedCancellations`]).
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// EIP-191 signature of "{replacementUuid (hyphenated)}:{timestamp}" by signingAddress.
+    /// Not needed if the request has an X-Flashbots-Signature of signingAddress.
+    /// Checked if present, required if [`OrderInputConfig::require_signed_cancellations`].
+    #[serde(default)]
+    pub signature: Option<Bytes>,
+}
+
+/// params for mev_cancelBundleByHash
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawCancelBundleByHash {
+    /// As returned by eth_sendBundle/mev_sendBundle.
+    pub bundle_hash: B256,
+    /// Unix seconds (see [`SignedCancellations`]).
+    pub timestamp: u64,
+    /// EIP-191 signature of "{bundleHash (0x prefixed hex)}:{timestamp}" by the signer of the bundle.
+    pub signature: Bytes,
+}
+
+/// Signed cancellations older (or newer) than this vs our clock are rejected.
+const SIGNED_CANCELLATION_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Replay protection of the signed cancellations.
+/// The signed message includes a timestamp, we only take fresh ones and remember the ones we took until they are
+/// stale so a captured cancellation can't be used again (eg: to cancel the bundle the searcher sent later with the
+/// same replacementUuid).
+/// Shared by the json rpc and the gRPC server.
+#[derive(Debug, Default)]
+pub struct SignedCancellations {
+    /// (signer, signed message) -> timestamp.
+    seen: Mutex<HashMap<(Address, String), u64>>,
+}
+
+impl SignedCancellations {
+    /// now: unix seconds.
+    fn check(
+        &self,
+        signer: Address,
+        message: String,
+        timestamp: u64,
+        now: u64,
+    ) -> Result<(), ErrorObject<'static>> {
+        let max_age = SIGNED_CANCELLATION_MAX_AGE.as_secs();
+        if timestamp.abs_diff(now) > max_age {
+            return Err(ErrorObject::owned(
+                -32602,
+                "stale cancellation timestamp",
+                None::<()>,
+            ));
+        }
+        let mut seen = self.seen.lock();
+        // cancellations are rare enough to clean on every check
+        seen.retain(|_, seen_timestamp| seen_timestamp.abs_diff(now) <= max_age);
+        if seen.insert((signer, message), timestamp).is_some() {
+            return Err(ErrorObject::owned(
+                -32602,
+                "cancellation already used",
+                None::<()>,
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn unix_now() -> u64 {
+    OffsetDateTime::now_utc().unix_timestamp().max(0) as u64
+}
+
+/// Signer of the EIP-191 (personal_sign) signature of message.
+pub(super) fn recover_message_signer(message: &str, signature: &[u8]) -> Option<Address> {
+    Signature::try_from(signature)
+        .ok()?
+        .recover_address_from_msg(message)
+        .ok()
+}
+
+fn invalid_signature() -> ErrorObject<'static> {
+    ErrorObject::owned(-32602, "invalid signature", None::<()>)
+}
+
+/// X-Flashbots-Signature signer of the request (see [`super::flashbots_signature`]), a bad signature is always an
+/// error.
+fn request_signer(require_signature: bool) -> Result<Option<Address>, ErrorObject<'static>> {
+    match request_signature() {
+        RequestSignature::Valid(signer) => Ok(Some(signer)),
+        RequestSignature::Missing if require_signature => Err(ErrorObject::owned(
+            -32602,
+            "missing X-Flashbots-Signature",
+            None::<()>,
+        )),
+        RequestSignature::Missing => Ok(None),
+        RequestSignature::Invalid => Err(invalid_signature()),
+    }
+}
+
+/// The request signer becomes the signer of the order, if the order already names one (set by a proxy in front of
+/// us) they must be the same.
+fn apply_request_signer(
+    order_signer: &mut Option<Address>,
+    request_signer: Option<Address>,
+) -> Result<(), ErrorObject<'static>> {
+    let Some(request_signer) = request_signer else {
+        return Ok(());
+    };
+    match order_signer {
+        Some(order_signer) if *order_signer != request_signer => Err(ErrorObject::owned(
+            -32602,
+            "signer doesn't match X-Flashbots-Signature",
+            None::<()>,
+        )),
+        _ => {
+            *order_signer = Some(request_signer);
+            Ok(())
+        }
+    }
+}
+
+/// Checks the authorization of the cancellation: a signature of signingAddress in the params or an
+/// X-Flashbots-Signature (request_signer) of signingAddress, both need a fresh timestamp never used before.
+/// Without any they are only taken if !require_signature (a proxy in front of us authenticates the signers).
+/// Cancellations we take are rebroadcast (orderpool sync, orderflow sharing) and the peers don't check them again.
+/// now: unix seconds.
+pub(super) fn cancel_bundle_key(
+    cancel_bundle: &RawCancelBundle,
+    request_signer: Option<Address>,
+    require_signature: bool,
+    signed_cancellations: &SignedCancellations,
+    now: u64,
+) -> Result<BundleReplacementKey, ErrorObject<'static>> {
+    let signing_address = cancel_bundle.signing_address;
+    let message = cancel_bundle
+        .timestamp
+        .map(|timestamp| format!("{}:{}", cancel_bundle.replacement_uuid, timestamp));
+    let authorized = match (&cancel_bundle.signature, request_signer) {
+        (Some(signature), _) => {
+            let signer = message
+                .as_ref()
+                .and_then(|message| recover_message_signer(message, signature));
+            if signer != Some(signing_address) {
+                warn!(replacement_uuid = %cancel_bundle.replacement_uuid, "Cancel bundle signature does not match signingAddress");
+                return Err(invalid_signature());
+            }
+            true
+        }
+        (None, Some(request_signer)) if request_signer != signing_address => {
+            return Err(ErrorObject::owned(
+                -32602,
+                "signingAddress doesn't match X-Flashbots-Signature",
+                None::<()>,
+            ))
+        }
+        (None, Some(_)) => true,
+        (None, None) if require_signature => {
+            return Err(ErrorObject::owned(-32602, "signature required", None::<()>))
+        }
+        (None, None) => false,
+    };
+    if authorized {
+        let (Some(message), Some(timestamp)) = (message, cancel_bundle.timestamp) else {
+            return Err(ErrorObject::owned(-32602, "timestamp required", None::<()>));
+        };
+        signed_cancellations.check(signing_address, message, timestamp, now)?;
+    }
+    Ok(BundleReplacementKey::new(
+        cancel_bundle.replacement_uuid,
+        cancel_bundle.signing_address,
+    ))
+}
+
+/// Signer of the cancellation and the message it signed.
+fn cancel_by_hash_signer(
+    cancel: &RawCancelBundleByHash,
+) -> Result<(Address, String), ErrorObject<'static>> {
+    let message = format!("{}:{}", cancel.bundle_hash, cancel.timestamp);
+    let signer =
+        recover_message_signer(&message, &cancel.signature).ok_or_else(invalid_signature)?;
+    Ok((signer, message))
 }
 
 /// Parses bundle cancellations a sends CancelBundle to the results.
 async fn handle_cancel_bundle(
     results: mpsc::Sender<ReplaceableOrderPoolCommand>,
     timeout: Duration,
+    require_signature: bool,
+    signed_cancellations: Arc<SignedCancellations>,
     params: jsonrpsee::types::Params<'static>,
-) {
+) -> Result<(), ErrorObject<'static>> {
+    let request_signer = request_signer(false)?;
     let cancel_bundle: RawCancelBundle = match params.one() {
         Ok(cancel_bundle) => cancel_bundle,
         Err(err) => {
             warn!(?err, "Failed to parse cancel bundle");
             // @Metric
-            return;
+            return Ok(());
         }
     };
-    let key = BundleReplacementKey::new(
-        cancel_bundle.replacement_uuid,
-        cancel_bundle.signing_address,
-    );
+    let key = cancel_bundle_key(
+        &cancel_bundle,
+        request_signer,
+        require_signature,
+        &signed_cancellations,
+        unix_now(),
+    )?;
     send_command(
         ReplaceableOrderPoolCommand::CancelBundle(key),
         &results,
         timeout,
     )
     .await;
+    Ok(())
+}
+
+/// Removes the bundles/sbundles with the hash sent by the signer of the request from the pool (and so from the
+/// simulations in flight), bundles of other signers with the same hash are untouched.
+async fn handle_cancel_bundle_by_hash(
+    results: mpsc::Sender<ReplaceableOrderPoolCommand>,
+    timeout: Duration,
+    signed_cancellations: Arc<SignedCancellations>,
+    params: jsonrpsee::types::Params<'static>,
+) -> Result<(), ErrorObject<'static>> {
+    let cancel: RawCancelBundleByHash = params.one()?;
+    let (signer, message) = cancel_by_hash_signer(&cancel)?;
+    signed_cancellations.check(signer, message, cancel.timestamp, unix_now())?;
+    trace!(bundle_hash = ?cancel.bundle_hash, ?signer, "Received cancel bundle by hash");
+    send_command(
+        ReplaceableOrderPoolCommand::CancelBundleByHash(CancelBundleByHash {
+            hash: cancel.bundle_hash,
+            signer,
+        }),
+        &results,
+        timeout,
+    )
+    .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::order_input::RequestLimits,
+        primitives::TransactionSignedEcRecoveredWithBlobs, telemetry::RPC_CALLS, utils::Signer,
+    };
+    use alloy_consensus::TxEip1559;
+    use alloy_primitives::eip191_hash_message;
+    use jsonrpsee::{core::client::ClientT, rpc_params, ws_client::WsClientBuilder};
+    use std::{net::Ipv4Addr, path::PathBuf};
+
+    #[test]
+    fn test_apply_request_signer() {
+        let (proxy_signer, signer) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        let mut order_signer = None;
+        apply_request_signer(&mut order_signer, None).unwrap();
+        assert_eq!(order_signer, None);
+        apply_request_signer(&mut order_signer, Some(signer)).unwrap();
+        assert_eq!(order_signer, Some(signer));
+        apply_request_signer(&mut order_signer, Some(signer)).unwrap();
+
+        let mut order_signer = Some(proxy_signer);
+        assert!(apply_request_signer(&mut order_signer, Some(signer)).is_err());
+        apply_request_signer(&mut order_signer, None).unwrap();
+        assert_eq!(order_signer, Some(proxy_signer));
+        // outside the http middleware nothing is signed
+        assert_eq!(request_signer(false).unwrap(), None);
+        assert!(request_signer(true).is_err());
+    }
+
+    #[test]
+    fn test_next_replacement_nonce() {
+        let arrival = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1000) as u64;
+        let first = next_replacement_nonce();
+        let second = next_replacement_nonce();
+        assert!(first >= arrival);
+        assert!(second > first);
+    }
+
+    #[test]
+    fn test_recover_message_signer() {
+        let signer = Signer::random();
+        let bundle_hash = B256::with_last_byte(1);
+        let message = bundle_hash.to_string();
+        let signature = signer
+            .sign_message(eip191_hash_message(&message))
+            .unwrap()
+            .as_bytes();
+        assert_eq!(
+            recover_message_signer(&message, &signature),
+            Some(signer.address)
+        );
+        // signed something else
+        assert_ne!(
+            recover_message_signer(&B256::with_last_byte(2).to_string(), &signature),
+            Some(signer.address)
+        );
+        assert_eq!(recover_message_signer(&message, &signature[..64]), None);
+    }
+
+    fn sign(signer: &Signer, message: &str) -> Bytes {
+        signer
+            .sign_message(eip191_hash_message(message))
+            .unwrap()
+            .as_bytes()
+            .to_vec()
+            .into()
+    }
+
+    #[test]
+    fn test_cancel_bundle_key() {
+        let signer = Signer::random();
+        let replacement_uuid = Uuid::new_v4();
+        let now = 1_700_000_000;
+        let signed = |timestamp: u64| RawCancelBundle {
+            replacement_uuid,
+            signing_address: signer.address,
+            timestamp: Some(timestamp),
+            signature: Some(sign(
+                &signer,
+                &format!("{}:{}", replacement_uuid, timestamp),
+            )),
+        };
+        let key = BundleReplacementKey::new(replacement_uuid, signer.address);
+        let seen = SignedCancellations::default();
+
+        assert_eq!(
+            cancel_bundle_key(&signed(now), None, true, &seen, now).unwrap(),
+            key
+        );
+        // replayed
+        assert!(cancel_bundle_key(&signed(now), None, true, &seen, now + 1).is_err());
+        // a new timestamp is a new cancellation
+        cancel_bundle_key(&signed(now + 1), None, true, &seen, now + 1).unwrap();
+        // stale
+        let max_age = SIGNED_CANCELLATION_MAX_AGE.as_secs();
+        assert!(cancel_bundle_key(&signed(now - max_age - 1), None, true, &seen, now).is_err());
+        // the old uuid only signature is replayable
+        let uuid_only = RawCancelBundle {
+            signature: Some(sign(&signer, &replacement_uuid.to_string())),
+            ..signed(now + 2)
+        };
+        assert!(cancel_bundle_key(&uuid_only, None, true, &seen, now + 2).is_err());
+        // signed by someone else
+        let other = RawCancelBundle {
+            signing_address: Address::with_last_byte(1),
+            ..signed(now + 3)
+        };
+        assert!(cancel_bundle_key(&other, None, true, &seen, now + 3).is_err());
+
+        // X-Flashbots-Signature of signingAddress
+        let unsigned = RawCancelBundle {
+            signature: None,
+            ..signed(now + 4)
+        };
+        cancel_bundle_key(&unsigned, Some(signer.address), true, &seen, now + 4).unwrap();
+        assert!(cancel_bundle_key(&unsigned, Some(signer.address), true, &seen, now + 4).is_err());
+        let unsigned = RawCancelBundle {
+            signature: None,
+            ..signed(now + 5)
+        };
+        assert!(cancel_bundle_key(
+            &unsigned,
+            Some(Address::with_last_byte(1)),
+            true,
+            &seen,
+            now + 5
+        )
+        .is_err());
+        let no_timestamp = RawCancelBundle {
+            timestamp: None,
+            ..unsigned.clone()
+        };
+        assert!(cancel_bundle_key(&no_timestamp, Some(signer.address), true, &seen, now).is_err());
+
+        // unsigned only if not required
+        assert!(cancel_bundle_key(&no_timestamp, None, true, &seen, now).is_err());
+        assert_eq!(
+            cancel_bundle_key(&no_timestamp, None, false, &seen, now).unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn test_cancel_by_hash_signer() {
+        let signer = Signer::random();
+        let bundle_hash = B256::with_last_byte(1);
+        let cancel = RawCancelBundleByHash {
+            bundle_hash,
+            timestamp: 10,
+            signature: sign(&signer, &format!("{}:{}", bundle_hash, 10)),
+        };
+        let (cancel_signer, message) = cancel_by_hash_signer(&cancel).unwrap();
+        assert_eq!(cancel_signer, signer.address);
+        let seen = SignedCancellations::default();
+        seen.check(cancel_signer, message.clone(), cancel.timestamp, 10)
+            .unwrap();
+        assert!(seen
+            .check(cancel_signer, message, cancel.timestamp, 10)
+            .is_err());
+        // the signature doesn't cover another timestamp
+        let moved = RawCancelBundleByHash {
+            timestamp: 11,
+            ..cancel
+        };
+        assert_ne!(
+            cancel_by_hash_signer(&moved).map(|(signer, _)| signer).ok(),
+            Some(signer.address)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_size_aware_decoder() {
+        let decoder = SizeAwareDecoder::new(1024, 1);
+        let caller = std::thread::current().id();
+        let small = decoder
+            .decode(10, || std::thread::current().id())
+            .await
+            .unwrap();
+        assert_eq!(small, caller);
+        let big = decoder
+            .decode(5 * 1024 * 1024, || std::thread::current().id())
+            .await
+            .unwrap();
+        assert_ne!(big, caller);
+    }
+
+    #[tokio::test]
+    async fn test_ws_send_bundle() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = OrderInputConfig::new(
+            false,
+            false,
+            PathBuf::new(),
+            port,
+            Ipv4Addr::LOCALHOST,
+            10,
+            Duration::from_millis(50),
+            10,
+        );
+        config.server_transport = RpcTransport::Ws;
+        let (results_sender, mut results) = mpsc::channel(10);
+        let cancel = CancellationToken::new();
+        let server = start_server_accepting_bundles(
+            config,
+            results_sender,
+            RpcModule::new(()),
+            None,
+            Default::default(),
+            cancel.clone(),
+        )
+        .await
+        .unwrap();
+
+        let ws_calls = || RPC_CALLS.with_label_values(&["eth_sendBundle", "ws"]).get();
+        let ws_calls_before = ws_calls();
+        let tx = Signer::random()
+            .sign_tx(
+                TxEip1559 {
+                    chain_id: 1,
+                    ..Default::default()
+                }
+                .into(),
+            )
+            .unwrap();
+        let tx = TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap();
+        let client = WsClientBuilder::default()
+            .build(format!("ws://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+        // several calls on the same connection
+        for block in 1..=2u64 {
+            let response: Option<serde_json::Value> = client
+                .request(
+                    "eth_sendBundle",
+                    rpc_params![serde_json::json!({
+                        "blockNumber": format!("{:#x}", block),
+                        "txs": [tx.envelope_encoded_no_blobs()],
+                    })],
+                )
+                .await
+                .unwrap();
+            assert!(response.is_some());
+            match results.recv().await {
+                Some(ReplaceableOrderPoolCommand::Order(Order::Bundle(bundle))) => {
+                    assert_eq!(bundle.block, block);
+                    assert_eq!(bundle.txs[0].hash(), tx.hash());
+                }
+                other => panic!("Unexpected command {:?}", other),
+            }
+        }
+        assert!(ws_calls() >= ws_calls_before + 2);
+
+        drop(client);
+        cancel.cancel();
+        server.await.unwrap();
+    }
+
+    /// On a single threaded runtime a big decode done in place would block the small request until it finished.
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_big_decode_does_not_block_small_requests() {
+        let decoder = Arc::new(SizeAwareDecoder::new(1024, 1));
+        let (small_decoded, wait_small) = std::sync::mpsc::channel();
+        let big = tokio::spawn({
+            let decoder = decoder.clone();
+            async move {
+                decoder
+                    .decode(5 * 1024 * 1024, move || {
+                        wait_small.recv_timeout(Duration::from_secs(5)).is_ok()
+                    })
+                    .await
+                    .unwrap()
+            }
+        });
+        // let the big one start
+        tokio::task::yield_now().await;
+        decoder.decode(10, || ()).await.unwrap();
+        // fails if the big one already gave up
+        let _ = small_decoded.send(());
+        assert!(
+            big.await.unwrap(),
+            "big decode finished before the small one"
+        );
+    }
+
+    async fn start_http_server(
+        limits: RequestLimits,
+    ) -> (
+        String,
+        mpsc::Receiver<ReplaceableOrderPoolCommand>,
+        CancellationToken,
+        JoinHandle<()>,
+    ) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = OrderInputConfig::new(
+            false,
+            false,
+            PathBuf::new(),
+            port,
+            Ipv4Addr::LOCALHOST,
+            10,
+            Duration::from_millis(50),
+            10,
+        );
+        config.request_limits = limits;
+        let (results_sender, results) = mpsc::channel(10);
+        let cancel = CancellationToken::new();
+        let server = start_server_accepting_bundles(
+            config,
+            results_sender,
+            RpcModule::new(()),
+            None,
+            Default::default(),
+            cancel.clone(),
+        )
+        .await
+        .unwrap();
+        (
+            format!("http://127.0.0.1:{}", port),
+            results,
+            cancel,
+            server,
+        )
+    }
+
+    /// Error code of a json rpc response (the first one for batches), None if it's not an error.
+    async fn post(url: &str, body: Vec<u8>, gzip: bool) -> Option<i64> {
+        let mut request = reqwest::Client::new()
+            .post(url)
+            .header("Content-Type", "application/json");
+        if gzip {
+            request = request.header("Content-Encoding", "gzip");
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        let response = match response {
+            serde_json::Value::Array(mut responses) => responses.remove(0),
+            response => response,
+        };
+        response["error"]["code"].as_i64()
+    }
+
+    fn cancel_bundle_call(id: u64) -> serde_json::Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "eth_cancelBundle",
+            "params": [{"replacementUuid": "8f3b5a7c-1d2e-4f60-9a8b-7c6d5e4f3a2b", "signingAddress": Address::ZERO}],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        let limits = RequestLimits {
+            max_request_body_size: 64 * 1024,
+            max_batch_size: 2,
+            max_bundle_txs: 1,
+            ..Default::default()
+        };
+        let (url, _results, cancel, server) = start_http_server(limits).await;
+
+        // body size
+        let big_call = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{"blockNumber": "0x1", "txs": [format!("0x{}", "00".repeat(64 * 1024))]}],
+        });
+        assert_eq!(
+            post(&url, serde_json::to_vec(&big_call).unwrap(), false).await,
+            Some(-32701)
+        );
+
+        // batch size
+        let batch = |len: u64| {
+            serde_json::to_vec(&(0..len).map(cancel_bundle_call).collect::<Vec<_>>()).unwrap()
+        };
+        assert_ne!(post(&url, batch(2), false).await, Some(-32010));
+        assert_eq!(post(&url, batch(3), false).await, Some(-32010));
+
+        // bundle txs
+        let two_txs = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{"blockNumber": "0x1", "txs": ["0x00", "0x00"]}],
+        });
+        assert_eq!(
+            post(&url, serde_json::to_vec(&two_txs).unwrap(), false).await,
+            Some(-32602)
+        );
+
+        cancel.cancel();
+        server.await.unwrap();
+    }
+
+    /// Bodies are never decompressed: a small gzip body that would inflate past the limit is just a parse error.
+    #[tokio::test]
+    async fn test_compressed_body_is_not_decompressed() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let (url, mut results, cancel, server) = start_http_server(RequestLimits {
+            max_request_body_size: 64 * 1024,
+            ..Default::default()
+        })
+        .await;
+        let bomb = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{"blockNumber": "0x1", "txs": [format!("0x{}", "00".repeat(1024 * 1024))]}],
+        });
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&serde_json::to_vec(&bomb).unwrap())
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < 64 * 1024);
+        assert_eq!(post(&url, compressed, true).await, Some(-32700));
+        assert!(results.try_recv().is_err());
+
+        cancel.cancel();
+        server.await.unwrap();
+    }
 }
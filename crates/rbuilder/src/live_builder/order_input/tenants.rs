@@ -0,0 +1,320 @@
+//! Tenants are orderflow partners that get isolated quotas on our resources.
+//! A tenant is identified by the signers of its bundles (mempool txs never belong to a tenant) and can have:
+//! - max_pool_orders: max orders it can have at the same time in the [`super::orderpool::OrderPool`]. Extra orders are dropped on intake.
+//! - max_simulations_per_slot: max simulations we run for its orders on each slot. Extra simulations are dropped.
+//! - private: its orders are not synced to our standby instances unless sync is also set.
+//! - max_block_exposures: max distinct submitted blocks its orders can be in without landing, after that we stop
+//!   including them (see [`crate::building::exposure_budget`]).
+//! - sandbox: its orders are simulated by dedicated workers, optionally CPU capped with a cgroup (see
+//!   [`crate::live_builder::simulation::tenant_sandbox`]).
+//!
+//! Metrics are labeled with the tenant name (see [`crate::telemetry::inc_tenant_orders`]).
+//! Like the signer reputation, the simulation side reads the registry registered via [`set_tenant_registry`].
+
+use crate::primitives::Order;
+use ahash::HashMap;
+use alloy_primitives::Address;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::sync::Arc;
+
+lazy_static! {
+    static ref TENANT_REGISTRY: Mutex<Option<Arc<TenantRegistry>>> = Mutex::new(None);
+}
+
+/// Registry used by the simulation jobs. Empty (no tenants) if none was set.
+pub fn tenant_registry() -> Arc<TenantRegistry> {
+    TENANT_REGISTRY.lock().clone().unwrap_or_default()
+}
+
+pub fn set_tenant_registry(registry: Arc<TenantRegistry>) {
+    *TENANT_REGISTRY.lock() = Some(registry);
+}
+
+/// Index of the tenant in the [`TenantRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TenantId(usize);
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantConfig {
+    /// Used on logs and metric labels.
+    pub name: String,
+    /// Bundle signers of the tenant.
+    pub signers: Vec<Address>,
+    pub max_pool_orders: Option<usize>,
+    pub max_simulations_per_slot: Option<usize>,
+    pub private: bool,
+    /// Private tenants: also send its orders to our standby instances (see [`super::orderpool_sync`]).
+    pub sync: bool,
+    /// If set we POST here which of the tenant orders landed in our blocks
+    /// (see [`crate::live_builder::block_output::inclusion_notifier`]).
+    pub inclusion_webhook_url: Option<String>,
+    pub max_block_exposures: Option<usize>,
+    pub sandbox: Option<TenantSandboxConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantSandboxConfig {
+    /// Workers simulating only the orders of the tenant.
+    pub simulation_threads: usize,
+    /// cpu.max of the cgroup of the workers in % of one CPU, only used if tenant_cgroup_root is set.
+    pub cpu_quota_percent: Option<u32>,
+}
+
+impl Default for TenantSandboxConfig {
+    fn default() -> Self {
+        Self {
+            simulation_threads: 1,
+            cpu_quota_percent: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TenantRegistry {
+    tenants: Vec<TenantConfig>,
+    by_signer: HashMap<Address, TenantId>,
+}
+
+impl TenantRegistry {
+    pub fn new(tenants: Vec<TenantConfig>) -> eyre::Result<Self> {
+        let mut by_signer = HashMap::default();
+        for (index, tenant) in tenants.iter().enumerate() {
+            if tenants[..index].iter().any(|t| t.name == tenant.name) {
+                eyre::bail!("Duplicated tenant name {}", tenant.name);
+            }
+            if tenant
+                .sandbox
+                .as_ref()
+                .map_or(false, |sandbox| sandbox.simulation_threads == 0)
+            {
+                eyre::bail!("Tenant {} sandbox has no simulation threads", tenant.name);
+            }
+            for signer in &tenant.signers {
+                if by_signer.insert(*signer, TenantId(index)).is_some() {
+                    eyre::bail!("Signer {:?} belongs to more than one tenant", signer);
+                }
+            }
+        }
+        Ok(Self { tenants, by_signer })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    pub fn has_inclusion_webhooks(&self) -> bool {
+        self.tenants
+            .iter()
+            .any(|tenant| tenant.inclusion_webhook_url.is_some())
+    }
+
+    pub fn has_exposure_budgets(&self) -> bool {
+        self.tenants
+            .iter()
+            .any(|tenant| tenant.max_block_exposures.is_some())
+    }
+
+    /// Tenants with a simulation sandbox.
+    pub fn sandboxed(
+        &self,
+    ) -> impl Iterator<Item = (TenantId, &TenantConfig, &TenantSandboxConfig)> + '_ {
+        self.tenants
+            .iter()
+            .enumerate()
+            .filter_map(|(index, tenant)| {
+                tenant
+                    .sandbox
+                    .as_ref()
+                    .map(|sandbox| (TenantId(index), tenant, sandbox))
+            })
+    }
+
+    /// Exposure budget of the order's tenant.
+    pub fn max_block_exposures(&self, order: &Order) -> Option<usize> {
+        self.tenant_of(order)
+            .and_then(|tenant| self.config(tenant).max_block_exposures)
+    }
+
+    pub fn tenant_of(&self, order: &Order) -> Option<TenantId> {
+        if self.tenants.is_empty() {
+            return None;
+        }
+        order
+            .signer()
+            .and_then(|signer| self.by_signer.get(&signer).copied())
+    }
+
+    pub fn config(&self, id: TenantId) -> &TenantConfig {
+        &self.tenants[id.0]
+    }
+
+    pub fn name(&self, id: TenantId) -> &str {
+        &self.tenants[id.0].name
+    }
+
+    /// false for orders of private tenants that didn't allow the sync to our standby instances.
+    pub fn can_sync(&self, order: &Order) -> bool {
+        self.tenant_of(order).map_or(true, |tenant| {
+            let config = self.config(tenant);
+            !config.private || config.sync
+        })
+    }
+}
+
+/// Per slot count of simulations used by each tenant.
+#[derive(Debug)]
+pub struct TenantSimulationBudget {
+    registry: Arc<TenantRegistry>,
+    used: HashMap<TenantId, usize>,
+}
+
+impl TenantSimulationBudget {
+    pub fn new(registry: Arc<TenantRegistry>) -> Self {
+        Self {
+            registry,
+            used: HashMap::default(),
+        }
+    }
+
+    /// Consumes one simulation of the order's tenant.
+    /// Returns the tenant name (None for orders without tenant) so the caller can label metrics.
+    /// Fails (without consuming) if the tenant has no budget left.
+    pub fn try_consume(&mut self, order: &Order) -> Result<Option<&str>, &str> {
+        let Some(tenant) = self.registry.tenant_of(order) else {
+            return Ok(None);
+        };
+        let used = self.used.entry(tenant).or_default();
+        let config = self.registry.config(tenant);
+        if config
+            .max_simulations_per_slot
+            .map_or(false, |max| *used >= max)
+        {
+            return Err(&config.name);
+        }
+        *used += 1;
+        Ok(Some(&config.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Bundle, Metadata};
+    use std::str::FromStr;
+
+    fn signer(n: u8) -> Address {
+        Address::with_last_byte(n)
+    }
+
+    fn bundle(signer: Option<Address>, block: u64) -> Order {
+        Order::Bundle(Bundle {
+            block,
+            min_timestamp: None,
+            max_timestamp: None,
+            txs: Vec::new(),
+            reverting_tx_hashes: Vec::new(),
+            hash: Default::default(),
+            uuid: uuid::Uuid::from_u128(block as u128),
+            replacement_data: None,
+            signer,
+            metadata: Metadata::default(),
+        })
+    }
+
+    fn registry() -> TenantRegistry {
+        TenantRegistry::new(vec![
+            TenantConfig {
+                name: "private".to_string(),
+                signers: vec![signer(1), signer(2)],
+                max_simulations_per_slot: Some(2),
+                private: true,
+                ..Default::default()
+            },
+            TenantConfig {
+                name: "public".to_string(),
+                signers: vec![signer(3)],
+                ..Default::default()
+            },
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = registry();
+        let private_order = bundle(Some(signer(2)), 1);
+        let public_order = bundle(Some(signer(3)), 1);
+        let no_tenant_order = bundle(Some(signer(4)), 1);
+        assert_eq!(registry.tenant_of(&private_order), Some(TenantId(0)));
+        assert_eq!(registry.tenant_of(&no_tenant_order), None);
+        assert_eq!(registry.tenant_of(&bundle(None, 1)), None);
+
+        assert!(!registry.can_sync(&private_order));
+        assert!(registry.can_sync(&public_order));
+        assert!(registry.can_sync(&no_tenant_order));
+        let mut tenants = registry.tenants.clone();
+        tenants[0].sync = true;
+        assert!(TenantRegistry::new(tenants)
+            .unwrap()
+            .can_sync(&private_order));
+    }
+
+    #[test]
+    fn test_invalid_registry() {
+        let tenant = TenantConfig {
+            name: "a".to_string(),
+            signers: vec![signer(1)],
+            ..Default::default()
+        };
+        assert!(TenantRegistry::new(vec![tenant.clone(), tenant.clone()]).is_err());
+        let other = TenantConfig {
+            name: "b".to_string(),
+            ..tenant.clone()
+        };
+        assert!(TenantRegistry::new(vec![tenant, other]).is_err());
+    }
+
+    #[test]
+    fn test_simulation_budget() {
+        let mut budget = TenantSimulationBudget::new(Arc::new(registry()));
+        let order = bundle(Some(signer(1)), 1);
+        assert_eq!(budget.try_consume(&order), Ok(Some("private")));
+        assert_eq!(
+            budget.try_consume(&bundle(Some(signer(2)), 2)),
+            Ok(Some("private"))
+        );
+        assert_eq!(budget.try_consume(&order), Err("private"));
+        // no limit
+        for _ in 0..10 {
+            assert_eq!(
+                budget.try_consume(&bundle(Some(signer(3)), 1)),
+                Ok(Some("public"))
+            );
+        }
+        assert_eq!(budget.try_consume(&bundle(Some(signer(4)), 1)), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config: TenantConfig = toml::from_str(
+            r#"
+            name = "partner"
+            signers = ["0x0000000000000000000000000000000000000001"]
+            max_pool_orders = 100
+            private = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.signers,
+            vec![Address::from_str("0x0000000000000000000000000000000000000001").unwrap()]
+        );
+        assert_eq!(config.max_pool_orders, Some(100));
+        assert_eq!(config.max_simulations_per_slot, None);
+        assert!(config.private);
+    }
+}
@@ -0,0 +1,162 @@
+//! Typed txs are only accepted by the input RPC (json and gRPC) once the fork introducing their type is active
+//! according to the chain spec (eg: no blob txs before Cancun, no EIP-7702 txs before Prague) so the searcher gets a
+//! precise error when sending the order instead of it being silently dropped by the simulation.
+//!
+//! At intake we don't know the block the order will land on:
+//! - Timestamp activated forks are checked against now + [`FORK_ACTIVATION_LOOKAHEAD_SECS`] so orders for the first
+//!   block of the fork (sent before it) are accepted.
+//! - Block activated forks are checked against the target block of bundles, mempool txs only need the fork to be
+//!   scheduled.
+
+use crate::primitives::Order;
+use alloy_primitives::TxHash;
+use reth::primitives::TxType;
+use reth_chainspec::{ChainSpec, EthereumHardfork, ForkCondition};
+use thiserror::Error;
+
+/// A slot.
+pub const FORK_ACTIVATION_LOOKAHEAD_SECS: u64 = 12;
+
+/// Forks that introduced a tx type.
+const TX_TYPE_FORKS: [EthereumHardfork; 4] = [
+    EthereumHardfork::Berlin,
+    EthereumHardfork::London,
+    EthereumHardfork::Cancun,
+    EthereumHardfork::Prague,
+];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Tx {tx:?} type {tx_type:?} needs the {fork} fork which is not active on this chain yet")]
+pub struct TxTypeNotActive {
+    pub tx: TxHash,
+    pub tx_type: TxType,
+    pub fork: EthereumHardfork,
+}
+
+/// Fork that introduced the tx type, None for legacy txs.
+pub fn tx_type_activation_fork(tx_type: TxType) -> Option<EthereumHardfork> {
+    match tx_type {
+        TxType::Eip2930 => Some(EthereumHardfork::Berlin),
+        TxType::Eip1559 => Some(EthereumHardfork::London),
+        TxType::Eip4844 => Some(EthereumHardfork::Cancun),
+        TxType::Eip7702 => Some(EthereumHardfork::Prague),
+        _ => None,
+    }
+}
+
+/// Activation of the forks introducing tx types, taken from the chain spec.
+/// Default accepts every tx type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxTypeForks {
+    conditions: Vec<(EthereumHardfork, ForkCondition)>,
+}
+
+impl TxTypeForks {
+    pub fn new(chain_spec: &ChainSpec) -> Self {
+        Self {
+            conditions: TX_TYPE_FORKS
+                .into_iter()
+                .map(|fork| (fork, chain_spec.fork(fork)))
+                .collect(),
+        }
+    }
+
+    /// target_block: block the order targets, None if unknown.
+    fn is_active(&self, fork: EthereumHardfork, target_block: Option<u64>, now: u64) -> bool {
+        let Some((_, condition)) = self.conditions.iter().find(|(f, _)| *f == fork) else {
+            return true;
+        };
+        match condition {
+            ForkCondition::Timestamp(_) => {
+                condition.active_at_timestamp(now + FORK_ACTIVATION_LOOKAHEAD_SECS)
+            }
+            ForkCondition::Block(_) | ForkCondition::TTD { .. } => {
+                target_block.map_or(true, |block| condition.active_at_block(block))
+            }
+            ForkCondition::Never => false,
+        }
+    }
+
+    /// Err for the first tx of the order whose type needs a fork that is not active. now: unix timestamp in seconds.
+    pub fn check_order(&self, order: &Order, now: u64) -> Result<(), TxTypeNotActive> {
+        let target_block = order.target_block();
+        for (tx, _) in order.list_txs() {
+            let tx_type = tx.internal_tx_unsecure().tx_type();
+            if let Some(fork) = tx_type_activation_fork(tx_type) {
+                if !self.is_active(fork, target_block, now) {
+                    return Err(TxTypeNotActive {
+                        tx: tx.hash(),
+                        tx_type,
+                        fork,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        primitives::{MempoolTx, TransactionSignedEcRecoveredWithBlobs},
+        utils::Signer,
+    };
+    use alloy_consensus::TxEip1559;
+    use reth_chainspec::{ChainSpecBuilder, MAINNET};
+
+    fn eip1559_order() -> Order {
+        let tx = Signer::random()
+            .sign_tx(
+                TxEip1559 {
+                    chain_id: 1,
+                    ..Default::default()
+                }
+                .into(),
+            )
+            .unwrap();
+        Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_no_blobs(tx).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_is_active() {
+        let forks = TxTypeForks::new(&MAINNET);
+        // mainnet Cancun: 1710338135
+        assert!(!forks.is_active(EthereumHardfork::Cancun, None, 1_700_000_000));
+        assert!(forks.is_active(EthereumHardfork::Cancun, None, 1_720_000_000));
+        // first Cancun block sent a bit before it
+        assert!(forks.is_active(EthereumHardfork::Cancun, None, 1_710_338_130));
+        // mainnet London: 12965000
+        assert!(forks.is_active(EthereumHardfork::London, None, 0));
+        assert!(!forks.is_active(EthereumHardfork::London, Some(12_000_000), 0));
+        assert!(forks.is_active(EthereumHardfork::London, Some(13_000_000), 0));
+        assert!(TxTypeForks::default().is_active(EthereumHardfork::Prague, None, 0));
+
+        assert_eq!(tx_type_activation_fork(TxType::Legacy), None);
+        assert_eq!(
+            tx_type_activation_fork(TxType::Eip4844),
+            Some(EthereumHardfork::Cancun)
+        );
+    }
+
+    #[test]
+    fn test_check_order() {
+        let order = eip1559_order();
+        assert_eq!(TxTypeForks::new(&MAINNET).check_order(&order, 0), Ok(()));
+        let pre_london = ChainSpecBuilder::mainnet()
+            .with_fork(EthereumHardfork::London, ForkCondition::Never)
+            .build();
+        let res = TxTypeForks::new(&pre_london).check_order(&order, 0);
+        assert_eq!(
+            res,
+            Err(TxTypeNotActive {
+                tx: order.list_txs()[0].0.hash(),
+                tx_type: TxType::Eip1559,
+                fork: EthereumHardfork::London,
+            })
+        );
+    }
+}
@@ -1,90 +1,315 @@
+#[cfg(feature = "devp2p")]
+use super::devp2p_listener::start_devp2p_listener;
 use super::{OrderInputConfig, ReplaceableOrderPoolCommand};
 use crate::{
+    live_builder::base_config::EnvOrValue,
     primitives::{MempoolTx, Order, TransactionSignedEcRecoveredWithBlobs},
-    telemetry::add_txfetcher_time_to_query,
+    telemetry::{add_mempool_source_arrival, add_txfetcher_time_to_query},
 };
-use alloy_primitives::{hex, Bytes, FixedBytes};
-use alloy_provider::{IpcConnect, Provider, ProviderBuilder, RootProvider};
+use alloy_primitives::{hex, Bytes, FixedBytes, TxHash};
+use alloy_provider::{IpcConnect, Provider, ProviderBuilder, RootProvider, WsConnect};
 use alloy_pubsub::PubSubFrontend;
 use futures::StreamExt;
-use std::{pin::pin, time::Instant};
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{
     sync::{mpsc, mpsc::error::SendTimeoutError},
     task::JoinHandle,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
+
+/// Name of the el_node_ipc_path source on metrics.
+const EL_NODE_SOURCE_NAME: &str = "el_node";
+/// Txs remembered for dedup, much more than what several nodes can announce before the slowest one catches up.
+const DEDUP_CAPACITY: usize = 100_000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolSourceEndpoint {
+    Ipc(PathBuf),
+    Ws(String),
+}
+
+/// Extra node we get pending txs from (the el node is always a source).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolSource {
+    /// Used on logs and metric labels.
+    pub name: String,
+    pub endpoint: MempoolSourceEndpoint,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolSourceConfig {
+    pub name: String,
+    pub ipc_path: Option<PathBuf>,
+    pub ws_url: Option<EnvOrValue<String>>,
+}
+
+impl MempoolSourceConfig {
+    pub fn to_source(&self) -> eyre::Result<MempoolSource> {
+        let endpoint = match (&self.ipc_path, &self.ws_url) {
+            (Some(ipc_path), None) => MempoolSourceEndpoint::Ipc(ipc_path.clone()),
+            (None, Some(ws_url)) => MempoolSourceEndpoint::Ws(ws_url.value()?),
+            _ => eyre::bail!(
+                "Mempool source {} needs exactly one of ipc_path and ws_url",
+                self.name
+            ),
+        };
+        Ok(MempoolSource {
+            name: self.name.clone(),
+            endpoint,
+        })
+    }
+}
+
+/// Listener of the devp2p tx gossip, see [`super::devp2p_listener`] (needs the devp2p feature).
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Devp2pListenerConfig {
+    /// tcp (eth protocol) and udp (discovery) port, DEFAULT_DEVP2P_PORT if unset.
+    pub port: Option<u16>,
+    /// enode urls, the chain bootnodes if empty.
+    pub bootnodes: Vec<String>,
+    /// Max outbound and max inbound peers, DEFAULT_DEVP2P_MAX_PEERS if unset.
+    pub max_peers: Option<usize>,
+    /// Hex node key, a random one (new node id on every restart) if unset.
+    pub secret_key: Option<EnvOrValue<String>>,
+}
+
+/// Txs announced by any source, with the time of the first announcement.
+#[derive(Debug)]
+pub(super) struct MempoolDedup {
+    seen: LruCache<TxHash, Instant>,
+}
 
-/// Subscribes to EL mempool and pushes new txs as orders in results.
+impl MempoolDedup {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            seen: LruCache::new(NonZeroUsize::new(capacity).unwrap()),
+        }
+    }
+
+    /// None if it's the first time we see tx_hash (caller should fetch it), otherwise the delay after the first announcement.
+    fn on_announced(&mut self, tx_hash: TxHash, now: Instant) -> Option<Duration> {
+        if let Some(first_seen) = self.seen.peek(&tx_hash) {
+            return Some(now.saturating_duration_since(*first_seen));
+        }
+        self.seen.put(tx_hash, now);
+        None
+    }
+
+    /// Call if we failed to fetch the tx so the next source that announces it can fetch it.
+    pub(super) fn forget(&mut self, tx_hash: &TxHash) {
+        self.seen.pop(tx_hash);
+    }
+}
+
+pub(super) type SharedMempoolDedup = Arc<Mutex<MempoolDedup>>;
+
+async fn connect(endpoint: &MempoolSourceEndpoint) -> eyre::Result<RootProvider<PubSubFrontend>> {
+    Ok(match endpoint {
+        MempoolSourceEndpoint::Ipc(path) => {
+            ProviderBuilder::new()
+                .on_ipc(IpcConnect::new(path.clone()))
+                .await?
+        }
+        MempoolSourceEndpoint::Ws(url) => ProviderBuilder::new().on_ws(WsConnect::new(url)).await?,
+    })
+}
+
+/// Subscribes to EL mempool (unless config.el_node_mempool is false) and the extra config.mempool_sources and devp2p
+/// listener, and pushes new txs as orders in results.
 /// This version allows 4844 by subscribing to subscribe_pending_txs to get the hashes and then calling eth_getRawTransactionByHash
 /// to get the raw tx that, in case of 4844 tx, may include blobs.
+/// Txs announced by several sources are fetched only from the first one.
+/// The EL node is critical (if its stream closes we cancel global_cancel), extra sources just reconnect.
 /// In the future we may consider updating reth so we can process blob txs in a different task to avoid slowing down non blob txs.
 pub async fn subscribe_to_txpool_with_blobs(
     config: OrderInputConfig,
     results: mpsc::Sender<ReplaceableOrderPoolCommand>,
     global_cancel: CancellationToken,
 ) -> eyre::Result<JoinHandle<()>> {
-    let ipc = IpcConnect::new(config.ipc_path);
-    let provider = ProviderBuilder::new().on_ipc(ipc).await?;
+    let dedup: SharedMempoolDedup = Arc::new(Mutex::new(MempoolDedup::new(DEDUP_CAPACITY)));
 
-    let handle = tokio::spawn(async move {
-        info!("Subscribe to txpool with blobs: started");
+    let mut handles = Vec::new();
+    // before connecting to the el node, the listener doesn't need it
+    #[cfg(feature = "devp2p")]
+    if let Some(listener) = config.devp2p_listener.clone() {
+        handles.push(
+            start_devp2p_listener(
+                listener,
+                dedup.clone(),
+                config.clone(),
+                results.clone(),
+                global_cancel.clone(),
+            )
+            .await?,
+        );
+    }
+    for source in config.mempool_sources.clone() {
+        let config = config.clone();
+        let results = results.clone();
+        let dedup = dedup.clone();
+        let global_cancel = global_cancel.clone();
+        handles.push(tokio::spawn(async move {
+            while !global_cancel.is_cancelled() {
+                match connect(&source.endpoint).await {
+                    Ok(provider) => {
+                        let results_closed = run_source(
+                            &source.name,
+                            provider,
+                            &dedup,
+                            &config,
+                            &results,
+                            &global_cancel,
+                        )
+                        .await;
+                        if results_closed {
+                            break;
+                        }
+                        warn!(source = source.name, "Mempool source stream closed");
+                    }
+                    Err(err) => warn!(
+                        source = source.name,
+                        ?err,
+                        "Failed to connect to mempool source"
+                    ),
+                }
+                tokio::select! {
+                    _ = global_cancel.cancelled() => break,
+                    _ = tokio::time::sleep(RECONNECT_DELAY) => {},
+                }
+            }
+            info!(source = source.name, "Mempool source finished");
+        }));
+    }
+
+    if config.el_node_mempool {
+        let provider = connect(&MempoolSourceEndpoint::Ipc(config.ipc_path.clone())).await?;
+        handles.push(tokio::spawn(async move {
+            info!("Subscribe to txpool with blobs: started");
+            run_source(
+                EL_NODE_SOURCE_NAME,
+                provider,
+                &dedup,
+                &config,
+                &results,
+                &global_cancel,
+            )
+            .await;
+            // stream is closed, cancelling token because builder can't work without this stream
+            global_cancel.cancel();
+            info!("Subscribe to txpool: finished");
+        }));
+    }
+
+    Ok(tokio::spawn(async move {
+        for handle in handles {
+            handle.await.unwrap_or_default();
+        }
+    }))
+}
 
-        let stream = match provider.subscribe_pending_transactions().await {
-            Ok(stream) => stream.into_stream().take_until(global_cancel.cancelled()),
+/// Forwards the txs announced by provider until its stream closes or global_cancel.
+/// Returns true if results was closed.
+async fn run_source(
+    source: &str,
+    provider: RootProvider<PubSubFrontend>,
+    dedup: &SharedMempoolDedup,
+    config: &OrderInputConfig,
+    results: &mpsc::Sender<ReplaceableOrderPoolCommand>,
+    global_cancel: &CancellationToken,
+) -> bool {
+    let stream = match provider.subscribe_pending_transactions().await {
+        Ok(stream) => stream.into_stream().take_until(global_cancel.cancelled()),
+        Err(err) => {
+            error!(source, ?err, "Failed to subscribe to txpool stream");
+            return false;
+        }
+    };
+    let mut stream = pin!(stream);
+
+    while let Some(tx_hash) = stream.next().await {
+        let start = Instant::now();
+        if !is_first_announcement(source, dedup, tx_hash, start) {
+            continue;
+        }
+
+        let tx_with_blobs = match get_tx_with_blobs(tx_hash, &provider).await {
+            Ok(Some(tx_with_blobs)) => tx_with_blobs,
+            Ok(None) => {
+                trace!(source, ?tx_hash, "tx not found in tx pool");
+                dedup.lock().forget(&tx_hash);
+                continue;
+            }
             Err(err) => {
-                error!(?err, "Failed to subscribe to ipc txpool stream");
-                // Closing builder because this job is critical so maybe restart will help
-                global_cancel.cancel();
-                return;
+                error!(source, ?tx_hash, ?err, "Failed to get tx pool");
+                dedup.lock().forget(&tx_hash);
+                continue;
             }
         };
-        let mut stream = pin!(stream);
-
-        while let Some(tx_hash) = stream.next().await {
-            let start = Instant::now();
 
-            let tx_with_blobs = match get_tx_with_blobs(tx_hash, &provider).await {
-                Ok(Some(tx_with_blobs)) => tx_with_blobs,
-                Ok(None) => {
-                    trace!(?tx_hash, "tx not found in tx pool");
-                    continue;
-                }
-                Err(err) => {
-                    error!(?tx_hash, ?err, "Failed to get tx pool");
-                    continue;
-                }
-            };
-
-            let tx = MempoolTx::new(tx_with_blobs);
-            let order = Order::Tx(tx);
-            let parse_duration = start.elapsed();
-            trace!(order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), "Mempool transaction received with blobs");
-            add_txfetcher_time_to_query(parse_duration);
-
-            match results
-                .send_timeout(
-                    ReplaceableOrderPoolCommand::Order(order),
-                    config.results_channel_timeout,
-                )
-                .await
-            {
-                Ok(()) => {}
-                Err(SendTimeoutError::Timeout(_)) => {
-                    error!("Failed to send txpool tx to results channel, timeout");
-                }
-                Err(SendTimeoutError::Closed(_)) => {
-                    break;
-                }
-            }
+        if send_mempool_tx(source, tx_with_blobs, start, config, results).await {
+            return true;
         }
+    }
+    false
+}
 
-        // stream is closed, cancelling token because builder can't work without this stream
-        global_cancel.cancel();
-        info!("Subscribe to txpool: finished");
-    });
+/// Records the arrival of tx_hash from source, false if another source already announced it.
+pub(super) fn is_first_announcement(
+    source: &str,
+    dedup: &SharedMempoolDedup,
+    tx_hash: TxHash,
+    now: Instant,
+) -> bool {
+    let delay = dedup.lock().on_announced(tx_hash, now);
+    add_mempool_source_arrival(source, delay);
+    delay.is_none()
+}
 
-    Ok(handle)
+/// Sends the tx source announced at start to results.
+/// Returns true if results was closed.
+pub(super) async fn send_mempool_tx(
+    source: &str,
+    tx_with_blobs: TransactionSignedEcRecoveredWithBlobs,
+    start: Instant,
+    config: &OrderInputConfig,
+    results: &mpsc::Sender<ReplaceableOrderPoolCommand>,
+) -> bool {
+    let tx = MempoolTx::new(tx_with_blobs);
+    let order = Order::Tx(tx);
+    let parse_duration = start.elapsed();
+    trace!(source, order = ?order.id(), parse_duration_mus = parse_duration.as_micros(), "Mempool transaction received with blobs");
+    add_txfetcher_time_to_query(parse_duration);
+
+    match results
+        .send_timeout(
+            ReplaceableOrderPoolCommand::Order(order),
+            config.results_channel_timeout,
+        )
+        .await
+    {
+        Ok(()) => false,
+        Err(SendTimeoutError::Timeout(_)) => {
+            error!(
+                source,
+                "Failed to send txpool tx to results channel, timeout"
+            );
+            false
+        }
+        Err(SendTimeoutError::Closed(_)) => true,
+    }
 }
 
 /// Calls eth_getRawTransactionByHash on EL node and decodes.
@@ -115,6 +340,30 @@ async fn get_tx_with_blobs(
 #[cfg(test)]
 mod test {
     use super::*;
+
+    #[test]
+    fn test_mempool_dedup() {
+        let mut dedup = MempoolDedup::new(2);
+        let now = Instant::now();
+        let later = now + Duration::from_millis(30);
+        let (tx1, tx2, tx3) = (
+            TxHash::with_last_byte(1),
+            TxHash::with_last_byte(2),
+            TxHash::with_last_byte(3),
+        );
+        assert_eq!(dedup.on_announced(tx1, now), None);
+        assert_eq!(
+            dedup.on_announced(tx1, later),
+            Some(Duration::from_millis(30))
+        );
+        // failed fetch, next source fetches it
+        dedup.forget(&tx1);
+        assert_eq!(dedup.on_announced(tx1, later), None);
+        // oldest txs are forgotten
+        assert_eq!(dedup.on_announced(tx2, later), None);
+        assert_eq!(dedup.on_announced(tx3, later), None);
+        assert_eq!(dedup.on_announced(tx1, later), None);
+    }
     use alloy_consensus::{SidecarBuilder, SimpleCoder};
     use alloy_network::{EthereumWallet, TransactionBuilder};
     use alloy_node_bindings::Anvil;
@@ -171,7 +420,7 @@ mod test {
         let recv_tx = receiver.recv().await.unwrap();
 
         let tx_with_blobs = match recv_tx {
-            ReplaceableOrderPoolCommand::Order(Order::Tx(MempoolTx { tx_with_blobs })) => {
+            ReplaceableOrderPoolCommand::Order(Order::Tx(MempoolTx { tx_with_blobs, .. })) => {
                 Some(tx_with_blobs)
             }
             _ => None,
@@ -193,7 +442,7 @@ mod test {
         let recv_tx = receiver.recv().await.unwrap();
 
         let tx_without_blobs = match recv_tx {
-            ReplaceableOrderPoolCommand::Order(Order::Tx(MempoolTx { tx_with_blobs })) => {
+            ReplaceableOrderPoolCommand::Order(Order::Tx(MempoolTx { tx_with_blobs, .. })) => {
                 Some(tx_with_blobs)
             }
             _ => None,
@@ -12,6 +12,7 @@ use crate::{
             payload_source::PayloadSourceMuxer,
             relay_epoch_cache::{RelaysForSlotData, SlotData},
         },
+        proposer_overrides::{ProposerOverride, ProposerOverrides},
         SlotSource,
     },
     primitives::mev_boost::{MevBoostRelay, MevBoostRelayID},
@@ -20,7 +21,7 @@ use ahash::HashSet;
 use alloy_eips::merge::SLOT_DURATION;
 use alloy_primitives::{utils::format_ether, Address, B256, U256};
 use alloy_rpc_types_beacon::events::PayloadAttributesEvent;
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use tokio::{sync::mpsc, task::JoinHandle};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
@@ -42,6 +43,8 @@ pub struct MevBoostSlotData {
     /// List of relays agreeing to the slot_data. It may not contain all the relays (eg: errors, forks, validators registering only to some relays)
     pub relays: Vec<MevBoostRelayID>,
     pub slot_data: SlotData,
+    /// Override of the slot proposer (see [`crate::live_builder::proposer_overrides`]).
+    pub proposer_override: Option<Arc<ProposerOverride>>,
 }
 
 impl MevBoostSlotData {
@@ -82,6 +85,7 @@ pub struct MevBoostSlotDataGenerator {
     cls: Vec<Client>,
     relays: Vec<MevBoostRelay>,
     blocklist: HashSet<Address>,
+    proposer_overrides: ProposerOverrides,
 
     global_cancellation: CancellationToken,
 }
@@ -97,10 +101,18 @@ impl MevBoostSlotDataGenerator {
             cls,
             relays,
             blocklist,
+            proposer_overrides: Default::default(),
             global_cancellation,
         }
     }
 
+    pub fn with_proposer_overrides(self, proposer_overrides: ProposerOverrides) -> Self {
+        Self {
+            proposer_overrides,
+            ..self
+        }
+    }
+
     /// Spawns the reader task.
     /// It reads from a PayloadSourceMuxer, replaces the fee_recipient/gas_limit with the info from the relays and filters duplicates.
     /// Why the need for replacing fee_recipient?
@@ -143,11 +155,13 @@ impl MevBoostSlotDataGenerator {
                     .payload_attributes
                     .suggested_fee_recipient = slot_data.fee_recipient;
 
+                let proposer_override = self.proposer_overrides.resolve(&slot_data.pubkey);
                 let mev_boost_slot_data = MevBoostSlotData {
                     payload_attributes_event: correct_event,
                     suggested_gas_limit: slot_data.gas_limit,
                     relays,
                     slot_data,
+                    proposer_override,
                 };
 
                 if let Err(err) =
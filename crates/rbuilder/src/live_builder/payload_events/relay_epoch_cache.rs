@@ -1,5 +1,5 @@
 use crate::{
-    mev_boost::{RelayError, ValidatorSlotData},
+    mev_boost::{RelayError, ValidatorPreferences, ValidatorSlotData},
     primitives::mev_boost::{MevBoostRelay, MevBoostRelayID},
     telemetry::{inc_conn_relay_errors, inc_other_relay_errors, inc_too_many_req_relay_errors},
 };
@@ -17,6 +17,16 @@ pub struct SlotData {
     pub gas_limit: u64,
     /// Selected registered validator for the slot key.
     pub pubkey: H384,
+    /// Merge of the preferences of all the relays agreeing on the registration so we satisfy all of them.
+    pub preferences: ValidatorPreferences,
+}
+
+impl SlotData {
+    fn same_registration(&self, other: &SlotData) -> bool {
+        self.fee_recipient == other.fee_recipient
+            && self.gas_limit == other.gas_limit
+            && self.pubkey == other.pubkey
+    }
 }
 
 /// Gets ValidatorSlotData for a single slot via get_slot_data.
@@ -135,15 +145,17 @@ impl RelaysForSlotData {
                 fee_recipient: relay_data.entry.message.fee_recipient,
                 gas_limit: relay_data.entry.message.gas_limit,
                 pubkey: relay_data.entry.message.pubkey,
+                preferences: relay_data.preferences.unwrap_or_default(),
             };
-            if let Some(slot_data) = &slot_data {
-                if slot_data != &relay_slot_data {
+            if let Some(slot_data) = &mut slot_data {
+                if !slot_data.same_registration(&relay_slot_data) {
                     warn!(
                         relay_slot_data = ?relay_slot_data, slot_data = ?slot_data,
                         "Relay returned slot data that is different from returned from other relay",
                     );
                     continue;
                 }
+                slot_data.preferences = slot_data.preferences.merge(&relay_slot_data.preferences);
             } else {
                 // since relays are sorted the relay with the highest priority will determine the value of slot_data
                 slot_data = Some(relay_slot_data);
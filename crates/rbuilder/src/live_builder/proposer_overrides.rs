@@ -0,0 +1,169 @@
+//! Per proposer exceptions to how we build and bid (eg: our own validators or partners).
+//! Proposers are identified by their validator pubkey, which we only know once the payload attributes for the slot
+//! arrive, so [`crate::live_builder::payload_events::MevBoostSlotDataGenerator`] resolves the override there and
+//! everything downstream (builders pool, bidder factory, bidding service) reads it from
+//! [`crate::live_builder::payload_events::MevBoostSlotData::proposer_override`].
+
+use ahash::HashMap;
+use alloy_primitives::{utils::parse_ether, U256};
+use primitive_types::H384;
+use serde::Deserialize;
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BiddingStrategy {
+    /// Same bidding as any other slot.
+    #[default]
+    Default,
+    /// Bid the true block value (plus the relay floor subsidy), the bid adjuster is not used.
+    TrueBlockValue,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProposerOverrideConfig {
+    /// Validator BLS pubkeys (0x hex) the override applies to.
+    pub pubkeys: Vec<String>,
+    /// If not empty only these live_builders run on their slots.
+    pub include_builders: Vec<String>,
+    /// These live_builders don't run on their slots.
+    pub exclude_builders: Vec<String>,
+    pub bidding: BiddingStrategy,
+    /// Replaces max_relay_floor_subsidy_eth on their slots ("0" disables the subsidy).
+    pub max_relay_floor_subsidy_eth: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProposerOverride {
+    include_builders: Vec<String>,
+    exclude_builders: Vec<String>,
+    pub bidding: BiddingStrategy,
+    pub max_relay_floor_subsidy: Option<U256>,
+}
+
+impl ProposerOverride {
+    pub fn runs_builder(&self, builder_name: &str) -> bool {
+        let included = self.include_builders.is_empty()
+            || self
+                .include_builders
+                .iter()
+                .any(|name| name == builder_name);
+        included
+            && !self
+                .exclude_builders
+                .iter()
+                .any(|name| name == builder_name)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProposerOverrides {
+    by_pubkey: HashMap<H384, Arc<ProposerOverride>>,
+}
+
+impl ProposerOverrides {
+    /// live_builders: names of the builders we run, overrides can only name those and must leave at least one.
+    pub fn from_config(
+        configs: &[ProposerOverrideConfig],
+        live_builders: &[String],
+    ) -> eyre::Result<Self> {
+        let mut by_pubkey = HashMap::default();
+        for config in configs {
+            for name in config
+                .include_builders
+                .iter()
+                .chain(&config.exclude_builders)
+            {
+                if !live_builders.contains(name) {
+                    eyre::bail!("Proposer override builder {} is not in live_builders", name);
+                }
+            }
+            let proposer_override = Arc::new(ProposerOverride {
+                include_builders: config.include_builders.clone(),
+                exclude_builders: config.exclude_builders.clone(),
+                bidding: config.bidding,
+                max_relay_floor_subsidy: config
+                    .max_relay_floor_subsidy_eth
+                    .as_deref()
+                    .map(parse_ether)
+                    .transpose()?,
+            });
+            if !live_builders
+                .iter()
+                .any(|name| proposer_override.runs_builder(name))
+            {
+                eyre::bail!(
+                    "Proposer override for {:?} leaves no builder to run",
+                    config.pubkeys
+                );
+            }
+            for pubkey in &config.pubkeys {
+                let pubkey = H384::from_str(pubkey)
+                    .map_err(|err| eyre::eyre!("Invalid proposer pubkey {}: {}", pubkey, err))?;
+                if by_pubkey
+                    .insert(pubkey, proposer_override.clone())
+                    .is_some()
+                {
+                    eyre::bail!("Proposer {:?} has more than one override", pubkey);
+                }
+            }
+        }
+        Ok(Self { by_pubkey })
+    }
+
+    pub fn resolve(&self, pubkey: &H384) -> Option<Arc<ProposerOverride>> {
+        self.by_pubkey.get(pubkey).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "0xb097a69fa420d01c293fed6b2596778d0722a2b076e401c2789cabce773a17c865285ff71b5dd545c7e77bee6ef8a41b";
+
+    #[test]
+    fn test_proposer_overrides() {
+        let live_builders = vec!["mgp-ordering".to_string(), "parallel".to_string()];
+        let config = ProposerOverrideConfig {
+            pubkeys: vec![PUBKEY.to_string()],
+            exclude_builders: vec!["parallel".to_string()],
+            bidding: BiddingStrategy::TrueBlockValue,
+            max_relay_floor_subsidy_eth: Some("0.01".to_string()),
+            ..Default::default()
+        };
+        let overrides = ProposerOverrides::from_config(&[config.clone()], &live_builders).unwrap();
+        let proposer_override = overrides.resolve(&H384::from_str(PUBKEY).unwrap()).unwrap();
+        assert!(proposer_override.runs_builder("mgp-ordering"));
+        assert!(!proposer_override.runs_builder("parallel"));
+        assert_eq!(proposer_override.bidding, BiddingStrategy::TrueBlockValue);
+        assert_eq!(
+            proposer_override.max_relay_floor_subsidy,
+            Some(parse_ether("0.01").unwrap())
+        );
+        assert_eq!(overrides.resolve(&H384::zero()), None);
+
+        let include_only = ProposerOverride {
+            include_builders: vec!["parallel".to_string()],
+            ..Default::default()
+        };
+        assert!(!include_only.runs_builder("mgp-ordering"));
+        assert!(include_only.runs_builder("parallel"));
+
+        // unknown builder
+        let unknown = ProposerOverrideConfig {
+            include_builders: vec!["nope".to_string()],
+            ..config.clone()
+        };
+        assert!(ProposerOverrides::from_config(&[unknown], &live_builders).is_err());
+        // nothing left to run
+        let nothing = ProposerOverrideConfig {
+            exclude_builders: live_builders.clone(),
+            ..config.clone()
+        };
+        assert!(ProposerOverrides::from_config(&[nothing], &live_builders).is_err());
+        // same proposer twice
+        assert!(ProposerOverrides::from_config(&[config.clone(), config], &live_builders).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+//! Signer reputation keeps track, per bundle signer, of how its orders behave:
+//! simulation failures (spam), inclusion in our final bids and the profit they contributed.
+//! Reputations are persisted in a sqlite db so they survive restarts and can be inspected/adjusted via the admin rpc.
+//! Like the error storage, recording is done via free functions that do nothing if the store was not spawned.
+
+use ahash::{HashMap, HashSet};
+use alloy_primitives::{Address, U256};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use sqlx::{
+    sqlite::SqliteConnectOptions, ConnectOptions, Connection, Executor, Row, SqliteConnection,
+};
+use std::{path::Path, str::FromStr, sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How often dirty reputations are written to the db.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref REPUTATION_STORE: Mutex<Option<Arc<SignerReputationStore>>> = Mutex::new(None);
+}
+
+fn reputation_store() -> Option<Arc<SignerReputationStore>> {
+    REPUTATION_STORE.lock().clone()
+}
+
+/// Raw counters we keep per signer. Rates and score are derived from them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerReputation {
+    pub simulated_orders: u64,
+    pub failed_simulations: u64,
+    /// Orders included in the last block we submitted for a slot.
+    pub included_orders: u64,
+    /// Sum of the coinbase profit of the included orders.
+    pub profit_contributed: U256,
+    /// Manual adjustment set by the operator, added to the computed score.
+    pub score_adjustment: f64,
+}
+
+impl SignerReputation {
+    /// Fraction of the simulated orders that made it into our final bids.
+    pub fn success_rate(&self) -> f64 {
+        if self.simulated_orders == 0 {
+            return 0.0;
+        }
+        self.included_orders as f64 / self.simulated_orders as f64
+    }
+
+    /// Fraction of the simulated orders that failed simulation.
+    pub fn spam_rate(&self) -> f64 {
+        if self.simulated_orders == 0 {
+            return 0.0;
+        }
+        self.failed_simulations as f64 / self.simulated_orders as f64
+    }
+
+    pub fn score(&self) -> f64 {
+        self.success_rate() - self.spam_rate() + self.score_adjustment
+    }
+}
+
+/// What we return on the admin rpc.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerReputationReport {
+    pub signer: Address,
+    #[serde(flatten)]
+    pub reputation: SignerReputation,
+    pub success_rate: f64,
+    pub spam_rate: f64,
+    pub score: f64,
+}
+
+impl SignerReputationReport {
+    fn new(signer: Address, reputation: SignerReputation) -> Self {
+        Self {
+            signer,
+            success_rate: reputation.success_rate(),
+            spam_rate: reputation.spam_rate(),
+            score: reputation.score(),
+            reputation,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SignerReputationStoreInner {
+    reputations: HashMap<Address, SignerReputation>,
+    /// Signers modified since the last flush to the db.
+    dirty: HashSet<Address>,
+}
+
+/// In memory reputations, periodically flushed to the db by the task created on [`spawn_signer_reputation_store`].
+#[derive(Debug, Default)]
+pub struct SignerReputationStore {
+    inner: Mutex<SignerReputationStoreInner>,
+}
+
+impl SignerReputationStore {
+    fn with_reputations(reputations: HashMap<Address, SignerReputation>) -> Self {
+        Self {
+            inner: Mutex::new(SignerReputationStoreInner {
+                reputations,
+                dirty: HashSet::default(),
+            }),
+        }
+    }
+
+    fn update(&self, signer: Address, f: impl FnOnce(&mut SignerReputation)) {
+        let mut inner = self.inner.lock();
+        f(inner.reputations.entry(signer).or_default());
+        inner.dirty.insert(signer);
+    }
+
+    pub fn record_simulation(&self, signer: Address, success: bool) {
+        self.update(signer, |reputation| {
+            reputation.simulated_orders += 1;
+            if !success {
+                reputation.failed_simulations += 1;
+            }
+        });
+    }
+
+    pub fn record_included_order(&self, signer: Address, profit: U256) {
+        self.update(signer, |reputation| {
+            reputation.included_orders += 1;
+            reputation.profit_contributed += profit;
+        });
+    }
+
+    pub fn set_score_adjustment(&self, signer: Address, score_adjustment: f64) {
+        self.update(signer, |reputation| {
+            reputation.score_adjustment = score_adjustment;
+        });
+    }
+
+    pub fn get(&self, signer: &Address) -> Option<SignerReputation> {
+        self.inner.lock().reputations.get(signer).cloned()
+    }
+
+    pub fn reports(&self) -> Vec<SignerReputationReport> {
+        let inner = self.inner.lock();
+        let mut reports: Vec<_> = inner
+            .reputations
+            .iter()
+            .map(|(signer, reputation)| SignerReputationReport::new(*signer, reputation.clone()))
+            .collect();
+        reports.sort_by(|a, b| b.score.total_cmp(&a.score));
+        reports
+    }
+
+    fn take_dirty(&self) -> Vec<(Address, SignerReputation)> {
+        let mut inner = self.inner.lock();
+        let dirty = std::mem::take(&mut inner.dirty);
+        dirty
+            .into_iter()
+            .filter_map(|signer| {
+                inner
+                    .reputations
+                    .get(&signer)
+                    .map(|reputation| (signer, reputation.clone()))
+            })
+            .collect()
+    }
+
+    /// Puts back entries we failed to write so they are retried on the next flush.
+    fn mark_dirty(&self, signers: impl IntoIterator<Item = Address>) {
+        self.inner.lock().dirty.extend(signers);
+    }
+}
+
+/// Opens (or creates) the reputation db at `db_path`, loads the reputations and spawns a task
+/// that flushes changes to the db until `global_cancel` is cancelled.
+/// The returned store is also used by [`record_simulation_result`] and [`record_included_orders`].
+pub async fn spawn_signer_reputation_store(
+    db_path: impl AsRef<Path>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<Arc<SignerReputationStore>> {
+    let mut storage = SignerReputationStorage::new_from_path(db_path).await?;
+    let reputations = storage.load_reputations().await?;
+    info!(
+        signers = reputations.len(),
+        "Loaded signer reputations from storage"
+    );
+    let store = Arc::new(SignerReputationStore::with_reputations(reputations));
+    *REPUTATION_STORE.lock() = Some(store.clone());
+
+    let flushed_store = store.clone();
+    tokio::spawn(async move {
+        loop {
+            let cancelled = tokio::select! {
+                _ = global_cancel.cancelled() => true,
+                _ = tokio::time::sleep(FLUSH_INTERVAL) => false,
+            };
+            let dirty = flushed_store.take_dirty();
+            if !dirty.is_empty() {
+                if let Err(err) = storage.write_reputations(&dirty).await {
+                    warn!(?err, "Error writing signer reputations to storage");
+                    flushed_store.mark_dirty(dirty.into_iter().map(|(signer, _)| signer));
+                }
+            }
+            if cancelled {
+                return;
+            }
+        }
+    });
+    Ok(store)
+}
+
+/// Call after simulating an order with a signer.
+pub fn record_simulation_result(signer: Address, success: bool) {
+    if let Some(store) = reputation_store() {
+        store.record_simulation(signer, success);
+    }
+}
+
+/// Call with the (signer, coinbase profit) of the orders included in the last block we submitted for a slot.
+pub fn record_included_orders(included_orders: &[(Address, U256)]) {
+    if let Some(store) = reputation_store() {
+        for (signer, profit) in included_orders {
+            store.record_included_order(*signer, *profit);
+        }
+    }
+}
+
+fn invalid_params_error(err: impl std::fmt::Display) -> ErrorObject<'static> {
+    ErrorObject::owned(-32602, err.to_string(), None::<()>)
+}
+
+/// Admin methods to inspect and adjust reputations:
+/// - admin_getSignerReputation(signer)
+/// - admin_listSignerReputations()
+/// - admin_setSignerReputationAdjustment(signer, score_adjustment)
+pub fn signer_reputation_rpc_module(
+    store: Arc<SignerReputationStore>,
+) -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+
+    let store_clone = store.clone();
+    module.register_method("admin_getSignerReputation", move |params, _| {
+        let signer: Address = params.one().map_err(invalid_params_error)?;
+        Ok::<_, ErrorObject<'static>>(
+            store_clone
+                .get(&signer)
+                .map(|reputation| SignerReputationReport::new(signer, reputation)),
+        )
+    })?;
+
+    let store_clone = store.clone();
+    module.register_method("admin_listSignerReputations", move |_, _| {
+        Ok::<_, ErrorObject<'static>>(store_clone.reports())
+    })?;
+
+    module.register_method("admin_setSignerReputationAdjustment", move |params, _| {
+        let (signer, score_adjustment): (Address, f64) =
+            params.parse().map_err(invalid_params_error)?;
+        if !score_adjustment.is_finite() {
+            return Err(invalid_params_error("score adjustment must be finite"));
+        }
+        store.set_score_adjustment(signer, score_adjustment);
+        info!(?signer, score_adjustment, "Signer reputation adjusted");
+        Ok(SignerReputationReport::new(
+            signer,
+            store.get(&signer).unwrap_or_default(),
+        ))
+    })?;
+
+    Ok(module)
+}
+
+#[derive(Debug)]
+struct SignerReputationStorage {
+    conn: SqliteConnection,
+}
+
+impl SignerReputationStorage {
+    async fn new_from_path(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let mut res = Self {
+            conn: SqliteConnectOptions::new()
+                .filename(path)
+                .create_if_missing(true)
+                .connect()
+                .await?,
+        };
+        res.create_tables().await?;
+        Ok(res)
+    }
+
+    #[allow(dead_code)]
+    async fn new_from_memory() -> eyre::Result<Self> {
+        let mut res = Self {
+            conn: SqliteConnectOptions::new().connect().await?,
+        };
+        res.create_tables().await?;
+        Ok(res)
+    }
+
+    async fn create_tables(&mut self) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                r#"
+            CREATE TABLE IF NOT EXISTS signer_reputations (
+                signer TEXT PRIMARY KEY NOT NULL,
+                simulated_orders INTEGER NOT NULL,
+                failed_simulations INTEGER NOT NULL,
+                included_orders INTEGER NOT NULL,
+                profit_contributed TEXT NOT NULL,
+                score_adjustment REAL NOT NULL
+            );
+            "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_reputations(&mut self) -> eyre::Result<HashMap<Address, SignerReputation>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT signer, simulated_orders, failed_simulations, included_orders, profit_contributed, score_adjustment
+            FROM signer_reputations
+            "#,
+        )
+        .fetch_all(&mut self.conn)
+        .await?;
+
+        let mut res = HashMap::default();
+        for row in rows {
+            let signer = Address::from_str(&row.try_get::<String, _>("signer")?)?;
+            let reputation = SignerReputation {
+                simulated_orders: row.try_get::<i64, _>("simulated_orders")? as u64,
+                failed_simulations: row.try_get::<i64, _>("failed_simulations")? as u64,
+                included_orders: row.try_get::<i64, _>("included_orders")? as u64,
+                profit_contributed: U256::from_str(
+                    &row.try_get::<String, _>("profit_contributed")?,
+                )?,
+                score_adjustment: row.try_get("score_adjustment")?,
+            };
+            res.insert(signer, reputation);
+        }
+        Ok(res)
+    }
+
+    async fn write_reputations(
+        &mut self,
+        reputations: &[(Address, SignerReputation)],
+    ) -> eyre::Result<()> {
+        let mut tx = self.conn.begin().await?;
+        for (signer, reputation) in reputations {
+            sqlx::query(
+                r#"
+                INSERT INTO signer_reputations (signer, simulated_orders, failed_simulations, included_orders, profit_contributed, score_adjustment)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(signer) DO UPDATE SET
+                    simulated_orders = excluded.simulated_orders,
+                    failed_simulations = excluded.failed_simulations,
+                    included_orders = excluded.included_orders,
+                    profit_contributed = excluded.profit_contributed,
+                    score_adjustment = excluded.score_adjustment
+                "#,
+            )
+            .bind(signer.to_string())
+            .bind(reputation.simulated_orders as i64)
+            .bind(reputation.failed_simulations as i64)
+            .bind(reputation.included_orders as i64)
+            .bind(reputation.profit_contributed.to_string())
+            .bind(reputation.score_adjustment)
+            .execute(tx.as_mut())
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reputation_rates() {
+        let store = SignerReputationStore::default();
+        let signer = Address::random();
+        store.record_simulation(signer, true);
+        store.record_simulation(signer, true);
+        store.record_simulation(signer, false);
+        store.record_simulation(signer, true);
+        store.record_included_order(signer, U256::from(10));
+        store.record_included_order(signer, U256::from(5));
+
+        let reputation = store.get(&signer).unwrap();
+        assert_eq!(reputation.simulated_orders, 4);
+        assert_eq!(reputation.profit_contributed, U256::from(15));
+        assert_eq!(reputation.success_rate(), 0.5);
+        assert_eq!(reputation.spam_rate(), 0.25);
+        assert_eq!(reputation.score(), 0.25);
+
+        store.set_score_adjustment(signer, 1.0);
+        assert_eq!(store.get(&signer).unwrap().score(), 1.25);
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        let store = SignerReputationStore::default();
+        let signer = Address::random();
+        store.record_simulation(signer, false);
+        assert_eq!(store.take_dirty().len(), 1);
+        assert!(store.take_dirty().is_empty());
+        store.mark_dirty([signer]);
+        assert_eq!(store.take_dirty().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reputation_storage_roundtrip() {
+        let mut storage = SignerReputationStorage::new_from_memory().await.unwrap();
+        let signer = Address::random();
+        let mut reputation = SignerReputation {
+            simulated_orders: 10,
+            failed_simulations: 2,
+            included_orders: 3,
+            profit_contributed: U256::from(1_000_000_000_000_000_000u128),
+            score_adjustment: -0.5,
+        };
+        storage
+            .write_reputations(&[(signer, reputation.clone())])
+            .await
+            .unwrap();
+        // overwrite
+        reputation.included_orders = 4;
+        storage
+            .write_reputations(&[(signer, reputation.clone())])
+            .await
+            .unwrap();
+
+        let loaded = storage.load_reputations().await.unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(&signer), Some(&reputation));
+    }
+}
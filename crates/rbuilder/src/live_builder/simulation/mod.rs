@@ -1,12 +1,19 @@
+pub mod shared_workers;
 pub mod sim_worker;
 mod simulation_job;
+pub mod tenant_sandbox;
 
 use crate::{
     building::{
+        order_validity::OrderValidityContext,
         sim::{SimTree, SimulatedResult, SimulationRequest},
+        sim_queue::SimQueueWeights,
         BlockBuildingContext,
     },
-    live_builder::order_input::orderpool::OrdersForBlock,
+    live_builder::{
+        order_input::{orderpool::OrdersForBlock, tenants::TenantRegistry},
+        slot_resource_report::SlotResourceCounters,
+    },
     primitives::{OrderId, SimulatedOrder},
     utils::gen_uid,
 };
@@ -14,17 +21,23 @@ use ahash::HashMap;
 use parking_lot::Mutex;
 use reth_provider::StateProviderFactory;
 use simulation_job::SimulationJob;
-use std::sync::Arc;
-use tokio::{sync::mpsc, task::JoinHandle};
+use std::{path::Path, sync::Arc};
+use tenant_sandbox::SandboxedQueues;
+use tokio::{
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{info_span, Instrument};
 
 #[derive(Debug)]
 pub struct SlotOrderSimResults {
     pub orders: mpsc::Receiver<SimulatedOrderCommand>,
+    /// Id of the SimulationContext of the job in [`CurrentSimulationContexts`].
+    pub context_id: BlockContextId,
 }
 
-type BlockContextId = u64;
+pub type BlockContextId = u64;
 
 /// Struct representing the need of order simulation for a particular block.
 #[derive(Debug, Clone)]
@@ -32,8 +45,14 @@ pub struct SimulationContext {
     pub block_ctx: BlockBuildingContext,
     /// Simulation requests come in through this channel.
     pub requests: flume::Receiver<SimulationRequest>,
+    /// Requests of the sandboxed tenants by tenant name (see [`tenant_sandbox`]).
+    pub tenant_requests: HashMap<String, flume::Receiver<SimulationRequest>>,
     /// Simulation results go out through this channel.
     pub results: mpsc::Sender<SimulatedResult>,
+    /// Notified by the workers every time they take a request (shared or tenant) so the job refills the queues.
+    pub request_taken: Arc<Notify>,
+    /// Resources used by the slot, the workers count their simulations here.
+    pub resource_counters: Arc<SlotResourceCounters>,
 }
 
 /// All active SimulationContexts
@@ -42,7 +61,17 @@ pub struct CurrentSimulationContexts {
     pub contexts: HashMap<BlockContextId, SimulationContext>,
 }
 
+impl CurrentSimulationContexts {
+    /// Removes the context of a job so workers stop serving it even if its SimulationJob is still alive.
+    /// Contexts of other jobs for the same block (eg: a new session after a reorg) are untouched.
+    /// Returns true if it was still there.
+    pub fn release_context(&mut self, context_id: BlockContextId) -> bool {
+        self.contexts.remove(&context_id).is_some()
+    }
+}
+
 /// Struct that creates several [`sim_worker::run_sim_worker`] threads to allow concurrent simulation for the same block.
+/// It can also create shared workers (see [`shared_workers`]) that move between simulation and building when needed.
 /// Usage:
 /// 1 Create a single instance via [`OrderSimulationPool::new`] which receives the input.
 /// 2 For each block call [`OrderSimulationPool::spawn_simulation_job`] which will spawn a task to run the simulations.
@@ -55,6 +84,9 @@ pub struct OrderSimulationPool<P> {
     running_tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
     current_contexts: Arc<Mutex<CurrentSimulationContexts>>,
     worker_threads: Vec<std::thread::JoinHandle<()>>,
+    sim_queue_weights: SimQueueWeights,
+    /// Tenants with simulation sandboxes, see [`OrderSimulationPool::with_tenant_sandboxes`].
+    tenants: Arc<TenantRegistry>,
 }
 
 /// Result of a simulation.
@@ -70,7 +102,12 @@ impl<P> OrderSimulationPool<P>
 where
     P: StateProviderFactory + Clone + 'static,
 {
-    pub fn new(provider: P, num_workers: usize, global_cancellation: CancellationToken) -> Self {
+    pub fn new(
+        provider: P,
+        num_workers: usize,
+        num_shared_workers: usize,
+        global_cancellation: CancellationToken,
+    ) -> Self {
         let mut result = Self {
             provider,
             running_tasks: Arc::new(Mutex::new(Vec::new())),
@@ -78,6 +115,8 @@ where
                 contexts: HashMap::default(),
             })),
             worker_threads: Vec::new(),
+            sim_queue_weights: SimQueueWeights::default(),
+            tenants: Default::default(),
         };
         for i in 0..num_workers {
             let ctx = Arc::clone(&result.current_contexts);
@@ -91,9 +130,52 @@ where
                 .expect("Failed to start sim worker thread");
             result.worker_threads.push(handle);
         }
+        if num_shared_workers > 0 {
+            result
+                .worker_threads
+                .extend(shared_workers::spawn_shared_workers(
+                    num_shared_workers,
+                    num_workers,
+                    Arc::clone(&result.current_contexts),
+                    result.provider.clone(),
+                    global_cancellation,
+                ));
+        }
         result
     }
 
+    /// Weights of the order classes in the simulation queue of every job (see [`crate::building::sim_queue`]).
+    pub fn with_sim_queue_weights(self, sim_queue_weights: SimQueueWeights) -> Self {
+        Self {
+            sim_queue_weights,
+            ..self
+        }
+    }
+
+    /// Spawns the dedicated workers of the tenants with a sandbox, their orders are only simulated there.
+    /// If cgroup_root is set the workers of each tenant are CPU capped on their own cgroup.
+    pub fn with_tenant_sandboxes(
+        mut self,
+        tenants: Arc<TenantRegistry>,
+        cgroup_root: Option<&Path>,
+        global_cancellation: CancellationToken,
+    ) -> Self {
+        let workers = tenant_sandbox::spawn_tenant_workers(
+            &tenants,
+            cgroup_root,
+            self.worker_threads.len(),
+            Arc::clone(&self.current_contexts),
+            self.provider.clone(),
+            global_cancellation,
+        );
+        self.worker_threads.extend(workers);
+        Self { tenants, ..self }
+    }
+
+    pub fn current_contexts(&self) -> Arc<Mutex<CurrentSimulationContexts>> {
+        Arc::clone(&self.current_contexts)
+    }
+
     /// Prepares the context to run a SimulationJob and spawns a task with it.
     /// The returned SlotOrderSimResults can be polled to the simulation stream.
     /// IMPORTANT: By calling spawn_simulation_job we lock some worker threads on the given block.
@@ -104,26 +186,35 @@ where
         ctx: BlockBuildingContext,
         input: OrdersForBlock,
         block_cancellation: CancellationToken,
+        resource_counters: Arc<SlotResourceCounters>,
     ) -> SlotOrderSimResults {
         let (slot_sim_results_sender, slot_sim_results_receiver) = mpsc::channel(10_000);
 
         let provider = self.provider.clone();
         let current_contexts = Arc::clone(&self.current_contexts);
+        let sim_queue_weights = self.sim_queue_weights;
+        let (sandboxed_queues, tenant_requests) = SandboxedQueues::new(Arc::clone(&self.tenants));
         let block_context: BlockContextId = gen_uid();
         let span = info_span!("sim_ctx", block = ctx.block_env.number.to::<u64>(), parent = ?ctx.attributes.parent);
 
         let handle = tokio::spawn(
             async move {
-                let sim_tree = SimTree::new(provider, ctx.attributes.parent);
+                let sim_tree = SimTree::new(provider, ctx.attributes.parent)
+                    .with_validity_check(OrderValidityContext::new(&ctx))
+                    .with_queue_weights(sim_queue_weights);
                 let new_order_sub = input.new_order_sub;
                 let (sim_req_sender, sim_req_receiver) = flume::unbounded();
                 let (sim_results_sender, sim_results_receiver) = mpsc::channel(1024);
+                let request_taken = Arc::new(Notify::new());
                 {
                     let mut contexts = current_contexts.lock();
                     let sim_context = SimulationContext {
                         block_ctx: ctx,
                         requests: sim_req_receiver,
+                        tenant_requests,
                         results: sim_results_sender,
+                        request_taken: Arc::clone(&request_taken),
+                        resource_counters: Arc::clone(&resource_counters),
                     };
                     contexts.contexts.insert(block_context, sim_context);
                 }
@@ -131,9 +222,12 @@ where
                     block_cancellation,
                     new_order_sub,
                     sim_req_sender,
+                    sandboxed_queues,
+                    request_taken,
                     sim_results_receiver,
                     slot_sim_results_sender,
                     sim_tree,
+                    resource_counters,
                 );
 
                 simulation_job.run().await;
@@ -155,6 +249,7 @@ where
 
         SlotOrderSimResults {
             orders: slot_sim_results_receiver,
+            context_id: block_context,
         }
     }
 }
@@ -180,7 +275,7 @@ mod tests {
             ProviderFactoryReopener::new_from_existing(test_context.provider_factory().clone())
                 .unwrap();
 
-        let sim_pool = OrderSimulationPool::new(provider_factory_reopener, 4, cancel.clone());
+        let sim_pool = OrderSimulationPool::new(provider_factory_reopener, 4, 0, cancel.clone());
         let (order_sender, order_receiver) = mpsc::unbounded_channel();
         let orders_for_block = OrdersForBlock {
             new_order_sub: order_receiver,
@@ -190,6 +285,7 @@ mod tests {
             test_context.block_building_context().clone(),
             orders_for_block,
             cancel.clone(),
+            Default::default(),
         );
 
         // Create a simple tx that sends to coinbase 5 wei.
@@ -216,4 +312,39 @@ mod tests {
             };
         }
     }
+
+    #[tokio::test]
+    async fn test_release_context() {
+        let test_context = TestChainState::new(BlockArgs::default().number(11)).unwrap();
+        let sim_pool = OrderSimulationPool::new(
+            ProviderFactoryReopener::new_from_existing(test_context.provider_factory().clone())
+                .unwrap(),
+            0,
+            0,
+            CancellationToken::new(),
+        );
+        // a stuck session and the one replacing it, both for the same block
+        let spawn_job = || {
+            let (order_sender, order_receiver) = mpsc::unbounded_channel();
+            let results = sim_pool.spawn_simulation_job(
+                test_context.block_building_context().clone(),
+                OrdersForBlock {
+                    new_order_sub: order_receiver,
+                },
+                CancellationToken::new(),
+                Default::default(),
+            );
+            // keep the input open so the jobs keep running
+            (order_sender, results)
+        };
+        let (_stuck_orders, stuck) = spawn_job();
+        let (_new_orders, new) = spawn_job();
+        let contexts = sim_pool.current_contexts();
+        while contexts.lock().contexts.len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert!(contexts.lock().release_context(stuck.context_id));
+        assert!(!contexts.lock().release_context(stuck.context_id));
+        assert!(contexts.lock().contexts.contains_key(&new.context_id));
+    }
 }
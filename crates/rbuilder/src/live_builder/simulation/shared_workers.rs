@@ -0,0 +1,344 @@
+//! Shared workers move between order simulation and block building depending on where the demand is.
+//! Early in the slot most of the work is simulating incoming orders, late in the slot most of the work is building/sealing
+//! so instead of giving each stage a static pool we keep a set of shared workers that are periodically rebalanced using
+//! the queue depths of both stages and the time left until the slot.
+//!
+//! Building stages that want to use shared workers register their work via [`register_building_work`].
+use super::{sim_worker, CurrentSimulationContexts};
+use crate::utils::clock::Clock;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use reth_provider::StateProviderFactory;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::trace;
+
+/// How often we recompute the split between stages.
+const REBALANCE_INTERVAL: Duration = Duration::from_millis(20);
+/// Sleep for a building worker that found nothing to do.
+const IDLE_BUILDING_WORKER_SLEEP: Duration = Duration::from_millis(1);
+
+lazy_static! {
+    static ref SHARED_WORKER_POOL: Mutex<Option<Arc<SharedWorkerPool>>> = Mutex::new(None);
+}
+
+/// Building work that can be executed by shared workers.
+pub trait BuildingWork: Send + Sync {
+    /// Tasks waiting to be executed.
+    fn pending_tasks(&self) -> usize;
+    /// Executes a single pending task. Returns false if there was nothing to execute.
+    fn run_task(&self) -> bool;
+    /// Finished work is dropped from the pool.
+    fn is_finished(&self) -> bool;
+}
+
+/// Makes `work` available to the shared workers (if there are any).
+pub fn register_building_work(work: Arc<dyn BuildingWork>) {
+    if let Some(pool) = SHARED_WORKER_POOL.lock().clone() {
+        pool.building_work.lock().push(work);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerStage {
+    Simulation,
+    Building,
+}
+
+/// Decides how many of the shared workers go to each stage.
+#[derive(Debug, Clone)]
+pub struct RebalancePolicy {
+    pub shared_workers: usize,
+    /// When we are closer than this to the slot timestamp building tasks are considered more urgent.
+    pub late_slot_window: Duration,
+    /// On the late slot window each pending building task counts as this many simulations.
+    pub late_slot_building_weight: usize,
+}
+
+impl RebalancePolicy {
+    pub fn new(shared_workers: usize) -> Self {
+        Self {
+            shared_workers,
+            late_slot_window: Duration::from_secs(2),
+            late_slot_building_weight: 4,
+        }
+    }
+
+    /// Number of shared workers that should be simulating, the rest should be building.
+    /// time_left is None when there is no slot in progress.
+    pub fn simulation_workers(
+        &self,
+        sim_queue_depth: usize,
+        building_queue_depth: usize,
+        time_left: Option<Duration>,
+    ) -> usize {
+        if building_queue_depth == 0 {
+            return self.shared_workers;
+        }
+        if sim_queue_depth == 0 {
+            return 0;
+        }
+        let building_weight = match time_left {
+            Some(time_left) if time_left <= self.late_slot_window => self.late_slot_building_weight,
+            _ => 1,
+        };
+        let sim_demand = sim_queue_depth as f64;
+        let building_demand = building_queue_depth as f64 * building_weight as f64;
+        let sim_workers =
+            (self.shared_workers as f64 * sim_demand / (sim_demand + building_demand)).round();
+        (sim_workers as usize).min(self.shared_workers)
+    }
+}
+
+pub struct SharedWorkerPool {
+    policy: RebalancePolicy,
+    /// Shared workers with index < simulation_workers simulate, the rest build.
+    simulation_workers: AtomicUsize,
+    building_work: Mutex<Vec<Arc<dyn BuildingWork>>>,
+}
+
+impl std::fmt::Debug for SharedWorkerPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedWorkerPool")
+            .field("policy", &self.policy)
+            .field("simulation_workers", &self.simulation_workers)
+            .field("building_work", &self.building_work.lock().len())
+            .finish()
+    }
+}
+
+impl SharedWorkerPool {
+    pub fn new(policy: RebalancePolicy) -> Self {
+        Self {
+            simulation_workers: AtomicUsize::new(policy.shared_workers),
+            policy,
+            building_work: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn stage(&self, shared_worker_idx: usize) -> WorkerStage {
+        if shared_worker_idx < self.simulation_workers.load(Ordering::Relaxed) {
+            WorkerStage::Simulation
+        } else {
+            WorkerStage::Building
+        }
+    }
+
+    fn rebalance(&self, sim_contexts: &Mutex<CurrentSimulationContexts>) {
+        let (sim_queue_depth, slot_timestamp) = {
+            let contexts = sim_contexts.lock();
+            let depth = contexts
+                .contexts
+                .values()
+                .map(|ctx| ctx.requests.len())
+                .sum::<usize>();
+            let slot_timestamp = contexts
+                .contexts
+                .values()
+                .map(|ctx| (ctx.block_ctx.timestamp(), ctx.block_ctx.clock.clone()))
+                .min_by_key(|(timestamp, _)| *timestamp);
+            (depth, slot_timestamp)
+        };
+        let building_queue_depth = {
+            let mut building_work = self.building_work.lock();
+            building_work.retain(|work| !work.is_finished());
+            building_work
+                .iter()
+                .map(|work| work.pending_tasks())
+                .sum::<usize>()
+        };
+        let time_left = slot_timestamp.map(|(timestamp, clock)| clock.duration_until(timestamp));
+        let simulation_workers =
+            self.policy
+                .simulation_workers(sim_queue_depth, building_queue_depth, time_left);
+        let prev = self
+            .simulation_workers
+            .swap(simulation_workers, Ordering::Relaxed);
+        if prev != simulation_workers {
+            trace!(
+                sim_queue_depth,
+                building_queue_depth,
+                ?time_left,
+                simulation_workers,
+                "Rebalanced shared workers"
+            );
+        }
+    }
+
+    /// Runs a single task from any registered building work. Returns false if there was nothing to do.
+    fn run_building_task(&self) -> bool {
+        let building_work = self.building_work.lock().clone();
+        building_work
+            .iter()
+            .any(|work| !work.is_finished() && work.run_task())
+    }
+}
+
+/// Creates the global [`SharedWorkerPool`] and spawns the shared workers and the rebalancing thread.
+/// Shared workers are identified on the simulation telemetry starting from first_worker_id.
+pub fn spawn_shared_workers<P>(
+    shared_workers: usize,
+    first_worker_id: usize,
+    sim_contexts: Arc<Mutex<CurrentSimulationContexts>>,
+    provider: P,
+    global_cancellation: CancellationToken,
+) -> Vec<std::thread::JoinHandle<()>>
+where
+    P: StateProviderFactory + Clone + 'static,
+{
+    let pool = Arc::new(SharedWorkerPool::new(RebalancePolicy::new(shared_workers)));
+    *SHARED_WORKER_POOL.lock() = Some(pool.clone());
+
+    let mut handles = Vec::new();
+    {
+        let pool = pool.clone();
+        let sim_contexts = sim_contexts.clone();
+        let cancel = global_cancellation.clone();
+        handles.push(
+            std::thread::Builder::new()
+                .name("shared_worker_rebalancer".to_string())
+                .spawn(move || {
+                    while !cancel.is_cancelled() {
+                        pool.rebalance(&sim_contexts);
+                        sleep(REBALANCE_INTERVAL);
+                    }
+                })
+                .expect("Failed to start shared worker rebalancer thread"),
+        );
+    }
+    for idx in 0..shared_workers {
+        let pool = pool.clone();
+        let sim_contexts = sim_contexts.clone();
+        let provider = provider.clone();
+        let cancel = global_cancellation.clone();
+        handles.push(
+            std::thread::Builder::new()
+                .name(format!("shared_thread:{}", idx))
+                .spawn(move || {
+                    run_shared_worker(
+                        idx,
+                        first_worker_id + idx,
+                        &pool,
+                        &sim_contexts,
+                        &provider,
+                        &cancel,
+                    );
+                })
+                .expect("Failed to start shared worker thread"),
+        );
+    }
+    handles
+}
+
+fn run_shared_worker<P>(
+    shared_worker_idx: usize,
+    worker_id: usize,
+    pool: &SharedWorkerPool,
+    sim_contexts: &Arc<Mutex<CurrentSimulationContexts>>,
+    provider: &P,
+    global_cancellation: &CancellationToken,
+) where
+    P: StateProviderFactory,
+{
+    while !global_cancellation.is_cancelled() {
+        match pool.stage(shared_worker_idx) {
+            WorkerStage::Simulation => sim_worker::run_sim_worker_while(
+                worker_id,
+                sim_contexts,
+                provider,
+                global_cancellation,
+                &sim_worker::SimWorkerQueue::Shared,
+                || pool.stage(shared_worker_idx) == WorkerStage::Simulation,
+            ),
+            WorkerStage::Building => {
+                if !pool.run_building_task() {
+                    sleep(IDLE_BUILDING_WORKER_SLEEP);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn test_simulation_workers() {
+        let policy = RebalancePolicy::new(4);
+        let early = Some(Duration::from_secs(10));
+        let late = Some(Duration::from_secs(1));
+
+        // nothing to build, everybody simulates
+        assert_eq!(policy.simulation_workers(0, 0, early), 4);
+        assert_eq!(policy.simulation_workers(100, 0, late), 4);
+        // nothing to simulate, everybody builds
+        assert_eq!(policy.simulation_workers(0, 10, early), 0);
+        // proportional split
+        assert_eq!(policy.simulation_workers(30, 10, early), 3);
+        assert_eq!(policy.simulation_workers(10, 10, None), 2);
+        // late in the slot building gets priority
+        assert_eq!(policy.simulation_workers(30, 10, late), 2);
+        assert_eq!(policy.simulation_workers(10, 10, late), 1);
+        assert_eq!(policy.simulation_workers(10, 10, Some(Duration::ZERO)), 1);
+    }
+
+    struct TestWork {
+        pending: AtomicUsize,
+        finished: AtomicBool,
+    }
+
+    impl BuildingWork for TestWork {
+        fn pending_tasks(&self) -> usize {
+            self.pending.load(Ordering::Relaxed)
+        }
+
+        fn run_task(&self) -> bool {
+            self.pending
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |p| p.checked_sub(1))
+                .is_ok()
+        }
+
+        fn is_finished(&self) -> bool {
+            self.finished.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let pool = SharedWorkerPool::new(RebalancePolicy::new(2));
+        let sim_contexts = Mutex::new(CurrentSimulationContexts {
+            contexts: Default::default(),
+        });
+        let work = Arc::new(TestWork {
+            pending: AtomicUsize::new(1),
+            finished: AtomicBool::new(false),
+        });
+        pool.building_work.lock().push(work.clone());
+
+        // no simulations pending, all shared workers go to building
+        pool.rebalance(&sim_contexts);
+        assert_eq!(pool.stage(0), WorkerStage::Building);
+        assert_eq!(pool.stage(1), WorkerStage::Building);
+
+        assert!(pool.run_building_task());
+        assert!(!pool.run_building_task());
+
+        // no building pending, back to simulation
+        pool.rebalance(&sim_contexts);
+        assert_eq!(pool.stage(0), WorkerStage::Simulation);
+        assert_eq!(pool.stage(1), WorkerStage::Simulation);
+
+        work.finished.store(true, Ordering::Relaxed);
+        pool.rebalance(&sim_contexts);
+        assert!(pool.building_work.lock().is_empty());
+    }
+}
@@ -3,10 +3,16 @@ use crate::{
         sim::{NonceKey, OrderSimResult, SimulatedResult},
         simulate_order, BlockState,
     },
-    live_builder::simulation::CurrentSimulationContexts,
+    live_builder::{
+        fault_injection::{self, FaultPoint},
+        signer_reputation,
+        simulation::CurrentSimulationContexts,
+        state_access_heatmap::record_state_access,
+    },
     telemetry,
     telemetry::add_sim_thread_utilisation_timings,
 };
+use alloy_primitives::U256;
 use parking_lot::Mutex;
 use reth::revm::cached::CachedReads;
 use reth_provider::StateProviderFactory;
@@ -18,6 +24,18 @@ use std::{
 use tokio_util::sync::CancellationToken;
 use tracing::error;
 
+/// Max time we block waiting for a simulation request before checking if we should keep simulating.
+const REQUEST_POLL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Request queue a worker serves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimWorkerQueue {
+    /// SimulationContext::requests.
+    Shared,
+    /// SimulationContext::tenant_requests of the tenant (see [`super::tenant_sandbox`]).
+    Tenant(String),
+}
+
 /// Function that continuously looks for a SimulationContext on ctx and when it finds one it polls its "request for simulation" channel (SimulationContext::requests).
 /// When the channel closes it goes back to waiting for a new SimulationContext.
 /// It's blocking so it's expected to run in its own thread.
@@ -28,28 +46,80 @@ pub fn run_sim_worker<P>(
     global_cancellation: CancellationToken,
 ) where
     P: StateProviderFactory,
+{
+    run_sim_worker_while(
+        worker_id,
+        &ctx,
+        &provider,
+        &global_cancellation,
+        &SimWorkerQueue::Shared,
+        || true,
+    )
+}
+
+/// Same as [`run_sim_worker`] but returns as soon as keep_simulating returns false (checked between simulations).
+pub fn run_sim_worker_while<P>(
+    worker_id: usize,
+    ctx: &Arc<Mutex<CurrentSimulationContexts>>,
+    provider: &P,
+    global_cancellation: &CancellationToken,
+    queue: &SimWorkerQueue,
+    keep_simulating: impl Fn() -> bool,
+) where
+    P: StateProviderFactory,
 {
     loop {
-        if global_cancellation.is_cancelled() {
+        if global_cancellation.is_cancelled() || !keep_simulating() {
             return;
         }
-        let current_sim_context = loop {
+        let (current_sim_context_id, current_sim_context) = loop {
             let next_ctx = {
                 let ctxs = ctx.lock();
-                ctxs.contexts.iter().next().map(|(_, c)| c.clone())
+                ctxs.contexts.iter().next().map(|(id, c)| (*id, c.clone()))
             };
             // @Perf chose random context so its more fair when we have 2 instead of 1
             if let Some(ctx) = next_ctx {
                 break ctx;
+            } else if !keep_simulating() {
+                return;
             } else {
                 // contexts are created for a duration of the slot so this is not a problem
                 sleep(Duration::from_millis(50));
             }
         };
 
+        let requests = match queue {
+            SimWorkerQueue::Shared => current_sim_context.requests.clone(),
+            SimWorkerQueue::Tenant(tenant) => {
+                match current_sim_context.tenant_requests.get(tenant) {
+                    Some(requests) => requests.clone(),
+                    None => {
+                        // shouldn't happen, the pool creates the queues of all its sandboxes
+                        sleep(REQUEST_POLL_TIMEOUT);
+                        continue;
+                    }
+                }
+            }
+        };
+
         let mut cached_reads = CachedReads::default();
         let mut last_sim_finished = Instant::now();
-        while let Ok(task) = current_sim_context.requests.recv() {
+        loop {
+            if !keep_simulating() {
+                return;
+            }
+            let task = match requests.recv_timeout(REQUEST_POLL_TIMEOUT) {
+                Ok(task) => task,
+                Err(flume::RecvTimeoutError::Timeout) => {
+                    // Context released by the session reaper while its job is stuck.
+                    if !ctx.lock().contexts.contains_key(&current_sim_context_id) {
+                        break;
+                    }
+                    continue;
+                }
+                Err(flume::RecvTimeoutError::Disconnected) => break,
+            };
+            current_sim_context.request_taken.notify_one();
             let sim_thread_wait_time = last_sim_finished.elapsed();
             let sim_start = Instant::now();
 
@@ -66,6 +136,8 @@ pub fn run_sim_worker<P>(
             };
             let start_time = Instant::now();
             let mut block_state = BlockState::new(state_provider).with_cached_reads(cached_reads);
+            let order_signer = task.order.signer();
+            let order_id = task.order.id();
             let sim_result = simulate_order(
                 task.parents.clone(),
                 task.order,
@@ -75,7 +147,15 @@ pub fn run_sim_worker<P>(
             match sim_result {
                 Ok(sim_result) => {
                     let sim_ok = match sim_result.result {
-                        OrderSimResult::Success(simulated_order, nonces_after) => {
+                        OrderSimResult::Success(mut simulated_order, nonces_after) => {
+                            if fault_injection::triggered(FaultPoint::CorruptSimulation).is_some() {
+                                let sim_value = &mut simulated_order.sim_value;
+                                sim_value.coinbase_profit *= U256::from(10);
+                                sim_value.mev_gas_price *= U256::from(10);
+                            }
+                            if let Some(used_state_trace) = &simulated_order.used_state_trace {
+                                record_state_access(used_state_trace);
+                            }
                             let result = SimulatedResult {
                                 id: task.id,
                                 simulated_order,
@@ -92,9 +172,20 @@ pub fn run_sim_worker<P>(
                                 .unwrap_or_default();
                             true
                         }
-                        OrderSimResult::Failed(_) => false,
+                        OrderSimResult::Failed(err) => {
+                            telemetry::record_order_drop(
+                                order_id,
+                                telemetry::OrderDropStage::Simulation,
+                                err.reason(),
+                            );
+                            false
+                        }
                     };
                     telemetry::inc_simulated_orders(sim_ok);
+                    current_sim_context.resource_counters.record_simulation();
+                    if let Some(signer) = order_signer {
+                        signer_reputation::record_simulation_result(signer, sim_ok);
+                    }
                     telemetry::inc_simulation_gas_used(sim_result.gas_used);
                 }
                 Err(err) => {
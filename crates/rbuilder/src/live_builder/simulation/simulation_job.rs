@@ -2,17 +2,32 @@ use std::fmt;
 
 use crate::{
     building::sim::{SimTree, SimulatedResult, SimulationRequest},
-    live_builder::order_input::order_sink::OrderPoolCommand,
+    live_builder::{
+        order_input::{
+            order_sink::OrderPoolCommand,
+            tenants::{tenant_registry, TenantSimulationBudget},
+        },
+        slot_resource_report::SlotResourceCounters,
+    },
     primitives::{Order, OrderId},
+    telemetry::{
+        inc_order_validity_rejections, inc_tenant_orders, record_order_drop, OrderDropStage,
+    },
 };
 use ahash::HashSet;
 use alloy_primitives::utils::format_ether;
 use reth_provider::StateProviderFactory;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn};
 
-use super::SimulatedOrderCommand;
+use super::{tenant_sandbox::SandboxedQueues, SimulatedOrderCommand};
+
+/// Requests waiting in the sim workers channel (and in each sandboxed tenant channel). The rest wait in the SimTree
+/// where they are popped fairly between order classes (see [`crate::building::sim_queue`]), a long channel would be a
+/// FIFO in front of it.
+const MAX_QUEUED_SIM_REQUESTS: usize = 64;
 
 /// Struct that continuously simulates orders.
 /// Create and call run()
@@ -31,6 +46,11 @@ pub struct SimulationJob<P> {
     new_order_sub: mpsc::UnboundedReceiver<OrderPoolCommand>,
     /// Here we send requests to the simulator pool
     sim_req_sender: flume::Sender<SimulationRequest>,
+    /// Requests of sandboxed tenants go here instead of sim_req_sender.
+    sandboxed_queues: SandboxedQueues,
+    /// Notified by the workers when they take a request, failed simulations don't send results so this is how we know
+    /// there's room to send more.
+    request_taken: Arc<Notify>,
     /// Here we receive the results we asked to sim_req_sender
     sim_results_receiver: mpsc::Receiver<SimulatedResult>,
     /// Output of the simulations
@@ -54,24 +74,34 @@ pub struct SimulationJob<P> {
     /// Got first sim result -> add to not_cancelled_simulated_orders.
     /// Got second sim result -> We DON'T send since we see on not_cancelled_simulated_orders that we already did it!
     not_cancelled_sent_simulated_orders: HashSet<OrderId>,
+
+    /// Simulations used by each tenant on this slot.
+    tenant_budget: TenantSimulationBudget,
+    resource_counters: Arc<SlotResourceCounters>,
 }
 
 impl<P> SimulationJob<P>
 where
     P: StateProviderFactory + Clone + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         block_cancellation: CancellationToken,
         new_order_sub: mpsc::UnboundedReceiver<OrderPoolCommand>,
         sim_req_sender: flume::Sender<SimulationRequest>,
+        sandboxed_queues: SandboxedQueues,
+        request_taken: Arc<Notify>,
         sim_results_receiver: mpsc::Receiver<SimulatedResult>,
         slot_sim_results_sender: mpsc::Sender<SimulatedOrderCommand>,
         sim_tree: SimTree<P>,
+        resource_counters: Arc<SlotResourceCounters>,
     ) -> Self {
         Self {
             block_cancellation,
             new_order_sub,
             sim_req_sender,
+            sandboxed_queues,
+            request_taken,
             sim_results_receiver,
             slot_sim_results_sender,
             sim_tree,
@@ -79,6 +109,8 @@ where
             orders_simulated_ok: OrderCounter::default(),
             in_flight_orders: Default::default(),
             not_cancelled_sent_simulated_orders: Default::default(),
+            tenant_budget: TenantSimulationBudget::new(tenant_registry()),
+            resource_counters,
         }
     }
 
@@ -118,6 +150,7 @@ where
                         new_sim_results.clear();
                     }
                 }
+                _ = self.request_taken.notified(), if self.sim_tree.ready_orders_len() != 0 => {}
                 _ = self.block_cancellation.cancelled() => {
                     return;
                 }
@@ -130,20 +163,51 @@ where
         self.in_flight_orders.contains(order_id)
     }
 
-    /// Pops tasks from SimTree and sends them for simulation
+    /// Pops tasks from SimTree and sends them for simulation until the channel has MAX_QUEUED_SIM_REQUESTS.
+    /// Tasks of a sandboxed tenant whose channel is already at MAX_QUEUED_SIM_REQUESTS go back to the SimTree.
     fn send_new_tasks_for_simulation(&mut self) {
         // submit sim tasks loop
         loop {
-            let mut new_sim_request = self.sim_tree.pop_simulation_tasks(1024);
+            let free = MAX_QUEUED_SIM_REQUESTS.saturating_sub(self.sim_req_sender.len());
+            if free == 0 {
+                break;
+            }
+            let new_sim_request = self.sim_tree.pop_simulation_tasks(free);
             if new_sim_request.is_empty() {
                 break;
             }
-            // filter out cancelled orders
-            new_sim_request.retain(|s| self.order_still_valid(&s.order.id()));
-
+            let mut requeued = Vec::new();
+            let popped = new_sim_request.len();
             for sim_request in new_sim_request {
                 let order_id = sim_request.order.id();
-                let delivered = match self.sim_req_sender.try_send(sim_request) {
+                // filter out cancelled orders
+                if !self.order_still_valid(&order_id) {
+                    continue;
+                }
+                let sender = match self.sandboxed_queues.sender_for(&sim_request.order) {
+                    Some(sender) if sender.len() >= MAX_QUEUED_SIM_REQUESTS => {
+                        requeued.push(sim_request);
+                        continue;
+                    }
+                    Some(sender) => sender,
+                    None => &self.sim_req_sender,
+                };
+                // filter out orders from tenants over their simulation budget
+                match self.tenant_budget.try_consume(&sim_request.order) {
+                    Ok(Some(tenant)) => inc_tenant_orders(tenant, "simulated"),
+                    Ok(None) => {}
+                    Err(tenant) => {
+                        trace!(
+                            ?order_id,
+                            tenant,
+                            "Tenant simulation budget exhausted, dropping order"
+                        );
+                        inc_tenant_orders(tenant, "dropped_sim_budget");
+                        self.in_flight_orders.remove(&order_id);
+                        continue;
+                    }
+                }
+                let delivered = match sender.try_send(sim_request) {
                     Ok(()) => true,
                     Err(flume::TrySendError::Full(_)) => {
                         warn!("Sim channel is full, dropping order");
@@ -161,6 +225,14 @@ where
                     self.in_flight_orders.remove(&order_id);
                 }
             }
+            let all_requeued = requeued.len() == popped;
+            for sim_request in requeued {
+                self.sim_tree.requeue_simulation_task(sim_request);
+            }
+            if all_requeued {
+                // only full tenant channels left, their workers notify request_taken when there's room
+                break;
+            }
         }
     }
 
@@ -239,13 +311,22 @@ where
     /// feeding the sim tree.
     fn process_new_order(&mut self, order: Order) -> bool {
         self.orders_received.accumulate(&order);
+        self.resource_counters.record_order_received();
         let order_id = order.id();
         if let Err(err) = self.sim_tree.push_orders(vec![order]) {
             error!(?err, "Failed to push order into the sim tree");
             // @Metric
             return false;
         }
-        self.in_flight_orders.insert(order_id);
+        let rejected_orders = self.sim_tree.take_rejected_orders();
+        if rejected_orders.is_empty() {
+            self.in_flight_orders.insert(order_id);
+        }
+        for (order_id, err) in rejected_orders {
+            debug!(?order_id, %err, "Order rejected by validity check");
+            inc_order_validity_rejections(err.reason());
+            record_order_drop(order_id, OrderDropStage::Intake, err.reason());
+        }
         true
     }
 
@@ -0,0 +1,248 @@
+//! Simulation sandboxes for untrusted tenants (see [`crate::live_builder::order_input::tenants`]).
+//! Orders of a tenant with a sandbox are not simulated by the shared workers but by a few workers of its own that
+//! only serve its request queue so a pathological flow (eg: bundles burning all the gas in loops) can only slow down
+//! itself.
+//! If a cgroup v2 root is configured the tenant workers are also moved to root/tenant-{name} (a threaded cgroup) with
+//! cpu.max set from cpu_quota_percent so the OS caps the CPU they can take from the rest of the builder.
+//! The root must be a delegated cgroup we can write to containing the rbuilder process (eg: systemd Delegate=yes).
+//! If the cgroup can't be set up we log it and the workers run unconfined.
+
+use super::{
+    sim_worker::{self, SimWorkerQueue},
+    CurrentSimulationContexts,
+};
+use crate::{
+    building::sim::SimulationRequest,
+    live_builder::order_input::tenants::{TenantId, TenantRegistry},
+    primitives::Order,
+};
+use ahash::HashMap;
+use parking_lot::Mutex;
+use reth_provider::StateProviderFactory;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// cpu.max period.
+pub const CPU_PERIOD_US: u64 = 100_000;
+
+/// cpu.max content for a quota of cpu_quota_percent of one CPU (200 is 2 full CPUs).
+pub fn cpu_max(cpu_quota_percent: u32) -> String {
+    let quota = (CPU_PERIOD_US * cpu_quota_percent as u64 / 100).max(1);
+    format!("{} {}", quota, CPU_PERIOD_US)
+}
+
+#[derive(Debug, Clone)]
+pub struct TenantCgroup {
+    path: PathBuf,
+}
+
+impl TenantCgroup {
+    /// Creates (or reuses) root/tenant-{tenant} and sets its cpu quota.
+    pub fn create(root: &Path, tenant: &str, cpu_quota_percent: Option<u32>) -> io::Result<Self> {
+        fs::write(root.join("cgroup.subtree_control"), "+cpu")?;
+        let path = root.join(format!("tenant-{}", tenant));
+        fs::create_dir_all(&path)?;
+        // only threaded cgroups can hold some of the threads of a process
+        fs::write(path.join("cgroup.type"), "threaded")?;
+        if let Some(cpu_quota_percent) = cpu_quota_percent {
+            fs::write(path.join("cpu.max"), cpu_max(cpu_quota_percent))?;
+        }
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn add_current_thread(&self) -> io::Result<()> {
+        // SAFETY: gettid has no preconditions, it only returns the id of the calling thread.
+        let tid = unsafe { libc::gettid() };
+        fs::write(self.path.join("cgroup.threads"), tid.to_string())
+    }
+
+    /// cgroups are linux only.
+    #[cfg(not(target_os = "linux"))]
+    pub fn add_current_thread(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Request queues of the sandboxed tenants for a simulation job.
+#[derive(Debug, Default)]
+pub struct SandboxedQueues {
+    registry: Arc<TenantRegistry>,
+    senders: HashMap<TenantId, flume::Sender<SimulationRequest>>,
+}
+
+impl SandboxedQueues {
+    /// Returns the queues and the receivers (by tenant name) for the workers.
+    pub fn new(
+        registry: Arc<TenantRegistry>,
+    ) -> (Self, HashMap<String, flume::Receiver<SimulationRequest>>) {
+        let mut senders = HashMap::default();
+        let mut receivers = HashMap::default();
+        for (tenant, config, _) in registry.sandboxed() {
+            let (sender, receiver) = flume::unbounded();
+            senders.insert(tenant, sender);
+            receivers.insert(config.name.clone(), receiver);
+        }
+        (Self { registry, senders }, receivers)
+    }
+
+    /// None if the order must go to the shared queue.
+    pub fn sender_for(&self, order: &Order) -> Option<&flume::Sender<SimulationRequest>> {
+        if self.senders.is_empty() {
+            return None;
+        }
+        self.registry
+            .tenant_of(order)
+            .and_then(|tenant| self.senders.get(&tenant))
+    }
+}
+
+/// Spawns the workers of every sandboxed tenant of registry.
+/// worker_id_offset is the id of the first one (ids are used on the sim thread metrics).
+pub fn spawn_tenant_workers<P>(
+    registry: &TenantRegistry,
+    cgroup_root: Option<&Path>,
+    worker_id_offset: usize,
+    ctx: Arc<Mutex<CurrentSimulationContexts>>,
+    provider: P,
+    global_cancellation: CancellationToken,
+) -> Vec<std::thread::JoinHandle<()>>
+where
+    P: StateProviderFactory + Clone + 'static,
+{
+    let mut handles = Vec::new();
+    for (_, config, sandbox) in registry.sandboxed() {
+        let cgroup = cgroup_root.and_then(|root| {
+            match TenantCgroup::create(root, &config.name, sandbox.cpu_quota_percent) {
+                Ok(cgroup) => {
+                    info!(tenant = %config.name, path = ?cgroup.path(), "Tenant simulation cgroup ready");
+                    Some(cgroup)
+                }
+                Err(err) => {
+                    warn!(tenant = %config.name, ?err, "Failed to set up tenant simulation cgroup, running unconfined");
+                    None
+                }
+            }
+        });
+        for i in 0..sandbox.simulation_threads {
+            let worker_id = worker_id_offset + handles.len();
+            let queue = SimWorkerQueue::Tenant(config.name.clone());
+            let cgroup = cgroup.clone();
+            let ctx = Arc::clone(&ctx);
+            let provider = provider.clone();
+            let cancel = global_cancellation.clone();
+            let tenant = config.name.clone();
+            let handle = std::thread::Builder::new()
+                .name(format!("sim_tenant:{}:{}", tenant, i))
+                .spawn(move || {
+                    if let Some(cgroup) = cgroup {
+                        if let Err(err) = cgroup.add_current_thread() {
+                            warn!(%tenant, ?err, "Failed to move simulation thread to tenant cgroup");
+                        }
+                    }
+                    sim_worker::run_sim_worker_while(
+                        worker_id,
+                        &ctx,
+                        &provider,
+                        &cancel,
+                        &queue,
+                        || true,
+                    );
+                })
+                .expect("Failed to start tenant sim worker thread");
+            handles.push(handle);
+        }
+    }
+    handles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        live_builder::order_input::tenants::{TenantConfig, TenantSandboxConfig},
+        primitives::{Bundle, Metadata},
+    };
+    use alloy_primitives::Address;
+
+    #[test]
+    fn test_cpu_max() {
+        assert_eq!(cpu_max(50), "50000 100000");
+        assert_eq!(cpu_max(200), "200000 100000");
+        assert_eq!(cpu_max(0), "1 100000");
+    }
+
+    #[test]
+    fn test_tenant_cgroup() {
+        // a plain dir stands for the delegated cgroup root
+        let root = tempfile::tempdir().unwrap();
+        let cgroup = TenantCgroup::create(root.path(), "untrusted", Some(50)).unwrap();
+        assert_eq!(cgroup.path(), root.path().join("tenant-untrusted"));
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(root.path().join("cgroup.subtree_control")), "+cpu");
+        assert_eq!(read(cgroup.path().join("cgroup.type")), "threaded");
+        assert_eq!(read(cgroup.path().join("cpu.max")), "50000 100000");
+
+        // reused, no quota leaves cpu.max alone
+        let no_quota = TenantCgroup::create(root.path(), "untrusted", None).unwrap();
+        assert_eq!(no_quota.path(), cgroup.path());
+        assert_eq!(read(cgroup.path().join("cpu.max")), "50000 100000");
+
+        #[cfg(target_os = "linux")]
+        {
+            cgroup.add_current_thread().unwrap();
+            let tid: i32 = read(cgroup.path().join("cgroup.threads")).parse().unwrap();
+            assert!(tid > 0);
+        }
+
+        assert!(TenantCgroup::create(&root.path().join("missing"), "untrusted", None).is_err());
+    }
+
+    #[test]
+    fn test_sandboxed_queues() {
+        let registry = TenantRegistry::new(vec![
+            TenantConfig {
+                name: "untrusted".to_string(),
+                signers: vec![Address::with_last_byte(1)],
+                sandbox: Some(TenantSandboxConfig::default()),
+                ..Default::default()
+            },
+            TenantConfig {
+                name: "trusted".to_string(),
+                signers: vec![Address::with_last_byte(2)],
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let (queues, receivers) = SandboxedQueues::new(Arc::new(registry));
+        assert_eq!(receivers.len(), 1);
+        assert!(receivers.contains_key("untrusted"));
+
+        let bundle = |signer: u8| {
+            Order::Bundle(Bundle {
+                block: 1,
+                min_timestamp: None,
+                max_timestamp: None,
+                txs: Vec::new(),
+                reverting_tx_hashes: Vec::new(),
+                hash: Default::default(),
+                uuid: Default::default(),
+                replacement_data: None,
+                signer: Some(Address::with_last_byte(signer)),
+                metadata: Metadata::default(),
+            })
+        };
+        assert!(queues.sender_for(&bundle(1)).is_some());
+        assert!(queues.sender_for(&bundle(2)).is_none());
+        assert!(queues.sender_for(&bundle(3)).is_none());
+    }
+}
@@ -0,0 +1,243 @@
+//! Estimates our chance of winning a slot before building for it so slots we essentially can't win are built with
+//! reduced effort (see [`crate::building::BlockBuildingContext::reduced_effort`]).
+//!
+//! Signals:
+//! - Relays with the proposer registration: only those relays will take our bid.
+//! - Relay connectivity: relays failing right now (see [`crate::telemetry::relay_is_failing`]) don't count.
+//! - Historical win rate for the slot fee recipient, smoothed towards our global win rate. Proposers that never pick
+//!   our bids (vertically integrated, not listening to our relays) quickly get a win rate close to 0.
+//!
+//! History is in memory only, after a restart every slot gets full effort until we learn again.
+//! Predicting has no side effects, the slots are recorded apart ([`record_slot`]) once the effort is decided.
+//! Reduced effort slots only count when we win them: losing them is expected (we chose to) and counting it would
+//! feed the predictor its own output, a win still lets a fee recipient recover.
+
+use crate::{
+    live_builder::{
+        block_output::bidding::interfaces::LandedBlockInfo, payload_events::MevBoostSlotData,
+    },
+    telemetry::{inc_slot_outcome_predictions, relay_is_failing},
+};
+use ahash::HashMap;
+use alloy_primitives::Address;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+/// Slots we built for and never saw landing (eg: reorgs, missed slots) are forgotten after this many newer ones.
+const MAX_PENDING_SLOTS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotOutcomePredictorConfig {
+    /// Slots with a predicted win probability below this get reduced effort.
+    pub min_win_probability: f64,
+    /// Weight (in slots) of the global win rate when estimating the win rate of a fee recipient.
+    /// Also the min history needed for a fee recipient with no wins to fall below min_win_probability.
+    pub prior_slots: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct WinHistory {
+    slots: u64,
+    wins: u64,
+}
+
+impl WinHistory {
+    fn record(&mut self, won: bool) {
+        self.slots += 1;
+        if won {
+            self.wins += 1;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SlotOutcomePredictor {
+    config: SlotOutcomePredictorConfig,
+    global: WinHistory,
+    per_fee_recipient: HashMap<Address, WinHistory>,
+    /// block number -> (fee recipient, reduced effort) for the slots we built and whose outcome we don't know yet.
+    pending_slots: BTreeMap<u64, (Address, bool)>,
+}
+
+impl SlotOutcomePredictor {
+    pub fn new(config: SlotOutcomePredictorConfig) -> Self {
+        Self {
+            config,
+            global: WinHistory::default(),
+            per_fee_recipient: HashMap::default(),
+            pending_slots: BTreeMap::new(),
+        }
+    }
+
+    /// usable_relays: relays with the proposer registration that are not failing.
+    pub fn win_probability(&self, fee_recipient: Address, usable_relays: usize) -> f64 {
+        if usable_relays == 0 {
+            return 0.0;
+        }
+        // Laplace smoothing so we start at 0.5 with no history
+        let global_rate = (self.global.wins + 1) as f64 / (self.global.slots + 2) as f64;
+        let history = self
+            .per_fee_recipient
+            .get(&fee_recipient)
+            .copied()
+            .unwrap_or_default();
+        let prior = self.config.prior_slots as f64;
+        (history.wins as f64 + prior * global_rate) / (history.slots as f64 + prior)
+    }
+
+    pub fn should_reduce_effort(&self, fee_recipient: Address, usable_relays: usize) -> bool {
+        self.win_probability(fee_recipient, usable_relays) < self.config.min_win_probability
+    }
+
+    /// Call for every slot we build.
+    pub fn record_slot(&mut self, block_number: u64, fee_recipient: Address, reduced_effort: bool) {
+        self.pending_slots
+            .insert(block_number, (fee_recipient, reduced_effort));
+        while self.pending_slots.len() > MAX_PENDING_SLOTS {
+            self.pending_slots.pop_first();
+        }
+    }
+
+    pub fn record_landed_block(&mut self, block_number: u64, won: bool) {
+        let Some((fee_recipient, reduced_effort)) = self.pending_slots.remove(&block_number) else {
+            return;
+        };
+        if reduced_effort && !won {
+            return;
+        }
+        self.global.record(won);
+        self.per_fee_recipient
+            .entry(fee_recipient)
+            .or_default()
+            .record(won);
+    }
+}
+
+lazy_static! {
+    static ref PREDICTOR: Mutex<Option<SlotOutcomePredictor>> = Mutex::new(None);
+}
+
+pub fn init_slot_outcome_predictor(config: SlotOutcomePredictorConfig) {
+    *PREDICTOR.lock() = Some(SlotOutcomePredictor::new(config));
+}
+
+/// True if we should build the slot with reduced effort, does not record anything (see [`record_slot`]).
+/// Always false if the predictor is not initialized.
+pub fn should_reduce_effort(payload: &MevBoostSlotData) -> bool {
+    let predictor = PREDICTOR.lock();
+    let Some(predictor) = predictor.as_ref() else {
+        return false;
+    };
+    let usable_relays = payload
+        .relays
+        .iter()
+        .filter(|relay| !relay_is_failing(relay))
+        .count();
+    let fee_recipient = payload.fee_recipient();
+    let win_probability = predictor.win_probability(fee_recipient, usable_relays);
+    let reduce_effort = predictor.should_reduce_effort(fee_recipient, usable_relays);
+    debug!(
+        slot = payload.slot(),
+        block = payload.block(),
+        ?fee_recipient,
+        usable_relays,
+        win_probability,
+        reduce_effort,
+        "Slot outcome prediction"
+    );
+    inc_slot_outcome_predictions(reduce_effort);
+    reduce_effort
+}
+
+/// Call for every slot we build, reduced_effort: what we built it with.
+pub fn record_slot(payload: &MevBoostSlotData, reduced_effort: bool) {
+    if let Some(predictor) = PREDICTOR.lock().as_mut() {
+        predictor.record_slot(payload.block(), payload.fee_recipient(), reduced_effort);
+    }
+}
+
+pub fn record_landed_blocks(landed_blocks: &[LandedBlockInfo]) {
+    if let Some(predictor) = PREDICTOR.lock().as_mut() {
+        for block in landed_blocks {
+            predictor.record_landed_block(block.block_number, block.beneficiary_is_builder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predictor() -> SlotOutcomePredictor {
+        SlotOutcomePredictor::new(SlotOutcomePredictorConfig {
+            min_win_probability: 0.01,
+            prior_slots: 10,
+        })
+    }
+
+    #[test]
+    fn test_no_usable_relays() {
+        let predictor = predictor();
+        assert_eq!(predictor.win_probability(Address::ZERO, 0), 0.0);
+        assert!(predictor.should_reduce_effort(Address::ZERO, 0));
+        assert!(!predictor.should_reduce_effort(Address::ZERO, 1));
+    }
+
+    #[test]
+    fn test_fee_recipient_history() {
+        let mut predictor = predictor();
+        let never_wins = Address::with_last_byte(1);
+        let sometimes_wins = Address::with_last_byte(2);
+        let mut block = 0;
+        for i in 0..1000 {
+            block += 1;
+            predictor.record_slot(block, never_wins, false);
+            predictor.record_landed_block(block, false);
+            block += 1;
+            predictor.record_slot(block, sometimes_wins, false);
+            predictor.record_landed_block(block, i % 5 == 0);
+        }
+        assert!(predictor.should_reduce_effort(never_wins, 3));
+        assert!(!predictor.should_reduce_effort(sometimes_wins, 3));
+        // unknown fee recipient gets the global rate (~10%)
+        let unknown = predictor.win_probability(Address::with_last_byte(3), 3);
+        assert!((0.09..0.11).contains(&unknown));
+    }
+
+    #[test]
+    fn test_only_pending_slots_count() {
+        let mut predictor = predictor();
+        predictor.record_landed_block(1, true);
+        assert_eq!(predictor.global, WinHistory::default());
+
+        predictor.record_slot(2, Address::ZERO, false);
+        predictor.record_landed_block(2, true);
+        predictor.record_landed_block(2, true);
+        assert_eq!(predictor.global, WinHistory { slots: 1, wins: 1 });
+
+        for block in 0..(MAX_PENDING_SLOTS as u64 + 10) {
+            predictor.record_slot(block, Address::ZERO, false);
+        }
+        assert_eq!(predictor.pending_slots.len(), MAX_PENDING_SLOTS);
+        predictor.record_landed_block(0, true);
+        assert_eq!(predictor.global, WinHistory { slots: 1, wins: 1 });
+    }
+
+    #[test]
+    fn test_reduced_effort_losses_dont_count() {
+        let mut predictor = predictor();
+        let fee_recipient = Address::with_last_byte(1);
+        for block in 0..100 {
+            predictor.record_slot(block, fee_recipient, true);
+            predictor.record_landed_block(block, false);
+        }
+        assert_eq!(predictor.global, WinHistory::default());
+        assert!(predictor.per_fee_recipient.is_empty());
+
+        predictor.record_slot(100, fee_recipient, true);
+        predictor.record_landed_block(100, true);
+        assert_eq!(predictor.global, WinHistory { slots: 1, wins: 1 });
+    }
+}
@@ -0,0 +1,372 @@
+//! Per slot resource usage report for capacity planning: process CPU time, peak RSS, simulations and state (DB) reads
+//! next to the orderflow we got (orders received) and what it was worth (best bid), so resource use can be
+//! correlated with orderflow volume and value.
+//! Reports are exported as metrics and, if configured, persisted in a sqlite performance db.
+//! The db connection is owned by the writer spawned here, other per slot records (eg: the relays
+//! [`SlotOutcome`]s) are written through it too ([`write_slot_outcome`]).
+//!
+//! Simulations and orders received are counted on the [`SlotResourceCounters`] of the slot (shared with its simulation
+//! job), state reads are process wide (see [`crate::building::state_read_metrics`]).
+
+use super::block_output::relay_data_poller::{
+    create_slot_outcomes_table, insert_slot_outcome, SlotOutcome,
+};
+use crate::{building::state_read_metrics::SlotStateReads, telemetry::add_slot_resource_report};
+use alloy_primitives::U256;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Executor, SqliteConnection};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// How often RSS is sampled to get the slot peak.
+pub const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Records not written yet when the db is this far behind are dropped.
+const MAX_PENDING_RECORDS: usize = 100;
+
+lazy_static! {
+    static ref RECORD_SENDER: Mutex<Option<mpsc::Sender<PerformanceDbRecord>>> = Mutex::new(None);
+}
+
+/// Work done for one slot, updated by its simulation job and the sim workers serving it.
+#[derive(Debug, Default)]
+pub struct SlotResourceCounters {
+    simulations: AtomicU64,
+    orders_received: AtomicU64,
+}
+
+impl SlotResourceCounters {
+    /// Call for every order simulation.
+    pub fn record_simulation(&self) {
+        self.simulations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Call for every order that reaches the simulation of the slot.
+    pub fn record_order_received(&self) {
+        self.orders_received.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// user + system CPU time of the whole process.
+fn process_cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return Duration::ZERO;
+    }
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+/// None if we can't read it (eg: not on linux).
+fn current_rss_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+    Some(resident_pages * page_size as u64)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotResourceReport {
+    pub slot: u64,
+    pub block: u64,
+    pub building_time_ms: u64,
+    /// Process CPU time (all threads) while building the slot.
+    pub cpu_time_ms: u64,
+    pub peak_rss_bytes: u64,
+    pub simulations: u64,
+    pub orders_received: u64,
+    pub state_reads: u64,
+    pub slow_state_reads: u64,
+    /// Sum over all threads.
+    pub state_read_time_ms: u64,
+    pub bid_submissions: u64,
+    pub best_bid_value: U256,
+}
+
+#[derive(Debug)]
+enum PerformanceDbRecord {
+    SlotResourceReport(SlotResourceReport),
+    SlotOutcome(SlotOutcome),
+}
+
+/// Tracks the resources used while building one slot.
+#[derive(Debug)]
+pub struct SlotResourceTracker {
+    slot: u64,
+    block: u64,
+    started_at: Instant,
+    cpu_time_start: Duration,
+    peak_rss_bytes: u64,
+    counters: Arc<SlotResourceCounters>,
+}
+
+impl SlotResourceTracker {
+    /// Call when we start building the slot.
+    pub fn start(slot: u64, block: u64) -> Self {
+        let mut res = Self {
+            slot,
+            block,
+            started_at: Instant::now(),
+            cpu_time_start: process_cpu_time(),
+            peak_rss_bytes: 0,
+            counters: Default::default(),
+        };
+        res.sample_memory();
+        res
+    }
+
+    /// To be given to the simulation job of the slot.
+    pub fn counters(&self) -> Arc<SlotResourceCounters> {
+        self.counters.clone()
+    }
+
+    pub fn sample_memory(&mut self) {
+        if let Some(rss) = current_rss_bytes() {
+            self.peak_rss_bytes = self.peak_rss_bytes.max(rss);
+        }
+    }
+
+    /// Samples memory until deadline.
+    pub async fn sample_memory_until(&mut self, deadline: Instant) {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            tokio::time::sleep(MEMORY_SAMPLE_INTERVAL.min(deadline - now)).await;
+            self.sample_memory();
+        }
+    }
+
+    /// state_reads must be taken by the caller since they are also used for other metrics.
+    pub fn finish(
+        mut self,
+        state_reads: SlotStateReads,
+        bid_submissions: u64,
+        best_bid_value: U256,
+    ) -> SlotResourceReport {
+        self.sample_memory();
+        SlotResourceReport {
+            slot: self.slot,
+            block: self.block,
+            building_time_ms: self.started_at.elapsed().as_millis() as u64,
+            cpu_time_ms: process_cpu_time()
+                .saturating_sub(self.cpu_time_start)
+                .as_millis() as u64,
+            peak_rss_bytes: self.peak_rss_bytes,
+            simulations: self.counters.simulations.load(Ordering::Relaxed),
+            orders_received: self.counters.orders_received.load(Ordering::Relaxed),
+            state_reads: state_reads.reads,
+            slow_state_reads: state_reads.slow_reads,
+            state_read_time_ms: state_reads.read_time.as_millis() as u64,
+            bid_submissions,
+            best_bid_value,
+        }
+    }
+}
+
+/// Exports the report as metrics and sends it to the performance db (if spawned).
+pub fn publish_slot_resource_report(report: SlotResourceReport) {
+    debug!(?report, "Slot resource report");
+    add_slot_resource_report(&report);
+    send_record(PerformanceDbRecord::SlotResourceReport(report));
+}
+
+/// Sends the outcome to the performance db (if spawned).
+pub fn write_slot_outcome(outcome: SlotOutcome) {
+    send_record(PerformanceDbRecord::SlotOutcome(outcome));
+}
+
+fn send_record(record: PerformanceDbRecord) {
+    if let Some(sender) = RECORD_SENDER.lock().as_ref() {
+        if sender.try_send(record).is_err() {
+            warn!("Performance db record dropped, performance db writer is behind");
+        }
+    }
+}
+
+/// Opens (or creates) the performance db at `db_path` and spawns a task writing the published reports and slot
+/// outcomes until `global_cancel` is cancelled.
+pub async fn spawn_slot_resource_report_writer(
+    db_path: impl AsRef<Path>,
+    global_cancel: CancellationToken,
+) -> eyre::Result<()> {
+    let mut storage = SlotResourceReportStorage::new_from_path(db_path).await?;
+    let (sender, mut receiver) = mpsc::channel(MAX_PENDING_RECORDS);
+    *RECORD_SENDER.lock() = Some(sender);
+    tokio::spawn(async move {
+        loop {
+            let record = tokio::select! {
+                _ = global_cancel.cancelled() => return,
+                record = receiver.recv() => match record {
+                    Some(record) => record,
+                    None => return,
+                },
+            };
+            match record {
+                PerformanceDbRecord::SlotResourceReport(report) => {
+                    if let Err(err) = storage.write_report(&report).await {
+                        warn!(
+                            ?err,
+                            slot = report.slot,
+                            "Error writing slot resource report"
+                        );
+                    }
+                }
+                PerformanceDbRecord::SlotOutcome(outcome) => {
+                    if let Err(err) = insert_slot_outcome(&mut storage.conn, &outcome).await {
+                        warn!(?err, slot = outcome.slot, "Error writing slot outcome");
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[derive(Debug)]
+struct SlotResourceReportStorage {
+    conn: SqliteConnection,
+}
+
+impl SlotResourceReportStorage {
+    async fn new_from_path(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let mut res = Self {
+            conn: SqliteConnectOptions::new()
+                .filename(path)
+                .create_if_missing(true)
+                .connect()
+                .await?,
+        };
+        res.create_tables().await?;
+        Ok(res)
+    }
+
+    #[cfg(test)]
+    async fn new_from_memory() -> eyre::Result<Self> {
+        let mut res = Self {
+            conn: SqliteConnectOptions::new().connect().await?,
+        };
+        res.create_tables().await?;
+        Ok(res)
+    }
+
+    async fn create_tables(&mut self) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                r#"
+            CREATE TABLE IF NOT EXISTS slot_resource_reports (
+                slot INTEGER NOT NULL,
+                block INTEGER NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                building_time_ms INTEGER NOT NULL,
+                cpu_time_ms INTEGER NOT NULL,
+                peak_rss_bytes INTEGER NOT NULL,
+                simulations INTEGER NOT NULL,
+                orders_received INTEGER NOT NULL,
+                state_reads INTEGER NOT NULL,
+                slow_state_reads INTEGER NOT NULL,
+                state_read_time_ms INTEGER NOT NULL,
+                bid_submissions INTEGER NOT NULL,
+                best_bid_value TEXT NOT NULL
+            );
+            "#,
+            )
+            .await?;
+        create_slot_outcomes_table(&mut self.conn).await?;
+
+        Ok(())
+    }
+
+    async fn write_report(&mut self, report: &SlotResourceReport) -> eyre::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO slot_resource_reports (slot, block, recorded_at, building_time_ms, cpu_time_ms, peak_rss_bytes,
+                simulations, orders_received, state_reads, slow_state_reads, state_read_time_ms, bid_submissions, best_bid_value)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(report.slot as i64)
+        .bind(report.block as i64)
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .bind(report.building_time_ms as i64)
+        .bind(report.cpu_time_ms as i64)
+        .bind(report.peak_rss_bytes as i64)
+        .bind(report.simulations as i64)
+        .bind(report.orders_received as i64)
+        .bind(report.state_reads as i64)
+        .bind(report.slow_state_reads as i64)
+        .bind(report.state_read_time_ms as i64)
+        .bind(report.bid_submissions as i64)
+        .bind(report.best_bid_value.to_string())
+        .execute(&mut self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slot_resource_report() {
+        let tracker = SlotResourceTracker::start(10, 100);
+        let counters = tracker.counters();
+        for _ in 0..3 {
+            counters.record_simulation();
+        }
+        counters.record_order_received();
+        // counters of other slots don't count
+        SlotResourceTracker::start(11, 101)
+            .counters()
+            .record_simulation();
+        // burn some cpu
+        let mut x = 0u64;
+        for i in 0..1_000_000u64 {
+            x = x.wrapping_add(std::hint::black_box(i));
+        }
+        std::hint::black_box(x);
+        let state_reads = SlotStateReads {
+            reads: 5,
+            slow_reads: 1,
+            read_time: Duration::from_millis(7),
+        };
+        let report = tracker.finish(state_reads, 2, U256::from(42));
+        assert_eq!(report.slot, 10);
+        assert_eq!(report.block, 100);
+        assert_eq!(report.simulations, 3);
+        assert_eq!(report.orders_received, 1);
+        assert_eq!(report.state_reads, 5);
+        assert_eq!(report.state_read_time_ms, 7);
+        assert_eq!(report.best_bid_value, U256::from(42));
+        if cfg!(target_os = "linux") {
+            assert!(report.peak_rss_bytes > 0);
+        }
+
+        let mut storage = SlotResourceReportStorage::new_from_memory().await.unwrap();
+        storage.write_report(&report).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM slot_resource_reports")
+            .fetch_one(&mut storage.conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
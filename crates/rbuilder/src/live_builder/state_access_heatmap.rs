@@ -0,0 +1,300 @@
+//! State access heatmap: which contracts/storage slots simulated orders read and write, aggregated per hour.
+//! Helps sizing the state caches and shows when a single hot contract (eg: a popular DEX pool) is touched by most
+//! orders and so dominates the conflicts.
+//! Reports are served by the admin rpc (admin_stateAccessHeatmap) and the hottest contract share is exported as a metric.
+//!
+//! To bound memory we track at most MAX_CONTRACTS contracts and MAX_SLOTS_PER_CONTRACT slots per contract per hour,
+//! accesses to new ones after that only count on the contract/hour totals.
+
+use crate::{building::evm_inspector::UsedStateTrace, telemetry::set_hottest_contract_order_share};
+use ahash::{HashMap, HashSet};
+use alloy_primitives::{Address, B256};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{cmp::Reverse, collections::VecDeque};
+use time::OffsetDateTime;
+
+const MAX_CONTRACTS: usize = 10_000;
+const MAX_SLOTS_PER_CONTRACT: usize = 1_000;
+/// Closed hours we keep.
+const MAX_HOURS: usize = 24;
+/// Contracts/slots shown per report.
+const TOP_CONTRACTS: usize = 50;
+const TOP_SLOTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct AccessCount {
+    reads: u64,
+    writes: u64,
+}
+
+#[derive(Debug, Default)]
+struct ContractAccess {
+    access: AccessCount,
+    /// Orders that touched the contract.
+    orders: u64,
+    slots: HashMap<B256, AccessCount>,
+}
+
+#[derive(Debug, Default)]
+struct HourHeatmap {
+    hour: u64,
+    orders: u64,
+    access: AccessCount,
+    contracts: HashMap<Address, ContractAccess>,
+}
+
+impl HourHeatmap {
+    fn new(hour: u64) -> Self {
+        Self {
+            hour,
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, trace: &UsedStateTrace) {
+        self.orders += 1;
+        let mut touched = HashSet::default();
+        let accesses = trace
+            .read_slot_values
+            .keys()
+            .map(|slot| (slot, false))
+            .chain(trace.written_slot_values.keys().map(|slot| (slot, true)));
+        for (slot, write) in accesses {
+            let count = |access: &mut AccessCount| {
+                if write {
+                    access.writes += 1;
+                } else {
+                    access.reads += 1;
+                }
+            };
+            count(&mut self.access);
+            if self.contracts.len() >= MAX_CONTRACTS && !self.contracts.contains_key(&slot.address)
+            {
+                continue;
+            }
+            let contract = self.contracts.entry(slot.address).or_default();
+            count(&mut contract.access);
+            if touched.insert(slot.address) {
+                contract.orders += 1;
+            }
+            if contract.slots.len() < MAX_SLOTS_PER_CONTRACT
+                || contract.slots.contains_key(&slot.key)
+            {
+                count(contract.slots.entry(slot.key).or_default());
+            }
+        }
+    }
+
+    fn report(&self) -> HeatmapReport {
+        let mut contracts: Vec<_> = self
+            .contracts
+            .iter()
+            .map(|(address, contract)| {
+                let mut slots: Vec<_> = contract
+                    .slots
+                    .iter()
+                    .map(|(key, access)| SlotAccessReport {
+                        key: *key,
+                        reads: access.reads,
+                        writes: access.writes,
+                    })
+                    .collect();
+                slots.sort_by_key(|slot| Reverse(slot.reads + slot.writes));
+                slots.truncate(TOP_SLOTS);
+                ContractAccessReport {
+                    address: *address,
+                    reads: contract.access.reads,
+                    writes: contract.access.writes,
+                    orders: contract.orders,
+                    order_share: share(contract.orders, self.orders),
+                    slots,
+                }
+            })
+            .collect();
+        contracts.sort_by(|a, b| {
+            (b.reads + b.writes)
+                .cmp(&(a.reads + a.writes))
+                .then(a.address.cmp(&b.address))
+        });
+        contracts.truncate(TOP_CONTRACTS);
+        HeatmapReport {
+            hour_start: self.hour * 3600,
+            orders: self.orders,
+            reads: self.access.reads,
+            writes: self.access.writes,
+            tracked_contracts: self.contracts.len(),
+            contracts,
+        }
+    }
+
+    fn hottest_contract_order_share(&self) -> f64 {
+        let orders = self
+            .contracts
+            .values()
+            .map(|contract| contract.orders)
+            .max()
+            .unwrap_or_default();
+        share(orders, self.orders)
+    }
+}
+
+fn share(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotAccessReport {
+    pub key: B256,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractAccessReport {
+    pub address: Address,
+    pub reads: u64,
+    pub writes: u64,
+    pub orders: u64,
+    /// Fraction of the simulated orders of the hour that touched the contract.
+    pub order_share: f64,
+    /// Hottest slots.
+    pub slots: Vec<SlotAccessReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatmapReport {
+    /// Unix timestamp.
+    pub hour_start: u64,
+    pub orders: u64,
+    pub reads: u64,
+    pub writes: u64,
+    pub tracked_contracts: usize,
+    /// Hottest contracts.
+    pub contracts: Vec<ContractAccessReport>,
+}
+
+#[derive(Debug, Default)]
+pub struct StateAccessHeatmap {
+    current: HourHeatmap,
+    /// Newest first.
+    closed: VecDeque<HeatmapReport>,
+}
+
+impl StateAccessHeatmap {
+    pub fn record_at(&mut self, trace: &UsedStateTrace, hour: u64) {
+        if hour != self.current.hour {
+            let closed = std::mem::replace(&mut self.current, HourHeatmap::new(hour));
+            if closed.orders > 0 {
+                set_hottest_contract_order_share(closed.hottest_contract_order_share());
+                self.closed.push_front(closed.report());
+                self.closed.truncate(MAX_HOURS);
+            }
+        }
+        self.current.record(trace);
+    }
+
+    /// Current hour first.
+    pub fn reports(&self, hours: usize) -> Vec<HeatmapReport> {
+        std::iter::once(self.current.report())
+            .chain(self.closed.iter().cloned())
+            .take(hours)
+            .collect()
+    }
+}
+
+lazy_static! {
+    static ref HEATMAP: Mutex<Option<StateAccessHeatmap>> = Mutex::new(None);
+}
+
+pub fn init_state_access_heatmap() {
+    *HEATMAP.lock() = Some(StateAccessHeatmap::default());
+}
+
+/// Call for every successfully simulated order. Does nothing if the heatmap was not initialized.
+pub fn record_state_access(trace: &UsedStateTrace) {
+    if let Some(heatmap) = HEATMAP.lock().as_mut() {
+        let hour = OffsetDateTime::now_utc().unix_timestamp() as u64 / 3600;
+        heatmap.record_at(trace, hour);
+    }
+}
+
+/// - admin_stateAccessHeatmap(hours): reports for the current and the last hours - 1 closed hours.
+pub fn state_access_heatmap_rpc_module() -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    module.register_method("admin_stateAccessHeatmap", |params, _| {
+        let hours: usize = params.one().unwrap_or(1);
+        Ok::<_, ErrorObject<'static>>(
+            HEATMAP
+                .lock()
+                .as_ref()
+                .map(|heatmap| heatmap.reports(hours))
+                .unwrap_or_default(),
+        )
+    })?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::evm_inspector::SlotKey;
+
+    fn trace(reads: &[(Address, u64)], writes: &[(Address, u64)]) -> UsedStateTrace {
+        let slots = |slots: &[(Address, u64)]| {
+            slots
+                .iter()
+                .map(|(address, key)| {
+                    (
+                        SlotKey {
+                            address: *address,
+                            key: B256::with_last_byte(*key as u8),
+                        },
+                        B256::ZERO,
+                    )
+                })
+                .collect()
+        };
+        UsedStateTrace {
+            read_slot_values: slots(reads),
+            written_slot_values: slots(writes),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let pool = Address::with_last_byte(1);
+        let token = Address::with_last_byte(2);
+        let mut heatmap = StateAccessHeatmap::default();
+        heatmap.record_at(&trace(&[(pool, 0), (pool, 1)], &[(pool, 0)]), 10);
+        heatmap.record_at(&trace(&[(pool, 0)], &[(token, 5)]), 10);
+        heatmap.record_at(&trace(&[(token, 5)], &[]), 10);
+
+        let report = &heatmap.reports(1)[0];
+        assert_eq!(report.hour_start, 36_000);
+        assert_eq!(report.orders, 3);
+        assert_eq!((report.reads, report.writes), (4, 2));
+        let hottest = &report.contracts[0];
+        assert_eq!(hottest.address, pool);
+        assert_eq!((hottest.reads, hottest.writes, hottest.orders), (3, 1, 2));
+        assert_eq!(hottest.slots[0].key, B256::with_last_byte(0));
+        assert_eq!((hottest.slots[0].reads, hottest.slots[0].writes), (2, 1));
+        assert!((hottest.order_share - 2.0 / 3.0).abs() < 1e-9);
+
+        heatmap.record_at(&trace(&[(token, 5)], &[]), 11);
+        let reports = heatmap.reports(5);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].orders, 1);
+        assert_eq!(reports[1].orders, 3);
+    }
+}
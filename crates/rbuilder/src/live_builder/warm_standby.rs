@@ -0,0 +1,227 @@
+//! Warm standby: the instance runs the whole pipeline (order intake, simulation, building, bidding) but instead of
+//! submitting to the relays it records the would-be submissions until it's promoted. Since everything is already warm a
+//! promoted instance submits its next bid with no warmup, useful for failovers and for canary deployments (compare
+//! the would-be submissions with the real ones of the active instance before promoting).
+//!
+//! Promotion is done by the operator via the admin rpc (see [`warm_standby_rpc_module`]):
+//! - admin_promote() / admin_demote() -> true if the state changed
+//! - admin_standbyStatus() -> [`StandbyStatus`]
+//! - admin_wouldBeSubmissions(limit) -> last [`WouldBeSubmission`]s, newest last
+//!
+//! or by [`super::leader_election`] that promotes the instance when it becomes leader and demotes it when it loses the
+//! lease.
+//!
+//! When not enabled [`is_promoted`] is always true.
+
+use crate::{
+    telemetry::{inc_standby_would_be_submissions, set_standby_promoted},
+    utils::offset_datetime_to_timestamp_ms,
+};
+use alloy_primitives::{BlockHash, U256};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+/// Would-be submissions we remember, oldest ones are forgotten first.
+pub const MAX_WOULD_BE_SUBMISSIONS: usize = 1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROMOTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WouldBeSubmission {
+    pub slot: u64,
+    pub block_number: u64,
+    pub block_hash: BlockHash,
+    pub bid_value: U256,
+    pub true_bid_value: U256,
+    pub builder_name: String,
+    pub timestamp_ms: u64,
+}
+
+/// Response of admin_standbyStatus.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandbyStatus {
+    pub enabled: bool,
+    pub promoted: bool,
+    /// Since start, not only the ones we remember.
+    pub would_be_submissions: u64,
+}
+
+#[derive(Debug, Default)]
+struct WouldBeSubmissions {
+    submissions: VecDeque<WouldBeSubmission>,
+    total: u64,
+}
+
+impl WouldBeSubmissions {
+    fn push(&mut self, submission: WouldBeSubmission) {
+        self.total += 1;
+        self.submissions.push_back(submission);
+        while self.submissions.len() > MAX_WOULD_BE_SUBMISSIONS {
+            self.submissions.pop_front();
+        }
+    }
+
+    /// Last limit submissions, newest last.
+    fn last(&self, limit: usize) -> Vec<WouldBeSubmission> {
+        let skip = self.submissions.len().saturating_sub(limit);
+        self.submissions.iter().skip(skip).cloned().collect()
+    }
+}
+
+lazy_static! {
+    static ref WOULD_BE_SUBMISSIONS: Mutex<WouldBeSubmissions> =
+        Mutex::new(WouldBeSubmissions::default());
+}
+
+/// Starts in standby, nothing is submitted until [`promote`].
+pub fn init_warm_standby() {
+    PROMOTED.store(false, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+    set_standby_promoted(false);
+    info!("Warm standby enabled, relay submissions paused until promoted");
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// true if we should submit to the relays.
+pub fn is_promoted() -> bool {
+    !is_enabled() || PROMOTED.load(Ordering::Relaxed)
+}
+
+/// Returns true if we were not promoted. Does nothing if warm standby is not enabled.
+pub fn promote() -> bool {
+    set_promoted(true)
+}
+
+/// Returns true if we were promoted. Does nothing if warm standby is not enabled.
+pub fn demote() -> bool {
+    set_promoted(false)
+}
+
+fn set_promoted(promoted: bool) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    let changed = PROMOTED.swap(promoted, Ordering::Relaxed) != promoted;
+    if changed {
+        if promoted {
+            info!("Warm standby promoted, submitting to the relays");
+        } else {
+            warn!("Warm standby demoted, relay submissions paused");
+        }
+    }
+    set_standby_promoted(promoted);
+    changed
+}
+
+pub fn record_would_be_submission(
+    slot: u64,
+    block_number: u64,
+    block_hash: BlockHash,
+    bid_value: U256,
+    true_bid_value: U256,
+    builder_name: String,
+) {
+    inc_standby_would_be_submissions();
+    WOULD_BE_SUBMISSIONS.lock().push(WouldBeSubmission {
+        slot,
+        block_number,
+        block_hash,
+        bid_value,
+        true_bid_value,
+        builder_name,
+        timestamp_ms: offset_datetime_to_timestamp_ms(OffsetDateTime::now_utc()),
+    });
+}
+
+pub fn standby_status() -> StandbyStatus {
+    StandbyStatus {
+        enabled: is_enabled(),
+        promoted: is_promoted(),
+        would_be_submissions: WOULD_BE_SUBMISSIONS.lock().total,
+    }
+}
+
+pub fn warm_standby_rpc_module() -> eyre::Result<RpcModule<()>> {
+    fn not_enabled_error() -> ErrorObject<'static> {
+        ErrorObject::owned(-32000, "warm standby is not enabled", None::<()>)
+    }
+
+    let mut module = RpcModule::new(());
+    module.register_method("admin_promote", |_, _| {
+        if !is_enabled() {
+            return Err(not_enabled_error());
+        }
+        Ok::<_, ErrorObject<'static>>(promote())
+    })?;
+    module.register_method("admin_demote", |_, _| {
+        if !is_enabled() {
+            return Err(not_enabled_error());
+        }
+        Ok::<_, ErrorObject<'static>>(demote())
+    })?;
+    module.register_method("admin_standbyStatus", |_, _| {
+        Ok::<_, ErrorObject<'static>>(standby_status())
+    })?;
+    module.register_method("admin_wouldBeSubmissions", |params, _| {
+        let mut seq = params.sequence();
+        let limit: Option<usize> = seq.optional_next()?;
+        Ok::<_, ErrorObject<'static>>(
+            WOULD_BE_SUBMISSIONS
+                .lock()
+                .last(limit.unwrap_or(MAX_WOULD_BE_SUBMISSIONS)),
+        )
+    })?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submission(slot: u64) -> WouldBeSubmission {
+        WouldBeSubmission {
+            slot,
+            block_number: slot,
+            block_hash: BlockHash::ZERO,
+            bid_value: U256::from(slot),
+            true_bid_value: U256::from(slot),
+            builder_name: "mgp-ordering".to_string(),
+            timestamp_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_would_be_submissions_eviction() {
+        let mut submissions = WouldBeSubmissions::default();
+        for slot in 0..MAX_WOULD_BE_SUBMISSIONS as u64 + 10 {
+            submissions.push(submission(slot));
+        }
+        assert_eq!(submissions.total, MAX_WOULD_BE_SUBMISSIONS as u64 + 10);
+        assert_eq!(submissions.submissions.len(), MAX_WOULD_BE_SUBMISSIONS);
+        assert_eq!(submissions.submissions[0].slot, 10);
+
+        let last = submissions.last(2);
+        assert_eq!(
+            last.iter().map(|s| s.slot).collect::<Vec<_>>(),
+            vec![
+                MAX_WOULD_BE_SUBMISSIONS as u64 + 8,
+                MAX_WOULD_BE_SUBMISSIONS as u64 + 9
+            ]
+        );
+        assert_eq!(submissions.last(usize::MAX).len(), MAX_WOULD_BE_SUBMISSIONS);
+    }
+}
@@ -1,27 +1,30 @@
 mod error;
 pub mod fake_mev_boost_relay;
+mod payload_body;
+mod rejection;
 pub mod rpc;
 pub mod sign_payload;
 
-use super::utils::u256decimal_serde_helper;
+use super::{roothash::payment_proof::ProposerPaymentProof, utils::u256decimal_serde_helper};
 
-use alloy_primitives::{Address, BlockHash, Bytes, U256};
+use alloy_primitives::{Address, BlockHash, Bytes, FixedBytes, B256, U256};
 use alloy_rpc_types_beacon::relay::{
     BidTrace, SignedBidSubmissionV2, SignedBidSubmissionV3, SignedBidSubmissionV4,
 };
-use flate2::{write::GzEncoder, Compression};
+use payload_body::{buffered_body, streaming_body, BufferedBytes};
 use primitive_types::H384;
 use reqwest::{
     header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
     Body, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
-use ssz::Encode;
-use std::{io::Write, str::FromStr};
+use serde_with::{serde_as, DisplayFromStr, PickFirst};
+use sha2::{Digest, Sha256};
+use std::{str::FromStr, sync::Arc};
 use url::Url;
 
 pub use error::*;
+pub use rejection::*;
 pub use sign_payload::*;
 
 const JSON_CONTENT_TYPE: &str = "application/json";
@@ -132,6 +135,8 @@ pub struct RelayClient {
     authorization_header: Option<String>,
     builder_id_header: Option<String>,
     api_token_header: Option<String>,
+    /// Stream the submit block body instead of building it in memory (see [`payload_body`]).
+    streaming_submit: bool,
 }
 
 impl RelayClient {
@@ -147,9 +152,21 @@ impl RelayClient {
             authorization_header,
             builder_id_header,
             api_token_header,
+            streaming_submit: false,
         }
     }
 
+    pub fn with_streaming_submit(self, streaming_submit: bool) -> Self {
+        Self {
+            streaming_submit,
+            ..self
+        }
+    }
+
+    pub fn streaming_submit(&self) -> bool {
+        self.streaming_submit
+    }
+
     pub fn from_known_relay(relay: KnownRelay) -> Self {
         Self::from_url(relay.url(), None, None, None)
     }
@@ -238,6 +255,30 @@ pub struct ValidatorSlotData {
     #[serde_as(as = "DisplayFromStr")]
     pub validator_index: u64,
     pub entry: ValidatorRegistration,
+    /// Extra constraints some relays send along with the registration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferences: Option<ValidatorPreferences>,
+}
+
+/// Proposer constraints, beyond the registration, a relay asks us to respect when building for the slot.
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ValidatorPreferences {
+    /// Max blobs the proposer is willing to include.
+    #[serde_as(as = "Option<PickFirst<(DisplayFromStr, _)>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_blob_count: Option<u64>,
+}
+
+impl ValidatorPreferences {
+    /// Preferences satisfying both self and other.
+    pub fn merge(&self, other: &ValidatorPreferences) -> ValidatorPreferences {
+        let max_blob_count = match (self.max_blob_count, other.max_blob_count) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        ValidatorPreferences { max_blob_count }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -278,9 +319,28 @@ pub enum SubmitBlockErr {
     PayloadDelivered,
     #[error("Bid below floor")]
     BidBelowFloor,
-    #[cfg_attr(not(feature = "redact-sensitive"), error("Simulation Error: {0}"))]
+    #[cfg_attr(
+        not(feature = "redact-sensitive"),
+        error("Simulation Error: {message}")
+    )]
     #[cfg_attr(feature = "redact-sensitive", error("Simulation Error: [REDACTED]"))]
-    SimError(String),
+    SimError {
+        rejection: RelayRejection,
+        message: String,
+    },
+    /// Known relay error outside the simulation (eg: blobs, signature).
+    #[cfg_attr(
+        not(feature = "redact-sensitive"),
+        error("Relay rejected the block: {message}")
+    )]
+    #[cfg_attr(
+        feature = "redact-sensitive",
+        error("Relay rejected the block: [REDACTED]")
+    )]
+    Rejected {
+        rejection: RelayRejection,
+        message: String,
+    },
     #[error("RPC conversion Error")]
     /// RPC validates the submissions (eg: limit of txs) much more that our model.
     RPCConversionError(Error),
@@ -297,6 +357,8 @@ pub enum SubmitBlockErr {
     InvalidHeader,
     #[error("Block known")]
     BlockKnown,
+    #[error("Relay requires a payment proof but the block has none")]
+    MissingPaymentProof,
 }
 
 impl std::fmt::Debug for SubmitBlockErr {
@@ -358,10 +420,10 @@ impl RelayClient {
             .await
     }
 
-    async fn get_one_builder_block_received(
+    async fn get_builder_blocks_received(
         &self,
         query: &str,
-    ) -> Result<Option<BuilderBlockReceived>, RelayError> {
+    ) -> Result<Vec<BuilderBlockReceived>, RelayError> {
         let url = {
             let mut url = self.url.clone();
             url.set_path("/relay/v1/data/bidtraces/builder_blocks_received");
@@ -375,16 +437,35 @@ impl RelayClient {
             .await?;
 
         match payloads {
-            RelayResponse::Ok(payloads) => {
-                if payloads.is_empty() {
-                    return Ok(None);
-                }
-                Ok(Some(payloads[0].clone()))
-            }
+            RelayResponse::Ok(payloads) => Ok(payloads),
             RelayResponse::Error(error) => Err(RelayError::RelayError(error)),
         }
     }
 
+    async fn get_one_builder_block_received(
+        &self,
+        query: &str,
+    ) -> Result<Option<BuilderBlockReceived>, RelayError> {
+        Ok(self
+            .get_builder_blocks_received(query)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Bids of builder_pubkey received by the relay for the slot.
+    pub async fn builder_blocks_received_slot(
+        &self,
+        slot: u64,
+        builder_pubkey: H384,
+    ) -> Result<Vec<BuilderBlockReceived>, RelayError> {
+        self.get_builder_blocks_received(&format!(
+            "slot={}&builder_pubkey={:?}",
+            slot, builder_pubkey
+        ))
+        .await
+    }
+
     pub async fn builder_block_received_block_hash(
         &self,
         block_hash: BlockHash,
@@ -448,11 +529,14 @@ impl RelayClient {
     }
 
     /// Mainly takes care of ssz/json raw/gzip
+    /// payment_proof is only supported on json.
     async fn call_relay_submit_block(
         &self,
-        data: &SubmitBlockRequest,
+        data: &Arc<SubmitBlockRequest>,
         ssz: bool,
         gzip: bool,
+        payment_proof: Option<&ProposerPaymentProof>,
+        receipt: &mut SubmitBlockReceipt,
     ) -> Result<Response, SubmitBlockErr> {
         let url = {
             let mut url = self.url.clone();
@@ -463,58 +547,83 @@ impl RelayClient {
         let mut builder = self.client.post(url.clone());
         let mut headers = HeaderMap::new();
         // SSZ vs JSON
-        let (mut body_data, content_type) = if ssz {
-            (
-                match data {
-                    SubmitBlockRequest::Capella(data) => data.0.as_ssz_bytes(),
-                    SubmitBlockRequest::Deneb(data) => data.0.as_ssz_bytes(),
-                    SubmitBlockRequest::Electra(data) => data.0.as_ssz_bytes(),
-                },
-                SSZ_CONTENT_TYPE,
-            )
+        let content_type = if ssz {
+            SSZ_CONTENT_TYPE
         } else {
-            (
-                serde_json::to_vec(&data)
-                    .map_err(|e| SubmitBlockErr::RPCSerializationError(e.to_string()))?,
-                JSON_CONTENT_TYPE,
-            )
+            JSON_CONTENT_TYPE
         };
         headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
         self.add_auth_headers(&mut headers)
             .map_err(|_| SubmitBlockErr::InvalidHeader)?;
-
         // GZIP
         if gzip {
             headers.insert(
                 CONTENT_ENCODING,
                 HeaderValue::from_static(GZIP_CONTENT_ENCODING),
             );
-            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-            encoder
-                .write_all(&body_data)
-                .map_err(|e| SubmitBlockErr::RPCSerializationError(e.to_string()))?;
-            body_data = encoder
-                .finish()
-                .map_err(|e| SubmitBlockErr::RPCSerializationError(e.to_string()))?;
         }
 
-        builder = builder.headers(headers).body(Body::from(body_data));
+        let buffered = Arc::new(BufferedBytes::default());
+        let res = if self.streaming_submit {
+            if payment_proof.is_some() && ssz {
+                return Err(SubmitBlockErr::RPCSerializationError(
+                    "payment proof can't be sent using ssz".to_string(),
+                ));
+            }
+            let (body, mut payload_hash) = streaming_body(
+                data.clone(),
+                payment_proof.cloned(),
+                ssz,
+                gzip,
+                buffered.clone(),
+            );
+            let res = builder.headers(headers).body(body).send().await;
+            // only there if the whole body was taken by the connection
+            receipt.payload_hash = payload_hash.try_recv().ok();
+            res
+        } else {
+            let (body, payload_hash) = buffered_body(data, payment_proof, ssz, gzip, &buffered)?;
+            receipt.payload_hash = Some(payload_hash);
+            builder = builder.headers(headers).body(Body::from(body));
+            builder.send().await
+        };
+        receipt.body_peak_bytes = Some(buffered.peak());
 
-        Ok(builder
-            .send()
-            .await
-            .map_err(|e| RelayError::RequestError(e.into()))?)
+        Ok(res.map_err(|e| RelayError::RequestError(e.into()))?)
     }
 
     /// Submits the block (call_relay_submit_block) and processes some special errors.
     pub async fn submit_block(
         &self,
-        data: &SubmitBlockRequest,
+        data: &Arc<SubmitBlockRequest>,
         ssz: bool,
         gzip: bool,
+        payment_proof: Option<&ProposerPaymentProof>,
     ) -> Result<(), SubmitBlockErr> {
-        let resp = self.call_relay_submit_block(data, ssz, gzip).await?;
+        self.submit_block_with_receipt(
+            data,
+            ssz,
+            gzip,
+            payment_proof,
+            &mut SubmitBlockReceipt::default(),
+        )
+        .await
+    }
+
+    /// Like submit_block but also fills receipt with what was sent and answered (as far as we got).
+    pub async fn submit_block_with_receipt(
+        &self,
+        data: &Arc<SubmitBlockRequest>,
+        ssz: bool,
+        gzip: bool,
+        payment_proof: Option<&ProposerPaymentProof>,
+        receipt: &mut SubmitBlockReceipt,
+    ) -> Result<(), SubmitBlockErr> {
+        let resp = self
+            .call_relay_submit_block(data, ssz, gzip, payment_proof, receipt)
+            .await?;
         let status = resp.status();
+        receipt.status = Some(status.as_u16());
 
         if status == StatusCode::TOO_MANY_REQUESTS {
             return Err(RelayError::TooManyRequests.into());
@@ -527,6 +636,7 @@ impl RelayClient {
             .bytes()
             .await
             .map_err(|e| RelayError::RequestError(e.into()))?;
+        receipt.response_digest = Some(B256::from_slice(&Sha256::digest(&data)));
 
         if status == StatusCode::OK && data.as_ref() == b"" {
             return Ok(());
@@ -558,13 +668,22 @@ impl RelayClient {
                         {
                             Err(RelayError::InternalError.into())
                         } else {
-                            Err(SubmitBlockErr::SimError(msg.to_string()))
+                            Err(SubmitBlockErr::SimError {
+                                rejection: RelayRejection::parse(msg),
+                                message: msg.to_string(),
+                            })
                         }
                     }
                     _ if msg.contains("request timeout hit") => {
                         Err(RelayError::ConnectionError.into())
                     }
-                    _ => Err(RelayError::RelayError(error).into()),
+                    _ => match RelayRejection::parse(msg) {
+                        RelayRejection::Unknown => Err(RelayError::RelayError(error).into()),
+                        rejection => Err(SubmitBlockErr::Rejected {
+                            rejection,
+                            message: msg.to_string(),
+                        }),
+                    },
                 }
             }
             Err(_) => {
@@ -599,6 +718,19 @@ impl RelayClient {
     }
 }
 
+/// What we sent to the relay and what it answered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubmitBlockReceipt {
+    /// sha256 of the request body before compression. None if we failed before encoding it.
+    pub payload_hash: Option<B256>,
+    /// None if we got no response.
+    pub status: Option<u16>,
+    /// sha256 of the response body.
+    pub response_digest: Option<B256>,
+    /// Max bytes of the request body we had in memory at once (see [`payload_body`]).
+    pub body_peak_bytes: Option<usize>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ElectraSubmitBlockRequest(SignedBidSubmissionV4);
 
@@ -622,6 +754,14 @@ pub enum SubmitBlockRequest {
     Electra(ElectraSubmitBlockRequest),
 }
 
+/// Json submission extended with the proof for relays that require it.
+#[derive(Debug, Serialize)]
+pub struct SubmitBlockRequestWithPaymentProof<'a> {
+    #[serde(flatten)]
+    pub request: &'a SubmitBlockRequest,
+    pub proposer_payment_proof: &'a ProposerPaymentProof,
+}
+
 impl SubmitBlockRequest {
     pub fn bid_trace(&self) -> BidTrace {
         match self {
@@ -630,6 +770,27 @@ impl SubmitBlockRequest {
             SubmitBlockRequest::Electra(req) => req.0.message.clone(),
         }
     }
+
+    pub fn builder_pubkey(&self) -> H384 {
+        H384::from_slice(self.bid_trace().builder_pubkey.as_slice())
+    }
+
+    pub fn fork(&self) -> PayloadFork {
+        match self {
+            SubmitBlockRequest::Capella(_) => PayloadFork::Capella,
+            SubmitBlockRequest::Deneb(_) => PayloadFork::Deneb,
+            SubmitBlockRequest::Electra(_) => PayloadFork::Electra,
+        }
+    }
+
+    /// BLS signature of the bid trace.
+    pub fn signature(&self) -> FixedBytes<96> {
+        match self {
+            SubmitBlockRequest::Capella(req) => req.0.signature,
+            SubmitBlockRequest::Deneb(req) => req.0.signature,
+            SubmitBlockRequest::Electra(req) => req.0.signature,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -781,10 +942,78 @@ mod tests {
 
         let relay_url = Url::from_str(&srv.endpoint()).unwrap();
         let relay = RelayClient::from_url(relay_url, None, None, None);
-        let sub_relay = SubmitBlockRequest::Deneb(generator.create_deneb_submit_block_request());
+        let sub_relay = Arc::new(SubmitBlockRequest::Deneb(
+            generator.create_deneb_submit_block_request(),
+        ));
+        relay
+            .submit_block(&sub_relay, true, true, None)
+            .await
+            .expect("OPS!");
         relay
-            .submit_block(&sub_relay, true, true)
+            .with_streaming_submit(true)
+            .submit_block(&sub_relay, false, true, None)
             .await
             .expect("OPS!");
     }
+
+    #[test]
+    fn test_validator_slot_data_preferences() {
+        let registration = r#"{"slot":"10","validator_index":"5","entry":{"message":{"fee_recipient":"0x0000000000000000000000000000000000000001","gas_limit":"30000000","timestamp":"1","pubkey":"0x111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111"},"signature":"0x222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222"}"#;
+        let no_preferences: ValidatorSlotData =
+            serde_json::from_str(&format!("{}}}", registration)).unwrap();
+        assert_eq!(no_preferences.preferences, None);
+
+        let with_preferences: ValidatorSlotData = serde_json::from_str(&format!(
+            r#"{},"preferences":{{"max_blob_count":"3","filtering":"global"}}}}"#,
+            registration
+        ))
+        .unwrap();
+        assert_eq!(
+            with_preferences.preferences,
+            Some(ValidatorPreferences {
+                max_blob_count: Some(3)
+            })
+        );
+
+        let a = ValidatorPreferences {
+            max_blob_count: Some(3),
+        };
+        let b = ValidatorPreferences {
+            max_blob_count: Some(2),
+        };
+        assert_eq!(a.merge(&b), b);
+        assert_eq!(a.merge(&ValidatorPreferences::default()), a);
+    }
+
+    #[test]
+    fn test_submission_with_payment_proof_json() {
+        use crate::roothash::payment_proof::AccountStateProof;
+
+        let mut generator = TestDataGenerator::default();
+        let request = SubmitBlockRequest::Deneb(generator.create_deneb_submit_block_request());
+        let account = AccountStateProof {
+            balance: U256::from(1),
+            nonce: 0,
+            code_hash: Default::default(),
+            storage_root: Default::default(),
+            account_proof: vec![],
+        };
+        let proof = ProposerPaymentProof {
+            fee_recipient: Address::ZERO,
+            pre_state: account.clone(),
+            post_state: account,
+        };
+        let json = serde_json::to_value(SubmitBlockRequestWithPaymentProof {
+            request: &request,
+            proposer_payment_proof: &proof,
+        })
+        .unwrap();
+        // same fields as the plain submission + the proof
+        assert!(json["message"].is_object());
+        assert!(json["execution_payload"].is_object());
+        assert_eq!(
+            json["proposer_payment_proof"]["feeRecipient"],
+            serde_json::to_value(Address::ZERO).unwrap()
+        );
+    }
 }
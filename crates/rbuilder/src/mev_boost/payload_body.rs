@@ -0,0 +1,296 @@
+//! Encoding of the submit block request body.
+//! Buffered: the whole body (and its gzipped copy) is built in memory before sending it.
+//! Streaming: the body is encoded on a blocking thread into [`PAYLOAD_CHUNK_SIZE`] chunks that are sent
+//! (chunked transfer encoding) as the connection takes them, at most [`MAX_PENDING_CHUNKS`] chunks wait in memory.
+//! SSZ can't be encoded incrementally so for ssz the encoded block is kept until it's fully written.
+
+use super::{SubmitBlockErr, SubmitBlockRequest, SubmitBlockRequestWithPaymentProof};
+use crate::roothash::payment_proof::ProposerPaymentProof;
+use alloy_primitives::B256;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::Body;
+use sha2::{Digest, Sha256};
+use ssz::Encode;
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+pub const PAYLOAD_CHUNK_SIZE: usize = 64 * 1024;
+pub const MAX_PENDING_CHUNKS: usize = 4;
+
+/// Body bytes held in memory by an encoding, current and max.
+#[derive(Debug, Default)]
+pub struct BufferedBytes {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl BufferedBytes {
+    fn add(&self, bytes: usize) {
+        let current = self.current.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn sub(&self, bytes: usize) {
+        self.current.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+/// sha256 of everything written (the uncompressed body).
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> (W, B256) {
+        (self.inner, B256::from_slice(&self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Cuts what's written into chunks and sends them to the body stream, blocks while the stream has
+/// MAX_PENDING_CHUNKS chunks waiting.
+struct ChunkSender {
+    chunk: Vec<u8>,
+    sender: mpsc::Sender<io::Result<Vec<u8>>>,
+    buffered: Arc<BufferedBytes>,
+}
+
+impl ChunkSender {
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(PAYLOAD_CHUNK_SIZE));
+        let len = chunk.len();
+        self.buffered.add(len);
+        self.sender.blocking_send(Ok(chunk)).map_err(|_| {
+            self.buffered.sub(len);
+            io::Error::new(io::ErrorKind::BrokenPipe, "relay request body dropped")
+        })
+    }
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(PAYLOAD_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        if self.chunk.len() == PAYLOAD_CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(len)
+    }
+
+    /// Only sends full chunks, the last one is sent by send_chunk when we are done.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn serialization_error(err: impl ToString) -> SubmitBlockErr {
+    SubmitBlockErr::RPCSerializationError(err.to_string())
+}
+
+/// Writes the uncompressed body (json or ssz). payment_proof is only supported on json.
+pub fn write_body<W: Write>(
+    data: &SubmitBlockRequest,
+    payment_proof: Option<&ProposerPaymentProof>,
+    ssz: bool,
+    writer: &mut W,
+    buffered: &BufferedBytes,
+) -> Result<(), SubmitBlockErr> {
+    if let Some(payment_proof) = payment_proof {
+        if ssz {
+            return Err(SubmitBlockErr::RPCSerializationError(
+                "payment proof can't be sent using ssz".to_string(),
+            ));
+        }
+        serde_json::to_writer(
+            writer,
+            &SubmitBlockRequestWithPaymentProof {
+                request: data,
+                proposer_payment_proof: payment_proof,
+            },
+        )
+        .map_err(serialization_error)
+    } else if ssz {
+        let body = match data {
+            SubmitBlockRequest::Capella(data) => data.0.as_ssz_bytes(),
+            SubmitBlockRequest::Deneb(data) => data.0.as_ssz_bytes(),
+            SubmitBlockRequest::Electra(data) => data.0.as_ssz_bytes(),
+        };
+        buffered.add(body.len());
+        let res = writer.write_all(&body).map_err(serialization_error);
+        buffered.sub(body.len());
+        res
+    } else {
+        serde_json::to_writer(writer, data).map_err(serialization_error)
+    }
+}
+
+/// Whole body in memory. Returns the body to send (gzipped if gzip) and the sha256 of the uncompressed one.
+pub fn buffered_body(
+    data: &SubmitBlockRequest,
+    payment_proof: Option<&ProposerPaymentProof>,
+    ssz: bool,
+    gzip: bool,
+    buffered: &BufferedBytes,
+) -> Result<(Vec<u8>, B256), SubmitBlockErr> {
+    let mut writer = HashingWriter::new(Vec::new());
+    write_body(data, payment_proof, ssz, &mut writer, buffered)?;
+    let (body, payload_hash) = writer.finish();
+    buffered.add(body.len());
+    if !gzip {
+        return Ok((body, payload_hash));
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&body).map_err(serialization_error)?;
+    let compressed = encoder.finish().map_err(serialization_error)?;
+    buffered.add(compressed.len());
+    Ok((compressed, payload_hash))
+}
+
+/// Streamed body (gzipped if gzip) and the sha256 of the uncompressed body that is available once the body
+/// stream has been fully consumed.
+/// Encoding errors end the stream with an error so the request fails instead of sending a truncated body.
+pub fn streaming_body(
+    data: Arc<SubmitBlockRequest>,
+    payment_proof: Option<ProposerPaymentProof>,
+    ssz: bool,
+    gzip: bool,
+    buffered: Arc<BufferedBytes>,
+) -> (Body, oneshot::Receiver<B256>) {
+    let (sender, receiver) = mpsc::channel(MAX_PENDING_CHUNKS);
+    let (hash_sender, hash_receiver) = oneshot::channel();
+    let error_sender = sender.clone();
+    let chunks = ChunkSender {
+        chunk: Vec::with_capacity(PAYLOAD_CHUNK_SIZE),
+        sender,
+        buffered: buffered.clone(),
+    };
+    let stream_buffered = buffered.clone();
+    tokio::task::spawn_blocking(move || {
+        let encode = || -> Result<(), SubmitBlockErr> {
+            // the hash is published before the last chunks so it's there when the stream ends
+            let mut chunks = if gzip {
+                let mut writer = HashingWriter::new(GzEncoder::new(chunks, Compression::default()));
+                write_body(&data, payment_proof.as_ref(), ssz, &mut writer, &buffered)?;
+                let (encoder, payload_hash) = writer.finish();
+                let _ = hash_sender.send(payload_hash);
+                encoder.finish().map_err(serialization_error)?
+            } else {
+                let mut writer = HashingWriter::new(chunks);
+                write_body(&data, payment_proof.as_ref(), ssz, &mut writer, &buffered)?;
+                let (chunks, payload_hash) = writer.finish();
+                let _ = hash_sender.send(payload_hash);
+                chunks
+            };
+            chunks.send_chunk().map_err(serialization_error)
+        };
+        if let Err(err) = encode() {
+            let _ = error_sender.blocking_send(Err(io::Error::other(err.to_string())));
+        }
+    });
+    let stream = ReceiverStream::new(receiver).map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            stream_buffered.sub(chunk.len());
+        }
+        chunk
+    });
+    (Body::wrap_stream(stream), hash_receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn chunk_sender(
+        buffered: &Arc<BufferedBytes>,
+    ) -> (ChunkSender, mpsc::Receiver<io::Result<Vec<u8>>>) {
+        let (sender, receiver) = mpsc::channel(MAX_PENDING_CHUNKS);
+        (
+            ChunkSender {
+                chunk: Vec::new(),
+                sender,
+                buffered: buffered.clone(),
+            },
+            receiver,
+        )
+    }
+
+    #[test]
+    fn test_chunked_gzip_and_hash() {
+        let data: Vec<u8> = (0..3 * PAYLOAD_CHUNK_SIZE as u32 + 10)
+            .flat_map(|i| i.to_le_bytes())
+            .collect();
+        let buffered = Arc::new(BufferedBytes::default());
+        let (chunks, mut receiver) = chunk_sender(&buffered);
+        let consumer_buffered = buffered.clone();
+        let consumer = std::thread::spawn(move || {
+            let mut body = Vec::new();
+            while let Some(chunk) = receiver.blocking_recv() {
+                let chunk = chunk.unwrap();
+                assert!(chunk.len() <= PAYLOAD_CHUNK_SIZE);
+                consumer_buffered.sub(chunk.len());
+                body.extend(chunk);
+            }
+            body
+        });
+
+        let mut writer = HashingWriter::new(GzEncoder::new(chunks, Compression::default()));
+        writer.write_all(&data).unwrap();
+        let (encoder, payload_hash) = writer.finish();
+        let mut chunks = encoder.finish().unwrap();
+        chunks.send_chunk().unwrap();
+        drop(chunks);
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(consumer.join().unwrap().as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(payload_hash, B256::from_slice(&Sha256::digest(&data)));
+        assert!(buffered.peak() <= MAX_PENDING_CHUNKS * PAYLOAD_CHUNK_SIZE + PAYLOAD_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_chunk_sender_dropped_body() {
+        let buffered = Arc::new(BufferedBytes::default());
+        let (mut chunks, receiver) = chunk_sender(&buffered);
+        drop(receiver);
+        let err = chunks.write_all(&[0; PAYLOAD_CHUNK_SIZE]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(buffered.current.load(Ordering::Relaxed), 0);
+    }
+}
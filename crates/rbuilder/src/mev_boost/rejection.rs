@@ -0,0 +1,180 @@
+//! Typed version of the relay error messages we know how to act on.
+//! Relays (mostly the flashbots validation node) only give us free text so, like the rest of the error handling, this
+//! is substring matching, anything we don't recognize is [`RelayRejection::Unknown`].
+
+use alloy_primitives::B256;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayRejection {
+    /// A tx of the block failed on the relay simulation (eg: "could not apply tx 3 [0x..]: nonce too low").
+    /// tx_hash is None if the relay didn't say which one.
+    TxFailed {
+        tx_hash: Option<B256>,
+    },
+    /// The proposer payment the relay saw doesn't match our bid.
+    PaymentMismatch,
+    /// Blobs bundle problems (commitments, proofs, versioned hashes...).
+    Blobs,
+    /// The relay doesn't accept the signature of the submission.
+    InvalidSignature,
+    Unknown,
+}
+
+/// What we can do about a rejection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remediation {
+    /// Keep building the slot without the orders containing the tx.
+    RebuildWithout(B256),
+    /// Stop submitting to this relay for the rest of the slot, other relays may accept our blocks.
+    SkipRelay,
+    /// Nothing to fix on the fly: stop building the slot.
+    StopSlot,
+}
+
+const TX_FAILED_PATTERNS: &[&str] = &["could not apply tx", "tx reverted", "transaction reverted"];
+const PAYMENT_MISMATCH_PATTERNS: &[&str] = &[
+    "inaccurate payment",
+    "payment tx",
+    "proposer payment",
+    "balance difference",
+];
+const BLOBS_PATTERNS: &[&str] = &["blob", "kzg", "commitment", "versioned hash"];
+
+/// First 0x prefixed 32 bytes hex in msg.
+fn find_hash(msg: &str) -> Option<B256> {
+    msg.match_indices("0x").find_map(|(start, _)| {
+        let hex = msg.get(start..start + 66)?;
+        B256::from_str(hex).ok()
+    })
+}
+
+/// Hash of the failed tx: the first hash after the tx failure pattern, hashes before it (eg: the block hash) are not
+/// the tx.
+fn find_tx_hash(msg: &str, msg_lowercase: &str) -> Option<B256> {
+    let pattern_end = TX_FAILED_PATTERNS
+        .iter()
+        .filter_map(|pat| msg_lowercase.find(pat).map(|start| start + pat.len()))
+        .min()?;
+    find_hash(msg.get(pattern_end..)?)
+}
+
+impl RelayRejection {
+    pub fn parse(msg: &str) -> Self {
+        let msg_lowercase = msg.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|pat| msg_lowercase.contains(pat));
+        // payment first: "payment tx reverted" is not one of our txs
+        if matches(PAYMENT_MISMATCH_PATTERNS) {
+            RelayRejection::PaymentMismatch
+        } else if matches(TX_FAILED_PATTERNS) {
+            RelayRejection::TxFailed {
+                tx_hash: find_tx_hash(msg, &msg_lowercase),
+            }
+        } else if matches(BLOBS_PATTERNS) {
+            RelayRejection::Blobs
+        } else if msg_lowercase.contains("invalid signature") {
+            RelayRejection::InvalidSignature
+        } else {
+            RelayRejection::Unknown
+        }
+    }
+
+    pub fn remediation(&self) -> Remediation {
+        match self {
+            RelayRejection::TxFailed {
+                tx_hash: Some(tx_hash),
+            } => Remediation::RebuildWithout(*tx_hash),
+            RelayRejection::TxFailed { tx_hash: None } => Remediation::StopSlot,
+            RelayRejection::PaymentMismatch => Remediation::StopSlot,
+            RelayRejection::Blobs => Remediation::SkipRelay,
+            // the signing key/domain needs a config change, other relays may still accept our blocks
+            RelayRejection::InvalidSignature => Remediation::SkipRelay,
+            RelayRejection::Unknown => Remediation::StopSlot,
+        }
+    }
+
+    /// Short name for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RelayRejection::TxFailed { .. } => "tx_failed",
+            RelayRejection::PaymentMismatch => "payment_mismatch",
+            RelayRejection::Blobs => "blobs",
+            RelayRejection::InvalidSignature => "invalid_signature",
+            RelayRejection::Unknown => "unknown",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_relay_rejection() {
+        let tx_hash = B256::with_last_byte(0xab);
+        let rejection = RelayRejection::parse(&format!(
+            "simulation failed: could not apply tx 3 [{:?}]: nonce too low: address 0x1, tx: 5 state: 6",
+            tx_hash
+        ));
+        assert_eq!(
+            rejection,
+            RelayRejection::TxFailed {
+                tx_hash: Some(tx_hash)
+            }
+        );
+        assert_eq!(
+            rejection.remediation(),
+            Remediation::RebuildWithout(tx_hash)
+        );
+
+        let rejection = RelayRejection::parse("simulation failed: transaction reverted");
+        assert_eq!(rejection, RelayRejection::TxFailed { tx_hash: None });
+        assert_eq!(rejection.remediation(), Remediation::StopSlot);
+
+        assert_eq!(
+            RelayRejection::parse("simulation failed: payment tx reverted"),
+            RelayRejection::PaymentMismatch
+        );
+        assert_eq!(
+            RelayRejection::parse("simulation failed: inaccurate payment 10, expected 12"),
+            RelayRejection::PaymentMismatch
+        );
+        assert_eq!(
+            RelayRejection::parse("invalid blobs bundle: KZG proof mismatch"),
+            RelayRejection::Blobs
+        );
+        assert_eq!(
+            RelayRejection::parse("invalid signature"),
+            RelayRejection::InvalidSignature
+        );
+        assert_eq!(
+            RelayRejection::InvalidSignature.remediation(),
+            Remediation::SkipRelay
+        );
+        assert_eq!(
+            RelayRejection::parse("simulation failed: something new"),
+            RelayRejection::Unknown
+        );
+        // truncated hash
+        assert_eq!(find_hash("tx [0xabcd]"), None);
+
+        // the block hash before the tx one is not the failed tx
+        let block_hash = B256::with_last_byte(0xcd);
+        assert_eq!(
+            RelayRejection::parse(&format!(
+                "block {:?} simulation failed: could not apply tx 0 [{:?}]: nonce too low",
+                block_hash, tx_hash
+            )),
+            RelayRejection::TxFailed {
+                tx_hash: Some(tx_hash)
+            }
+        );
+        assert_eq!(
+            RelayRejection::parse(&format!(
+                "block {:?} simulation failed: could not apply tx 0: nonce too low",
+                block_hash
+            )),
+            RelayRejection::TxFailed { tx_hash: None }
+        );
+    }
+}
@@ -3,7 +3,7 @@ use super::{
     SubmitBlockRequest,
 };
 use crate::utils::u256decimal_serde_helper;
-use alloy_eips::eip2718::Encodable2718;
+use alloy_eips::{eip2718::Encodable2718, merge::SLOT_DURATION};
 use alloy_primitives::{Address, BlockHash, Bytes, FixedBytes, B256, U256};
 use alloy_rpc_types_beacon::{
     events::PayloadAttributesData,
@@ -26,6 +26,8 @@ use reth_primitives::{BlobTransactionSidecar, SealedBlock};
 use serde_with::{serde_as, DisplayFromStr};
 use std::sync::Arc;
 
+const SLOTS_PER_EPOCH: u64 = 32;
+
 /// Object to sign blocks to be sent to relays.
 #[derive(Debug, Clone)]
 pub struct BLSBlockSigner {
@@ -108,10 +110,61 @@ fn a2e_address(a: &Address) -> ExecutionAddress {
     ExecutionAddress::try_from(a.as_slice()).unwrap()
 }
 
+/// Fork that decides the payload/bid containers of a submission: Capella (SignedBidSubmissionV2), Deneb (V3, adds the
+/// blobs bundle and blob gas fields) and Electra (V4, adds the execution requests).
+/// Forks activate on epoch boundaries so the fork is chosen per slot epoch and every slot of an epoch gets the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PayloadFork {
+    Capella,
+    Deneb,
+    Electra,
+}
+
+impl PayloadFork {
+    /// Fork of the epoch of slot, the one active at the first slot of the epoch.
+    /// slot_timestamp: timestamp of slot (eg: of the block built for it), used to get the timestamps of the epoch.
+    pub fn for_slot(chain_spec: &ChainSpec, slot: u64, slot_timestamp: u64) -> Self {
+        let epoch_start_timestamp =
+            slot_timestamp.saturating_sub((slot % SLOTS_PER_EPOCH) * SLOT_DURATION.as_secs());
+        Self::at_timestamp(chain_spec, epoch_start_timestamp)
+    }
+
+    fn at_timestamp(chain_spec: &ChainSpec, timestamp: u64) -> Self {
+        if chain_spec.is_prague_active_at_timestamp(timestamp) {
+            PayloadFork::Electra
+        } else if chain_spec.is_cancun_active_at_timestamp(timestamp) {
+            PayloadFork::Deneb
+        } else {
+            PayloadFork::Capella
+        }
+    }
+
+    /// Checks that the block and the execution requests fit in the containers of the fork.
+    /// Capella containers have no blobs bundle, blobs given for them are not sent.
+    fn check_payload(
+        self,
+        sealed_block: &SealedBlock,
+        execution_requests: &[Bytes],
+    ) -> eyre::Result<()> {
+        if self >= PayloadFork::Deneb
+            && (sealed_block.blob_gas_used.is_none() || sealed_block.excess_blob_gas.is_none())
+        {
+            eyre::bail!("{:?} block without blob gas used/excess blob gas", self);
+        }
+        if self < PayloadFork::Electra && !execution_requests.is_empty() {
+            eyre::bail!("{:?} payload can't contain execution requests", self);
+        }
+        Ok(())
+    }
+}
+
+/// The payload containers follow the fork of the epoch of the slot (see [`PayloadFork::for_slot`]).
 #[allow(clippy::too_many_arguments)]
 pub fn sign_block_for_relay(
     signer: &BLSBlockSigner,
     sealed_block: &SealedBlock,
+    // Canonical envelopes of the sealed_block txs. If empty they are encoded from sealed_block.
+    encoded_txs: &[Bytes],
     blobs_bundle: &[Arc<BlobTransactionSidecar>],
     execution_requests: &[Bytes], // The Pectra execution requests for this bid.
     chain_spec: &ChainSpec,
@@ -119,6 +172,9 @@ pub fn sign_block_for_relay(
     pubkey: H384,
     value: U256,
 ) -> eyre::Result<SubmitBlockRequest> {
+    let fork = PayloadFork::for_slot(chain_spec, attrs.proposal_slot, sealed_block.timestamp);
+    fork.check_payload(sealed_block, execution_requests)?;
+
     let message = BidTrace {
         slot: attrs.proposal_slot,
         parent_hash: attrs.parent_block_hash,
@@ -149,16 +205,20 @@ pub fn sign_block_for_relay(
             extra_data: sealed_block.extra_data.clone(),
             base_fee_per_gas: U256::from(sealed_block.base_fee_per_gas.unwrap_or_default()),
             block_hash: sealed_block.hash(),
-            transactions: sealed_block
-                .body
-                .transactions
-                .iter()
-                .map(|tx| {
-                    let mut buf = Vec::new();
-                    tx.encode_2718(&mut buf);
-                    buf.into()
-                })
-                .collect(),
+            transactions: if encoded_txs.len() == sealed_block.body.transactions.len() {
+                encoded_txs.to_vec()
+            } else {
+                sealed_block
+                    .body
+                    .transactions
+                    .iter()
+                    .map(|tx| {
+                        let mut buf = Vec::new();
+                        tx.encode_2718(&mut buf);
+                        buf.into()
+                    })
+                    .collect()
+            },
         },
         withdrawals: sealed_block
             .body
@@ -177,20 +237,21 @@ pub fn sign_block_for_relay(
             .unwrap_or_default(),
     };
 
-    let submit_block_request = if chain_spec.is_cancun_active_at_timestamp(sealed_block.timestamp) {
+    let submit_block_request = if fork == PayloadFork::Capella {
+        SubmitBlockRequest::Capella(CapellaSubmitBlockRequest(SignedBidSubmissionV2 {
+            message,
+            execution_payload: capella_payload,
+            signature,
+        }))
+    } else {
+        // checked by check_payload
         let execution_payload = ExecutionPayloadV3 {
             payload_inner: capella_payload,
-            blob_gas_used: sealed_block
-                .blob_gas_used
-                .expect("deneb block does not have blob gas used"),
-            excess_blob_gas: sealed_block
-                .excess_blob_gas
-                .expect("deneb block does not have excess blob gas"),
+            blob_gas_used: sealed_block.blob_gas_used.unwrap_or_default(),
+            excess_blob_gas: sealed_block.excess_blob_gas.unwrap_or_default(),
         };
-
         let blobs_bundle = marshal_txs_blobs_sidecars(blobs_bundle);
-
-        if chain_spec.is_prague_active_at_timestamp(sealed_block.timestamp) {
+        if fork == PayloadFork::Electra {
             SubmitBlockRequest::Electra(ElectraSubmitBlockRequest(SignedBidSubmissionV4 {
                 message,
                 execution_payload,
@@ -206,13 +267,6 @@ pub fn sign_block_for_relay(
                 signature,
             }))
         }
-    } else {
-        let execution_payload = capella_payload;
-        SubmitBlockRequest::Capella(CapellaSubmitBlockRequest(SignedBidSubmissionV2 {
-            message,
-            execution_payload,
-            signature,
-        }))
     };
 
     Ok(submit_block_request)
@@ -241,6 +295,144 @@ fn marshal_txs_blobs_sidecars(txs_blobs_sidecars: &[Arc<BlobTransactionSidecar>]
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::mev_boost::rpc::TestDataGenerator;
+    use reth_chainspec::{ChainSpecBuilder, EthereumHardfork, ForkCondition, MAINNET};
+    use reth_primitives::SealedHeader;
+
+    /// Mainnet beacon chain genesis.
+    const GENESIS_TIME: u64 = 1_606_824_023;
+    const DENEB_EPOCH: u64 = 10;
+    const ELECTRA_EPOCH: u64 = 20;
+
+    fn slot_timestamp(slot: u64) -> u64 {
+        GENESIS_TIME + slot * SLOT_DURATION.as_secs()
+    }
+
+    fn chain_spec_with_forks(cancun_timestamp: u64, prague_timestamp: u64) -> ChainSpec {
+        ChainSpecBuilder::default()
+            .chain(MAINNET.chain)
+            .genesis(MAINNET.genesis.clone())
+            .shanghai_activated()
+            .with_fork(
+                EthereumHardfork::Cancun,
+                ForkCondition::Timestamp(cancun_timestamp),
+            )
+            .with_fork(
+                EthereumHardfork::Prague,
+                ForkCondition::Timestamp(prague_timestamp),
+            )
+            .build()
+    }
+
+    fn fork_chain_spec() -> ChainSpec {
+        chain_spec_with_forks(
+            slot_timestamp(DENEB_EPOCH * SLOTS_PER_EPOCH),
+            slot_timestamp(ELECTRA_EPOCH * SLOTS_PER_EPOCH),
+        )
+    }
+
+    fn block_at_slot(slot: u64, blob_gas: bool) -> SealedBlock {
+        let mut block = SealedBlock::default();
+        let mut header = block.header().clone();
+        header.timestamp = slot_timestamp(slot);
+        if blob_gas {
+            header.blob_gas_used = Some(0);
+            header.excess_blob_gas = Some(0);
+        }
+        block.header = SealedHeader::new(header, BlockHash::default());
+        block
+    }
+
+    #[test]
+    fn test_payload_fork_boundaries() {
+        let chain_spec = fork_chain_spec();
+        let fork = |slot: u64| PayloadFork::for_slot(&chain_spec, slot, slot_timestamp(slot));
+        assert_eq!(fork(0), PayloadFork::Capella);
+        assert_eq!(
+            fork(DENEB_EPOCH * SLOTS_PER_EPOCH - 1),
+            PayloadFork::Capella
+        );
+        assert_eq!(fork(DENEB_EPOCH * SLOTS_PER_EPOCH), PayloadFork::Deneb);
+        assert_eq!(
+            fork(ELECTRA_EPOCH * SLOTS_PER_EPOCH - 1),
+            PayloadFork::Deneb
+        );
+        assert_eq!(fork(ELECTRA_EPOCH * SLOTS_PER_EPOCH), PayloadFork::Electra);
+        assert_eq!(
+            fork(ELECTRA_EPOCH * SLOTS_PER_EPOCH * 1000),
+            PayloadFork::Electra
+        );
+    }
+
+    #[test]
+    fn test_payload_fork_per_epoch() {
+        // forks activating in the middle of an epoch only apply from the next epoch
+        let deneb_epoch_start = DENEB_EPOCH * SLOTS_PER_EPOCH;
+        let chain_spec = chain_spec_with_forks(
+            slot_timestamp(deneb_epoch_start + 5),
+            slot_timestamp(ELECTRA_EPOCH * SLOTS_PER_EPOCH),
+        );
+        let fork = |slot: u64| PayloadFork::for_slot(&chain_spec, slot, slot_timestamp(slot));
+        assert_eq!(fork(deneb_epoch_start + 5), PayloadFork::Capella);
+        assert_eq!(
+            fork(deneb_epoch_start + SLOTS_PER_EPOCH - 1),
+            PayloadFork::Capella
+        );
+        assert_eq!(
+            fork(deneb_epoch_start + SLOTS_PER_EPOCH),
+            PayloadFork::Deneb
+        );
+        // slots before genesis time never underflow
+        assert_eq!(
+            PayloadFork::for_slot(&chain_spec, SLOTS_PER_EPOCH - 1, 0),
+            PayloadFork::Capella
+        );
+    }
+
+    #[test]
+    fn test_sign_block_for_relay_per_fork() {
+        let chain_spec = fork_chain_spec();
+        let attrs = TestDataGenerator::default().create_payload_attribute_data();
+        let signer = BLSBlockSigner::test_signer();
+        let sign = |slot: u64, blob_gas: bool, execution_requests: &[Bytes]| {
+            let attrs = PayloadAttributesData {
+                proposal_slot: slot,
+                ..attrs.clone()
+            };
+            sign_block_for_relay(
+                &signer,
+                &block_at_slot(slot, blob_gas),
+                &[],
+                &[],
+                execution_requests,
+                &chain_spec,
+                &attrs,
+                H384::default(),
+                U256::from(1),
+            )
+        };
+
+        let last_capella = DENEB_EPOCH * SLOTS_PER_EPOCH - 1;
+        let first_deneb = DENEB_EPOCH * SLOTS_PER_EPOCH;
+        let first_electra = ELECTRA_EPOCH * SLOTS_PER_EPOCH;
+        for (slot, expected) in [
+            (last_capella, PayloadFork::Capella),
+            (first_deneb, PayloadFork::Deneb),
+            (first_electra - 1, PayloadFork::Deneb),
+            (first_electra, PayloadFork::Electra),
+        ] {
+            let request = sign(slot, expected >= PayloadFork::Deneb, &[]).unwrap();
+            assert_eq!(request.fork(), expected, "slot {}", slot);
+            assert_eq!(request.bid_trace().value, U256::from(1));
+        }
+
+        // deneb containers need the blob gas fields
+        assert!(sign(first_deneb, false, &[]).is_err());
+        // execution requests only fit from electra
+        let requests = [Bytes::from_static(&[1])];
+        assert!(sign(first_electra - 1, true, &requests).is_err());
+        assert!(sign(first_electra, true, &requests).is_ok());
+    }
 
     #[test]
     fn test_private_pub_key() {
@@ -0,0 +1,176 @@
+//! Canonical json format for orders, meant for external tooling (orderflow proxies, analytics, archives).
+//!
+//! A canonical order is a json object with:
+//! - `version`: format version, currently [`CANONICAL_ORDER_VERSION`]. Decoding fails on unknown versions.
+//! - `order`: a [`RawOrder`] tagged with `type` (`"tx"`, `"bundle"` or `"shareBundle"`). The rest of the fields are the
+//!   same ones we get on the APIs (eth_sendRawTransaction, eth_sendBundle, mev_sendBundle).
+//!   Txs are always encoded without blob data (for 4844 only tx_payload_body).
+//! - `metadata.receivedAtTimestampMs`: when the order reached the builder (unix ms).
+//! - `metadata.signer`: X-Flashbots-Signature signer of the request that sent the order, skipped if none.
+//!
+//! Encoding is deterministic: fields are always written in the same order, optional fields are either always
+//! present or skipped when empty and reverting tx hashes are sorted, so the same [`Order`] always gives the same json.
+//! Examples can be found on `src/primitives/test_data/canonical`, they are used as golden files on the tests.
+
+use super::{
+    serialize::{RawOrder, RawOrderConvertError, TxEncoding},
+    Metadata, Order,
+};
+use crate::utils::{offset_datetime_to_timestamp_ms, timestamp_ms_to_offset_datetime};
+use alloy_primitives::Address;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const CANONICAL_ORDER_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalOrderMetadata {
+    pub received_at_timestamp_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalOrder {
+    pub version: u32,
+    pub order: RawOrder,
+    pub metadata: CanonicalOrderMetadata,
+}
+
+#[derive(Error, Debug)]
+pub enum CanonicalOrderError {
+    #[error("Unsupported canonical order version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("Json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to decode order: {0}")]
+    Order(#[from] RawOrderConvertError),
+}
+
+impl CanonicalOrder {
+    pub fn from_order(order: Order) -> Self {
+        let received_at_timestamp_ms =
+            offset_datetime_to_timestamp_ms(order.metadata().received_at_timestamp);
+        let signer = order.metadata().signer;
+        Self {
+            version: CANONICAL_ORDER_VERSION,
+            order: order.into(),
+            metadata: CanonicalOrderMetadata {
+                received_at_timestamp_ms,
+                signer,
+            },
+        }
+    }
+
+    /// Decodes the order restoring its metadata.
+    pub fn decode(self) -> Result<Order, CanonicalOrderError> {
+        if self.version != CANONICAL_ORDER_VERSION {
+            return Err(CanonicalOrderError::UnsupportedVersion(self.version));
+        }
+        let mut order = self.order.decode(TxEncoding::NoBlobData)?;
+        let metadata = Metadata {
+            received_at_timestamp: timestamp_ms_to_offset_datetime(
+                self.metadata.received_at_timestamp_ms,
+            ),
+            signer: self.metadata.signer,
+        };
+        *order.metadata_mut() = metadata;
+        Ok(order)
+    }
+
+    pub fn to_json(&self) -> Result<String, CanonicalOrderError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CanonicalOrderError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl From<Order> for CanonicalOrder {
+    fn from(order: Order) -> Self {
+        Self::from_order(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLDEN_TX: &str = include_str!("./test_data/canonical/tx.json");
+    const GOLDEN_BUNDLE: &str = include_str!("./test_data/canonical/bundle.json");
+    const GOLDEN_SHARE_BUNDLE: &str = include_str!("./test_data/canonical/share_bundle.json");
+
+    /// golden -> Order -> canonical must give back the golden file.
+    fn assert_golden_round_trip(golden: &str) -> Order {
+        let canonical = CanonicalOrder::from_json(golden).unwrap();
+        let order = canonical.clone().decode().unwrap();
+        let encoded = CanonicalOrder::from_order(order.clone());
+        assert_eq!(encoded, canonical);
+        // the files are pretty printed but field order and content must match byte by byte
+        assert_eq!(
+            serde_json::to_string_pretty(&encoded).unwrap(),
+            golden.trim_end()
+        );
+
+        // deterministic encoding
+        let json = encoded.to_json().unwrap();
+        let reencoded =
+            CanonicalOrder::from_order(CanonicalOrder::from_json(&json).unwrap().decode().unwrap());
+        assert_eq!(reencoded.to_json().unwrap(), json);
+        order
+    }
+
+    #[test]
+    fn test_golden_tx() {
+        let order = assert_golden_round_trip(GOLDEN_TX);
+        assert!(matches!(order, Order::Tx(_)));
+        assert_eq!(
+            offset_datetime_to_timestamp_ms(order.metadata().received_at_timestamp),
+            1700000000123
+        );
+    }
+
+    #[test]
+    fn test_golden_bundle() {
+        let order = assert_golden_round_trip(GOLDEN_BUNDLE);
+        let Order::Bundle(bundle) = &order else {
+            panic!("expected bundle");
+        };
+        assert_eq!(bundle.txs.len(), 2);
+        assert_eq!(bundle.replacement_data.as_ref().unwrap().sequence_number, 3);
+        assert_eq!(bundle.min_timestamp, Some(1700000000));
+        assert_eq!(
+            offset_datetime_to_timestamp_ms(order.metadata().received_at_timestamp),
+            1700000000456
+        );
+    }
+
+    #[test]
+    fn test_golden_share_bundle() {
+        let order = assert_golden_round_trip(GOLDEN_SHARE_BUNDLE);
+        let Order::ShareBundle(bundle) = &order else {
+            panic!("expected share bundle");
+        };
+        assert_eq!(bundle.max_block, bundle.block + 2);
+        assert_eq!(bundle.inner_bundle.body.len(), 2);
+        assert_eq!(bundle.inner_bundle.refund.len(), 1);
+        assert!(bundle.signer.is_some());
+        assert_eq!(
+            offset_datetime_to_timestamp_ms(order.metadata().received_at_timestamp),
+            1700000000789
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut canonical = CanonicalOrder::from_json(GOLDEN_TX).unwrap();
+        canonical.version = CANONICAL_ORDER_VERSION + 1;
+        assert!(matches!(
+            canonical.decode(),
+            Err(CanonicalOrderError::UnsupportedVersion(_))
+        ));
+    }
+}
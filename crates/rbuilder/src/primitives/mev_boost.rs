@@ -1,4 +1,9 @@
-use crate::mev_boost::{RelayClient, SubmitBlockErr, SubmitBlockRequest};
+use crate::{
+    building::extra_data::ExtraDataTemplate,
+    mev_boost::{RelayClient, SubmitBlockErr, SubmitBlockReceipt, SubmitBlockRequest},
+    roothash::payment_proof::ProposerPaymentProof,
+};
+use alloy_primitives::{utils::parse_ether, U256};
 use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
 use serde::{Deserialize, Deserializer};
 use std::{env, sync::Arc, time::Duration};
@@ -18,6 +23,9 @@ pub struct RelayConfig {
     pub use_ssz_for_submit: bool,
     #[serde(default)]
     pub use_gzip_for_submit: bool,
+    /// Stream the submission body in chunks instead of building it in memory, for very big blocks.
+    #[serde(default)]
+    pub use_streaming_for_submit: bool,
     #[serde(default)]
     pub optimistic: bool,
     #[serde(default, deserialize_with = "deserialize_env_var")]
@@ -28,6 +36,16 @@ pub struct RelayConfig {
     pub api_token_header: Option<String>,
     #[serde(default)]
     pub interval_between_submissions_ms: Option<u64>,
+    /// Relay wants a [`ProposerPaymentProof`] with each submission (json only).
+    #[serde(default)]
+    pub requires_payment_proof: bool,
+    /// Relay rejects bids below this value (eg: "0.001"), we don't submit them.
+    #[serde(default)]
+    pub min_bid_value_eth: Option<String>,
+    /// Extra data template (see [`crate::building::extra_data`]) for the blocks we send to this relay instead of the
+    /// config one.
+    #[serde(default)]
+    pub extra_data: Option<String>,
 }
 
 impl RelayConfig {
@@ -61,11 +79,23 @@ pub struct MevBoostRelay {
     pub use_gzip_for_submit: bool,
     /// Relay accepts optimistic submissions.
     pub optimistic: bool,
+    pub requires_payment_proof: bool,
     pub submission_rate_limiter: Option<Arc<DefaultDirectRateLimiter>>,
+    /// Bid floor of the relay.
+    pub min_bid_value: Option<U256>,
+    /// Extra data override, the blocks get resealed with it before submitting them here.
+    pub extra_data: Option<ExtraDataTemplate>,
 }
 
 impl MevBoostRelay {
-    pub fn from_config(config: &RelayConfig) -> eyre::Result<Self> {
+    /// extra_data_builder_name: {builder_name} of the extra_data override.
+    pub fn from_config(config: &RelayConfig, extra_data_builder_name: &str) -> eyre::Result<Self> {
+        if config.requires_payment_proof && config.use_ssz_for_submit {
+            eyre::bail!(
+                "Relay {}: requires_payment_proof is only supported on json submissions",
+                config.name
+            );
+        }
         let client = {
             let url: Url = config.url.parse()?;
             RelayClient::from_url(
@@ -74,6 +104,7 @@ impl MevBoostRelay {
                 config.builder_id_header.clone(),
                 config.api_token_header.clone(),
             )
+            .with_streaming_submit(config.use_streaming_for_submit)
         };
 
         let submission_rate_limiter = config.interval_between_submissions_ms.map(|d| {
@@ -82,6 +113,19 @@ impl MevBoostRelay {
             ))
         });
 
+        let min_bid_value = config
+            .min_bid_value_eth
+            .as_deref()
+            .map(parse_ether)
+            .transpose()?;
+
+        let extra_data = config
+            .extra_data
+            .as_deref()
+            .map(|template| ExtraDataTemplate::parse(template, extra_data_builder_name))
+            .transpose()
+            .map_err(|err| eyre::eyre!("Relay {}: {}", config.name, err))?;
+
         Ok(MevBoostRelay {
             id: config.name.to_string(),
             client,
@@ -89,13 +133,48 @@ impl MevBoostRelay {
             use_ssz_for_submit: config.use_ssz_for_submit,
             use_gzip_for_submit: config.use_gzip_for_submit,
             optimistic: config.optimistic,
+            requires_payment_proof: config.requires_payment_proof,
             submission_rate_limiter,
+            min_bid_value,
+            extra_data,
         })
     }
 
-    pub async fn submit_block(&self, data: &SubmitBlockRequest) -> Result<(), SubmitBlockErr> {
+    /// Extra data of the blocks for this relay if it differs from block_extra_data.
+    pub fn extra_data_override(&self, slot: u64, block_extra_data: &[u8]) -> Option<Vec<u8>> {
+        self.extra_data
+            .as_ref()
+            .map(|template| template.render(slot))
+            .filter(|extra_data| extra_data.as_slice() != block_extra_data)
+    }
+
+    /// false if the bid is below the relay floor.
+    pub fn accepts_bid_value(&self, bid_value: U256) -> bool {
+        self.min_bid_value
+            .map_or(true, |min_bid_value| bid_value >= min_bid_value)
+    }
+
+    /// payment_proof is only sent if the relay requires it.
+    /// receipt gets what was sent and answered (see [`RelayClient::submit_block_with_receipt`]).
+    pub async fn submit_block(
+        &self,
+        data: &Arc<SubmitBlockRequest>,
+        payment_proof: Option<&ProposerPaymentProof>,
+        receipt: &mut SubmitBlockReceipt,
+    ) -> Result<(), SubmitBlockErr> {
+        let payment_proof = if self.requires_payment_proof {
+            Some(payment_proof.ok_or(SubmitBlockErr::MissingPaymentProof)?)
+        } else {
+            None
+        };
         self.client
-            .submit_block(data, self.use_ssz_for_submit, self.use_gzip_for_submit)
+            .submit_block_with_receipt(
+                data,
+                self.use_ssz_for_submit,
+                self.use_gzip_for_submit,
+                payment_proof,
+                receipt,
+            )
             .await
     }
 }
@@ -127,6 +206,7 @@ mod test {
         authorization_header = 'env:XXX'
         builder_id_header = 'env:YYY'
         api_token_header = 'env:ZZZ'
+        min_bid_value_eth = '0.001'
         ";
 
         std::env::set_var("XXX", "AAA");
@@ -140,5 +220,62 @@ mod test {
         assert_eq!(config.authorization_header.unwrap(), "AAA");
         assert_eq!(config.builder_id_header.unwrap(), "BBB");
         assert_eq!(config.api_token_header.unwrap(), "CCC");
+        assert_eq!(config.min_bid_value_eth.as_deref(), Some("0.001"));
+    }
+
+    #[test]
+    fn test_relay_bid_floor() {
+        let config = RelayConfig {
+            name: "relay1".to_string(),
+            url: "http://localhost:1234".to_string(),
+            min_bid_value_eth: Some("0.001".to_string()),
+            ..Default::default()
+        };
+        let relay = MevBoostRelay::from_config(&config, "bob").unwrap();
+        let floor = parse_ether("0.001").unwrap();
+        assert!(relay.accepts_bid_value(floor));
+        assert!(!relay.accepts_bid_value(floor - U256::from(1)));
+
+        let relay = MevBoostRelay::from_config(
+            &RelayConfig {
+                min_bid_value_eth: None,
+                ..config
+            },
+            "bob",
+        )
+        .unwrap();
+        assert!(relay.accepts_bid_value(U256::ZERO));
+    }
+
+    #[test]
+    fn test_relay_extra_data_override() {
+        let config = RelayConfig {
+            name: "relay1".to_string(),
+            url: "http://localhost:1234".to_string(),
+            extra_data: Some("{builder_name} {slot}".to_string()),
+            ..Default::default()
+        };
+        let relay = MevBoostRelay::from_config(&config, "bob").unwrap();
+        assert_eq!(
+            relay.extra_data_override(7, b"main"),
+            Some(b"bob 7".to_vec())
+        );
+        assert_eq!(relay.extra_data_override(7, b"bob 7"), None);
+
+        let relay = MevBoostRelay::from_config(
+            &RelayConfig {
+                extra_data: None,
+                ..config.clone()
+            },
+            "bob",
+        )
+        .unwrap();
+        assert_eq!(relay.extra_data_override(7, b"main"), None);
+
+        let too_long = RelayConfig {
+            extra_data: Some("x".repeat(33)),
+            ..config
+        };
+        assert!(MevBoostRelay::from_config(&too_long, "bob").is_err());
     }
 }
@@ -1,5 +1,6 @@
 //! Order types used as elements for block building.
 
+pub mod canonical;
 pub mod fmt;
 pub mod mev_boost;
 pub mod order_builder;
@@ -10,7 +11,7 @@ use crate::building::evm_inspector::UsedStateTrace;
 use alloy_consensus::Transaction as _;
 use alloy_eips::{
     eip2718::{Decodable2718, Eip2718Error, Encodable2718},
-    eip4844::{Blob, Bytes48},
+    eip4844::{Blob, Bytes48, DATA_GAS_PER_BLOB},
 };
 use alloy_primitives::{keccak256, Address, Bytes, TxHash, B256, U256};
 use derivative::Derivative;
@@ -22,7 +23,14 @@ use reth_primitives::{
 };
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{cmp::Ordering, collections::HashMap, fmt::Display, str::FromStr, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 pub use test_data_generator::TestDataGenerator;
 use thiserror::Error;
 use uuid::Uuid;
@@ -31,12 +39,16 @@ use uuid::Uuid;
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Metadata {
     pub received_at_timestamp: time::OffsetDateTime,
+    /// Verified X-Flashbots-Signature signer of the request that sent the order (see
+    /// [`crate::live_builder::order_input::flashbots_signature`]).
+    pub signer: Option<Address>,
 }
 
 impl Metadata {
     pub fn with_current_received_at() -> Self {
         Self {
             received_at_timestamp: time::OffsetDateTime::now_utc(),
+            signer: None,
         }
     }
 }
@@ -110,7 +122,7 @@ pub struct Bundle {
     /// Virtual hash generated by concatenating all txs hashes (+some more info) and hashing them.
     /// See [Bundle::hash_slow] for more details.
     pub hash: B256,
-    /// Unique id we generate.
+    /// Unique id we generate (also used in OrderId::Bundle), see [bundle_uuid].
     pub uuid: Uuid,
     /// Unique id, bundle signer.
     /// The unique id was generated by the sender and is used for updates/cancellations.
@@ -143,44 +155,49 @@ impl Bundle {
             .collect()
     }
 
-    /// Recalculate bundle hash and uuid.
-    /// Hash is computed from child tx hashes + reverting_tx_hashes.
+    /// Recalculate bundle hash and uuid (see [bundle_hash] and [bundle_uuid]).
+    /// Also sorts reverting_tx_hashes.
     pub fn hash_slow(&mut self) {
-        let hash = self
-            .txs
-            .iter()
-            .flat_map(|tx| tx.hash().0.to_vec())
-            .collect::<Vec<_>>();
-        self.hash = keccak256(hash);
-
-        let uuid = {
-            // Block, hash, reverting hashes.
-            let mut buff = Vec::with_capacity(8 + 32 + 32 * self.reverting_tx_hashes.len());
-            {
-                let block = self.block as i64;
-                buff.append(&mut block.encode_var_vec());
-            }
-            buff.extend_from_slice(self.hash.as_slice());
-            self.reverting_tx_hashes.sort();
-            for reverted_hash in &self.reverting_tx_hashes {
-                buff.extend_from_slice(reverted_hash.as_slice());
-            }
-            let hash = {
-                let mut res = [0u8; 16];
-                let mut hasher = Sha256::new();
-                // We write 16 zeroes to replicate golang hashing behavior.
-                hasher.update(res);
-                hasher.update(&buff);
-                let output = hasher.finalize();
-                res.copy_from_slice(&output.as_slice()[0..16]);
-                res
-            };
-            uuid::Builder::from_sha1_bytes(hash).into_uuid()
-        };
-        self.uuid = uuid;
+        self.hash = bundle_hash(self.txs.iter().map(|tx| tx.hash()));
+        self.reverting_tx_hashes.sort();
+        self.uuid = bundle_uuid(self.block, &self.hash, &self.reverting_tx_hashes);
     }
 }
 
+/// keccak256 of the concatenated hashes.
+/// Used for [Bundle::hash] and for the nodes of a ShareBundle with more than one element.
+pub fn bundle_hash(hashes: impl IntoIterator<Item = B256>) -> B256 {
+    keccak256(
+        hashes
+            .into_iter()
+            .flat_map(|hash| hash.0)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Uuid of an eth_sendBundle bundle, same as the flashbots (golang) implementation:
+/// first 16 bytes of sha256(16 zero bytes ++ zigzag varint(block) ++ bundle_hash ++ sorted reverting_tx_hashes) with
+/// the uuid v5 version and variant bits set.
+/// The order of reverting_tx_hashes does not matter.
+pub fn bundle_uuid(block: u64, bundle_hash: &B256, reverting_tx_hashes: &[B256]) -> Uuid {
+    let mut reverting_tx_hashes = reverting_tx_hashes.to_vec();
+    reverting_tx_hashes.sort();
+    let mut buff = Vec::with_capacity(8 + 32 + 32 * reverting_tx_hashes.len());
+    buff.append(&mut (block as i64).encode_var_vec());
+    buff.extend_from_slice(bundle_hash.as_slice());
+    for reverted_hash in &reverting_tx_hashes {
+        buff.extend_from_slice(reverted_hash.as_slice());
+    }
+    let mut res = [0u8; 16];
+    let mut hasher = Sha256::new();
+    // We write 16 zeroes to replicate golang hashing behavior.
+    hasher.update(res);
+    hasher.update(&buff);
+    let output = hasher.finalize();
+    res.copy_from_slice(&output.as_slice()[0..16]);
+    uuid::Builder::from_sha1_bytes(res).into_uuid()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TxRevertBehavior {
     /// Tx in a bundle can't revert.
@@ -230,6 +247,14 @@ pub enum ShareBundleBody {
 }
 
 impl ShareBundleBody {
+    /// Vec<(Tx, allowed to revert)>
+    pub fn list_txs(&self) -> Vec<(&TransactionSignedEcRecoveredWithBlobs, bool)> {
+        match self {
+            Self::Tx(sbundle_tx) => vec![(&sbundle_tx.tx, sbundle_tx.revert_behavior.can_revert())],
+            Self::Bundle(bundle) => bundle.list_txs(),
+        }
+    }
+
     pub fn refund_config(&self) -> Option<Vec<RefundConfig>> {
         match self {
             Self::Tx(sbundle_tx) => Some(vec![RefundConfig {
@@ -322,12 +347,7 @@ impl ShareBundleInner {
         if hashes.len() == 1 {
             hashes[0]
         } else {
-            keccak256(
-                hashes
-                    .into_iter()
-                    .flat_map(|h| h.0.to_vec())
-                    .collect::<Vec<_>>(),
-            )
+            bundle_hash(hashes)
         }
     }
 }
@@ -341,6 +361,65 @@ pub struct ReplacementKey {
 
 pub type ShareBundleReplacementData = ReplacementData<ShareBundleReplacementKey>;
 
+/// What the sender of a mev_sendBundle allows the MEV-Share node to share about it (privacy.hints).
+/// We don't share anything ourselves, we only keep them so the bundle can be forwarded/exported as it came.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyHint {
+    Calldata,
+    ContractAddress,
+    Logs,
+    FunctionSelector,
+    Hash,
+    TxHash,
+    DefaultLogs,
+    SpecialLogs,
+    Full,
+}
+
+impl PrivacyHint {
+    pub const ALL: [PrivacyHint; 9] = [
+        Self::Calldata,
+        Self::ContractAddress,
+        Self::Logs,
+        Self::FunctionSelector,
+        Self::Hash,
+        Self::TxHash,
+        Self::DefaultLogs,
+        Self::SpecialLogs,
+        Self::Full,
+    ];
+
+    pub fn parse(hint: &str) -> Option<Self> {
+        Some(match hint {
+            "calldata" => Self::Calldata,
+            "contract_address" => Self::ContractAddress,
+            "logs" => Self::Logs,
+            "function_selector" => Self::FunctionSelector,
+            "hash" => Self::Hash,
+            "tx_hash" => Self::TxHash,
+            "default_logs" => Self::DefaultLogs,
+            "special_logs" => Self::SpecialLogs,
+            "full" => Self::Full,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Calldata => "calldata",
+            Self::ContractAddress => "contract_address",
+            Self::Logs => "logs",
+            Self::FunctionSelector => "function_selector",
+            Self::Hash => "hash",
+            Self::TxHash => "tx_hash",
+            Self::DefaultLogs => "default_logs",
+            Self::SpecialLogs => "special_logs",
+            Self::Full => "full",
+        }
+    }
+}
+
 /// Preprocessed Share bundle originated by mev_sendBundle (https://docs.flashbots.net/flashbots-auction/advanced/rpc-endpoint#eth_sendbundle)
 /// Instead of having hashes (as in the original definition) it contains the actual txs.
 #[derive(Derivative)]
@@ -357,6 +436,8 @@ pub struct ShareBundle {
     pub replacement_data: Option<ShareBundleReplacementData>,
     /// Only used internally when we build a virtual (not part of the orderflow) ShareBundle from other orders.
     pub original_orders: Vec<Order>,
+    /// privacy.hints of the top level bundle (unknown hints are dropped).
+    pub privacy_hints: Vec<PrivacyHint>,
 
     #[derivative(PartialEq = "ignore", Hash = "ignore")]
     pub metadata: Metadata,
@@ -406,6 +487,10 @@ pub struct TransactionSignedEcRecoveredWithBlobs {
     tx: TransactionSignedEcRecovered,
     /// Will have a non empty BlobTransactionSidecar if TransactionSignedEcRecovered is 4844
     pub blobs_sidecar: Arc<BlobTransactionSidecar>,
+    /// Canonical envelope (no blobs) of tx, shared by all the clones.
+    /// When decoded from a canonical envelope it's the exact buffer we received, otherwise it's encoded on first use.
+    #[derivative(PartialEq = "ignore")]
+    envelope: Arc<OnceLock<Bytes>>,
 
     #[derivative(PartialEq = "ignore", Hash = "ignore")]
     pub metadata: Metadata,
@@ -457,10 +542,19 @@ impl TransactionSignedEcRecoveredWithBlobs {
         Self {
             tx,
             blobs_sidecar: Default::default(),
+            envelope: Default::default(),
             metadata: Default::default(),
         }
     }
 
+    /// raw_tx must be the canonical envelope of self.tx.
+    fn with_envelope(self, raw_tx: Bytes) -> Self {
+        Self {
+            envelope: Arc::new(OnceLock::from(raw_tx)),
+            ..self
+        }
+    }
+
     pub fn hash(&self) -> TxHash {
         self.tx.hash()
     }
@@ -493,21 +587,40 @@ impl TransactionSignedEcRecoveredWithBlobs {
 
     /// Encodes the "raw" canonical format of transaction (NOT the one used in `eth_sendRawTransaction`) BLOB DATA IS NOT ENCODED.
     /// I intensionally omitted the version with blob data since we don't use it and may lead to confusions/bugs.
+    /// No copy is made, the buffer is shared (see [`Self::envelope`]).
     /// USE CAREFULLY since this exposes the signed tx.
     pub fn envelope_encoded_no_blobs(&self) -> Bytes {
-        let mut buf = Vec::new();
-        self.tx.as_signed().encode_2718(&mut buf);
-        buf.into()
+        self.envelope
+            .get_or_init(|| {
+                let mut buf = Vec::new();
+                self.tx.as_signed().encode_2718(&mut buf);
+                buf.into()
+            })
+            .clone()
     }
 
     /// Decodes the "raw" format of transaction (e.g. `eth_sendRawTransaction`) with the blob data (network format)
     pub fn decode_enveloped_with_real_blobs(
         raw_tx: Bytes,
     ) -> Result<TransactionSignedEcRecoveredWithBlobs, RawTxWithBlobsConvertError> {
-        let raw_tx = &mut raw_tx.as_ref();
-        let pooled_tx: PooledTransactionsElement =
-            PooledTransactionsElement::decode_2718(raw_tx)
-                .map_err(RawTxWithBlobsConvertError::FailedToDecodeTransaction)?;
+        let buf = &mut raw_tx.as_ref();
+        let pooled_tx: PooledTransactionsElement = PooledTransactionsElement::decode_2718(buf)
+            .map_err(RawTxWithBlobsConvertError::FailedToDecodeTransaction)?;
+        // For non blob txs the network format is the canonical envelope so we can keep the buffer.
+        let canonical_envelope = (buf.is_empty()
+            && !matches!(pooled_tx, PooledTransactionsElement::BlobTransaction(_)))
+        .then(|| raw_tx.clone());
+        let tx_with_blobs = Self::from_pooled_tx(pooled_tx)?;
+        Ok(match canonical_envelope {
+            Some(envelope) => tx_with_blobs.with_envelope(envelope),
+            None => tx_with_blobs,
+        })
+    }
+
+    /// Tx in the network format (eg: devp2p PooledTransactions), blob txs come with their sidecar.
+    pub fn from_pooled_tx(
+        pooled_tx: PooledTransactionsElement,
+    ) -> Result<TransactionSignedEcRecoveredWithBlobs, RawTxWithBlobsConvertError> {
         let signer = pooled_tx
             .recover_signer()
             .ok_or(RawTxWithBlobsConvertError::InvalidTransactionSignature)?;
@@ -549,17 +662,21 @@ impl TransactionSignedEcRecoveredWithBlobs {
                 Ok(TransactionSignedEcRecoveredWithBlobs {
                     tx: tx.with_signer(signer),
                     blobs_sidecar: Arc::new(sidecar),
+                    envelope: Default::default(),
                     metadata: Metadata::default(),
                 })
             }
         }
     }
+
     /// Decodes the "raw" canonical format of transaction (NOT the one used in `eth_sendRawTransaction`) generating fake blob data for backtesting
     pub fn decode_enveloped_with_fake_blobs(
         raw_tx: Bytes,
     ) -> Result<TransactionSignedEcRecoveredWithBlobs, RawTxWithBlobsConvertError> {
-        let decoded = TransactionSigned::decode_2718(&mut raw_tx.as_ref())
+        let buf = &mut raw_tx.as_ref();
+        let decoded = TransactionSigned::decode_2718(buf)
             .map_err(RawTxWithBlobsConvertError::FailedToDecodeTransaction)?;
+        let canonical_envelope = buf.is_empty().then(|| raw_tx.clone());
         let tx = decoded
             .into_ecrecovered()
             .ok_or(RawTxWithBlobsConvertError::InvalidTransactionSignature)?;
@@ -573,10 +690,15 @@ impl TransactionSignedEcRecoveredWithBlobs {
                 .proofs
                 .push(Bytes48::from([0u8; BYTES_PER_PROOF]));
         }
-        Ok(TransactionSignedEcRecoveredWithBlobs {
+        let tx_with_blobs = TransactionSignedEcRecoveredWithBlobs {
             tx,
             blobs_sidecar: Arc::new(fake_sidecar),
+            envelope: Default::default(),
             metadata: Metadata::default(),
+        };
+        Ok(match canonical_envelope {
+            Some(envelope) => tx_with_blobs.with_envelope(envelope),
+            None => tx_with_blobs,
         })
     }
 }
@@ -591,11 +713,25 @@ impl std::hash::Hash for TransactionSignedEcRecoveredWithBlobs {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MempoolTx {
     pub tx_with_blobs: TransactionSignedEcRecoveredWithBlobs,
+    /// Last block the tx can be included in. Only private txs (eth_sendPrivateTransaction maxBlockNumber) have it.
+    pub max_block: Option<u64>,
 }
 
 impl MempoolTx {
     pub fn new(tx_with_blobs: TransactionSignedEcRecoveredWithBlobs) -> Self {
-        Self { tx_with_blobs }
+        Self {
+            tx_with_blobs,
+            max_block: None,
+        }
+    }
+
+    pub fn with_max_block(self, max_block: Option<u64>) -> Self {
+        Self { max_block, ..self }
+    }
+
+    /// false if block is past max_block.
+    pub fn valid_for_block(&self, block: u64) -> bool {
+        self.max_block.map_or(true, |max_block| block <= max_block)
     }
 }
 
@@ -728,6 +864,21 @@ impl Order {
         }
     }
 
+    /// Blob gas of all the txs, optional ones included so it's the max the order can use.
+    /// Counted from the versioned hashes of the txs, the sidecar may be missing (eg: txs from a block).
+    pub fn max_blob_gas(&self) -> u64 {
+        self.list_txs()
+            .iter()
+            .map(|(tx, _)| {
+                let blobs = tx
+                    .internal_tx_unsecure()
+                    .blob_versioned_hashes()
+                    .map_or(0, |hashes| hashes.len());
+                blobs as u64 * DATA_GAS_PER_BLOB
+            })
+            .sum()
+    }
+
     pub fn has_blobs(&self) -> bool {
         self.list_txs()
             .iter()
@@ -742,13 +893,33 @@ impl Order {
         }
     }
 
-    /// Address that signed the bundle request
+    /// false if the order can't be included in block (only checks the [MempoolTx::max_block] of private txs, bundles
+    /// are routed by [Order::target_block]).
+    pub fn valid_for_block(&self, block: u64) -> bool {
+        match self {
+            Order::Tx(tx) => tx.valid_for_block(block),
+            Order::Bundle(_) | Order::ShareBundle(_) => true,
+        }
+    }
+
+    /// Hash returned by eth_sendBundle/mev_sendBundle, None for mempool txs.
+    pub fn bundle_hash(&self) -> Option<B256> {
+        match self {
+            Order::Bundle(bundle) => Some(bundle.hash),
+            Order::ShareBundle(bundle) => Some(bundle.hash),
+            Order::Tx(_) => None,
+        }
+    }
+
+    /// Address that signed the bundle request, for txs (and bundles without one) the verified signer of the
+    /// request that sent them ([`Metadata::signer`]).
     pub fn signer(&self) -> Option<Address> {
         match self {
             Order::Bundle(bundle) => bundle.signer,
             Order::ShareBundle(bundle) => bundle.signer,
             Order::Tx(_) => None,
         }
+        .or(self.metadata().signer)
     }
 
     pub fn metadata(&self) -> &Metadata {
@@ -758,6 +929,14 @@ impl Order {
             Order::ShareBundle(bundle) => &bundle.metadata,
         }
     }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        match self {
+            Order::Bundle(bundle) => &mut bundle.metadata,
+            Order::Tx(tx) => &mut tx.tx_with_blobs.metadata,
+            Order::ShareBundle(bundle) => &mut bundle.metadata,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -795,6 +974,42 @@ impl SimValue {
     }
 }
 
+/// CPU cost of executing an order alone, measured on its first (top of block) simulation.
+/// Default (zero) if unknown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExecutionCost {
+    /// EVM time (parents excluded).
+    pub evm_time: Duration,
+    /// Storage slots and balances read.
+    pub state_reads: usize,
+}
+
+impl ExecutionCost {
+    /// Slots/balances read by the trace.
+    pub fn new(evm_time: Duration, used_state_trace: Option<&UsedStateTrace>) -> Self {
+        Self {
+            evm_time,
+            state_reads: used_state_trace.map_or(0, |trace| {
+                trace.read_slot_values.len() + trace.read_balances.len()
+            }),
+        }
+    }
+
+    /// value per µs of EVM time (an unknown cost counts as 1µs).
+    pub fn value_per_cost(&self, value: U256) -> U256 {
+        value / U256::from(self.evm_time.as_micros().max(1))
+    }
+}
+
+impl std::iter::Sum for ExecutionCost {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |sum, cost| Self {
+            evm_time: sum.evm_time + cost.evm_time,
+            state_reads: sum.state_reads + cost.state_reads,
+        })
+    }
+}
+
 /// Order simulated (usually on top of block) + SimValue
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SimulatedOrder {
@@ -804,6 +1019,7 @@ pub struct SimulatedOrder {
     pub prev_order: Option<OrderId>,
     /// Info about read/write slots during the simulation to help figure out what the Order is doing.
     pub used_state_trace: Option<UsedStateTrace>,
+    pub execution_cost: ExecutionCost,
 }
 
 impl SimulatedOrder {
@@ -818,6 +1034,15 @@ impl SimulatedOrder {
 
 /// Unique OrderId used along the whole builder.
 /// Sadly it's not perfect since we still might have some collisions (eg: ShareBundle is the tx tree hash which does not include all the other cfg).
+///
+/// Ids only depend on the canonical content of the order so submitters can compute them on their side (or ask
+/// rbuilder_orderId) and correlate them with our status streams. They are part of our API: DON'T CHANGE the derivation.
+/// - Tx: tx hash.
+/// - Bundle: [bundle_uuid] of the block, the [bundle_hash] of the tx hashes and the reverting tx hashes.
+/// - ShareBundle: DFS over the body where a tx is its hash and an inner bundle its own hash. A body with a single
+///   element hashes to that element, otherwise to the [bundle_hash] of the elements (see [ShareBundleInner::hash_slow]).
+///
+/// The Display impl ("tx:0x..", "bundle:uuid", "sbundle:0x..") is the form used by the APIs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum OrderId {
     Tx(B256),
@@ -941,7 +1166,7 @@ fn can_execute_with_block_base_fee<Transaction: AsRef<TransactionSigned>>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy_consensus::TxLegacy;
+    use alloy_consensus::{TxEip4844, TxLegacy};
     use alloy_primitives::fixed_bytes;
     use reth_primitives::{Transaction, TransactionSigned};
     use uuid::uuid;
@@ -966,6 +1191,67 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_max_blob_gas_counts_versioned_hashes() {
+        let tx = TransactionSignedEcRecovered::from_signed_transaction(
+            TransactionSigned {
+                transaction: Transaction::Eip4844(TxEip4844 {
+                    blob_versioned_hashes: vec![B256::with_last_byte(1), B256::with_last_byte(2)],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Address::default(),
+        );
+        // no sidecar
+        let order = Order::Tx(MempoolTx::new(
+            TransactionSignedEcRecoveredWithBlobs::new_for_testing(tx),
+        ));
+        assert_eq!(order.max_blob_gas(), 2 * DATA_GAS_PER_BLOB);
+        let no_blobs = Order::Tx(MempoolTx::new(crate::utils::test_utils::tx(1)));
+        assert_eq!(no_blobs.max_blob_gas(), 0);
+    }
+
+    #[test]
+    fn test_signer_falls_back_to_request_signer() {
+        let mut order = Order::Tx(MempoolTx::new(crate::utils::test_utils::tx(1)));
+        assert_eq!(order.signer(), None);
+        order.metadata_mut().signer = Some(Address::with_last_byte(1));
+        assert_eq!(order.signer(), Some(Address::with_last_byte(1)));
+    }
+
+    /// Pins the OrderId derivation, if this fails you are breaking the ids submitters compute.
+    #[test]
+    fn test_order_id_derivation_is_stable() {
+        assert_eq!(
+            bundle_hash([B256::repeat_byte(0x11), B256::repeat_byte(0x22)]),
+            fixed_bytes!("3e92e0db88d6afea9edc4eedf62fffa4d92bcdfc310dccbe943747fe8302e871")
+        );
+        let tx_hash =
+            fixed_bytes!("da7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c");
+        let hash = bundle_hash([tx_hash]);
+        assert_eq!(
+            hash,
+            fixed_bytes!("cf3c567aede099e5455207ed81c4884f72a4c0c24ddca331163a335525cd22cc")
+        );
+        assert_eq!(
+            bundle_uuid(18_050_847, &hash, &[tx_hash]),
+            uuid!("a90205bc-2afd-5afe-b315-f17d597ffd97")
+        );
+        assert_eq!(
+            bundle_uuid(
+                18_050_847,
+                &hash,
+                &[B256::repeat_byte(0xff), B256::repeat_byte(0xaa)]
+            ),
+            bundle_uuid(
+                18_050_847,
+                &hash,
+                &[B256::repeat_byte(0xaa), B256::repeat_byte(0xff)]
+            )
+        );
+    }
+
     #[test]
     fn test_order_id_json() {
         let id = OrderId::Tx(fixed_bytes!(
@@ -1042,4 +1328,38 @@ mod tests {
             fixed_bytes!("02e81e3cee67f25203db1178fb11070fcdace65c4eef80daa4037d9b49f011f5")
         );
     }
+
+    #[test]
+    fn test_execution_cost() {
+        let mut trace = UsedStateTrace::default();
+        for key in 0..3u8 {
+            trace.read_slot_values.insert(
+                crate::building::evm_inspector::SlotKey {
+                    address: Address::default(),
+                    key: B256::with_last_byte(key),
+                },
+                B256::ZERO,
+            );
+        }
+        trace.read_balances.insert(Address::default(), U256::ZERO);
+        let cost = ExecutionCost::new(Duration::from_micros(150), Some(&trace));
+        assert_eq!(cost.state_reads, 4);
+        assert_eq!(cost.evm_time, Duration::from_micros(150));
+        assert_eq!(
+            ExecutionCost::new(Duration::ZERO, None),
+            ExecutionCost::default()
+        );
+        assert_eq!(cost.value_per_cost(U256::from(300)), U256::from(2));
+        assert_eq!(
+            ExecutionCost::default().value_per_cost(U256::from(300)),
+            U256::from(300)
+        );
+        assert_eq!(
+            [cost, cost].into_iter().sum::<ExecutionCost>(),
+            ExecutionCost {
+                evm_time: Duration::from_micros(300),
+                state_reads: 8
+            }
+        );
+    }
 }
@@ -230,6 +230,7 @@ impl ShareBundleBuilder {
             signer: None,
             replacement_data: None,
             original_orders: Vec::new(),
+            privacy_hints: Vec::new(),
             metadata: Default::default(),
         };
         bundle.hash_slow();
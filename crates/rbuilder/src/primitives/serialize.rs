@@ -1,5 +1,5 @@
 use super::{
-    Bundle, BundleReplacementData, BundleReplacementKey, MempoolTx, Order,
+    Bundle, BundleReplacementData, BundleReplacementKey, MempoolTx, Order, OrderId, PrivacyHint,
     RawTxWithBlobsConvertError, Refund, RefundConfig, ShareBundle, ShareBundleBody,
     ShareBundleInner, ShareBundleReplacementData, ShareBundleReplacementKey, ShareBundleTx,
     TransactionSignedEcRecoveredWithBlobs, TxRevertBehavior,
@@ -12,7 +12,7 @@ use reth_chainspec::MAINNET;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::serde_as;
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, trace};
 use uuid::Uuid;
 
 /// Encoding mode for raw transactions (https://eips.ethereum.org/EIPS/eip-4844)
@@ -81,12 +81,54 @@ where
     Ok(opt.unwrap_or_default())
 }
 
+/// Older searcher tooling sends quantities (block numbers, timestamps) as json numbers, hex strings ("0x1a") or
+/// decimal strings ("26").
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyQuantity {
+    Number(u64),
+    String(String),
+}
+
+impl LegacyQuantity {
+    fn into_u64<E: serde::de::Error>(self) -> Result<u64, E> {
+        match self {
+            LegacyQuantity::Number(number) => Ok(number),
+            LegacyQuantity::String(string) => match string.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => string.parse(),
+            }
+            .map_err(|_| E::custom(format!("invalid quantity: {}", string))),
+        }
+    }
+}
+
+fn deserialize_quantity<'de, D>(deserializer: D) -> Result<U64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(U64::from(
+        LegacyQuantity::deserialize(deserializer)?.into_u64()?,
+    ))
+}
+
+fn deserialize_optional_quantity<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<LegacyQuantity>::deserialize(deserializer)?
+        .map(LegacyQuantity::into_u64)
+        .transpose()
+}
+
 /// Struct to de/serialize json Bundles from bundles APIs and from/db.
 /// Does not assume a particular format on txs.
+/// blockNumber and timestamps also accept the legacy formats (see [`LegacyQuantity`]).
 #[serde_as]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RawBundle {
+    #[serde(deserialize_with = "deserialize_quantity")]
     pub block_number: U64,
     pub txs: Vec<Bytes>,
     #[serde(default, deserialize_with = "deserialize_vec_b256_from_null_or_string")]
@@ -95,15 +137,74 @@ pub struct RawBundle {
     pub replacement_uuid: Option<Uuid>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signing_address: Option<Address>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_quantity"
+    )]
     pub min_timestamp: Option<u64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_quantity"
+    )]
     pub max_timestamp: Option<u64>,
     /// See [`BundleReplacementData`] sequence_number
     #[serde(skip_serializing_if = "Option::is_none")]
     pub replacement_nonce: Option<u64>,
 }
 
+/// Positional eth_sendBundle params of mev-geth v0.1: [txs, blockNumber, minTimestamp, maxTimestamp,
+/// revertingTxHashes], everything after blockNumber is optional.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LegacyRawBundle(
+    pub Vec<Bytes>,
+    #[serde(deserialize_with = "deserialize_quantity")] pub U64,
+    #[serde(default, deserialize_with = "deserialize_optional_quantity")] pub Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_optional_quantity")] pub Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_vec_b256_from_null_or_string")] pub Vec<B256>,
+);
+
+impl From<LegacyRawBundle> for RawBundle {
+    fn from(legacy: LegacyRawBundle) -> Self {
+        let LegacyRawBundle(txs, block_number, min_timestamp, max_timestamp, reverting_tx_hashes) =
+            legacy;
+        Self {
+            block_number,
+            txs,
+            reverting_tx_hashes,
+            replacement_uuid: None,
+            signing_address: None,
+            min_timestamp,
+            max_timestamp,
+            replacement_nonce: None,
+        }
+    }
+}
+
+/// eth_callBundle params (flashbots format). Other fields (stateBlockNumber, timestamp...) are ignored, the bundle is
+/// simulated on top of the parent of the block being built with no timestamp bounds.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawCallBundle {
+    pub txs: Vec<Bytes>,
+    #[serde(deserialize_with = "deserialize_quantity")]
+    pub block_number: U64,
+}
+
+impl From<RawCallBundle> for RawBundle {
+    fn from(call_bundle: RawCallBundle) -> Self {
+        LegacyRawBundle(
+            call_bundle.txs,
+            call_bundle.block_number,
+            None,
+            None,
+            Vec::new(),
+        )
+        .into()
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RawBundleConvertError {
     #[error("Failed to decode transaction, idx: {0}, error: {0}")]
@@ -224,6 +325,62 @@ impl RawTx {
     }
 }
 
+/// eth_sendPrivateTransaction params (flashbots format).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPrivateTx {
+    pub tx: Bytes,
+    /// Last block the tx can be included in, no limit if unset.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_quantity"
+    )]
+    pub max_block_number: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferences: Option<RawPrivateTxPreferences>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPrivateTxPreferences {
+    /// Share with every builder. Ignored: we never send orders to other builders, private txs only go to our own
+    /// standby instances (see [`crate::live_builder::order_input::orderpool_sync`]).
+    #[serde(default)]
+    pub fast: bool,
+    /// Same as the share bundle one (hints are not kept, we don't share with other builders).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<RawShareBundlePrivacy>,
+}
+
+impl RawPrivateTx {
+    /// Same rules as [RawShareBundle::targets_builder].
+    pub fn targets_builder(&self, builder_names: &[String]) -> bool {
+        privacy_targets_builder(
+            self.preferences
+                .as_ref()
+                .and_then(|preferences| preferences.privacy.as_ref()),
+            builder_names,
+        )
+    }
+
+    pub fn decode(self, encoding: TxEncoding) -> Result<MempoolTx, RawTxWithBlobsConvertError> {
+        Ok(MempoolTx::new(encoding.decode(self.tx)?).with_max_block(self.max_block_number))
+    }
+
+    /// Preferences are lost.
+    pub fn encode_no_blobs(value: MempoolTx) -> Self {
+        Self {
+            tx: value.tx_with_blobs.envelope_encoded_no_blobs(),
+            max_block_number: value.max_block,
+            preferences: None,
+        }
+    }
+}
+
+/// RawShareBundle::version values we accept (on every nesting level).
+pub const SHARE_BUNDLE_VERSIONS: &[&str] = &["v0.1", "version-1"];
+
 /// Struct to de/serialize json Bundles from bundles APIs and from/db.
 /// Does not assume a particular format on txs.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -236,6 +393,8 @@ pub struct RawShareBundle {
     pub validity: Option<RawShareBundleValidity>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<RawShareBundleMetadatada>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<RawShareBundlePrivacy>,
     pub replacement_uuid: Option<Uuid>,
 }
 
@@ -247,6 +406,13 @@ pub struct RawShareBundleInclusion {
     pub max_block: Option<U64>,
 }
 
+impl RawShareBundleInclusion {
+    /// (block, max_block), max_block defaults to block.
+    pub fn range(&self) -> (u64, u64) {
+        (self.block.to(), self.max_block.unwrap_or(self.block).to())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RawShareBundleBody {
@@ -280,6 +446,31 @@ pub struct RawShareBundleMetadatada {
     pub cancelled: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawShareBundlePrivacy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hints: Option<Vec<String>>,
+    /// Builders (by name) allowed to see the bundle. None or empty means any builder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builders: Option<Vec<String>>,
+}
+
+/// true if privacy.builders is not set/empty or names one of builder_names (case insensitive).
+fn privacy_targets_builder(
+    privacy: Option<&RawShareBundlePrivacy>,
+    builder_names: &[String],
+) -> bool {
+    match privacy.and_then(|privacy| privacy.builders.as_ref()) {
+        Some(builders) if !builders.is_empty() => builders.iter().any(|builder| {
+            builder_names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(builder.trim()))
+        }),
+        _ => true,
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RawShareBundleConvertError {
     #[error("Failed to decode transaction, idx: {0}, error: {0}")]
@@ -292,6 +483,12 @@ pub enum RawShareBundleConvertError {
     EmptyBody,
     #[error("Total refund percent exceeds 100")]
     TotalRefundTooBig,
+    #[error("Refund body idx {0} out of range")]
+    RefundBodyIdxOutOfRange(usize),
+    #[error("Invalid inclusion, block {0} max block {1}")]
+    InvalidInclusion(u64, u64),
+    #[error("Inclusion of nested bundle does not overlap with its parent")]
+    NestedInclusionOutOfRange,
     #[error("Refund config does not add to 100")]
     RefundConfigIncorrect,
     #[error("Found cancel on decode_new_bundle")]
@@ -313,6 +510,29 @@ pub enum RawShareBundleDecodeResult {
 }
 
 impl RawShareBundle {
+    /// true if we (known by any of builder_names) are allowed to see the bundle.
+    /// Every bundle in the tree must allow us since accepting the bundle exposes the nested ones.
+    /// Names are compared case insensitive.
+    pub fn targets_builder(&self, builder_names: &[String]) -> bool {
+        privacy_targets_builder(self.privacy.as_ref(), builder_names)
+            && self
+                .body
+                .iter()
+                .filter_map(|body| body.bundle.as_ref())
+                .all(|bundle| bundle.targets_builder(builder_names))
+    }
+
+    /// Txs of the bundle including the ones on nested bundles.
+    pub fn tx_count(&self) -> usize {
+        self.body
+            .iter()
+            .map(|body| {
+                body.tx.is_some() as usize
+                    + body.bundle.as_ref().map_or(0, |bundle| bundle.tx_count())
+            })
+            .sum()
+    }
+
     /// Same as decode but fails on cancel
     pub fn decode_new_bundle(
         self,
@@ -331,13 +551,12 @@ impl RawShareBundle {
         self,
         encoding: TxEncoding,
     ) -> Result<RawShareBundleDecodeResult, RawShareBundleConvertError> {
-        let (block, max_block) = (
-            self.inclusion.block.to(),
-            self.inclusion
-                .max_block
-                .unwrap_or(self.inclusion.block)
-                .to(),
-        );
+        let (block, max_block) = self.inclusion.range();
+        if max_block < block {
+            return Err(RawShareBundleConvertError::InvalidInclusion(
+                block, max_block,
+            ));
+        }
 
         let signer = self.metadata.as_ref().and_then(|m| m.signer);
         let replacement_nonce = self.metadata.as_ref().and_then(|m| m.replacement_nonce);
@@ -364,7 +583,28 @@ impl RawShareBundle {
             ));
         }
 
-        let (_, inner_bundle) = extract_inner_bundle(0, 0, self, &encoding)?;
+        let privacy_hints = self
+            .privacy
+            .as_ref()
+            .and_then(|privacy| privacy.hints.as_ref())
+            .map(|hints| {
+                hints
+                    .iter()
+                    .filter_map(|hint| {
+                        let parsed = PrivacyHint::parse(hint);
+                        if parsed.is_none() {
+                            trace!(?hint, "Ignoring unknown privacy hint");
+                        }
+                        parsed
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // nested bundles can narrow the blocks where the whole bundle is valid
+        let mut inclusion = (block, max_block);
+        let (_, inner_bundle) = extract_inner_bundle(0, 0, &mut inclusion, self, &encoding)?;
+        let (block, max_block) = inclusion;
         let mut bundle = ShareBundle {
             hash: Default::default(),
             block,
@@ -373,6 +613,7 @@ impl RawShareBundle {
             signer,
             replacement_data,
             original_orders: Vec::new(),
+            privacy_hints,
             metadata: Default::default(),
         };
 
@@ -393,6 +634,16 @@ impl RawShareBundle {
             replacement_nonce: None,
             cancelled: false,
         });
+        result.privacy = (!value.privacy_hints.is_empty()).then(|| RawShareBundlePrivacy {
+            hints: Some(
+                value
+                    .privacy_hints
+                    .iter()
+                    .map(|hint| hint.as_str().to_string())
+                    .collect(),
+            ),
+            builders: None,
+        });
         result
     }
 }
@@ -413,16 +664,19 @@ fn create_revert_behavior(can_revert: bool, revert_mode: Option<String>) -> TxRe
     }
 }
 
+/// inclusion is the (block, max_block) range where all the bundles seen so far are valid, it's narrowed with the
+/// inclusion of the nested bundles.
 fn extract_inner_bundle(
     depth: usize,
     mut tx_count: usize,
+    inclusion: &mut (u64, u64),
     raw: RawShareBundle,
     encoding: &TxEncoding,
 ) -> Result<(usize, ShareBundleInner), RawShareBundleConvertError> {
     if depth > 5 {
         return Err(RawShareBundleConvertError::BundleTooDeep);
     }
-    if raw.version != "v0.1" && raw.version != "version-1" {
+    if !SHARE_BUNDLE_VERSIONS.contains(&raw.version.as_str()) {
         return Err(RawShareBundleConvertError::IncorrectVersion);
     }
 
@@ -443,10 +697,15 @@ fn extract_inner_bundle(
                 }
 
                 if let Some(bundle) = body.bundle {
-                    // TODO: check that inclusion is correct
+                    let (block, max_block) = bundle.inclusion.range();
+                    inclusion.0 = inclusion.0.max(block);
+                    inclusion.1 = inclusion.1.min(max_block);
+                    if inclusion.0 > inclusion.1 {
+                        return Err(RawShareBundleConvertError::NestedInclusionOutOfRange);
+                    }
 
                     let (new_tx_count, extracted_inner_bundle) =
-                        extract_inner_bundle(depth + 1, tx_count, *bundle, encoding)?;
+                        extract_inner_bundle(depth + 1, tx_count, inclusion, *bundle, encoding)?;
                     tx_count = new_tx_count;
                     return Ok(ShareBundleBody::Bundle(extracted_inner_bundle));
                 }
@@ -463,6 +722,12 @@ fn extract_inner_bundle(
                 return Err(RawShareBundleConvertError::TotalRefundTooBig);
             }
 
+            if let Some(refund) = v.refund.iter().find(|r| r.body_idx >= body.len()) {
+                return Err(RawShareBundleConvertError::RefundBodyIdxOutOfRange(
+                    refund.body_idx,
+                ));
+            }
+
             if !v.refund_config.is_empty()
                 && v.refund_config.iter().map(|r| r.percent).sum::<usize>() > 100
             {
@@ -526,6 +791,7 @@ fn inner_bundle_to_raw_bundle_no_blobs(
         body,
         validity,
         metadata: None,
+        privacy: None,
         replacement_uuid: None,
     }
 }
@@ -537,6 +803,8 @@ pub enum RawOrder {
     Bundle(RawBundle),
     Tx(RawTx),
     ShareBundle(RawShareBundle),
+    /// Mempool tx with [MempoolTx::max_block].
+    PrivateTx(RawPrivateTx),
 }
 
 #[derive(Error, Debug)]
@@ -563,6 +831,10 @@ impl RawOrder {
                 tx.decode(encoding)
                     .map_err(RawOrderConvertError::FailedToDecodeTransaction)?,
             )),
+            RawOrder::PrivateTx(tx) => Ok(Order::Tx(
+                tx.decode(encoding)
+                    .map_err(RawOrderConvertError::FailedToDecodeTransaction)?,
+            )),
 
             RawOrder::ShareBundle(bundle) => Ok(Order::ShareBundle(
                 bundle
@@ -573,10 +845,41 @@ impl RawOrder {
     }
 }
 
+/// Payload of one of the intake methods, allows to compute the [OrderId] an order gets without submitting it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "method", content = "params")]
+pub enum RawOrderSubmission {
+    #[serde(rename = "eth_sendBundle")]
+    Bundle(RawBundle),
+    #[serde(rename = "mev_sendBundle")]
+    ShareBundle(RawShareBundle),
+    #[serde(rename = "eth_sendRawTransaction")]
+    Tx(Bytes),
+    #[serde(rename = "eth_sendPrivateTransaction")]
+    PrivateTx(RawPrivateTx),
+}
+
+impl RawOrderSubmission {
+    /// Fully decodes the payload (same as the intake) so invalid orders fail here too.
+    /// Fails for mev_sendBundle cancellations.
+    pub fn order_id(self) -> Result<OrderId, RawOrderConvertError> {
+        let raw_order = match self {
+            Self::Bundle(bundle) => RawOrder::Bundle(bundle),
+            Self::ShareBundle(bundle) => RawOrder::ShareBundle(bundle),
+            Self::Tx(tx) => RawOrder::Tx(RawTx { tx }),
+            Self::PrivateTx(tx) => RawOrder::PrivateTx(tx),
+        };
+        Ok(raw_order.decode(TxEncoding::WithBlobData)?.id())
+    }
+}
+
 impl From<Order> for RawOrder {
     fn from(value: Order) -> Self {
         match value {
             Order::Bundle(bundle) => Self::Bundle(RawBundle::encode_no_blobs(bundle)),
+            Order::Tx(tx) if tx.max_block.is_some() => {
+                Self::PrivateTx(RawPrivateTx::encode_no_blobs(tx))
+            }
             Order::Tx(tx) => Self::Tx(RawTx::encode_no_blobs(tx)),
             Order::ShareBundle(bundle) => {
                 Self::ShareBundle(RawShareBundle::encode_no_blobs(bundle))
@@ -764,6 +1067,60 @@ mod tests {
         assert_eq!(bundle.max_timestamp, None);
     }
 
+    #[test]
+    fn test_legacy_bundle_formats() {
+        let hash = "0xda7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c";
+        let expected = RawBundle {
+            block_number: U64::from(18_050_847),
+            txs: Vec::new(),
+            reverting_tx_hashes: vec![fixed_bytes!(
+                "da7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c"
+            )],
+            replacement_uuid: None,
+            signing_address: None,
+            min_timestamp: Some(10),
+            max_timestamp: Some(26),
+            replacement_nonce: None,
+        };
+        for (block_number, min_timestamp, max_timestamp) in [
+            (r#""0x1136F1F""#, "10", "26"),
+            ("18050847", r#""0xa""#, r#""26""#),
+            (r#""18050847""#, "10", r#""0x1a""#),
+        ] {
+            let json = format!(
+                r#"{{"blockNumber": {}, "txs": [], "minTimestamp": {}, "maxTimestamp": {}, "revertingTxHashes": ["{}"]}}"#,
+                block_number, min_timestamp, max_timestamp, hash
+            );
+            let raw_bundle: RawBundle = serde_json::from_str(&json).unwrap();
+            assert_eq!(raw_bundle, expected);
+        }
+        assert!(
+            serde_json::from_str::<RawBundle>(r#"{"blockNumber": "0xzz", "txs": []}"#).is_err()
+        );
+
+        // mev-geth v0.1 positional params
+        let legacy: LegacyRawBundle =
+            serde_json::from_str(&format!(r#"[[], "0x1136F1F", 10, "0x1a", ["{}"]]"#, hash))
+                .unwrap();
+        assert_eq!(RawBundle::from(legacy), expected);
+        let legacy: LegacyRawBundle = serde_json::from_str(r#"[[], "0x1136F1F", 0, 0]"#).unwrap();
+        let bundle = RawBundle::from(legacy)
+            .try_into(TxEncoding::WithBlobData)
+            .unwrap();
+        assert_eq!(bundle.block, 18_050_847);
+        assert_eq!(bundle.min_timestamp, Some(0));
+        assert_eq!(bundle.max_timestamp, Some(0));
+
+        let call_bundle: RawCallBundle = serde_json::from_str(
+            r#"{"txs": [], "blockNumber": "0x1136F1F", "stateBlockNumber": "latest", "timestamp": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            RawBundle::from(call_bundle).block_number,
+            U64::from(18_050_847)
+        );
+    }
+
     #[test]
     fn test_correct_raw_tx_decoding() {
         // raw json string
@@ -799,6 +1156,79 @@ mod tests {
         assert_eq!(tx.value(), U256::from(36280797113317316u128));
     }
 
+    #[test]
+    fn test_private_tx_decoding() {
+        let tx_json = r#"{
+            "tx": "0x02f9037b018203cd8405f5e1008503692da370830388ba943fc91a3afd70395cd496c647d5a6cc9d4b2b7fad8780e531581b77c4b903043593564c000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000000000000000000000000000000000000064f390d300000000000000000000000000000000000000000000000000000000000000030b090c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000001e0000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000009184e72a0000000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000000000000000b5ea574dd8f2b735424dfc8c4e16760fc44a931b000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000000c001a0a9ea84ad107d335afd5e5d2ddcc576f183be37386a9ac6c9d4469d0329c22e87a06a51ea5a0809f43bf72d0156f1db956da3a9f3da24b590b7eed01128ff84a2c1",
+            "maxBlockNumber": "0x1136F1F",
+            "preferences": { "fast": true, "privacy": { "builders": ["flashbots"] } }
+        }"#;
+        let raw_private_tx: RawPrivateTx =
+            serde_json::from_str(tx_json).expect("failed to decode private tx");
+        assert_eq!(raw_private_tx.max_block_number, Some(18_050_847));
+        assert!(!raw_private_tx.targets_builder(&["rbuilder".to_string()]));
+        assert!(raw_private_tx.targets_builder(&["Flashbots".to_string()]));
+
+        let tx = raw_private_tx
+            .clone()
+            .decode(TxEncoding::WithBlobData)
+            .expect("failed to convert private tx");
+        assert_eq!(tx.max_block, Some(18_050_847));
+        assert!(tx.valid_for_block(18_050_847));
+        assert!(!tx.valid_for_block(18_050_848));
+
+        let roundtrip = RawPrivateTx::encode_no_blobs(tx.clone());
+        assert_eq!(roundtrip.tx, raw_private_tx.tx);
+        assert_eq!(roundtrip.max_block_number, Some(18_050_847));
+        assert_eq!(
+            RawOrder::from(Order::Tx(tx)),
+            RawOrder::PrivateTx(roundtrip)
+        );
+
+        // only tx, no window
+        let raw_private_tx: RawPrivateTx =
+            serde_json::from_str(r#"{ "tx": "0x02f9037b018203cd8405f5e1008503692da370830388ba943fc91a3afd70395cd496c647d5a6cc9d4b2b7fad8780e531581b77c4b903043593564c000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000000000000000000000000000000000000064f390d300000000000000000000000000000000000000000000000000000000000000030b090c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000001e0000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000009184e72a0000000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000000000000000b5ea574dd8f2b735424dfc8c4e16760fc44a931b000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000000c001a0a9ea84ad107d335afd5e5d2ddcc576f183be37386a9ac6c9d4469d0329c22e87a06a51ea5a0809f43bf72d0156f1db956da3a9f3da24b590b7eed01128ff84a2c1" }"#).unwrap();
+        let tx = raw_private_tx.decode(TxEncoding::WithBlobData).unwrap();
+        assert_eq!(tx.max_block, None);
+        assert!(tx.valid_for_block(u64::MAX));
+    }
+
+    #[test]
+    fn test_raw_tx_envelope_is_not_copied() {
+        let raw_tx = RawTx {
+            tx: bytes!("02f86b0180843b9aca00852ecc889a0082520894c87037874aed04e51c29f582394217a0a2b89d808080c080a0a463985c616dd8ee17d7ef9112af4e6e06a27b071525b42182fe7b0b5c8b4925a00af5ca177ffef2ff28449292505d41be578bebb77110dfc09361d2fb56998260"),
+        };
+        for encoding in [TxEncoding::WithBlobData, TxEncoding::NoBlobData] {
+            let tx = raw_tx.clone().decode(encoding).unwrap();
+            let encoded = RawTx::encode_no_blobs(tx.clone());
+            assert_eq!(encoded, raw_tx);
+            // same buffer we got
+            assert_eq!(encoded.tx.as_ptr(), raw_tx.tx.as_ptr());
+            // and after re-encoding the tx
+            let mut buf = Vec::new();
+            tx.tx_with_blobs
+                .internal_tx_unsecure()
+                .as_signed()
+                .encode_2718(&mut buf);
+            assert_eq!(buf, raw_tx.tx.to_vec());
+        }
+
+        // txs we create are encoded once
+        let tx = TransactionSignedEcRecoveredWithBlobs::new_for_testing(
+            raw_tx
+                .clone()
+                .decode(TxEncoding::NoBlobData)
+                .unwrap()
+                .tx_with_blobs
+                .into_internal_tx_unsecure(),
+        );
+        let clone = tx.clone();
+        assert_eq!(
+            tx.envelope_encoded_no_blobs().as_ptr(),
+            clone.envelope_encoded_no_blobs().as_ptr()
+        );
+    }
+
     #[test]
     fn test_correct_share_bundle_decoding() {
         // raw json string
@@ -860,6 +1290,7 @@ mod tests {
 
         let bundle_request: RawShareBundle =
             serde_json::from_str(bundle_json).expect("failed to decode share bundle");
+        assert_eq!(bundle_request.tx_count(), 3);
 
         let bundle = bundle_request
             .clone()
@@ -963,6 +1394,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_share_bundle_privacy_builders() {
+        let bundle = |privacy: &str, inner_privacy: &str| -> RawShareBundle {
+            let json = format!(
+                r#"{{
+                "version": "v0.1",
+                "inclusion": {{ "block": "0x1" }},
+                "body": [{{ "bundle": {{
+                    "version": "v0.1",
+                    "inclusion": {{ "block": "0x1" }},
+                    "body": [{{ "tx": "0x00" }}]
+                    {inner_privacy}
+                }} }}]
+                {privacy}
+            }}"#
+            );
+            serde_json::from_str(&json).expect("failed to parse share bundle")
+        };
+        let names = vec!["rbuilder".to_string(), "our-builder".to_string()];
+
+        // no restriction
+        assert!(bundle("", "").targets_builder(&names));
+        assert!(bundle(r#", "privacy": { "hints": ["calldata"] }"#, "").targets_builder(&names));
+        assert!(bundle(r#", "privacy": { "builders": [] }"#, "").targets_builder(&names));
+        // listed (case insensitive)
+        assert!(bundle(
+            r#", "privacy": { "builders": ["flashbots", "RBuilder"] }"#,
+            ""
+        )
+        .targets_builder(&names));
+        assert!(
+            bundle(r#", "privacy": { "builders": ["our-builder"] }"#, "").targets_builder(&names)
+        );
+        // targeted elsewhere
+        let elsewhere = bundle(r#", "privacy": { "builders": ["flashbots"] }"#, "");
+        assert!(!elsewhere.targets_builder(&names));
+        assert!(!elsewhere.targets_builder(&[]));
+        assert!(bundle("", "").targets_builder(&[]));
+        // nested bundle targeted elsewhere
+        assert!(
+            !bundle("", r#", "privacy": { "builders": ["flashbots"] }"#).targets_builder(&names)
+        );
+
+        // kept when reserialized
+        let json = serde_json::to_string(&elsewhere).unwrap();
+        let reparsed: RawShareBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(reparsed, elsewhere);
+    }
+
+    #[test]
+    fn test_share_bundle_validity() {
+        let bundle = |inclusion: &str, inner_inclusion: &str, validity: &str, privacy: &str| {
+            let json = format!(
+                r#"{{
+                "version": "v0.1",
+                "inclusion": {inclusion},
+                "body": [
+                    {{ "bundle": {{
+                        "version": "v0.1",
+                        "inclusion": {inner_inclusion},
+                        "body": [{{ "tx": "0x02f86b0180843b9aca00852ecc889a0082520894c87037874aed04e51c29f582394217a0a2b89d808080c080a0a463985c616dd8ee17d7ef9112af4e6e06a27b071525b42182fe7b0b5c8b4925a00af5ca177ffef2ff28449292505d41be578bebb77110dfc09361d2fb56998260" }}]
+                    }} }},
+                    {{ "tx": "0x02f8730101843b9aca00852ecc889a008288b894c10000000000000000000000000000000000000088016345785d8a000080c001a0650c394d77981e46be3d8cf766ecc435ec3706375baed06eb9bef21f9da2828da064965fdf88b91575cd74f20301649c9d011b234cefb6c1761cc5dd579e4750b1" }}
+                ]
+                {validity}
+                {privacy}
+            }}"#
+            );
+            serde_json::from_str::<RawShareBundle>(&json)
+                .expect("failed to parse share bundle")
+                .decode_new_bundle(TxEncoding::WithBlobData)
+        };
+        let refund = r#", "validity": { "refund": [{ "bodyIdx": 0, "percent": 50 }, { "bodyIdx": 0, "percent": 40 }] }"#;
+
+        // nested bundles narrow the inclusion
+        let decoded = bundle(
+            r#"{ "block": "0x1", "maxBlock": "0x5" }"#,
+            r#"{ "block": "0x2", "maxBlock": "0x8" }"#,
+            refund,
+            "",
+        )
+        .unwrap();
+        assert_eq!((decoded.block, decoded.max_block), (2, 5));
+        assert_eq!(decoded.inner_bundle.refund.len(), 2);
+
+        assert!(matches!(
+            bundle(
+                r#"{ "block": "0x1", "maxBlock": "0x3" }"#,
+                r#"{ "block": "0x4" }"#,
+                "",
+                ""
+            ),
+            Err(RawShareBundleConvertError::NestedInclusionOutOfRange)
+        ));
+        assert!(matches!(
+            bundle(
+                r#"{ "block": "0x3", "maxBlock": "0x1" }"#,
+                r#"{ "block": "0x1" }"#,
+                "",
+                ""
+            ),
+            Err(RawShareBundleConvertError::InvalidInclusion(3, 1))
+        ));
+        assert!(matches!(
+            bundle(
+                r#"{ "block": "0x1" }"#,
+                r#"{ "block": "0x1" }"#,
+                r#", "validity": { "refund": [{ "bodyIdx": 2, "percent": 50 }] }"#,
+                ""
+            ),
+            Err(RawShareBundleConvertError::RefundBodyIdxOutOfRange(2))
+        ));
+        assert!(matches!(
+            bundle(
+                r#"{ "block": "0x1" }"#,
+                r#"{ "block": "0x1" }"#,
+                r#", "validity": { "refund": [{ "bodyIdx": 0, "percent": 60 }, { "bodyIdx": 1, "percent": 50 }] }"#,
+                ""
+            ),
+            Err(RawShareBundleConvertError::TotalRefundTooBig)
+        ));
+
+        // known hints are kept (and reencoded), unknown ones dropped
+        let decoded = bundle(
+            r#"{ "block": "0x1" }"#,
+            r#"{ "block": "0x1" }"#,
+            refund,
+            r#", "privacy": { "hints": ["calldata", "tx_hash", "not_a_hint"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            decoded.privacy_hints,
+            vec![PrivacyHint::Calldata, PrivacyHint::TxHash]
+        );
+        let encoded = RawShareBundle::encode_no_blobs(decoded);
+        assert_eq!(
+            encoded.privacy.and_then(|privacy| privacy.hints),
+            Some(vec!["calldata".to_string(), "tx_hash".to_string()])
+        );
+    }
+
     #[test]
     fn test_correct_raw_order_decoding() {
         // raw json string
@@ -988,6 +1560,42 @@ mod tests {
         assert!(matches!(raw_order, RawOrder::Tx(_)));
     }
 
+    #[test]
+    fn test_raw_order_submission_order_id() {
+        let tx = "0x02f9037b018203cd8405f5e1008503692da370830388ba943fc91a3afd70395cd496c647d5a6cc9d4b2b7fad8780e531581b77c4b903043593564c000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000000000000000000000000000000000000064f390d300000000000000000000000000000000000000000000000000000000000000030b090c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003000000000000000000000000000000000000000000000000000000000000006000000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000001e0000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000009184e72a0000000000000000000000000000000000000000000000000000080e531581b77c400000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000000000000000b5ea574dd8f2b735424dfc8c4e16760fc44a931b000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000000c001a0a9ea84ad107d335afd5e5d2ddcc576f183be37386a9ac6c9d4469d0329c22e87a06a51ea5a0809f43bf72d0156f1db956da3a9f3da24b590b7eed01128ff84a2c1";
+        let submission: RawOrderSubmission = serde_json::from_str(&format!(
+            r#"{{"method": "eth_sendBundle", "params": {{"blockNumber": "0x1136F1F", "txs": ["{tx}"], "revertingTxHashes": ["0xda7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c"]}}}}"#
+        ))
+        .unwrap();
+        assert_eq!(
+            submission.order_id().unwrap().to_string(),
+            "bundle:a90205bc-2afd-5afe-b315-f17d597ffd97"
+        );
+
+        let submission: RawOrderSubmission = serde_json::from_str(&format!(
+            r#"{{"method": "eth_sendRawTransaction", "params": "{tx}"}}"#
+        ))
+        .unwrap();
+        assert_eq!(
+            submission.order_id().unwrap(),
+            OrderId::Tx(fixed_bytes!(
+                "da7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c"
+            ))
+        );
+
+        // single tx share bundle hashes to the tx
+        let submission: RawOrderSubmission = serde_json::from_str(&format!(
+            r#"{{"method": "mev_sendBundle", "params": {{"version": "v0.1", "inclusion": {{"block": "0x1136F1F"}}, "body": [{{"tx": "{tx}"}}], "validity": {{}}}}}}"#
+        ))
+        .unwrap();
+        assert_eq!(
+            submission.order_id().unwrap(),
+            OrderId::ShareBundle(fixed_bytes!(
+                "da7007bee134daa707d0e7399ce35bb451674f042fbbbcac3f6a3cb77846949c"
+            ))
+        );
+    }
+
     /// We decode a 4484 Tx in canonical format using WithBlobData which is for network format.
     /// We expect the specific error FailedToDecodeTransactionProbablyIs4484Canonical.
     #[test]
@@ -92,6 +92,7 @@ impl TestDataGenerator {
             signer: replacement_data.as_ref().map(|r| r.key.key().signer),
             replacement_data,
             original_orders: Vec::new(),
+            privacy_hints: Vec::new(),
             metadata: Default::default(),
         };
         res.hash_slow();
@@ -131,9 +132,7 @@ impl TestDataGenerator {
     }
 
     pub fn create_mempool_tx(&mut self, sender_nonce: AccountNonce) -> MempoolTx {
-        MempoolTx {
-            tx_with_blobs: self.create_tx_with_blobs_nonce(sender_nonce),
-        }
+        MempoolTx::new(self.create_tx_with_blobs_nonce(sender_nonce))
     }
 
     pub fn create_tx_order(&mut self, sender_nonce: AccountNonce) -> Order {
@@ -1,3 +1,4 @@
+pub mod payment_proof;
 mod prefetcher;
 
 use alloy_primitives::B256;
@@ -58,6 +59,12 @@ pub struct RootHashConfig {
     pub mode: RootHashMode,
     pub use_sparse_trie: bool,
     pub compare_sparse_trie_output: bool,
+    /// Generate a [`payment_proof::ProposerPaymentProof`] for each finalized block (ignored on SkipRootHash).
+    pub generate_payment_proof: bool,
+    /// Generate a [`crate::building::block_template::BlockTemplate`] for each finalized block (ignored on
+    /// SkipRootHash). Finalize only keeps the inputs, the slow part (a proof for every account touched by the block)
+    /// is done when the block is submitted.
+    pub generate_block_template: bool,
 }
 
 impl RootHashConfig {
@@ -66,6 +73,8 @@ impl RootHashConfig {
             mode: RootHashMode::SkipRootHash,
             use_sparse_trie: false,
             compare_sparse_trie_output: false,
+            generate_payment_proof: false,
+            generate_block_template: false,
         }
     }
 
@@ -74,6 +83,22 @@ impl RootHashConfig {
             mode: RootHashMode::CorrectRoot,
             use_sparse_trie,
             compare_sparse_trie_output,
+            generate_payment_proof: false,
+            generate_block_template: false,
+        }
+    }
+
+    pub fn with_block_template(self, generate_block_template: bool) -> Self {
+        Self {
+            generate_block_template,
+            ..self
+        }
+    }
+
+    pub fn with_payment_proof(self, generate_payment_proof: bool) -> Self {
+        Self {
+            generate_payment_proof,
+            ..self
         }
     }
 }
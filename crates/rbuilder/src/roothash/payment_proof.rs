@@ -0,0 +1,113 @@
+//! Proof of the proposer payment.
+//! Some relays don't want to trust the bid value and ask for merkle proofs of the fee recipient account before (parent
+//! state root) and after (block state root) the block so they can check the balance change without executing it.
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use reth::providers::ExecutionOutcome;
+use reth_errors::ProviderError;
+use reth_provider::{StateProofProvider, StateProviderFactory};
+use reth_trie::{AccountProof, TrieInput};
+use revm_primitives::KECCAK_EMPTY;
+use serde::{Deserialize, Serialize};
+
+/// Account state + the trie nodes from the state root to the account leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountStateProof {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code_hash: B256,
+    pub storage_root: B256,
+    pub account_proof: Vec<Bytes>,
+}
+
+impl From<AccountProof> for AccountStateProof {
+    fn from(proof: AccountProof) -> Self {
+        let info = proof.info.unwrap_or_default();
+        Self {
+            balance: info.balance,
+            nonce: info.nonce,
+            code_hash: info.bytecode_hash.unwrap_or(KECCAK_EMPTY),
+            storage_root: proof.storage_root,
+            account_proof: proof.proof,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposerPaymentProof {
+    pub fee_recipient: Address,
+    /// Against the parent block state root.
+    pub pre_state: AccountStateProof,
+    /// Against the block state root.
+    pub post_state: AccountStateProof,
+}
+
+impl ProposerPaymentProof {
+    /// What the proposer got paid by the block.
+    pub fn balance_diff(&self) -> U256 {
+        self.post_state
+            .balance
+            .saturating_sub(self.pre_state.balance)
+    }
+}
+
+/// Generates the proofs of fee_recipient on the parent state and on the parent state + outcome.
+pub fn generate_payment_proof<P>(
+    provider: &P,
+    parent_hash: B256,
+    outcome: &ExecutionOutcome,
+    fee_recipient: Address,
+) -> Result<ProposerPaymentProof, ProviderError>
+where
+    P: StateProviderFactory,
+{
+    let state_provider = provider.history_by_block_hash(parent_hash)?;
+    let pre_state = state_provider.proof(TrieInput::default(), fee_recipient, &[])?;
+    let post_state = state_provider.proof(
+        TrieInput::from_state(outcome.hash_state_slow()),
+        fee_recipient,
+        &[],
+    )?;
+    Ok(ProposerPaymentProof {
+        fee_recipient,
+        pre_state: pre_state.into(),
+        post_state: post_state.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::Account;
+
+    fn account_proof(balance: u64) -> AccountProof {
+        AccountProof {
+            info: Some(Account {
+                nonce: 1,
+                balance: U256::from(balance),
+                bytecode_hash: None,
+            }),
+            proof: vec![Bytes::from_static(&[1, 2, 3])],
+            ..AccountProof::new(Address::ZERO)
+        }
+    }
+
+    #[test]
+    fn test_payment_proof() {
+        let proof = ProposerPaymentProof {
+            fee_recipient: Address::ZERO,
+            pre_state: account_proof(10).into(),
+            post_state: account_proof(25).into(),
+        };
+        assert_eq!(proof.balance_diff(), U256::from(15));
+        assert_eq!(proof.pre_state.code_hash, KECCAK_EMPTY);
+
+        let json = serde_json::to_value(&proof).unwrap();
+        assert!(json["preState"]["accountProof"].is_array());
+        assert!(json["postState"]["storageRoot"].is_string());
+        let decoded: ProposerPaymentProof = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, proof);
+    }
+}
@@ -5,8 +5,12 @@
 //!
 //! When metric server is spawned is serves prometheus metrics at: /debug/metrics/prometheus
 
+use super::status_page::{
+    record_relay_accepted_submission, record_relay_error, record_relay_submit_latency,
+};
 use crate::{
-    building::ExecutionResult, primitives::mev_boost::MevBoostRelayID, utils::build_info::Version,
+    building::ExecutionResult, live_builder::slot_resource_report::SlotResourceReport,
+    primitives::mev_boost::MevBoostRelayID, utils::build_info::Version,
 };
 use alloy_primitives::{utils::Unit, U256};
 use bigdecimal::num_traits::Pow;
@@ -14,8 +18,8 @@ use ctor::ctor;
 use lazy_static::lazy_static;
 use metrics_macros::register_metrics;
 use prometheus::{
-    Counter, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
-    Registry,
+    Counter, Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry,
 };
 use std::time::Duration;
 use time::OffsetDateTime;
@@ -109,12 +113,33 @@ register_metrics! {
         &["relay", "kind"]
     )
     .unwrap();
+    pub static RELAY_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("relay_rejections", "Blocks rejected by relays by parsed reason"),
+        &["relay", "reason"]
+    )
+    .unwrap();
     pub static BLOCK_SIM_ERRORS: IntCounter = IntCounter::new("block_sim_errors", "counter of block simulation errors")
     .unwrap();
     pub static SIMULATED_OK_ORDERS: IntCounter =
         IntCounter::new("simulated_ok_orders", "Simulated succeeded orders").unwrap();
     pub static SIMULATED_FAILED_ORDERS: IntCounter =
         IntCounter::new("simulated_failed_orders", "Simulated failed orders").unwrap();
+    pub static ORDER_VALIDITY_REJECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("order_validity_rejections", "Orders rejected by the validity check before simulation"),
+        &["reason"],
+    ).unwrap();
+    pub static ORDER_DROPS: IntCounterVec = IntCounterVec::new(
+        Opts::new("order_drops", "Orders dropped or rejected by stage and reason (see OrderDropReason)"),
+        &["stage", "reason"],
+    ).unwrap();
+    pub static SHARE_BUNDLES_NOT_TARGETED: IntCounter = IntCounter::new(
+        "share_bundles_not_targeted", "mev-share bundles dropped because privacy.builders does not list us").unwrap();
+    pub static URGENT_RESEALS: IntCounter = IntCounter::new(
+        "urgent_reseals", "Bids sealed right away because they improved the best bid by more than the configured delta").unwrap();
+    pub static BID_ADJUSTMENTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("bid_adjustments", "Bids sent to the bid adjuster sidecar by result"),
+        &["result"],
+    ).unwrap();
     pub static SIMULATION_GAS_USED: IntCounter =
         IntCounter::new("simulation_gas_used", "Simulation gas used").unwrap();
     pub static ACTIVE_SLOTS: IntCounter =
@@ -133,11 +158,28 @@ register_metrics! {
         &["relay"],
     )
     .unwrap();
+    pub static RELAY_SUBMIT_BODY_PEAK_BYTES: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "relay_submit_body_peak_bytes",
+            "Max bytes of a relay submission body held in memory at once"
+        )
+        .buckets(exponential_buckets_range(1024.0, 64.0 * 1024.0 * 1024.0, 50)),
+        &["relay", "streaming"],
+    )
+    .unwrap();
     pub static VERSION: IntGaugeVec = IntGaugeVec::new(
         Opts::new("version", "Version of the builder"),
         &["git", "git_ref", "build_time_utc"]
     )
     .unwrap();
+    pub static RELAY_BIDS_BELOW_FLOOR: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "relay_bids_below_floor",
+            "Submissions skipped because the bid was below the relay floor"
+        ),
+        &["relay"]
+    )
+    .unwrap();
     pub static RELAY_ACCEPTED_SUBMISSIONS: IntCounterVec = IntCounterVec::new(
         Opts::new(
             "relay_accepted_submissions",
@@ -186,6 +228,195 @@ register_metrics! {
             .buckets(exponential_buckets_range(1.0, 3000.0, 100)),
         &[],
     ).unwrap();
+    pub static MEMPOOL_SOURCE_TXS: IntCounterVec = IntCounterVec::new(
+        Opts::new("mempool_source_txs", "Pending txs announced by each mempool source"),
+        &["source", "arrival"],
+    ).unwrap();
+    pub static MEMPOOL_SOURCE_ARRIVAL_DELAY: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("mempool_source_arrival_delay", "Delay of a mempool source announcing a tx after the first source did (ms)")
+            .buckets(exponential_buckets_range(1.0, 3000.0, 100)),
+        &["source"],
+    ).unwrap();
+    pub static DEVP2P_DROPPED: IntCounterVec = IntCounterVec::new(
+        Opts::new("devp2p_dropped", "devp2p network events and GetPooledTransactions requests dropped for lack of room"),
+        &["kind"],
+    ).unwrap();
+    pub static MEMPOOL_TX_REPLACEMENTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("mempool_tx_replacements", "Mempool txs reusing the sender and nonce of a tx in the orderpool"),
+        &["result"],
+    ).unwrap();
+
+    pub static STATE_READ_TIME: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("state_read_time", "Time of a single read from the state provider (us)")
+            .buckets(exponential_buckets_range(0.5, 50_000.0, 50)),
+        &["kind"],
+    ).unwrap();
+
+    /// Reads slower than SLOW_STATE_READ_THRESHOLD, these are usually page-cache misses on MDBX.
+    pub static SLOW_STATE_READS: IntCounterVec = IntCounterVec::new(
+        Opts::new("slow_state_reads", "Slow reads from the state provider"),
+        &["kind"],
+    ).unwrap();
+
+    /// Sum over all threads of the time spent reading from the state provider during a slot.
+    pub static BYTECODE_CACHE_ACCESSES: IntCounterVec = IntCounterVec::new(
+        Opts::new("bytecode_cache_accesses", "Bytecode cache lookups"),
+        &["result"]
+    )
+    .unwrap();
+
+    pub static CONFLICT_CACHE_ACCESSES: IntCounterVec = IntCounterVec::new(
+        Opts::new("conflict_cache_accesses", "Conflict cache lookups"),
+        &["result"]
+    )
+    .unwrap();
+
+    pub static SLOT_STATE_READ_TIME: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("slot_state_read_time", "Time spent reading state during a slot (ms)")
+            .buckets(exponential_buckets_range(1.0, 100_000.0, 50)),
+        &[],
+    ).unwrap();
+
+    /// SLOT_STATE_READ_TIME / time we had to build the slot. Values close or above 1 mean the slot was DB bound.
+    pub static SLOT_STATE_READ_BUDGET_RATIO: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("slot_state_read_budget_ratio", "Slot state read time over slot building time")
+            .buckets(exponential_buckets_range(0.001, 10.0, 50)),
+        &[],
+    ).unwrap();
+
+    pub static SLOT_CPU_TIME: Histogram = Histogram::with_opts(
+        HistogramOpts::new("slot_cpu_time", "Process CPU time while building a slot (ms)")
+            .buckets(exponential_buckets_range(10.0, 1_000_000.0, 50))
+    ).unwrap();
+    pub static SLOT_PEAK_RSS: Histogram = Histogram::with_opts(
+        HistogramOpts::new("slot_peak_rss", "Peak process RSS while building a slot (MiB)")
+            .buckets(exponential_buckets_range(64.0, 256_000.0, 50))
+    ).unwrap();
+    pub static SLOT_SIMULATIONS: Histogram = Histogram::with_opts(
+        HistogramOpts::new("slot_simulations", "Order simulations executed while building a slot")
+            .buckets(exponential_buckets_range(1.0, 1_000_000.0, 50))
+    ).unwrap();
+    pub static SLOT_STATE_READS: Histogram = Histogram::with_opts(
+        HistogramOpts::new("slot_state_reads", "Reads from the state provider while building a slot")
+            .buckets(exponential_buckets_range(1.0, 100_000_000.0, 50))
+    ).unwrap();
+    pub static SLOT_ORDERS_RECEIVED: Histogram = Histogram::with_opts(
+        HistogramOpts::new("slot_orders_received", "Orders received for a slot")
+            .buckets(exponential_buckets_range(1.0, 1_000_000.0, 50))
+    ).unwrap();
+
+    /// Late orders that went through the fast path by outcome (injected, conflicting, unprofitable...).
+    pub static LATE_ORDER_FAST_PATH: IntCounterVec = IntCounterVec::new(
+        Opts::new("late_order_fast_path", "Late orders processed by the fast path"),
+        &["outcome"],
+    ).unwrap();
+
+    pub static CONFLICT_EXHAUSTIVE_SEARCH_GAIN: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("conflict_exhaustive_search_gain_bps", "Avg profit exhaustive search adds over greedy for conflict groups"),
+        &["group_len"],
+    ).unwrap();
+    pub static CONFLICT_EXHAUSTIVE_SEARCH_MAX_GROUP_LEN: IntGauge =
+        IntGauge::new("conflict_exhaustive_search_max_group_len", "Learned max conflict group len for exhaustive search").unwrap();
+
+    pub static CONFLICT_PRUNED_SETS: IntCounter =
+        IntCounter::new("conflict_pruned_sets", "Conflict sets too big for the resolver pruned to their top orders").unwrap();
+    pub static CONFLICT_PRUNED_ORDERS: IntCounter =
+        IntCounter::new("conflict_pruned_orders", "Orders dropped from pruned conflict sets").unwrap();
+    pub static CONFLICT_PRUNED_VALUE_SUM: Counter =
+        Counter::new("conflict_pruned_value_sum", "Sum of the profit alone (ETH) of the orders dropped from pruned conflict sets").unwrap();
+
+    /// detector: slow, slow_parallel, fast (see [`crate::building::conflict`]).
+    pub static CONFLICT_DETECTION_TIME: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("conflict_detection_time", "Time to find the conflicts of an order set (ms)")
+            .buckets(exponential_buckets_range(0.1, 100_000.0, 50)),
+        &["detector"],
+    ).unwrap();
+    pub static CONFLICT_PAIRS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("conflict_pairs", "Order pairs of each conflict kind found by a detection")
+            .buckets(exponential_buckets_range(1.0, 10_000_000.0, 50)),
+        &["detector", "kind"],
+    ).unwrap();
+    pub static CONFLICT_SETS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("conflict_sets", "Conflict sets found by a detection")
+            .buckets(exponential_buckets_range(1.0, 100_000.0, 50)),
+        &["detector"],
+    ).unwrap();
+    pub static CONFLICT_SET_SIZE: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("conflict_set_size", "Orders in each conflict set")
+            .buckets(exponential_buckets_range(2.0, 100_000.0, 50)),
+        &["detector"],
+    ).unwrap();
+
+    /// transport: http, ws.
+    pub static RPC_CONNECTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("rpc_connections", "Connections accepted by the input RPC server"),
+        &["transport"],
+    ).unwrap();
+    pub static RPC_OPEN_CONNECTIONS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("rpc_open_connections", "Open connections of the input RPC server"),
+        &["transport"],
+    ).unwrap();
+    pub static RPC_CONNECTION_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("rpc_connection_duration", "Lifetime of the input RPC server connections (s)")
+            .buckets(exponential_buckets_range(0.01, 86_400.0, 50)),
+        &["transport"],
+    ).unwrap();
+    pub static RPC_CALLS: IntCounterVec = IntCounterVec::new(
+        Opts::new("rpc_calls", "Calls to the input RPC server"),
+        &["method", "transport"],
+    ).unwrap();
+    /// key: what the submitter was identified by (signer, ip, unknown).
+    pub static RPC_RATE_LIMITED: IntCounterVec = IntCounterVec::new(
+        Opts::new("rpc_rate_limited", "Input RPC requests rejected by the rate limiter"),
+        &["key"],
+    ).unwrap();
+
+    pub static ORDERFLOW_SHARED: IntCounterVec = IntCounterVec::new(
+        Opts::new("orderflow_shared", "Orders and cancellations shared with other builders"),
+        &["peer", "direction", "result"],
+    ).unwrap();
+
+    pub static TENANT_ORDERS: IntCounterVec = IntCounterVec::new(
+        Opts::new("tenant_orders", "Orderflow partner orders by event"),
+        &["tenant", "event"],
+    ).unwrap();
+    pub static INCLUSION_NOTIFICATIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("inclusion_notifications", "Landed block inclusion notifications sent to orderflow partners"),
+        &["tenant", "result"],
+    ).unwrap();
+    pub static REFUND_SETTLEMENT_PAYMENTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("refund_settlement_payments", "Deferred refund settlement payments by result"),
+        &["result"],
+    ).unwrap();
+    pub static ACCRUED_REFUNDS: Gauge =
+        Gauge::new("accrued_refunds", "Deferred refunds owed by the builder (ETH)").unwrap();
+    pub static REAPED_BUILDING_SESSIONS: IntCounter = IntCounter::new(
+        "reaped_building_sessions", "Building sessions with jobs still running after the slot deadline").unwrap();
+    pub static SLOT_OUTCOME_PREDICTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("slot_outcome_predictions", "Slots by building effort decided by the slot outcome predictor"),
+        &["effort"],
+    ).unwrap();
+    /// Fraction of the simulated orders in the last closed hour that touched the hottest contract.
+    pub static HOTTEST_CONTRACT_ORDER_SHARE: Gauge =
+        Gauge::new("hottest_contract_order_share", "Fraction of simulated orders touching the hottest contract").unwrap();
+    pub static IS_LEADER: IntGauge =
+        IntGauge::new("is_leader", "1 if this replica holds the relay submission lease").unwrap();
+    pub static LEADERSHIP_CHANGES: IntCounterVec = IntCounterVec::new(
+        Opts::new("leadership_changes", "Relay submission lease acquisitions and losses"),
+        &["event"],
+    ).unwrap();
+    pub static NODE_DEGRADED: IntGauge =
+        IntGauge::new("node_degraded", "1 if submissions are paused because the node head is behind").unwrap();
+    pub static NODE_HEAD_LAG: Gauge =
+        Gauge::new("node_head_lag", "Age of the node head block (seconds)").unwrap();
+    pub static NODE_DEGRADED_CHANGES: IntCounterVec = IntCounterVec::new(
+        Opts::new("node_degraded_changes", "Degraded mode enters and exits"),
+        &["event"],
+    ).unwrap();
+    pub static STANDBY_PROMOTED: IntGauge =
+        IntGauge::new("standby_promoted", "1 if this warm standby instance is promoted and submits to the relays").unwrap();
+    pub static STANDBY_WOULD_BE_SUBMISSIONS: IntCounter =
+        IntCounter::new("standby_would_be_submissions", "Bids recorded instead of submitted while in warm standby").unwrap();
 
      /////////////////////////////////
      // SUBSIDY
@@ -210,6 +441,19 @@ register_metrics! {
         )
         .unwrap();
 
+    /// Slot results from the relays data APIs (won, lost, unknown).
+    pub static SLOT_OUTCOMES: IntCounterVec = IntCounterVec::new(
+        Opts::new("slot_outcomes", "Slot results according to the relays data APIs"),
+        &["result"],
+    ).unwrap();
+    /// Winning bid - our best bid on the slots we lost.
+    pub static SLOT_LOSS_MARGIN: HistogramVec = HistogramVec::new(
+            HistogramOpts::new("slot_loss_margin", "By how much (eth) we lost a slot")
+                .buckets(exponential_buckets_range(0.00001, 1.0, 200)),
+        &[],
+        )
+        .unwrap();
+
     pub static TOTAL_LANDED_SUBSIDIES_SUM: Counter =
         Counter::new("total_landed_subsidies_sum", "Sum of all total landed subsidies").unwrap();
 }
@@ -227,19 +471,28 @@ pub(super) fn set_version(version: Version) {
 pub fn inc_other_relay_errors(relay: &MevBoostRelayID) {
     RELAY_ERRORS
         .with_label_values(&[relay.as_str(), RELAY_ERROR_OTHER])
-        .inc()
+        .inc();
+    record_relay_error(relay, RELAY_ERROR_OTHER);
 }
 
 pub fn inc_conn_relay_errors(relay: &MevBoostRelayID) {
     RELAY_ERRORS
         .with_label_values(&[relay.as_str(), RELAY_ERROR_CONNECTION])
-        .inc()
+        .inc();
+    record_relay_error(relay, RELAY_ERROR_CONNECTION);
 }
 
 pub fn inc_too_many_req_relay_errors(relay: &MevBoostRelayID) {
     RELAY_ERRORS
         .with_label_values(&[relay.as_str(), RELAY_ERROR_TOO_MANY_REQUESTS])
-        .inc()
+        .inc();
+    record_relay_error(relay, RELAY_ERROR_TOO_MANY_REQUESTS);
+}
+
+pub fn inc_relay_rejections(relay: &MevBoostRelayID, reason: &str) {
+    RELAY_REJECTIONS
+        .with_label_values(&[relay.as_str(), reason])
+        .inc();
 }
 
 pub fn inc_failed_block_simulations() {
@@ -259,6 +512,27 @@ pub fn inc_simulated_orders(ok: bool) {
     }
 }
 
+pub fn inc_order_validity_rejections(reason: &str) {
+    ORDER_VALIDITY_REJECTIONS.with_label_values(&[reason]).inc();
+}
+
+pub fn inc_order_drops(stage: &str, reason: &str) {
+    ORDER_DROPS.with_label_values(&[stage, reason]).inc();
+}
+
+pub fn inc_share_bundles_not_targeted() {
+    SHARE_BUNDLES_NOT_TARGETED.inc();
+}
+
+pub fn inc_urgent_reseals() {
+    URGENT_RESEALS.inc();
+}
+
+/// result: "adjusted", "clamped" (above the true block value), "unchanged" or "failed"
+pub fn inc_bid_adjustments(result: &str) {
+    BID_ADJUSTMENTS.with_label_values(&[result]).inc();
+}
+
 /// Gas used in any context of block building
 pub fn inc_simulation_gas_used(gas: u64) {
     SIMULATION_GAS_USED.inc_by(gas);
@@ -336,12 +610,26 @@ pub fn add_relay_submit_time(relay: &MevBoostRelayID, duration: Duration) {
     RELAY_SUBMIT_TIME
         .with_label_values(&[relay.as_str()])
         .observe(duration.as_millis() as f64);
+    record_relay_submit_latency(relay, duration);
+}
+
+pub fn add_relay_submit_body_peak_bytes(relay: &MevBoostRelayID, streaming: bool, bytes: usize) {
+    RELAY_SUBMIT_BODY_PEAK_BYTES
+        .with_label_values(&[relay.as_str(), if streaming { "true" } else { "false" }])
+        .observe(bytes as f64);
+}
+
+pub fn inc_relay_bids_below_floor(relay: &MevBoostRelayID) {
+    RELAY_BIDS_BELOW_FLOOR
+        .with_label_values(&[relay.as_str()])
+        .inc();
 }
 
 pub fn inc_relay_accepted_submissions(relay: &MevBoostRelayID, optimistic: bool) {
     RELAY_ACCEPTED_SUBMISSIONS
         .with_label_values(&[relay.as_str(), &optimistic.to_string()])
         .inc();
+    record_relay_accepted_submission(relay);
 }
 
 pub fn add_txfetcher_time_to_query(duration: Duration) {
@@ -352,6 +640,35 @@ pub fn add_txfetcher_time_to_query(duration: Duration) {
     TXFETCHER_TRANSACTION_COUNTER.inc();
 }
 
+/// kind: "event" or "pooled_request".
+pub fn inc_devp2p_dropped(kind: &str) {
+    DEVP2P_DROPPED.with_label_values(&[kind]).inc();
+}
+
+/// delay None: source was the first to announce the tx.
+pub fn add_mempool_source_arrival(source: &str, delay: Option<Duration>) {
+    match delay {
+        Some(delay) => {
+            MEMPOOL_SOURCE_TXS
+                .with_label_values(&[source, "duplicate"])
+                .inc();
+            MEMPOOL_SOURCE_ARRIVAL_DELAY
+                .with_label_values(&[source])
+                .observe(delay.as_secs_f64() * 1000.0);
+        }
+        None => {
+            MEMPOOL_SOURCE_TXS
+                .with_label_values(&[source, "first"])
+                .inc();
+        }
+    }
+}
+
+/// result: "replaced" (new tx pays more and replaces the old one) or "underpriced" (new tx dropped)
+pub fn inc_mempool_tx_replacements(result: &str) {
+    MEMPOOL_TX_REPLACEMENTS.with_label_values(&[result]).inc();
+}
+
 pub fn inc_provider_reopen_counter() {
     PROVIDER_REOPEN_COUNTER.inc();
 }
@@ -397,6 +714,185 @@ pub fn measure_block_e2e_latency(included_orders: &[ExecutionResult]) {
     }
 }
 
+pub fn inc_bytecode_cache_access(hit: bool) {
+    BYTECODE_CACHE_ACCESSES
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+pub fn inc_conflict_cache_access(hit: bool) {
+    CONFLICT_CACHE_ACCESSES
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+pub fn add_state_read_time(kind: &str, duration: Duration, slow: bool) {
+    STATE_READ_TIME
+        .with_label_values(&[kind])
+        .observe(duration.as_secs_f64() * 1_000_000.0);
+    if slow {
+        SLOW_STATE_READS.with_label_values(&[kind]).inc();
+    }
+}
+
+pub fn add_slot_state_read_time(read_time: Duration, building_time: Duration) {
+    SLOT_STATE_READ_TIME
+        .with_label_values(&[])
+        .observe(read_time.as_secs_f64() * 1000.0);
+    if !building_time.is_zero() {
+        SLOT_STATE_READ_BUDGET_RATIO
+            .with_label_values(&[])
+            .observe(read_time.as_secs_f64() / building_time.as_secs_f64());
+    }
+}
+
+pub fn add_slot_resource_report(report: &SlotResourceReport) {
+    SLOT_CPU_TIME.observe(report.cpu_time_ms as f64);
+    SLOT_PEAK_RSS.observe(report.peak_rss_bytes as f64 / (1024.0 * 1024.0));
+    SLOT_SIMULATIONS.observe(report.simulations as f64);
+    SLOT_STATE_READS.observe(report.state_reads as f64);
+    SLOT_ORDERS_RECEIVED.observe(report.orders_received as f64);
+}
+
+pub fn inc_late_order_fast_path(outcome: &str) {
+    LATE_ORDER_FAST_PATH.with_label_values(&[outcome]).inc();
+}
+
+/// gains: (group_len, avg gain in bps)
+pub fn set_conflict_exhaustive_search_stats(gains: &[(usize, f64)], learned_max_group_len: usize) {
+    for (group_len, gain_bps) in gains {
+        CONFLICT_EXHAUSTIVE_SEARCH_GAIN
+            .with_label_values(&[&group_len.to_string()])
+            .set(*gain_bps as i64);
+    }
+    CONFLICT_EXHAUSTIVE_SEARCH_MAX_GROUP_LEN.set(learned_max_group_len as i64);
+}
+
+/// A conflict set pruned by the resolver, pruned_value: profit alone of the dropped orders.
+pub fn add_conflict_set_pruned(pruned_orders: usize, pruned_value: U256) {
+    CONFLICT_PRUNED_SETS.inc();
+    CONFLICT_PRUNED_ORDERS.inc_by(pruned_orders as u64);
+    CONFLICT_PRUNED_VALUE_SUM
+        .inc_by(2.0_f64.powf(pruned_value.approx_log2()) / 10_f64.pow(Unit::ETHER.get()));
+}
+
+pub fn inc_rpc_connections(transport: &str) {
+    RPC_CONNECTIONS.with_label_values(&[transport]).inc();
+    RPC_OPEN_CONNECTIONS.with_label_values(&[transport]).inc();
+}
+
+/// duration None if we missed the connection start.
+pub fn add_rpc_connection_closed(transport: &str, duration: Option<Duration>) {
+    RPC_OPEN_CONNECTIONS.with_label_values(&[transport]).dec();
+    if let Some(duration) = duration {
+        RPC_CONNECTION_DURATION
+            .with_label_values(&[transport])
+            .observe(duration.as_secs_f64());
+    }
+}
+
+pub fn inc_rpc_calls(method: &str, transport: &str) {
+    RPC_CALLS.with_label_values(&[method, transport]).inc();
+}
+
+pub fn inc_rpc_rate_limited(key: &str) {
+    RPC_RATE_LIMITED.with_label_values(&[key]).inc();
+}
+
+/// peer: url for egress, origin for ingress.
+pub fn inc_orderflow_shared(peer: &str, direction: &str, result: &str, count: usize) {
+    ORDERFLOW_SHARED
+        .with_label_values(&[peer, direction, result])
+        .inc_by(count as u64);
+}
+
+/// One conflict detection run. pairs_by_kind: (kind, pairs), set_sizes: orders of every conflict set.
+pub fn add_conflict_detection(
+    detector: &str,
+    pairs_by_kind: &[(&str, usize)],
+    set_sizes: &[usize],
+    duration: Duration,
+) {
+    CONFLICT_DETECTION_TIME
+        .with_label_values(&[detector])
+        .observe(duration.as_secs_f64() * 1000.0);
+    for (kind, pairs) in pairs_by_kind {
+        CONFLICT_PAIRS
+            .with_label_values(&[detector, kind])
+            .observe(*pairs as f64);
+    }
+    CONFLICT_SETS
+        .with_label_values(&[detector])
+        .observe(set_sizes.len() as f64);
+    let set_size = CONFLICT_SET_SIZE.with_label_values(&[detector]);
+    for size in set_sizes {
+        set_size.observe(*size as f64);
+    }
+}
+
+/// event: received, dropped_pool_quota, simulated, dropped_sim_budget
+pub fn inc_tenant_orders(tenant: &str, event: &str) {
+    TENANT_ORDERS.with_label_values(&[tenant, event]).inc();
+}
+
+/// result: delivered, failed
+pub fn inc_inclusion_notifications(tenant: &str, result: &str) {
+    INCLUSION_NOTIFICATIONS
+        .with_label_values(&[tenant, result])
+        .inc();
+}
+
+/// result: sent, failed, settled, dropped
+pub fn inc_refund_settlement_payments(result: &str) {
+    REFUND_SETTLEMENT_PAYMENTS
+        .with_label_values(&[result])
+        .inc();
+}
+
+pub fn set_accrued_refunds(value: U256) {
+    ACCRUED_REFUNDS.set(2.0_f64.powf(value.approx_log2()) / 10_f64.pow(Unit::ETHER.get()));
+}
+
+pub fn inc_reaped_building_sessions() {
+    REAPED_BUILDING_SESSIONS.inc();
+}
+
+pub fn inc_slot_outcome_predictions(reduced_effort: bool) {
+    let effort = if reduced_effort { "reduced" } else { "full" };
+    SLOT_OUTCOME_PREDICTIONS.with_label_values(&[effort]).inc();
+}
+
+pub fn set_hottest_contract_order_share(share: f64) {
+    HOTTEST_CONTRACT_ORDER_SHARE.set(share);
+}
+
+pub fn set_is_leader(is_leader: bool) {
+    IS_LEADER.set(is_leader as i64);
+}
+
+/// event: "acquired" or "lost"
+pub fn inc_leadership_changes(event: &str) {
+    LEADERSHIP_CHANGES.with_label_values(&[event]).inc();
+}
+
+pub fn set_node_degraded(degraded: bool, head_lag: Duration) {
+    NODE_DEGRADED.set(degraded as i64);
+    NODE_HEAD_LAG.set(head_lag.as_secs_f64());
+}
+
+/// event: "entered" or "exited"
+pub fn inc_node_degraded_changes(event: &str) {
+    NODE_DEGRADED_CHANGES.with_label_values(&[event]).inc();
+}
+
+pub fn set_standby_promoted(promoted: bool) {
+    STANDBY_PROMOTED.set(promoted as i64);
+}
+
+pub fn inc_standby_would_be_submissions() {
+    STANDBY_WOULD_BE_SUBMISSIONS.inc();
+}
+
 /// landed vs attempt
 fn subsidized_label(landed: bool) -> &'static str {
     if landed {
@@ -422,6 +918,16 @@ pub fn add_subsidy_value(value: U256, landed: bool) {
     }
 }
 
+pub fn inc_slot_outcomes(result: &str) {
+    SLOT_OUTCOMES.with_label_values(&[result]).inc();
+}
+
+pub fn add_slot_loss_margin(value: U256) {
+    SLOT_LOSS_MARGIN
+        .with_label_values(&[])
+        .observe(2.0_f64.powf(value.approx_log2()) / 10_f64.pow(Unit::ETHER.get()));
+}
+
 pub(super) fn gather_prometheus_metrics() -> String {
     use prometheus::Encoder;
     let encoder = prometheus::TextEncoder::new();
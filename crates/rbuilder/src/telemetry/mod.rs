@@ -6,7 +6,11 @@
 
 mod dynamic_logs;
 mod metrics;
+mod order_drops;
 pub mod servers;
+mod status_page;
 
 pub use dynamic_logs::*;
 pub use metrics::*;
+pub use order_drops::*;
+pub use status_page::*;
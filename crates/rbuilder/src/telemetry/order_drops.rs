@@ -0,0 +1,276 @@
+//! Why orders disappear: every place where an order is dropped or rejected reports it here with a stage and a short
+//! reason code. Each report increments the order_drops{stage, reason} counter and the last drop of every stage of
+//! every order is remembered (for the last [`MAX_TRACKED_ORDERS`] orders) so operators can tell a searcher what
+//! happened to their order (admin_orderStatus on the admin rpc, see [`order_drops_rpc_module`]: order ids are easy to
+//! guess and the drops of an order say something about the orders competing with it).
+//! Reports are spread over SHARDS maps so the building threads don't serialize on a single lock.
+//!
+//! Reasons by stage:
+//! - intake: [`crate::building::order_validity::OrderValidityError::reason`] plus [`INTAKE_UNDERPRICED_REPLACEMENT`],
+//!   [`INTAKE_REPLACED`], [`INTAKE_STALE_REPLACEMENT`], [`INTAKE_CANCELLED`] and [`INTAKE_TENANT_POOL_QUOTA`].
+//! - simulation: [`crate::building::OrderErr::reason`].
+//! - building: [`crate::building::ExecutionError::reason`] plus [`BUILDING_ZERO_PROFIT_TX`].
+//!
+//! Building drops are per block attempt, an order failing for one builder may still land with another one, that's why
+//! every stage keeps its own last drop and how many times the order was dropped there.
+
+use super::inc_order_drops;
+use crate::{primitives::OrderId, utils::offset_datetime_to_timestamp_ms};
+use jsonrpsee::{types::ErrorObject, RpcModule};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+use time::OffsetDateTime;
+
+/// Mempool tx with the same sender and nonce as one we have but not paying more.
+pub const INTAKE_UNDERPRICED_REPLACEMENT: &str = "underpriced_replacement";
+/// Mempool tx replaced by a new one with the same sender and nonce paying more or bundle replaced by a new version
+/// (same replacementUuid and signer, higher sequence number).
+pub const INTAKE_REPLACED: &str = "replaced";
+/// Bundle version with a sequence number not higher than the one we have for its replacementUuid.
+pub const INTAKE_STALE_REPLACEMENT: &str = "stale_replacement";
+/// Bundle cancelled via its replacementUuid (or sent after the cancellation).
+pub const INTAKE_CANCELLED: &str = "cancelled";
+/// The tenant of the order has max_pool_orders orders in the pool.
+pub const INTAKE_TENANT_POOL_QUOTA: &str = "tenant_pool_quota";
+/// Skipped because of the zero_profit_txs policy of the builder.
+pub const BUILDING_ZERO_PROFIT_TX: &str = "zero_profit_tx";
+
+/// Orders we remember the drops of, oldest ones (of each shard) are forgotten first.
+pub const MAX_TRACKED_ORDERS: usize = 100_000;
+const SHARDS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderDropStage {
+    Intake,
+    Simulation,
+    Building,
+}
+
+impl OrderDropStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderDropStage::Intake => "intake",
+            OrderDropStage::Simulation => "simulation",
+            OrderDropStage::Building => "building",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderDrop {
+    pub stage: OrderDropStage,
+    pub reason: &'static str,
+    pub time: OffsetDateTime,
+    /// Drops of the order on this stage (the last one is this).
+    pub count: u32,
+}
+
+/// Last drop of each stage of an order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderDrops {
+    pub intake: Option<OrderDrop>,
+    pub simulation: Option<OrderDrop>,
+    pub building: Option<OrderDrop>,
+}
+
+impl OrderDrops {
+    fn stage_mut(&mut self, stage: OrderDropStage) -> &mut Option<OrderDrop> {
+        match stage {
+            OrderDropStage::Intake => &mut self.intake,
+            OrderDropStage::Simulation => &mut self.simulation,
+            OrderDropStage::Building => &mut self.building,
+        }
+    }
+
+    fn record(&mut self, stage: OrderDropStage, reason: &'static str, time: OffsetDateTime) {
+        let last = self.stage_mut(stage);
+        let count = last.map_or(0, |last| last.count) + 1;
+        *last = Some(OrderDrop {
+            stage,
+            reason,
+            time,
+            count,
+        });
+    }
+
+    /// Stage order.
+    pub fn drops(&self) -> impl Iterator<Item = &OrderDrop> {
+        [&self.intake, &self.simulation, &self.building]
+            .into_iter()
+            .flatten()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderDropStatus {
+    pub stage: OrderDropStage,
+    pub reason: String,
+    pub timestamp_ms: u64,
+    pub count: u32,
+}
+
+/// Response of admin_orderStatus.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderStatus {
+    pub order_id: String,
+    /// Last drop of each stage the order was dropped on, in stage order.
+    pub drops: Vec<OrderDropStatus>,
+}
+
+impl OrderStatus {
+    pub fn new(order_id: OrderId, drops: &OrderDrops) -> Self {
+        Self {
+            order_id: order_id.to_string(),
+            drops: drops
+                .drops()
+                .map(|drop| OrderDropStatus {
+                    stage: drop.stage,
+                    reason: drop.reason.to_string(),
+                    timestamp_ms: offset_datetime_to_timestamp_ms(drop.time),
+                    count: drop.count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct OrderDropsShard {
+    drops: HashMap<OrderId, OrderDrops>,
+    /// Insertion order of drops keys.
+    order_ids: VecDeque<OrderId>,
+}
+
+impl OrderDropsShard {
+    fn record(
+        &mut self,
+        order_id: OrderId,
+        stage: OrderDropStage,
+        reason: &'static str,
+        time: OffsetDateTime,
+        max_orders: usize,
+    ) {
+        let drops = self.drops.entry(order_id).or_insert_with(|| {
+            self.order_ids.push_back(order_id);
+            OrderDrops::default()
+        });
+        drops.record(stage, reason, time);
+        while self.order_ids.len() > max_orders {
+            if let Some(oldest) = self.order_ids.pop_front() {
+                self.drops.remove(&oldest);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref ORDER_DROPS: Vec<Mutex<OrderDropsShard>> =
+        (0..SHARDS).map(|_| Mutex::default()).collect();
+}
+
+fn shard(order_id: &OrderId) -> &'static Mutex<OrderDropsShard> {
+    let mut hasher = DefaultHasher::new();
+    order_id.hash(&mut hasher);
+    &ORDER_DROPS[hasher.finish() as usize % SHARDS]
+}
+
+pub fn record_order_drop(order_id: OrderId, stage: OrderDropStage, reason: &'static str) {
+    inc_order_drops(stage.as_str(), reason);
+    shard(&order_id).lock().record(
+        order_id,
+        stage,
+        reason,
+        OffsetDateTime::now_utc(),
+        MAX_TRACKED_ORDERS.div_ceil(SHARDS),
+    );
+}
+
+/// Drops reported for order_id, None if it was never dropped (or it was too long ago).
+pub fn order_drops(order_id: &OrderId) -> Option<OrderDrops> {
+    shard(order_id).lock().drops.get(order_id).copied()
+}
+
+/// admin_orderStatus(order id): [`OrderStatus`] of the order, null if it was never dropped.
+pub fn order_drops_rpc_module() -> eyre::Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    module.register_method("admin_orderStatus", |params, _| {
+        let order_id: String = params.one()?;
+        let order_id: OrderId = order_id
+            .parse()
+            .map_err(|err: eyre::Report| ErrorObject::owned(-32602, err.to_string(), None::<()>))?;
+        Ok::<_, ErrorObject<'static>>(
+            order_drops(&order_id).map(|drops| OrderStatus::new(order_id, &drops)),
+        )
+    })?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::order_id;
+
+    #[test]
+    fn test_order_drops_eviction() {
+        let mut shard = OrderDropsShard::default();
+        let time = OffsetDateTime::UNIX_EPOCH;
+        let max_orders = 10;
+        shard.record(
+            order_id(0),
+            OrderDropStage::Simulation,
+            "nonce_too_low",
+            time,
+            max_orders,
+        );
+        shard.record(
+            order_id(0),
+            OrderDropStage::Simulation,
+            "tx_reverted",
+            time,
+            max_orders,
+        );
+        assert_eq!(shard.order_ids.len(), 1);
+        let simulation = shard.drops[&order_id(0)].simulation.unwrap();
+        assert_eq!((simulation.reason, simulation.count), ("tx_reverted", 2));
+
+        for i in 1..=max_orders as u64 {
+            shard.record(
+                order_id(i),
+                OrderDropStage::Simulation,
+                "tx_reverted",
+                time,
+                max_orders,
+            );
+        }
+        assert_eq!(shard.drops.len(), max_orders);
+        assert!(!shard.drops.contains_key(&order_id(0)));
+        assert!(shard.drops.contains_key(&order_id(1)));
+    }
+
+    #[test]
+    fn test_order_status() {
+        let id = order_id(1_000_000_001);
+        record_order_drop(id, OrderDropStage::Simulation, "nonce_too_low");
+        // building attempts don't hide the simulation drop
+        record_order_drop(id, OrderDropStage::Building, "nonce_too_high");
+        record_order_drop(id, OrderDropStage::Building, "gas_limit");
+        let drops = order_drops(&id).unwrap();
+        let status = serde_json::to_value(OrderStatus::new(id, &drops)).unwrap();
+        assert_eq!(status["orderId"], id.to_string());
+        assert_eq!(status["drops"].as_array().unwrap().len(), 2);
+        assert_eq!(status["drops"][0]["stage"], "simulation");
+        assert_eq!(status["drops"][0]["reason"], "nonce_too_low");
+        assert_eq!(status["drops"][1]["stage"], "building");
+        assert_eq!(status["drops"][1]["reason"], "gas_limit");
+        assert_eq!(status["drops"][1]["count"], 2);
+        assert!(order_drops(&order_id(1_000_000_002)).is_none());
+    }
+}
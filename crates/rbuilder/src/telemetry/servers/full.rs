@@ -16,6 +16,7 @@ use crate::{
     telemetry::{
         dynamic_logs::{default_log_config, reset_log_config, set_log_config},
         metrics::{gather_prometheus_metrics, set_version},
+        status_page::render_status_page,
     },
     utils::build_info::Version,
 };
@@ -29,15 +30,25 @@ pub async fn spawn(
 
     // metrics over /debug/metrics/prometheus
     let metrics_route = warp::path!("debug" / "metrics" / "prometheus").and_then(metrics_handler);
+    // html page for humans over /status
+    let status_route = warp::path!("status").and_then(status_handler);
 
     if enable_dynamic_log {
         let log_set_route = warp::path!("debug" / "log" / "set" / String)
             .and(warp::query::<LogQuery>())
             .and_then(set_rust_log_handle);
         let log_reset_route = warp::path!("debug" / "log" / "reset").and_then(reset_log_handle);
-        tokio::spawn(warp::serve(metrics_route.or(log_set_route).or(log_reset_route)).run(addr));
+        tokio::spawn(
+            warp::serve(
+                metrics_route
+                    .or(status_route)
+                    .or(log_set_route)
+                    .or(log_reset_route),
+            )
+            .run(addr),
+        );
     } else {
-        tokio::spawn(warp::serve(metrics_route).run(addr));
+        tokio::spawn(warp::serve(metrics_route.or(status_route)).run(addr));
     }
 
     Ok(())
@@ -47,6 +58,10 @@ async fn metrics_handler() -> Result<impl Reply, Rejection> {
     Ok(gather_prometheus_metrics())
 }
 
+async fn status_handler() -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::html(render_status_page()))
+}
+
 #[derive(Debug, Deserialize)]
 struct LogQuery {
     file: Option<PathBuf>,
@@ -0,0 +1,491 @@
+//! Minimal html status page for on-call humans, served by the full telemetry server at /status.
+//! It's self contained (inline css, no js, no external assets) and refreshes itself every few seconds.
+//!
+//! Shows:
+//! - The slot we are building for and the best bid we submitted.
+//! - Relay health: accepted submissions and errors per relay.
+//! - Relay priority: the order we last submitted to the relays in (see [`relay_priority`]).
+//! - Recent landed blocks (our fee recipient was the coinbase) with our balance change.
+//!
+//! Data is fed via the record_* functions, like metrics they are globals.
+
+use crate::primitives::mev_boost::MevBoostRelayID;
+use alloy_primitives::{utils::format_ether, U256};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    time::Duration,
+};
+use time::OffsetDateTime;
+
+const MAX_LANDED_BLOCKS: usize = 20;
+/// Errors older than this don't affect relay health.
+const RELAY_ERROR_WINDOW: Duration = Duration::from_secs(60);
+const REFRESH_INTERVAL_SECS: u64 = 5;
+/// Weight of a new sample in the relay submit latency moving average.
+const RELAY_LATENCY_EWMA_ALPHA: f64 = 0.2;
+
+lazy_static! {
+    static ref BUILDER_STATUS: Mutex<BuilderStatus> = Mutex::new(BuilderStatus::default());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SlotStatus {
+    slot: u64,
+    block_number: u64,
+    started_at: OffsetDateTime,
+    submissions: u64,
+    best_bid_value: U256,
+    last_submission_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RelayStatus {
+    accepted_submissions: u64,
+    errors: u64,
+    last_accepted_at: Option<OffsetDateTime>,
+    last_error_at: Option<OffsetDateTime>,
+    last_error: Option<&'static str>,
+    /// Exponential moving average of the accepted submissions time.
+    submit_latency_ms: Option<f64>,
+    /// Slots won with a payload delivered by this relay.
+    wins: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayHealth {
+    Ok,
+    /// Recent errors but also recent accepted submissions.
+    Degraded,
+    /// Recent errors and nothing accepted since.
+    Failing,
+}
+
+impl RelayHealth {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RelayHealth::Ok => "ok",
+            RelayHealth::Degraded => "degraded",
+            RelayHealth::Failing => "failing",
+        }
+    }
+}
+
+impl RelayStatus {
+    fn health(&self, now: OffsetDateTime) -> RelayHealth {
+        let Some(last_error_at) = self.last_error_at else {
+            return RelayHealth::Ok;
+        };
+        if now - last_error_at > RELAY_ERROR_WINDOW {
+            return RelayHealth::Ok;
+        }
+        match self.last_accepted_at {
+            Some(last_accepted_at) if last_accepted_at > last_error_at => RelayHealth::Degraded,
+            _ => RelayHealth::Failing,
+        }
+    }
+
+    /// Higher goes first: (wins + 1) / latency, relays without latency samples get worst_latency_ms.
+    fn priority_score(&self, worst_latency_ms: f64) -> f64 {
+        let latency_ms = self.submit_latency_ms.unwrap_or(worst_latency_ms).max(1.0);
+        (self.wins + 1) as f64 / latency_ms
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LandedBlockStatus {
+    block_number: u64,
+    timestamp: OffsetDateTime,
+    balance_before: U256,
+    balance_after: U256,
+}
+
+#[derive(Debug, Default)]
+struct BuilderStatus {
+    current_slot: Option<SlotStatus>,
+    relays: BTreeMap<MevBoostRelayID, RelayStatus>,
+    /// Newest first.
+    landed_blocks: VecDeque<LandedBlockStatus>,
+    /// Last result of [`relay_priority`].
+    relay_priority: Vec<MevBoostRelayID>,
+}
+
+impl BuilderStatus {
+    /// relays sorted by [`RelayStatus::priority_score`], failing relays last. Ties keep the given order.
+    fn relay_priority(
+        &self,
+        relays: &[MevBoostRelayID],
+        now: OffsetDateTime,
+    ) -> Vec<MevBoostRelayID> {
+        let worst_latency_ms = self
+            .relays
+            .values()
+            .filter_map(|relay_status| relay_status.submit_latency_ms)
+            .fold(1.0, f64::max);
+        let default_status = RelayStatus::default();
+        let mut by_priority = relays
+            .iter()
+            .map(|relay| {
+                let relay_status = self.relays.get(relay).unwrap_or(&default_status);
+                let failing = relay_status.health(now) == RelayHealth::Failing;
+                (
+                    relay,
+                    failing,
+                    relay_status.priority_score(worst_latency_ms),
+                )
+            })
+            .collect::<Vec<_>>();
+        by_priority.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| b.2.total_cmp(&a.2)));
+        by_priority
+            .into_iter()
+            .map(|(relay, _, _)| relay.clone())
+            .collect()
+    }
+}
+
+/// Call when we start building for a new slot.
+pub fn record_slot_started(slot: u64, block_number: u64) {
+    BUILDER_STATUS.lock().current_slot = Some(SlotStatus {
+        slot,
+        block_number,
+        started_at: OffsetDateTime::now_utc(),
+        submissions: 0,
+        best_bid_value: U256::ZERO,
+        last_submission_at: None,
+    });
+}
+
+/// Call on every bid we send to the relays.
+pub fn record_bid_submitted(slot: u64, block_number: u64, bid_value: U256) {
+    let mut status = BUILDER_STATUS.lock();
+    let slot_status = status.current_slot.get_or_insert_with(|| SlotStatus {
+        slot,
+        block_number,
+        started_at: OffsetDateTime::now_utc(),
+        submissions: 0,
+        best_bid_value: U256::ZERO,
+        last_submission_at: None,
+    });
+    if slot_status.slot != slot || slot_status.block_number != block_number {
+        // late submission for an old slot
+        return;
+    }
+    slot_status.submissions += 1;
+    slot_status.best_bid_value = slot_status.best_bid_value.max(bid_value);
+    slot_status.last_submission_at = Some(OffsetDateTime::now_utc());
+}
+
+pub fn record_relay_accepted_submission(relay: &MevBoostRelayID) {
+    let mut status = BUILDER_STATUS.lock();
+    let relay_status = status.relays.entry(relay.clone()).or_default();
+    relay_status.accepted_submissions += 1;
+    relay_status.last_accepted_at = Some(OffsetDateTime::now_utc());
+}
+
+pub fn record_relay_submit_latency(relay: &MevBoostRelayID, duration: Duration) {
+    let mut status = BUILDER_STATUS.lock();
+    let relay_status = status.relays.entry(relay.clone()).or_default();
+    let sample = duration.as_secs_f64() * 1000.0;
+    relay_status.submit_latency_ms = Some(match relay_status.submit_latency_ms {
+        Some(latency) => latency + RELAY_LATENCY_EWMA_ALPHA * (sample - latency),
+        None => sample,
+    });
+}
+
+/// Call when we won a slot and relay delivered the payload.
+pub fn record_relay_win(relay: &MevBoostRelayID) {
+    BUILDER_STATUS
+        .lock()
+        .relays
+        .entry(relay.clone())
+        .or_default()
+        .wins += 1;
+}
+
+/// Order to submit to relays in: most winning and fastest first, failing ones last.
+/// The result is shown on the status page.
+pub fn relay_priority(relays: &[MevBoostRelayID]) -> Vec<MevBoostRelayID> {
+    let mut status = BUILDER_STATUS.lock();
+    let priority = status.relay_priority(relays, OffsetDateTime::now_utc());
+    status.relay_priority.clone_from(&priority);
+    priority
+}
+
+pub fn record_relay_error(relay: &MevBoostRelayID, error: &'static str) {
+    let mut status = BUILDER_STATUS.lock();
+    let relay_status = status.relays.entry(relay.clone()).or_default();
+    relay_status.errors += 1;
+    relay_status.last_error_at = Some(OffsetDateTime::now_utc());
+    relay_status.last_error = Some(error);
+}
+
+/// Call for blocks where we were the coinbase.
+pub fn record_landed_block(
+    block_number: u64,
+    timestamp: OffsetDateTime,
+    balance_before: U256,
+    balance_after: U256,
+) {
+    let mut status = BUILDER_STATUS.lock();
+    status.landed_blocks.push_front(LandedBlockStatus {
+        block_number,
+        timestamp,
+        balance_before,
+        balance_after,
+    });
+    status.landed_blocks.truncate(MAX_LANDED_BLOCKS);
+}
+
+/// (submissions, best bid value) of the slot, zeros if it's not the current one anymore.
+pub fn slot_bids(slot: u64, block_number: u64) -> (u64, U256) {
+    match &BUILDER_STATUS.lock().current_slot {
+        Some(slot_status)
+            if slot_status.slot == slot && slot_status.block_number == block_number =>
+        {
+            (slot_status.submissions, slot_status.best_bid_value)
+        }
+        _ => (0, U256::ZERO),
+    }
+}
+
+/// Recent errors and nothing accepted since.
+pub fn relay_is_failing(relay: &MevBoostRelayID) -> bool {
+    BUILDER_STATUS
+        .lock()
+        .relays
+        .get(relay)
+        .map_or(false, |relay_status| {
+            relay_status.health(OffsetDateTime::now_utc()) == RelayHealth::Failing
+        })
+}
+
+pub fn render_status_page() -> String {
+    render(&BUILDER_STATUS.lock(), OffsetDateTime::now_utc())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn format_ago(now: OffsetDateTime, time: Option<OffsetDateTime>) -> String {
+    match time {
+        Some(time) => format!("{:.1}s ago", (now - time).as_seconds_f64()),
+        None => "-".to_string(),
+    }
+}
+
+fn format_balance_change(before: U256, after: U256) -> String {
+    if after >= before {
+        format!("+{}", format_ether(after - before))
+    } else {
+        format!("-{}", format_ether(before - after))
+    }
+}
+
+fn render(status: &BuilderStatus, now: OffsetDateTime) -> String {
+    let mut html = String::new();
+    // writing to a String never fails.
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{REFRESH_INTERVAL_SECS}">
+<title>rbuilder status</title>
+<style>
+body {{ font-family: monospace; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 2em; }}
+th, td {{ border: 1px solid #999; padding: 0.3em 0.8em; text-align: left; }}
+.ok {{ color: #080; }}
+.degraded {{ color: #b60; }}
+.failing {{ color: #c00; font-weight: bold; }}
+</style>
+</head>
+<body>
+<h1>rbuilder status</h1>
+<p>Generated at {now}</p>
+"#
+    );
+
+    html.push_str("<h2>Slot</h2>\n");
+    match &status.current_slot {
+        Some(slot) => {
+            let _ = write!(
+                html,
+                "<table>\n<tr><th>slot</th><td>{}</td></tr>\n<tr><th>block</th><td>{}</td></tr>\n\
+                 <tr><th>started</th><td>{}</td></tr>\n<tr><th>submissions</th><td>{}</td></tr>\n\
+                 <tr><th>best bid (eth)</th><td>{}</td></tr>\n<tr><th>last submission</th><td>{}</td></tr>\n</table>\n",
+                slot.slot,
+                slot.block_number,
+                format_ago(now, Some(slot.started_at)),
+                slot.submissions,
+                format_ether(slot.best_bid_value),
+                format_ago(now, slot.last_submission_at),
+            );
+        }
+        None => html.push_str("<p>Not building</p>\n"),
+    }
+
+    html.push_str("<h2>Relays</h2>\n");
+    if status.relays.is_empty() {
+        html.push_str("<p>No relay activity</p>\n");
+    } else {
+        html.push_str(
+            "<table>\n<tr><th>relay</th><th>health</th><th>accepted</th><th>errors</th>\
+             <th>last accepted</th><th>last error</th><th>latency (ms)</th><th>wins</th></tr>\n",
+        );
+        for (relay, relay_status) in &status.relays {
+            let health = relay_status.health(now).as_str();
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"{health}\">{health}</td><td>{}</td><td>{}</td><td>{}</td><td>{} {}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(relay),
+                relay_status.accepted_submissions,
+                relay_status.errors,
+                format_ago(now, relay_status.last_accepted_at),
+                relay_status.last_error.unwrap_or_default(),
+                format_ago(now, relay_status.last_error_at),
+                relay_status
+                    .submit_latency_ms
+                    .map_or("-".to_string(), |latency| format!("{:.1}", latency)),
+                relay_status.wins,
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Relay priority</h2>\n");
+    if status.relay_priority.is_empty() {
+        html.push_str("<p>Automatic relay priority not used</p>\n");
+    } else {
+        html.push_str("<ol>\n");
+        for relay in &status.relay_priority {
+            let _ = writeln!(html, "<li>{}</li>", escape_html(relay));
+        }
+        html.push_str("</ol>\n");
+    }
+
+    html.push_str("<h2>Recent landed blocks</h2>\n");
+    if status.landed_blocks.is_empty() {
+        html.push_str("<p>No landed blocks</p>\n");
+    } else {
+        html.push_str(
+            "<table>\n<tr><th>block</th><th>time</th><th>balance change (eth)</th></tr>\n",
+        );
+        for block in &status.landed_blocks {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                block.block_number,
+                format_ago(now, Some(block.timestamp)),
+                format_balance_change(block.balance_before, block.balance_after),
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_health() {
+        let now = OffsetDateTime::now_utc();
+        let mut relay = RelayStatus::default();
+        assert_eq!(relay.health(now), RelayHealth::Ok);
+        relay.last_error_at = Some(now - Duration::from_secs(1));
+        assert_eq!(relay.health(now), RelayHealth::Failing);
+        relay.last_accepted_at = Some(now);
+        assert_eq!(relay.health(now), RelayHealth::Degraded);
+        relay.last_error_at = Some(now - RELAY_ERROR_WINDOW - Duration::from_secs(1));
+        assert_eq!(relay.health(now), RelayHealth::Ok);
+    }
+
+    #[test]
+    fn test_relay_priority() {
+        let now = OffsetDateTime::now_utc();
+        let relays = ["slow", "fast", "winner", "failing", "unknown"].map(String::from);
+        let mut status = BuilderStatus::default();
+        let mut relay = |name: &str, latency_ms: f64, wins: u64| {
+            let relay_status = status.relays.entry(name.to_string()).or_default();
+            relay_status.submit_latency_ms = Some(latency_ms);
+            relay_status.wins = wins;
+        };
+        relay("slow", 200.0, 0);
+        relay("fast", 20.0, 0);
+        relay("winner", 50.0, 3);
+        relay("failing", 10.0, 10);
+        status.relays.get_mut("failing").unwrap().last_error_at = Some(now);
+        assert_eq!(
+            status.relay_priority(&relays, now),
+            ["winner", "fast", "slow", "unknown", "failing"].map(String::from)
+        );
+        // no data: config order
+        assert_eq!(
+            BuilderStatus::default().relay_priority(&relays, now),
+            relays.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let now = OffsetDateTime::now_utc();
+        let mut status = BuilderStatus::default();
+        assert!(render(&status, now).contains("Not building"));
+
+        status.current_slot = Some(SlotStatus {
+            slot: 100,
+            block_number: 20,
+            started_at: now,
+            submissions: 3,
+            best_bid_value: U256::from(10).pow(U256::from(17)),
+            last_submission_at: Some(now),
+        });
+        status.relays.insert(
+            "<relay>".to_string(),
+            RelayStatus {
+                errors: 1,
+                last_error_at: Some(now),
+                last_error: Some("conn"),
+                ..Default::default()
+            },
+        );
+        status.landed_blocks.push_front(LandedBlockStatus {
+            block_number: 19,
+            timestamp: now,
+            balance_before: U256::from(10).pow(U256::from(18)),
+            balance_after: U256::ZERO,
+        });
+        status.relay_priority = vec!["<relay>".to_string()];
+        let html = render(&status, now);
+        assert!(html.contains("<td>100</td>"));
+        assert!(html.contains("<li>&lt;relay&gt;</li>"));
+        assert!(html.contains("0.100000000000000000"));
+        assert!(html.contains("&lt;relay&gt;"));
+        assert!(!html.contains("<relay>"));
+        assert!(html.contains("class=\"failing\""));
+        assert!(html.contains("-1.000000000000000000"));
+        // no external assets
+        assert!(
+            !html.contains("http://") && !html.contains("https://") && !html.contains("<script")
+        );
+    }
+}
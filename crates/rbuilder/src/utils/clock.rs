@@ -0,0 +1,141 @@
+//! Clock used by the time dependent logic of the live builder (slot cutoffs, header waits, late order window...).
+//! Production uses [`SystemClock`], tests (and the backtester) can use [`SimulatedClock`] to run deterministically
+//! or [`AcceleratedClock`] to replay slots faster than real time.
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+
+#[async_trait]
+pub trait Clock: Debug + Send + Sync {
+    fn now_utc(&self) -> OffsetDateTime;
+
+    async fn sleep(&self, duration: Duration);
+
+    /// Zero if time already passed.
+    fn duration_until(&self, time: OffsetDateTime) -> Duration {
+        Duration::try_from(time - self.now_utc()).unwrap_or_default()
+    }
+}
+
+pub type ClockRef = Arc<dyn Clock>;
+
+pub fn system_clock() -> ClockRef {
+    Arc::new(SystemClock)
+}
+
+/// Wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now_utc(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Time only moves when someone sleeps or calls [`SimulatedClock::advance`].
+/// Sleeping advances the clock by the slept duration and returns immediately so code waiting on a deadline runs
+/// instantly and always observes the same times.
+#[derive(Debug)]
+pub struct SimulatedClock {
+    now: Mutex<OffsetDateTime>,
+}
+
+impl SimulatedClock {
+    pub fn new(now: OffsetDateTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+
+    pub fn set(&self, now: OffsetDateTime) {
+        *self.now.lock() = now;
+    }
+}
+
+#[async_trait]
+impl Clock for SimulatedClock {
+    fn now_utc(&self) -> OffsetDateTime {
+        *self.now.lock()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+        // let other tasks see the new time
+        tokio::task::yield_now().await
+    }
+}
+
+/// Real time running speed times faster, starting at start.
+#[derive(Debug, Clone)]
+pub struct AcceleratedClock {
+    start: OffsetDateTime,
+    started_at: Instant,
+    speed: f64,
+}
+
+impl AcceleratedClock {
+    /// Panics if speed is not positive.
+    pub fn new(start: OffsetDateTime, speed: f64) -> Self {
+        assert!(speed > 0.0, "Clock speed must be positive");
+        Self {
+            start,
+            started_at: Instant::now(),
+            speed,
+        }
+    }
+}
+
+#[async_trait]
+impl Clock for AcceleratedClock {
+    fn now_utc(&self) -> OffsetDateTime {
+        self.start + self.started_at.elapsed().mul_f64(self.speed)
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration.div_f64(self.speed)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulated_clock() {
+        let start = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let clock = SimulatedClock::new(start);
+        assert_eq!(clock.now_utc(), start);
+
+        clock.sleep(Duration::from_secs(12)).await;
+        assert_eq!(clock.now_utc(), start + Duration::from_secs(12));
+
+        let deadline = start + Duration::from_secs(13);
+        assert_eq!(clock.duration_until(deadline), Duration::from_secs(1));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.duration_until(deadline), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_accelerated_clock() {
+        let start = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let clock = AcceleratedClock::new(start, 100.0);
+        clock.sleep(Duration::from_secs(1)).await;
+        assert!(clock.now_utc() >= start + Duration::from_secs(1));
+    }
+}
@@ -1,5 +1,6 @@
 //! a2r prefix = alloy to reth conversion
 pub mod build_info;
+pub mod clock;
 pub mod constants;
 pub mod error_storage;
 pub mod fmt;
@@ -1,5 +1,5 @@
 use ahash::HashMap;
-use alloy_primitives::{Address, B256};
+use alloy_primitives::{Address, B256, U256};
 use parking_lot::Mutex;
 use reth::providers::StateProviderBox;
 use reth_errors::ProviderResult;
@@ -57,4 +57,9 @@ impl NonceCacheRef {
         cache.insert(address, nonce);
         Ok(nonce)
     }
+
+    /// Not cached.
+    pub fn balance(&self, address: Address) -> ProviderResult<U256> {
+        Ok(self.state.account_balance(address)?.unwrap_or_default())
+    }
 }
@@ -27,6 +27,7 @@ use rbuilder::{
     },
     primitives::{Bundle, BundleReplacementKey, Order},
     telemetry,
+    utils::clock::ClockRef,
 };
 use reth_db_api::Database;
 use reth_primitives::TransactionSigned;
@@ -74,6 +75,7 @@ impl SlotSource for OurSlotSource {
                     suggested_gas_limit: gas_limit.unwrap_or(0),
                     relays: vec![],
                     slot_data: Default::default(),
+                    proposer_override: None,
                 };
 
                 if slot_sender.send(mev_boost_data).is_err() {
@@ -125,6 +127,9 @@ impl BundlePoolOps {
                 drop_failed_orders: true,
                 coinbase_payment: false,
                 build_duration_deadline_ms: None,
+                zero_profit_txs: Default::default(),
+                zero_profit_tx_max_gas: None,
+                value_per_cost_window_ms: None,
             }),
         };
 
@@ -270,6 +275,7 @@ impl UnfinishedBlockBuildingSinkFactory for SinkFactory {
         &mut self,
         _slot_data: MevBoostSlotData,
         _cancel: CancellationToken,
+        _clock: ClockRef,
     ) -> Arc<dyn UnfinishedBlockBuildingSink> {
         Arc::new(Sink {
             block_building_helper_tx: self.block_building_helper_tx.clone(),